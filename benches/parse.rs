@@ -0,0 +1,140 @@
+//! Benchmarks of the parse path: frames of the stove, DAT pages and JSON of the state
+//!
+//! When run by `cargo bench`, each benchmark is then timed against its budget, and the
+//! run exits with 1 if one is over, so that a regression fails CI:
+//! `cargo bench --bench parse -- --quick`.
+
+use criterion::{criterion_group, Criterion};
+use hottoh_api::hottoh::hottoh_structs::{CommandData, DAT0Data};
+use hottoh_api::hottoh::layouts::select_layout;
+use hottoh_api::hottoh::shared_struct::SharedState;
+use hottoh_api::hottoh::tcp_client_structs::Response;
+use std::hint::black_box;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// Frames of the hydro stove of `tests/vectors/reference_hydro.log`
+const INF_FRAME: &str = "#00011C---0018INFRHOTTOH-A1B2C3;2.6.1;-71;23C7\n";
+const DAT0_FRAME: &str = "#00012C---0061DATR0;85;1;1;118;0;0;1;0;198;210;70;300;0;0;0;0;552;650;300;800;243;0;4;1;5;0;0;3;5;0;0;0;0;0;0;17;3;C335\n";
+const DAT1_FRAME: &str = "#00013C---001CDATR1;612;300;850;0;0;0;0;0;0;0;8D9B\n";
+const DAT2_FRAME: &str = "#00014C---0047DATR2;1;1;60;0;100;452;600;300;800;650;700;400;850;480;500;350;650;0;0;0;0;031F\n";
+
+/// Params of the DAT0 frame of the air stove of `tests/vectors/reference_air.log`, which
+/// has no params after the last known one
+const DAT0_PARAMS: &str =
+    "0;9;1;1;5;8;1;0;0;215;220;70;300;0;0;0;0;0;0;0;0;1423;3;3;1;5;1450;2;2;5;0;0;0;0;0;0";
+
+/// Time a single call may take on average in release builds, about ten times the time
+/// measured on a desktop CPU
+const BUDGETS: [(&str, Duration); 3] = [
+    ("from_message_dat0", Duration::from_micros(30)),
+    ("dat0_from_slice", Duration::from_micros(10)),
+    ("shared_state_to_json", Duration::from_micros(100)),
+];
+
+/// Number of calls timed for the budget check
+const BUDGET_ITERATIONS: u32 = 10_000;
+
+/// Parses a frame of the reference layout
+///
+/// # Arguments
+///
+/// * `frame` - The frame
+///
+/// # Returns
+///
+/// * `Response` - The parsed frame
+fn parse(frame: &str) -> Response {
+    Response::from_message(frame, select_layout(None, None)).expect("valid frame")
+}
+
+/// Builds a shared state holding the pages of the hydro stove
+///
+/// # Returns
+///
+/// * `SharedState` - The state
+fn hydro_state() -> SharedState {
+    let mut state = SharedState::new();
+    for frame in [INF_FRAME, DAT0_FRAME, DAT1_FRAME, DAT2_FRAME] {
+        // No subscriber receives the events of the pages
+        let _ = match parse(frame).take_command_data() {
+            Some(CommandData::Inf(data)) => state.set_inf(data),
+            Some(CommandData::Dat0(data)) => state.set_dat0(data),
+            Some(CommandData::Dat1(data)) => state.set_dat1(data),
+            Some(CommandData::Dat2(data)) => state.set_dat2(data),
+            _ => unreachable!("the frames are pages"),
+        };
+    }
+    state
+}
+
+/// Runs the benchmarked function of a budget
+///
+/// # Arguments
+///
+/// * `name` - The name of the benchmark
+/// * `params` - Params of a DAT0 frame
+/// * `state` - State serialized
+fn run(name: &str, params: &[&str], state: &SharedState) {
+    match name {
+        "from_message_dat0" => {
+            black_box(parse(black_box(DAT0_FRAME)));
+        }
+        "dat0_from_slice" => {
+            black_box(DAT0Data::from_slice(black_box(params)).expect("valid params"));
+        }
+        "shared_state_to_json" => {
+            black_box(serde_json::to_string(black_box(state)).expect("serializable"));
+        }
+        _ => unreachable!("unknown benchmark {}", name),
+    }
+}
+
+fn benchmarks(c: &mut Criterion) {
+    let params: Vec<&str> = DAT0_PARAMS.split(';').collect();
+    let state = hydro_state();
+    for (name, _) in BUDGETS {
+        c.bench_function(name, |b| b.iter(|| run(name, &params, &state)));
+    }
+}
+
+criterion_group!(benches, benchmarks);
+
+/// Times each benchmark against its budget
+///
+/// # Returns
+///
+/// * `Vec<String>` - The benchmarks over their budget, with their average time
+fn check_budgets() -> Vec<String> {
+    let params: Vec<&str> = DAT0_PARAMS.split(';').collect();
+    let state = hydro_state();
+    let mut over = Vec::new();
+    for (name, budget) in BUDGETS {
+        let started = Instant::now();
+        for _ in 0..BUDGET_ITERATIONS {
+            run(name, &params, &state);
+        }
+        let average = started.elapsed() / BUDGET_ITERATIONS;
+        println!("{}: {:?} per call (budget {:?})", name, average, budget);
+        if average > budget {
+            over.push(format!("{} ({:?} > {:?})", name, average, budget));
+        }
+    }
+    over
+}
+
+fn main() -> ExitCode {
+    benches();
+    Criterion::default().configure_from_args().final_summary();
+    // `cargo test --benches` runs each benchmark once in a debug build, where the budgets do not apply
+    if !std::env::args().any(|arg| arg == "--bench") {
+        return ExitCode::SUCCESS;
+    }
+    let over = check_budgets();
+    if over.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("Over budget: {}", over.join(", "));
+        ExitCode::FAILURE
+    }
+}
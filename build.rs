@@ -0,0 +1,23 @@
+use std::process::Command;
+
+/// Embeds the short git hash of the build in the HOTTOH_GIT_HASH environment variable,
+/// and generates the gRPC service from `proto/hottoh.proto`
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=HOTTOH_GIT_HASH={}", git_hash);
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+
+    // protoc is bundled, so that building does not need it installed
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform");
+    std::env::set_var("PROTOC", protoc);
+    tonic_build::compile_protos("proto/hottoh.proto").expect("cannot compile proto/hottoh.proto");
+}
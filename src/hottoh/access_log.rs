@@ -0,0 +1,219 @@
+use crate::hottoh::api_keys::{KeyId, Role};
+use crate::hottoh::child_lock::PIN_FIELD;
+use crate::hottoh::config::HttpApiConfig;
+use crate::hottoh::http_api::read_body;
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::redaction::REDACTED;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage};
+use log::info;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Paths whose request bodies carry credentials or signed payloads, never logged: the
+/// inbound webhooks and the pairing (one-time code), including the admin routes
+const REDACTED_BODY_PATHS: &[&str] = &["/api/webhooks/", "/api/pairing", "/api/admin/pairing/"];
+/// Maximum number of body bytes written to the access log
+const MAX_LOGGED_BODY: usize = 512;
+
+/// Settings and sampling state of the access log
+///
+/// Successful GET requests (dashboards polling the state) are sampled per route;
+/// commands and errors are always logged.
+pub struct AccessLog {
+    /// Log one successful GET request out of this many per route (0 never logs them)
+    sample: u64,
+    /// Whether request bodies are logged
+    log_bodies: bool,
+    /// Number of successful GET requests seen per route
+    counters: Mutex<HashMap<String, u64>>,
+}
+
+impl AccessLog {
+    /// Creates the access log settings from the HTTP API configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The HTTP API configuration
+    ///
+    /// # Returns
+    ///
+    /// * `AccessLog` - The access log settings
+    pub fn from_config(config: &HttpApiConfig) -> Self {
+        Self {
+            sample: config.access_log_sample,
+            log_bodies: config.access_log_bodies,
+            counters: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Decides whether a request is logged
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    /// * `route` - Route pattern of the request
+    /// * `success` - Whether the response status is a success
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the request must be logged
+    fn is_sampled(&self, method: &Method, route: &str, success: bool) -> bool {
+        if !success || method != Method::GET || self.sample == 1 {
+            return true;
+        }
+        if self.sample == 0 {
+            return false;
+        }
+        let mut counters = mutex_lock(&self.counters, "access log counters");
+        let count = counters.entry(route.to_string()).or_insert(0);
+        *count += 1;
+        (*count - 1).is_multiple_of(self.sample)
+    }
+}
+
+/// Middleware writing one access log line per request
+///
+/// The line contains the client address, the request, the matched route, the
+/// status, the latency, the outcome, the API key or token and its role (`-` without
+/// `[auth]` section or when the request is rejected before), and the request body if
+/// enabled (redacted for the endpoints receiving credentials or signed payloads).
+/// Response bodies, such as the API keys issued by the pairing, are never logged.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<impl MessageBody>, Error>` - The response of the service
+pub async fn log_request(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(access_log) = req.app_data::<web::Data<Arc<AccessLog>>>().cloned() else {
+        return next.call(req).await;
+    };
+    let started = Instant::now();
+    let method = req.method().clone();
+    let path = req.path().to_string();
+    let peer = req
+        .peer_addr()
+        .map_or_else(|| "-".to_string(), |addr| addr.ip().to_string());
+
+    let body = if access_log.log_bodies && method != Method::GET {
+        let bytes = read_body(&mut req).await?;
+        Some(logged_body(&path, &bytes))
+    } else {
+        None
+    };
+
+    let response = next.call(req).await?;
+
+    let status = response.status();
+    let route = response
+        .request()
+        .match_pattern()
+        .unwrap_or_else(|| "-".to_string());
+    if access_log.is_sampled(&method, &route, status.is_success()) {
+        let outcome = if status.is_server_error() {
+            "server_error"
+        } else if status.is_client_error() {
+            "client_error"
+        } else {
+            "ok"
+        };
+        let key = response
+            .request()
+            .extensions()
+            .get::<KeyId>()
+            .map_or_else(|| "-".to_string(), |key| key.0.clone());
+        let role = response
+            .request()
+            .extensions()
+            .get::<Role>()
+            .map_or("-", Role::as_str);
+        let body = body.map_or_else(String::new, |body| format!(" body={}", body));
+        info!(
+            "{} \"{} {}\" route={} status={} latency_ms={:.1} outcome={} key={} role={}{}",
+            peer,
+            method,
+            path,
+            route,
+            status.as_u16(),
+            started.elapsed().as_secs_f64() * 1000.0,
+            outcome,
+            key,
+            role,
+            body
+        );
+    }
+    Ok(response)
+}
+
+/// Prepares a request body for the access log
+///
+/// # Arguments
+///
+/// * `path` - Path of the request
+/// * `bytes` - The request body
+///
+/// # Returns
+///
+/// * `String` - The body, redacted for sensitive paths, without PIN and truncated
+fn logged_body(path: &str, bytes: &[u8]) -> String {
+    if REDACTED_BODY_PATHS
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+    {
+        return REDACTED.to_string();
+    }
+    let without_pin = match serde_json::from_slice::<Value>(bytes) {
+        Ok(Value::Object(mut fields)) if fields.contains_key(PIN_FIELD) => {
+            fields.insert(PIN_FIELD.to_string(), Value::from(REDACTED));
+            Some(Value::Object(fields).to_string().into_bytes())
+        }
+        _ => None,
+    };
+    let bytes = without_pin.as_deref().unwrap_or(bytes);
+    let body = String::from_utf8_lossy(&bytes[..bytes.len().min(MAX_LOGGED_BODY)]);
+    let body = body.replace(['\r', '\n'], " ");
+    if bytes.len() > MAX_LOGGED_BODY {
+        format!("{}...", body)
+    } else {
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_logged_body_redacts_credentials() {
+        let pairing = br#"{"code":"042517","name":"panel"}"#;
+        assert_eq!(logged_body("/api/pairing", pairing), REDACTED);
+        assert_eq!(
+            logged_body("/api/admin/pairing/code", br#"{"role":"read"}"#),
+            REDACTED
+        );
+        assert_eq!(logged_body("/api/webhooks/alarm", b"{}"), REDACTED);
+        let logged = logged_body("/api/dat/set_on_off", br#"{"value":1,"pin":"1234"}"#);
+        assert!(!logged.contains("1234"));
+        assert!(logged.contains(r#""value":1"#));
+    }
+
+    #[test]
+    fn test_logged_body_truncated() {
+        let body = "a\n".repeat(MAX_LOGGED_BODY);
+        let logged = logged_body("/api/dat/set_on_off", body.as_bytes());
+        assert!(logged.ends_with("..."));
+        assert!(!logged.contains('\n'));
+    }
+}
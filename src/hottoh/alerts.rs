@@ -0,0 +1,804 @@
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::history::parse_span;
+use crate::hottoh::locks::{read_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::supervisor::supervise;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval between two evaluations of the alert rules
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(1);
+/// Window over which the rate of change of a field is computed
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+/// Shortest span of samples from which a rate of change is computed
+const MIN_RATE_SPAN: Duration = Duration::from_secs(30);
+/// Pages older than this are not evaluated, e.g. while the stove is disconnected
+const STALE_PAGE_SECS: u64 = 60;
+/// Priorities of the alerts, as published to the notifiers
+const PRIORITIES: [&str; 3] = ["normal", "high", "critical"];
+/// Longest silence, in seconds
+pub const MAX_SILENCE_SECS: i64 = 30 * 86_400;
+
+/// Condition of an alert rule on a numeric field
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Condition {
+    /// The value is above the threshold
+    Above(f64),
+    /// The value is below the threshold
+    Below(f64),
+    /// The value rises faster than this, per minute
+    RisePerMin(f64),
+    /// The value falls faster than this, per minute
+    FallPerMin(f64),
+}
+
+impl Condition {
+    /// Describes an observed value, e.g. `is 235.5` or `changes by 25.0/min`
+    ///
+    /// # Arguments
+    ///
+    /// * `observed` - The value, or its rate of change for a rate condition
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The description
+    fn describe_observed(&self, observed: f64) -> String {
+        match self {
+            Condition::Above(_) | Condition::Below(_) => format!("is {}", observed),
+            Condition::RisePerMin(_) | Condition::FallPerMin(_) => {
+                format!("changes by {:.1}/min", observed)
+            }
+        }
+    }
+
+    /// Describes the condition, e.g. `above 230`
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The description
+    fn describe(&self) -> String {
+        match self {
+            Condition::Above(threshold) => format!("above {}", threshold),
+            Condition::Below(threshold) => format!("below {}", threshold),
+            Condition::RisePerMin(rate) => format!("rising faster than {}/min", rate),
+            Condition::FallPerMin(rate) => format!("falling faster than {}/min", rate),
+        }
+    }
+}
+
+/// Rule of the `[alerts]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    /// Name of the rule
+    name: String,
+    /// Field, as named in the state (`page.field`)
+    field: String,
+    /// Condition raising the alert
+    condition: Condition,
+    /// Time the condition must hold before the alert fires
+    after: Duration,
+    /// Time the condition must be false before the alert resolves
+    resolve_after: Duration,
+    /// Priority published to the notifiers
+    priority: &'static str,
+    /// Labels matched by the silences, e.g. `chimney`
+    labels: Vec<String>,
+    /// Interval between two notifications of an alert still active and not acknowledged,
+    /// None to notify once
+    repeat: Option<Duration>,
+}
+
+impl AlertRule {
+    /// Parses a rule such as `field=dat0.smoke_t, above=230, for=5m, priority=critical`
+    ///
+    /// The condition is one of `above`, `below`, `rise_per_min` or `fall_per_min`.
+    /// `for` defaults to firing at once, `resolve_for` to `for` and `priority` to `high`;
+    /// `labels` (`|`-separated) and `repeat` are optional.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The known fields, as returned by `known_fields`
+    /// * `name` - Name of the rule
+    /// * `spec` - The rule
+    ///
+    /// # Returns
+    ///
+    /// * `Result<AlertRule, String>` - The rule, or an error if it is invalid
+    fn parse(fields: &[String], name: &str, spec: &str) -> Result<Self, String> {
+        let mut field = None;
+        let mut condition = None;
+        let mut after = Duration::ZERO;
+        let mut resolve_after = None;
+        let mut priority = "high";
+        let mut labels = Vec::new();
+        let mut repeat = None;
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected setting=value, got '{}'", item))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || format!("invalid value '{}' for {}", value, key);
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|number| number.is_finite())
+                    .ok_or_else(invalid)
+            };
+            let span = || parse_span(value).map(|secs| Duration::from_secs(secs as u64));
+            let new_condition = match key {
+                "field" => {
+                    field = Some(resolve_field(fields, value)?);
+                    None
+                }
+                "above" => Some(Condition::Above(number()?)),
+                "below" => Some(Condition::Below(number()?)),
+                "rise_per_min" => Some(Condition::RisePerMin(
+                    number()
+                        .ok()
+                        .filter(|rate| *rate > 0.0)
+                        .ok_or_else(invalid)?,
+                )),
+                "fall_per_min" => Some(Condition::FallPerMin(
+                    number()
+                        .ok()
+                        .filter(|rate| *rate > 0.0)
+                        .ok_or_else(invalid)?,
+                )),
+                "for" => {
+                    after = span()?;
+                    None
+                }
+                "resolve_for" => {
+                    resolve_after = Some(span()?);
+                    None
+                }
+                "priority" => {
+                    priority = PRIORITIES
+                        .into_iter()
+                        .find(|priority| *priority == value)
+                        .ok_or_else(|| {
+                            format!("priority must be one of {}", PRIORITIES.join(", "))
+                        })?;
+                    None
+                }
+                "labels" => {
+                    labels = value
+                        .split('|')
+                        .map(str::trim)
+                        .filter(|label| !label.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                    None
+                }
+                "repeat" => {
+                    repeat = Some(span()?);
+                    None
+                }
+                other => return Err(format!("unknown setting '{}'", other)),
+            };
+            if let Some(new_condition) = new_condition {
+                if condition.replace(new_condition).is_some() {
+                    return Err("only one of above, below, rise_per_min or fall_per_min".into());
+                }
+            }
+        }
+        Ok(Self {
+            name: name.to_string(),
+            field: field.ok_or("field is required")?,
+            condition: condition
+                .ok_or("one of above, below, rise_per_min or fall_per_min is required")?,
+            after,
+            resolve_after: resolve_after.unwrap_or(after),
+            priority,
+            labels,
+            repeat,
+        })
+    }
+}
+
+/// Parses the rules of the `[alerts]` section
+///
+/// # Arguments
+///
+/// * `config` - The rules by name
+///
+/// # Returns
+///
+/// * `Result<Vec<AlertRule>, String>` - The rules sorted by name, or the first error
+pub fn parse_alert_rules(config: &HashMap<String, String>) -> Result<Vec<AlertRule>, String> {
+    let fields = known_fields();
+    let mut names: Vec<&String> = config.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            AlertRule::parse(&fields, name, &config[name]).map_err(|e| format!("{}: {}", name, e))
+        })
+        .collect()
+}
+
+/// Alert whose rule fired and has not resolved yet
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveAlert {
+    /// Name of the rule
+    name: String,
+    /// Field, as `page.field`
+    field: String,
+    /// Value of the field when the alert fired (rate per minute for a rate of change)
+    value: f64,
+    /// Priority of the rule
+    priority: &'static str,
+    /// When the alert fired, in RFC 3339
+    since: String,
+    /// Description of the alert
+    message: String,
+    /// Labels of the rule
+    labels: Vec<String>,
+    /// Whether the alert was acknowledged, which stops its repeated notifications
+    acknowledged: bool,
+    /// Whether a silence matches the rule, which stops its notifications
+    silenced: bool,
+}
+
+impl ActiveAlert {
+    /// Gets the name of the rule
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The name
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Checks if the alert was acknowledged
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if acknowledged
+    pub fn is_acknowledged(&self) -> bool {
+        self.acknowledged
+    }
+
+    /// Acknowledges the alert
+    pub fn acknowledge(&mut self) {
+        self.acknowledged = true;
+    }
+}
+
+/// Temporary silence of the notifications of the alerts of a rule or with a label
+#[derive(Debug, Clone, Serialize)]
+pub struct Silence {
+    /// ID of the silence
+    id: u64,
+    /// Name of the rule silenced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule: Option<String>,
+    /// Label silenced
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    /// Reason of the silence
+    comment: String,
+    /// When the silence was created, in RFC 3339
+    created: String,
+    /// When the silence expires, in RFC 3339
+    expires: String,
+    /// When the silence expires, in seconds since the epoch
+    #[serde(skip)]
+    expires_at: i64,
+}
+
+impl Silence {
+    /// Creates a silence
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the silence
+    /// * `rule` - Name of the rule silenced, or None to silence a label
+    /// * `label` - Label silenced, or None to silence a rule
+    /// * `comment` - Reason of the silence
+    /// * `secs` - Duration of the silence, in seconds
+    ///
+    /// # Returns
+    ///
+    /// * `Silence` - The silence
+    pub fn new(
+        id: u64,
+        rule: Option<String>,
+        label: Option<String>,
+        comment: String,
+        secs: i64,
+    ) -> Self {
+        let now = chrono::Local::now();
+        let expires = now + chrono::Duration::seconds(secs);
+        Self {
+            id,
+            rule,
+            label,
+            comment,
+            created: now.to_rfc3339(),
+            expires: expires.to_rfc3339(),
+            expires_at: expires.timestamp(),
+        }
+    }
+
+    /// Gets the ID of the silence
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The ID
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Checks if the silence expired
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True once its expiry is reached
+    pub fn is_expired(&self) -> bool {
+        chrono::Local::now().timestamp() >= self.expires_at
+    }
+
+    /// Checks if the silence applies to a rule
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - The rule
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if it names the rule or one of its labels, and has not expired
+    fn matches(&self, rule: &AlertRule) -> bool {
+        let matched = match (&self.rule, &self.label) {
+            (Some(name), _) => *name == rule.name,
+            (None, Some(label)) => rule.labels.contains(label),
+            (None, None) => false,
+        };
+        matched && !self.is_expired()
+    }
+}
+
+/// Evaluation of a rule
+#[derive(Default)]
+struct Tracking {
+    /// Recent samples, for the rate of change
+    samples: VecDeque<(Instant, f64)>,
+    /// Since when the condition holds, while the alert is not active
+    pending_since: Option<Instant>,
+    /// Since when the condition is false, while the alert is active
+    resolving_since: Option<Instant>,
+    /// When the alert was last notified, while it is active
+    notified_at: Option<Instant>,
+    /// The alert, if active
+    active: Option<ActiveAlert>,
+}
+
+/// Computes the rate of change of the samples
+///
+/// # Arguments
+///
+/// * `samples` - The samples of the rate window, oldest first
+///
+/// # Returns
+///
+/// * `Option<f64>` - The change per minute, or None if the samples span less than
+///   `MIN_RATE_SPAN`
+fn rate_per_min(samples: &VecDeque<(Instant, f64)>) -> Option<f64> {
+    let (first_time, first) = samples.front()?;
+    let (last_time, last) = samples.back()?;
+    let span = last_time.duration_since(*first_time);
+    (span >= MIN_RATE_SPAN).then(|| (last - first) / span.as_secs_f64() * 60.0)
+}
+
+/// Starts a thread evaluating the alert rules on numeric fields
+///
+/// An alert fires when its condition has held for `for`, and resolves by itself when
+/// the condition has been false for `resolve_for`, so that a value hovering around the
+/// threshold does not flood the notifiers. Both are published on the event bus, and
+/// again every `repeat` while the alert is active, unless it was acknowledged. Alerts
+/// matched by a silence are not published. The active alerts are listed in
+/// `/api/alarms` and `/api/alerts`.
+///
+/// # Arguments
+///
+/// * `config` - The `[alerts]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_alerts_thread(
+    config: Option<&HashMap<String, String>>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let rules = match parse_alert_rules(config?) {
+        Ok(rules) if !rules.is_empty() => rules,
+        Ok(_) => return None,
+        Err(e) => {
+            error!("Alerts disabled: invalid rule {}", e);
+            return None;
+        }
+    };
+    info!("Evaluating {} alert rule(s)", rules.len());
+
+    Some(supervise(
+        "alerts",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let mut tracking: Vec<Tracking> = rules.iter().map(|_| Tracking::default()).collect();
+            let mut next_evaluation = Instant::now();
+            while running.load(Ordering::SeqCst) {
+                if Instant::now() < next_evaluation {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                next_evaluation += EVALUATION_INTERVAL;
+
+                let (state, acknowledged, silenced) = {
+                    let shared = read_lock(&shared_state, "shared state");
+                    let acknowledged = acknowledged_names(shared.get_alerts());
+                    let silenced: Vec<bool> = rules
+                        .iter()
+                        .map(|rule| shared.get_silences().any(|silence| silence.matches(rule)))
+                        .collect();
+                    (serde_json::to_value(&*shared), acknowledged, silenced)
+                };
+                let state = match state {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for the alerts: {}", e);
+                        continue;
+                    }
+                };
+                let mut changed = false;
+                for ((rule, tracked), silenced) in
+                    rules.iter().zip(tracking.iter_mut()).zip(silenced)
+                {
+                    if let Some(active) = &mut tracked.active {
+                        active.acknowledged |= acknowledged.contains(&active.name);
+                        changed |= active.silenced != silenced;
+                        active.silenced = silenced;
+                    }
+                    let page = rule.field.split('.').next().unwrap_or_default();
+                    let fresh = state
+                        .get(page)
+                        .and_then(|page| page.get("age_seconds"))
+                        .and_then(Value::as_u64)
+                        .is_some_and(|age| age <= STALE_PAGE_SECS);
+                    let value = get_field(&state, &rule.field)
+                        .and_then(Value::as_f64)
+                        .filter(|_| fresh);
+                    changed |= evaluate(rule, tracked, value, silenced, &events);
+                }
+                if changed {
+                    // Alerts acknowledged since the state was read stay acknowledged
+                    let mut shared = write_lock(&shared_state, "shared state");
+                    let acknowledged = acknowledged_names(shared.get_alerts());
+                    let alerts = tracking
+                        .iter_mut()
+                        .filter_map(|tracked| tracked.active.as_mut())
+                        .map(|active| {
+                            active.acknowledged |= acknowledged.contains(&active.name);
+                            active.clone()
+                        })
+                        .collect();
+                    shared.set_alerts(alerts);
+                }
+            }
+            info!("Alerts thread stopped.");
+        },
+    ))
+}
+
+/// Gets the names of the acknowledged alerts
+///
+/// # Arguments
+///
+/// * `alerts` - The active alerts
+///
+/// # Returns
+///
+/// * `Vec<String>` - The names of the rules whose alert is acknowledged
+fn acknowledged_names(alerts: &[ActiveAlert]) -> Vec<String> {
+    alerts
+        .iter()
+        .filter(|alert| alert.acknowledged)
+        .map(|alert| alert.name.clone())
+        .collect()
+}
+
+/// Evaluates a rule with the current value of its field
+///
+/// While the value is unknown (field not received or page stale), the alert keeps
+/// its state and the debounce restarts. A silenced alert still fires and resolves, but
+/// is not published on the event bus.
+///
+/// # Arguments
+///
+/// * `rule` - The rule
+/// * `tracked` - The evaluation of the rule
+/// * `value` - The value of the field, or None if unknown
+/// * `silenced` - Whether a silence matches the rule
+/// * `events` - The event bus
+///
+/// # Returns
+///
+/// * `bool` - True if the alert fired or resolved
+fn evaluate(
+    rule: &AlertRule,
+    tracked: &mut Tracking,
+    value: Option<f64>,
+    silenced: bool,
+    events: &EventBus,
+) -> bool {
+    let now = Instant::now();
+    let Some(value) = value else {
+        tracked.samples.clear();
+        tracked.pending_since = None;
+        tracked.resolving_since = None;
+        return false;
+    };
+    tracked.samples.push_back((now, value));
+    while tracked
+        .samples
+        .front()
+        .is_some_and(|(time, _)| now.duration_since(*time) > RATE_WINDOW)
+    {
+        tracked.samples.pop_front();
+    }
+
+    let observed = match rule.condition {
+        Condition::Above(_) | Condition::Below(_) => Some(value),
+        Condition::RisePerMin(_) | Condition::FallPerMin(_) => rate_per_min(&tracked.samples),
+    };
+    let Some(observed) = observed else {
+        return false;
+    };
+    let met = match rule.condition {
+        Condition::Above(threshold) => observed > threshold,
+        Condition::Below(threshold) => observed < threshold,
+        Condition::RisePerMin(rate) => observed > rate,
+        Condition::FallPerMin(rate) => observed < -rate,
+    };
+    let firing = |message: String| Event::AlertFiring {
+        name: rule.name.clone(),
+        field: rule.field.clone(),
+        value: observed,
+        priority: rule.priority,
+        message,
+    };
+
+    match &mut tracked.active {
+        None if met => {
+            let since = *tracked.pending_since.get_or_insert(now);
+            if now.duration_since(since) < rule.after {
+                return false;
+            }
+            tracked.pending_since = None;
+            let message = format!(
+                "{}: {} {}, {} for {}s",
+                rule.name,
+                rule.field,
+                rule.condition.describe_observed(observed),
+                rule.condition.describe(),
+                rule.after.as_secs()
+            );
+            if silenced {
+                info!("Alert (silenced) {}", message);
+            } else {
+                warn!("Alert {}", message);
+                events.publish(firing(message.clone()));
+            }
+            tracked.notified_at = Some(now);
+            tracked.active = Some(ActiveAlert {
+                name: rule.name.clone(),
+                field: rule.field.clone(),
+                value: observed,
+                priority: rule.priority,
+                since: chrono::Local::now().to_rfc3339(),
+                message,
+                labels: rule.labels.clone(),
+                acknowledged: false,
+                silenced,
+            });
+            true
+        }
+        None => {
+            tracked.pending_since = None;
+            false
+        }
+        Some(active) if met => {
+            tracked.resolving_since = None;
+            let due = rule.repeat.is_some_and(|repeat| {
+                tracked
+                    .notified_at
+                    .is_none_or(|notified_at| now.duration_since(notified_at) >= repeat)
+            });
+            if due && !active.acknowledged && !silenced {
+                warn!("Alert still active {}", active.message);
+                events.publish(firing(active.message.clone()));
+                tracked.notified_at = Some(now);
+            }
+            false
+        }
+        Some(_) => {
+            let since = *tracked.resolving_since.get_or_insert(now);
+            if now.duration_since(since) < rule.resolve_after {
+                return false;
+            }
+            tracked.resolving_since = None;
+            tracked.notified_at = None;
+            tracked.active = None;
+            let message = format!(
+                "{}: {} {}, no longer {}",
+                rule.name,
+                rule.field,
+                rule.condition.describe_observed(observed),
+                rule.condition.describe()
+            );
+            if silenced {
+                info!("Alert resolved (silenced) {}", message);
+            } else {
+                info!("Alert resolved {}", message);
+                events.publish(Event::AlertResolved {
+                    name: rule.name.clone(),
+                    field: rule.field.clone(),
+                    value: observed,
+                    message,
+                });
+            }
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hottoh::config::NotificationsConfig;
+    use crate::hottoh::notifications::{start_notifications_thread, Notifications};
+    use serde_json::json;
+    use std::io::{ErrorKind, Read, Write};
+    use std::net::TcpListener;
+
+    fn smoke_rule() -> AlertRule {
+        let config = HashMap::from([(
+            "smoke_high".to_string(),
+            "field=dat0.smoke_t, above=230, priority=critical".to_string(),
+        )]);
+        parse_alert_rules(&config).unwrap().remove(0)
+    }
+
+    #[test]
+    fn alerts_fire_and_resolve_on_the_bus() {
+        let rule = smoke_rule();
+        let events = EventBus::new();
+        let receiver = events.subscribe();
+        let mut tracked = Tracking::default();
+
+        assert!(!evaluate(&rule, &mut tracked, Some(200.0), false, &events));
+        assert!(evaluate(&rule, &mut tracked, Some(240.0), false, &events));
+        match receiver.try_recv().unwrap() {
+            Event::AlertFiring {
+                name,
+                value,
+                priority,
+                ..
+            } => {
+                assert_eq!(name, "smoke_high");
+                assert_eq!(value, 240.0);
+                assert_eq!(priority, "critical");
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        // Still above the threshold: no new event without `repeat`
+        assert!(!evaluate(&rule, &mut tracked, Some(250.0), false, &events));
+        assert!(receiver.try_recv().is_err());
+
+        assert!(evaluate(&rule, &mut tracked, Some(220.0), false, &events));
+        assert!(matches!(
+            receiver.try_recv().unwrap(),
+            Event::AlertResolved { value, .. } if value == 220.0
+        ));
+    }
+
+    #[test]
+    fn silenced_alerts_are_not_published() {
+        let rule = smoke_rule();
+        let events = EventBus::new();
+        let receiver = events.subscribe();
+        let mut tracked = Tracking::default();
+        assert!(evaluate(&rule, &mut tracked, Some(240.0), true, &events));
+        assert!(tracked.active.as_ref().is_some_and(|alert| alert.silenced));
+        assert!(evaluate(&rule, &mut tracked, Some(220.0), true, &events));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn firing_alerts_are_posted_to_the_notification_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let config: NotificationsConfig = serde_json::from_value(json!({
+            "webhook_url": format!("http://{}/hook", listener.local_addr().unwrap()),
+        }))
+        .unwrap();
+        let events = Arc::new(EventBus::new());
+        let mut state = SharedState::new();
+        state.set_events(Arc::clone(&events));
+        let shared_state = Arc::new(RwLock::new(state));
+        let notifications = Notifications::from_config(&config, Arc::clone(&shared_state)).unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let handle = start_notifications_thread(
+            Some(&config),
+            Some(Arc::new(notifications)),
+            Arc::clone(&running),
+            Arc::new(Metrics::new()),
+        )
+        .unwrap();
+
+        // The notifications thread subscribes once started: the alert fires again, as a
+        // new rule evaluation, until the webhook is called
+        let rule = smoke_rule();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut stream = loop {
+            assert!(Instant::now() < deadline, "the webhook was not called");
+            evaluate(&rule, &mut Tracking::default(), Some(240.0), false, &events);
+            thread::sleep(Duration::from_millis(50));
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => continue,
+                Err(e) => panic!("{}", e),
+            }
+        };
+        stream.set_nonblocking(false).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut request = String::new();
+        let mut buffer = [0; 4096];
+        let complete = |request: &str| {
+            request.split_once("\r\n\r\n").is_some_and(|(head, body)| {
+                head.lines()
+                    .filter_map(|line| line.strip_prefix("Content-Length: "))
+                    .any(|length| length.parse() == Ok(body.len()))
+            })
+        };
+        while !complete(&request) {
+            let read = stream.read(&mut buffer).unwrap();
+            assert!(read > 0, "incomplete request");
+            request.push_str(&String::from_utf8_lossy(&buffer[..read]));
+        }
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+            .unwrap();
+        drop(stream);
+        drop(listener);
+        running.store(false, Ordering::SeqCst);
+        handle.join().unwrap();
+
+        assert!(request.starts_with("POST /hook HTTP/1.1"));
+        let body: Value = serde_json::from_str(request.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        assert_eq!(body["type"], "alert_firing");
+        assert_eq!(body["name"], "smoke_high");
+        assert_eq!(body["priority"], "critical");
+    }
+}
@@ -0,0 +1,676 @@
+use crate::hottoh::config::AuthConfig;
+use crate::hottoh::http_api::ApiError;
+use crate::hottoh::inbound_webhooks::to_hex;
+use crate::hottoh::jwt::JwtAuth;
+use crate::hottoh::listeners::Listeners;
+use crate::hottoh::locks::{mutex_lock, read_lock, write_lock};
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use log::{debug, info};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "X-Api-Key";
+/// Paths reachable without API key: the inbound webhooks, whose body is signed, the
+/// pairing, which takes a one-time code, and the API documentation
+const EXEMPT_PATHS: &[&str] = &[
+    "/api/webhooks/",
+    "/api/pairing",
+    "/swagger-ui/",
+    "/api-docs/",
+];
+/// File keeping the paired keys when `auth.paired_keys_file` is not set
+pub const DEFAULT_PAIRED_KEYS_FILE: &str = "paired_keys";
+
+/// Identifier of the credential of a request, added to the request extensions for the
+/// access log: the name of the API key, or `jwt:` followed by the subject of the token
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyId(pub String);
+
+/// Role of an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// GET, HEAD and OPTIONS requests only, e.g. for a monitoring dashboard
+    Read,
+    /// Every request
+    Control,
+}
+
+impl Role {
+    /// Parses a role
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - `read` or `control`
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Role>` - The role, or None if it is unknown
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "read" => Some(Role::Read),
+            "control" => Some(Role::Control),
+            _ => None,
+        }
+    }
+
+    /// Gets the name of the role
+    ///
+    /// # Returns
+    ///
+    /// * `&'static str` - `read` or `control`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Read => "read",
+            Role::Control => "control",
+        }
+    }
+
+    /// Checks if the role allows a request
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the role may send requests with this method
+    pub fn allows(&self, method: &Method) -> bool {
+        *self == Role::Control || matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+    }
+}
+
+/// API key of the `[auth]` section
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    /// Name of the key, only used in the logs
+    name: String,
+    /// The key
+    key: String,
+    /// Requests allowed with the key
+    role: Role,
+}
+
+/// API keys or JSON Web Tokens required by every request, configured in the `[auth]` section
+pub struct ApiKeys {
+    /// The keys, empty if none is configured
+    keys: Vec<ApiKey>,
+    /// Keys issued to the paired clients
+    paired: RwLock<Vec<ApiKey>>,
+    /// File keeping the paired keys, None if the `[auth]` section is absent
+    paired_file: Option<String>,
+    /// Serializes the changes of the paired keys with the writes of their file
+    saving: Mutex<()>,
+    /// Validation of the JSON Web Tokens, None if disabled
+    jwt: Option<Arc<JwtAuth>>,
+}
+
+impl ApiKeys {
+    /// Creates the keys from the configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[auth]` section, or None if absent
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ApiKeys, String>` - The keys, none if the section is absent, or an error
+    ///   if they are invalid, the section has neither keys nor JSON Web Tokens, or the
+    ///   paired keys cannot be read
+    pub fn from_config(config: Option<&AuthConfig>) -> Result<Self, String> {
+        let Some(config) = config else {
+            return Ok(Self {
+                keys: Vec::new(),
+                paired: RwLock::new(Vec::new()),
+                paired_file: None,
+                saving: Mutex::new(()),
+                jwt: None,
+            });
+        };
+        let jwt = JwtAuth::from_config(config).map_err(|e| format!("auth: {}", e))?;
+        let keys = if config.keys.trim().is_empty() && jwt.is_some() {
+            Vec::new()
+        } else {
+            parse_api_keys(&config.keys).map_err(|e| format!("auth.keys: {}", e))?
+        };
+        let paired_file = config
+            .paired_keys_file
+            .clone()
+            .unwrap_or_else(|| DEFAULT_PAIRED_KEYS_FILE.to_string());
+        let paired =
+            load_paired_keys(&paired_file).map_err(|e| format!("auth.paired_keys_file: {}", e))?;
+        if let Some(key) = paired
+            .iter()
+            .find(|paired| keys.iter().any(|key| key.name == paired.name))
+        {
+            return Err(format!(
+                "auth.paired_keys_file: '{}' is also the name of a key of auth.keys",
+                key.name
+            ));
+        }
+        Ok(Self {
+            keys,
+            paired: RwLock::new(paired),
+            paired_file: Some(paired_file),
+            saving: Mutex::new(()),
+            jwt: jwt.map(Arc::new),
+        })
+    }
+
+    /// Checks if requests need an API key
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if at least one key, or the JSON Web Tokens, are configured
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.jwt.is_some()
+    }
+
+    /// Finds a key
+    ///
+    /// Every key is compared in constant time, to not leak how many characters match.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key received
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(String, Role)>` - The name and the role of the key, or None if it is
+    ///   neither configured nor paired
+    fn find(&self, key: &str) -> Option<(String, Role)> {
+        let paired = read_lock(&self.paired, "paired keys");
+        let found = self
+            .keys
+            .iter()
+            .chain(paired.iter())
+            .fold(None, |found, expected| {
+                let matches = key.len() == expected.key.len()
+                    && key
+                        .bytes()
+                        .zip(expected.key.bytes())
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0;
+                if matches {
+                    Some(expected)
+                } else {
+                    found
+                }
+            });
+        found.map(|key| (key.name.clone(), key.role))
+    }
+
+    /// Checks if JSON Web Tokens are accepted
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if they are configured, their validation possibly blocking to
+    ///   fetch the keys of the identity provider
+    pub fn has_jwt(&self) -> bool {
+        self.jwt.is_some()
+    }
+
+    /// Authenticates a client by its API key or JSON Web Token
+    ///
+    /// # Arguments
+    ///
+    /// * `credential` - The key, or the token when they are accepted
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(String, String, Role), String>` - The ID of the key (`jwt:<subject>`
+    ///   for a token), its label for the logs and its role, or the reason it is refused
+    pub fn authenticate(&self, credential: &str) -> Result<(String, String, Role), String> {
+        match &self.jwt {
+            Some(jwt) if JwtAuth::is_token(credential) => match jwt.validate(credential) {
+                Ok((subject, role)) => Ok((
+                    format!("jwt:{}", subject),
+                    format!("token of '{}'", subject),
+                    role,
+                )),
+                Err(e) => Err(format!("invalid token: {}", e)),
+            },
+            _ => self
+                .find(credential)
+                .map(|(name, role)| {
+                    let label = format!("API key '{}'", name);
+                    (name, label, role)
+                })
+                .ok_or_else(|| "invalid API key".to_string()),
+        }
+    }
+
+    /// Checks the name of a client to pair
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the client
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - Success, or an error if the pairing is disabled, the name
+    ///   is invalid or already used by a key
+    pub fn check_name(&self, name: &str) -> Result<(), String> {
+        if self.paired_file.is_none() {
+            return Err("the [auth] section is not configured".into());
+        }
+        if name.is_empty()
+            || name.len() > 64
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(format!(
+                "invalid name '{}', expected up to 64 letters, digits, '_' or '-'",
+                name
+            ));
+        }
+        let paired = read_lock(&self.paired, "paired keys");
+        if self
+            .keys
+            .iter()
+            .chain(paired.iter())
+            .any(|key| key.name == name)
+        {
+            return Err(format!("a key named '{}' already exists", name));
+        }
+        Ok(())
+    }
+
+    /// Issues a key to a paired client and saves it
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the client, used in the logs and to revoke the key
+    /// * `role` - Requests allowed with the key
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - The key, or an error if the name cannot be used, no
+    ///   random bytes are available or the file cannot be written
+    pub fn issue(&self, name: &str, role: Role) -> Result<String, String> {
+        let mut bytes = [0u8; 20];
+        getrandom::getrandom(&mut bytes).map_err(|e| format!("cannot generate key: {}", e))?;
+        let key = to_hex(&bytes);
+
+        let _saving = mutex_lock(&self.saving, "paired keys file");
+        self.check_name(name)?;
+        write_lock(&self.paired, "paired keys").push(ApiKey {
+            name: name.to_string(),
+            key: key.clone(),
+            role,
+        });
+        if let Err(e) = self.save() {
+            write_lock(&self.paired, "paired keys").retain(|key| key.name != name);
+            return Err(e);
+        }
+        info!("Issued the {} API key '{}'", role.as_str(), name);
+        Ok(key)
+    }
+
+    /// Revokes the key of a paired client
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the client
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, String>` - True if the key was revoked, false if no client has
+    ///   this name, or an error if the file cannot be written
+    pub fn revoke(&self, name: &str) -> Result<bool, String> {
+        let _saving = mutex_lock(&self.saving, "paired keys file");
+        let revoked = {
+            let mut paired = write_lock(&self.paired, "paired keys");
+            let index = paired.iter().position(|key| key.name == name);
+            index.map(|index| paired.remove(index))
+        };
+        let Some(revoked) = revoked else {
+            return Ok(false);
+        };
+        if let Err(e) = self.save() {
+            write_lock(&self.paired, "paired keys").push(revoked);
+            return Err(e);
+        }
+        info!("Revoked the API key '{}'", name);
+        Ok(true)
+    }
+
+    /// Lists the paired clients
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(String, Role)>` - The name and the role of each paired key
+    pub fn get_paired(&self) -> Vec<(String, Role)> {
+        read_lock(&self.paired, "paired keys")
+            .iter()
+            .map(|key| (key.name.clone(), key.role))
+            .collect()
+    }
+
+    /// Writes the paired keys to their file, readable by the owner only
+    ///
+    /// Called while holding `saving`, the keys being copied so that the requests are
+    /// not blocked by the disk.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - Success, or an error if the file cannot be written
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.paired_file else {
+            return Err("the [auth] section is not configured".into());
+        };
+        let mut content =
+            String::from("# API keys issued by POST /api/pairing, as name:role=key\n");
+        for key in read_lock(&self.paired, "paired keys").iter() {
+            content.push_str(&format!("{}:{}={}\n", key.name, key.role.as_str(), key.key));
+        }
+        write_private(path, &content).map_err(|e| format!("cannot write {}: {}", path, e))
+    }
+}
+
+/// Writes a file holding secrets, readable by the owner only on Unix
+///
+/// # Arguments
+///
+/// * `path` - Path of the file
+/// * `content` - The content
+///
+/// # Returns
+///
+/// * `io::Result<()>` - Success, or the error
+fn write_private(path: &str, content: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(content.as_bytes())
+}
+
+/// Reads the keys issued to the paired clients
+///
+/// # Arguments
+///
+/// * `path` - File keeping the keys, one `name:role=key` per line
+///
+/// # Returns
+///
+/// * `Result<Vec<ApiKey>, String>` - The keys, none if the file does not exist, or an
+///   error if it cannot be read or is invalid
+fn load_paired_keys(path: &str) -> Result<Vec<ApiKey>, String> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("cannot read {}: {}", path, e)),
+    };
+    let keys: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if keys.is_empty() {
+        return Ok(Vec::new());
+    }
+    parse_api_keys(&keys.join(",")).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Parses the API keys
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated `name=key`, e.g. `home_assistant=3f9a0c1e7b2d4f6a8c0e`, where
+///   the name may be followed by the role, `read` or `control` (the default), as in
+///   `dashboard:read=7b2d4f6a8c0e3f9a0c1e`
+///
+/// # Returns
+///
+/// * `Result<Vec<ApiKey>, String>` - The keys, or the first error
+pub fn parse_api_keys(spec: &str) -> Result<Vec<ApiKey>, String> {
+    let mut keys: Vec<ApiKey> = Vec::new();
+    for item in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (name, key) = item
+            .split_once('=')
+            .map(|(name, key)| (name.trim(), key.trim()))
+            .filter(|(name, _)| !name.is_empty())
+            .ok_or_else(|| format!("expected name=key, got '{}'", item))?;
+        let (name, role) = match name.split_once(':') {
+            Some((name, role)) => (
+                name.trim(),
+                Role::parse(role.trim()).ok_or_else(|| {
+                    format!(
+                        "unknown role '{}' of key '{}', expected read or control",
+                        role.trim(),
+                        name.trim()
+                    )
+                })?,
+            ),
+            None => (name, Role::Control),
+        };
+        if key.len() < 16 {
+            return Err(format!("key '{}' must have at least 16 characters", name));
+        }
+        if keys.iter().any(|known| known.name == name) {
+            return Err(format!("duplicate key name '{}'", name));
+        }
+        keys.push(ApiKey {
+            name: name.to_string(),
+            key: key.to_string(),
+            role,
+        });
+    }
+    if keys.is_empty() {
+        return Err("at least one key is required".into());
+    }
+    Ok(keys)
+}
+
+/// Reads the API key of a request
+///
+/// # Arguments
+///
+/// * `req` - The request
+///
+/// # Returns
+///
+/// * `Option<String>` - The key of the `X-Api-Key` header, or of an `Authorization:
+///   Bearer` header, if any
+fn request_key(req: &ServiceRequest) -> Option<String> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    };
+    header(API_KEY_HEADER)
+        .or_else(|| header("Authorization").and_then(|value| value.strip_prefix("Bearer ")))
+        .map(|key| key.trim().to_string())
+}
+
+/// Middleware rejecting requests without a valid API key or JSON Web Token with 401
+///
+/// The key is read from the `X-Api-Key` header, or from an `Authorization: Bearer`
+/// header, which may also carry a token when `jwt_secret`, `jwks_url` or `jwks_file` is set.
+/// Read-only keys and tokens get 403 on requests other than GET and HEAD, and the role of
+/// the credential is added to the request extensions for the WebSocket commands, with
+/// its [`KeyId`] for the access log. CORS
+/// preflight requests, inbound webhooks (signed), the pairing (one-time code), the API
+/// documentation and the listeners with `auth=none` are exempt.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<BoxBody>, Error>` - The response of the service, 401 or 403
+pub async fn require_api_key(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let keys = req.app_data::<web::Data<Arc<ApiKeys>>>().cloned();
+    let Some(keys) = keys.filter(|keys| keys.is_enabled()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let open_listener = req
+        .app_data::<web::Data<Arc<Listeners>>>()
+        .is_some_and(|listeners| !listeners.requires_api_key(req.app_config().local_addr()));
+    if open_listener
+        || req.method() == Method::OPTIONS
+        || EXEMPT_PATHS
+            .iter()
+            .any(|prefix| req.path().starts_with(prefix))
+    {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let Some(credential) = request_key(&req) else {
+        let response = ApiError::Unauthorized(format!(
+            "API key required ({} or Authorization: Bearer header)",
+            API_KEY_HEADER
+        ))
+        .error_response();
+        return Ok(req.into_response(response));
+    };
+    let authenticated = if keys.has_jwt() && JwtAuth::is_token(&credential) {
+        let keys = Arc::clone(keys.get_ref());
+        web::block(move || keys.authenticate(&credential))
+            .await
+            .unwrap_or_else(|e| Err(format!("cannot validate token: {}", e)))
+    } else {
+        keys.authenticate(&credential)
+    };
+
+    match authenticated {
+        Ok((_, label, role)) if !role.allows(req.method()) => {
+            let response = ApiError::Forbidden(format!("{} is read-only", label)).error_response();
+            Ok(req.into_response(response))
+        }
+        Ok((id, label, role)) => {
+            debug!("{} {} with {}", req.method(), req.path(), label);
+            req.extensions_mut().insert(role);
+            req.extensions_mut().insert(KeyId(id));
+            Ok(next.call(req).await?.map_into_boxed_body())
+        }
+        Err(e) => {
+            let response = ApiError::Unauthorized(e).error_response();
+            Ok(req.into_response(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Builds the keys of an `[auth]` section keeping the paired keys in a temporary file
+    fn api_keys(name: &str, keys: &str) -> (ApiKeys, String) {
+        let path = std::env::temp_dir()
+            .join(format!("hottoh_paired_{}_{}", std::process::id(), name))
+            .to_string_lossy()
+            .into_owned();
+        let _ = fs::remove_file(&path);
+        let config: AuthConfig =
+            serde_json::from_value(json!({ "keys": keys, "paired_keys_file": path })).unwrap();
+        (ApiKeys::from_config(Some(&config)).unwrap(), path)
+    }
+
+    #[test]
+    fn test_parse_api_keys() {
+        let keys = parse_api_keys("ha=0123456789abcdef, dash:read=fedcba9876543210").unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!((keys[0].name.as_str(), keys[0].role), ("ha", Role::Control));
+        assert_eq!((keys[1].name.as_str(), keys[1].role), ("dash", Role::Read));
+
+        assert!(parse_api_keys("").is_err());
+        assert!(parse_api_keys("ha=short").is_err());
+        assert!(parse_api_keys("ha:admin=0123456789abcdef").is_err());
+        assert!(parse_api_keys("ha=0123456789abcdef,ha=fedcba9876543210").is_err());
+        assert!(parse_api_keys("=0123456789abcdef").is_err());
+    }
+
+    #[test]
+    fn test_role_allows() {
+        assert!(Role::Read.allows(&Method::GET));
+        assert!(!Role::Read.allows(&Method::POST));
+        assert!(Role::Control.allows(&Method::DELETE));
+    }
+
+    #[test]
+    fn test_exempt_paths() {
+        let exempt = |path: &str| EXEMPT_PATHS.iter().any(|prefix| path.starts_with(prefix));
+        assert!(exempt("/api/pairing"));
+        assert!(exempt("/api/webhooks/alarm"));
+        assert!(!exempt("/api/admin/pairing/code"));
+        assert!(!exempt("/api/state"));
+    }
+
+    #[test]
+    fn test_issue_find_revoke() {
+        let (keys, path) = api_keys("issue", "ha=0123456789abcdef");
+        assert!(keys.find("0123456789abcdef").is_some());
+
+        let key = keys.issue("panel", Role::Read).unwrap();
+        assert_eq!(key.len(), 40);
+        assert_eq!(keys.find(&key), Some(("panel".to_string(), Role::Read)));
+        assert_eq!(keys.get_paired(), vec![("panel".to_string(), Role::Read)]);
+
+        // The keys are kept across restarts
+        let config: AuthConfig = serde_json::from_value(
+            json!({ "keys": "ha=0123456789abcdef", "paired_keys_file": path }),
+        )
+        .unwrap();
+        let reloaded = ApiKeys::from_config(Some(&config)).unwrap();
+        assert_eq!(reloaded.find(&key), Some(("panel".to_string(), Role::Read)));
+
+        assert_eq!(keys.revoke("panel"), Ok(true));
+        assert_eq!(keys.revoke("panel"), Ok(false));
+        assert!(keys.find(&key).is_none());
+        assert!(ApiKeys::from_config(Some(&config))
+            .unwrap()
+            .get_paired()
+            .is_empty());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_check_name() {
+        let (keys, path) = api_keys("names", "ha=0123456789abcdef");
+        assert!(keys.check_name("kitchen_panel").is_ok());
+        assert!(keys.check_name("ha").is_err());
+        assert!(keys.check_name("").is_err());
+        assert!(keys.check_name("a:control").is_err());
+        assert!(keys.check_name(&"a".repeat(65)).is_err());
+        keys.issue("kitchen_panel", Role::Control).unwrap();
+        assert!(keys.check_name("kitchen_panel").is_err());
+        assert!(keys.issue("kitchen_panel", Role::Control).is_err());
+        let _ = fs::remove_file(&path);
+
+        // Without [auth], no key can be issued
+        let disabled = ApiKeys::from_config(None).unwrap();
+        assert!(!disabled.is_enabled());
+        assert!(disabled.check_name("panel").is_err());
+    }
+
+    #[test]
+    fn test_paired_name_clash() {
+        let (keys, path) = api_keys("clash", "ha=0123456789abcdef");
+        keys.issue("panel", Role::Control).unwrap();
+        let config: AuthConfig = serde_json::from_value(
+            json!({ "keys": "panel=0123456789abcdef", "paired_keys_file": path }),
+        )
+        .unwrap();
+        assert!(ApiKeys::from_config(Some(&config)).is_err());
+        let _ = fs::remove_file(&path);
+    }
+}
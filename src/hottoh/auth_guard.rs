@@ -0,0 +1,294 @@
+use crate::hottoh::config::HttpApiConfig;
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::network_filter::request_client_ip;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpResponse};
+use chrono::{Local, TimeDelta};
+use log::{info, warn};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Period over which the failed attempts of a client are counted
+const FAILURE_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// Duration of the first ban of a client, doubled at each new ban
+const BASE_BAN: Duration = Duration::from_secs(60);
+/// Longest ban
+const MAX_BAN: Duration = Duration::from_secs(24 * 60 * 60);
+/// Delay without failure after which a client starts again from the shortest ban
+const FORGET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+/// Number of authentication events kept
+const MAX_EVENTS: usize = 100;
+
+/// Failed authentication attempts of a client
+#[derive(Debug, Clone)]
+struct ClientFailures {
+    /// Failed attempts since the start of the window
+    failures: u32,
+    /// Start of the window of the failed attempts
+    window_start: Instant,
+    /// Last failed attempt
+    last_failure: Instant,
+    /// Number of bans so far, giving the duration of the next one
+    bans: u32,
+    /// End of the ban in progress, if any
+    banned_until: Option<Instant>,
+}
+
+/// Authentication event, as listed by the admin endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthEvent {
+    /// Time of the event, in RFC 3339
+    at: String,
+    /// Address of the client
+    client: String,
+    /// `failure`, `ban` or `unban`
+    kind: &'static str,
+    /// Path of the request, or the reason of the event
+    detail: String,
+}
+
+/// Protection of the API against the guessing of credentials (PIN, signatures,
+/// pairing codes)
+///
+/// A client failing to authenticate `max_auth_failures` times within 10 minutes is
+/// banned, for 1 minute the first time and twice as long at each new ban, up to 24
+/// hours. Failures, bans and unbans are kept in a log of the last authentication events.
+pub struct AuthGuard {
+    /// Failed attempts allowed before a ban (0 disables the bans)
+    max_failures: u32,
+    /// Failed attempts by client
+    clients: Mutex<BTreeMap<IpAddr, ClientFailures>>,
+    /// Last authentication events, oldest first
+    events: Mutex<VecDeque<AuthEvent>>,
+}
+
+impl AuthGuard {
+    /// Creates the guard from the HTTP API configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The HTTP API configuration
+    ///
+    /// # Returns
+    ///
+    /// * `AuthGuard` - The guard, without failed attempts
+    pub fn from_config(config: &HttpApiConfig) -> Self {
+        Self {
+            max_failures: config.max_auth_failures,
+            clients: Mutex::new(BTreeMap::new()),
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records an authentication event, also written to the logs
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Address of the client
+    /// * `kind` - `failure`, `ban` or `unban`
+    /// * `detail` - Path of the request, or the reason of the event
+    fn record(&self, client: IpAddr, kind: &'static str, detail: String) {
+        match kind {
+            "failure" => warn!("Authentication failure from {} on {}", client, detail),
+            _ => info!("Authentication {} of {}: {}", kind, client, detail),
+        }
+        let mut events = mutex_lock(&self.events, "auth events");
+        if events.len() >= MAX_EVENTS {
+            events.pop_front();
+        }
+        events.push_back(AuthEvent {
+            at: Local::now().to_rfc3339(),
+            client: client.to_string(),
+            kind,
+            detail,
+        });
+    }
+
+    /// Gets the remaining duration of the ban of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Address of the client
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Duration>` - The remaining duration, None if the client is not banned
+    pub fn banned_for(&self, client: IpAddr) -> Option<Duration> {
+        let now = Instant::now();
+        mutex_lock(&self.clients, "auth failures")
+            .get(&client)?
+            .banned_until
+            .filter(|until| *until > now)
+            .map(|until| until - now)
+    }
+
+    /// Records a failed authentication attempt, banning the client after too many
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Address of the client
+    /// * `path` - Path of the request
+    pub fn fail(&self, client: IpAddr, path: &str) {
+        self.record(client, "failure", path.to_string());
+        if self.max_failures == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let ban = {
+            let mut clients = mutex_lock(&self.clients, "auth failures");
+            // Clients not seen for a day are forgotten, bans included
+            clients.retain(|_, failures| {
+                failures.banned_until.is_some_and(|until| until > now)
+                    || now - failures.last_failure < FORGET_AFTER
+            });
+            let failures = clients.entry(client).or_insert(ClientFailures {
+                failures: 0,
+                window_start: now,
+                last_failure: now,
+                bans: 0,
+                banned_until: None,
+            });
+            if now - failures.window_start >= FAILURE_WINDOW {
+                failures.failures = 0;
+                failures.window_start = now;
+            }
+            failures.failures += 1;
+            failures.last_failure = now;
+            if failures.failures < self.max_failures {
+                return;
+            }
+            let duration = BASE_BAN
+                .saturating_mul(2u32.saturating_pow(failures.bans))
+                .min(MAX_BAN);
+            failures.bans += 1;
+            failures.failures = 0;
+            failures.window_start = now;
+            failures.banned_until = Some(now + duration);
+            duration
+        };
+        self.record(
+            client,
+            "ban",
+            format!(
+                "{} failed attempts, banned for {} seconds",
+                self.max_failures,
+                ban.as_secs()
+            ),
+        );
+    }
+
+    /// Lists the clients banned or with failed attempts
+    ///
+    /// # Returns
+    ///
+    /// * `serde_json::Value` - The clients with their failed attempts, number of bans
+    ///   and end of the ban in progress (null if none)
+    pub fn list(&self) -> serde_json::Value {
+        let now = Instant::now();
+        let clients = mutex_lock(&self.clients, "auth failures");
+        let clients = clients
+            .iter()
+            .map(|(client, failures)| {
+                let remaining = failures
+                    .banned_until
+                    .filter(|until| *until > now)
+                    .map(|until| TimeDelta::from_std(until - now).unwrap_or_default());
+                let banned_until =
+                    remaining.map(|remaining| (Local::now() + remaining).to_rfc3339());
+                json!({
+                    "client": client.to_string(),
+                    "failures": failures.failures,
+                    "bans": failures.bans,
+                    "banned_until": banned_until,
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "max_failures": self.max_failures,
+            "clients": clients,
+        })
+    }
+
+    /// Gets the last authentication events
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<AuthEvent>` - The events, oldest first
+    pub fn get_events(&self) -> Vec<AuthEvent> {
+        mutex_lock(&self.events, "auth events")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Lifts the bans and forgets the failed attempts
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - Address of the client, or None for all of them
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The number of clients cleared
+    pub fn clear(&self, client: Option<IpAddr>) -> usize {
+        let cleared = {
+            let mut clients = mutex_lock(&self.clients, "auth failures");
+            match client {
+                Some(client) => clients.remove_entry(&client).into_iter().collect(),
+                None => std::mem::take(&mut *clients)
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+            }
+        };
+        for (client, _) in &cleared {
+            self.record(*client, "unban", "cleared by an administrator".into());
+        }
+        cleared.len()
+    }
+}
+
+/// Middleware rejecting the banned clients with 429, and counting the failed
+/// authentications (401 responses) of the others
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<BoxBody>, Error>` - The response of the service, or 429
+pub async fn guard_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let guard = req.app_data::<web::Data<Arc<AuthGuard>>>().cloned();
+    let (Some(guard), Some(client)) = (guard, request_client_ip(req.request())) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if let Some(remaining) = guard.banned_for(client) {
+        // Rounded up, so that retrying after this delay succeeds
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        let response = HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", seconds.to_string()))
+            .json(json!({
+                "error": "Too many failed authentications, try again later",
+                "retry_after_seconds": seconds
+            }));
+        return Ok(req.into_response(response));
+    }
+
+    let path = req.path().to_string();
+    let response = next.call(req).await?.map_into_boxed_body();
+    if response.status() == StatusCode::UNAUTHORIZED {
+        guard.fail(client, &path);
+    }
+    Ok(response)
+}
@@ -0,0 +1,5 @@
+/// Version of the application, from Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short git hash of the build, or "unknown" when built outside a git checkout
+pub const GIT_HASH: &str = env!("HOTTOH_GIT_HASH");
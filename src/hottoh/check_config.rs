@@ -0,0 +1,464 @@
+use crate::hottoh::alerts::parse_alert_rules;
+use crate::hottoh::api_keys::ApiKeys;
+use crate::hottoh::config::{get_env_overrides, load_config, AppConfig, LogTarget};
+use crate::hottoh::failover::StoveAddresses;
+use crate::hottoh::fan_curve::parse_fan_curves;
+use crate::hottoh::freeze_watchdog::parse_watched_fields;
+use crate::hottoh::frost_guard::FrostGuard;
+use crate::hottoh::history::{parse_history_fields, parse_levels};
+use crate::hottoh::home_assistant::parse_power_kw;
+use crate::hottoh::http_client;
+use crate::hottoh::inbound_webhooks::parse_action;
+use crate::hottoh::listeners::Listeners;
+use crate::hottoh::maintenance::MaintenanceSchedule;
+use crate::hottoh::mqtt::render_topic;
+use crate::hottoh::network_filter::parse_networks;
+use crate::hottoh::notifications::check_notifications;
+use crate::hottoh::openhab::parse_command_items;
+use crate::hottoh::plugins::PluginRegistry;
+use crate::hottoh::profiles::parse_profile;
+use crate::hottoh::quiet_hours::QuietHours;
+use crate::hottoh::redaction::parse_frame_mask;
+use crate::hottoh::rules::parse_rules;
+use crate::hottoh::scripts::check_scripts;
+use crate::hottoh::state_fields::parse_field_mapping;
+use crate::hottoh::syslog::parse_syslog_url;
+use crate::hottoh::temperature::validate_decimals;
+use crate::hottoh::throttle::CommandThrottle;
+use crate::hottoh::timezone::validate_timezone;
+use crate::hottoh::wake_on_lan::WakeOnLan;
+use flexi_logger::LogSpecification;
+use std::net::IpAddr;
+
+/// Usage of the check-config subcommand
+const USAGE: &str = "Usage: hottoh_api check-config [path/to/config]
+
+Loads and validates the configuration, including the HOTTOH_<SECTION>__<KEY>
+environment overrides, without connecting to the stove. Exits with 1 on error.";
+
+/// Runs `hottoh_api check-config [path/to/config]`
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `check-config`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success if the configuration is valid, or the errors found
+pub fn run_check_config(args: &[String]) -> Result<(), String> {
+    let config_path = match args {
+        [] => None,
+        [flag] if flag == "--help" || flag == "-h" => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        [path] => Some(path.as_str()),
+        _ => return Err(format!("Too many arguments\n\n{}", USAGE)),
+    };
+
+    for (variable, key) in get_env_overrides() {
+        println!("{} overridden by {}", key, variable);
+    }
+    let config =
+        load_config(config_path).map_err(|e| format!("Cannot load configuration: {}", e))?;
+
+    let errors = validate_config(&config);
+    if !errors.is_empty() {
+        return Err(format!(
+            "{} error(s) in configuration:\n{}",
+            errors.len(),
+            errors
+                .iter()
+                .map(|e| format!("  - {}", e))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ));
+    }
+    println!("Configuration is valid.");
+    Ok(())
+}
+
+/// Checks the values of a loaded configuration
+///
+/// Loading only checks the types of the values; this checks what the workers would
+/// otherwise reject (or silently disable) at runtime.
+///
+/// # Arguments
+///
+/// * `config` - The configuration
+///
+/// # Returns
+///
+/// * `Vec<String>` - The errors found, each prefixed with the offending key
+pub fn validate_config(config: &AppConfig) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut check = |key: &str, result: Result<(), String>| {
+        if let Err(e) = result {
+            errors.push(format!("{}: {}", key, e));
+        }
+    };
+
+    if let Some(timezone) = config.general.timezone.as_deref() {
+        check("general.timezone", validate_timezone(timezone.trim()));
+    }
+    check(
+        "general.temperature_decimals",
+        validate_decimals(config.general.temperature_decimals).map(|_| ()),
+    );
+
+    check("stove.ip", not_empty(&config.stove.ip));
+    check("stove.port", not_zero(config.stove.port));
+    check(
+        "stove.fallback_addresses",
+        StoveAddresses::from_config(&config.stove).map(|_| ()),
+    );
+    check(
+        "stove.wake_mac",
+        WakeOnLan::from_config(&config.stove).map(|_| ()),
+    );
+
+    check(
+        "http_api.ip",
+        config
+            .http_api
+            .ip
+            .parse::<IpAddr>()
+            .map(|_| ())
+            .map_err(|_| format!("'{}' is not an IP address", config.http_api.ip)),
+    );
+    check("http_api.port", not_zero(config.http_api.port));
+    if config.listeners.is_some() {
+        check(
+            "listeners",
+            Listeners::from_config(&config.http_api, config.listeners.as_ref()).and_then(
+                |listeners| {
+                    listeners.get_listeners().iter().try_for_each(|listener| {
+                        listener
+                            .load_tls()
+                            .map(|_| ())
+                            .map_err(|e| format!("{}: {}", listener.get_name(), e))
+                    })
+                },
+            ),
+        );
+    }
+    check(
+        "http_api.allowed_networks",
+        parse_networks(&config.http_api.allowed_networks).map(|_| ()),
+    );
+    check(
+        "http_api.trusted_proxies",
+        parse_networks(&config.http_api.trusted_proxies).map(|_| ()),
+    );
+    if let Some(auth) = &config.auth {
+        check("auth", ApiKeys::from_config(Some(auth)).map(|_| ()));
+    }
+
+    check(
+        "log.level",
+        LogSpecification::parse(&config.log.level)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    );
+    if matches!(config.log.get_target(), LogTarget::File | LogTarget::Both) {
+        check("log.directory", not_empty(&config.log.directory));
+    }
+    check(
+        "log.frame_mask",
+        parse_frame_mask(&config.log.frame_mask).map(|_| ()),
+    );
+    if config.log.max_file_size_mb == Some(0) {
+        check("log.max_file_size_mb", Err("must not be 0".into()));
+    }
+
+    if let Some(syslog) = config.log.syslog.as_deref() {
+        check("log.syslog", parse_syslog_url(syslog).map(|_| ()));
+    }
+
+    if let Some(maintenance) = &config.maintenance {
+        check(
+            "maintenance.windows",
+            MaintenanceSchedule::parse(&maintenance.windows).map(|_| ()),
+        );
+    }
+
+    if let Some(rules) = &config.rules {
+        if let Err(e) = parse_rules(rules) {
+            check("rules", Err(e));
+        }
+    }
+
+    if let Some(watchdog) = &config.freeze_watchdog {
+        if let Err(e) = parse_watched_fields(watchdog) {
+            check("freeze_watchdog", Err(e));
+        }
+    }
+
+    if let Some(alerts) = &config.alerts {
+        if let Err(e) = parse_alert_rules(alerts) {
+            check("alerts", Err(e));
+        }
+    }
+
+    if let Some(fan_curve) = &config.fan_curve {
+        check("fan_curve", parse_fan_curves(fan_curve).map(|_| ()));
+    }
+
+    if let Some(quiet_hours) = &config.quiet_hours {
+        check(
+            "quiet_hours",
+            QuietHours::from_config(quiet_hours).map(|_| ()),
+        );
+    }
+
+    if let Some(frost_guard) = &config.frost_guard {
+        check(
+            "frost_guard",
+            FrostGuard::from_config(frost_guard).map(|_| ()),
+        );
+    }
+
+    if let Some(history) = &config.history {
+        check(
+            "history.fields",
+            parse_history_fields(&history.fields).map(|_| ()),
+        );
+        check("history.levels", parse_levels(&history.levels).map(|_| ()));
+        if history.sample_secs == 0 {
+            check("history.sample_secs", Err("must not be 0".into()));
+        }
+    }
+
+    if let Some(monitor) = &config.ignition_monitor {
+        check(
+            "ignition_monitor.history_file",
+            not_empty(&monitor.history_file),
+        );
+        if monitor.slower_percent == 0 {
+            check(
+                "ignition_monitor.slower_percent",
+                Err("must not be 0".into()),
+            );
+        }
+        if monitor.consecutive == 0 {
+            check("ignition_monitor.consecutive", Err("must not be 0".into()));
+        }
+    }
+
+    if let Some(throttle) = &config.throttle {
+        check(
+            "throttle",
+            CommandThrottle::from_config(throttle).map(|_| ()),
+        );
+    }
+
+    if let Some(scripts) = &config.scripts {
+        check("scripts.directory", check_scripts(scripts));
+    }
+
+    if let Some(notifications) = &config.notifications {
+        check("notifications", check_notifications(notifications));
+        if let Some(url) = &notifications.webhook_url {
+            check("notifications.webhook_url", http_url(url));
+        }
+        if let Some(topic) = &notifications.mqtt_topic {
+            let topic = match &config.mqtt {
+                Some(mqtt) => render_topic(topic, mqtt.topic_prefix.trim_end_matches('/'), None),
+                None => Err("needs the [mqtt] section".into()),
+            };
+            check("notifications.mqtt_topic", topic.map(|_| ()));
+        }
+    }
+
+    if let Some(multiplexer) = &config.multiplexer {
+        check(
+            "multiplexer.ip",
+            multiplexer
+                .ip
+                .parse::<IpAddr>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not an IP address", multiplexer.ip)),
+        );
+        check("multiplexer.port", not_zero(multiplexer.port));
+    }
+
+    if let Some(grpc) = &config.grpc {
+        check(
+            "grpc.ip",
+            grpc.ip
+                .parse::<IpAddr>()
+                .map(|_| ())
+                .map_err(|_| format!("'{}' is not an IP address", grpc.ip)),
+        );
+        check("grpc.port", not_zero(grpc.port));
+    }
+
+    if let Some(plugins) = &config.plugins {
+        check(
+            "plugins",
+            PluginRegistry::from_config(Some(plugins)).map(|_| ()),
+        );
+    }
+
+    if let Some(webhooks) = &config.inbound_webhooks {
+        check("inbound_webhooks.secret", not_empty(&webhooks.secret));
+        let mut names: Vec<&String> = webhooks.actions.keys().collect();
+        names.sort();
+        for name in names {
+            check(
+                &format!("inbound_webhooks.{}", name),
+                parse_action(&webhooks.actions[name]).map(|_| ()),
+            );
+        }
+    }
+
+    if let Some(profiles) = &config.profiles {
+        let mut names: Vec<&String> = profiles.keys().collect();
+        names.sort();
+        for name in names {
+            check(
+                &format!("profiles.{}", name),
+                parse_profile(&profiles[name]).map(|_| ()),
+            );
+        }
+    }
+
+    if let Some(jeedom) = &config.jeedom {
+        check("jeedom.url", http_url(&jeedom.url));
+        match jeedom.platform.as_str() {
+            "jeedom" => check("jeedom.apikey", not_empty(&jeedom.apikey)),
+            "eedomus" => {
+                check("jeedom.api_user", not_empty(&jeedom.api_user));
+                check("jeedom.api_secret", not_empty(&jeedom.api_secret));
+            }
+            other => check(
+                "jeedom.platform",
+                Err(format!("unknown platform '{}' (jeedom or eedomus)", other)),
+            ),
+        }
+        check("jeedom.fields", non_empty_mapping(&jeedom.fields));
+    }
+
+    if let Some(openhab) = &config.openhab {
+        check("openhab.url", http_url(&openhab.url));
+        check(
+            "openhab.items",
+            parse_field_mapping(&openhab.items).map(|_| ()),
+        );
+        check(
+            "openhab.commands",
+            parse_command_items(&openhab.commands).map(|_| ()),
+        );
+        if openhab.items.trim().is_empty() && openhab.commands.trim().is_empty() {
+            check("openhab", Err("no item configured".into()));
+        }
+    }
+
+    if let Some(mqtt) = &config.mqtt {
+        check("mqtt.host", not_empty(&mqtt.host));
+        check("mqtt.client_id", not_empty(&mqtt.client_id));
+        check("mqtt.topic_prefix", not_empty(&mqtt.topic_prefix));
+        check(
+            "mqtt.status_topic",
+            render_topic(&mqtt.status_topic, &mqtt.topic_prefix, None).map(|_| ()),
+        );
+        check(
+            "mqtt.availability_topic",
+            render_topic(
+                &mqtt.availability_topic,
+                &mqtt.topic_prefix,
+                Some("default"),
+            )
+            .map(|_| ()),
+        );
+        if mqtt.port == 0 {
+            check("mqtt.port", Err("must not be 0".into()));
+        }
+    }
+
+    if let Some(home_assistant) = &config.home_assistant {
+        check("home_assistant.url", http_url(&home_assistant.url));
+        check("home_assistant.token", not_empty(&home_assistant.token));
+        check(
+            "home_assistant.power_kw",
+            parse_power_kw(&home_assistant.power_kw).map(|_| ()),
+        );
+        check(
+            "home_assistant.statistic_prefix",
+            not_empty(&home_assistant.statistic_prefix),
+        );
+        check(
+            "home_assistant.state_file",
+            not_empty(&home_assistant.state_file),
+        );
+        if home_assistant.sample_secs == 0 {
+            check("home_assistant.sample_secs", Err("must not be 0".into()));
+        }
+    }
+
+    errors
+}
+
+/// Checks that a value is not empty
+///
+/// # Arguments
+///
+/// * `value` - The value
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if it is empty
+fn not_empty(value: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err("must not be empty".into());
+    }
+    Ok(())
+}
+
+/// Checks that a port is not zero
+///
+/// # Arguments
+///
+/// * `port` - The port
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if it is zero
+fn not_zero(port: u16) -> Result<(), String> {
+    if port == 0 {
+        return Err("must not be 0".into());
+    }
+    Ok(())
+}
+
+/// Checks that a URL can be reached by the integrations HTTP client
+///
+/// # Arguments
+///
+/// * `url` - The URL
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if it is not an http:// or https:// URL
+fn http_url(url: &str) -> Result<(), String> {
+    if !url.starts_with("http://") && !http_client::is_https(url) {
+        return Err(format!("'{}' must start with http:// or https://", url));
+    }
+    Ok(())
+}
+
+/// Checks that a field mapping is valid and not empty
+///
+/// # Arguments
+///
+/// * `spec` - The mapping, a comma-separated list of `page.field=target`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or the parse error
+fn non_empty_mapping(spec: &str) -> Result<(), String> {
+    if parse_field_mapping(spec)?.is_empty() {
+        return Err("no field configured".into());
+    }
+    Ok(())
+}
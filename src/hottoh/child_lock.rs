@@ -0,0 +1,172 @@
+use crate::hottoh::config::HttpApiConfig;
+use crate::hottoh::http_api::{read_body, ApiError};
+use crate::hottoh::request_signing::SignedRequest;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Header carrying the PIN of state-changing requests
+pub const PIN_HEADER: &str = "X-Hottoh-Pin";
+/// Body field carrying the PIN, for clients that cannot set headers
+pub const PIN_FIELD: &str = "pin";
+/// Paths authenticated otherwise (signature, pairing code), not subject to the PIN nor
+/// to the request signing
+const EXEMPT_PATHS: &[&str] = &["/api/webhooks/", "/api/pairing"];
+
+/// PIN required by state-changing requests, like the child lock of the stove panel
+pub struct ChildLock {
+    /// The PIN (empty disables the lock)
+    pin: String,
+}
+
+impl ChildLock {
+    /// Creates the lock from the HTTP API configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The HTTP API configuration
+    ///
+    /// # Returns
+    ///
+    /// * `ChildLock` - The lock
+    pub fn from_config(config: &HttpApiConfig) -> Self {
+        Self {
+            pin: config.pin.trim().to_string(),
+        }
+    }
+
+    /// Gets the PIN, handed out to paired clients
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The PIN, or None if the lock is disabled
+    pub fn get_pin(&self) -> Option<&str> {
+        (!self.pin.is_empty()).then_some(self.pin.as_str())
+    }
+
+    /// Checks if a request needs the PIN
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Method of the request
+    /// * `path` - Path of the request
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the lock is enabled and the request may change the state
+    fn is_required(&self, method: &Method, path: &str) -> bool {
+        !self.pin.is_empty() && is_state_changing(method, path)
+    }
+
+    /// Checks a PIN
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The PIN received
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if it matches the configured one
+    pub fn verify(&self, pin: &str) -> bool {
+        // Constant-time comparison, to not leak how many digits match
+        pin.len() == self.pin.len()
+            && pin
+                .bytes()
+                .zip(self.pin.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// Checks if a request may change the state and is not authenticated by its own means
+///
+/// # Arguments
+///
+/// * `method` - Method of the request
+/// * `path` - Path of the request
+///
+/// # Returns
+///
+/// * `bool` - True for requests other than GET, HEAD and OPTIONS, except the inbound
+///   webhooks and the pairing
+pub fn is_state_changing(method: &Method, path: &str) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        && !EXEMPT_PATHS.iter().any(|prefix| path.starts_with(prefix))
+}
+
+/// Middleware rejecting state-changing requests without the right PIN with 401
+///
+/// The PIN is read from the `X-Hottoh-Pin` header, or from the `pin` field of a JSON
+/// body. Inbound webhooks are exempt since their body is signed, and so is the pairing
+/// of new clients, which uses a one-time code. Requests with a valid signature (see
+/// `http_api.signing_secret`) are accepted without the PIN.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<BoxBody>, Error>` - The response of the service, or 401
+pub async fn require_pin(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(lock) = req.app_data::<web::Data<Arc<ChildLock>>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if !lock.is_required(req.method(), req.path()) || req.extensions().contains::<SignedRequest>() {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let header = req
+        .headers()
+        .get(PIN_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let pin = match header {
+        Some(pin) => Some(pin),
+        None => {
+            let bytes = read_body(&mut req).await?;
+            body_pin(&bytes)
+        }
+    };
+
+    match pin {
+        Some(pin) if lock.verify(pin.trim()) => Ok(next.call(req).await?.map_into_boxed_body()),
+        Some(_) => {
+            let response = ApiError::Unauthorized("wrong PIN".into()).error_response();
+            Ok(req.into_response(response))
+        }
+        None => {
+            let response = ApiError::Unauthorized(format!(
+                "PIN required ({} header or '{}' body field)",
+                PIN_HEADER, PIN_FIELD
+            ))
+            .error_response();
+            Ok(req.into_response(response))
+        }
+    }
+}
+
+/// Reads the PIN from a JSON body
+///
+/// # Arguments
+///
+/// * `body` - The request body
+///
+/// # Returns
+///
+/// * `Option<String>` - The `pin` field, given as a string or a number, if any
+fn body_pin(body: &[u8]) -> Option<String> {
+    match serde_json::from_slice::<Value>(body).ok()?.get(PIN_FIELD)? {
+        Value::String(pin) => Some(pin.clone()),
+        Value::Number(pin) => Some(pin.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,159 @@
+use crate::hottoh::events::Event;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::pending::has_read_back;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::tcp_client::{queue_write_requests, CommandError, CommandOrigin};
+use crate::hottoh::tcp_client_structs::Request;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::sync::mpsc::Receiver;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Outcome of a write command sent to the stove
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandOutcome {
+    /// The stove acknowledged the command, but did not report the value in time (or
+    /// the command has no value to read back)
+    Accepted,
+    /// The stove reported the value set by the command
+    Confirmed(Value),
+    /// The stove did not acknowledge the command in time
+    TimedOut,
+    /// The command was dropped before being sent, `cancelled` or `expired`
+    Rejected(&'static str),
+}
+
+impl CommandOutcome {
+    /// Gets the name of the outcome, as returned by the API
+    ///
+    /// # Returns
+    ///
+    /// * `&'static str` - `accepted`, `confirmed`, `timed_out` or `rejected`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CommandOutcome::Accepted => "accepted",
+            CommandOutcome::Confirmed(_) => "confirmed",
+            CommandOutcome::TimedOut => "timed_out",
+            CommandOutcome::Rejected(_) => "rejected",
+        }
+    }
+}
+
+/// Handle on a queued write command, resolving to its outcome
+///
+/// The handle follows the command on the event bus: the stove acknowledging the
+/// write (`command_accepted`), reporting the value (`command_confirmed`), or the
+/// command being dropped (`command_dropped`).
+pub struct CommandHandle {
+    /// ID of the request
+    request_id: u32,
+    /// Whether the stove reports the value set by the command
+    read_back: bool,
+    /// Events published since before the command was queued
+    receiver: Receiver<Event>,
+}
+
+impl CommandHandle {
+    /// Gets the ID of the request
+    ///
+    /// # Returns
+    ///
+    /// * `u32` - The request ID, as used by `DELETE /api/command/{id}`
+    pub fn get_request_id(&self) -> u32 {
+        self.request_id
+    }
+
+    /// Waits for the outcome of the command
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time to wait
+    ///
+    /// # Returns
+    ///
+    /// * `CommandOutcome` - `Confirmed` as soon as the stove reports the value, else
+    ///   `Accepted` if it acknowledged the command, else `TimedOut`
+    pub fn wait(self, timeout: Duration) -> CommandOutcome {
+        let deadline = Instant::now() + timeout;
+        let mut accepted = false;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match self.receiver.recv_timeout(remaining) {
+                Ok(Event::CommandConfirmed {
+                    request_id, value, ..
+                }) if request_id == self.request_id => {
+                    return CommandOutcome::Confirmed(value);
+                }
+                Ok(Event::CommandAccepted { request_id }) if request_id == self.request_id => {
+                    if !self.read_back {
+                        return CommandOutcome::Accepted;
+                    }
+                    accepted = true;
+                }
+                Ok(Event::CommandDropped { request_id, reason })
+                    if request_id == self.request_id =>
+                {
+                    return match reason {
+                        "no_answer" => CommandOutcome::TimedOut,
+                        reason => CommandOutcome::Rejected(reason),
+                    };
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+        if accepted {
+            CommandOutcome::Accepted
+        } else {
+            CommandOutcome::TimedOut
+        }
+    }
+}
+
+/// Queues a write command and returns a handle on its outcome
+///
+/// The checks and the queuing are those of `queue_write_requests`.
+///
+/// # Arguments
+///
+/// * `request_queue` - The request queue
+/// * `request_id_counter` - The request ID counter
+/// * `shared_state` - Shared state where the pending value is recorded
+/// * `origin` - Source of the command
+/// * `action` - The StoveCommands value
+/// * `value` - The value sent to the stove
+/// * `ttl` - Delay after which the request is dropped if not sent, zero to keep it,
+///   None for the configured delay
+///
+/// # Returns
+///
+/// * `Result<CommandHandle, CommandError>` - The handle on the queued command, or the
+///   reason it was rejected
+pub fn send_command(
+    request_queue: &RwLock<VecDeque<Request>>,
+    request_id_counter: &Mutex<u32>,
+    shared_state: &RwLock<SharedState>,
+    origin: CommandOrigin,
+    action: u32,
+    value: &impl ToString,
+    ttl: Option<Duration>,
+) -> Result<CommandHandle, CommandError> {
+    // Subscribed before queuing, so that no event of the command is missed
+    let receiver = read_lock(shared_state, "shared state")
+        .get_events()
+        .subscribe();
+    let request_ids = queue_write_requests(
+        request_queue,
+        request_id_counter,
+        shared_state,
+        origin,
+        &[(action, value.to_string())],
+        ttl,
+    )?;
+    Ok(CommandHandle {
+        request_id: request_ids[0],
+        read_back: has_read_back(action),
+        receiver,
+    })
+}
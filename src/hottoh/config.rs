@@ -1,8 +1,10 @@
-use config::{Config, ConfigError, File, FileFormat};
-use serde::Deserialize;
+use config::{Config, ConfigError, Environment, File, FileFormat};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Configuration for logging
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 pub struct LogConfig {
     /// Log level (trace, debug, info, warn, error)
     pub level: String,
@@ -10,39 +12,797 @@ pub struct LogConfig {
     pub directory: String,
     /// Maximum number of log files to keep
     pub max_log_files: usize,
+    /// Size in megabytes at which the log file is rotated, or None to rotate it daily
+    #[serde(default)]
+    pub max_file_size_mb: Option<u64>,
+    /// Compress the rotated log files with gzip
+    #[serde(default)]
+    pub compress: bool,
+    /// Fields masked in the raw frames written to the logs, as comma-separated
+    /// `page.field` (e.g. `inf.hostname`)
+    #[serde(default = "default_frame_mask")]
+    pub frame_mask: String,
+    /// Length beyond which the raw frames written to the logs are truncated, 0 to keep
+    /// them whole
+    #[serde(default = "default_frame_max_len")]
+    pub frame_max_len: usize,
+    /// Write logs to files in `directory`, or only to stdout if false (ignored if `target` is set)
+    #[serde(default = "default_log_to_file")]
+    pub to_file: bool,
+    /// Where logs are written (stdout, file, both or journald)
+    #[serde(default)]
+    pub target: Option<LogTarget>,
+    /// Remote syslog collector also receiving the logs (udp://host:port or tcp://host:port)
+    #[serde(default)]
+    pub syslog: Option<String>,
+}
+
+impl LogConfig {
+    /// Gets where logs are written
+    ///
+    /// # Returns
+    ///
+    /// * `LogTarget` - The configured target, or the one matching `to_file` if absent
+    pub fn get_target(&self) -> LogTarget {
+        match self.target {
+            Some(target) => target,
+            None if self.to_file => LogTarget::Both,
+            None => LogTarget::Stdout,
+        }
+    }
+}
+
+/// Logs are written to files by default
+fn default_log_to_file() -> bool {
+    true
+}
+
+/// The hostname of the stove is masked in the logged frames by default
+fn default_frame_mask() -> String {
+    "inf.hostname".to_string()
+}
+
+/// Logged frames are truncated beyond 512 characters by default
+fn default_frame_max_len() -> usize {
+    512
+}
+
+/// Destination of the logs
+#[derive(Debug, Deserialize, JsonSchema, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// Standard output only
+    Stdout,
+    /// Rotating files only
+    File,
+    /// Rotating files, with info and above also written to stderr
+    Both,
+    /// systemd journal, with syslog priorities
+    Journald,
+}
+
+/// General settings
+#[derive(Debug, Deserialize, JsonSchema, Serialize, Default)]
+pub struct GeneralConfig {
+    /// IANA timezone used for local timestamps (e.g. Europe/Paris), system one if absent
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Hide identifying fields (hostname) from the API, logs and exported metrics
+    #[serde(default)]
+    pub redact: bool,
+    /// Reject every command to the stove (monitoring only), can be changed at runtime
+    /// with `POST /api/admin/read_only`
+    #[serde(default)]
+    pub read_only: bool,
+    /// File keeping the persistent bridge ID, `bridge.id` in the working directory if absent
+    #[serde(default)]
+    pub id_file: Option<String>,
+    /// File keeping the end of the time-limited run in progress, `run_timer.json` in the
+    /// working directory if absent
+    #[serde(default)]
+    pub run_timer_file: Option<String>,
+    /// Directory of the crash reports written on panic, `crashes` in the working
+    /// directory if absent
+    #[serde(default)]
+    pub crash_dir: Option<String>,
+    /// File receiving every response parsed from the stove as JSON lines, for research
+    /// (disabled if absent)
+    #[serde(default)]
+    pub response_log: Option<String>,
+    /// Decimals of the temperatures in the API and the integrations, 1 if absent or 0
+    /// for integers
+    #[serde(default)]
+    pub temperature_decimals: Option<u8>,
+    /// Rounding of the temperatures written to the stove, and of the integer ones
+    #[serde(default)]
+    pub temperature_rounding: TemperatureRounding,
+}
+
+/// Rounding of the halves
+#[derive(Debug, Deserialize, JsonSchema, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TemperatureRounding {
+    /// Halves rounded away from zero (20.25 gives 20.3)
+    #[default]
+    HalfUp,
+    /// Halves rounded to the even neighbour, or banker's rounding (20.25 gives 20.2)
+    HalfEven,
 }
 
 /// Configuration for the stove connection
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 pub struct StoveConfig {
     /// IP address of the stove
     pub ip: String,
     /// TCP port of the stove
     pub port: u16,
+    /// Window in seconds during which a response with the same req_id and CRC is
+    /// considered a retransmission and dropped (0 disables deduplication)
+    #[serde(default = "default_dedup_window_secs")]
+    pub dedup_window_secs: u64,
+    /// Seconds without a DAT0 response after which the connection is considered
+    /// half-open and reopened (0 disables the check)
+    #[serde(default = "default_stale_reconnect_secs")]
+    pub stale_reconnect_secs: u64,
+    /// Seconds after which a command not sent yet, e.g. while the stove is
+    /// disconnected, is dropped (0 keeps commands until they are sent)
+    #[serde(default = "default_command_ttl_secs")]
+    pub command_ttl_secs: u64,
+    /// Comma-separated `host:port` addresses tried in order when the primary one is
+    /// down (e.g. an Ethernet serial bridge in front of the Wi-Fi module)
+    #[serde(default)]
+    pub fallback_addresses: String,
+    /// Seconds after which the primary address is tried again while connected to a
+    /// fallback one (0 stays on the fallback until it fails)
+    #[serde(default = "default_failback_secs")]
+    pub failback_secs: u64,
+    /// Resolve the hostnames of the stove at each connection attempt, so that a stove
+    /// renumbered by DHCP is found again (false resolves them once)
+    #[serde(default = "default_resolve_on_reconnect")]
+    pub resolve_on_reconnect: bool,
+    /// MAC address of the Wi-Fi bridge, woken up with a magic packet when the stove
+    /// is unreachable (empty disables Wake-on-LAN)
+    #[serde(default)]
+    pub wake_mac: String,
+    /// Address the Wake-on-LAN magic packet is sent to
+    #[serde(default = "default_wake_broadcast")]
+    pub wake_broadcast: String,
+    /// Ping the stove and the gateway when the stove cannot be reached, to report
+    /// whether the stove or the network is down
+    #[serde(default)]
+    pub ping_check: bool,
+    /// Address of the gateway pinged by `ping_check` (empty only pings the stove)
+    #[serde(default)]
+    pub gateway: String,
+}
+
+/// Default response deduplication window in seconds
+fn default_dedup_window_secs() -> u64 {
+    5
+}
+
+/// Default delay without DAT0 before reconnecting, in seconds
+fn default_stale_reconnect_secs() -> u64 {
+    60
+}
+
+/// Default delay after which a command not sent yet is dropped, in seconds
+fn default_command_ttl_secs() -> u64 {
+    300
+}
+
+/// Default delay before trying the primary stove address again, in seconds
+fn default_failback_secs() -> u64 {
+    300
+}
+
+/// Hostnames of the stove are resolved again at each connection attempt by default
+fn default_resolve_on_reconnect() -> bool {
+    true
+}
+
+/// Default address of the Wake-on-LAN magic packet
+fn default_wake_broadcast() -> String {
+    "255.255.255.255:9".to_string()
 }
 
 /// Configuration for the HTTP API
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 pub struct HttpApiConfig {
     /// IP address to bind the HTTP server
     pub ip: String,
     /// Port to bind the HTTP server
     pub port: u16,
+    /// Log one successful GET request out of this many per route (1 logs all, 0 none);
+    /// commands and errors are always logged
+    #[serde(default = "default_access_log_sample")]
+    pub access_log_sample: u64,
+    /// Include request bodies in the access log (redacted for signed webhooks)
+    #[serde(default)]
+    pub access_log_bodies: bool,
+    /// Comma-separated networks allowed to use the API (e.g. `192.168.1.0/24`);
+    /// empty allows every client
+    #[serde(default)]
+    pub allowed_networks: String,
+    /// Comma-separated reverse proxies whose `X-Forwarded-For` header is trusted
+    #[serde(default)]
+    pub trusted_proxies: String,
+    /// PIN required by state-changing requests, in the `X-Hottoh-Pin` header or the `pin`
+    /// body field (empty disables the child lock)
+    #[serde(default)]
+    pub pin: String,
+    /// Secret used to sign state-changing requests with HMAC-SHA256, as an alternative
+    /// to the PIN (empty disables the signing)
+    #[serde(default)]
+    pub signing_secret: String,
+    /// Failed authentications (wrong PIN, signature or pairing code) within 10 minutes
+    /// after which a client is banned (0 disables the bans)
+    #[serde(default = "default_max_auth_failures")]
+    pub max_auth_failures: u32,
+}
+
+/// Every request is logged by default
+fn default_access_log_sample() -> u64 {
+    1
+}
+
+/// Default number of failed authentications before a ban
+fn default_max_auth_failures() -> u32 {
+    5
+}
+
+/// Configuration of the maintenance windows
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct MaintenanceConfig {
+    /// Comma-separated windows during which automation commands are suppressed and
+    /// manual ones need `force=true` (e.g. `sat 10:00-12:00, mon-fri 02:00-03:00`)
+    pub windows: String,
+}
+
+/// Configuration of the raw protocol multiplexer
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct MultiplexerConfig {
+    /// IP address to listen on, local clients only by default
+    #[serde(default = "default_multiplexer_ip")]
+    pub ip: String,
+    /// Port to listen on, where clients connect as if it was the stove
+    pub port: u16,
+}
+
+/// Default listening address of the multiplexer
+fn default_multiplexer_ip() -> String {
+    "127.0.0.1".to_string()
+}
+
+/// Configuration of the gRPC server
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct GrpcConfig {
+    /// IP address to listen on, local clients only by default
+    #[serde(default = "default_grpc_ip")]
+    pub ip: String,
+    /// Port to listen on
+    pub port: u16,
+}
+
+/// Default listening address of the gRPC server
+fn default_grpc_ip() -> String {
+    "127.0.0.1".to_string()
+}
+
+/// Configuration of the telemetry history
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct HistoryConfig {
+    /// Fields recorded, as comma-separated `page.field`
+    #[serde(default = "default_history_fields")]
+    pub fields: String,
+    /// Interval between two raw samples, in seconds
+    #[serde(default = "default_history_sample_secs")]
+    pub sample_secs: u64,
+    /// Levels of the history, as comma-separated `bucket:retention`, where `bucket` is
+    /// `raw` or a span such as `15m`
+    #[serde(default = "default_history_levels")]
+    pub levels: String,
+}
+
+/// Default fields of the history: temperatures and power
+fn default_history_fields() -> String {
+    "dat0.ambient_t1,dat0.smoke_t,dat0.power_level".to_string()
+}
+
+/// Default interval between two raw samples of the history
+fn default_history_sample_secs() -> u64 {
+    10
+}
+
+/// Default levels of the history: raw for 6 hours, then 1-minute aggregates for
+/// 7 days and 15-minute ones for a year
+fn default_history_levels() -> String {
+    "raw:6h,1m:7d,15m:365d".to_string()
+}
+
+/// Configuration of the ignition monitor
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct IgnitionMonitorConfig {
+    /// File keeping the durations and smoke temperature ramps of the past ignitions
+    #[serde(default = "default_ignition_history_file")]
+    pub history_file: String,
+    /// Slowdown compared to the baseline from which an ignition is abnormal, in percent
+    #[serde(default = "default_ignition_slower_percent")]
+    pub slower_percent: u32,
+    /// Abnormal ignitions in a row after which a maintenance is suggested
+    #[serde(default = "default_ignition_consecutive")]
+    pub consecutive: usize,
+}
+
+/// Default file of the ignition history
+fn default_ignition_history_file() -> String {
+    "ignition_history.json".to_string()
+}
+
+/// Default slowdown from which an ignition is abnormal, in percent
+fn default_ignition_slower_percent() -> u32 {
+    25
+}
+
+/// Default number of abnormal ignitions in a row before suggesting a maintenance
+fn default_ignition_consecutive() -> usize {
+    3
+}
+
+/// Configuration of the fan curve
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct FanCurveConfig {
+    /// Speeds of fan 1 by power level, as `power=speed` (e.g. `1=1, 3=2, 5=4`)
+    #[serde(default)]
+    pub fan_1: Option<String>,
+    /// Speeds of fan 2 by power level
+    #[serde(default)]
+    pub fan_2: Option<String>,
+    /// Speeds of fan 3 by power level
+    #[serde(default)]
+    pub fan_3: Option<String>,
+    /// Delay during which a fan set manually is not changed by the curve
+    #[serde(default = "default_fan_override_secs")]
+    pub override_secs: u64,
+}
+
+/// Default delay during which a fan set manually is left alone
+fn default_fan_override_secs() -> u64 {
+    1800
+}
+
+/// Configuration of the quiet hours
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct QuietHoursConfig {
+    /// Comma-separated windows, with the syntax of the maintenance windows
+    /// (e.g. `daily 22:00-07:00`)
+    pub windows: String,
+    /// Highest power level during the quiet hours (unchanged if absent)
+    #[serde(default)]
+    pub max_power: Option<u64>,
+    /// Highest speed of the fans during the quiet hours (unchanged if absent)
+    #[serde(default)]
+    pub max_fan_speed: Option<u64>,
+}
+
+/// Configuration of the frost guard
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct FrostGuardConfig {
+    /// Comma-separated temperatures watched, as `page.field` (e.g. `dat0.ambient_t1, dat0.water`)
+    #[serde(default = "default_frost_guard_fields")]
+    pub fields: String,
+    /// Temperature under which the stove is turned on, in °C
+    #[serde(default = "default_frost_guard_min_temp")]
+    pub min_temp: f64,
+    /// Power level at which the stove is turned on
+    #[serde(default = "default_frost_guard_power")]
+    pub power: u32,
+}
+
+/// Default temperature watched by the frost guard
+fn default_frost_guard_fields() -> String {
+    "dat0.ambient_t1".to_string()
+}
+
+/// Default temperature under which the stove is turned on, in °C
+fn default_frost_guard_min_temp() -> f64 {
+    5.0
+}
+
+/// Default power level at which the frost guard turns the stove on
+fn default_frost_guard_power() -> u32 {
+    1
+}
+
+/// Configuration of the plugins
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct PluginsConfig {
+    /// Comma-separated built-in plugins to load (e.g. `event_log`)
+    #[serde(default)]
+    pub enabled: String,
+    /// Comma-separated commands added to the protocol, as `Name=number`
+    /// (e.g. `SetRecipe2=17`), sent with `POST /api/plugins/commands/{name}`
+    #[serde(default)]
+    pub commands: String,
+}
+
+/// Configuration of the scripting hooks
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct ScriptsConfig {
+    /// Directory of the `.rhai` scripts, reloaded when a file changes
+    pub directory: String,
+    /// Operations a hook may run before it is aborted, to stop endless loops (0 for no
+    /// limit)
+    #[serde(default = "default_script_max_operations")]
+    pub max_operations: u64,
+}
+
+/// Default operation budget of a script hook
+fn default_script_max_operations() -> u64 {
+    100_000
+}
+
+/// Configuration of the inbound webhooks
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct InboundWebhooksConfig {
+    /// Shared secret used to sign the webhook bodies (HMAC-SHA256)
+    pub secret: String,
+    /// Named actions, each a comma-separated list of `Command=value`
+    /// (e.g. `stove_on = OnOff=1,PowerLevel=3`)
+    #[serde(flatten)]
+    pub actions: HashMap<String, String>,
+}
+
+/// Configuration of the push to Jeedom or eedomus virtual devices
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct JeedomConfig {
+    /// Base URL of the box (e.g. http://192.168.1.10)
+    pub url: String,
+    /// Platform of the box (jeedom or eedomus)
+    #[serde(default = "default_jeedom_platform")]
+    pub platform: String,
+    /// Jeedom API key
+    #[serde(default)]
+    pub apikey: String,
+    /// eedomus API user
+    #[serde(default)]
+    pub api_user: String,
+    /// eedomus API secret
+    #[serde(default)]
+    pub api_secret: String,
+    /// Fields to push, as a comma-separated list of `page.field=command id`
+    pub fields: String,
+    /// Interval in seconds between two checks for changed values
+    #[serde(default = "default_jeedom_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// Default Jeedom platform
+fn default_jeedom_platform() -> String {
+    "jeedom".to_string()
+}
+
+/// Default interval between two Jeedom pushes in seconds
+fn default_jeedom_interval_secs() -> u64 {
+    30
+}
+
+/// Configuration of the openHAB item synchronization
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct OpenHabConfig {
+    /// Base URL of openHAB (e.g. http://192.168.1.20:8080)
+    pub url: String,
+    /// API token (openHAB 3 and later), empty if not required
+    #[serde(default)]
+    pub token: String,
+    /// State items, as a comma-separated list of `page.field=item`
+    #[serde(default)]
+    pub items: String,
+    /// Command items, as a comma-separated list of `Command=item`
+    #[serde(default)]
+    pub commands: String,
+    /// Interval in seconds between two synchronizations
+    #[serde(default = "default_openhab_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// Default interval between two openHAB synchronizations in seconds
+fn default_openhab_interval_secs() -> u64 {
+    10
+}
+
+/// Configuration of the availability topics published to an MQTT broker
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct MqttConfig {
+    /// Host name or IP address of the broker
+    pub host: String,
+    /// Port of the broker
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    /// Client ID of the bridge
+    #[serde(default = "default_mqtt_client_id")]
+    pub client_id: String,
+    /// User name, if required by the broker
+    #[serde(default)]
+    pub username: Option<String>,
+    /// Password, if required by the broker
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Prefix of the topics, substituted for `{prefix}` in the topic templates
+    #[serde(default = "default_mqtt_topic_prefix")]
+    pub topic_prefix: String,
+    /// Template of the availability topic of the bridge
+    #[serde(default = "default_mqtt_status_topic")]
+    pub status_topic: String,
+    /// Template of the availability topic of a stove, with `{stove_id}` substituted
+    #[serde(default = "default_mqtt_availability_topic")]
+    pub availability_topic: String,
+    /// Keep-alive interval in seconds, after which the broker publishes the Last Will
+    #[serde(default = "default_mqtt_keep_alive_secs")]
+    pub keep_alive_secs: u64,
+}
+
+/// Default port of the MQTT broker
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+/// Default MQTT client ID
+fn default_mqtt_client_id() -> String {
+    "hottoh_api".to_string()
+}
+
+/// Default prefix of the MQTT topics
+fn default_mqtt_topic_prefix() -> String {
+    "hottoh".to_string()
+}
+
+/// Default template of the MQTT availability topic of the bridge
+fn default_mqtt_status_topic() -> String {
+    "{prefix}/status".to_string()
+}
+
+/// Default template of the MQTT availability topic of a stove
+fn default_mqtt_availability_topic() -> String {
+    "{prefix}/{stove_id}/availability".to_string()
+}
+
+/// Default MQTT keep-alive interval in seconds
+fn default_mqtt_keep_alive_secs() -> u64 {
+    30
+}
+
+/// Configuration of the notifications sent on events, with their payload templates
+///
+/// A template is minijinja text, or `@` followed by the path of a file holding it. It sees
+/// `event` (the event, with its `type`), `state` (the shared state as returned by
+/// `/api/state`) and `time`. Without a template the payload is the event as JSON.
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct NotificationsConfig {
+    /// Comma-separated event types notified (e.g. `alarm_raised, frost_alert`)
+    #[serde(default = "default_notification_events")]
+    pub events: String,
+    /// URL the notifications are POSTed to (disabled if absent)
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Template of the webhook body
+    #[serde(default)]
+    pub webhook_template: Option<String>,
+    /// Topic the notifications are published to, through the `[mqtt]` broker (disabled
+    /// if absent)
+    #[serde(default)]
+    pub mqtt_topic: Option<String>,
+    /// Template of the MQTT payload
+    #[serde(default)]
+    pub mqtt_template: Option<String>,
+    /// Token of the Telegram bot sending the notifications (disabled if absent)
+    #[serde(default)]
+    pub telegram_token: Option<String>,
+    /// Chat the Telegram notifications are sent to
+    #[serde(default)]
+    pub telegram_chat_id: Option<String>,
+    /// Template of the Telegram message
+    #[serde(default)]
+    pub telegram_template: Option<String>,
+}
+
+/// Default event types notified
+fn default_notification_events() -> String {
+    "alarm_raised, frost_alert, alert_firing, alert_resolved, value_frozen, maintenance_suggested"
+        .to_string()
+}
+
+/// Configuration of the long-term statistics pushed to Home Assistant
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct HomeAssistantConfig {
+    /// Base URL of Home Assistant (e.g. http://192.168.1.30:8123)
+    pub url: String,
+    /// Long-lived access token
+    pub token: String,
+    /// Heat output at each power level in kW, comma-separated from power level 1
+    pub power_kw: String,
+    /// Prefix of the statistic IDs (`<prefix>:energy`, `<prefix>:runtime`), also
+    /// their source in Home Assistant
+    #[serde(default = "default_home_assistant_prefix")]
+    pub statistic_prefix: String,
+    /// File keeping the totals and the hours not pushed yet
+    #[serde(default = "default_home_assistant_state_file")]
+    pub state_file: String,
+    /// Interval in seconds between two samples of the state of the stove
+    #[serde(default = "default_home_assistant_sample_secs")]
+    pub sample_secs: u64,
+}
+
+/// Default prefix of the Home Assistant statistic IDs
+fn default_home_assistant_prefix() -> String {
+    "hottoh".to_string()
+}
+
+/// Default file of the Home Assistant statistics totals
+fn default_home_assistant_state_file() -> String {
+    "ha_statistics.json".to_string()
+}
+
+/// Default interval between two samples for the Home Assistant statistics in seconds
+fn default_home_assistant_sample_secs() -> u64 {
+    10
+}
+
+/// Configuration of the authentication of the HTTP API
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
+pub struct AuthConfig {
+    /// API keys, as a comma-separated list of `name=key`, the name being only used in
+    /// the logs and optionally followed by the role (`name:read=key` for a read-only key)
+    #[serde(default)]
+    pub keys: String,
+    /// File keeping the API keys issued to the clients paired with `POST /api/pairing`
+    /// (`paired_keys` in the working directory if absent)
+    #[serde(default)]
+    pub paired_keys_file: Option<String>,
+    /// Shared secret of the HS256 JSON Web Tokens, at least 32 characters (HS256 tokens
+    /// rejected if absent)
+    #[serde(default)]
+    pub jwt_secret: Option<String>,
+    /// https:// URL of the JSON Web Key Set of the RS256 tokens of an identity provider
+    /// (RS256 tokens rejected if absent, as well as `jwks_file`)
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Local JSON Web Key Set file of the RS256 tokens, pinned instead of `jwks_url`
+    #[serde(default)]
+    pub jwks_file: Option<String>,
+    /// Expected `iss` claim of the tokens (not checked if absent)
+    #[serde(default)]
+    pub jwt_issuer: Option<String>,
+    /// Expected `aud` claim of the tokens (not checked if absent)
+    #[serde(default)]
+    pub jwt_audience: Option<String>,
+    /// Claim holding the roles, a space-separated string or an array
+    #[serde(default = "default_jwt_role_claim")]
+    pub jwt_role_claim: String,
+    /// Comma-separated claim values granting the control role
+    #[serde(default = "default_jwt_control_roles")]
+    pub jwt_control_roles: String,
+    /// Comma-separated claim values granting the read-only role
+    #[serde(default = "default_jwt_read_roles")]
+    pub jwt_read_roles: String,
+}
+
+/// Default claim holding the roles of the JSON Web Tokens
+fn default_jwt_role_claim() -> String {
+    "roles".to_string()
+}
+
+/// Default claim value granting the control role
+fn default_jwt_control_roles() -> String {
+    "control".to_string()
+}
+
+/// Default claim value granting the read-only role
+fn default_jwt_read_roles() -> String {
+    "read".to_string()
 }
 
 /// Main application configuration
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema, Serialize)]
 pub struct AppConfig {
+    /// General settings
+    #[serde(default)]
+    pub general: GeneralConfig,
     /// Stove connection configuration
     pub stove: StoveConfig,
     /// HTTP API configuration
     pub http_api: HttpApiConfig,
     /// Logging configuration
     pub log: LogConfig,
+    /// API keys required by the HTTP API (no authentication if absent)
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Listeners of the HTTP API by name, each a comma-separated list of `ip`, `port`,
+    /// `tls_cert`, `tls_key` and `auth` settings (`http_api.ip` and `http_api.port` if
+    /// absent)
+    #[serde(default)]
+    pub listeners: Option<HashMap<String, String>>,
+    /// Inbound webhooks configuration (disabled if absent)
+    #[serde(default)]
+    pub inbound_webhooks: Option<InboundWebhooksConfig>,
+    /// Jeedom/eedomus push configuration (disabled if absent)
+    #[serde(default)]
+    pub jeedom: Option<JeedomConfig>,
+    /// openHAB synchronization configuration (disabled if absent)
+    #[serde(default)]
+    pub openhab: Option<OpenHabConfig>,
+    /// Availability topics published to an MQTT broker (disabled if absent)
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+    /// Notifications sent on events (disabled if absent)
+    #[serde(default)]
+    pub notifications: Option<NotificationsConfig>,
+    /// Long-term statistics pushed to Home Assistant (disabled if absent)
+    #[serde(default)]
+    pub home_assistant: Option<HomeAssistantConfig>,
+    /// Maintenance windows (none if absent)
+    #[serde(default)]
+    pub maintenance: Option<MaintenanceConfig>,
+    /// Automation rules by name, each `<condition> => <actions>` (none if absent)
+    #[serde(default)]
+    pub rules: Option<HashMap<String, String>>,
+    /// Plugins and additional commands (none if absent)
+    #[serde(default)]
+    pub plugins: Option<PluginsConfig>,
+    /// Scripting hooks (disabled if absent)
+    #[serde(default)]
+    pub scripts: Option<ScriptsConfig>,
+    /// Raw protocol multiplexer (disabled if absent)
+    #[serde(default)]
+    pub multiplexer: Option<MultiplexerConfig>,
+    /// gRPC server, with the state and the commands of the HTTP API (disabled if absent)
+    #[serde(default)]
+    pub grpc: Option<GrpcConfig>,
+    /// Throttling profiles, `default` and by manufacturer, each a comma-separated list of
+    /// `max_writes_per_minute`, `min_on_off_gap_secs` and `max_setpoint_step` limits
+    /// (built-in limits if absent)
+    #[serde(default)]
+    pub throttle: Option<HashMap<String, String>>,
+    /// Fields expected to vary by `page.field`, each a comma-separated list of
+    /// `after_secs`, `states` and `notify` settings (disabled if absent)
+    #[serde(default)]
+    pub freeze_watchdog: Option<HashMap<String, String>>,
+    /// Alert rules by name, each a comma-separated list of `field`, condition (`above`,
+    /// `below`, `rise_per_min` or `fall_per_min`), `for`, `resolve_for`, `priority`,
+    /// `labels` and `repeat` settings (disabled if absent)
+    #[serde(default)]
+    pub alerts: Option<HashMap<String, String>>,
+    /// History of the telemetry (disabled if absent)
+    #[serde(default)]
+    pub history: Option<HistoryConfig>,
+    /// Detection of slowing ignitions (disabled if absent)
+    #[serde(default)]
+    pub ignition_monitor: Option<IgnitionMonitorConfig>,
+    /// Fan speeds enforced by power level (disabled if absent)
+    #[serde(default)]
+    pub fan_curve: Option<FanCurveConfig>,
+    /// Limits applied during the quiet hours (disabled if absent)
+    #[serde(default)]
+    pub quiet_hours: Option<QuietHoursConfig>,
+    /// Stove turned on when a temperature gets too low (disabled if absent)
+    #[serde(default)]
+    pub frost_guard: Option<FrostGuardConfig>,
+    /// Named profiles, each a comma-separated list of `Command=value` settings
+    /// (none if absent)
+    #[serde(default)]
+    pub profiles: Option<HashMap<String, String>>,
 }
 
+/// Prefix of the environment variables overriding the configuration
+const ENV_PREFIX: &str = "HOTTOH";
+/// Separator between the section and the key in the environment variable names
+const ENV_SEPARATOR: &str = "__";
+
 /// Loads the application configuration from a file
 ///
+/// Any key can be overridden by an environment variable named
+/// `HOTTOH_<SECTION>__<KEY>` (e.g. `HOTTOH_STOVE__IP=192.168.1.100`).
+///
 /// # Arguments
 ///
 /// * `config_path` - Optional path to the configuration file. If not provided, "config" is used.
@@ -54,6 +814,61 @@ pub fn load_config(config_path: Option<&str>) -> Result<AppConfig, ConfigError>
     let path = config_path.unwrap_or("config");
     let settings = Config::builder()
         .add_source(File::new(path, FileFormat::Ini))
+        .add_source(
+            Environment::with_prefix(ENV_PREFIX)
+                .prefix_separator("_")
+                .separator(ENV_SEPARATOR),
+        )
         .build()?;
     settings.try_deserialize::<AppConfig>()
 }
+
+/// Usage of the config-schema subcommand
+const SCHEMA_USAGE: &str = "Usage: hottoh_api config-schema
+
+Prints the JSON Schema of the configuration, for editors and config management
+tools. The INI values are strings, converted to the types of the schema on load.";
+
+/// Runs `hottoh_api config-schema`
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `config-schema`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success if the schema was printed, or an error
+pub fn run_config_schema(args: &[String]) -> Result<(), String> {
+    match args {
+        [] => {}
+        [flag] if flag == "--help" || flag == "-h" => {
+            println!("{}", SCHEMA_USAGE);
+            return Ok(());
+        }
+        _ => return Err(format!("Unexpected arguments\n\n{}", SCHEMA_USAGE)),
+    }
+    let schema = serde_json::to_string_pretty(&schemars::schema_for!(AppConfig))
+        .map_err(|e| format!("Cannot serialize the schema: {}", e))?;
+    println!("{}", schema);
+    Ok(())
+}
+
+/// Lists the environment variables overriding the configuration
+///
+/// # Returns
+///
+/// * `Vec<(String, String)>` - The (variable, overridden key) pairs, sorted by variable
+pub fn get_env_overrides() -> Vec<(String, String)> {
+    let prefix = format!("{}_", ENV_PREFIX);
+    let mut overrides: Vec<(String, String)> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let key = name
+                .strip_prefix(&prefix)?
+                .to_lowercase()
+                .replace(ENV_SEPARATOR, ".");
+            Some((name, key))
+        })
+        .collect();
+    overrides.sort();
+    overrides
+}
@@ -0,0 +1,203 @@
+use crate::hottoh::hottoh_const::{StoveCommands, StoveState};
+use crate::hottoh::inbound_webhooks::to_hex;
+use crate::hottoh::locks::mutex_lock;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Validity of a confirmation token
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(30);
+
+/// Checks if a command is destructive in the current state of the stove
+///
+/// Turning off a running stove, and the experimental recipe and pellet setpoint
+/// commands, need a confirmation.
+///
+/// # Arguments
+///
+/// * `action` - The StoveCommands value
+/// * `value` - The value sent to the stove
+/// * `stove_state` - The current state of the stove
+///
+/// # Returns
+///
+/// * `bool` - True if the command needs a confirmation
+pub fn needs_confirmation(action: u32, value: &str, stove_state: &StoveState) -> bool {
+    if action == StoveCommands::OnOff as u32 {
+        value.trim() == "0" && *stove_state == StoveState::Power
+    } else {
+        action == StoveCommands::HottohSetRecipe as u32
+            || action == StoveCommands::HottohSetPelSetpoint as u32
+    }
+}
+
+/// Command waiting for its confirmation
+#[derive(Debug)]
+struct PendingConfirmation {
+    action: u32,
+    value: String,
+    expires_at: Instant,
+}
+
+/// Tokens confirming destructive commands
+///
+/// A destructive command (e.g. turning off a running stove) is not queued on the
+/// first call: a short-lived token is returned instead, and the command is only
+/// queued when it is sent again with this token. This prevents accidental shutdowns
+/// from a mistyped tap on a dashboard.
+#[derive(Debug, Default)]
+pub struct ConfirmationTokens {
+    tokens: Mutex<HashMap<String, PendingConfirmation>>,
+}
+
+impl ConfirmationTokens {
+    /// Creates an empty token store
+    ///
+    /// # Returns
+    ///
+    /// * `ConfirmationTokens` - The token store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Issues a token for a command
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - The StoveCommands value
+    /// * `value` - The value of the command
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - The token, or an error if no random bytes are available
+    pub fn issue(&self, action: u32, value: &str) -> Result<String, String> {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).map_err(|e| format!("cannot generate token: {}", e))?;
+        let token = to_hex(&bytes);
+        let mut tokens = mutex_lock(&self.tokens, "confirmation tokens");
+        tokens.retain(|_, pending| pending.expires_at > Instant::now());
+        tokens.insert(
+            token.clone(),
+            PendingConfirmation {
+                action,
+                value: value.trim().to_string(),
+                expires_at: Instant::now() + CONFIRMATION_TTL,
+            },
+        );
+        Ok(token)
+    }
+
+    /// Consumes a token
+    ///
+    /// A token can only be used once, and only for a request holding the command it was
+    /// issued for.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token received
+    /// * `commands` - The StoveCommands values with the values of the request
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the token is valid for one of the commands
+    pub fn redeem(&self, token: &str, commands: &[(u32, String)]) -> bool {
+        let mut tokens = mutex_lock(&self.tokens, "confirmation tokens");
+        match tokens.remove(token.trim()) {
+            Some(pending) => {
+                pending.expires_at > Instant::now()
+                    && commands.iter().any(|(action, value)| {
+                        pending.action == *action && pending.value == value.trim()
+                    })
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ON_OFF: u32 = StoveCommands::OnOff as u32;
+
+    #[test]
+    fn needs_confirmation_only_for_destructive_commands() {
+        assert!(needs_confirmation(ON_OFF, " 0 ", &StoveState::Power));
+        assert!(!needs_confirmation(ON_OFF, "0", &StoveState::Off));
+        assert!(!needs_confirmation(ON_OFF, "1", &StoveState::Power));
+        assert!(needs_confirmation(
+            StoveCommands::HottohSetRecipe as u32,
+            "2",
+            &StoveState::Off
+        ));
+        assert!(needs_confirmation(
+            StoveCommands::HottohSetPelSetpoint as u32,
+            "5",
+            &StoveState::Off
+        ));
+        assert!(!needs_confirmation(
+            StoveCommands::PowerLevel as u32,
+            "3",
+            &StoveState::Power
+        ));
+    }
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let tokens = ConfirmationTokens::new();
+        let first = tokens.issue(ON_OFF, "0").unwrap();
+        let second = tokens.issue(ON_OFF, "0").unwrap();
+        assert_eq!(first.len(), 32);
+        assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn redeem_accepts_a_token_once_for_its_command() {
+        let tokens = ConfirmationTokens::new();
+        let token = tokens.issue(ON_OFF, " 0").unwrap();
+        let commands = [
+            (StoveCommands::PowerLevel as u32, "3".to_string()),
+            (ON_OFF, "0 ".to_string()),
+        ];
+        assert!(tokens.redeem(&format!(" {} ", token), &commands));
+        assert!(!tokens.redeem(&token, &commands));
+    }
+
+    #[test]
+    fn redeem_rejects_other_commands_and_consumes_the_token() {
+        let tokens = ConfirmationTokens::new();
+        let token = tokens.issue(ON_OFF, "0").unwrap();
+        assert!(!tokens.redeem(&token, &[(ON_OFF, "1".to_string())]));
+        // A token used for the wrong command cannot be retried
+        assert!(!tokens.redeem(&token, &[(ON_OFF, "0".to_string())]));
+
+        let token = tokens.issue(ON_OFF, "0").unwrap();
+        assert!(!tokens.redeem(
+            &token,
+            &[(StoveCommands::HottohSetRecipe as u32, "0".to_string())]
+        ));
+        assert!(!tokens.redeem("unknown", &[(ON_OFF, "0".to_string())]));
+    }
+
+    #[test]
+    fn expired_tokens_are_rejected_and_pruned() {
+        let tokens = ConfirmationTokens::new();
+        let token = tokens.issue(ON_OFF, "0").unwrap();
+        let expire = |token: &str| {
+            mutex_lock(&tokens.tokens, "test")
+                .get_mut(token)
+                .unwrap()
+                .expires_at = Instant::now();
+        };
+        expire(&token);
+        assert!(!tokens.redeem(&token, &[(ON_OFF, "0".to_string())]));
+
+        let expired = tokens.issue(ON_OFF, "0").unwrap();
+        expire(&expired);
+        let valid = tokens.issue(ON_OFF, "0").unwrap();
+        let pending = mutex_lock(&tokens.tokens, "test");
+        assert!(!pending.contains_key(&expired));
+        assert!(pending.contains_key(&valid));
+    }
+}
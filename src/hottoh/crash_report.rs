@@ -0,0 +1,206 @@
+use crate::hottoh::build_info::{GIT_HASH, VERSION};
+use crate::hottoh::diagnostics::{latest_log_file, tail_lines};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use chrono::Local;
+use serde_json::{json, Value};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::{self, PanicHookInfo};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::thread;
+
+/// Directory of the crash reports, in the working directory, if not configured
+pub const DEFAULT_CRASH_DIR: &str = "crashes";
+
+/// Number of log lines included in a crash report
+const CRASH_LOG_LINES: usize = 100;
+
+/// Number of crash reports kept in the directory, the oldest ones being removed
+const MAX_CRASH_REPORTS: usize = 10;
+
+/// Prefix of the names of the crash report files
+const CRASH_FILE_PREFIX: &str = "crash-";
+
+/// Installs a panic hook writing a crash report for every panic
+///
+/// The report is a JSON file with the panic message and location, the backtrace, the
+/// last log lines and a summary of the state, written to `directory` before the
+/// previous hook runs. Supervised threads are still restarted afterwards.
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the crash reports
+/// * `log_directory` - Directory of the log files, or None if logs are not written to files
+/// * `shared_state` - Shared state summarized in the reports
+/// * `metrics` - Runtime metrics summarized in the reports
+pub fn install_crash_reporter(
+    directory: String,
+    log_directory: Option<String>,
+    shared_state: Arc<RwLock<SharedState>>,
+    metrics: Arc<Metrics>,
+) {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let report = build_report(info, log_directory.as_deref(), &shared_state, &metrics);
+        match write_report(Path::new(&directory), &report) {
+            Ok(path) => eprintln!("Crash report written to {}", path.display()),
+            Err(e) => eprintln!("Cannot write crash report to '{}': {}", directory, e),
+        }
+        previous(info);
+    }));
+}
+
+/// Builds the crash report of a panic
+///
+/// # Arguments
+///
+/// * `info` - The panic information
+/// * `log_directory` - Directory of the log files, if any
+/// * `shared_state` - Shared state to summarize
+/// * `metrics` - Runtime metrics to summarize
+///
+/// # Returns
+///
+/// * `Value` - The report
+fn build_report(
+    info: &PanicHookInfo,
+    log_directory: Option<&str>,
+    shared_state: &RwLock<SharedState>,
+    metrics: &Metrics,
+) -> Value {
+    let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = info.payload().downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    };
+
+    // Make sure buffered log lines are written before reading the file
+    log::logger().flush();
+    let log = match log_directory.and_then(|directory| latest_log_file(Path::new(directory))) {
+        Some(path) => tail_lines(&path, CRASH_LOG_LINES),
+        None => String::from("No log file found\n"),
+    };
+
+    json!({
+        "time": Local::now().to_rfc3339(),
+        "version": VERSION,
+        "git_hash": GIT_HASH,
+        "thread": thread::current().name().unwrap_or("unnamed"),
+        "message": message,
+        "location": info.location().map(|location| location.to_string()),
+        "backtrace": Backtrace::force_capture().to_string(),
+        "state": state_summary(shared_state, metrics),
+        "log": log.lines().collect::<Vec<_>>(),
+    })
+}
+
+/// Summarizes the state of the bridge for a crash report
+///
+/// The panicking thread may hold the lock of the shared state, so it is not waited for.
+///
+/// # Arguments
+///
+/// * `shared_state` - The shared state
+/// * `metrics` - Runtime metrics
+///
+/// # Returns
+///
+/// * `Value` - The summary, without the stove data if the shared state is locked
+fn state_summary(shared_state: &RwLock<SharedState>, metrics: &Metrics) -> Value {
+    let stove = match shared_state.try_read() {
+        Ok(state) => json!({
+            "state_version": state.get_version().get(),
+            "stove_state": state.get_dat0().get_index_stove_state(),
+            "firmware": state.get_inf().get_version(),
+            "commands_pending": !state.get_pending().is_empty(),
+            "read_only": state.is_read_only(),
+        }),
+        Err(_) => Value::String("shared state locked".into()),
+    };
+    json!({
+        "uptime_seconds": metrics.get_uptime_secs(),
+        "stove_address": metrics.get_stove_address().map(|(address, fallback)| json!({
+            "address": address,
+            "fallback": fallback,
+        })),
+        "thread_crashes": metrics.get_thread_crashes(),
+        "stove": stove,
+    })
+}
+
+/// Writes a crash report and removes the oldest ones
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the crash reports, created if needed
+/// * `report` - The report
+///
+/// # Returns
+///
+/// * `std::io::Result<PathBuf>` - The path of the report, or the write error
+fn write_report(directory: &Path, report: &Value) -> std::io::Result<PathBuf> {
+    fs::create_dir_all(directory)?;
+    let name = format!(
+        "{}{}.json",
+        CRASH_FILE_PREFIX,
+        Local::now().format("%Y%m%d-%H%M%S%.3f")
+    );
+    let path = directory.join(name);
+    fs::write(
+        &path,
+        serde_json::to_string_pretty(report).unwrap_or_default(),
+    )?;
+
+    let reports = list_reports(directory);
+    for old in reports
+        .iter()
+        .take(reports.len().saturating_sub(MAX_CRASH_REPORTS))
+    {
+        let _ = fs::remove_file(old);
+    }
+    Ok(path)
+}
+
+/// Lists the crash reports of a directory, oldest first
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the crash reports
+///
+/// # Returns
+///
+/// * `Vec<PathBuf>` - The reports, sorted by name, i.e. by time
+fn list_reports(directory: &Path) -> Vec<PathBuf> {
+    let mut reports: Vec<PathBuf> = fs::read_dir(directory)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(CRASH_FILE_PREFIX) && name.ends_with(".json"))
+        })
+        .collect();
+    reports.sort();
+    reports
+}
+
+/// Reads the latest crash report
+///
+/// # Arguments
+///
+/// * `directory` - Directory of the crash reports
+///
+/// # Returns
+///
+/// * `Option<Value>` - The report, or None if there is none or it cannot be read
+pub fn read_last_crash(directory: &Path) -> Option<Value> {
+    let path = list_reports(directory).pop()?;
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
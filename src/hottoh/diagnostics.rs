@@ -0,0 +1,161 @@
+use crate::hottoh::build_info::{GIT_HASH, VERSION};
+use crate::hottoh::config::AppConfig;
+use crate::hottoh::frame_capture::FrameCapture;
+use crate::hottoh::hottoh_const::PROTOCOL_PROFILE;
+use crate::hottoh::metrics::{Metrics, StoveLabels};
+use crate::hottoh::redaction::REDACTED;
+use crate::hottoh::resource_usage::ResourceUsage;
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+/// Configuration keys holding secrets, never included in the bundle
+const SECRET_KEYS: &[&str] = &[
+    "secret",
+    "apikey",
+    "api_secret",
+    "token",
+    "pin",
+    "signing_secret",
+    "jwt_secret",
+    "keys",
+];
+
+/// Builds a zip archive with everything needed to report an issue
+///
+/// The archive contains the version information, the effective configuration with
+/// its secrets redacted, the metrics (connection statistics), the last raw frames
+/// and the last lines of the current log file.
+///
+/// # Arguments
+///
+/// * `config` - The application configuration
+/// * `metrics` - Runtime metrics
+/// * `stove` - Labels of the connected stove
+/// * `resources` - Resources used by the process
+/// * `frame_capture` - Capture of the last raw frames
+/// * `log_lines` - Number of log lines to include
+///
+/// # Returns
+///
+/// * `zip::result::ZipResult<Vec<u8>>` - The zip archive or an error
+pub fn build_bundle(
+    config: &AppConfig,
+    metrics: &Metrics,
+    stove: &StoveLabels,
+    resources: &ResourceUsage,
+    frame_capture: &FrameCapture,
+    log_lines: usize,
+) -> zip::result::ZipResult<Vec<u8>> {
+    let version = json!({
+        "version": VERSION,
+        "git_hash": GIT_HASH,
+        "protocol_profile": PROTOCOL_PROFILE,
+        "uptime_seconds": metrics.get_uptime_secs(),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+    });
+    let mut effective_config = serde_json::to_value(config).unwrap_or(Value::Null);
+    redact_secrets(&mut effective_config);
+
+    // Make sure buffered log lines are written before reading the file
+    log::logger().flush();
+    let log_tail = match latest_log_file(Path::new(&config.log.directory)) {
+        Some(path) => tail_lines(&path, log_lines),
+        None => String::from("No log file found\n"),
+    };
+
+    let files = [
+        ("version.json", to_pretty_json(&version)),
+        ("config.json", to_pretty_json(&effective_config)),
+        ("metrics.txt", metrics.render(stove, resources)),
+        ("frames.log", frame_capture.render()),
+        ("log.txt", log_tail),
+    ];
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (name, content) in files {
+        zip.start_file(name, options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// Replaces the values of secret keys with a placeholder, recursively
+///
+/// # Arguments
+///
+/// * `value` - The configuration serialized as JSON
+fn redact_secrets(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_KEYS.contains(&key.as_str()) {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact_secrets(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_secrets),
+        _ => {}
+    }
+}
+
+/// Serializes a value as indented JSON
+///
+/// # Arguments
+///
+/// * `value` - The value to serialize
+///
+/// # Returns
+///
+/// * `String` - The JSON text
+fn to_pretty_json(value: &Value) -> String {
+    serde_json::to_string_pretty(value).unwrap_or_default()
+}
+
+/// Finds the most recently modified log file of a directory
+///
+/// # Arguments
+///
+/// * `directory` - The log directory
+///
+/// # Returns
+///
+/// * `Option<PathBuf>` - The log file, or None if there is none
+pub fn latest_log_file(directory: &Path) -> Option<PathBuf> {
+    fs::read_dir(directory)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .max_by_key(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .map(|entry| entry.path())
+}
+
+/// Reads the last lines of a file
+///
+/// # Arguments
+///
+/// * `path` - The file
+/// * `count` - Number of lines to keep
+///
+/// # Returns
+///
+/// * `String` - The last lines, or the read error
+pub fn tail_lines(path: &Path, count: usize) -> String {
+    match fs::read_to_string(path) {
+        Ok(content) => {
+            let lines: Vec<&str> = content.lines().collect();
+            let start = lines.len().saturating_sub(count);
+            let mut tail = lines[start..].join("\n");
+            tail.push('\n');
+            tail
+        }
+        Err(e) => format!("Cannot read {}: {}\n", path.display(), e),
+    }
+}
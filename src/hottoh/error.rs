@@ -0,0 +1,63 @@
+use crate::hottoh::tcp_client::CommandError;
+use crate::hottoh::tcp_client_structs::ResponseError;
+use std::io;
+use thiserror::Error;
+
+/// Errors of the communication with the stove, by kind
+///
+/// The detailed errors of each step (`ResponseError`, `CommandError`) convert into
+/// it, so that callers can match on the kind of failure instead of parsing messages.
+#[derive(Error, Debug)]
+pub enum HottohError {
+    /// The connection to the stove failed or was lost
+    #[error("transport error: {0}")]
+    Transport(#[source] io::Error),
+    /// A frame does not follow the protocol
+    #[error("protocol error: {0}")]
+    Protocol(String),
+    /// The checksum of a frame does not match its content
+    #[error("invalid CRC: received {received}, computed {computed}")]
+    Crc {
+        /// CRC carried by the frame
+        received: String,
+        /// CRC computed from the content of the frame
+        computed: String,
+    },
+    /// The stove did not answer in time
+    #[error("timeout: {0}")]
+    Timeout(String),
+    /// The stove or the bridge does not support the request
+    #[error("not supported: {0}")]
+    NotSupported(String),
+    /// The value is outside of the range accepted by the stove
+    #[error("out of range: {0}")]
+    OutOfRange(String),
+    /// The bridge cannot accept the command now (read-only mode, maintenance
+    /// window, throttling)
+    #[error("busy: {0}")]
+    Busy(String),
+}
+
+impl From<io::Error> for HottohError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Self::Timeout(e.to_string()),
+            _ => Self::Transport(e),
+        }
+    }
+}
+
+impl From<ResponseError> for HottohError {
+    fn from(e: ResponseError) -> Self {
+        match e {
+            ResponseError::NotImplemented(message) => Self::NotSupported(message),
+            e => Self::Protocol(e.to_string()),
+        }
+    }
+}
+
+impl From<CommandError> for HottohError {
+    fn from(e: CommandError) -> Self {
+        Self::Busy(e.to_string())
+    }
+}
@@ -0,0 +1,201 @@
+use crate::hottoh::hottoh_const::StoveState;
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::tcp_client::CommandOrigin;
+use log::{debug, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender, TrySendError};
+use std::sync::Mutex;
+
+/// Number of events a subscriber may leave unread before the next ones are dropped
+pub const SUBSCRIBER_CAPACITY: usize = 1024;
+
+/// Event published on the bus
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// A page was received from the stove (`inf`, `dat0`, `dat1` or `dat2`)
+    StateUpdated { page: &'static str },
+    /// The stove changed state
+    StateTransition { from: StoveState, to: StoveState },
+    /// A command was queued for the stove
+    CommandQueued {
+        request_id: u32,
+        action: u32,
+        value: String,
+        origin: CommandOrigin,
+    },
+    /// The stove acknowledged a write command
+    CommandAccepted { request_id: u32 },
+    /// The stove reported the value set by a command
+    CommandConfirmed {
+        request_id: u32,
+        field: &'static str,
+        value: Value,
+    },
+    /// A command was dropped, `cancelled`, `expired` or `cleared` from the queue before
+    /// being sent, or `no_answer` from the stove after it
+    CommandDropped {
+        request_id: u32,
+        reason: &'static str,
+    },
+    /// The connection to the stove was opened or lost
+    ConnectionChanged { connected: bool },
+    /// The stove reported a new firmware version, `layout` being the DAT layout
+    /// selected for it
+    FirmwareChanged {
+        from: String,
+        to: String,
+        layout: &'static str,
+    },
+    /// The stove entered an alarm state
+    AlarmRaised { state: StoveState },
+    /// A watched field has not changed for longer than expected
+    ValueFrozen {
+        field: String,
+        value: Value,
+        unchanged_secs: u64,
+    },
+    /// A watched temperature dropped below the frost guard floor, with the `high`
+    /// priority when the stove was turned on and `critical` when it cannot heat
+    FrostAlert {
+        field: String,
+        temperature: f64,
+        min_temp: f64,
+        priority: &'static str,
+        message: String,
+    },
+    /// An alert rule of the `[alerts]` section fired, `value` being the rate per minute
+    /// for a rate of change
+    AlertFiring {
+        name: String,
+        field: String,
+        value: f64,
+        priority: &'static str,
+        message: String,
+    },
+    /// A fired alert rule resolved, its condition being false again
+    AlertResolved {
+        name: String,
+        field: String,
+        value: f64,
+        message: String,
+    },
+    /// The ignitions got slower than usual, e.g. because of a dirty burn pot or a worn igniter
+    MaintenanceSuggested { reason: String },
+}
+
+/// Bus distributing the events to the integrations
+///
+/// Each subscriber gets its own channel of `SUBSCRIBER_CAPACITY` events, so a slow
+/// subscriber does not delay the others, and publishing never blocks the stove
+/// communication. The events a lagging subscriber has no room for are dropped for it,
+/// and subscribers that dropped their receiver are removed at the next event.
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Channel of a subscriber, with the number of events dropped since it lags
+#[derive(Debug)]
+struct Subscriber {
+    sender: SyncSender<Event>,
+    dropped: u64,
+}
+
+impl EventBus {
+    /// Creates a bus without subscribers
+    ///
+    /// # Returns
+    ///
+    /// * `EventBus` - The bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the events published from now on
+    ///
+    /// # Returns
+    ///
+    /// * `Receiver<Event>` - The channel receiving the events
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = sync_channel(SUBSCRIBER_CAPACITY);
+        mutex_lock(&self.subscribers, "event subscribers").push(Subscriber { sender, dropped: 0 });
+        receiver
+    }
+
+    /// Publishes an event to every subscriber
+    ///
+    /// Never blocks: a subscriber whose channel is full misses the event. It must not be
+    /// called with a lock of the shared state or of the queues held, as the subscribers
+    /// take them.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    pub fn publish(&self, event: Event) {
+        debug!("Event: {:?}", event);
+        mutex_lock(&self.subscribers, "event subscribers").retain_mut(
+            |subscriber| match subscriber.sender.try_send(event.clone()) {
+                Ok(()) => {
+                    if subscriber.dropped > 0 {
+                        info!(
+                            "Event subscriber caught up after missing {} events",
+                            subscriber.dropped
+                        );
+                        subscriber.dropped = 0;
+                    }
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    if subscriber.dropped == 0 {
+                        warn!(
+                            "Event subscriber lagging by {} events, dropping the next ones",
+                            SUBSCRIBER_CAPACITY
+                        );
+                    }
+                    subscriber.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lagging_subscribers_miss_events_without_blocking() {
+        let bus = EventBus::new();
+        let lagging = bus.subscribe();
+        let reading = bus.subscribe();
+        for _ in 0..SUBSCRIBER_CAPACITY + 10 {
+            bus.publish(Event::ConnectionChanged { connected: true });
+            assert!(reading.try_recv().is_ok());
+        }
+        assert_eq!(lagging.try_iter().count(), SUBSCRIBER_CAPACITY);
+
+        bus.publish(Event::ConnectionChanged { connected: false });
+        assert!(matches!(
+            lagging.try_recv(),
+            Ok(Event::ConnectionChanged { connected: false })
+        ));
+        assert_eq!(
+            mutex_lock(&bus.subscribers, "event subscribers")[0].dropped,
+            0
+        );
+    }
+
+    #[test]
+    fn dropped_receivers_are_removed() {
+        let bus = EventBus::new();
+        let kept = bus.subscribe();
+        drop(bus.subscribe());
+        bus.publish(Event::ConnectionChanged { connected: true });
+        assert_eq!(mutex_lock(&bus.subscribers, "event subscribers").len(), 1);
+        assert!(kept.try_recv().is_ok());
+    }
+}
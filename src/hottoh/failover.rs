@@ -0,0 +1,204 @@
+use crate::hottoh::config::StoveConfig;
+use crate::hottoh::error::HottohError;
+use log::{info, warn};
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Time allowed to open a connection to an address before trying the next one
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Addresses at which the stove is reachable, in failover order
+///
+/// Some stoves are reachable both through their Wi-Fi module and an Ethernet serial
+/// bridge. The primary address (`stove.ip` and `stove.port`) is tried first, then the
+/// fallback addresses in the order of `stove.fallback_addresses`. Hostnames are
+/// resolved again at each connection attempt, unless `stove.resolve_on_reconnect` is
+/// disabled, so that the bridge follows a stove renumbered by DHCP.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoveAddresses {
+    /// Addresses as `host:port`, the primary one first
+    addresses: Vec<String>,
+    /// Last resolution of each address, empty until resolved
+    resolved: Vec<Vec<SocketAddr>>,
+    /// Resolve the addresses at each connection attempt, instead of once
+    re_resolve: bool,
+    /// Delay after which the primary address is tried again while on a fallback one
+    failback: Duration,
+}
+
+impl StoveAddresses {
+    /// Creates the addresses from the `[stove]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[stove]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Result<StoveAddresses, String>` - The addresses, or an error if a fallback is invalid
+    pub fn from_config(config: &StoveConfig) -> Result<Self, String> {
+        let mut addresses = Self::primary(config).addresses;
+        for address in config
+            .fallback_addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+        {
+            match address.rsplit_once(':') {
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+                _ => return Err(format!("invalid address '{}', expected host:port", address)),
+            }
+            if addresses.iter().any(|known| known == address) {
+                return Err(format!("address '{}' is listed twice", address));
+            }
+            addresses.push(address.to_string());
+        }
+        Ok(Self {
+            resolved: vec![Vec::new(); addresses.len()],
+            addresses,
+            re_resolve: config.resolve_on_reconnect,
+            failback: Duration::from_secs(config.failback_secs),
+        })
+    }
+
+    /// Creates the addresses from the `[stove]` section, without the fallback ones
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[stove]` section
+    ///
+    /// # Returns
+    ///
+    /// * `StoveAddresses` - The primary address only
+    pub fn primary(config: &StoveConfig) -> Self {
+        Self {
+            addresses: vec![format!("{}:{}", config.ip, config.port)],
+            resolved: vec![Vec::new()],
+            re_resolve: config.resolve_on_reconnect,
+            failback: Duration::from_secs(config.failback_secs),
+        }
+    }
+
+    /// Gets an address
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the address, 0 for the primary one
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The address as `host:port`
+    pub fn get(&self, index: usize) -> &str {
+        &self.addresses[index]
+    }
+
+    /// Gets the delay after which the primary address is tried again
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Duration>` - The delay, None if there is no fallback or failback is disabled
+    pub fn get_failback(&self) -> Option<Duration> {
+        (self.addresses.len() > 1 && !self.failback.is_zero()).then_some(self.failback)
+    }
+
+    /// Connects to the first reachable address, in failover order
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(usize, TcpStream)>` - The position of the address and the connection,
+    ///   None if no address is reachable
+    pub fn connect(&mut self) -> Option<(usize, TcpStream)> {
+        for index in 0..self.addresses.len() {
+            match self.connect_to(index) {
+                Ok(stream) => {
+                    if index > 0 {
+                        info!(
+                            "Primary stove address {} is down, using fallback {}",
+                            self.addresses[0], self.addresses[index]
+                        );
+                    }
+                    return Some((index, stream));
+                }
+                Err(e) => warn!(
+                    "Could not connect to stove at {}: {}",
+                    self.addresses[index], e
+                ),
+            }
+        }
+        None
+    }
+
+    /// Checks whether the primary address accepts connections again
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if a connection to the primary address could be opened
+    pub fn primary_reachable(&mut self) -> bool {
+        self.connect_to(0).is_ok()
+    }
+
+    /// Opens a connection to an address, resolving it first if needed
+    ///
+    /// A change of the resolved address is logged. If the resolution fails, the last
+    /// resolved address is used.
+    ///
+    /// # Arguments
+    ///
+    /// * `index` - Position of the address, 0 for the primary one
+    ///
+    /// # Returns
+    ///
+    /// * `Result<TcpStream, HottohError>` - The connection, or a `Transport` or `Timeout`
+    ///   error
+    fn connect_to(&mut self, index: usize) -> Result<TcpStream, HottohError> {
+        let address = &self.addresses[index];
+        let previous = &self.resolved[index];
+        if previous.is_empty() || self.re_resolve {
+            match address.to_socket_addrs() {
+                Ok(resolved) => {
+                    let resolved = resolved.collect::<Vec<SocketAddr>>();
+                    if !previous.is_empty() && !resolved.is_empty() && resolved != *previous {
+                        info!(
+                            "Stove address {} now resolves to {:?}, was {:?}",
+                            address, resolved, previous
+                        );
+                    }
+                    if !resolved.is_empty() {
+                        self.resolved[index] = resolved;
+                    }
+                }
+                Err(e) if previous.is_empty() => return Err(e.into()),
+                Err(e) => warn!(
+                    "Cannot resolve stove address {}: {}. Using {:?}",
+                    address, e, previous
+                ),
+            }
+        }
+        Ok(connect_timeout(address, &self.resolved[index])?)
+    }
+}
+
+/// Opens a connection, giving up after [`CONNECT_TIMEOUT`] on each resolved address
+///
+/// # Arguments
+///
+/// * `address` - The address as `host:port`
+/// * `resolved` - The socket addresses it resolves to
+///
+/// # Returns
+///
+/// * `io::Result<TcpStream>` - The connection, or the last error
+fn connect_timeout(address: &str, resolved: &[SocketAddr]) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("cannot resolve {}", address),
+    );
+    for socket_address in resolved {
+        match TcpStream::connect_timeout(socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
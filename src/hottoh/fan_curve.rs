@@ -0,0 +1,238 @@
+use crate::hottoh::config::FanCurveConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::get_field;
+use crate::hottoh::stove_client::StoveClient;
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::CommandOrigin;
+use log::{debug, error, info, warn};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Speeds of a fan by power level
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanCurve {
+    /// Fan number, from 1 to 3
+    fan: u8,
+    /// Command setting the speed of the fan
+    command: StoveCommands,
+    /// Speed by power level
+    speeds: BTreeMap<u64, u64>,
+}
+
+/// Parses the speeds of a fan, such as `1=1, 3=2, 5=4`
+///
+/// Power levels without a speed leave the fan unchanged.
+///
+/// # Arguments
+///
+/// * `spec` - The speeds by power level
+///
+/// # Returns
+///
+/// * `Result<BTreeMap<u64, u64>, String>` - The speeds, or an error if they are invalid
+fn parse_speeds(spec: &str) -> Result<BTreeMap<u64, u64>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (power, speed) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected power=speed, got '{}'", item))?;
+            let parse = |value: &str| {
+                value
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid number '{}'", value.trim()))
+            };
+            Ok((parse(power)?, parse(speed)?))
+        })
+        .collect()
+}
+
+/// Parses the curves of the `[fan_curve]` section
+///
+/// # Arguments
+///
+/// * `config` - The `[fan_curve]` section
+///
+/// # Returns
+///
+/// * `Result<Vec<FanCurve>, String>` - The curves of the configured fans, or the first error
+pub fn parse_fan_curves(config: &FanCurveConfig) -> Result<Vec<FanCurve>, String> {
+    [
+        (1, StoveCommands::FanSpeed1, &config.fan_1),
+        (2, StoveCommands::FanSpeed2, &config.fan_2),
+        (3, StoveCommands::FanSpeed3, &config.fan_3),
+    ]
+    .into_iter()
+    .filter_map(|(fan, command, spec)| Some((fan, command, spec.as_ref()?)))
+    .map(|(fan, command, spec)| {
+        parse_speeds(spec)
+            .map(|speeds| FanCurve {
+                fan,
+                command,
+                speeds,
+            })
+            .map_err(|e| format!("fan_{}: {}", fan, e))
+    })
+    .collect()
+}
+
+/// Fan being driven by its curve
+struct FanState {
+    curve: FanCurve,
+    /// Power level for which the curve was last applied, None to apply it again
+    applied: Option<u64>,
+    /// End of the manual override, if any
+    override_until: Option<Instant>,
+}
+
+/// Starts a thread setting the fan speeds from the power level
+///
+/// Many stoves do not link the fans to the power. Whenever the power level changes
+/// while the stove is on, each fan is set to the speed of its curve. A fan set
+/// through the API is left alone for `override_secs`, after which its curve applies
+/// again. Commands are sent as automation commands, so they are suppressed in
+/// read-only mode and during maintenance windows.
+///
+/// # Arguments
+///
+/// * `config` - The `[fan_curve]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_fan_curve_thread(
+    config: Option<&FanCurveConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let curves = match parse_fan_curves(config) {
+        Ok(curves) if !curves.is_empty() => curves,
+        Ok(_) => return None,
+        Err(e) => {
+            error!("Fan curve disabled: invalid curve {}", e);
+            return None;
+        }
+    };
+    let override_duration = Duration::from_secs(config.override_secs);
+    info!("Driving {} fan(s) from the power level", curves.len());
+
+    Some(supervise(
+        "fan_curve",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let client = StoveClient::new(
+                commands.clone(),
+                Arc::clone(&shared_state),
+                CommandOrigin::Automation,
+            );
+            let mut fans: Vec<FanState> = curves
+                .iter()
+                .map(|curve| FanState {
+                    curve: curve.clone(),
+                    applied: None,
+                    override_until: None,
+                })
+                .collect();
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { page: "dat0" }) => {}
+                    Ok(Event::CommandQueued {
+                        action,
+                        origin: CommandOrigin::Manual { .. },
+                        ..
+                    }) => {
+                        if let Some(fan) = fans
+                            .iter_mut()
+                            .find(|fan| fan.curve.command as u32 == action)
+                        {
+                            info!(
+                                "Fan {} set manually, curve suspended for {} seconds",
+                                fan.curve.fan,
+                                override_duration.as_secs()
+                            );
+                            fan.override_until = Some(Instant::now() + override_duration);
+                            fan.applied = None;
+                        }
+                        continue;
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for the fan curve: {}", e);
+                        continue;
+                    }
+                };
+                let stove_on = get_field(&state, "dat0.index_stove_on")
+                    .and_then(|on| on.as_bool())
+                    .unwrap_or_default();
+                let power =
+                    get_field(&state, "dat0.index_power_level").and_then(|power| power.as_u64());
+                let Some(power) = power.filter(|_| stove_on) else {
+                    fans.iter_mut().for_each(|fan| fan.applied = None);
+                    continue;
+                };
+
+                for fan in &mut fans {
+                    if fan
+                        .override_until
+                        .is_some_and(|until| until > Instant::now())
+                        || fan.applied == Some(power)
+                    {
+                        continue;
+                    }
+                    fan.override_until = None;
+                    fan.applied = Some(power);
+                    let Some(&speed) = fan.curve.speeds.get(&power) else {
+                        continue;
+                    };
+                    let current =
+                        get_field(&state, &format!("dat0.index_fan_{}_set", fan.curve.fan))
+                            .and_then(|current| current.as_u64());
+                    if current == Some(speed) {
+                        continue;
+                    }
+                    let speed = u8::try_from(speed).unwrap_or(u8::MAX);
+                    match client.set_fan_speed(fan.curve.fan, speed) {
+                        Ok(handle) => debug!(
+                            "Fan {} set to {} for power {} (id {})",
+                            fan.curve.fan,
+                            speed,
+                            power,
+                            handle.get_request_id()
+                        ),
+                        Err(e) => warn!(
+                            "Fan {} curve command {} rejected: {}",
+                            fan.curve.fan, speed, e
+                        ),
+                    }
+                }
+            }
+            info!("Fan curve thread stopped.");
+        },
+    ))
+}
@@ -0,0 +1,89 @@
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::redaction::redact_frame;
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+/// Number of raw frames kept for diagnostics
+const CAPTURE_CAPACITY: usize = 200;
+
+/// Direction of a raw frame
+#[derive(Debug, Clone, Copy)]
+pub enum FrameDirection {
+    /// Sent to the stove
+    Sent,
+    /// Received from the stove
+    Received,
+}
+
+/// Ring buffer of the last raw frames exchanged with the stove
+///
+/// Included in the diagnostics bundle so that protocol issues can be reported
+/// with the exact frames.
+#[derive(Debug)]
+pub struct FrameCapture {
+    /// Whether identifying params must be masked
+    redact: bool,
+    frames: Mutex<VecDeque<(DateTime<Utc>, FrameDirection, String)>>,
+}
+
+impl FrameCapture {
+    /// Creates an empty capture
+    ///
+    /// # Arguments
+    ///
+    /// * `redact` - Whether identifying params must be masked
+    ///
+    /// # Returns
+    ///
+    /// * `FrameCapture` - A new capture
+    pub fn new(redact: bool) -> Self {
+        Self {
+            redact,
+            frames: Mutex::new(VecDeque::with_capacity(CAPTURE_CAPACITY)),
+        }
+    }
+
+    /// Records a raw frame, dropping the oldest one when full
+    ///
+    /// # Arguments
+    ///
+    /// * `direction` - Direction of the frame
+    /// * `frame` - The raw frame
+    pub fn record(&self, direction: FrameDirection, frame: &str) {
+        let frame = if self.redact {
+            redact_frame(frame)
+        } else {
+            frame.into()
+        };
+        let mut frames = mutex_lock(&self.frames, "frame capture");
+        if frames.len() == CAPTURE_CAPACITY {
+            frames.pop_front();
+        }
+        frames.push_back((Utc::now(), direction, frame.trim_end().to_string()));
+    }
+
+    /// Renders the captured frames, one per line, oldest first
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The frames as text
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (at, direction, frame) in mutex_lock(&self.frames, "frame capture").iter() {
+            let arrow = match direction {
+                FrameDirection::Sent => ">>",
+                FrameDirection::Received => "<<",
+            };
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                at.to_rfc3339_opts(SecondsFormat::Millis, true),
+                arrow,
+                frame
+            );
+        }
+        out
+    }
+}
@@ -0,0 +1,289 @@
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::hottoh_const::StoveState;
+use crate::hottoh::locks::{read_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::supervisor::supervise;
+use log::{error, info, warn};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Field of the state expected to vary in some stove states
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchedField {
+    /// Field, as named in the state (`page.field`)
+    field: String,
+    /// Delay after which an unchanged value is reported
+    after: Duration,
+    /// Stove states in which the field is watched
+    states: Vec<StoveState>,
+    /// Whether a frozen value is published on the event bus
+    notify: bool,
+}
+
+impl WatchedField {
+    /// Parses a watch such as `after_secs=1800, states=Power|LowPellet, notify=true`
+    ///
+    /// `states` defaults to `Power` and `notify` to false.
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - The known fields, as returned by `known_fields`
+    /// * `name` - The field, as `page.field` (e.g. `dat0.smoke_t`)
+    /// * `spec` - The watch
+    ///
+    /// # Returns
+    ///
+    /// * `Result<WatchedField, String>` - The watch, or an error if it is invalid
+    fn parse(fields: &[String], name: &str, spec: &str) -> Result<Self, String> {
+        let mut watch = Self {
+            field: resolve_field(fields, name)?,
+            after: Duration::ZERO,
+            states: vec![StoveState::Power],
+            notify: false,
+        };
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected setting=value, got '{}'", item))?;
+            let value = value.trim();
+            let invalid = || format!("invalid value '{}' for {}", value, key.trim());
+            match key.trim() {
+                "after_secs" => {
+                    watch.after = value
+                        .parse()
+                        .ok()
+                        .filter(|secs| *secs > 0)
+                        .map(Duration::from_secs)
+                        .ok_or_else(invalid)?
+                }
+                "states" => {
+                    watch.states = value
+                        .split('|')
+                        .map(|state| {
+                            serde_json::from_value(Value::from(state.trim()))
+                                .map_err(|_| format!("unknown stove state '{}'", state.trim()))
+                        })
+                        .collect::<Result<_, String>>()?
+                }
+                "notify" => watch.notify = value.parse().map_err(|_| invalid())?,
+                other => return Err(format!("unknown setting '{}'", other)),
+            }
+        }
+        if watch.after.is_zero() {
+            return Err("after_secs is required".into());
+        }
+        Ok(watch)
+    }
+}
+
+/// Parses the fields of the `[freeze_watchdog]` section
+///
+/// # Arguments
+///
+/// * `config` - The watches by field
+///
+/// # Returns
+///
+/// * `Result<Vec<WatchedField>, String>` - The watches sorted by field, or the first error
+pub fn parse_watched_fields(config: &HashMap<String, String>) -> Result<Vec<WatchedField>, String> {
+    let fields = known_fields();
+    let mut names: Vec<&String> = config.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| {
+            WatchedField::parse(&fields, name, &config[name])
+                .map_err(|e| format!("{}: {}", name, e))
+        })
+        .collect()
+}
+
+/// Value that has not changed for longer than expected
+#[derive(Debug, Clone, Serialize)]
+pub struct FrozenValue {
+    /// Field, as `page.field`
+    field: String,
+    /// The unchanged value
+    value: Value,
+    /// When the value was first seen, in RFC 3339
+    since: String,
+    /// Delay after which the value was reported, in seconds
+    after_secs: u64,
+}
+
+/// Value of a watched field being tracked
+struct Tracking {
+    value: Value,
+    seen_at: Instant,
+    since: String,
+    frozen: bool,
+}
+
+/// Starts a thread reporting the watched fields whose value stops changing
+///
+/// A sensor or a board that stops updating a value (e.g. the smoke temperature while
+/// the stove burns) is reported in `/api/alarms`, and optionally published on the
+/// event bus. The tracking restarts when the stove leaves the watched states or the
+/// connection is lost.
+///
+/// # Arguments
+///
+/// * `config` - The `[freeze_watchdog]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_freeze_watchdog_thread(
+    config: Option<&HashMap<String, String>>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let watches = match parse_watched_fields(config?) {
+        Ok(watches) if !watches.is_empty() => watches,
+        Ok(_) => return None,
+        Err(e) => {
+            error!("Freeze watchdog disabled: invalid field {}", e);
+            return None;
+        }
+    };
+    info!("Watching {} field(s) for frozen values", watches.len());
+
+    Some(supervise(
+        "freeze_watchdog",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut tracking: Vec<Option<Tracking>> = watches.iter().map(|_| None).collect();
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { .. }) => {}
+                    Ok(Event::ConnectionChanged { connected: false }) => {
+                        tracking.iter_mut().for_each(|tracked| *tracked = None);
+                        write_lock(&shared_state, "shared state").set_frozen_values(Vec::new());
+                        continue;
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!(
+                            "Cannot serialize shared state for the freeze watchdog: {}",
+                            e
+                        );
+                        continue;
+                    }
+                };
+                let stove_state = get_field(&state, "dat0.index_stove_state")
+                    .and_then(|value| serde_json::from_value::<StoveState>(value.clone()).ok());
+
+                let mut changed = false;
+                for (watch, tracked) in watches.iter().zip(tracking.iter_mut()) {
+                    changed |= track(watch, tracked, &state, stove_state.as_ref(), &events);
+                }
+                if changed {
+                    let frozen = watches
+                        .iter()
+                        .zip(&tracking)
+                        .filter_map(|(watch, tracked)| {
+                            let tracked = tracked.as_ref().filter(|tracked| tracked.frozen)?;
+                            Some(FrozenValue {
+                                field: watch.field.clone(),
+                                value: tracked.value.clone(),
+                                since: tracked.since.clone(),
+                                after_secs: watch.after.as_secs(),
+                            })
+                        })
+                        .collect();
+                    write_lock(&shared_state, "shared state").set_frozen_values(frozen);
+                }
+            }
+            info!("Freeze watchdog thread stopped.");
+        },
+    ))
+}
+
+/// Updates the tracking of a watched field
+///
+/// # Arguments
+///
+/// * `watch` - The watched field
+/// * `tracked` - The tracking of the field, None if not tracked
+/// * `state` - The shared state serialized as JSON
+/// * `stove_state` - The current state of the stove, if received
+/// * `events` - The event bus
+///
+/// # Returns
+///
+/// * `bool` - True if the field became frozen or stopped being frozen
+fn track(
+    watch: &WatchedField,
+    tracked: &mut Option<Tracking>,
+    state: &Value,
+    stove_state: Option<&StoveState>,
+    events: &EventBus,
+) -> bool {
+    let was_frozen = tracked.as_ref().is_some_and(|tracked| tracked.frozen);
+    let value = get_field(state, &watch.field)
+        .filter(|_| stove_state.is_some_and(|stove_state| watch.states.contains(stove_state)));
+    let Some(value) = value else {
+        *tracked = None;
+        return was_frozen;
+    };
+
+    match tracked {
+        Some(tracked) if tracked.value == *value => {
+            if tracked.frozen || tracked.seen_at.elapsed() < watch.after {
+                return false;
+            }
+            tracked.frozen = true;
+            let message = format!(
+                "{} has not changed from {} since {}: possible sensor or board issue",
+                watch.field, value, tracked.since
+            );
+            if watch.notify {
+                warn!("{}", message);
+                events.publish(Event::ValueFrozen {
+                    field: watch.field.clone(),
+                    value: value.clone(),
+                    unchanged_secs: tracked.seen_at.elapsed().as_secs(),
+                });
+            } else {
+                info!("{}", message);
+            }
+            true
+        }
+        _ => {
+            if was_frozen {
+                info!("{} changed again, to {}", watch.field, value);
+            }
+            *tracked = Some(Tracking {
+                value: value.clone(),
+                seen_at: Instant::now(),
+                since: chrono::Local::now().to_rfc3339(),
+                frozen: false,
+            });
+            was_frozen
+        }
+    }
+}
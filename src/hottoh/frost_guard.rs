@@ -0,0 +1,226 @@
+use crate::hottoh::config::FrostGuardConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::{StoveCommands, StoveState};
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_requests, CommandOrigin};
+use log::{error, info, warn};
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Priority of the alert when the stove was turned on
+pub const PRIORITY_HIGH: &str = "high";
+/// Priority of the alert when the stove cannot heat (alarm state, command rejected)
+pub const PRIORITY_CRITICAL: &str = "critical";
+/// Rise above the floor after which the temperatures are considered safe again, in °C
+const HYSTERESIS: f64 = 1.0;
+
+/// Protection against frost, turning the stove on when a temperature gets too low
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrostGuard {
+    /// Watched temperatures, as named in the state (`page.field`)
+    fields: Vec<String>,
+    /// Temperature under which the stove is turned on, in °C
+    min_temp: f64,
+    /// Power level at which the stove is turned on
+    power: u32,
+}
+
+impl FrostGuard {
+    /// Creates the frost guard from the `[frost_guard]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[frost_guard]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Result<FrostGuard, String>` - The frost guard, or an error if it is invalid
+    pub fn from_config(config: &FrostGuardConfig) -> Result<Self, String> {
+        let known = known_fields();
+        let fields = config
+            .fields
+            .split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| resolve_field(&known, field))
+            .collect::<Result<Vec<String>, String>>()?;
+        if fields.is_empty() {
+            return Err("fields is required".into());
+        }
+        if !(1..=10).contains(&config.power) {
+            return Err(format!(
+                "power must be between 1 and 10, got {}",
+                config.power
+            ));
+        }
+        Ok(Self {
+            fields,
+            min_temp: config.min_temp,
+            power: config.power,
+        })
+    }
+
+    /// Finds the coldest watched temperature
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The shared state serialized as JSON
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(&str, f64)>` - The field and its temperature, None if none was received
+    fn coldest<'a>(&'a self, state: &Value) -> Option<(&'a str, f64)> {
+        self.fields
+            .iter()
+            .filter_map(|field| Some((field.as_str(), get_field(state, field)?.as_f64()?)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+}
+
+/// Starts a thread turning the stove on when a watched temperature drops below the floor
+///
+/// When the coldest watched temperature gets below `min_temp` while the stove is off,
+/// the stove is turned on at the configured power and a `frost_alert` event is
+/// published with the `high` priority. If the stove cannot heat, because it is in an
+/// alarm state (e.g. no pellets) or the command is rejected, the alert is published
+/// again with the `critical` priority. The guard is armed again once the temperatures
+/// are 1°C above the floor. Commands are sent as automation commands, so they are
+/// suppressed in read-only mode and during maintenance windows.
+///
+/// # Arguments
+///
+/// * `config` - The `[frost_guard]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_frost_guard_thread(
+    config: Option<&FrostGuardConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let guard = match FrostGuard::from_config(config?) {
+        Ok(guard) => guard,
+        Err(e) => {
+            error!("Frost guard disabled: {}", e);
+            return None;
+        }
+    };
+    info!(
+        "Frost guard: {} below {}°C",
+        guard.fields.join(", "),
+        guard.min_temp
+    );
+
+    Some(supervise(
+        "frost_guard",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            // Priority of the last alert, None while the temperatures are safe
+            let mut alerted: Option<&'static str> = None;
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { .. }) => {}
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for the frost guard: {}", e);
+                        continue;
+                    }
+                };
+                let Some((field, temperature)) = guard.coldest(&state) else {
+                    continue;
+                };
+                let stove_state = get_field(&state, "dat0.index_stove_state")
+                    .and_then(|value| serde_json::from_value::<StoveState>(value.clone()).ok());
+                let Some(stove_state) = stove_state else {
+                    continue;
+                };
+
+                if temperature >= guard.min_temp + HYSTERESIS {
+                    if alerted.take().is_some() {
+                        info!("Frost guard: {} back to {}°C", field, temperature);
+                    }
+                    continue;
+                }
+                if temperature >= guard.min_temp || alerted == Some(PRIORITY_CRITICAL) {
+                    continue;
+                }
+
+                let (priority, message) = if stove_state.is_alarm() {
+                    (
+                        PRIORITY_CRITICAL,
+                        format!(
+                            "the stove cannot heat, it is in the {:?} state",
+                            stove_state
+                        ),
+                    )
+                } else if alerted.is_some() {
+                    continue;
+                } else if stove_state != StoveState::Off {
+                    // Already heating, the alert is raised if it stops in an alarm state
+                    continue;
+                } else {
+                    match queue_write_requests(
+                        &commands.request_queue,
+                        &commands.request_id_counter,
+                        &shared_state,
+                        CommandOrigin::Automation,
+                        &[
+                            (StoveCommands::OnOff as u32, "1".to_string()),
+                            (StoveCommands::PowerLevel as u32, guard.power.to_string()),
+                        ],
+                        None,
+                    ) {
+                        Ok(request_ids) => (
+                            PRIORITY_HIGH,
+                            format!(
+                                "turning the stove on at power {} (id {:?})",
+                                guard.power, request_ids
+                            ),
+                        ),
+                        Err(e) => (
+                            PRIORITY_CRITICAL,
+                            format!("the stove cannot be turned on: {}", e),
+                        ),
+                    }
+                };
+                warn!(
+                    "Frost guard: {} is {}°C, below {}°C, {}",
+                    field, temperature, guard.min_temp, message
+                );
+                events.publish(Event::FrostAlert {
+                    field: field.to_string(),
+                    temperature,
+                    min_temp: guard.min_temp,
+                    priority,
+                    message,
+                });
+                alerted = Some(priority);
+            }
+            info!("Frost guard thread stopped.");
+        },
+    ))
+}
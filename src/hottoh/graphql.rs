@@ -0,0 +1,384 @@
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_stream::{parse_stream_fields, snapshot, state_changes};
+use crate::hottoh::ws_commands::WsCommands;
+use actix_ws::{CloseCode, CloseReason, Message, MessageStream, Session};
+use async_graphql::dynamic::{
+    Field, FieldFuture, FieldValue, InputValue, Object, ResolverContext, Scalar, Schema,
+    Subscription, SubscriptionField, SubscriptionFieldFuture, TypeRef,
+};
+use async_graphql::http::{WebSocket, WsMessage};
+use async_graphql::{Data, Error};
+use futures_util::{future, StreamExt};
+use log::debug;
+use serde_json::{json, Map, Value};
+use std::sync::{Arc, RwLock};
+
+pub use async_graphql::http::WebSocketProtocols as Protocols;
+
+/// Scalar of the values without a fixed type (empty pages, values not received yet)
+const JSON_SCALAR: &str = "JSON";
+/// Deepest query accepted, the state having at most 3 levels
+const MAX_DEPTH: usize = 16;
+
+/// Commands of a GraphQL request, added to the data of the request
+///
+/// Absent for the queries sent with GET, so that mutations need a POST or the
+/// WebSocket.
+pub struct GraphqlCommands {
+    /// Handler of the commands, with the role, PIN and address of the client
+    pub commands: WsCommands,
+    /// Whether the PIN or the signature of the request was checked by the middlewares
+    /// (POST), the commands of the WebSocket being checked one by one
+    pub verified: bool,
+}
+
+/// Converts a name of the state to the name of its GraphQL type
+///
+/// # Arguments
+///
+/// * `prefix` - Name of the type holding the value
+/// * `name` - Name of the value, e.g. `dat0`
+///
+/// # Returns
+///
+/// * `String` - The type name, e.g. `Dat0`
+fn type_name(prefix: &str, name: &str) -> String {
+    let name: String = name
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    format!("{}{}", prefix, name)
+}
+
+/// Builds the GraphQL type of a value of the state, registering the object types
+///
+/// The types are inferred from the default state: pages and the objects they hold
+/// become object types, whose fields can be selected one by one, and values empty
+/// until received are given as JSON, except the age of the pages.
+///
+/// # Arguments
+///
+/// * `type_prefix` - Name of the type holding the value
+/// * `name` - Name of the value
+/// * `value` - The value in the default state
+/// * `objects` - The object types, where the types of the nested objects are added
+///
+/// # Returns
+///
+/// * `TypeRef` - The type of the value
+fn value_type(type_prefix: &str, name: &str, value: &Value, objects: &mut Vec<Object>) -> TypeRef {
+    match value {
+        Value::Bool(_) => TypeRef::named(TypeRef::BOOLEAN),
+        Value::Number(number) if number.is_f64() => TypeRef::named(TypeRef::FLOAT),
+        Value::Number(_) => TypeRef::named(TypeRef::INT),
+        Value::String(_) => TypeRef::named(TypeRef::STRING),
+        Value::Null if name == "age_seconds" => TypeRef::named(TypeRef::FLOAT),
+        Value::Object(fields) if !fields.is_empty() => {
+            let object_name = type_name(type_prefix, name);
+            let object = object_type(&object_name, fields, objects);
+            objects.push(object);
+            TypeRef::named(object_name)
+        }
+        _ => TypeRef::named(JSON_SCALAR),
+    }
+}
+
+/// Builds an object type resolving its fields from a serialized object
+///
+/// # Arguments
+///
+/// * `name` - Name of the type
+/// * `fields` - The object in the default state
+/// * `objects` - The object types, where the types of the nested objects are added
+///
+/// # Returns
+///
+/// * `Object` - The type
+fn object_type(name: &str, fields: &Map<String, Value>, objects: &mut Vec<Object>) -> Object {
+    fields
+        .iter()
+        .fold(Object::new(name), |object, (field, value)| {
+            let ty = value_type(name, field, value, objects);
+            let nested = matches!(value, Value::Object(fields) if !fields.is_empty());
+            let field_name = field.clone();
+            object.field(Field::new(field.clone(), ty, move |ctx| {
+                let field_name = field_name.clone();
+                FieldFuture::new(async move {
+                    let parent = ctx.parent_value.try_downcast_ref::<Value>()?;
+                    match parent.get(&field_name) {
+                        None | Some(Value::Null) => Ok(None),
+                        Some(value) if nested => Ok(Some(FieldValue::owned_any(value.clone()))),
+                        Some(value) => Ok(Some(FieldValue::value(
+                            async_graphql::Value::from_json(value.clone())?,
+                        ))),
+                    }
+                })
+            }))
+        })
+}
+
+/// Gets the shared state from the data of the schema
+///
+/// # Arguments
+///
+/// * `ctx` - Context of the resolver
+///
+/// # Returns
+///
+/// * `async_graphql::Result<Arc<RwLock<SharedState>>>` - The shared state
+fn state_data(ctx: &ResolverContext<'_>) -> async_graphql::Result<Arc<RwLock<SharedState>>> {
+    Ok(Arc::clone(ctx.data::<Arc<RwLock<SharedState>>>()?))
+}
+
+/// Reads the `fields` argument of a subscription
+///
+/// # Arguments
+///
+/// * `ctx` - Context of the resolver
+///
+/// # Returns
+///
+/// * `async_graphql::Result<Option<Vec<String>>>` - The pages and fields, None if absent
+fn fields_argument(ctx: &ResolverContext<'_>) -> async_graphql::Result<Option<Vec<String>>> {
+    let Some(fields) = ctx.args.get("fields") else {
+        return Ok(None);
+    };
+    let fields = fields
+        .list()?
+        .iter()
+        .map(|field| field.string().map(str::to_string))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Some(parse_stream_fields(&fields.join(","))?))
+}
+
+/// Builds the GraphQL schema over the shared state
+///
+/// `state` gives the pages with their fields, `version` the version of the state, the
+/// `command` mutation sends a command as the WebSocket commands do, and the `state`
+/// subscription streams the state at each change.
+///
+/// # Arguments
+///
+/// * `shared_state` - The shared state
+///
+/// # Returns
+///
+/// * `Result<Schema, String>` - The schema, or an error if it is invalid
+pub fn build_schema(shared_state: Arc<RwLock<SharedState>>) -> Result<Schema, String> {
+    let default_state = serde_json::to_value(SharedState::new()).unwrap_or_default();
+    let mut objects = Vec::new();
+    let state_type = object_type(
+        "State",
+        default_state.as_object().unwrap_or(&Map::new()),
+        &mut objects,
+    );
+
+    let query = Object::new("Query")
+        .field(Field::new("state", TypeRef::named_nn("State"), |ctx| {
+            FieldFuture::new(async move {
+                let shared_state = state_data(&ctx)?;
+                let (_, state) = snapshot(&shared_state, None);
+                Ok(Some(FieldValue::owned_any(state)))
+            })
+        }))
+        .field(Field::new(
+            "version",
+            TypeRef::named_nn(TypeRef::INT),
+            |ctx| {
+                FieldFuture::new(async move {
+                    let shared_state = state_data(&ctx)?;
+                    let (version, _) = snapshot(&shared_state, None);
+                    Ok(Some(FieldValue::value(version)))
+                })
+            },
+        ));
+
+    let mutation = Object::new("Mutation").field(
+        Field::new("command", TypeRef::named_nn(JSON_SCALAR), |ctx| {
+            FieldFuture::new(async move {
+                let commands = ctx.data_opt::<GraphqlCommands>().ok_or_else(|| {
+                    Error::new("mutations are sent with POST /graphql or on the WebSocket")
+                })?;
+                let arg = |name: &str| {
+                    ctx.args
+                        .get(name)
+                        .map(|value| value.as_value().clone().into_json())
+                        .transpose()
+                };
+                let message = json!({
+                    "type": "command",
+                    "command": ctx.args.try_get("command")?.string()?,
+                    "value": ctx.args.try_get("value")?.i64()?,
+                    "pin": arg("pin")?,
+                    "force": arg("force")?.unwrap_or(Value::Bool(false)),
+                    "confirm": arg("confirm")?,
+                    "ttl": arg("ttl")?,
+                })
+                .to_string();
+                let ack = if commands.verified {
+                    commands.commands.handle_verified(&message)
+                } else {
+                    commands.commands.handle(&message).0
+                };
+                Ok(Some(FieldValue::value(async_graphql::Value::from_json(
+                    ack,
+                )?)))
+            })
+        })
+        .argument(InputValue::new(
+            "command",
+            TypeRef::named_nn(TypeRef::STRING),
+        ))
+        .argument(InputValue::new("value", TypeRef::named_nn(TypeRef::INT)))
+        .argument(InputValue::new("pin", TypeRef::named(TypeRef::STRING)))
+        .argument(InputValue::new("force", TypeRef::named(TypeRef::BOOLEAN)))
+        .argument(InputValue::new("confirm", TypeRef::named(TypeRef::STRING)))
+        .argument(InputValue::new("ttl", TypeRef::named(TypeRef::INT))),
+    );
+
+    let subscription = Subscription::new("Subscription").field(
+        SubscriptionField::new("state", TypeRef::named_nn("State"), |ctx| {
+            SubscriptionFieldFuture::new(async move {
+                let changes = state_changes(state_data(&ctx)?, fields_argument(&ctx)?);
+                Ok(changes.map(|(_, state)| Ok(FieldValue::owned_any(state))))
+            })
+        })
+        .argument(InputValue::new(
+            "fields",
+            TypeRef::named_nn_list(TypeRef::STRING),
+        )),
+    );
+
+    let schema = Schema::build("Query", Some("Mutation"), Some("Subscription"))
+        .register(Scalar::new(JSON_SCALAR))
+        .register(state_type)
+        .register(query)
+        .register(mutation)
+        .register(subscription)
+        .limit_depth(MAX_DEPTH)
+        .data(shared_state);
+    objects
+        .into_iter()
+        .fold(schema, |schema, object| schema.register(object))
+        .finish()
+        .map_err(|e| format!("invalid GraphQL schema: {}", e))
+}
+
+/// Serves GraphQL queries, mutations and subscriptions over a WebSocket
+///
+/// The `graphql-transport-ws` and `graphql-ws` protocols are supported. Pings are
+/// answered, and the connection ends when the client closes it.
+///
+/// # Arguments
+///
+/// * `session` - Session of the WebSocket
+/// * `messages` - Messages received from the client
+/// * `schema` - The GraphQL schema
+/// * `protocol` - Protocol chosen on the handshake
+/// * `commands` - Handler of the commands sent with the `command` mutation
+pub async fn serve_graphql_ws(
+    mut session: Session,
+    messages: MessageStream,
+    schema: Schema,
+    protocol: Protocols,
+    commands: WsCommands,
+) {
+    let pong = session.clone();
+    let input = messages
+        .take_while(|message| {
+            future::ready(matches!(message, Ok(m) if !matches!(m, Message::Close(_))))
+        })
+        .filter_map(move |message| {
+            let mut pong = pong.clone();
+            async move {
+                match message {
+                    Ok(Message::Text(text)) => Some(text.as_bytes().to_vec()),
+                    Ok(Message::Binary(bytes)) => Some(bytes.to_vec()),
+                    Ok(Message::Ping(bytes)) => {
+                        let _ = pong.pong(&bytes).await;
+                        None
+                    }
+                    _ => None,
+                }
+            }
+        });
+    let mut data = Data::default();
+    data.insert(GraphqlCommands {
+        commands,
+        verified: false,
+    });
+    let mut replies = WebSocket::new(schema, Box::pin(input), protocol).connection_data(data);
+    while let Some(reply) = replies.next().await {
+        match reply {
+            WsMessage::Text(text) => {
+                if session.text(text).await.is_err() {
+                    return;
+                }
+            }
+            WsMessage::Close(code, reason) => {
+                let reason = CloseReason {
+                    code: CloseCode::Other(code),
+                    description: Some(reason),
+                };
+                let _ = session.close(Some(reason)).await;
+                return;
+            }
+        }
+    }
+    debug!("GraphQL WebSocket client disconnected");
+    let _ = session.close(None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs a query against the schema of a default state
+    fn query(query: &str) -> Value {
+        let schema = build_schema(Arc::new(RwLock::new(SharedState::new()))).unwrap();
+        let response = actix_web::rt::System::new().block_on(schema.execute(query));
+        serde_json::to_value(response).unwrap()
+    }
+
+    #[test]
+    fn test_type_name() {
+        assert_eq!(type_name("", "dat0"), "Dat0");
+        assert_eq!(type_name("Dat2", "actuators"), "Dat2Actuators");
+        assert_eq!(type_name("", "read_only"), "ReadOnly");
+    }
+
+    #[test]
+    fn test_query_selected_fields() {
+        let response = query("{ version state { read_only inf { hostname } } }");
+        assert!(response.get("errors").is_none(), "{}", response);
+        assert_eq!(
+            response["data"],
+            json!({"version": 0, "state": {"read_only": false, "inf": {"hostname": ""}}})
+        );
+    }
+
+    #[test]
+    fn test_query_unknown_field() {
+        let response = query("{ state { dat0 { index_nothing } } }");
+        assert!(response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("index_nothing"));
+    }
+
+    #[test]
+    fn test_mutation_needs_post() {
+        let response = query("mutation { command(command: \"PowerLevel\", value: 3) }");
+        assert!(response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("POST"));
+    }
+}
@@ -0,0 +1,310 @@
+use crate::hottoh::api_keys::{ApiKeys, Role, API_KEY_HEADER};
+use crate::hottoh::auth_guard::AuthGuard;
+use crate::hottoh::child_lock::ChildLock;
+use crate::hottoh::config::GrpcConfig;
+use crate::hottoh::jwt::JwtAuth;
+use crate::hottoh::network_filter::NetworkFilter;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::plugins::PluginRegistry;
+use crate::hottoh::request_signing::RequestSigning;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_stream::{parse_stream_fields, select_fields, snapshot, state_changes};
+use crate::hottoh::ws_commands::WsCommands;
+use actix_web::{rt, web};
+use futures_util::{Stream, StreamExt};
+use log::{debug, error, info};
+use proto::hottoh_server::{Hottoh, HottohServer};
+use proto::{CommandReply, CommandRequest, State, StateRequest};
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+
+/// Messages and service generated from `proto/hottoh.proto`
+pub mod proto {
+    tonic::include_proto!("hottoh");
+}
+
+/// gRPC service of the state and the commands, checked as the HTTP API
+pub struct GrpcService {
+    /// Queues used to send requests to the stove
+    pub commands: CommandQueue,
+    /// Shared state holding the stove data
+    pub shared_state: Arc<RwLock<SharedState>>,
+    /// Commands registered by the plugins
+    pub plugins: Arc<PluginRegistry>,
+    /// PIN required by the commands
+    pub child_lock: Arc<ChildLock>,
+    /// Signing of the commands, which then need the PIN
+    pub signing: Arc<RequestSigning>,
+    /// API keys required by every call
+    pub api_keys: Arc<ApiKeys>,
+    /// Bans of the clients failing to authenticate
+    pub auth_guard: Arc<AuthGuard>,
+    /// Networks allowed to use the API
+    pub network_filter: Arc<NetworkFilter>,
+}
+
+impl GrpcService {
+    /// Checks the client of a call: its network, its bans and its API key
+    ///
+    /// The key is read from the `x-api-key` metadata, or from an `authorization: Bearer`
+    /// one, which may also carry a JSON Web Token.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The call
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<Role>, Status>` - The role of the key, None if the API keys are
+    ///   disabled, or `PERMISSION_DENIED`, `RESOURCE_EXHAUSTED` (banned) or
+    ///   `UNAUTHENTICATED`
+    async fn authorize<T>(&self, request: &Request<T>) -> Result<Option<Role>, Status> {
+        let client = request.remote_addr().map(|address| address.ip());
+        if let Some(client) = client {
+            if !self.network_filter.is_allowed(client) {
+                return Err(Status::permission_denied(
+                    "Client not in the allowed networks",
+                ));
+            }
+            if let Some(remaining) = self.auth_guard.banned_for(client) {
+                // Rounded up, so that retrying after this delay succeeds
+                let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+                return Err(Status::resource_exhausted(format!(
+                    "Too many failed authentications, try again in {} seconds",
+                    seconds
+                )));
+            }
+        }
+        if !self.api_keys.is_enabled() {
+            return Ok(None);
+        }
+
+        let metadata = |name: &str| {
+            request
+                .metadata()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+        };
+        let credential = metadata(&API_KEY_HEADER.to_ascii_lowercase())
+            .or_else(|| metadata("authorization").and_then(|value| value.strip_prefix("Bearer ")))
+            .map(|key| key.trim().to_string());
+        let authenticated = match credential {
+            None => Err(format!(
+                "API key required ({} or authorization: Bearer metadata)",
+                API_KEY_HEADER.to_ascii_lowercase()
+            )),
+            Some(credential) if self.api_keys.has_jwt() && JwtAuth::is_token(&credential) => {
+                let api_keys = Arc::clone(&self.api_keys);
+                web::block(move || api_keys.authenticate(&credential))
+                    .await
+                    .unwrap_or_else(|e| Err(format!("cannot validate token: {}", e)))
+            }
+            Some(credential) => self.api_keys.authenticate(&credential),
+        };
+        match authenticated {
+            Ok((_, label, role)) => {
+                debug!("gRPC call with {}", label);
+                Ok(Some(role))
+            }
+            Err(e) => {
+                if let Some(client) = client {
+                    self.auth_guard.fail(client, "grpc");
+                }
+                Err(Status::unauthenticated(e))
+            }
+        }
+    }
+}
+
+/// Parses the fields of a state request
+///
+/// # Arguments
+///
+/// * `request` - The request
+///
+/// # Returns
+///
+/// * `Result<Option<Vec<String>>, String>` - The pages and fields, None for the whole
+///   state, or an error if one is unknown
+fn requested_fields(request: &StateRequest) -> Result<Option<Vec<String>>, String> {
+    if request.fields.is_empty() {
+        return Ok(None);
+    }
+    parse_stream_fields(&request.fields.join(",")).map(Some)
+}
+
+/// Converts the acknowledgement of a command to its reply
+///
+/// # Arguments
+///
+/// * `ack` - The acknowledgement, as sent to the WebSocket clients
+///
+/// # Returns
+///
+/// * `CommandReply` - The reply
+fn command_reply(ack: &Value) -> CommandReply {
+    let text = |name: &str| ack.get(name).and_then(Value::as_str).map(str::to_string);
+    let success = ack.get("success").and_then(Value::as_bool).unwrap_or(false);
+    CommandReply {
+        success,
+        status: ack
+            .get("status")
+            .and_then(Value::as_u64)
+            .and_then(|status| u32::try_from(status).ok())
+            .unwrap_or(if success { 200 } else { 500 }),
+        request_id: ack
+            .get("request_id")
+            .and_then(Value::as_u64)
+            .and_then(|id| u32::try_from(id).ok()),
+        error: text("error"),
+        confirmation_token: text("confirmation_token"),
+    }
+}
+
+#[tonic::async_trait]
+impl Hottoh for GrpcService {
+    type StreamStateStream = Pin<Box<dyn Stream<Item = Result<State, Status>> + Send>>;
+
+    async fn get_state(&self, request: Request<StateRequest>) -> Result<Response<State>, Status> {
+        self.authorize(&request).await?;
+        let fields = requested_fields(request.get_ref()).map_err(Status::invalid_argument)?;
+        let (version, state) = snapshot(&self.shared_state, fields.as_deref());
+        Ok(Response::new(State {
+            version,
+            json: state.to_string(),
+        }))
+    }
+
+    // The items of the stream are results with a Status, as tonic requires
+    #[allow(clippy::result_large_err)]
+    async fn stream_state(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<Self::StreamStateStream>, Status> {
+        self.authorize(&request).await?;
+        let fields = requested_fields(request.get_ref()).map_err(Status::invalid_argument)?;
+        let changes = state_changes(Arc::clone(&self.shared_state), fields.clone()).map(
+            move |(version, state)| {
+                let state = match &fields {
+                    Some(fields) => select_fields(&state, fields),
+                    None => state,
+                };
+                Ok(State {
+                    version,
+                    json: state.to_string(),
+                })
+            },
+        );
+        Ok(Response::new(Box::pin(changes)))
+    }
+
+    async fn command(
+        &self,
+        request: Request<CommandRequest>,
+    ) -> Result<Response<CommandReply>, Status> {
+        let role = self.authorize(&request).await?;
+        let client = request.remote_addr().map(|address| address.ip());
+        let commands = WsCommands {
+            request_queue: Arc::clone(&self.commands.request_queue),
+            request_id_counter: Arc::clone(&self.commands.request_id_counter),
+            shared_state: Arc::clone(&self.shared_state),
+            plugins: Arc::clone(&self.plugins),
+            child_lock: Arc::clone(&self.child_lock),
+            auth_guard: client.map(|client: IpAddr| (Arc::clone(&self.auth_guard), client)),
+            role,
+            pin: None,
+            signing: Arc::clone(&self.signing),
+        };
+        let command = request.into_inner();
+        let message = json!({
+            "type": "command",
+            "command": command.command,
+            "value": command.value,
+            "pin": command.pin,
+            "force": command.force,
+            "confirm": command.confirm,
+            "ttl": command.ttl,
+        });
+        let (ack, _) = commands.handle(&message.to_string());
+        Ok(Response::new(command_reply(&ack)))
+    }
+}
+
+/// Starts the gRPC server on the runtime of the HTTP server
+///
+/// # Arguments
+///
+/// * `config` - The `[grpc]` section, or None if absent
+/// * `service` - The service
+pub fn start_grpc_server(config: Option<&GrpcConfig>, service: GrpcService) {
+    let Some(config) = config else {
+        return;
+    };
+    let address = match format!("{}:{}", config.ip, config.port).parse::<SocketAddr>() {
+        Ok(address) => address,
+        Err(e) => {
+            error!("gRPC server disabled: invalid address {}: {}", config.ip, e);
+            return;
+        }
+    };
+    info!("Starting gRPC server on {}", address);
+    rt::spawn(async move {
+        if let Err(e) = Server::builder()
+            .add_service(HottohServer::new(service))
+            .serve(address)
+            .await
+        {
+            error!("gRPC server stopped: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requested_fields_resolve_the_names() {
+        let request = StateRequest { fields: vec![] };
+        assert_eq!(requested_fields(&request).unwrap(), None);
+        let request = StateRequest {
+            fields: vec!["dat0.ambient_t1".into(), "inf".into()],
+        };
+        assert_eq!(
+            requested_fields(&request).unwrap(),
+            Some(vec!["dat0.index_ambient_t1".to_string(), "inf".to_string()])
+        );
+        let request = StateRequest {
+            fields: vec!["dat9".into()],
+        };
+        assert_eq!(
+            requested_fields(&request).unwrap_err(),
+            "unknown page 'dat9'"
+        );
+    }
+
+    #[test]
+    fn command_reply_follows_the_ack() {
+        let reply = command_reply(&json!({"type": "ack", "success": true, "request_id": 42}));
+        assert!(reply.success);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.request_id, Some(42));
+
+        let reply = command_reply(&json!({
+            "type": "ack",
+            "success": false,
+            "status": 202,
+            "error": "Confirmation required",
+            "confirmation_token": "abc",
+        }));
+        assert!(!reply.success);
+        assert_eq!(reply.status, 202);
+        assert_eq!(reply.confirmation_token.as_deref(), Some("abc"));
+        assert_eq!(reply.request_id, None);
+    }
+}
@@ -0,0 +1,1110 @@
+use crate::hottoh::config::HistoryConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::{mutex_lock, read_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::supervisor::supervise;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+/// Pages older than this are not sampled, e.g. while the stove is disconnected
+const STALE_PAGE_SECS: u64 = 60;
+
+/// Annotations kept at most, the oldest ones being removed first
+const MAX_ANNOTATIONS: usize = 10_000;
+
+/// Level of the history, raw samples or aggregates over fixed buckets
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryLevel {
+    /// Name of the level in the API, `raw` or the bucket (e.g. `15m`)
+    name: String,
+    /// Length of the buckets in seconds, None for the raw samples
+    bucket_secs: Option<i64>,
+    /// Age after which the points are removed, in seconds
+    retention_secs: i64,
+}
+
+impl HistoryLevel {
+    /// Gets the name of the level
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - `raw` or the bucket, e.g. `15m`
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Describes the level for the API
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - The name, bucket and retention of the level
+    pub fn to_json(&self) -> Value {
+        json!({
+            "level": self.name,
+            "bucket_secs": self.bucket_secs,
+            "retention_secs": self.retention_secs,
+        })
+    }
+}
+
+/// Parses a span such as `90s`, `15m`, `6h` or `30d`
+///
+/// # Arguments
+///
+/// * `span` - The span
+///
+/// # Returns
+///
+/// * `Result<i64, String>` - The span in seconds, or an error if it is invalid or zero
+pub fn parse_span(span: &str) -> Result<i64, String> {
+    let span = span.trim();
+    let invalid = || format!("invalid span '{}', expected e.g. 90s, 15m, 6h or 30d", span);
+    let unit = match span.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        Some('d') => 86_400,
+        _ => return Err(invalid()),
+    };
+    span[..span.len() - 1]
+        .parse::<i64>()
+        .ok()
+        .filter(|value| *value > 0)
+        .and_then(|value| value.checked_mul(unit))
+        .ok_or_else(invalid)
+}
+
+/// Parses the levels of the history
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated `bucket:retention`, e.g. `raw:6h, 1m:7d, 15m:365d`, where
+///   `bucket` is `raw` or a span; buckets must be increasing
+///
+/// # Returns
+///
+/// * `Result<Vec<HistoryLevel>, String>` - The levels, finest first, or the first error
+pub fn parse_levels(spec: &str) -> Result<Vec<HistoryLevel>, String> {
+    let mut levels: Vec<HistoryLevel> = Vec::new();
+    for item in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        let (bucket, retention) = item
+            .split_once(':')
+            .ok_or_else(|| format!("expected bucket:retention, got '{}'", item))?;
+        let bucket = bucket.trim();
+        let bucket_secs = match bucket {
+            "raw" => None,
+            bucket => Some(parse_span(bucket)?),
+        };
+        let finer = levels.last().map(|level| level.bucket_secs.unwrap_or(0));
+        if finer.is_some_and(|finer| bucket_secs.unwrap_or(0) <= finer) {
+            return Err(format!(
+                "level '{}' is not coarser than the previous one",
+                bucket
+            ));
+        }
+        levels.push(HistoryLevel {
+            name: bucket.to_string(),
+            bucket_secs,
+            retention_secs: parse_span(retention)?,
+        });
+    }
+    if levels.is_empty() {
+        return Err("at least one level is required".into());
+    }
+    Ok(levels)
+}
+
+/// Statistic computed over the buckets of a query
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    /// Average of the values
+    Avg,
+    /// Lowest value
+    Min,
+    /// Highest value
+    Max,
+}
+
+impl Aggregation {
+    /// Gets the name of the statistic
+    ///
+    /// # Returns
+    ///
+    /// * `&'static str` - `avg`, `min` or `max`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Aggregation::Avg => "avg",
+            Aggregation::Min => "min",
+            Aggregation::Max => "max",
+        }
+    }
+}
+
+/// Formats a time of the history
+///
+/// # Arguments
+///
+/// * `time` - The time, in seconds since the epoch
+///
+/// # Returns
+///
+/// * `String` - The time in RFC 3339
+fn format_time(time: i64) -> String {
+    DateTime::<Utc>::from_timestamp(time, 0)
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_default()
+}
+
+/// Raw sample, or aggregate of the samples of a bucket
+#[derive(Debug, Clone, PartialEq)]
+struct HistoryPoint {
+    /// Time of the sample or start of the bucket, in seconds since the epoch
+    time: i64,
+    /// Lowest value
+    min: f64,
+    /// Highest value
+    max: f64,
+    /// Sum of the values
+    sum: f64,
+    /// Number of values
+    count: u64,
+}
+
+impl HistoryPoint {
+    /// Creates a point from a single value
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time of the point, in seconds since the epoch
+    /// * `value` - The value
+    ///
+    /// # Returns
+    ///
+    /// * `HistoryPoint` - The point
+    fn new(time: i64, value: f64) -> Self {
+        Self {
+            time,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    /// Adds a value to the aggregate
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    /// Adds the values of another aggregate
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The other aggregate
+    fn merge(&mut self, other: &HistoryPoint) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    /// Serializes a statistic of the point for the API
+    ///
+    /// # Arguments
+    ///
+    /// * `aggregation` - The statistic
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - `{"time", "value"}`
+    fn to_value_json(&self, aggregation: Aggregation) -> Value {
+        let value = match aggregation {
+            Aggregation::Avg => self.sum / self.count as f64,
+            Aggregation::Min => self.min,
+            Aggregation::Max => self.max,
+        };
+        json!({ "time": format_time(self.time), "value": value })
+    }
+
+    /// Serializes the point for the API
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - Whether the point is a raw sample
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - `{"time", "value"}` for a raw sample, `{"time", "avg", "min", "max",
+    ///   "count"}` for an aggregate
+    fn to_json(&self, raw: bool) -> Value {
+        let time = format_time(self.time);
+        if raw {
+            json!({ "time": time, "value": self.sum })
+        } else {
+            json!({
+                "time": time,
+                "avg": self.sum / self.count as f64,
+                "min": self.min,
+                "max": self.max,
+                "count": self.count,
+            })
+        }
+    }
+}
+
+/// Event marked on the history, e.g. a command or a state transition
+#[derive(Debug, Clone, PartialEq)]
+struct Annotation {
+    /// Time of the event, in seconds since the epoch
+    time: i64,
+    /// Details of the event, a JSON object with a `type`
+    details: Value,
+}
+
+/// Points of a field at each level
+#[derive(Debug, Default)]
+struct Series {
+    /// Completed points of each level, oldest first
+    points: Vec<VecDeque<HistoryPoint>>,
+    /// Bucket being filled at each aggregated level
+    open: Vec<Option<HistoryPoint>>,
+}
+
+/// History of the telemetry, downsampled into coarser levels as it ages
+///
+/// Each sample is kept at the raw level and added to the current bucket of each
+/// aggregated level, e.g. raw samples for 6 hours, 1-minute min/max/avg for 7 days and
+/// 15-minute ones for a year. Points older than the retention of their level are
+/// removed, so the memory used is bounded by the configuration.
+#[derive(Debug, Default)]
+pub struct History {
+    /// Levels, finest first, empty if the history is disabled
+    levels: Vec<HistoryLevel>,
+    /// Fields recorded, as named in the state
+    fields: Vec<String>,
+    /// Points by field
+    series: Mutex<BTreeMap<String, Series>>,
+    /// Commands and state transitions, oldest first
+    annotations: Mutex<VecDeque<Annotation>>,
+}
+
+impl History {
+    /// Creates an empty history
+    ///
+    /// # Arguments
+    ///
+    /// * `fields` - Fields recorded, as named in the state
+    /// * `levels` - Levels, finest first
+    ///
+    /// # Returns
+    ///
+    /// * `History` - The history
+    pub fn new(fields: Vec<String>, levels: Vec<HistoryLevel>) -> Self {
+        Self {
+            levels,
+            fields,
+            series: Mutex::new(BTreeMap::new()),
+            annotations: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Checks whether the history is recorded
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if a `[history]` section is configured
+    pub fn is_enabled(&self) -> bool {
+        !self.levels.is_empty()
+    }
+
+    /// Gets the levels
+    ///
+    /// # Returns
+    ///
+    /// * `&[HistoryLevel]` - The levels, finest first
+    pub fn get_levels(&self) -> &[HistoryLevel] {
+        &self.levels
+    }
+
+    /// Gets the fields recorded
+    ///
+    /// # Returns
+    ///
+    /// * `&[String]` - The fields, as `page.field`
+    pub fn get_fields(&self) -> &[String] {
+        &self.fields
+    }
+
+    /// Records a value and removes the expired points
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field, as named in the state
+    /// * `time` - Time of the sample, in seconds since the epoch
+    /// * `value` - The value
+    pub fn record(&self, field: &str, time: i64, value: f64) {
+        let mut series = mutex_lock(&self.series, "history");
+        let series = series
+            .entry(field.to_string())
+            .or_insert_with(|| self.new_series());
+        for (i, level) in self.levels.iter().enumerate() {
+            let points = &mut series.points[i];
+            match level.bucket_secs {
+                None => points.push_back(HistoryPoint::new(time, value)),
+                Some(bucket) => {
+                    let start = time - time.rem_euclid(bucket);
+                    match &mut series.open[i] {
+                        Some(open) if open.time == start => open.add(value),
+                        open => {
+                            if let Some(done) = open.replace(HistoryPoint::new(start, value)) {
+                                points.push_back(done);
+                            }
+                        }
+                    }
+                }
+            }
+            while points
+                .front()
+                .is_some_and(|point| point.time < time - level.retention_secs)
+            {
+                points.pop_front();
+            }
+        }
+    }
+
+    /// Creates the empty series of a field
+    ///
+    /// # Returns
+    ///
+    /// * `Series` - A series without points at each level
+    fn new_series(&self) -> Series {
+        Series {
+            points: self.levels.iter().map(|_| VecDeque::new()).collect(),
+            open: self.levels.iter().map(|_| None).collect(),
+        }
+    }
+
+    /// Imports past samples of a field
+    ///
+    /// Unlike `record`, the samples may be older than the points already stored: they
+    /// are aggregated at each level and merged with the stored points, buckets present
+    /// in both being combined. Samples older than the retention of a level are not
+    /// kept at that level, and samples in the future are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field, as named in the state
+    /// * `samples` - The samples, as time in seconds since the epoch and value
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The number of samples kept at one level at least
+    pub fn import(&self, field: &str, samples: &[(i64, f64)]) -> usize {
+        let now = Utc::now().timestamp();
+        let mut samples: Vec<(i64, f64)> = samples
+            .iter()
+            .copied()
+            .filter(|(time, _)| *time <= now)
+            .collect();
+        samples.sort_by_key(|(time, _)| *time);
+        let longest = self
+            .levels
+            .iter()
+            .map(|level| level.retention_secs)
+            .max()
+            .unwrap_or(0);
+        let kept = samples
+            .iter()
+            .filter(|(time, _)| *time >= now - longest)
+            .count();
+
+        let mut series = mutex_lock(&self.series, "history");
+        let series = series
+            .entry(field.to_string())
+            .or_insert_with(|| self.new_series());
+        for (i, level) in self.levels.iter().enumerate() {
+            let mut imported: Vec<HistoryPoint> = Vec::new();
+            for &(time, value) in samples
+                .iter()
+                .filter(|(time, _)| *time >= now - level.retention_secs)
+            {
+                let start = level
+                    .bucket_secs
+                    .map_or(time, |bucket| time - time.rem_euclid(bucket));
+                match imported.last_mut() {
+                    Some(last) if level.bucket_secs.is_some() && last.time == start => {
+                        last.add(value)
+                    }
+                    _ => imported.push(HistoryPoint::new(start, value)),
+                }
+            }
+            // The bucket being filled absorbs the imported one with the same start
+            if let Some(open) = &mut series.open[i] {
+                let open_start = open.time;
+                imported.retain(|point| match point.time.cmp(&open_start) {
+                    std::cmp::Ordering::Less => true,
+                    std::cmp::Ordering::Equal => {
+                        open.merge(point);
+                        false
+                    }
+                    std::cmp::Ordering::Greater => false,
+                });
+            }
+
+            let stored = std::mem::take(&mut series.points[i]);
+            let mut merged = VecDeque::with_capacity(stored.len() + imported.len());
+            let mut imported = imported.into_iter().peekable();
+            for mut point in stored {
+                while let Some(earlier) = imported.next_if(|next| next.time < point.time) {
+                    merged.push_back(earlier);
+                }
+                if let Some(same) =
+                    imported.next_if(|next| level.bucket_secs.is_some() && next.time == point.time)
+                {
+                    point.merge(&same);
+                }
+                merged.push_back(point);
+            }
+            merged.extend(imported);
+            series.points[i] = merged;
+        }
+        kept
+    }
+
+    /// Records an annotation and removes the expired ones
+    ///
+    /// Annotations are kept as long as the longest level, within `MAX_ANNOTATIONS`.
+    ///
+    /// # Arguments
+    ///
+    /// * `time` - Time of the event, in seconds since the epoch
+    /// * `details` - Details of the event, a JSON object with a `type`
+    pub fn annotate(&self, time: i64, details: Value) {
+        let retention = self
+            .levels
+            .iter()
+            .map(|level| level.retention_secs)
+            .max()
+            .unwrap_or(0);
+        let mut annotations = mutex_lock(&self.annotations, "history annotations");
+        annotations.push_back(Annotation { time, details });
+        while annotations.len() > MAX_ANNOTATIONS
+            || annotations
+                .front()
+                .is_some_and(|annotation| annotation.time < time - retention)
+        {
+            annotations.pop_front();
+        }
+    }
+
+    /// Gets the annotations of a range
+    ///
+    /// # Arguments
+    ///
+    /// * `from` - Start of the range, in seconds since the epoch, or None for all
+    /// * `to` - End of the range, in seconds since the epoch, or None for now
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Value>` - The annotations, oldest first, with their `time` in RFC 3339
+    pub fn get_annotations(&self, from: Option<i64>, to: Option<i64>) -> Vec<Value> {
+        mutex_lock(&self.annotations, "history annotations")
+            .iter()
+            .filter(|annotation| {
+                from.is_none_or(|from| annotation.time >= from)
+                    && to.is_none_or(|to| annotation.time <= to)
+            })
+            .map(|annotation| {
+                let mut details = annotation.details.clone();
+                if let Some(details) = details.as_object_mut() {
+                    details.insert("time".into(), format_time(annotation.time).into());
+                }
+                details
+            })
+            .collect()
+    }
+
+    /// Describes the memory used by the history
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - The points stored at each level and for each field, the annotations
+    ///   and an estimate of the memory they use, in bytes
+    pub fn get_storage(&self) -> Value {
+        let series = mutex_lock(&self.series, "history");
+        let mut level_points = vec![0usize; self.levels.len()];
+        let mut fields = serde_json::Map::new();
+        for (field, series) in series.iter() {
+            let points: usize = series.points.iter().map(VecDeque::len).sum::<usize>()
+                + series.open.iter().flatten().count();
+            for (i, points) in series.points.iter().enumerate() {
+                level_points[i] += points.len() + usize::from(series.open[i].is_some());
+            }
+            fields.insert(field.clone(), json!(points));
+        }
+        drop(series);
+        let annotations = mutex_lock(&self.annotations, "history annotations");
+        let annotation_bytes: usize = annotations
+            .iter()
+            .map(|annotation| size_of::<Annotation>() + annotation.details.to_string().len())
+            .sum();
+        let total_points: usize = level_points.iter().sum();
+        json!({
+            "levels": self
+                .levels
+                .iter()
+                .zip(&level_points)
+                .map(|(level, points)| {
+                    let mut level = level.to_json();
+                    level["points"] = json!(points);
+                    level
+                })
+                .collect::<Vec<_>>(),
+            "fields": fields,
+            "points": total_points,
+            "annotations": annotations.len(),
+            "estimated_bytes": total_points * size_of::<HistoryPoint>() + annotation_bytes,
+        })
+    }
+
+    /// Gets the points of a field
+    ///
+    /// # Arguments
+    ///
+    /// * `field` - The field, as `page.field`, where the `index_` prefix can be omitted
+    /// * `level` - The level, or None to select it from `from` and `aggregate`
+    /// * `from` - Start of the range, in seconds since the epoch, or None for the whole
+    ///   retention
+    /// * `to` - End of the range, in seconds since the epoch, or None for now
+    /// * `aggregate` - Buckets in seconds (None for those of the level) and statistic
+    ///   computed over them, or None to return the points as stored
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(&HistoryLevel, Vec<Value>), String>` - The level used and its points,
+    ///   the bucket being filled included, or an error if the field or the level is
+    ///   unknown, or if the level is too coarse for the buckets
+    pub fn query(
+        &self,
+        field: &str,
+        level: Option<&str>,
+        from: Option<i64>,
+        to: Option<i64>,
+        aggregate: Option<(Option<i64>, Aggregation)>,
+    ) -> Result<(&HistoryLevel, Vec<Value>), String> {
+        let field = resolve_field(&self.fields, field)?;
+        let bucket = aggregate.and_then(|(bucket, _)| bucket);
+        let index = self.select_level(level, from, bucket)?;
+        let level = &self.levels[index];
+        let in_range = |point: &&HistoryPoint| {
+            from.is_none_or(|from| point.time >= from) && to.is_none_or(|to| point.time <= to)
+        };
+        let series = mutex_lock(&self.series, "history");
+        let Some(series) = series.get(&field) else {
+            return Ok((level, Vec::new()));
+        };
+        let points = series.points[index]
+            .iter()
+            .chain(series.open[index].as_ref())
+            .filter(in_range);
+        let Some((_, aggregation)) = aggregate else {
+            let raw = level.bucket_secs.is_none();
+            return Ok((level, points.map(|point| point.to_json(raw)).collect()));
+        };
+
+        let mut buckets: Vec<HistoryPoint> = Vec::new();
+        for point in points {
+            let start = bucket.map_or(point.time, |bucket| {
+                point.time - point.time.rem_euclid(bucket)
+            });
+            match buckets.last_mut() {
+                Some(last) if last.time == start => last.merge(point),
+                _ => buckets.push(HistoryPoint {
+                    time: start,
+                    ..point.clone()
+                }),
+            }
+        }
+        Ok((
+            level,
+            buckets
+                .iter()
+                .map(|point| point.to_value_json(aggregation))
+                .collect(),
+        ))
+    }
+
+    /// Selects the level of a query
+    ///
+    /// Without explicit level, the finest level still holding `from` is used; with
+    /// buckets, the coarsest level still holding `from` whose buckets fit in them, so
+    /// that fewer points are read.
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The requested level, if any
+    /// * `from` - Start of the range, in seconds since the epoch, if any
+    /// * `bucket` - The requested buckets in seconds, if any
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, String>` - Index of the level, or an error if it is unknown or
+    ///   too coarse for the buckets
+    fn select_level(
+        &self,
+        level: Option<&str>,
+        from: Option<i64>,
+        bucket: Option<i64>,
+    ) -> Result<usize, String> {
+        let fits = |level: &HistoryLevel| {
+            bucket.is_none_or(|bucket| {
+                level
+                    .bucket_secs
+                    .is_none_or(|secs| secs <= bucket && bucket % secs == 0)
+            })
+        };
+        let now = Utc::now().timestamp();
+        let holds =
+            |level: &HistoryLevel| from.is_none_or(|from| now - from <= level.retention_secs);
+
+        if let Some(name) = level {
+            let index = self
+                .levels
+                .iter()
+                .position(|level| level.name == name)
+                .ok_or_else(|| format!("unknown level '{}'", name))?;
+            if !fits(&self.levels[index]) {
+                return Err(format!("level '{}' is too coarse for the buckets", name));
+            }
+            return Ok(index);
+        }
+        if bucket.is_none() {
+            return Ok(from
+                .and_then(|_| self.levels.iter().position(holds))
+                .unwrap_or(self.levels.len() - 1));
+        }
+        let fitting: Vec<usize> = (0..self.levels.len())
+            .filter(|&i| fits(&self.levels[i]))
+            .collect();
+        fitting
+            .iter()
+            .rev()
+            .find(|&&i| holds(&self.levels[i]))
+            .or(fitting.last())
+            .copied()
+            .ok_or_else(|| "no level is fine enough for the buckets".to_string())
+    }
+}
+
+/// Parses the fields recorded in the history
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated `page.field`, where the `index_` prefix can be omitted
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, String>` - The fields as named in the state, or the first error
+pub fn parse_history_fields(spec: &str) -> Result<Vec<String>, String> {
+    let fields = known_fields();
+    let fields: Vec<String> = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| resolve_field(&fields, field))
+        .collect::<Result<_, _>>()?;
+    if fields.is_empty() {
+        return Err("at least one field is required".into());
+    }
+    Ok(fields)
+}
+
+/// Describes an event as an annotation of the history
+///
+/// Commands are annotated once accepted by the stove, with the action and the value of
+/// their `CommandQueued` event, which are kept in `queued` until then.
+///
+/// # Arguments
+///
+/// * `event` - The event
+/// * `queued` - Commands queued and not accepted nor dropped yet, by request ID
+///
+/// # Returns
+///
+/// * `Option<Value>` - Details of the annotation, or None if the event is not annotated
+fn annotation(event: Event, queued: &mut HashMap<u32, Value>) -> Option<Value> {
+    match event {
+        Event::StateTransition { from, to } => Some(json!({
+            "type": "state_transition",
+            "from": from,
+            "to": to,
+        })),
+        Event::CommandQueued {
+            request_id,
+            action,
+            value,
+            origin,
+        } => {
+            let command = StoveCommands::from_repr(action as usize);
+            let value = match value.parse::<f64>() {
+                Ok(tenths) if command.is_some_and(|command| command.is_temperature()) => {
+                    json!(tenths / 10.0)
+                }
+                Ok(number) => json!(number),
+                Err(_) => json!(value),
+            };
+            queued.insert(
+                request_id,
+                json!({
+                    "type": "command",
+                    "command": command.map(<&'static str>::from),
+                    "action": action,
+                    "value": value,
+                    "origin": origin,
+                }),
+            );
+            None
+        }
+        Event::CommandAccepted { request_id } => queued.remove(&request_id),
+        Event::CommandDropped { request_id, .. } => {
+            queued.remove(&request_id);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Starts a thread sampling the telemetry into the history
+///
+/// The numeric fields of `history.fields` are sampled every `history.sample_secs`,
+/// as long as their page was received recently. Commands accepted by the stove and
+/// state transitions are recorded as annotations.
+///
+/// # Arguments
+///
+/// * `config` - The `[history]` section, or None if disabled
+/// * `shared_state` - Shared state holding the stove data and the history
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_history_thread(
+    config: Option<&HistoryConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let history = match (
+        parse_history_fields(&config.fields),
+        parse_levels(&config.levels),
+    ) {
+        (Ok(fields), Ok(levels)) => Arc::new(History::new(fields, levels)),
+        (Err(e), _) | (_, Err(e)) => {
+            error!("History disabled: {}", e);
+            return None;
+        }
+    };
+    info!(
+        "Recording the history of {} field(s) at levels {}",
+        history.get_fields().len(),
+        history
+            .get_levels()
+            .iter()
+            .map(HistoryLevel::get_name)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    write_lock(&shared_state, "shared state").set_history(Arc::clone(&history));
+    let interval = Duration::from_secs(config.sample_secs.max(1));
+
+    Some(supervise(
+        "history",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut queued: HashMap<u32, Value> = HashMap::new();
+            let mut next_sample = Instant::now();
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(event) => {
+                        if let Some(details) = annotation(event, &mut queued) {
+                            history.annotate(Utc::now().timestamp(), details);
+                        }
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+                if Instant::now() < next_sample {
+                    continue;
+                }
+                next_sample += interval;
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for the history: {}", e);
+                        continue;
+                    }
+                };
+                let now = Utc::now().timestamp();
+                for field in history.get_fields() {
+                    let page = field.split('.').next().unwrap_or_default();
+                    let fresh = state
+                        .get(page)
+                        .and_then(|page| page.get("age_seconds"))
+                        .and_then(Value::as_u64)
+                        .is_some_and(|age| age <= STALE_PAGE_SECS);
+                    if let Some(value) = get_field(&state, field)
+                        .and_then(Value::as_f64)
+                        .filter(|_| fresh)
+                    {
+                        history.record(field, now, value);
+                    }
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Start of a 15-minute bucket, in seconds since the epoch
+    const START: i64 = 1_699_999_200;
+
+    fn history(levels: &str) -> History {
+        History::new(
+            vec!["dat0.index_smoke_t".into()],
+            parse_levels(levels).unwrap(),
+        )
+    }
+
+    fn query(
+        history: &History,
+        level: &str,
+        aggregate: Option<(Option<i64>, Aggregation)>,
+    ) -> Vec<Value> {
+        history
+            .query("dat0.smoke_t", Some(level), None, None, aggregate)
+            .unwrap()
+            .1
+    }
+
+    fn values(points: &[Value]) -> Vec<f64> {
+        points
+            .iter()
+            .map(|point| point["value"].as_f64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_span_accepts_units() {
+        assert_eq!(parse_span("90s"), Ok(90));
+        assert_eq!(parse_span(" 15m "), Ok(900));
+        assert_eq!(parse_span("6h"), Ok(21_600));
+        assert_eq!(parse_span("30d"), Ok(2_592_000));
+        for invalid in [
+            "",
+            "15",
+            "0m",
+            "-1h",
+            "1.5h",
+            "m",
+            "1w",
+            "9223372036854775807d",
+        ] {
+            assert!(parse_span(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_levels_requires_increasing_buckets() {
+        let levels = parse_levels("raw:6h, 1m:7d, 15m:365d").unwrap();
+        assert_eq!(
+            levels.iter().map(HistoryLevel::to_json).collect::<Vec<_>>(),
+            [
+                json!({"level": "raw", "bucket_secs": null, "retention_secs": 21_600}),
+                json!({"level": "1m", "bucket_secs": 60, "retention_secs": 604_800}),
+                json!({"level": "15m", "bucket_secs": 900, "retention_secs": 31_536_000}),
+            ]
+        );
+        assert_eq!(parse_levels("1m:1d").unwrap()[0].get_name(), "1m");
+        assert_eq!(
+            parse_levels("1m:1d, 60s:7d").unwrap_err(),
+            "level '60s' is not coarser than the previous one"
+        );
+        assert_eq!(
+            parse_levels("1m:1d, raw:1h").unwrap_err(),
+            "level 'raw' is not coarser than the previous one"
+        );
+        assert_eq!(
+            parse_levels("raw").unwrap_err(),
+            "expected bucket:retention, got 'raw'"
+        );
+        assert_eq!(
+            parse_levels(" , ").unwrap_err(),
+            "at least one level is required"
+        );
+    }
+
+    #[test]
+    fn record_aggregates_buckets() {
+        let history = history("raw:1h, 1m:1d");
+        for (offset, value) in [(0, 180.0), (20, 190.0), (59, 170.0), (60, 150.0)] {
+            history.record("dat0.index_smoke_t", START + offset, value);
+        }
+
+        assert_eq!(
+            values(&query(&history, "raw", None)),
+            [180.0, 190.0, 170.0, 150.0]
+        );
+        assert_eq!(
+            query(&history, "1m", None),
+            [
+                json!({"time": format_time(START), "avg": 180.0, "min": 170.0, "max": 190.0,
+                    "count": 3}),
+                // The bucket being filled is returned too
+                json!({"time": format_time(START + 60), "avg": 150.0, "min": 150.0,
+                    "max": 150.0, "count": 1}),
+            ]
+        );
+    }
+
+    #[test]
+    fn record_removes_expired_points() {
+        let history = history("raw:2m, 1m:3m");
+        for offset in [0, 60, 120, 180, 240] {
+            history.record("dat0.index_smoke_t", START + offset, offset as f64);
+        }
+        assert_eq!(values(&query(&history, "raw", None)), [120.0, 180.0, 240.0]);
+        let minutes: Vec<f64> = query(&history, "1m", None)
+            .iter()
+            .map(|point| point["avg"].as_f64().unwrap())
+            .collect();
+        assert_eq!(minutes, [60.0, 120.0, 180.0, 240.0]);
+        assert_eq!(history.get_storage()["points"], 7);
+    }
+
+    #[test]
+    fn query_aggregates_into_coarser_buckets() {
+        let history = history("raw:1h, 1m:1d");
+        for (offset, value) in [(0, 1.0), (30, 5.0), (60, 3.0), (150, 8.0), (170, 2.0)] {
+            history.record("dat0.index_smoke_t", START + offset, value);
+        }
+        let aggregate = |level, aggregation| {
+            let points = query(&history, level, Some((Some(120), aggregation)));
+            let times: Vec<String> = points
+                .iter()
+                .map(|point| point["time"].as_str().unwrap().to_string())
+                .collect();
+            assert_eq!(times, [format_time(START), format_time(START + 120)]);
+            values(&points)
+        };
+        assert_eq!(aggregate("raw", Aggregation::Max), [5.0, 8.0]);
+        assert_eq!(aggregate("1m", Aggregation::Min), [1.0, 2.0]);
+        assert_eq!(aggregate("1m", Aggregation::Avg), [3.0, 5.0]);
+        // The statistic of the stored buckets without rebucketing
+        assert_eq!(
+            values(&query(&history, "1m", Some((None, Aggregation::Max)))),
+            [5.0, 3.0, 8.0]
+        );
+    }
+
+    #[test]
+    fn query_selects_the_level() {
+        let history = history("raw:1h, 1m:1d, 15m:365d");
+        let level = |level: Option<&str>, from: Option<i64>, bucket: Option<i64>| {
+            history
+                .query(
+                    "dat0.smoke_t",
+                    level,
+                    from,
+                    None,
+                    bucket.map(|bucket| (Some(bucket), Aggregation::Avg)),
+                )
+                .map(|(level, _)| level.get_name().to_string())
+        };
+        let now = Utc::now().timestamp();
+        assert_eq!(level(None, None, None).unwrap(), "15m");
+        assert_eq!(level(None, Some(now - 600), None).unwrap(), "raw");
+        assert_eq!(level(None, Some(now - 7200), None).unwrap(), "1m");
+        assert_eq!(level(None, Some(now - 600), Some(300)).unwrap(), "1m");
+        assert_eq!(level(None, None, Some(1800)).unwrap(), "15m");
+        assert_eq!(level(None, Some(now - 7200), Some(90)).unwrap(), "raw");
+        assert_eq!(
+            level(Some("15m"), None, Some(60)).unwrap_err(),
+            "level '15m' is too coarse for the buckets"
+        );
+        assert_eq!(
+            level(Some("5m"), None, None).unwrap_err(),
+            "unknown level '5m'"
+        );
+        assert_eq!(
+            history
+                .query("dat0.nothing", None, None, None, None)
+                .unwrap_err(),
+            "unknown field 'dat0.nothing'"
+        );
+        assert!(query(&history, "raw", None).is_empty());
+    }
+
+    #[test]
+    fn import_merges_with_the_stored_points() {
+        let history = history("raw:1h, 1m:1d");
+        let now = Utc::now().timestamp();
+        let start = now - now.rem_euclid(60) - 600;
+        history.record("dat0.index_smoke_t", start + 10, 1.0);
+
+        let kept = history.import(
+            "dat0.index_smoke_t",
+            &[
+                (start + 20, 3.0),
+                (start - 55, 7.0),
+                (now + 1000, 9.0),
+                (now - 100_000, 5.0),
+            ],
+        );
+        assert_eq!(kept, 2);
+        assert_eq!(values(&query(&history, "raw", None)), [7.0, 1.0, 3.0]);
+        assert_eq!(
+            query(&history, "1m", None),
+            [
+                json!({"time": format_time(start - 60), "avg": 7.0, "min": 7.0, "max": 7.0,
+                    "count": 1}),
+                json!({"time": format_time(start), "avg": 2.0, "min": 1.0, "max": 3.0,
+                    "count": 2}),
+            ]
+        );
+    }
+}
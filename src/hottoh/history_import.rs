@@ -0,0 +1,324 @@
+use crate::hottoh::http_client;
+use crate::hottoh::state_fields::resolve_field;
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Path of the import endpoint
+pub const HISTORY_IMPORT_PATH: &str = "/api/history/import";
+
+/// Largest CSV file accepted by `POST /api/history/import`, in bytes
+pub const MAX_IMPORT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Formats of the times without offset, read in local time
+const LOCAL_TIME_FORMATS: &[&str] = &[
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%d/%m/%Y %H:%M:%S",
+    "%d/%m/%Y %H:%M",
+];
+
+/// Usage of the import-history subcommand
+const USAGE: &str =
+    "Usage: hottoh_api import-history <file.csv> [--url <URL>] [--api-key <key>] [--pin <PIN>]
+
+Sends a CSV file to the history of a running bridge (POST /api/history/import), e.g.
+to keep the data of a previous logger. The first column is the time (RFC 3339,
+YYYY-MM-DD HH:MM:SS in local time, or Unix seconds) and the header names the other
+columns as page.field (e.g. dat0.smoke_t); columns not recorded in the history are
+ignored. Commas or semicolons separate the columns.
+
+Options:
+  --url <URL>      URL of the bridge (default http://127.0.0.1:3000)
+  --api-key <key>  API key, if auth.keys is configured
+  --pin <PIN>      PIN, if http_api.pin is configured
+  --help           Show this help";
+
+/// Samples read from a CSV file
+#[derive(Debug, Default)]
+pub struct CsvImport {
+    /// Samples by field, as named in the state, with their time in seconds since the epoch
+    pub samples: BTreeMap<String, Vec<(i64, f64)>>,
+    /// Columns of the header not recorded in the history
+    pub ignored_columns: Vec<String>,
+    /// Number of rows read
+    pub rows: usize,
+    /// Number of non-empty cells which are not numbers
+    pub invalid_values: usize,
+}
+
+/// Parses the time of a CSV row
+///
+/// # Arguments
+///
+/// * `text` - The time, in RFC 3339, as a local time (e.g. `2024-01-15 10:00:00`) or
+///   in Unix seconds (milliseconds above 10^11)
+///
+/// # Returns
+///
+/// * `Option<i64>` - The time in seconds since the epoch, or None if it is invalid
+fn parse_time(text: &str) -> Option<i64> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.timestamp());
+    }
+    if let Ok(seconds) = text.parse::<f64>() {
+        let seconds = if seconds.abs() > 1e11 {
+            seconds / 1000.0
+        } else {
+            seconds
+        };
+        return seconds.is_finite().then_some(seconds as i64);
+    }
+    LOCAL_TIME_FORMATS.iter().find_map(|format| {
+        let time = NaiveDateTime::parse_from_str(text, format).ok()?;
+        Local
+            .from_local_datetime(&time)
+            .earliest()
+            .map(|time| time.timestamp())
+    })
+}
+
+/// Splits a CSV line into its cells, without their surrounding quotes
+///
+/// # Arguments
+///
+/// * `line` - The line
+/// * `separator` - `,` or `;`
+///
+/// # Returns
+///
+/// * `Vec<&str>` - The cells, trimmed
+fn split_line(line: &str, separator: char) -> Vec<&str> {
+    line.split(separator)
+        .map(|cell| cell.trim().trim_matches('"').trim())
+        .collect()
+}
+
+/// Parses a CSV file exported by another logger
+///
+/// The first column is the time, the header names the other columns as `page.field`,
+/// where the `index_` prefix can be omitted. The separator is `;` if the header has
+/// semicolons and no comma, in which case decimal commas are accepted.
+///
+/// # Arguments
+///
+/// * `text` - The content of the file
+/// * `fields` - The fields recorded in the history
+///
+/// # Returns
+///
+/// * `Result<CsvImport, String>` - The samples, or an error if the header has no known
+///   field or a time is invalid
+pub fn parse_csv(text: &str, fields: &[String]) -> Result<CsvImport, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or("the file is empty")?;
+    let header = header.trim_start_matches('\u{feff}');
+    let separator = if header.contains(';') && !header.contains(',') {
+        ';'
+    } else {
+        ','
+    };
+
+    let mut import = CsvImport::default();
+    let columns: Vec<Option<String>> = split_line(header, separator)
+        .into_iter()
+        .skip(1)
+        .map(|name| match resolve_field(fields, name) {
+            Ok(field) => Some(field),
+            Err(_) => {
+                import.ignored_columns.push(name.to_string());
+                None
+            }
+        })
+        .collect();
+    if columns.iter().all(Option::is_none) {
+        return Err(format!(
+            "no column is recorded in the history (fields: {})",
+            fields.join(", ")
+        ));
+    }
+
+    for (index, line) in lines {
+        let cells = split_line(line, separator);
+        let time = parse_time(cells[0])
+            .ok_or_else(|| format!("line {}: invalid time '{}'", index + 1, cells[0]))?;
+        import.rows += 1;
+        for (field, cell) in columns.iter().zip(&cells[1..]) {
+            let Some(field) = field else {
+                continue;
+            };
+            if cell.is_empty() {
+                continue;
+            }
+            let value = if separator == ';' {
+                cell.replace(',', ".").parse::<f64>()
+            } else {
+                cell.parse::<f64>()
+            };
+            match value {
+                Ok(value) if value.is_finite() => import
+                    .samples
+                    .entry(field.clone())
+                    .or_default()
+                    .push((time, value)),
+                _ => import.invalid_values += 1,
+            }
+        }
+    }
+    Ok(import)
+}
+
+/// Runs `hottoh_api import-history <file.csv> [--url <URL>] [--api-key <key>] [--pin <PIN>]`
+///
+/// The history is kept in memory by the running bridge, so the file is sent to it.
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `import-history`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error message
+pub fn run_import_history(args: &[String]) -> Result<(), String> {
+    let mut path: Option<&str> = None;
+    let mut url = "http://127.0.0.1:3000".to_string();
+    let mut api_key: Option<&str> = None;
+    let mut pin: Option<&str> = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("{} needs a value\n\n{}", arg, USAGE))
+        };
+        match arg.as_str() {
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            "--url" => url = value()?.trim_end_matches('/').to_string(),
+            "--api-key" => api_key = Some(value()?),
+            "--pin" => pin = Some(value()?),
+            other if path.is_none() && !other.starts_with("--") => path = Some(other),
+            other => return Err(format!("unexpected argument '{}'\n\n{}", other, USAGE)),
+        }
+    }
+    let path = path.ok_or_else(|| USAGE.to_string())?;
+    let content = fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+
+    let mut headers = vec![("Content-Type", "text/csv")];
+    if let Some(api_key) = api_key {
+        headers.push(("X-Api-Key", api_key));
+    }
+    if let Some(pin) = pin {
+        headers.push(("X-Hottoh-Pin", pin));
+    }
+    let response = http_client::send(
+        "POST",
+        &format!("{}/api/history/import", url),
+        &headers,
+        &content,
+    )
+    .map_err(|e| e.to_string())?;
+    if !response.is_success() {
+        return Err(format!("HTTP {}: {}", response.status, response.body));
+    }
+    println!("{}", response.body);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> Vec<String> {
+        vec!["dat0.index_smoke_t".into(), "dat0.index_ambient_t1".into()]
+    }
+
+    #[test]
+    fn parse_time_accepts_the_supported_formats() {
+        assert_eq!(parse_time("2024-01-15T10:00:00Z"), Some(1_705_312_800));
+        assert_eq!(parse_time("2024-01-15T11:00:00+01:00"), Some(1_705_312_800));
+        assert_eq!(parse_time("1705312800"), Some(1_705_312_800));
+        assert_eq!(parse_time("1705312800.9"), Some(1_705_312_800));
+        assert_eq!(parse_time("1705312800000"), Some(1_705_312_800));
+
+        let local = |text| {
+            let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap();
+            Local
+                .from_local_datetime(&time)
+                .earliest()
+                .unwrap()
+                .timestamp()
+        };
+        let expected = Some(local("2024-01-15 10:00:00"));
+        assert_eq!(parse_time("2024-01-15 10:00:00"), expected);
+        assert_eq!(parse_time("2024-01-15T10:00:00.250"), expected);
+        assert_eq!(parse_time("2024-01-15 10:00"), expected);
+        assert_eq!(parse_time("15/01/2024 10:00:00"), expected);
+        assert_eq!(parse_time("15/01/2024 10:00"), expected);
+
+        assert_eq!(parse_time("yesterday"), None);
+        assert_eq!(parse_time("2024-13-01 10:00"), None);
+        assert_eq!(parse_time("inf"), None);
+    }
+
+    #[test]
+    fn parse_csv_reads_the_known_columns() {
+        let text = "\u{feff}time,dat0.smoke_t,\"dat0.ambient_t1\",other\n\
+                    2024-01-15T10:00:00Z,180.5,21,x\n\
+                    \n\
+                    2024-01-15T10:01:00Z,,21.5,y\n\
+                    1705312920,abc,NaN,z\n";
+        let import = parse_csv(text, &fields()).unwrap();
+        assert_eq!(import.rows, 3);
+        assert_eq!(import.ignored_columns, ["other"]);
+        assert_eq!(import.invalid_values, 2);
+        assert_eq!(
+            import.samples["dat0.index_smoke_t"],
+            [(1_705_312_800, 180.5)]
+        );
+        assert_eq!(
+            import.samples["dat0.index_ambient_t1"],
+            [(1_705_312_800, 21.0), (1_705_312_860, 21.5)]
+        );
+    }
+
+    #[test]
+    fn parse_csv_accepts_semicolons_with_decimal_commas() {
+        let text = "time;dat0.smoke_t\r\n1705312800;\"180,5\"\r\n1705312860;181\r\n";
+        let import = parse_csv(text, &fields()).unwrap();
+        assert_eq!(
+            import.samples["dat0.index_smoke_t"],
+            [(1_705_312_800, 180.5), (1_705_312_860, 181.0)]
+        );
+        // With commas in the header, the decimal comma is a separator
+        let text = "time,dat0.smoke_t\n1705312800,180,5\n";
+        let import = parse_csv(text, &fields()).unwrap();
+        assert_eq!(
+            import.samples["dat0.index_smoke_t"],
+            [(1_705_312_800, 180.0)]
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_unusable_files() {
+        assert_eq!(
+            parse_csv(" \n\n", &fields()).unwrap_err(),
+            "the file is empty"
+        );
+        assert_eq!(
+            parse_csv("time,dat9.x\n1705312800,1\n", &fields()).unwrap_err(),
+            "no column is recorded in the history (fields: dat0.index_smoke_t, \
+             dat0.index_ambient_t1)"
+        );
+        assert_eq!(
+            parse_csv("time,dat0.smoke_t\n\n1705312800,1\nnoon,2\n", &fields()).unwrap_err(),
+            "line 4: invalid time 'noon'"
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use crate::hottoh::config::HomeAssistantConfig;
+use crate::hottoh::hottoh_const::StoveState;
+use crate::hottoh::http_client;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::get_field;
+use crate::hottoh::supervisor::supervise;
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Length of the statistics periods of Home Assistant, in seconds
+const HOUR_SECS: i64 = 3600;
+/// Interval between two attempts to push the hours not pushed yet
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+/// Hours kept while Home Assistant cannot be reached, the oldest ones being dropped
+const MAX_PENDING_HOURS: usize = 24 * 31;
+/// Pages older than this are not sampled, e.g. while the stove is disconnected
+const STALE_PAGE_SECS: u64 = 60;
+
+/// Statistic pushed to Home Assistant: ID suffix, name, unit and value of an hour
+type Statistic = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&HourTotals) -> f64,
+);
+
+/// Statistics pushed to Home Assistant
+const STATISTICS: [Statistic; 2] = [
+    ("energy", "Stove energy", "kWh", |hour| hour.energy_kwh),
+    ("runtime", "Stove runtime", "h", |hour| hour.runtime_hours),
+];
+
+/// Totals at the end of an hour
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct HourTotals {
+    /// Start of the hour, in seconds since the epoch
+    start: i64,
+    /// Estimated heat delivered since the first hour, in kWh
+    energy_kwh: f64,
+    /// Time spent heating since the first hour, in hours
+    runtime_hours: f64,
+}
+
+/// Totals kept across restarts
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Totals {
+    /// Estimated heat delivered since the first hour, in kWh
+    energy_kwh: f64,
+    /// Time spent heating since the first hour, in hours
+    runtime_hours: f64,
+    /// Completed hours not pushed yet, oldest first
+    pending: Vec<HourTotals>,
+}
+
+/// Reads the totals
+///
+/// # Arguments
+///
+/// * `path` - Path of the state file
+///
+/// # Returns
+///
+/// * `io::Result<Totals>` - The totals, zero if the file does not exist yet
+fn load_totals(path: &str) -> io::Result<Totals> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Totals::default()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the totals
+///
+/// # Arguments
+///
+/// * `path` - Path of the state file
+/// * `totals` - The totals
+///
+/// # Returns
+///
+/// * `io::Result<()>` - An error if the file cannot be written
+fn save_totals(path: &str, totals: &Totals) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(totals).map_err(io::Error::other)?;
+    fs::write(path, content)
+}
+
+/// Parses the heat output at each power level
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated outputs in kW, from power level 1 (e.g. `2.5, 3.5, 4.5`)
+///
+/// # Returns
+///
+/// * `Result<Vec<f64>, String>` - The outputs, or an error if one is invalid or none is given
+pub fn parse_power_kw(spec: &str) -> Result<Vec<f64>, String> {
+    let outputs: Vec<f64> = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|output| !output.is_empty())
+        .map(|output| {
+            output
+                .parse::<f64>()
+                .ok()
+                .filter(|output| output.is_finite() && *output >= 0.0)
+                .ok_or_else(|| format!("invalid output '{}'", output))
+        })
+        .collect::<Result<_, _>>()?;
+    if outputs.is_empty() {
+        return Err("at least one output is required".into());
+    }
+    Ok(outputs)
+}
+
+/// Sends the hours not pushed yet to Home Assistant
+///
+/// The energy and the runtime are imported as external statistics with a `sum`,
+/// through the `recorder.import_statistics` service.
+///
+/// # Arguments
+///
+/// * `url` - Base URL of Home Assistant, without trailing slash
+/// * `token` - Long-lived access token
+/// * `prefix` - Prefix of the statistic IDs
+/// * `hours` - The hours to push
+///
+/// # Returns
+///
+/// * `Result<(), String>` - An error if Home Assistant cannot be reached or rejects them
+fn push_hours(url: &str, token: &str, prefix: &str, hours: &[HourTotals]) -> Result<(), String> {
+    let authorization = format!("Bearer {}", token);
+    let headers = [
+        ("Authorization", authorization.as_str()),
+        ("Content-Type", "application/json"),
+    ];
+    for (name, title, unit, value) in STATISTICS {
+        let stats: Vec<Value> = hours
+            .iter()
+            .map(|hour| {
+                let start = DateTime::<Utc>::from_timestamp(hour.start, 0)
+                    .map(|start| start.to_rfc3339())
+                    .unwrap_or_default();
+                json!({ "start": start, "state": value(hour), "sum": value(hour) })
+            })
+            .collect();
+        let body = json!({
+            "statistic_id": format!("{}:{}", prefix, name),
+            "source": prefix,
+            "name": title,
+            "unit_of_measurement": unit,
+            "has_mean": false,
+            "has_sum": true,
+            "stats": stats,
+        });
+        let response = http_client::send(
+            "POST",
+            &format!("{}/api/services/recorder/import_statistics", url),
+            &headers,
+            &body.to_string(),
+        )
+        .map_err(|e| e.to_string())?;
+        if !response.is_success() {
+            return Err(format!("HTTP {}: {}", response.status, response.body));
+        }
+    }
+    Ok(())
+}
+
+/// Starts a thread pushing the energy and the runtime of the stove to Home Assistant
+///
+/// The stove is sampled every `sample_secs`: while it is heating, the time elapsed
+/// is added to the runtime and the output of its power level to the energy. The totals
+/// are pushed at the end of each hour as long-term statistics, so that the stove can be
+/// added to the Energy dashboard. Hours that cannot be pushed are kept in the state
+/// file and retried.
+///
+/// # Arguments
+///
+/// * `config` - The `[home_assistant]` section, or None if disabled
+/// * `shared_state` - Shared state holding the stove data
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_home_assistant_thread(
+    config: Option<&HomeAssistantConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let power_kw = match parse_power_kw(&config.power_kw) {
+        Ok(power_kw) => power_kw,
+        Err(e) => {
+            error!(
+                "Home Assistant statistics disabled: invalid power_kw: {}",
+                e
+            );
+            return None;
+        }
+    };
+    let url = config.url.trim_end_matches('/').to_string();
+    let token = config.token.clone();
+    let prefix = config.statistic_prefix.clone();
+    let state_file = config.state_file.clone();
+    let interval = Duration::from_secs(config.sample_secs.max(1));
+    info!(
+        "Pushing the energy and runtime statistics '{}' to Home Assistant at {}",
+        prefix, url
+    );
+
+    Some(supervise(
+        "home_assistant",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let mut totals = match load_totals(&state_file) {
+                Ok(totals) => totals,
+                Err(e) => {
+                    error!("Cannot read {}, totals restarted: {}", state_file, e);
+                    Totals::default()
+                }
+            };
+            let mut hour = Utc::now().timestamp().div_euclid(HOUR_SECS) * HOUR_SECS;
+            let mut last_sample = Instant::now();
+            let mut last_push: Option<Instant> = None;
+            while running.load(Ordering::SeqCst) {
+                if last_sample.elapsed() < interval {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                // Long gaps (e.g. a suspended host) are not counted as heating
+                let elapsed = last_sample.elapsed().min(interval * 2).as_secs_f64() / 3600.0;
+                last_sample = Instant::now();
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for Home Assistant: {}", e);
+                        continue;
+                    }
+                };
+                let fresh = state
+                    .get("dat0")
+                    .and_then(|page| page.get("age_seconds"))
+                    .and_then(Value::as_u64)
+                    .is_some_and(|age| age <= STALE_PAGE_SECS);
+                let heating = get_field(&state, "dat0.index_stove_state")
+                    .and_then(|state| serde_json::from_value::<StoveState>(state.clone()).ok())
+                    .is_some_and(|state| state.is_heating());
+                if fresh && heating {
+                    let level = get_field(&state, "dat0.index_power_level")
+                        .and_then(Value::as_u64)
+                        .unwrap_or(1)
+                        .max(1);
+                    let output = power_kw
+                        .get(level as usize - 1)
+                        .or(power_kw.last())
+                        .copied()
+                        .unwrap_or_default();
+                    totals.energy_kwh += output * elapsed;
+                    totals.runtime_hours += elapsed;
+                }
+
+                let now = Utc::now().timestamp().div_euclid(HOUR_SECS) * HOUR_SECS;
+                let ended = now != hour;
+                if ended {
+                    totals.pending.push(HourTotals {
+                        start: hour,
+                        energy_kwh: totals.energy_kwh,
+                        runtime_hours: totals.runtime_hours,
+                    });
+                    let excess = totals.pending.len().saturating_sub(MAX_PENDING_HOURS);
+                    totals.pending.drain(..excess);
+                    hour = now;
+                }
+                let retry = !totals.pending.is_empty()
+                    && last_push.is_none_or(|last| last.elapsed() >= RETRY_INTERVAL);
+                if ended || retry {
+                    last_push = Some(Instant::now());
+                    match push_hours(&url, &token, &prefix, &totals.pending) {
+                        Ok(()) => {
+                            debug!(
+                                "Pushed {} hour(s) of statistics to Home Assistant",
+                                totals.pending.len()
+                            );
+                            totals.pending.clear();
+                        }
+                        Err(e) => warn!(
+                            "Cannot push statistics to Home Assistant, {} hour(s) kept: {}",
+                            totals.pending.len(),
+                            e
+                        ),
+                    }
+                    if let Err(e) = save_totals(&state_file, &totals) {
+                        error!("Cannot write {}: {}", state_file, e);
+                    }
+                }
+            }
+            if let Err(e) = save_totals(&state_file, &totals) {
+                error!("Cannot write {}: {}", state_file, e);
+            }
+            info!("Home Assistant statistics thread stopped.");
+        },
+    ))
+}
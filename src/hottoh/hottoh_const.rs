@@ -1,6 +1,10 @@
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use strum_macros::IntoStaticStr;
+use strum_macros::{EnumString, FromRepr, IntoStaticStr};
+use utoipa::ToSchema;
+
+/// Protocol profile used to encode requests and decode responses
+pub const PROTOCOL_PROFILE: &str = "hottoh";
 
 /// Type of command to be sent to the stove
 #[derive(Debug, PartialEq)]
@@ -51,7 +55,7 @@ impl CommandType {
 }
 
 /// Command to be sent to the stove
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Command {
     /// Information command (hostname, version, signal)
     Inf,
@@ -111,7 +115,7 @@ impl FromStr for Command {
 }
 
 /// Current state of the stove
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default, ToSchema)]
 pub enum StoveState {
     #[default]
     Off = 0,
@@ -184,6 +188,51 @@ impl FromStr for StoveState {
     }
 }
 
+impl StoveState {
+    /// Checks if the state is an alarm needing an intervention
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True for the black out, ignition failure, pellet shortage and open cover
+    pub fn is_alarm(&self) -> bool {
+        matches!(
+            self,
+            StoveState::EndPellet
+                | StoveState::BlackOut
+                | StoveState::IgnitionFailed
+                | StoveState::NoPellet
+                | StoveState::CoverOpen
+        )
+    }
+
+    /// Checks if the stove is burning pellets at its power level
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True in the Power state, also when the pellets are low
+    pub fn is_heating(&self) -> bool {
+        matches!(self, StoveState::Power | StoveState::LowPellet)
+    }
+
+    /// Gets the phase of the startup sequence
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u8>` - The phase number, from 1 to 7, or None outside of the startup sequence
+    pub fn get_startup_phase(&self) -> Option<u8> {
+        match self {
+            StoveState::Starting1 => Some(1),
+            StoveState::Starting2 => Some(2),
+            StoveState::Starting3 => Some(3),
+            StoveState::Starting4 => Some(4),
+            StoveState::Starting5 => Some(5),
+            StoveState::Starting6 => Some(6),
+            StoveState::Starting7 => Some(7),
+            _ => None,
+        }
+    }
+}
+
 /// Chrono mode of the stove
 #[derive(PartialEq)]
 #[allow(dead_code)]
@@ -229,7 +278,7 @@ impl StoveManufacturer {
 }
 
 /// Commands that can be sent to the stove
-#[derive(IntoStaticStr, Debug, PartialEq)]
+#[derive(IntoStaticStr, EnumString, FromRepr, Debug, PartialEq, Clone, Copy)]
 #[allow(dead_code)]
 pub enum StoveCommands {
     OnOff = 0,
@@ -250,3 +299,24 @@ pub enum StoveCommands {
     HottohSetRecipe = 15,      // unknown
     HottohSetPelSetpoint = 16, // unknown
 }
+
+impl StoveCommands {
+    /// Checks if the command sets a temperature, sent in tenths of a degree
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True for temperature commands, false otherwise
+    pub fn is_temperature(&self) -> bool {
+        matches!(
+            self,
+            StoveCommands::AmbianceTemperature1
+                | StoveCommands::AmbianceTemperature2
+                | StoveCommands::ChronoTemperature1
+                | StoveCommands::ChronoTemperature2
+                | StoveCommands::ChronoTemperature3
+                | StoveCommands::SanTemperature
+                | StoveCommands::PufTemperature
+                | StoveCommands::BoilerTemperature
+        )
+    }
+}
@@ -1,642 +1,496 @@
 use super::hottoh_const::*;
+use crate::hottoh::layouts::HydroMapping;
+use crate::hottoh::redaction::REDACTED;
 use crate::hottoh::tcp_client_structs::ResponseError;
-use chrono::{Local, SecondsFormat};
+use crate::hottoh::temperature::serialize_tenths;
+use chrono::{DateTime, Local, SecondsFormat, TimeZone, Utc};
 use crc_any::CRCu16;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::{Serialize, Serializer};
 use std::str;
+use std::str::FromStr;
+use std::time::Instant;
+use utoipa::ToSchema;
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-pub struct INFData {
-    hostname: String,
-    version: String,
-    signal: String,
-    last_updated: String,
+/// Value that can be parsed from a single element of a response
+pub trait FieldValue: Sized {
+    /// Parses the value from a response element
+    ///
+    /// # Arguments
+    ///
+    /// * `s` - The response element
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Self>` - The parsed value, or None if invalid
+    fn parse_value(s: &str) -> Option<Self>;
 }
 
-impl INFData {
-    pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
-        if response_data.len() != 3 {
-            return Err(ResponseError::IncorrectResponseStruct(
-                "Incorrect number of elements".to_string(),
-            ));
+impl FieldValue for u16 {
+    fn parse_value(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+impl FieldValue for i16 {
+    fn parse_value(s: &str) -> Option<Self> {
+        s.parse().ok()
+    }
+}
+
+impl FieldValue for bool {
+    fn parse_value(s: &str) -> Option<Self> {
+        match s {
+            "0" => Some(false),
+            "1" => Some(true),
+            _ => None,
         }
+    }
+}
 
-        Ok(Self {
-            hostname: response_data[0].to_string(),
-            version: response_data[1].to_string(),
-            signal: response_data[2].to_string(),
-            last_updated: Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-        })
+impl FieldValue for String {
+    fn parse_value(s: &str) -> Option<Self> {
+        Some(s.to_string())
     }
 }
 
-#[derive(Default)]
-#[allow(dead_code)]
-pub struct DATReqResponseData {
-    value: String,
+impl FieldValue for StoveState {
+    fn parse_value(s: &str) -> Option<Self> {
+        StoveState::from_str(s).ok()
+    }
 }
 
-impl DATReqResponseData {
-    pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
-        if response_data.len() != 1 {
-            return Err(ResponseError::IncorrectResponseStruct(
-                "Incorrect number of elements".to_string(),
-            ));
-        }
-        Ok(Self {
-            value: response_data[0].to_string(),
-        })
+/// Value sent by the stove in tenths of a unit (e.g. 215 for 21.5°C)
+///
+/// Serialized as a decimal number, or as an integer if `general.temperature_decimals`
+/// is 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, ToSchema)]
+#[schema(value_type = f32)]
+pub struct Tenths(pub i16);
+
+impl Tenths {
+    /// Gets the value in units
+    ///
+    /// # Returns
+    ///
+    /// * `f32` - The value divided by ten
+    pub fn as_f32(&self) -> f32 {
+        (self.0 as f32) / 10.0
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
-pub struct DAT0Data {
-    index_page: u16,
-    #[serde(serialize_with = "serialize_stove_manufacturer")]
-    index_manufacturer: u16,
-    index_bitmap_visible: bool,
-    index_valid: bool,
-    index_stove_type: u16,
-    index_stove_state: StoveState,
-    index_stove_on: bool,
-    index_eco_mode: bool,
-    index_timer_on: u16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t1: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t1_set: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t1_set_min: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t1_set_max: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t2: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t2_set: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t2_set_min: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_ambient_t2_set_max: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_water: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_water_set: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_water_set_min: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_water_set_max: i16,
-    #[serde(serialize_with = "serialize_i16_as_f32")]
-    index_smoke_t: i16,
-    index_power_level: u16,
-    index_power_set: u16,
-    index_power_min: u16,
-    index_power_max: u16,
-    index_fan_smoke: u16,
-    index_fan_1: u16,
-    index_fan_1_set: u16,
-    index_fan_1_set_max: u16,
-    index_fan_2: u16,
-    index_fan_2_set: u16,
-    index_fan_2_set_max: u16,
-    index_fan_3: u16,
-    index_fan_3_set: u16,
-    index_fan_3_set_max: u16,
-    boiler_enabled: bool,
-    domestic_hot_water_enabled: bool,
-    fan_number: u16,
-    temp_room1_enabled: bool,
-    temp_room2_enabled: bool,
-    temp_room3_enabled: bool,
-    temp_water_enabled: bool,
-    pump_enabled: bool,
-    last_updated: String,
+impl FieldValue for Tenths {
+    fn parse_value(s: &str) -> Option<Self> {
+        s.parse().ok().map(Tenths)
+    }
 }
 
-impl DAT0Data {
-    pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
-        if response_data.len() != 36 {
-            return Err(ResponseError::IncorrectResponseStruct(
-                "Incorrect number of elements in DAT0 struct".to_string(),
-            ));
+impl Serialize for Tenths {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serialize_tenths(self.0, serializer)
+    }
+}
+
+/// Declares a response page from a table of fields
+///
+/// Each entry gives the index of the element in the response, the field name and
+/// its type. The type decides how the element is parsed (see [`FieldValue`]) and
+/// serialized, e.g. [`Tenths`] for values scaled by ten. Derived fields are computed
+/// from the parsed fields, and decoded fields are filled after parsing, e.g. from the
+/// firmware layout. The macro generates the struct, its serialization and OpenAPI
+/// schema, `from_slice`, and the list of the parsed fields.
+macro_rules! dat_page {
+    (
+        $(#[$meta:meta])*
+        pub struct $name:ident ($page:literal, $len:literal) {
+            $( $index:literal => $(#[$fmeta:meta])* $field:ident : $ty:ty, )*
+        }
+        $( derived {
+            $( $(#[$dmeta:meta])* $dfield:ident : $dty:ty = $dexpr:expr, )*
+        } )?
+        $( decoded {
+            $( $(#[$xmeta:meta])* $xfield:ident : $xty:ty, )*
+        } )?
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Serialize, Clone, Default, ToSchema)]
+        pub struct $name {
+            $( $(#[$fmeta])* $field: $ty, )*
+            $( $( $(#[$dmeta])* $dfield: $dty, )* )?
+            $( $( $(#[$xmeta])* $xfield: $xty, )* )?
+            /// Params sent after the last known one, kept as received
+            #[serde(skip_serializing_if = "Vec::is_empty")]
+            raw_extra: Vec<String>,
+            /// Wall-clock time at which the data was received, in UTC
+            #[serde(serialize_with = "serialize_last_updated")]
+            #[schema(value_type = String)]
+            last_updated: Option<DateTime<Utc>>,
+            /// Same time in the configured timezone, with its offset
+            #[serde(serialize_with = "serialize_last_updated")]
+            #[schema(value_type = String)]
+            last_updated_local: Option<DateTime<Local>>,
+            /// Monotonic time at which the data was received, serialized as its age in
+            /// seconds so that staleness is not affected by clock changes
+            #[serde(rename = "age_seconds", serialize_with = "serialize_age_seconds")]
+            #[schema(value_type = Option<u64>)]
+            received_at: Option<Instant>,
         }
-        let index_stove_type: u16 = response_data[4].parse().map_err(|_| {
-            ResponseError::IncorrectResponseStruct(format!(
-                "Invalid index_stove_type: {}",
-                response_data[4]
-            ))
-        })?;
-
-        // let boiler_enabled = (index_stove_type & (1 << 9)) != 0;
-        // let domestic_hot_water_enabled = (index_stove_type & (1 << 10)) != 0;
-        // let fan_number = (index_stove_type >> 12) & 0b11;
-        // let temp_room1_enabled = (index_stove_type & (1 << 15)) != 0;
-        // let temp_room2_enabled = (index_stove_type & (1 << 8)) != 0;
-        // let temp_room3_enabled = (index_stove_type & (1 << 7)) != 0;
-        // let temp_water_enabled = (index_stove_type & (1 << 14)) != 0;
-        // let pump_enabled = (index_stove_type & (1 << 4)) != 0;
-        let boiler_enabled = (index_stove_type & (1 << 6)) != 0;
-        let domestic_hot_water_enabled = (index_stove_type & (1 << 5)) != 0;
-        let fan_number = (index_stove_type >> 2) & 0b11;
-        let temp_room1_enabled = (index_stove_type & (1 << 0)) != 0;
-        let temp_room2_enabled = (index_stove_type & (1 << 8)) != 0;
-        let temp_room3_enabled = (index_stove_type & (1 << 7)) != 0;
-        let temp_water_enabled = (index_stove_type & (1 << 1)) != 0;
-        let pump_enabled = (index_stove_type & (1 << 4)) != 0;
-        Ok(Self {
-            index_page: response_data[0].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_page: {}",
-                    response_data[0]
-                ))
-            })?,
-            index_manufacturer: response_data[1].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_manufacturer: {}",
-                    response_data[1]
-                ))
-            })?,
-            index_bitmap_visible: parse_bool(response_data[2]).map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_bitmap_visible: {}",
-                    response_data[2]
-                ))
-            })?,
-            index_valid: parse_bool(response_data[3]).map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_valid: {}",
-                    response_data[3]
-                ))
-            })?,
-            index_stove_type,
-            index_stove_state: response_data[5].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_stove_state: {}",
-                    response_data[5]
-                ))
-            })?,
-            index_stove_on: parse_bool(response_data[6]).map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_stove_on: {}",
-                    response_data[6]
-                ))
-            })?,
-            index_eco_mode: parse_bool(response_data[7]).map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_eco_mode: {}",
-                    response_data[7]
-                ))
-            })?,
-            index_timer_on: response_data[8].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_timer_on: {}",
-                    response_data[8]
-                ))
-            })?,
-            index_ambient_t1: response_data[9].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t1: {}",
-                    response_data[9]
-                ))
-            })?,
-            index_ambient_t1_set: response_data[10].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t1_set: {}",
-                    response_data[10]
-                ))
-            })?,
-            index_ambient_t1_set_min: response_data[11].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t1_set_min: {}",
-                    response_data[11]
-                ))
-            })?,
-            index_ambient_t1_set_max: response_data[12].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t1_set_max: {}",
-                    response_data[12]
-                ))
-            })?,
-            index_ambient_t2: response_data[13].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t2: {}",
-                    response_data[13]
-                ))
-            })?,
-            index_ambient_t2_set: response_data[14].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t2_set: {}",
-                    response_data[14]
-                ))
-            })?,
-            index_ambient_t2_set_min: response_data[15].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t2_set_min: {}",
-                    response_data[15]
-                ))
-            })?,
-            index_ambient_t2_set_max: response_data[16].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_ambient_t2_set_max: {}",
-                    response_data[16]
-                ))
-            })?,
-            index_water: response_data[17].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_water: {}",
-                    response_data[17]
-                ))
-            })?,
-            index_water_set: response_data[18].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_water_set: {}",
-                    response_data[18]
-                ))
-            })?,
-            index_water_set_min: response_data[19].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_water_set_min: {}",
-                    response_data[19]
-                ))
-            })?,
-            index_water_set_max: response_data[20].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_water_set_max: {}",
-                    response_data[20]
-                ))
-            })?,
-            index_smoke_t: response_data[21].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_smoke_t: {}",
-                    response_data[21]
-                ))
-            })?,
-            index_power_level: response_data[22].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_power_level: {}",
-                    response_data[22]
-                ))
-            })?,
-            index_power_set: response_data[23].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_power_set: {}",
-                    response_data[23]
-                ))
-            })?,
-            index_power_min: response_data[24].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_power_min: {}",
-                    response_data[24]
-                ))
-            })?,
-            index_power_max: response_data[25].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_power_max: {}",
-                    response_data[25]
-                ))
-            })?,
-            index_fan_smoke: response_data[26].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_smoke: {}",
-                    response_data[26]
-                ))
-            })?,
-            index_fan_1: response_data[27].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_1: {}",
-                    response_data[27]
-                ))
-            })?,
-            index_fan_1_set: response_data[28].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_1_set: {}",
-                    response_data[28]
-                ))
-            })?,
-            index_fan_1_set_max: response_data[29].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_1_set_max: {}",
-                    response_data[29]
-                ))
-            })?,
-            index_fan_2: response_data[30].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_2: {}",
-                    response_data[30]
-                ))
-            })?,
-            index_fan_2_set: response_data[31].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_2_set: {}",
-                    response_data[31]
-                ))
-            })?,
-            index_fan_2_set_max: response_data[32].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_2_set_max: {}",
-                    response_data[32]
-                ))
-            })?,
-            index_fan_3: response_data[33].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_3: {}",
-                    response_data[33]
-                ))
-            })?,
-            index_fan_3_set: response_data[34].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_3_set: {}",
-                    response_data[34]
-                ))
-            })?,
-            index_fan_3_set_max: response_data[35].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_fan_3_set_max: {}",
-                    response_data[35]
-                ))
-            })?,
-            boiler_enabled,
-            domestic_hot_water_enabled,
-            fan_number,
-            temp_room1_enabled,
-            temp_room2_enabled,
-            temp_room3_enabled,
-            temp_water_enabled,
-            pump_enabled,
-            last_updated: Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-        })
+
+        impl $name {
+            /// Names of the parsed fields with their position in the response data
+            #[allow(dead_code)]
+            pub const FIELDS: &'static [(&'static str, usize)] =
+                &[ $( (stringify!($field), $index), )* ];
+
+            /// Parses the page from the response data
+            ///
+            /// # Arguments
+            ///
+            /// * `response_data` - The response parameters
+            ///
+            /// # Returns
+            ///
+            /// * `Result<Self, ResponseError>` - The parsed page or an error
+            pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
+                check_len(response_data, $page, $len)?;
+                $( let $field: $ty = parse_field(response_data, $index, stringify!($field))?; )*
+                $( $( let $dfield: $dty = $dexpr; )* )?
+                let now = Utc::now();
+                Ok(Self {
+                    $( $field, )*
+                    $( $( $dfield, )* )?
+                    $( $( $xfield: <$xty>::default(), )* )?
+                    raw_extra: Vec::new(),
+                    last_updated: Some(now),
+                    last_updated_local: Some(now.with_timezone(&Local)),
+                    received_at: Some(Instant::now()),
+                })
+            }
+
+            /// Stores the params received after the last known one
+            ///
+            /// # Arguments
+            ///
+            /// * `raw_extra` - The extra params
+            pub fn set_raw_extra(&mut self, raw_extra: Vec<String>) {
+                self.raw_extra = raw_extra;
+            }
+        }
+    };
+}
+
+dat_page! {
+    /// General information about the stove
+    pub struct INFData ("INF", 3) {
+        0 => hostname: String,
+        1 => version: String,
+        2 => signal: String,
     }
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct DAT1Data {
-    index_page: i16,
-    index_state: bool,
-    index_temperature_1: i16,
-    index_temperature_1_min: i16,
-    index_temperature_1_max: i16,
-    index_temperature_2: i16,
-    index_temperature_2_min: i16,
-    index_temperature_2_max: i16,
-    index_temperature_3: i16,
-    index_temperature_3_min: i16,
-    index_temperature_3_max: i16,
-    last_updated: String,
+dat_page! {
+    /// Response to a write request
+    #[allow(dead_code)]
+    pub struct DATReqResponseData ("DATReqResponse", 1) {
+        0 => value: String,
+    }
 }
 
-impl DAT1Data {
-    pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
-        if response_data.len() != 11 {
-            return Err(ResponseError::IncorrectResponseStruct(
-                "Incorrect number of elements in DAT1 struct".to_string(),
-            ));
-        }
+dat_page! {
+    /// Main stove data (status, temperatures, power levels, fans)
+    pub struct DAT0Data ("DAT0", 36) {
+        0 => index_page: u16,
+        1 => #[serde(serialize_with = "serialize_stove_manufacturer")]
+            #[schema(value_type = String)]
+            index_manufacturer: u16,
+        2 => index_bitmap_visible: bool,
+        3 => index_valid: bool,
+        4 => index_stove_type: u16,
+        5 => index_stove_state: StoveState,
+        6 => index_stove_on: bool,
+        7 => index_eco_mode: bool,
+        8 => index_timer_on: u16,
+        9 => index_ambient_t1: Tenths,
+        10 => index_ambient_t1_set: Tenths,
+        11 => index_ambient_t1_set_min: Tenths,
+        12 => index_ambient_t1_set_max: Tenths,
+        13 => index_ambient_t2: Tenths,
+        14 => index_ambient_t2_set: Tenths,
+        15 => index_ambient_t2_set_min: Tenths,
+        16 => index_ambient_t2_set_max: Tenths,
+        17 => index_water: Tenths,
+        18 => index_water_set: Tenths,
+        19 => index_water_set_min: Tenths,
+        20 => index_water_set_max: Tenths,
+        21 => index_smoke_t: Tenths,
+        22 => index_power_level: u16,
+        23 => index_power_set: u16,
+        24 => index_power_min: u16,
+        25 => index_power_max: u16,
+        26 => index_fan_smoke: u16,
+        27 => index_fan_1: u16,
+        28 => index_fan_1_set: u16,
+        29 => index_fan_1_set_max: u16,
+        30 => index_fan_2: u16,
+        31 => index_fan_2_set: u16,
+        32 => index_fan_2_set_max: u16,
+        33 => index_fan_3: u16,
+        34 => index_fan_3_set: u16,
+        35 => index_fan_3_set_max: u16,
+    }
+    // Other known bit layout of index_stove_type:
+    // boiler 9, domestic hot water 10, fans 12-13, room1 15, room2 8, room3 7, water 14, pump 4
+    derived {
+        boiler_enabled: bool = (index_stove_type & (1 << 6)) != 0,
+        domestic_hot_water_enabled: bool = (index_stove_type & (1 << 5)) != 0,
+        fan_number: u16 = (index_stove_type >> 2) & 0b11,
+        temp_room1_enabled: bool = (index_stove_type & (1 << 0)) != 0,
+        temp_room2_enabled: bool = (index_stove_type & (1 << 8)) != 0,
+        temp_room3_enabled: bool = (index_stove_type & (1 << 7)) != 0,
+        temp_water_enabled: bool = (index_stove_type & (1 << 1)) != 0,
+        pump_enabled: bool = (index_stove_type & (1 << 4)) != 0,
+    }
+}
+
+dat_page! {
+    /// Additional temperature data
+    pub struct DAT1Data ("DAT1", 11) {
+        0 => index_page: i16,
+        0 => index_state: bool,
+        1 => index_temperature_1: i16,
+        2 => index_temperature_1_min: i16,
+        3 => index_temperature_1_max: i16,
+        4 => index_temperature_2: i16,
+        5 => index_temperature_2_min: i16,
+        6 => index_temperature_2_max: i16,
+        7 => index_temperature_3: i16,
+        8 => index_temperature_3_min: i16,
+        9 => index_temperature_3_max: i16,
+    }
+}
+
+dat_page! {
+    /// Additional pump, valve and water circuit data
+    pub struct DAT2Data ("DAT2", 22) {
+        0 => index_page: i16,
+        1 => index_flow_switch: u16,
+        2 => index_generic_pump: u16,
+        3 => index_airex_1: u16,
+        4 => index_airex_2: u16,
+        5 => index_airex_3: u16,
+        6 => index_puffer: i16,
+        7 => index_puffer_set: i16,
+        8 => index_puffer_set_min: i16,
+        9 => index_puffer_set_max: i16,
+        10 => index_boiler: i16,
+        11 => index_boiler_set: i16,
+        12 => index_boiler_set_min: i16,
+        13 => index_boiler_set_max: i16,
+        14 => index_dhw: i16,
+        15 => index_dhw_set: i16,
+        16 => index_dhw_set_min: i16,
+        17 => index_dhw_set_max: i16,
+        18 => index_room_temp_3: i16,
+        19 => index_room_temp_3_set: i16,
+        20 => index_room_temp_3_set_min: i16,
+        21 => index_room_temp_3_set_max: i16,
+    }
+    decoded {
+        /// States of the hydraulic components, decoded from the raw values with the
+        /// mapping of the firmware layout
+        actuators: HydroActuators,
+    }
+}
+
+/// State of a hydraulic component (pump, valve, flow switch) reported in DAT2
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, ToSchema)]
+pub struct ActuatorState {
+    /// Whether the component is active
+    on: bool,
+    /// Modulation in percent, None for on/off components
+    percent: Option<u16>,
+}
+
+impl ActuatorState {
+    /// Creates the state of a component
+    ///
+    /// # Arguments
+    ///
+    /// * `on` - Whether the component is active
+    /// * `percent` - Modulation in percent, None for on/off components
+    ///
+    /// # Returns
+    ///
+    /// * `ActuatorState` - The state
+    pub fn new(on: bool, percent: Option<u16>) -> Self {
+        Self { on, percent }
+    }
+
+    /// Checks if the component is active
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the component is active
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
 
-        Ok(Self {
-            index_page: response_data[0].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_page: {}",
-                    response_data[0]
-                ))
-            })?,
-            index_state: parse_bool(response_data[0]).map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_state: {}",
-                    response_data[0]
-                ))
-            })?,
-            index_temperature_1: response_data[1].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_1: {}",
-                    response_data[1]
-                ))
-            })?,
-            index_temperature_1_min: response_data[2].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_1_min: {}",
-                    response_data[2]
-                ))
-            })?,
-            index_temperature_1_max: response_data[3].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_1_max: {}",
-                    response_data[3]
-                ))
-            })?,
-            index_temperature_2: response_data[4].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_2: {}",
-                    response_data[4]
-                ))
-            })?,
-            index_temperature_2_min: response_data[5].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_2_min: {}",
-                    response_data[5]
-                ))
-            })?,
-            index_temperature_2_max: response_data[6].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_2_max: {}",
-                    response_data[6]
-                ))
-            })?,
-            index_temperature_3: response_data[7].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_3: {}",
-                    response_data[7]
-                ))
-            })?,
-            index_temperature_3_min: response_data[8].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_3_min: {}",
-                    response_data[8]
-                ))
-            })?,
-            index_temperature_3_max: response_data[9].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_temperature_3_max: {}",
-                    response_data[9]
-                ))
-            })?,
-            last_updated: Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-        })
+    /// Gets the modulation of the component
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u16>` - The modulation in percent, None for on/off components
+    pub fn get_percent(&self) -> Option<u16> {
+        self.percent
     }
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
-pub struct DAT2Data {
-    index_page: i16,
-    index_flow_switch: u16,
-    index_generic_pump: u16,
-    index_airex_1: u16,
-    index_airex_2: u16,
-    index_airex_3: u16,
-    index_puffer: i16,
-    index_puffer_set: i16,
-    index_puffer_set_min: i16,
-    index_puffer_set_max: i16,
-    index_boiler: i16,
-    index_boiler_set: i16,
-    index_boiler_set_min: i16,
-    index_boiler_set_max: i16,
-    index_dhw: i16,
-    index_dhw_set: i16,
-    index_dhw_set_min: i16,
-    index_dhw_set_max: i16,
-    index_room_temp_3: i16,
-    index_room_temp_3_set: i16,
-    index_room_temp_3_set_min: i16,
-    index_room_temp_3_set_max: i16,
-    last_updated: String,
+/// States of the hydraulic components reported in DAT2
+///
+/// Components not used by the firmware layout are null.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct HydroActuators {
+    /// Water flow switch
+    pub flow_switch: Option<ActuatorState>,
+    /// Circulation pump
+    pub generic_pump: Option<ActuatorState>,
+    /// First auxiliary output
+    pub airex_1: Option<ActuatorState>,
+    /// Second auxiliary output
+    pub airex_2: Option<ActuatorState>,
+    /// Third auxiliary output
+    pub airex_3: Option<ActuatorState>,
+}
+
+impl HydroActuators {
+    /// Lists the components used by the firmware layout
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(&'static str, ActuatorState)>` - The components by name
+    pub fn list(&self) -> Vec<(&'static str, ActuatorState)> {
+        [
+            ("flow_switch", self.flow_switch),
+            ("generic_pump", self.generic_pump),
+            ("airex_1", self.airex_1),
+            ("airex_2", self.airex_2),
+            ("airex_3", self.airex_3),
+        ]
+        .into_iter()
+        .filter_map(|(name, state)| Some((name, state?)))
+        .collect()
+    }
+}
+
+impl INFData {
+    /// Gets the hostname of the stove
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The hostname
+    pub fn get_hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// Replaces the identifying fields (hostname) with a placeholder
+    pub fn redact(&mut self) {
+        self.hostname = REDACTED.to_string();
+    }
+
+    /// Gets the firmware version
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The firmware version
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
 }
 
 impl DAT2Data {
-    pub fn from_slice(response_data: &[&str]) -> Result<Self, ResponseError> {
-        if response_data.len() != 22 {
-            return Err(ResponseError::IncorrectResponseStruct(
-                "Incorrect number of elements in DAT2 struct".to_string(),
-            ));
+    /// Decodes the states of the hydraulic components from their raw values
+    ///
+    /// # Arguments
+    ///
+    /// * `mapping` - Meaning of the raw values for the firmware layout
+    pub fn decode_actuators(&mut self, mapping: &HydroMapping) {
+        self.actuators = HydroActuators {
+            flow_switch: mapping
+                .flow_switch
+                .map(|kind| kind.decode(self.index_flow_switch)),
+            generic_pump: mapping
+                .generic_pump
+                .map(|kind| kind.decode(self.index_generic_pump)),
+            airex_1: mapping.airex[0].map(|kind| kind.decode(self.index_airex_1)),
+            airex_2: mapping.airex[1].map(|kind| kind.decode(self.index_airex_2)),
+            airex_3: mapping.airex[2].map(|kind| kind.decode(self.index_airex_3)),
+        };
+    }
+
+    /// Gets the states of the hydraulic components
+    ///
+    /// # Returns
+    ///
+    /// * `&HydroActuators` - The states
+    pub fn get_actuators(&self) -> &HydroActuators {
+        &self.actuators
+    }
+}
+
+impl DAT0Data {
+    /// Gets the manufacturer code
+    ///
+    /// # Returns
+    ///
+    /// * `u16` - The manufacturer code
+    pub fn get_index_manufacturer(&self) -> u16 {
+        self.index_manufacturer
+    }
+
+    /// Gets the name of the manufacturer
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The manufacturer name, or its code if unknown
+    pub fn get_manufacturer_name(&self) -> String {
+        match StoveManufacturer::from_u16(self.index_manufacturer) {
+            Some(manufacturer) => format!("{:?}", manufacturer),
+            None => self.index_manufacturer.to_string(),
         }
+    }
+
+    /// Gets the state of the stove
+    ///
+    /// # Returns
+    ///
+    /// * `&StoveState` - The state
+    pub fn get_index_stove_state(&self) -> &StoveState {
+        &self.index_stove_state
+    }
 
-        Ok(Self {
-            index_page: response_data[0].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_page: {}",
-                    response_data[0]
-                ))
-            })?,
-            index_flow_switch: response_data[1].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_flow_switch: {}",
-                    response_data[1]
-                ))
-            })?,
-            index_generic_pump: response_data[2].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_generic_pump: {}",
-                    response_data[2]
-                ))
-            })?,
-            index_airex_1: response_data[3].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_airex_1: {}",
-                    response_data[3]
-                ))
-            })?,
-            index_airex_2: response_data[4].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_airex_2: {}",
-                    response_data[4]
-                ))
-            })?,
-            index_airex_3: response_data[5].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_airex_3: {}",
-                    response_data[5]
-                ))
-            })?,
-            index_puffer: response_data[6].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_puffer: {}",
-                    response_data[6]
-                ))
-            })?,
-            index_puffer_set: response_data[7].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_puffer_set: {}",
-                    response_data[7]
-                ))
-            })?,
-            index_puffer_set_min: response_data[8].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_puffer_set_min: {}",
-                    response_data[8]
-                ))
-            })?,
-            index_puffer_set_max: response_data[9].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_puffer_set_max: {}",
-                    response_data[9]
-                ))
-            })?,
-            index_boiler: response_data[10].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_boiler: {}",
-                    response_data[10]
-                ))
-            })?,
-            index_boiler_set: response_data[11].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_boiler_set: {}",
-                    response_data[11]
-                ))
-            })?,
-            index_boiler_set_min: response_data[12].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_boiler_set_min: {}",
-                    response_data[12]
-                ))
-            })?,
-            index_boiler_set_max: response_data[13].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_boiler_set_max: {}",
-                    response_data[13]
-                ))
-            })?,
-            index_dhw: response_data[14].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_dhw: {}",
-                    response_data[14]
-                ))
-            })?,
-            index_dhw_set: response_data[15].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_dhw_set: {}",
-                    response_data[15]
-                ))
-            })?,
-            index_dhw_set_min: response_data[16].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_dhw_set_min: {}",
-                    response_data[16]
-                ))
-            })?,
-            index_dhw_set_max: response_data[17].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_dhw_set_max: {}",
-                    response_data[17]
-                ))
-            })?,
-            index_room_temp_3: response_data[18].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_room_temp_3: {}",
-                    response_data[18]
-                ))
-            })?,
-            index_room_temp_3_set: response_data[19].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_room_temp_3_set: {}",
-                    response_data[19]
-                ))
-            })?,
-            index_room_temp_3_set_min: response_data[20].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_room_temp_3_set_min: {}",
-                    response_data[20]
-                ))
-            })?,
-            index_room_temp_3_set_max: response_data[21].parse().map_err(|_| {
-                ResponseError::IncorrectResponseStruct(format!(
-                    "Invalid index_room_temp_3_set_max: {}",
-                    response_data[21]
-                ))
-            })?,
-            last_updated: Local::now().to_rfc3339_opts(SecondsFormat::Secs, true),
-        })
+    /// Gets the smoke temperature
+    ///
+    /// # Returns
+    ///
+    /// * `&Tenths` - The temperature
+    pub fn get_index_smoke_t(&self) -> &Tenths {
+        &self.index_smoke_t
+    }
+
+    /// Checks if the page was received from the stove
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - False for the default page held before the first response
+    pub fn is_received(&self) -> bool {
+        self.received_at.is_some()
     }
 }
 
 #[allow(dead_code)]
+#[derive(Debug, Clone)]
 pub enum CommandData {
     Inf(INFData),
     Dat0(DAT0Data),
@@ -645,26 +499,131 @@ pub enum CommandData {
     DATReqResponse(DATReqResponseData),
 }
 
+impl CommandData {
+    /// Stores the params received after the last known one
+    ///
+    /// # Arguments
+    ///
+    /// * `raw_extra` - The extra params
+    pub fn set_raw_extra(&mut self, raw_extra: Vec<String>) {
+        match self {
+            CommandData::Inf(data) => data.set_raw_extra(raw_extra),
+            CommandData::Dat0(data) => data.set_raw_extra(raw_extra),
+            CommandData::Dat1(data) => data.set_raw_extra(raw_extra),
+            CommandData::Dat2(data) => data.set_raw_extra(raw_extra),
+            CommandData::DATReqResponse(data) => data.set_raw_extra(raw_extra),
+        }
+    }
+
+    /// Gets the name of the page
+    ///
+    /// # Returns
+    ///
+    /// * `&'static str` - `INF`, `DAT0`, `DAT1`, `DAT2` or `DAT write`
+    pub fn page(&self) -> &'static str {
+        match self {
+            CommandData::Inf(_) => "INF",
+            CommandData::Dat0(_) => "DAT0",
+            CommandData::Dat1(_) => "DAT1",
+            CommandData::Dat2(_) => "DAT2",
+            CommandData::DATReqResponse(_) => "DAT write",
+        }
+    }
+
+    /// Serializes the data of the page
+    ///
+    /// # Returns
+    ///
+    /// * `serde_json::Value` - The fields of the page, as in the state JSON
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            CommandData::Inf(data) => serde_json::to_value(data),
+            CommandData::Dat0(data) => serde_json::to_value(data),
+            CommandData::Dat1(data) => serde_json::to_value(data),
+            CommandData::Dat2(data) => serde_json::to_value(data),
+            CommandData::DATReqResponse(data) => serde_json::to_value(data),
+        }
+        .unwrap_or_default()
+    }
+}
+
 pub fn calculate_checksum(data: &str) -> String {
     let mut crc = CRCu16::crc16ccitt_false();
     crc.digest(data.as_bytes());
     format!("{:04X}", crc.get_crc())
 }
 
-fn parse_bool(s: &str) -> Result<bool, String> {
-    match s {
-        "0" => Ok(false),
-        "1" => Ok(true),
-        _ => Err(format!("Invalid boolean string: {}", s)),
+/// Checks that a response has the expected number of elements
+///
+/// # Arguments
+///
+/// * `response_data` - The response data
+/// * `page` - Name of the page, used in the error
+/// * `expected` - Expected number of elements
+///
+/// # Returns
+///
+/// * `Result<(), ResponseError>` - Success or an IncorrectLength error
+fn check_len(
+    response_data: &[&str],
+    page: &'static str,
+    expected: usize,
+) -> Result<(), ResponseError> {
+    if response_data.len() != expected {
+        return Err(ResponseError::IncorrectLength {
+            page,
+            expected,
+            actual: response_data.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Parses the element at the given index without allocating
+///
+/// # Arguments
+///
+/// * `response_data` - The response data
+/// * `index` - Index of the element
+/// * `field` - Name of the field, used in the error
+///
+/// # Returns
+///
+/// * `Result<T, ResponseError>` - The parsed value or an InvalidField error
+fn parse_field<T: FieldValue>(
+    response_data: &[&str],
+    index: usize,
+    field: &'static str,
+) -> Result<T, ResponseError> {
+    response_data
+        .get(index)
+        .and_then(|value| T::parse_value(value))
+        .ok_or(ResponseError::InvalidField { field, index })
+}
+
+fn serialize_last_updated<S, Tz>(
+    last_updated: &Option<DateTime<Tz>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    match last_updated {
+        Some(date) => serializer.serialize_str(&date.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        None => serializer.serialize_str(""),
     }
 }
 
-fn serialize_i16_as_f32<S>(value: &i16, serializer: S) -> Result<S::Ok, S::Error>
+fn serialize_age_seconds<S>(received_at: &Option<Instant>, serializer: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    let value_as_f32 = (*value as f32) / 10.0;
-    serializer.serialize_f32(value_as_f32)
+    match received_at {
+        Some(instant) => serializer.serialize_some(&instant.elapsed().as_secs()),
+        None => serializer.serialize_none(),
+    }
 }
 
 fn serialize_stove_manufacturer<S>(manufacturer: &u16, serializer: S) -> Result<S::Ok, S::Error>
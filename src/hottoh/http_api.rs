@@ -1,31 +1,99 @@
+use crate::hottoh::access_log::{log_request, AccessLog};
+use crate::hottoh::alerts::MAX_SILENCE_SECS;
+use crate::hottoh::api_keys::{require_api_key, ApiKeys, Role};
+use crate::hottoh::auth_guard::{guard_auth, AuthGuard};
+use crate::hottoh::build_info::{GIT_HASH, VERSION};
+use crate::hottoh::child_lock::{require_pin, ChildLock, PIN_HEADER};
+use crate::hottoh::command_outcome::{send_command, CommandOutcome};
 use crate::hottoh::config::AppConfig;
-use crate::hottoh::hottoh_const::{Command, CommandType, StoveCommands};
+use crate::hottoh::confirmation::CONFIRMATION_TTL;
+use crate::hottoh::crash_report::{read_last_crash, DEFAULT_CRASH_DIR};
+use crate::hottoh::diagnostics::build_bundle;
+use crate::hottoh::frame_capture::FrameCapture;
+use crate::hottoh::graphql::{build_schema, serve_graphql_ws, GraphqlCommands, Protocols};
+use crate::hottoh::grpc::{start_grpc_server, GrpcService};
+use crate::hottoh::history::{parse_span, Aggregation, HistoryLevel};
+use crate::hottoh::history_import::{parse_csv, HISTORY_IMPORT_PATH, MAX_IMPORT_BYTES};
+use crate::hottoh::hottoh_const::{
+    Command, CommandType, StoveCommands, StoveState, PROTOCOL_PROFILE,
+};
+use crate::hottoh::hottoh_structs::{
+    ActuatorState, DAT0Data, DAT1Data, DAT2Data, HydroActuators, INFData, Tenths,
+};
+use crate::hottoh::identity::{BridgeIdentity, DEFAULT_ID_FILE};
+use crate::hottoh::inbound_webhooks::InboundWebhooks;
+use crate::hottoh::listeners::Listeners;
+use crate::hottoh::locks::{mutex_lock, read_lock, write_lock};
+use crate::hottoh::metrics::{Metrics, StoveLabels, DEFAULT_STOVE_ID};
+use crate::hottoh::network_filter::{filter_request, request_client_ip, NetworkFilter};
+use crate::hottoh::normalized::{check_setpoint, normalize, NormalizedValue};
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::pairing::{PairingCodes, PAIRING_TTL};
+use crate::hottoh::plugins::PluginRegistry;
+use crate::hottoh::profiles::Profiles;
+use crate::hottoh::quiet_hours::{NORMAL_PROFILE, QUIET_PROFILE};
+use crate::hottoh::request_signing::{require_signature, RequestSigning};
+use crate::hottoh::resource_usage::ResourceUsage;
+use crate::hottoh::run_timer::{RunTimer, RunTimerSave, MAX_RUN_MINUTES};
 use crate::hottoh::shared_struct::SharedState;
-use crate::hottoh::tcp_client_structs::Request;
-use actix_web::{middleware, web, App, HttpResponse, HttpServer, ResponseError};
+use crate::hottoh::state_fields::resolve_field;
+use crate::hottoh::state_stream::{parse_stream_fields, stream_state};
+use crate::hottoh::state_version::wait_for_version;
+use crate::hottoh::stats_page::{render_stats_page, QueueDepths};
+use crate::hottoh::tcp_client::{
+    cancel_request, clear_queues, queue_write_requests, CancelError, CommandError, CommandOrigin,
+};
+use crate::hottoh::tcp_client_structs::{Request, Response};
+use crate::hottoh::temperature::to_tenths;
+use crate::hottoh::ws_commands::WsCommands;
+use actix_web::dev::{Payload, ServiceRequest};
+use actix_web::error::PayloadError;
+use actix_web::http::header;
+use actix_web::{
+    middleware, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, ResponseError,
+};
+use async_graphql::dynamic::Schema;
+use async_graphql::http::parse_query_string;
+use chrono::{DateTime, Local};
 use log::{debug, error, info, warn};
-use serde::Deserialize;
+use serde::{de, Deserialize, Deserializer};
 use serde_json::json;
 use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use utoipa::{OpenApi, ToSchema};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
 
+/// Largest body read by the middlewares, as the default limit of actix-web
+const MAX_BODY_BYTES: usize = 256 * 1024;
+
+/// Header carrying the signature of inbound webhooks
+const SIGNATURE_HEADER: &str = "X-Hottoh-Signature";
+
 /// API Error
 #[derive(Error, Debug)]
 pub enum ApiError {
     /// Invalid parameter
     #[error("Invalid parameter: {0}")]
     InvalidParameter(String),
-
-    /// Internal error
-    #[error("Internal error: {0}")]
-    InternalError(String),
-
-    /// Lock error
-    #[error("Lock error: {0}")]
-    LockError(String),
+    /// Missing or invalid credentials
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// Client not allowed to use the API
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    /// Unknown resource
+    #[error("Not found: {0}")]
+    NotFound(String),
+    /// Command rejected by the bridge
+    #[error("Command rejected: {0}")]
+    CommandRejected(#[from] CommandError),
+    /// Command not cancelled by the bridge
+    #[error("Command not cancelled: {0}")]
+    CommandNotCancelled(#[from] CancelError),
 }
 
 impl ResponseError for ApiError {
@@ -39,14 +107,66 @@ impl ResponseError for ApiError {
                 warn!("{}", self);
                 HttpResponse::BadRequest().json(error_json)
             }
-            ApiError::InternalError(_) => {
-                error!("{}", self);
-                HttpResponse::InternalServerError().json(error_json)
+            ApiError::Unauthorized(_) => {
+                warn!("{}", self);
+                HttpResponse::Unauthorized().json(error_json)
+            }
+            ApiError::Forbidden(_) => {
+                warn!("{}", self);
+                HttpResponse::Forbidden().json(error_json)
+            }
+            ApiError::NotFound(_) => {
+                warn!("{}", self);
+                HttpResponse::NotFound().json(error_json)
+            }
+            ApiError::CommandNotCancelled(CancelError::NotFound(_)) => {
+                warn!("{}", self);
+                HttpResponse::NotFound().json(error_json)
+            }
+            ApiError::CommandNotCancelled(CancelError::AlreadySent(_)) => {
+                warn!("{}", self);
+                HttpResponse::Conflict().json(error_json)
+            }
+            ApiError::CommandRejected(CommandError::ReadOnly) => {
+                warn!("{}", self);
+                HttpResponse::MethodNotAllowed().json(error_json)
+            }
+            ApiError::CommandRejected(CommandError::Maintenance(_)) => {
+                warn!("{}", self);
+                HttpResponse::Conflict().json(error_json)
+            }
+            ApiError::CommandRejected(CommandError::ConfirmationRequired(token)) => {
+                info!("Destructive command waiting for its confirmation");
+                HttpResponse::Accepted().json(json!({
+                    "success": false,
+                    "confirmation_required": true,
+                    "message": "Destructive command: send the same request with ?confirm=<token> to execute it",
+                    "confirmation_token": token,
+                    "expires_in_seconds": CONFIRMATION_TTL.as_secs(),
+                }))
             }
-            ApiError::LockError(_) => {
+            ApiError::CommandRejected(CommandError::Confirmation(_)) => {
                 error!("{}", self);
                 HttpResponse::InternalServerError().json(error_json)
             }
+            ApiError::CommandRejected(CommandError::Throttled(violation)) => {
+                warn!("{}", self);
+                let mut response = HttpResponse::TooManyRequests();
+                match violation.retry_after {
+                    Some(retry_after) => {
+                        // Rounded up, so that retrying after this delay succeeds
+                        let seconds =
+                            retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+                        response
+                            .insert_header(("Retry-After", seconds.to_string()))
+                            .json(json!({
+                                "error": self.to_string(),
+                                "retry_after_seconds": seconds
+                            }))
+                    }
+                    None => response.json(error_json),
+                }
+            }
         }
     }
 }
@@ -59,16 +179,79 @@ impl ResponseError for ApiError {
         get_dat0,
         get_dat1,
         get_dat2,
+        get_state,
+        get_pending,
+        get_normalized_state,
+        get_state_version,
+        get_ws,
+        get_graphql,
+        post_graphql,
+        get_alarms,
+        get_alerts,
+        post_alert_ack,
+        post_alert_silence,
+        delete_alert_silence,
+        get_startup_progress,
         post_on_off,
         post_eco_mode,
         post_ambiance_temp,
         post_chrono_mode,
         post_chrono_temp,
         post_fan_speed,
-        post_power_level
+        post_power_level,
+        post_run_for,
+        get_run_for,
+        delete_run_for,
+        get_metrics,
+        get_stats,
+        get_healthz,
+        get_version,
+        get_queue,
+        delete_command,
+        post_clear_queues,
+        get_read_only,
+        post_read_only,
+        get_auth_bans,
+        delete_auth_bans,
+        get_plugins,
+        post_plugin_command,
+        post_pairing_code,
+        post_pairing,
+        get_paired_keys,
+        delete_paired_key,
+        post_activate_profile,
+        get_profiles,
+        post_activate_named_profile,
+        get_diagnostics_bundle,
+        get_last_crash,
+        get_storage,
+        get_history,
+        post_history_import,
+        post_inbound_webhook
     ),
     components(
-        schemas(DatPostBool, DatPostU32, DatPostAmbianceTemp, DatPostFanSpeed, DatPostChronoTemp)
+        schemas(
+            DatPostBool,
+            DatPostU32,
+            DatPostI32,
+            DatPostAmbianceTemp,
+            DatPostFanSpeed,
+            DatPostChronoTemp,
+            RunForRequest,
+            PairingRequest,
+            PairingCodeRequest,
+            SilenceRequest,
+            ProfileRequest,
+            INFData,
+            DAT0Data,
+            DAT1Data,
+            DAT2Data,
+            HydroActuators,
+            ActuatorState,
+            StoveState,
+            Tenths,
+            NormalizedValue
+        )
     ),
     tags(
         (name = "hottoh", description = "Stove control API")
@@ -96,6 +279,78 @@ struct DatPostU32 {
     value: u32,
 }
 
+/// Parameters for the activation of a profile
+#[derive(Deserialize, ToSchema)]
+struct ProfileRequest {
+    /// Profile to activate, `quiet` or `normal`
+    ///
+    /// Example: `"normal"`
+    #[schema(example = "normal")]
+    profile: String,
+}
+
+/// Parameters of a silence of the alert notifications
+#[derive(Deserialize, ToSchema)]
+struct SilenceRequest {
+    /// Name of the alert rule to silence (exclusive with `label`)
+    ///
+    /// Example: `"smoke_high"`
+    #[schema(example = "smoke_high")]
+    rule: Option<String>,
+    /// Label of the alert rules to silence (exclusive with `rule`)
+    ///
+    /// Example: `"chimney"`
+    #[schema(example = "chimney")]
+    label: Option<String>,
+    /// Duration of the silence, e.g. `90m`, `6h` or `2d` (at most 30 days)
+    ///
+    /// Example: `"2d"`
+    #[schema(example = "2d")]
+    duration: String,
+    /// Reason of the silence
+    ///
+    /// Example: `"Technician visit on Friday"`
+    #[serde(default)]
+    #[schema(example = "Technician visit on Friday")]
+    comment: String,
+}
+
+/// Parameters for the pairing of a client
+#[derive(Deserialize, ToSchema)]
+struct PairingRequest {
+    /// One-time pairing code
+    ///
+    /// Example: `"042517"`
+    #[schema(example = "042517")]
+    code: String,
+    /// Name of the client, used in the logs and to revoke its key (letters, digits,
+    /// `_` and `-`)
+    ///
+    /// Example: `"kitchen_panel"`
+    #[schema(example = "kitchen_panel")]
+    name: String,
+}
+
+/// Parameters of a new pairing code
+#[derive(Deserialize, ToSchema)]
+struct PairingCodeRequest {
+    /// Role of the API key issued to the client, `read` or `control` (the default)
+    ///
+    /// Example: `"read"`
+    #[schema(example = "read")]
+    role: Option<String>,
+}
+
+/// Signed integer parameters for raw commands
+#[derive(Deserialize, ToSchema)]
+struct DatPostI32 {
+    /// Raw value sent to the stove
+    ///
+    /// Example: `215` for 21.5°C with a temperature command
+    #[schema(example = "215")]
+    value: i32,
+}
+
 /// Parameters for ambiance temperature
 #[derive(Deserialize, ToSchema)]
 struct DatPostAmbianceTemp {
@@ -141,12 +396,208 @@ struct DatPostChronoTemp {
     value: f32,
 }
 
+/// Query parameters for command endpoints
+#[derive(Deserialize, IntoParams)]
+struct CommandQuery {
+    /// Validate the command and return the frame that would be sent, without queuing it
+    ///
+    /// Accepts `1`/`0` or `true`/`false`
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    #[param(value_type = bool, example = false)]
+    dry_run: bool,
+    /// Send the command even during a maintenance window
+    ///
+    /// Accepts `1`/`0` or `true`/`false`
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    #[param(value_type = bool, example = false)]
+    force: bool,
+    /// Token returned by a previous call whose command needs a confirmation
+    /// (turning off a running stove, recipe and pellet setpoint commands)
+    #[serde(default)]
+    confirm: Option<String>,
+    /// Seconds after which the command is dropped if not sent yet, e.g. while the
+    /// stove is disconnected (0 keeps it until sent, `stove.command_ttl_secs` if absent)
+    #[serde(default)]
+    #[param(example = 300)]
+    ttl: Option<u64>,
+    /// Seconds to wait for the stove to acknowledge the command and report the value
+    /// (at most 30), the response then includes the outcome
+    #[serde(default)]
+    #[param(example = 10)]
+    wait: Option<u64>,
+}
+
+/// Parameters for a time-limited run
+#[derive(Deserialize, ToSchema)]
+struct RunForRequest {
+    /// Duration of the run in minutes (1-1440)
+    ///
+    /// Example: `90` to turn the stove off after an hour and a half
+    #[schema(example = "90")]
+    minutes: u64,
+    /// Power level during the run (1-10)
+    ///
+    /// Example: `4` for a medium power
+    #[schema(example = "4")]
+    power: u32,
+}
+
+/// Query parameters for a time-limited run
+#[derive(Deserialize, IntoParams)]
+struct RunForQuery {
+    /// Turn the stove on, and off at the end of the run, even during a maintenance window
+    ///
+    /// Accepts `1`/`0` or `true`/`false`
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    #[param(value_type = bool, example = false)]
+    force: bool,
+    /// Seconds after which the commands turning the stove on are dropped if not sent yet
+    /// (0 keeps them until sent, `stove.command_ttl_secs` if absent)
+    #[serde(default)]
+    #[param(example = 300)]
+    ttl: Option<u64>,
+}
+
+/// Query parameters for the activation of a named profile
+#[derive(Deserialize, IntoParams)]
+struct NamedProfileQuery {
+    /// Apply the profile even during a maintenance window
+    ///
+    /// Accepts `1`/`0` or `true`/`false`
+    #[serde(default, deserialize_with = "deserialize_flag")]
+    #[param(value_type = bool, example = false)]
+    force: bool,
+    /// Seconds after which the commands are dropped if not sent yet (0 keeps them
+    /// until sent, `stove.command_ttl_secs` if absent)
+    #[serde(default)]
+    #[param(example = 300)]
+    ttl: Option<u64>,
+}
+
+/// Query parameters of the inbound webhooks
+#[derive(Deserialize, IntoParams)]
+struct WebhookQuery {
+    /// Token returned by a previous call whose action holds a destructive command
+    #[serde(default)]
+    confirm: Option<String>,
+}
+
+/// Deserializes a query flag given as `1`/`0` or `true`/`false`
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match String::deserialize(deserializer)?.as_str() {
+        "1" | "true" => Ok(true),
+        "0" | "false" => Ok(false),
+        other => Err(de::Error::custom(format!("invalid flag: {}", other))),
+    }
+}
+
+/// Longest wait for the outcome of a command (`?wait=`)
+pub const MAX_COMMAND_WAIT_SECS: u64 = 30;
+
+/// Longest wait for a change of the state (`GET /api/state/version?since=`)
+const MAX_VERSION_WAIT_SECS: u64 = 30;
+
+/// Query parameters of the state WebSocket
+#[derive(Deserialize, IntoParams)]
+struct WsQuery {
+    /// Comma-separated pages or fields streamed, the whole state if absent
+    #[serde(default)]
+    #[param(example = "dat0.index_ambient_t1,dat0.index_stove_state")]
+    fields: Option<String>,
+}
+
+/// Query parameters for the version of the state
+#[derive(Deserialize, IntoParams)]
+struct StateVersionQuery {
+    /// Version last seen, to wait until the state changes since it
+    #[serde(default)]
+    #[param(example = 1024)]
+    since: Option<u64>,
+    /// Seconds to wait for a change (at most 30, the default)
+    #[serde(default)]
+    #[param(example = 10)]
+    timeout: Option<u64>,
+}
+
+/// Query parameters for the history of a field
+#[derive(Deserialize, IntoParams)]
+struct HistoryQuery {
+    /// Field, as `page.field` (the `index_` prefix can be omitted)
+    #[param(example = "dat0.smoke_t")]
+    field: String,
+    /// Level of the history, `raw` or a bucket such as `15m`; the finest level still
+    /// holding `from` if absent
+    #[serde(default)]
+    #[param(example = "15m")]
+    level: Option<String>,
+    /// Start of the range, in RFC 3339 (the whole retention of the level if absent)
+    #[serde(default)]
+    #[param(example = "2025-01-15T00:00:00Z")]
+    from: Option<String>,
+    /// End of the range, in RFC 3339 (now if absent)
+    #[serde(default)]
+    #[param(example = "2025-01-16T00:00:00Z")]
+    to: Option<String>,
+    /// Statistic computed over each bucket (`avg` if only `bucket` is given); points
+    /// are returned as stored if neither is given
+    #[serde(default)]
+    #[param(inline, example = "max")]
+    agg: Option<Aggregation>,
+    /// Buckets over which `agg` is computed, e.g. `15m` (those of the level if absent)
+    #[serde(default)]
+    #[param(example = "15m")]
+    bucket: Option<String>,
+}
+
+/// Upper bound of the log lines included in a diagnostics bundle
+const MAX_DIAGNOSTICS_LOG_LINES: usize = 10_000;
+
+/// Query parameters for the diagnostics bundle
+#[derive(Deserialize, IntoParams)]
+struct DiagnosticsQuery {
+    /// Number of log lines to include (at most 10000)
+    ///
+    /// Example: `500` for the last 500 lines
+    #[serde(default = "default_diagnostics_lines")]
+    #[param(example = 500)]
+    lines: usize,
+}
+
+/// Default number of log lines included in a diagnostics bundle
+fn default_diagnostics_lines() -> usize {
+    500
+}
+
+/// Query parameters for lifting the authentication bans
+#[derive(Deserialize, IntoParams)]
+struct AuthBansQuery {
+    /// Address of the client to clear, all clients if absent
+    ///
+    /// Example: `192.168.1.42`
+    #[param(value_type = Option<String>, example = "192.168.1.42")]
+    client: Option<IpAddr>,
+}
+
+/// Query parameters for clearing the queues
+#[derive(Deserialize, IntoParams)]
+struct ClearQueuesQuery {
+    /// Only drop read requests and responses, keeping pending writes
+    ///
+    /// Example: `true` to keep pending writes
+    #[serde(default)]
+    #[param(example = false)]
+    only_reads: bool,
+}
+
 /// Retrieves general information
 #[utoipa::path(
     get,
     path = "/api/inf",
     responses(
-        (status = 200, description = "Information retrieved successfully"),
+        (status = 200, description = "Information retrieved successfully", body = INFData),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
@@ -154,16 +605,8 @@ struct DatPostChronoTemp {
 async fn get_inf(
     data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<web::Json<serde_json::Value>, ApiError> {
-    match data.read() {
-        Ok(state) => {
-            let inf_clone = state.get_inf().clone();
-            Ok(web::Json(json!(inf_clone)))
-        }
-        Err(e) => Err(ApiError::LockError(format!(
-            "Failed to read shared state: {}",
-            e
-        ))),
-    }
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(state.get_inf())))
 }
 
 /// Retrieves DAT0 data
@@ -171,7 +614,7 @@ async fn get_inf(
     get,
     path = "/api/dat/0",
     responses(
-        (status = 200, description = "DAT0 data retrieved successfully"),
+        (status = 200, description = "DAT0 data retrieved successfully", body = DAT0Data),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
@@ -179,16 +622,8 @@ async fn get_inf(
 async fn get_dat0(
     data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<web::Json<serde_json::Value>, ApiError> {
-    match data.read() {
-        Ok(state) => {
-            let dat0_clone = state.get_dat0().clone();
-            Ok(web::Json(json!(dat0_clone)))
-        }
-        Err(e) => Err(ApiError::LockError(format!(
-            "Failed to read shared state: {}",
-            e
-        ))),
-    }
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(state.get_dat0())))
 }
 
 /// Retrieves DAT1 data
@@ -196,7 +631,7 @@ async fn get_dat0(
     get,
     path = "/api/dat/1",
     responses(
-        (status = 200, description = "DAT1 data retrieved successfully"),
+        (status = 200, description = "DAT1 data retrieved successfully", body = DAT1Data),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
@@ -204,16 +639,8 @@ async fn get_dat0(
 async fn get_dat1(
     data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<web::Json<serde_json::Value>, ApiError> {
-    match data.read() {
-        Ok(state) => {
-            let dat1_clone = state.get_dat1().clone();
-            Ok(web::Json(json!(dat1_clone)))
-        }
-        Err(e) => Err(ApiError::LockError(format!(
-            "Failed to read shared state: {}",
-            e
-        ))),
-    }
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(state.get_dat1())))
 }
 
 /// Retrieves DAT2 data
@@ -221,7 +648,7 @@ async fn get_dat1(
     get,
     path = "/api/dat/2",
     responses(
-        (status = 200, description = "DAT2 data retrieved successfully"),
+        (status = 200, description = "DAT2 data retrieved successfully", body = DAT2Data),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
@@ -229,355 +656,2150 @@ async fn get_dat1(
 async fn get_dat2(
     data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<web::Json<serde_json::Value>, ApiError> {
-    match data.read() {
-        Ok(state) => {
-            let dat2_clone = state.get_dat2().clone();
-            Ok(web::Json(json!(dat2_clone)))
-        }
-        Err(e) => Err(ApiError::LockError(format!(
-            "Failed to read shared state: {}",
-            e
-        ))),
-    }
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(state.get_dat2())))
 }
 
-/// Turns the stove on or off
+/// Retrieves the whole state in a single document
 ///
-/// Request example:
-/// ```json
-/// {
-///   "value": true
-/// }
-/// ```
-/// - `true`: Turns the stove on
-/// - `false`: Turns the stove off
+/// Returns the INF and DAT pages read under the same lock, with the version of the
+/// state, the pending setpoints and the read-only mode, so that integrations need one
+/// request per poll instead of four.
 #[utoipa::path(
-    post,
-    path = "/api/dat/set_on_off",
-    request_body = DatPostBool,
+    get,
+    path = "/api/state",
     responses(
-        (status = 200, description = "Stove turned on or off successfully"),
+        (status = 200, description = "State retrieved successfully", body = Object,
+            example = json!({"version": 1025, "inf": {"hostname": "stove"}, "dat0": {"index_stove_state": "Power"}, "dat1": {}, "dat2": {}, "pending": {}, "read_only": false})),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
 )]
-async fn post_on_off(
-    request: web::Json<DatPostBool>,
-    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
-    request_id_counter: web::Data<Arc<Mutex<u32>>>,
-) -> Result<HttpResponse, ApiError> {
-    let value = if request.value { 1 } else { 0 };
-    handle_request(
-        request_queue,
-        request_id_counter,
-        StoveCommands::OnOff as u32,
-        value,
-    )
-    .await
+async fn get_state(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<web::Json<serde_json::Value>, ApiError> {
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(*state)))
 }
 
-/// Activates or deactivates eco mode
+/// Retrieves the progress of the startup sequence
 ///
-/// Request example:
-/// ```json
-/// {
-///   "value": true
-/// }
-/// ```
-/// - `true`: Activates eco mode (energy saving)
-/// - `false`: Deactivates eco mode
+/// While the stove goes through Starting1 to Starting7, returns the phase, the time
+/// spent in it and, once a full startup was seen, the typical duration of the phases
+/// and the estimated remaining time. `progress` is null when the stove is not starting.
 #[utoipa::path(
-    post,
-    path = "/api/dat/set_eco_mode",
-    request_body = DatPostBool,
+    get,
+    path = "/api/startup_progress",
     responses(
-        (status = 200, description = "Eco mode set successfully"),
+        (status = 200, description = "Startup progress retrieved successfully", body = Object,
+            example = json!({"starting": true, "progress": {"phase": 3, "phases": 7, "name": "Starting3", "elapsed_secs": 42, "startup_elapsed_secs": 310, "typical_secs": 120, "remaining_secs": 480, "percent": 41}})),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
 )]
-async fn post_eco_mode(
-    request: web::Json<DatPostBool>,
-    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
-    request_id_counter: web::Data<Arc<Mutex<u32>>>,
-) -> Result<HttpResponse, ApiError> {
-    let value = if request.value { 1 } else { 0 };
-    handle_request(
-        request_queue,
-        request_id_counter,
-        StoveCommands::EcoMode as u32,
-        value,
-    )
-    .await
+async fn get_startup_progress(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<web::Json<serde_json::Value>, ApiError> {
+    let progress = read_lock(&data, "shared state").get_startup_progress();
+    Ok(web::Json(json!({
+        "starting": progress.is_some(),
+        "progress": progress,
+    })))
 }
 
-/// Sets the ambiance temperature
+/// Retrieves the active alarms
 ///
-/// Request example:
-/// ```json
-/// {
-///   "ambiance": 1,
-///   "value": 21.5
-/// }
-/// ```
-/// - `ambiance`: Ambiance number (1 or 2)
-/// - `value`: Temperature in degrees Celsius (ex: 21.5 for 21.5°C)
+/// Lists the alarm state of the stove, if any, the watched fields whose value stopped
+/// changing (see `[freeze_watchdog]`), which may point to a sensor or board issue, and
+/// the alerts fired and not resolved yet (see `[alerts]`).
 #[utoipa::path(
-    post,
-    path = "/api/dat/set_ambiance_temp",
-    request_body = DatPostAmbianceTemp,
+    get,
+    path = "/api/alarms",
     responses(
-        (status = 200, description = "Ambiance temperature set successfully"),
-        (status = 400, description = "Invalid parameters"),
+        (status = 200, description = "Alarms retrieved successfully", body = Object,
+            example = json!({
+                "stove": null,
+                "frozen_values": [{"field": "dat0.smoke_t", "value": 142.5, "since": "2024-01-15T20:05:12+01:00", "after_secs": 1800}],
+                "alerts": [{"name": "smoke_high", "field": "dat0.index_smoke_t", "value": 231.5, "priority": "critical", "since": "2024-01-15T20:12:40+01:00", "message": "smoke_high: dat0.index_smoke_t is 231.5, above 230 for 300s", "labels": [], "acknowledged": false, "silenced": false}]
+            })),
         (status = 500, description = "Internal server error")
     ),
     tag = "hottoh"
 )]
-async fn post_ambiance_temp(
-    request: web::Json<DatPostAmbianceTemp>,
-    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
-    request_id_counter: web::Data<Arc<Mutex<u32>>>,
-) -> Result<HttpResponse, ApiError> {
-    // Validation
-    if request.value.is_nan() || request.value.is_infinite() {
-        return Err(ApiError::InvalidParameter(
-            "Temperature cannot be NaN or infinite".into(),
-        ));
-    }
-
-    let command = match request.ambiance {
-        1 => StoveCommands::AmbianceTemperature1,
-        2 => StoveCommands::AmbianceTemperature2,
-        _ => {
-            return Err(ApiError::InvalidParameter(
-                "Ambiance number must be 1 or 2".into(),
-            ))
-        }
-    };
+async fn get_alarms(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<web::Json<serde_json::Value>, ApiError> {
+    let state = read_lock(&data, "shared state");
+    let stove_state = state.get_dat0().get_index_stove_state();
+    Ok(web::Json(json!({
+        "stove": stove_state.is_alarm().then_some(stove_state),
+        "frozen_values": state.get_frozen_values(),
+        "alerts": state.get_alerts(),
+    })))
+}
 
-    handle_request(
-        request_queue,
-        request_id_counter,
-        command as u32,
-        (request.value * 10.0) as i32,
-    )
-    .await
+/// Lists the active alerts and the silences
+///
+/// Alerts are raised by the rules of the `[alerts]` section. An acknowledged alert is
+/// not notified again until it resolves, and a silenced one is not notified at all.
+#[utoipa::path(
+    get,
+    path = "/api/alerts",
+    responses(
+        (status = 200, description = "Alerts retrieved successfully", body = Object,
+            example = json!({
+                "alerts": [{"name": "smoke_high", "field": "dat0.index_smoke_t", "value": 231.5, "priority": "critical", "since": "2024-01-15T20:12:40+01:00", "message": "smoke_high: dat0.index_smoke_t is 231.5, above 230 for 300s", "labels": ["chimney"], "acknowledged": false, "silenced": true}],
+                "silences": [{"id": 1, "label": "chimney", "comment": "Technician visit on Friday", "created": "2024-01-15T18:02:11+01:00", "expires": "2024-01-17T18:02:11+01:00"}]
+            }))
+    ),
+    tag = "hottoh"
+)]
+async fn get_alerts(data: web::Data<Arc<RwLock<SharedState>>>) -> HttpResponse {
+    let state = read_lock(&data, "shared state");
+    HttpResponse::Ok().json(json!({
+        "alerts": state.get_alerts(),
+        "silences": state.get_silences().collect::<Vec<_>>(),
+    }))
 }
 
-/// Activates or deactivates chrono mode
+/// Acknowledges an active alert
 ///
-/// Request example:
-/// ```json
-/// {
-///   "value": true
-/// }
-/// ```
-/// - `true`: Activates chrono mode (schedule programming)
-/// - `false`: Deactivates chrono mode
+/// The alert stays listed until it resolves, but its repeated notifications stop.
 #[utoipa::path(
     post,
-    path = "/api/dat/set_chrono_mode",
-    request_body = DatPostBool,
+    path = "/api/alerts/{name}/ack",
+    params(("name" = String, Path, description = "Name of the alert rule")),
     responses(
-        (status = 200, description = "Chrono mode set successfully"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Alert acknowledged", body = Object, example = json!({"success": true, "acknowledged": "smoke_high"})),
+        (status = 404, description = "No active alert for this rule")
     ),
     tag = "hottoh"
 )]
-async fn post_chrono_mode(
-    request: web::Json<DatPostBool>,
-    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
-    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+async fn post_alert_ack(
+    name: web::Path<String>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<HttpResponse, ApiError> {
-    handle_request(
-        request_queue,
-        request_id_counter,
-        StoveCommands::ChronoOnOff as u32,
-        request.value,
-    )
-    .await
+    let name = name.into_inner();
+    if !write_lock(&data, "shared state").acknowledge_alert(&name) {
+        return Err(ApiError::NotFound(format!("No active alert '{}'", name)));
+    }
+    info!("Alert '{}' acknowledged", name);
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "acknowledged": name
+    })))
 }
 
-/// Sets the chrono temperature
+/// Silences the notifications of the alerts of a rule, or with a label, for a while
 ///
-/// Request example:
-/// ```json
-/// {
-///   "chrono": 1,
-///   "value": 20.0
-/// }
-/// ```
-/// - `chrono`: Chrono number (1, 2 or 3)
-/// - `value`: Temperature in degrees Celsius (ex: 20.0 for 20°C)
+/// Matching alerts still fire, resolve and are listed, flagged `silenced`, but are not
+/// published to the notifiers until the silence expires or is deleted. Silences are
+/// kept in memory until the next restart.
 #[utoipa::path(
     post,
-    path = "/api/dat/set_chrono_temp",
-    request_body = DatPostChronoTemp,
+    path = "/api/alerts/silences",
+    request_body = SilenceRequest,
     responses(
-        (status = 200, description = "Chrono temperature set successfully"),
-        (status = 400, description = "Invalid parameters"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Silence created", body = Object, example = json!({"id": 1, "label": "chimney", "comment": "Technician visit on Friday", "created": "2024-01-15T18:02:11+01:00", "expires": "2024-01-17T18:02:11+01:00"})),
+        (status = 400, description = "Neither or both of rule and label, or invalid duration")
     ),
     tag = "hottoh"
 )]
-async fn post_chrono_temp(
-    request: web::Json<DatPostChronoTemp>,
-    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
-    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+async fn post_alert_silence(
+    request: web::Json<SilenceRequest>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<HttpResponse, ApiError> {
-    // Validation
+    let SilenceRequest {
+        rule,
+        label,
+        duration,
+        comment,
+    } = request.into_inner();
+    let rule = rule
+        .map(|rule| rule.trim().to_string())
+        .filter(|rule| !rule.is_empty());
+    let label = label
+        .map(|label| label.trim().to_string())
+        .filter(|label| !label.is_empty());
+    if rule.is_some() == label.is_some() {
+        return Err(ApiError::InvalidParameter(
+            "exactly one of rule or label is required".into(),
+        ));
+    }
+    let secs = parse_span(&duration).map_err(ApiError::InvalidParameter)?;
+    if secs > MAX_SILENCE_SECS {
+        return Err(ApiError::InvalidParameter(format!(
+            "duration must be at most {} days",
+            MAX_SILENCE_SECS / 86_400
+        )));
+    }
+    let silence = write_lock(&data, "shared state").add_silence(rule, label, comment, secs);
+    info!("Alerts silenced: {}", json!(silence));
+    Ok(HttpResponse::Ok().json(silence))
+}
+
+/// Deletes a silence before it expires
+#[utoipa::path(
+    delete,
+    path = "/api/alerts/silences/{id}",
+    params(("id" = u64, Path, description = "ID returned when the silence was created")),
+    responses(
+        (status = 200, description = "Silence deleted", body = Object, example = json!({"success": true, "id": 1})),
+        (status = 404, description = "No active silence with this ID")
+    ),
+    tag = "hottoh"
+)]
+async fn delete_alert_silence(
+    id: web::Path<u64>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let id = id.into_inner();
+    if !write_lock(&data, "shared state").remove_silence(id) {
+        return Err(ApiError::NotFound(format!("No active silence {}", id)));
+    }
+    info!("Silence {} deleted", id);
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "id": id
+    })))
+}
+
+/// Retrieves the setpoints written to the stove but not yet read back
+///
+/// Each queued command sets the expected value of its setpoint (e.g. `power_set`)
+/// until a DAT page confirms it, or for at most 10 seconds.
+#[utoipa::path(
+    get,
+    path = "/api/pending",
+    responses(
+        (status = 200, description = "Pending setpoints retrieved successfully", body = Object,
+            example = json!({"power_set": 5, "ambient_t1_set": 21.5})),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn get_pending(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<web::Json<serde_json::Value>, ApiError> {
+    let state = read_lock(&data, "shared state");
+    Ok(web::Json(json!(state.get_pending())))
+}
+
+/// Retrieves the settable values with the range accepted by the stove
+///
+/// Each value gives its measured `value`, its setpoint `set` (the value being written
+/// if any), the `min` and `max` setpoints reported by the stove and the `step`, so that
+/// clients can render sliders without hard-coding the ranges of each stove model.
+/// Values are null until the DAT page holding them is received.
+#[utoipa::path(
+    get,
+    path = "/api/state/normalized",
+    responses(
+        (status = 200, description = "Normalized state retrieved successfully", body = Object,
+            example = json!({"power_level": {"value": 3.0, "set": 5.0, "min": 1.0, "max": 5.0, "step": 1.0}, "ambiance_temp_1": {"value": 20.5, "set": 21.5, "min": 7.0, "max": 30.0, "step": 0.1}})),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn get_normalized_state(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<web::Json<serde_json::Value>, ApiError> {
+    let state = serde_json::to_value(&*read_lock(&data, "shared state")).unwrap_or_default();
+    Ok(web::Json(json!(normalize(&state))))
+}
+
+/// Gets the version of the state, incremented at each change
+///
+/// With `since`, the call waits until the version is greater than the given one, or
+/// until the timeout, so that clients learn about a change without polling the pages.
+#[utoipa::path(
+    get,
+    path = "/api/state/version",
+    params(StateVersionQuery),
+    responses(
+        (status = 200, description = "Version retrieved successfully, still `since` if nothing changed before the timeout", body = Object, example = json!({"version": 1025}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_state_version(
+    query: web::Query<StateVersionQuery>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> HttpResponse {
+    let version = match query.since {
+        Some(since) => {
+            let timeout = Duration::from_secs(
+                query
+                    .timeout
+                    .unwrap_or(MAX_VERSION_WAIT_SECS)
+                    .min(MAX_VERSION_WAIT_SECS),
+            );
+            let data = Arc::clone(&data);
+            web::block(move || wait_for_version(&data, since, timeout))
+                .await
+                .unwrap_or(since)
+        }
+        None => read_lock(&data, "shared state").get_version().get(),
+    };
+    HttpResponse::Ok().json(json!({ "version": version }))
+}
+
+/// Streams the state over a WebSocket, and receives commands on it
+///
+/// The whole state is sent on connection as `{"type": "full", "seq", "version",
+/// "state"}`, then each change as `{"type": "update", "seq", "version", "changes"}`,
+/// where `changes` is an RFC 7386 merge patch only holding the values that changed
+/// (e.g. `{"dat0": {"index_smoke_t": 142.5}}`), so that clients do not have to poll the
+/// pages. `seq` numbers the messages of each client from 1; on a gap, the client sends
+/// `{"type": "resync"}` to get the whole state again. With `?fields=`, only these pages
+/// and fields are streamed, and the client gets no update while none of them changes.
+/// Fields are named `page.field`, where the `index_` prefix can be omitted.
+///
+/// Clients may send commands as `{"type": "command", "id": 7, "command": "PowerLevel",
+/// "value": 3}`, with the raw value and optionally `pin`, `force`, `ttl` and `wait`.
+/// When `http_api.signing_secret` is set, commands are wrapped as `{"type": "signed",
+/// "timestamp", "signature", "message"}` unless they carry the PIN. They go through the
+/// same checks as the `POST /api/dat/set_*` endpoints, a read-only API key being
+/// rejected, and are acknowledged with `{"type": "ack", "id", "success",
+/// "request_id"}` or `{"type": "ack", "id", "success": false, "status", "error"}`.
+#[utoipa::path(
+    get,
+    path = "/api/ws",
+    params(WsQuery),
+    responses(
+        (status = 101, description = "Switching to the WebSocket protocol"),
+        (status = 400, description = "Not a WebSocket handshake, or unknown page or field")
+    ),
+    tag = "hottoh"
+)]
+async fn get_ws(
+    req: HttpRequest,
+    body: web::Payload,
+    query: web::Query<WsQuery>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let fields = match &query.fields {
+        Some(spec) => Some(parse_stream_fields(spec).map_err(ApiError::InvalidParameter)?),
+        None => None,
+    };
+    let commands = request_commands(&req)?;
+    let (response, session, messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(stream_state(
+        session,
+        messages,
+        Arc::clone(&data),
+        fields,
+        commands,
+    ));
+    Ok(response)
+}
+
+/// Builds the handler of the commands sent on a WebSocket or with GraphQL
+///
+/// The role of the API key, the `X-Hottoh-Pin` header and the address of the client
+/// are taken from the request.
+///
+/// # Arguments
+///
+/// * `req` - The request
+///
+/// # Returns
+///
+/// * `Result<WsCommands, actix_web::Error>` - The handler, or an error if the data of
+///   the application is missing
+fn request_commands(req: &HttpRequest) -> Result<WsCommands, actix_web::Error> {
+    fn data<T: 'static>(req: &HttpRequest) -> Result<Arc<T>, actix_web::Error> {
+        req.app_data::<web::Data<Arc<T>>>()
+            .map(|data| Arc::clone(data.get_ref()))
+            .ok_or_else(|| actix_web::error::ErrorInternalServerError("missing application data"))
+    }
+    let auth_guard = data::<AuthGuard>(req)?;
+    Ok(WsCommands {
+        request_queue: data(req)?,
+        request_id_counter: data(req)?,
+        shared_state: data(req)?,
+        plugins: data(req)?,
+        child_lock: data(req)?,
+        auth_guard: request_client_ip(req).map(|client| (auth_guard, client)),
+        role: req.extensions().get::<Role>().copied(),
+        pin: req
+            .headers()
+            .get(PIN_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+        signing: data(req)?,
+    })
+}
+
+/// Queries the state with GraphQL, or opens a GraphQL WebSocket
+///
+/// The query is given as `?query=` (with `variables` and `operationName`), e.g.
+/// `?query={state{dat0{index_ambient_t1 index_stove_state}}}`, and only the fields
+/// asked for are returned. Mutations need `POST /graphql`. With a WebSocket handshake
+/// using the `graphql-transport-ws` (or `graphql-ws`) protocol, the socket serves the
+/// queries, the `command` mutation, checked as the WebSocket commands, and the `state`
+/// subscription, sent at each change of the state or of its `fields`.
+#[utoipa::path(
+    get,
+    path = "/graphql",
+    params(
+        ("query" = String, Query, description = "GraphQL query", example = "{state{dat0{index_ambient_t1 index_stove_state}}}"),
+        ("variables" = Option<String>, Query, description = "Variables of the query, as JSON"),
+        ("operationName" = Option<String>, Query, description = "Operation to run when the query holds several")
+    ),
+    responses(
+        (status = 200, description = "Result of the query, with its errors", body = Object, example = json!({"data": {"state": {"dat0": {"index_ambient_t1": 20.5, "index_stove_state": 0}}}})),
+        (status = 101, description = "Switching to the GraphQL WebSocket protocol"),
+        (status = 400, description = "Invalid query string, or unsupported WebSocket protocol")
+    ),
+    tag = "hottoh"
+)]
+async fn get_graphql(
+    req: HttpRequest,
+    body: web::Payload,
+    schema: web::Data<Schema>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let upgrade = req
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+    if !upgrade {
+        let request = parse_query_string(req.query_string())
+            .map_err(|e| ApiError::InvalidParameter(e.to_string()))?;
+        return Ok(HttpResponse::Ok().json(schema.execute(request).await));
+    }
+
+    let protocol = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| protocol.trim().parse::<Protocols>().ok())
+        })
+        .ok_or_else(|| {
+            ApiError::InvalidParameter(
+                "expected the graphql-transport-ws or graphql-ws WebSocket protocol".into(),
+            )
+        })?;
+    let commands = request_commands(&req)?;
+    let (mut response, session, messages) = actix_ws::handle(&req, body)?;
+    response.headers_mut().insert(
+        header::SEC_WEBSOCKET_PROTOCOL,
+        header::HeaderValue::from_static(protocol.sec_websocket_protocol()),
+    );
+    actix_web::rt::spawn(serve_graphql_ws(
+        session,
+        messages,
+        Schema::clone(&schema),
+        protocol,
+        commands,
+    ));
+    Ok(response)
+}
+
+/// Queries the state or sends commands with GraphQL
+///
+/// The body is `{"query", "variables", "operationName"}`. As the other POST requests,
+/// it needs the PIN or a signature, and a control API key. The `command` mutation sends
+/// a command with its raw value, as the WebSocket commands, e.g.
+/// `mutation { command(command: "PowerLevel", value: 3) }`, and returns the
+/// acknowledgement.
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    request_body(content = Object, description = "GraphQL request", example = json!({"query": "mutation { command(command: \"PowerLevel\", value: 3) }"})),
+    responses(
+        (status = 200, description = "Result of the query, with its errors", body = Object, example = json!({"data": {"command": {"type": "ack", "id": null, "success": true, "request_id": 42}}})),
+        (status = 400, description = "Invalid request"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 403, description = "Read-only API key")
+    ),
+    tag = "hottoh"
+)]
+async fn post_graphql(
+    req: HttpRequest,
+    request: web::Json<async_graphql::Request>,
+    schema: web::Data<Schema>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let commands = request_commands(&req)?;
+    let request = request.into_inner().data(GraphqlCommands {
+        commands,
+        verified: true,
+    });
+    Ok(HttpResponse::Ok().json(schema.execute(request).await))
+}
+
+/// Turns the stove on or off
+///
+/// Request example:
+/// ```json
+/// {
+///   "value": true
+/// }
+/// ```
+/// - `true`: Turns the stove on
+/// - `false`: Turns the stove off
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_on_off",
+    request_body = DatPostBool,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Stove turned on or off successfully"),
+        (status = 202, description = "The stove is running: a confirmation token is returned, send the request again with it to turn the stove off"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_on_off(
+    request: web::Json<DatPostBool>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let value = if request.value { 1 } else { 0 };
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        StoveCommands::OnOff as u32,
+        value,
+    )
+    .await
+}
+
+/// Activates or deactivates eco mode
+///
+/// Request example:
+/// ```json
+/// {
+///   "value": true
+/// }
+/// ```
+/// - `true`: Activates eco mode (energy saving)
+/// - `false`: Deactivates eco mode
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_eco_mode",
+    request_body = DatPostBool,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Eco mode set successfully"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_eco_mode(
+    request: web::Json<DatPostBool>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let value = if request.value { 1 } else { 0 };
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        StoveCommands::EcoMode as u32,
+        value,
+    )
+    .await
+}
+
+/// Sets the ambiance temperature
+///
+/// Request example:
+/// ```json
+/// {
+///   "ambiance": 1,
+///   "value": 21.5
+/// }
+/// ```
+/// - `ambiance`: Ambiance number (1 or 2)
+/// - `value`: Temperature in degrees Celsius (ex: 21.5 for 21.5°C)
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_ambiance_temp",
+    request_body = DatPostAmbianceTemp,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Ambiance temperature set successfully"),
+        (status = 400, description = "Invalid parameters"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_ambiance_temp(
+    request: web::Json<DatPostAmbianceTemp>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    // Validation
     if request.value.is_nan() || request.value.is_infinite() {
         return Err(ApiError::InvalidParameter(
             "Temperature cannot be NaN or infinite".into(),
         ));
     }
 
-    let command = match request.chrono {
-        1 => StoveCommands::ChronoTemperature1,
-        2 => StoveCommands::ChronoTemperature2,
-        3 => StoveCommands::ChronoTemperature3,
-        _ => {
-            return Err(ApiError::InvalidParameter(
-                "Chrono number must be between 1 and 3".into(),
-            ))
+    let command = match request.ambiance {
+        1 => StoveCommands::AmbianceTemperature1,
+        2 => StoveCommands::AmbianceTemperature2,
+        _ => {
+            return Err(ApiError::InvalidParameter(
+                "Ambiance number must be 1 or 2".into(),
+            ))
+        }
+    };
+    let value = to_tenths(f64::from(request.value));
+    check_command_value(&data, command, value)?;
+
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        command as u32,
+        value,
+    )
+    .await
+}
+
+/// Activates or deactivates chrono mode
+///
+/// Request example:
+/// ```json
+/// {
+///   "value": true
+/// }
+/// ```
+/// - `true`: Activates chrono mode (schedule programming)
+/// - `false`: Deactivates chrono mode
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_chrono_mode",
+    request_body = DatPostBool,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Chrono mode set successfully"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_chrono_mode(
+    request: web::Json<DatPostBool>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        StoveCommands::ChronoOnOff as u32,
+        request.value,
+    )
+    .await
+}
+
+/// Sets the chrono temperature
+///
+/// Request example:
+/// ```json
+/// {
+///   "chrono": 1,
+///   "value": 20.0
+/// }
+/// ```
+/// - `chrono`: Chrono number (1, 2 or 3)
+/// - `value`: Temperature in degrees Celsius (ex: 20.0 for 20°C)
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_chrono_temp",
+    request_body = DatPostChronoTemp,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Chrono temperature set successfully"),
+        (status = 400, description = "Invalid parameters"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_chrono_temp(
+    request: web::Json<DatPostChronoTemp>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    // Validation
+    if request.value.is_nan() || request.value.is_infinite() {
+        return Err(ApiError::InvalidParameter(
+            "Temperature cannot be NaN or infinite".into(),
+        ));
+    }
+
+    let command = match request.chrono {
+        1 => StoveCommands::ChronoTemperature1,
+        2 => StoveCommands::ChronoTemperature2,
+        3 => StoveCommands::ChronoTemperature3,
+        _ => {
+            return Err(ApiError::InvalidParameter(
+                "Chrono number must be between 1 and 3".into(),
+            ))
+        }
+    };
+
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        command as u32,
+        to_tenths(f64::from(request.value)),
+    )
+    .await
+}
+
+/// Sets the fan speed
+///
+/// Request example:
+/// ```json
+/// {
+///   "fan": 1,
+///   "value": 3
+/// }
+/// ```
+/// - `fan`: Fan number (1, 2 or 3)
+/// - `value`: Speed (0 to 5, where 0 = off and 5 = maximum speed)
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_fan_speed",
+    request_body = DatPostFanSpeed,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Fan speed set successfully"),
+        (status = 400, description = "Invalid parameters"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_fan_speed(
+    request: web::Json<DatPostFanSpeed>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let command = match request.fan {
+        1 => StoveCommands::FanSpeed1,
+        2 => StoveCommands::FanSpeed2,
+        3 => StoveCommands::FanSpeed3,
+        _ => {
+            return Err(ApiError::InvalidParameter(
+                "Fan number must be between 1 and 3".into(),
+            ))
+        }
+    };
+    check_command_value(&data, command, raw_value(request.value)?)?;
+
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        command as u32,
+        request.value,
+    )
+    .await
+}
+
+/// Sets the power level
+///
+/// Request example:
+/// ```json
+/// {
+///   "value": 5
+/// }
+/// ```
+/// - `value`: Power level (0 to 10, where 0 = minimum and 10 = maximum)
+#[utoipa::path(
+    post,
+    path = "/api/dat/set_power_level",
+    request_body = DatPostU32,
+    params(CommandQuery),
+    responses(
+        (status = 200, description = "Power level set successfully"),
+        (status = 400, description = "Invalid parameters"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_power_level(
+    request: web::Json<DatPostU32>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    check_command_value(&data, StoveCommands::PowerLevel, raw_value(request.value)?)?;
+
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        StoveCommands::PowerLevel as u32,
+        request.value,
+    )
+    .await
+}
+
+/// Turns the stove on for a limited time
+///
+/// Request example:
+/// ```json
+/// {
+///   "minutes": 90,
+///   "power": 4
+/// }
+/// ```
+/// The stove is turned on at the given power level, and turned off automatically at
+/// the end of the run. The end is kept across restarts of the bridge, and replaces the
+/// one of a previous run.
+#[utoipa::path(
+    post,
+    path = "/api/dat/run_for",
+    request_body = RunForRequest,
+    params(RunForQuery),
+    responses(
+        (status = 200, description = "Stove turned on until the end of the run", body = Object, example = json!({"success": true, "request_ids": [12, 13], "off_at": "2026-10-16T21:30:00+02:00"})),
+        (status = 400, description = "Invalid parameters"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 409, description = "A maintenance window is active"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)")
+    ),
+    tag = "hottoh"
+)]
+async fn post_run_for(
+    request: web::Json<RunForRequest>,
+    query: web::Query<RunForQuery>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    // Validation
+    if !(1..=MAX_RUN_MINUTES).contains(&request.minutes) {
+        return Err(ApiError::InvalidParameter(format!(
+            "Duration must be between 1 and {} minutes",
+            MAX_RUN_MINUTES
+        )));
+    }
+    if !(1..=10).contains(&request.power) {
+        return Err(ApiError::InvalidParameter(
+            "Power level must be between 1 and 10".into(),
+        ));
+    }
+
+    let request_ids = queue_write_requests(
+        &request_queue,
+        &request_id_counter,
+        &data,
+        CommandOrigin::Manual {
+            force: query.force,
+            confirmed: false,
+        },
+        &[
+            (StoveCommands::OnOff as u32, "1".to_string()),
+            (StoveCommands::PowerLevel as u32, request.power.to_string()),
+        ],
+        query.ttl.map(Duration::from_secs),
+    )?;
+
+    let off_at = Local::now() + Duration::from_secs(request.minutes * 60);
+    let scheduled = write_lock(&data, "shared state")
+        .get_run_timer_mut()
+        .map(|run_timer| run_timer.schedule(off_at, query.force));
+    if let Some(scheduled) = scheduled {
+        if let Err(e) = save_run_timer(scheduled).await {
+            error!(
+                "Cannot save the end of the run, it will be lost on restart: {}",
+                e
+            );
+        }
+    }
+    info!(
+        "Running the stove at power {} until {} (id {:?})",
+        request.power,
+        off_at.to_rfc3339(),
+        request_ids
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "request_ids": request_ids,
+        "off_at": off_at.to_rfc3339()
+    })))
+}
+
+/// Saves a change of the run timer on a blocking thread
+///
+/// # Arguments
+///
+/// * `change` - The change, made with the shared state locked
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - An error if the file of the stop cannot be written or removed
+async fn save_run_timer(change: RunTimerSave) -> std::io::Result<()> {
+    web::block(move || change.save())
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+}
+
+/// Gets the end of the time-limited run in progress
+#[utoipa::path(
+    get,
+    path = "/api/dat/run_for",
+    responses(
+        (status = 200, description = "Run retrieved successfully, `off_at` is null if no run is in progress", body = Object, example = json!({"off_at": "2026-10-16T21:30:00+02:00", "remaining_seconds": 5400}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_run_for(data: web::Data<Arc<RwLock<SharedState>>>) -> HttpResponse {
+    let off_at = read_lock(&data, "shared state")
+        .get_run_timer()
+        .and_then(|run_timer| run_timer.get_off_at());
+    HttpResponse::Ok().json(json!({
+        "off_at": off_at.map(|off_at| off_at.to_rfc3339()),
+        "remaining_seconds": off_at.map(|off_at| (off_at - Local::now()).num_seconds().max(0)),
+    }))
+}
+
+/// Cancels the end of the time-limited run in progress
+///
+/// The stove keeps running until it is turned off.
+#[utoipa::path(
+    delete,
+    path = "/api/dat/run_for",
+    responses(
+        (status = 200, description = "Run cancelled successfully", body = Object, example = json!({"success": true})),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 404, description = "No run in progress")
+    ),
+    tag = "hottoh"
+)]
+async fn delete_run_for(
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let cancelled = write_lock(&data, "shared state")
+        .get_run_timer_mut()
+        .filter(|run_timer| run_timer.get_off_at().is_some())
+        .map(RunTimer::cancel)
+        .ok_or_else(|| ApiError::NotFound("no run in progress".into()))?;
+    if let Err(e) = save_run_timer(cancelled).await {
+        error!("Cannot remove run timer file: {}", e);
+    }
+    info!("Run cancelled, the stove keeps running");
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true
+    })))
+}
+
+/// Exposes runtime metrics in Prometheus text format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics retrieved successfully", content_type = "text/plain")
+    ),
+    tag = "hottoh"
+)]
+async fn get_metrics(
+    metrics: web::Data<Arc<Metrics>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    response_queue: web::Data<Arc<RwLock<VecDeque<Response>>>>,
+) -> HttpResponse {
+    let stove = stove_labels(&data);
+    let resources = ResourceUsage::collect(&request_queue, &response_queue);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.render(&stove, &resources))
+}
+
+/// Serves a lightweight HTML page with the runtime metrics
+///
+/// Shows the connection to the stove, the response latencies, the depths of the
+/// queues and the resources used by the process, for a quick health check from a
+/// phone without Prometheus or Grafana.
+#[utoipa::path(
+    get,
+    path = "/stats",
+    responses(
+        (status = 200, description = "Stats page", content_type = "text/html")
+    ),
+    tag = "hottoh"
+)]
+async fn get_stats(
+    metrics: web::Data<Arc<Metrics>>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    response_queue: web::Data<Arc<RwLock<VecDeque<Response>>>>,
+) -> HttpResponse {
+    let mut queues = QueueDepths::default();
+    for request in read_lock(&request_queue, "request queue")
+        .iter()
+        .filter(|request| !request.is_marked_as_deleted())
+    {
+        if request.is_sent() {
+            queues.sent_requests += 1;
+        } else {
+            queues.pending_requests += 1;
+        }
+    }
+    queues.responses = read_lock(&response_queue, "response queue")
+        .iter()
+        .filter(|response| !response.is_marked_as_deleted())
+        .count();
+    let resources = ResourceUsage::collect(&request_queue, &response_queue);
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(render_stats_page(&metrics, &queues, &resources))
+}
+
+/// Reports the health of the bridge
+///
+/// Includes the number of crashes of each supervised worker thread, the address of
+/// the stove in use (`null` while disconnected), the result of the reachability checks
+/// made while the stove cannot be reached (see `stove.ping_check`, `null` otherwise),
+/// and the maintenance window in progress (`null` outside of them).
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    responses(
+        (status = 200, description = "Health retrieved successfully")
+    ),
+    tag = "hottoh"
+)]
+async fn get_healthz(
+    metrics: web::Data<Arc<Metrics>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> HttpResponse {
+    let thread_crashes = metrics.get_thread_crashes();
+    let maintenance = read_lock(&data, "shared state")
+        .get_maintenance()
+        .active_window(Local::now())
+        .map(|window| {
+            json!({
+                "window": window.to_string(),
+                "message": "Maintenance in progress: automation commands are suppressed, manual ones need force=true",
+            })
+        });
+    let stove_address = metrics
+        .get_stove_address()
+        .map(|(address, fallback)| json!({"address": address, "fallback": fallback}));
+    HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "thread_crashes_total": thread_crashes.values().sum::<u64>(),
+        "thread_crashes": thread_crashes,
+        "stove_address": stove_address,
+        "network": metrics.get_network_diagnosis(),
+        "maintenance": maintenance,
+    }))
+}
+
+/// Retrieves build and runtime version information
+#[utoipa::path(
+    get,
+    path = "/api/version",
+    responses(
+        (status = 200, description = "Version retrieved successfully")
+    ),
+    tag = "hottoh"
+)]
+async fn get_version(
+    metrics: web::Data<Arc<Metrics>>,
+    identity: web::Data<Arc<BridgeIdentity>>,
+) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "bridge_id": identity.get_bridge_id(),
+        "version": VERSION,
+        "git_hash": GIT_HASH,
+        "protocol_profile": PROTOCOL_PROFILE,
+        "uptime_seconds": metrics.get_uptime_secs(),
+    }))
+}
+
+/// Drops all pending requests and responses
+///
+/// Useful to recover when stale writes have piled up in the queue and should
+/// no longer be delivered to the stove. The pending values of the dropped writes are
+/// removed, and a `command_dropped` event is published for each of them.
+#[utoipa::path(
+    post,
+    path = "/api/admin/queues/clear",
+    params(ClearQueuesQuery),
+    responses(
+        (status = 200, description = "Queues cleared successfully")
+    ),
+    tag = "hottoh"
+)]
+async fn post_clear_queues(
+    query: web::Query<ClearQueuesQuery>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    response_queue: web::Data<Arc<RwLock<VecDeque<Response>>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> HttpResponse {
+    let (requests_dropped, responses_dropped) =
+        clear_queues(&request_queue, &response_queue, &data, query.only_reads);
+    info!(
+        "Queues cleared (only_reads: {}): {} request(s) and {} response(s) dropped",
+        query.only_reads, requests_dropped, responses_dropped
+    );
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "requests_dropped": requests_dropped,
+        "responses_dropped": responses_dropped
+    }))
+}
+
+/// Lists the requests waiting in the queue
+///
+/// Each request is reported with its command, params, age and status: `pending`
+/// (not sent yet), `sent` (waiting for the response of the stove) or `deleted`
+/// (answered or expired, removed at the next cleanup).
+#[utoipa::path(
+    get,
+    path = "/api/admin/queue",
+    responses(
+        (status = 200, description = "Queue retrieved successfully", body = Object, example = json!({
+            "length": 1,
+            "requests": [{
+                "request_id": 7,
+                "command": "DAT",
+                "command_type": "W",
+                "params": ["2", "5"],
+                "status": "sent",
+                "age_seconds": 2,
+                "sent_seconds_ago": 1,
+                "expires_in_seconds": null
+            }]
+        }))
+    ),
+    tag = "hottoh"
+)]
+async fn get_queue(request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>) -> HttpResponse {
+    let requests: Vec<_> = read_lock(&request_queue, "request queue")
+        .iter()
+        .map(|request| {
+            let status = if request.is_marked_as_deleted() {
+                "deleted"
+            } else if request.is_sent() {
+                "sent"
+            } else {
+                "pending"
+            };
+            json!({
+                "request_id": request.get_req_id(),
+                "command": request.get_command().as_str(),
+                "command_type": request.get_command_type().as_str(),
+                "params": request.get_params(),
+                "status": status,
+                "age_seconds": request.get_queued_at().elapsed().as_secs(),
+                "sent_seconds_ago": request.get_sent_at().map(|sent_at| sent_at.elapsed().as_secs()),
+                "expires_in_seconds": request.get_expires_at().filter(|_| !request.is_sent()).map(|expires_at| expires_at.saturating_duration_since(Instant::now()).as_secs()),
+            })
+        })
+        .collect();
+    HttpResponse::Ok().json(json!({
+        "length": requests.len(),
+        "requests": requests
+    }))
+}
+
+/// Cancels a command that was not sent to the stove yet
+///
+/// The pending value of the command is dropped. Commands already sent cannot be
+/// cancelled.
+#[utoipa::path(
+    delete,
+    path = "/api/command/{id}",
+    params(("id" = u32, Path, description = "ID returned when the command was queued")),
+    responses(
+        (status = 200, description = "Command cancelled successfully", body = Object, example = json!({"success": true, "request_id": 12})),
+        (status = 404, description = "No queued command with this ID"),
+        (status = 409, description = "The command was already sent to the stove")
+    ),
+    tag = "hottoh"
+)]
+async fn delete_command(
+    id: web::Path<u32>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let request_id = id.into_inner();
+    cancel_request(&request_queue, &data, request_id)?;
+    info!("Command {} cancelled", request_id);
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "request_id": request_id
+    })))
+}
+
+/// Lists the clients banned after failed authentications, and the last authentication events
+///
+/// A client failing to authenticate `http_api.max_auth_failures` times within 10 minutes
+/// (wrong PIN, signature or pairing code) gets 429 for 1 minute, then twice as long at
+/// each new ban, up to 24 hours.
+#[utoipa::path(
+    get,
+    path = "/api/admin/auth/bans",
+    responses(
+        (status = 200, description = "Bans retrieved successfully", body = Object, example = json!({
+            "max_failures": 5,
+            "clients": [{"client": "192.168.1.42", "failures": 0, "bans": 1, "banned_until": "2024-01-15T20:06:12+01:00"}],
+            "events": [{"at": "2024-01-15T20:05:12+01:00", "client": "192.168.1.42", "kind": "ban", "detail": "5 failed attempts, banned for 60 seconds"}]
+        }))
+    ),
+    tag = "hottoh"
+)]
+async fn get_auth_bans(guard: web::Data<Arc<AuthGuard>>) -> HttpResponse {
+    let mut bans = guard.list();
+    bans["events"] = json!(guard.get_events());
+    HttpResponse::Ok().json(bans)
+}
+
+/// Lifts the authentication bans and forgets the failed attempts
+#[utoipa::path(
+    delete,
+    path = "/api/admin/auth/bans",
+    params(AuthBansQuery),
+    responses(
+        (status = 200, description = "Bans lifted successfully", body = Object, example = json!({"success": true, "cleared": 1}))
+    ),
+    tag = "hottoh"
+)]
+async fn delete_auth_bans(
+    guard: web::Data<Arc<AuthGuard>>,
+    query: web::Query<AuthBansQuery>,
+) -> HttpResponse {
+    let cleared = guard.clear(query.client);
+    HttpResponse::Ok().json(json!({ "success": true, "cleared": cleared }))
+}
+
+/// Gets the read-only mode
+///
+/// While enabled, every command to the stove is rejected with 405.
+#[utoipa::path(
+    get,
+    path = "/api/admin/read_only",
+    responses(
+        (status = 200, description = "Read-only mode retrieved successfully", body = Object, example = json!({"read_only": false}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_read_only(data: web::Data<Arc<RwLock<SharedState>>>) -> HttpResponse {
+    let read_only = read_lock(&data, "shared state").is_read_only();
+    HttpResponse::Ok().json(json!({ "read_only": read_only }))
+}
+
+/// Enables or disables the read-only mode
+///
+/// The change lasts until the next restart, where `general.read_only` applies again.
+#[utoipa::path(
+    post,
+    path = "/api/admin/read_only",
+    request_body = DatPostBool,
+    responses(
+        (status = 200, description = "Read-only mode changed successfully", body = Object, example = json!({"success": true, "read_only": true}))
+    ),
+    tag = "hottoh"
+)]
+async fn post_read_only(
+    request: web::Json<DatPostBool>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> HttpResponse {
+    write_lock(&data, "shared state").set_read_only(request.value);
+    info!(
+        "Read-only mode {}",
+        if request.value { "enabled" } else { "disabled" }
+    );
+    HttpResponse::Ok().json(json!({
+        "success": true,
+        "read_only": request.value
+    }))
+}
+
+/// Lists the loaded plugins and the commands and notifiers they registered
+#[utoipa::path(
+    get,
+    path = "/api/plugins",
+    responses(
+        (status = 200, description = "Plugins retrieved successfully", body = Object, example = json!({"plugins": ["event_log"], "commands": {"SetRecipe2": 17}, "notifiers": ["event_log"]}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_plugins(plugins: web::Data<Arc<PluginRegistry>>) -> HttpResponse {
+    HttpResponse::Ok().json(plugins.describe())
+}
+
+/// Sends a command registered by a plugin or in the `[plugins]` section
+///
+/// The value is sent as is, like with the inbound webhooks.
+#[utoipa::path(
+    post,
+    path = "/api/plugins/commands/{name}",
+    request_body = DatPostI32,
+    params(
+        ("name" = String, Path, description = "Name of the registered command"),
+        CommandQuery
+    ),
+    responses(
+        (status = 200, description = "Command queued successfully"),
+        (status = 202, description = "Destructive command: a confirmation token is returned, send the request again with it"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 404, description = "Unknown command"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn post_plugin_command(
+    name: web::Path<String>,
+    request: web::Json<DatPostI32>,
+    plugins: web::Data<Arc<PluginRegistry>>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: web::Query<CommandQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(action) = plugins.get_command(&name) else {
+        return Err(ApiError::NotFound(format!("Unknown command '{}'", name)));
+    };
+    handle_request(
+        request_queue,
+        request_id_counter,
+        data,
+        &query,
+        action,
+        request.value,
+    )
+    .await
+}
+
+/// Generates a new pairing code, replacing the previous one
+///
+/// The code is valid 10 minutes and can be used once by a new client on
+/// `POST /api/pairing` to obtain an API key, with the `control` role unless another
+/// one is given.
+#[utoipa::path(
+    post,
+    path = "/api/admin/pairing/code",
+    request_body(content = Option<PairingCodeRequest>, description = "Role of the key issued to the client"),
+    responses(
+        (status = 200, description = "Pairing code generated successfully", body = Object, example = json!({"code": "042517", "role": "control", "expires_in_seconds": 600})),
+        (status = 400, description = "Unknown role"),
+        (status = 401, description = "Missing or wrong PIN"),
+        (status = 404, description = "Pairing is disabled (no [auth] section)")
+    ),
+    tag = "hottoh"
+)]
+async fn post_pairing_code(
+    request: Option<web::Json<PairingCodeRequest>>,
+    pairing: web::Data<Arc<PairingCodes>>,
+    api_keys: web::Data<Arc<ApiKeys>>,
+) -> Result<HttpResponse, ApiError> {
+    if !api_keys.is_enabled() {
+        return Err(ApiError::NotFound(
+            "Pairing is disabled, the [auth] section is not configured".into(),
+        ));
+    }
+    let role = match request.as_ref().and_then(|request| request.role.as_deref()) {
+        Some(role) => Role::parse(role).ok_or_else(|| {
+            ApiError::InvalidParameter(format!("unknown role '{}', expected read or control", role))
+        })?,
+        None => Role::Control,
+    };
+    match pairing.generate(role) {
+        Ok(code) => {
+            info!("New pairing code generated for a {} key", role.as_str());
+            Ok(HttpResponse::Ok().json(json!({
+                "code": code,
+                "role": role.as_str(),
+                "expires_in_seconds": PAIRING_TTL.as_secs()
+            })))
+        }
+        Err(e) => {
+            error!("Cannot generate pairing code: {}", e);
+            Ok(HttpResponse::InternalServerError().json(json!({ "error": e })))
+        }
+    }
+}
+
+/// Pairs a new client
+///
+/// Exchanges the one-time code shown in the logs at startup, or generated with
+/// `POST /api/admin/pairing/code`, for an API key of the client, with the role of the
+/// code. The key is shown once and kept in `auth.paired_keys_file`, and can be revoked
+/// with `DELETE /api/admin/pairing/keys/{name}`. The code is dropped after 5 wrong
+/// attempts.
+#[utoipa::path(
+    post,
+    path = "/api/pairing",
+    request_body = PairingRequest,
+    responses(
+        (status = 200, description = "Client paired successfully", body = Object, example = json!({"name": "kitchen_panel", "role": "control", "api_key": "5e0c9b7f2a4d8e1c3b6a9f0d2e4c7a1b8d3f5e6c"})),
+        (status = 400, description = "Invalid name, or name already used by a key"),
+        (status = 401, description = "Invalid or expired code"),
+        (status = 404, description = "Pairing is disabled (no [auth] section)")
+    ),
+    tag = "hottoh"
+)]
+async fn post_pairing(
+    request: web::Json<PairingRequest>,
+    pairing: web::Data<Arc<PairingCodes>>,
+    api_keys: web::Data<Arc<ApiKeys>>,
+) -> Result<HttpResponse, ApiError> {
+    if !api_keys.is_enabled() {
+        return Err(ApiError::NotFound(
+            "Pairing is disabled, the [auth] section is not configured".into(),
+        ));
+    }
+    let name = request.name.trim().to_string();
+    // Checked before the code is used, so that a typo does not waste it
+    api_keys
+        .check_name(&name)
+        .map_err(ApiError::InvalidParameter)?;
+    let Some(role) = pairing.redeem(&request.code) else {
+        return Err(ApiError::Unauthorized(
+            "invalid or expired pairing code".into(),
+        ));
+    };
+    let issued = {
+        let api_keys = Arc::clone(&api_keys);
+        let name = name.clone();
+        web::block(move || api_keys.issue(&name, role)).await
+    };
+    match issued {
+        Ok(Ok(api_key)) => {
+            info!("New client '{}' paired", name);
+            Ok(HttpResponse::Ok().json(json!({
+                "name": name,
+                "role": role.as_str(),
+                "api_key": api_key
+            })))
+        }
+        Ok(Err(e)) => {
+            error!("Cannot issue the API key of '{}': {}", name, e);
+            Ok(HttpResponse::InternalServerError().json(json!({ "error": e })))
+        }
+        Err(e) => {
+            error!("Cannot issue the API key of '{}': {}", name, e);
+            Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Cannot issue the API key"
+            })))
+        }
+    }
+}
+
+/// Lists the clients paired with `POST /api/pairing`
+#[utoipa::path(
+    get,
+    path = "/api/admin/pairing/keys",
+    responses(
+        (status = 200, description = "Paired clients", body = Object, example = json!({"keys": [{"name": "kitchen_panel", "role": "control"}]}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_paired_keys(api_keys: web::Data<Arc<ApiKeys>>) -> HttpResponse {
+    let keys: Vec<_> = api_keys
+        .get_paired()
+        .into_iter()
+        .map(|(name, role)| json!({ "name": name, "role": role.as_str() }))
+        .collect();
+    HttpResponse::Ok().json(json!({ "keys": keys }))
+}
+
+/// Revokes the API key of a paired client
+#[utoipa::path(
+    delete,
+    path = "/api/admin/pairing/keys/{name}",
+    params(("name" = String, Path, description = "Name given to the client when it was paired")),
+    responses(
+        (status = 200, description = "Key revoked", body = Object, example = json!({"success": true, "name": "kitchen_panel"})),
+        (status = 404, description = "No paired client has this name")
+    ),
+    tag = "hottoh"
+)]
+async fn delete_paired_key(
+    name: web::Path<String>,
+    api_keys: web::Data<Arc<ApiKeys>>,
+) -> Result<HttpResponse, ApiError> {
+    let name = name.into_inner();
+    let revoked = {
+        let api_keys = Arc::clone(&api_keys);
+        let name = name.clone();
+        web::block(move || api_keys.revoke(&name)).await
+    };
+    match revoked {
+        Ok(Ok(true)) => Ok(HttpResponse::Ok().json(json!({ "success": true, "name": name }))),
+        Ok(Ok(false)) => Err(ApiError::NotFound(format!(
+            "no paired client is named '{}'",
+            name
+        ))),
+        Ok(Err(e)) => {
+            error!("Cannot revoke the API key of '{}': {}", name, e);
+            Ok(HttpResponse::InternalServerError().json(json!({ "error": e })))
+        }
+        Err(e) => {
+            error!("Cannot revoke the API key of '{}': {}", name, e);
+            Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "Cannot revoke the API key"
+            })))
+        }
+    }
+}
+
+/// Activates the quiet or the normal profile
+///
+/// Overrides the quiet hours until the scheduled profile changes: activating `normal`
+/// during the quiet hours lifts the limits until they end, activating `quiet` applies
+/// them until the next quiet hours start or end.
+#[utoipa::path(
+    post,
+    path = "/api/profiles/activate",
+    request_body = ProfileRequest,
+    responses(
+        (status = 200, description = "Profile activated successfully", body = Object, example = json!({"success": true, "profile": "normal"})),
+        (status = 400, description = "Unknown profile"),
+        (status = 404, description = "Quiet hours are not configured")
+    ),
+    tag = "hottoh"
+)]
+async fn post_activate_profile(
+    request: web::Json<ProfileRequest>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let quiet = match request.profile.as_str() {
+        QUIET_PROFILE => true,
+        NORMAL_PROFILE => false,
+        other => {
+            return Err(ApiError::InvalidParameter(format!(
+                "unknown profile '{}', expected '{}' or '{}'",
+                other, QUIET_PROFILE, NORMAL_PROFILE
+            )))
         }
     };
+    let mut state = write_lock(&data, "shared state");
+    let Some(quiet_hours) = state.get_quiet_hours_mut() else {
+        return Err(ApiError::NotFound("quiet hours are not configured".into()));
+    };
+    quiet_hours.activate(quiet, Local::now());
+    info!("Profile '{}' activated", request.profile);
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "profile": request.profile
+    })))
+}
 
-    handle_request(
-        request_queue,
-        request_id_counter,
-        command as u32,
-        (request.value * 10.0) as i32,
-    )
-    .await
+/// Lists the named profiles configured in `[profiles]`
+#[utoipa::path(
+    get,
+    path = "/api/profiles",
+    responses(
+        (status = 200, description = "Profiles retrieved successfully", body = Object, example = json!({"eco": "PowerLevel=2,FanSpeed1=1,EcoMode=1"}))
+    ),
+    tag = "hottoh"
+)]
+async fn get_profiles(profiles: web::Data<Arc<Profiles>>) -> HttpResponse {
+    HttpResponse::Ok().json(profiles.get_profiles())
 }
 
-/// Sets the fan speed
+/// Applies a named profile configured in `[profiles]`
 ///
-/// Request example:
-/// ```json
-/// {
-///   "fan": 1,
-///   "value": 3
-/// }
-/// ```
-/// - `fan`: Fan number (1, 2 or 3)
-/// - `value`: Speed (0 to 5, where 0 = off and 5 = maximum speed)
+/// All the settings of the profile are checked together and queued at once: if one of
+/// them is rejected (read-only mode, maintenance window, throttling), none is sent.
 #[utoipa::path(
     post,
-    path = "/api/dat/set_fan_speed",
-    request_body = DatPostFanSpeed,
+    path = "/api/profiles/{name}/activate",
+    params(
+        ("name" = String, Path, description = "Name of the profile"),
+        NamedProfileQuery
+    ),
     responses(
-        (status = 200, description = "Fan speed set successfully"),
-        (status = 400, description = "Invalid parameters"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Profile commands queued successfully", body = Object, example = json!({"success": true, "profile": "eco", "request_ids": [12, 13, 14]})),
+        (status = 404, description = "Unknown profile"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 409, description = "A maintenance window is active"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)")
     ),
     tag = "hottoh"
 )]
-async fn post_fan_speed(
-    request: web::Json<DatPostFanSpeed>,
+async fn post_activate_named_profile(
+    name: web::Path<String>,
+    query: web::Query<NamedProfileQuery>,
+    profiles: web::Data<Arc<Profiles>>,
     request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
     request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<HttpResponse, ApiError> {
-    // Validation
-    if request.value > 5 {
-        return Err(ApiError::InvalidParameter(
-            "Fan speed must be between 0 and 5".into(),
-        ));
-    }
+    let commands: Vec<(u32, String)> = profiles
+        .get_profile(&name)
+        .ok_or_else(|| ApiError::NotFound(format!("profile '{}'", name)))?
+        .iter()
+        .map(|(command, value)| (*command, value.to_string()))
+        .collect();
 
-    let command = match request.fan {
-        1 => StoveCommands::FanSpeed1,
-        2 => StoveCommands::FanSpeed2,
-        3 => StoveCommands::FanSpeed3,
-        _ => {
-            return Err(ApiError::InvalidParameter(
-                "Fan number must be between 1 and 3".into(),
+    let request_ids = queue_write_requests(
+        &request_queue,
+        &request_id_counter,
+        &data,
+        CommandOrigin::Manual {
+            force: query.force,
+            confirmed: false,
+        },
+        &commands,
+        query.ttl.map(Duration::from_secs),
+    )?;
+    info!("Profile '{}' queued request(s) {:?}", name, request_ids);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "profile": name.as_str(),
+        "request_ids": request_ids
+    })))
+}
+
+/// Builds a zip archive to attach to bug reports
+///
+/// Contains the version information, the effective configuration with its secrets
+/// redacted, the metrics, the last raw frames exchanged with the stove and the last
+/// log lines.
+#[utoipa::path(
+    get,
+    path = "/api/admin/diagnostics/bundle",
+    params(DiagnosticsQuery),
+    responses(
+        (status = 200, description = "Diagnostics bundle built successfully", content_type = "application/zip"),
+        (status = 500, description = "Internal server error")
+    ),
+    tag = "hottoh"
+)]
+async fn get_diagnostics_bundle(
+    query: web::Query<DiagnosticsQuery>,
+    config: web::Data<Arc<RwLock<AppConfig>>>,
+    metrics: web::Data<Arc<Metrics>>,
+    frame_capture: web::Data<Arc<FrameCapture>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
+    response_queue: web::Data<Arc<RwLock<VecDeque<Response>>>>,
+) -> HttpResponse {
+    let stove = stove_labels(&data);
+    let resources = ResourceUsage::collect(&request_queue, &response_queue);
+    let bundle = build_bundle(
+        &read_lock(&config, "config"),
+        &metrics,
+        &stove,
+        &resources,
+        &frame_capture,
+        query.lines.min(MAX_DIAGNOSTICS_LOG_LINES),
+    );
+    match bundle {
+        Ok(bundle) => HttpResponse::Ok()
+            .content_type("application/zip")
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"hottoh_diagnostics.zip\"",
             ))
+            .body(bundle),
+        Err(e) => {
+            error!("Cannot build diagnostics bundle: {}", e);
+            HttpResponse::InternalServerError().json(json!({
+                "error": format!("Cannot build diagnostics bundle: {}", e)
+            }))
         }
+    }
+}
+
+/// Gets the history of a field
+///
+/// Recent values are raw samples (`{"time", "value"}`); older ones are aggregated
+/// over buckets (`{"time", "avg", "min", "max", "count"}`, `time` being the start of
+/// the bucket), as configured in the `[history]` section.
+///
+/// With `agg` and `bucket` (e.g. `agg=max&bucket=15m`), the statistic is computed
+/// server-side over the buckets from the coarsest level that fits them, and the points
+/// are `{"time", "value"}`, so that a day graph does not need thousands of raw points.
+///
+/// The commands accepted by the stove and the state transitions of the range are
+/// returned as `annotations`, to mark them on the graphs.
+#[utoipa::path(
+    get,
+    path = "/api/history",
+    params(HistoryQuery),
+    responses(
+        (status = 200, description = "History retrieved successfully", body = Object,
+            example = json!({
+                "field": "dat0.index_smoke_t",
+                "level": "15m",
+                "levels": [{"level": "raw", "bucket_secs": null, "retention_secs": 21600}],
+                "points": [{"time": "2025-01-15T10:00:00+00:00", "avg": 142.3, "min": 138.0, "max": 147.5, "count": 90}],
+                "annotations": [{"time": "2025-01-15T10:07:12+00:00", "type": "command", "command": "PowerLevel", "action": 2, "value": 5.0, "origin": {"manual": {"force": false, "confirmed": false}}}]
+            })),
+        (status = 400, description = "Unknown field or level, invalid time or bucket, or level too coarse for the buckets"),
+        (status = 404, description = "History disabled")
+    ),
+    tag = "hottoh"
+)]
+async fn get_history(
+    query: web::Query<HistoryQuery>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let history = Arc::clone(read_lock(&data, "shared state").get_history());
+    if !history.is_enabled() {
+        return Err(ApiError::NotFound("History is disabled".into()));
+    }
+    let parse_time = |time: &Option<String>| {
+        time.as_deref()
+            .map(|time| {
+                DateTime::parse_from_rfc3339(time)
+                    .map(|time| time.timestamp())
+                    .map_err(|_| ApiError::InvalidParameter(format!("invalid time '{}'", time)))
+            })
+            .transpose()
     };
+    let (from, to) = (parse_time(&query.from)?, parse_time(&query.to)?);
+    let bucket = query
+        .bucket
+        .as_deref()
+        .map(parse_span)
+        .transpose()
+        .map_err(ApiError::InvalidParameter)?;
+    let aggregate = match (query.agg, bucket) {
+        (None, None) => None,
+        (agg, bucket) => Some((bucket, agg.unwrap_or(Aggregation::Avg))),
+    };
+    let (level, points) = history
+        .query(&query.field, query.level.as_deref(), from, to, aggregate)
+        .map_err(ApiError::InvalidParameter)?;
+    let mut response = json!({
+        "field": resolve_field(history.get_fields(), &query.field).unwrap_or_default(),
+        "level": level.get_name(),
+        "levels": history.get_levels().iter().map(HistoryLevel::to_json).collect::<Vec<_>>(),
+        "points": points,
+        "annotations": history.get_annotations(from, to),
+    });
+    if let Some((_, agg)) = aggregate {
+        response["agg"] = json!(agg.as_str());
+        response["bucket"] = json!(query.bucket.as_deref().unwrap_or(level.get_name()));
+    }
+    Ok(HttpResponse::Ok().json(response))
+}
 
-    handle_request(
-        request_queue,
-        request_id_counter,
-        command as u32,
-        request.value,
-    )
-    .await
+/// Imports past samples into the history from a CSV file
+///
+/// The first column is the time (RFC 3339, `YYYY-MM-DD HH:MM:SS` in local time, or
+/// Unix seconds) and the header names the other columns as `page.field`, e.g.
+/// `time,dat0.smoke_t,dat0.temp_room_1`. Columns not recorded in the history are
+/// ignored. The samples are merged with the stored points at each level, within its
+/// retention.
+#[utoipa::path(
+    post,
+    path = "/api/history/import",
+    request_body(content = String, content_type = "text/csv",
+        example = "time,dat0.smoke_t\n2025-01-15T10:00:00+00:00,142.5\n2025-01-15T10:00:10+00:00,143.0"),
+    responses(
+        (status = 200, description = "Samples imported", body = Object,
+            example = json!({
+                "rows": 2,
+                "imported": {"dat0.index_smoke_t": 2},
+                "ignored_columns": [],
+                "invalid_values": 0
+            })),
+        (status = 400, description = "Invalid CSV: no recorded column, invalid time or encoding"),
+        (status = 404, description = "History disabled")
+    ),
+    tag = "hottoh"
+)]
+async fn post_history_import(
+    body: web::Bytes,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+) -> Result<HttpResponse, ApiError> {
+    let history = Arc::clone(read_lock(&data, "shared state").get_history());
+    if !history.is_enabled() {
+        return Err(ApiError::NotFound("History is disabled".into()));
+    }
+    // Parsing and merging up to MAX_IMPORT_BYTES of CSV would stall the worker
+    let imported = web::block(move || {
+        let text = std::str::from_utf8(&body)
+            .map_err(|_| ApiError::InvalidParameter("the CSV must be UTF-8".into()))?;
+        let import = parse_csv(text, history.get_fields()).map_err(ApiError::InvalidParameter)?;
+        let imported = import
+            .samples
+            .iter()
+            .map(|(field, samples)| (field.clone(), json!(history.import(field, samples))))
+            .collect::<serde_json::Map<_, _>>();
+        Ok::<_, ApiError>((import, imported))
+    })
+    .await;
+    let (import, imported) = match imported {
+        Ok(imported) => imported?,
+        Err(e) => {
+            error!("History import failed: {}", e);
+            return Ok(HttpResponse::InternalServerError().json(json!({
+                "error": "History import failed"
+            })));
+        }
+    };
+    info!(
+        "Imported {} CSV row(s) into the history: {}",
+        import.rows,
+        serde_json::Value::Object(imported.clone())
+    );
+    Ok(HttpResponse::Ok().json(json!({
+        "rows": import.rows,
+        "imported": imported,
+        "ignored_columns": import.ignored_columns,
+        "invalid_values": import.invalid_values,
+    })))
 }
 
-/// Sets the power level
+/// Gets the storage used by the history
 ///
-/// Request example:
-/// ```json
-/// {
-///   "value": 5
-/// }
-/// ```
-/// - `value`: Power level (0 to 10, where 0 = minimum and 10 = maximum)
+/// The history is kept in memory: each level holds its points up to its retention and
+/// older ones are pruned as samples are recorded, so the size is bounded by the
+/// `[history]` section. Returns the points stored at each level and for each field,
+/// the annotations and an estimate of the memory used.
+#[utoipa::path(
+    get,
+    path = "/api/admin/storage",
+    responses(
+        (status = 200, description = "Storage of the history", body = Object,
+            example = json!({
+                "levels": [{"level": "raw", "bucket_secs": null, "retention_secs": 21600, "points": 6480}],
+                "fields": {"dat0.index_smoke_t": 2160},
+                "points": 6480,
+                "annotations": 12,
+                "estimated_bytes": 209000
+            })),
+        (status = 404, description = "History disabled")
+    ),
+    tag = "hottoh"
+)]
+async fn get_storage(data: web::Data<Arc<RwLock<SharedState>>>) -> Result<HttpResponse, ApiError> {
+    let history = Arc::clone(read_lock(&data, "shared state").get_history());
+    if !history.is_enabled() {
+        return Err(ApiError::NotFound("History is disabled".into()));
+    }
+    Ok(HttpResponse::Ok().json(history.get_storage()))
+}
+
+/// Gets the latest crash report
+///
+/// Reports are written to `general.crash_dir` when a thread panics, with the panic
+/// message and location, the backtrace, the last 100 log lines and a summary of the
+/// state. The last 10 are kept.
+#[utoipa::path(
+    get,
+    path = "/api/admin/last_crash",
+    responses(
+        (status = 200, description = "Latest crash report", body = Object),
+        (status = 404, description = "No crash report")
+    ),
+    tag = "hottoh"
+)]
+async fn get_last_crash(config: web::Data<Arc<RwLock<AppConfig>>>) -> HttpResponse {
+    let directory = read_lock(&config, "config")
+        .general
+        .crash_dir
+        .clone()
+        .unwrap_or_else(|| DEFAULT_CRASH_DIR.to_string());
+    match read_last_crash(Path::new(&directory)) {
+        Some(report) => HttpResponse::Ok().json(report),
+        None => HttpResponse::NotFound().json(json!({
+            "error": "No crash report"
+        })),
+    }
+}
+
+/// Triggers a named action configured in `[inbound_webhooks]`
+///
+/// The raw request body must be signed with the shared secret, and the signature
+/// sent in the `X-Hottoh-Signature` header as `sha256=<hex HMAC-SHA256 of the body>`.
+/// The body itself is not interpreted, so it can be any payload sent by the caller.
 #[utoipa::path(
     post,
-    path = "/api/dat/set_power_level",
-    request_body = DatPostU32,
+    path = "/api/webhooks/{action}",
+    request_body(content = String, content_type = "application/octet-stream", description = "Any payload, signed as-is"),
+    params(
+        ("action" = String, Path, description = "Name of the configured action"),
+        ("X-Hottoh-Signature" = String, Header, description = "sha256=<hex HMAC-SHA256 of the body>"),
+        WebhookQuery
+    ),
     responses(
-        (status = 200, description = "Power level set successfully"),
-        (status = 400, description = "Invalid parameters"),
-        (status = 500, description = "Internal server error")
+        (status = 200, description = "Action commands queued successfully"),
+        (status = 202, description = "The action holds a destructive command: a confirmation token is returned, send the request again with it"),
+        (status = 401, description = "Missing or invalid signature"),
+        (status = 405, description = "Read-only mode is enabled"),
+        (status = 429, description = "Command throttled (writes per minute, on/off gap or setpoint step)"),
+        (status = 404, description = "Unknown action")
     ),
     tag = "hottoh"
 )]
-async fn post_power_level(
-    request: web::Json<DatPostU32>,
+#[allow(clippy::too_many_arguments)]
+async fn post_inbound_webhook(
+    http_request: HttpRequest,
+    action: web::Path<String>,
+    query: web::Query<WebhookQuery>,
+    body: web::Bytes,
+    webhooks: web::Data<Arc<InboundWebhooks>>,
     request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
     request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
 ) -> Result<HttpResponse, ApiError> {
-    // Validation
-    if request.value > 10 {
-        return Err(ApiError::InvalidParameter(
-            "Power level must be between 0 and 10".into(),
-        ));
+    let signature = http_request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !webhooks.verify_signature(&body, signature) {
+        return Err(ApiError::Unauthorized(format!(
+            "invalid signature for webhook '{}'",
+            action
+        )));
     }
 
-    handle_request(
-        request_queue,
-        request_id_counter,
-        StoveCommands::PowerLevel as u32,
-        request.value,
-    )
-    .await
+    let commands: Vec<(u32, String)> = webhooks
+        .get_action(&action)
+        .ok_or_else(|| ApiError::NotFound(format!("webhook action '{}'", action)))?
+        .iter()
+        .map(|(command, value)| (*command, value.to_string()))
+        .collect();
+    let confirmed = query.confirm.as_deref().is_some_and(|token| {
+        read_lock(&data, "shared state")
+            .get_confirmations()
+            .redeem(token, &commands)
+    });
+
+    let request_ids = queue_write_requests(
+        &request_queue,
+        &request_id_counter,
+        &data,
+        CommandOrigin::Integration { confirmed },
+        &commands,
+        None,
+    )?;
+    info!(
+        "Inbound webhook '{}' queued request(s) {:?}",
+        action, request_ids
+    );
+
+    Ok(HttpResponse::Ok().json(json!({
+        "success": true,
+        "action": action.as_str(),
+        "request_ids": request_ids
+    })))
+}
+
+/// Builds the labels identifying the stove in the metrics
+///
+/// # Arguments
+///
+/// * `data` - The shared state
+///
+/// # Returns
+///
+/// * `StoveLabels` - The labels of the connected stove
+fn stove_labels(data: &RwLock<SharedState>) -> StoveLabels {
+    let state = read_lock(data, "shared state");
+    StoveLabels {
+        stove_id: DEFAULT_STOVE_ID.to_string(),
+        hostname: state.get_inf().get_hostname().to_string(),
+        firmware: state.get_inf().get_version().to_string(),
+        manufacturer: state.get_dat0().get_manufacturer_name(),
+        actuators: state.get_dat2().get_actuators().list(),
+    }
 }
 
 /// Starts the HTTP server
 pub async fn start_http_server(
-    request_queue: Arc<RwLock<VecDeque<Request>>>,
+    commands: CommandQueue,
+    response_queue: Arc<RwLock<VecDeque<Response>>>,
     shared_state: Arc<RwLock<SharedState>>,
-    request_id_counter: Arc<Mutex<u32>>,
+    metrics: Arc<Metrics>,
+    frame_capture: Arc<FrameCapture>,
+    plugins: Arc<PluginRegistry>,
     config: Arc<RwLock<AppConfig>>,
 ) -> std::io::Result<()> {
     // Extract necessary information from the config and release the lock
     // before asynchronous operations
-    let http_address = {
+    let (
+        listeners,
+        inbound_webhooks,
+        profiles,
+        access_log,
+        network_filter,
+        child_lock,
+        request_signing,
+        api_keys,
+        auth_guard,
+        id_file,
+    ) = {
         let cfg = config.read().expect("Cannot read config in http thread.");
-        format!("{}:{}", cfg.http_api.ip, cfg.http_api.port)
+        (
+            Arc::new(
+                Listeners::from_config(&cfg.http_api, cfg.listeners.as_ref())
+                    .map_err(std::io::Error::other)?,
+            ),
+            Arc::new(InboundWebhooks::from_config(cfg.inbound_webhooks.as_ref())),
+            Arc::new(Profiles::from_config(cfg.profiles.as_ref())),
+            Arc::new(AccessLog::from_config(&cfg.http_api)),
+            Arc::new(NetworkFilter::from_config(&cfg.http_api).map_err(std::io::Error::other)?),
+            Arc::new(ChildLock::from_config(&cfg.http_api)),
+            Arc::new(RequestSigning::from_config(&cfg.http_api)),
+            Arc::new(ApiKeys::from_config(cfg.auth.as_ref()).map_err(std::io::Error::other)?),
+            Arc::new(AuthGuard::from_config(&cfg.http_api)),
+            cfg.general
+                .id_file
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ID_FILE.to_string()),
+        )
     };
 
-    info!("Starting HTTP server on {}", http_address);
+    let identity = Arc::new(BridgeIdentity::load(&id_file));
+    info!("Bridge ID: {}", identity.get_bridge_id());
+    let graphql_schema = build_schema(Arc::clone(&shared_state)).map_err(std::io::Error::other)?;
+    start_grpc_server(
+        config
+            .read()
+            .expect("Cannot read config in http thread.")
+            .grpc
+            .as_ref(),
+        GrpcService {
+            commands: commands.clone(),
+            shared_state: Arc::clone(&shared_state),
+            plugins: Arc::clone(&plugins),
+            child_lock: Arc::clone(&child_lock),
+            signing: Arc::clone(&request_signing),
+            api_keys: Arc::clone(&api_keys),
+            auth_guard: Arc::clone(&auth_guard),
+            network_filter: Arc::clone(&network_filter),
+        },
+    );
+    let pairing = Arc::new(PairingCodes::new());
+    if api_keys.is_enabled() {
+        match pairing.generate(Role::Control) {
+            Ok(code) => info!(
+                "Pairing code: {} (valid {} minutes, POST it to /api/pairing)",
+                code,
+                PAIRING_TTL.as_secs() / 60
+            ),
+            Err(e) => error!("Cannot generate pairing code: {}", e),
+        }
+    }
+
+    let CommandQueue {
+        request_queue,
+        request_id_counter,
+    } = commands;
+
+    let tls_configs = listeners
+        .get_listeners()
+        .iter()
+        .map(|listener| {
+            listener
+                .load_tls()
+                .map_err(|e| std::io::Error::other(format!("{}: {}", listener.get_name(), e)))
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let app_listeners = Arc::clone(&listeners);
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .wrap(middleware::Logger::default())
+            .wrap(middleware::from_fn(require_pin))
+            .wrap(middleware::from_fn(require_signature))
+            .wrap(middleware::from_fn(require_api_key))
+            .wrap(middleware::from_fn(guard_auth))
+            .wrap(middleware::from_fn(filter_request))
+            .wrap(middleware::from_fn(log_request))
             .wrap(middleware::Compress::default())
+            .app_data(web::Data::new(access_log.clone()))
+            .app_data(web::Data::new(network_filter.clone()))
+            .app_data(web::Data::new(child_lock.clone()))
+            .app_data(web::Data::new(request_signing.clone()))
+            .app_data(web::Data::new(api_keys.clone()))
+            .app_data(web::Data::new(app_listeners.clone()))
+            .app_data(web::Data::new(auth_guard.clone()))
             .app_data(web::Data::new(request_queue.clone()))
+            .app_data(web::Data::new(response_queue.clone()))
             .app_data(web::Data::new(shared_state.clone()))
             .app_data(web::Data::new(request_id_counter.clone()))
+            .app_data(web::Data::new(metrics.clone()))
+            .app_data(web::Data::new(inbound_webhooks.clone()))
+            .app_data(web::Data::new(profiles.clone()))
+            .app_data(web::Data::new(frame_capture.clone()))
+            .app_data(web::Data::new(plugins.clone()))
+            .app_data(web::Data::new(identity.clone()))
+            .app_data(web::Data::new(pairing.clone()))
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(graphql_schema.clone()))
             .service(
                 SwaggerUi::new("/swagger-ui/{_:.*}")
                     .url("/api-docs/openapi.json", ApiDoc::openapi()),
             )
+            .route("/api/version", web::get().to(get_version))
             .route("/api/inf", web::get().to(get_inf))
             .route("/api/dat/0", web::get().to(get_dat0))
             .route("/api/dat/1", web::get().to(get_dat1))
             .route("/api/dat/2", web::get().to(get_dat2))
+            .route("/api/state", web::get().to(get_state))
+            .route("/api/pending", web::get().to(get_pending))
+            .route("/api/state/normalized", web::get().to(get_normalized_state))
+            .route("/api/state/version", web::get().to(get_state_version))
+            .route("/api/ws", web::get().to(get_ws))
+            .route("/graphql", web::get().to(get_graphql))
+            .route("/graphql", web::post().to(post_graphql))
+            .route("/api/alarms", web::get().to(get_alarms))
+            .route("/api/alerts", web::get().to(get_alerts))
+            .route("/api/alerts/silences", web::post().to(post_alert_silence))
+            .route(
+                "/api/alerts/silences/{id}",
+                web::delete().to(delete_alert_silence),
+            )
+            .route("/api/alerts/{name}/ack", web::post().to(post_alert_ack))
+            .route("/api/startup_progress", web::get().to(get_startup_progress))
             .route("/api/dat/set_on_off", web::post().to(post_on_off))
             .route("/api/dat/set_eco_mode", web::post().to(post_eco_mode))
             .route(
@@ -588,78 +2810,277 @@ pub async fn start_http_server(
             .route("/api/dat/set_chrono_temp", web::post().to(post_chrono_temp))
             .route("/api/dat/set_fan_speed", web::post().to(post_fan_speed))
             .route("/api/dat/set_power_level", web::post().to(post_power_level))
-    })
-    .bind(&http_address)?
-    .run()
-    .await
+            .route("/api/dat/run_for", web::post().to(post_run_for))
+            .route("/api/dat/run_for", web::get().to(get_run_for))
+            .route("/api/dat/run_for", web::delete().to(delete_run_for))
+            .route("/metrics", web::get().to(get_metrics))
+            .route("/stats", web::get().to(get_stats))
+            .route("/healthz", web::get().to(get_healthz))
+            .route("/api/admin/queue", web::get().to(get_queue))
+            .route("/api/command/{id}", web::delete().to(delete_command))
+            .route("/api/admin/queues/clear", web::post().to(post_clear_queues))
+            .route("/api/admin/read_only", web::get().to(get_read_only))
+            .route("/api/admin/read_only", web::post().to(post_read_only))
+            .route("/api/admin/auth/bans", web::get().to(get_auth_bans))
+            .route("/api/admin/auth/bans", web::delete().to(delete_auth_bans))
+            .route(
+                "/api/admin/diagnostics/bundle",
+                web::get().to(get_diagnostics_bundle),
+            )
+            .route("/api/admin/last_crash", web::get().to(get_last_crash))
+            .route("/api/admin/storage", web::get().to(get_storage))
+            .route("/api/history", web::get().to(get_history))
+            .service(
+                web::resource(HISTORY_IMPORT_PATH)
+                    .app_data(web::PayloadConfig::new(MAX_IMPORT_BYTES))
+                    .route(web::post().to(post_history_import)),
+            )
+            .route(
+                "/api/webhooks/{action}",
+                web::post().to(post_inbound_webhook),
+            )
+            .route("/api/admin/pairing/code", web::post().to(post_pairing_code))
+            .route("/api/pairing", web::post().to(post_pairing))
+            .route("/api/admin/pairing/keys", web::get().to(get_paired_keys))
+            .route(
+                "/api/admin/pairing/keys/{name}",
+                web::delete().to(delete_paired_key),
+            )
+            .route(
+                "/api/profiles/activate",
+                web::post().to(post_activate_profile),
+            )
+            .route("/api/profiles", web::get().to(get_profiles))
+            .route(
+                "/api/profiles/{name}/activate",
+                web::post().to(post_activate_named_profile),
+            )
+            .route("/api/plugins", web::get().to(get_plugins))
+            .route(
+                "/api/plugins/commands/{name}",
+                web::post().to(post_plugin_command),
+            )
+            .configure(|cfg| plugins.configure_routes(cfg))
+    });
+    for (listener, tls) in listeners.get_listeners().iter().zip(tls_configs) {
+        let scheme = if tls.is_some() { "https" } else { "http" };
+        info!(
+            "Starting HTTP server on {}://{} ({})",
+            scheme,
+            listener.get_address(),
+            listener.get_name()
+        );
+        server = match tls {
+            Some(tls) => server.bind_rustls_0_23(listener.get_address(), tls)?,
+            None => server.bind(listener.get_address())?,
+        };
+    }
+    server.run().await
+}
+
+/// Checks a setpoint against the range reported by the stove
+///
+/// # Arguments
+///
+/// * `data` - Shared state holding the stove data
+/// * `name` - Name of the value in the normalized state
+/// * `value` - The setpoint
+///
+/// # Returns
+///
+/// * `Result<(), ApiError>` - Success, or `InvalidParameter` if the stove does not have
+///   the feature or the value is outside of its range
+fn check_reported_range(
+    data: &RwLock<SharedState>,
+    name: &str,
+    value: f64,
+) -> Result<(), ApiError> {
+    let state = serde_json::to_value(&*read_lock(data, "shared state")).unwrap_or_default();
+    check_setpoint(&state, name, value).map_err(|e| ApiError::InvalidParameter(e.to_string()))
+}
+
+/// Converts a value of a request to the raw value of a command
+///
+/// # Arguments
+///
+/// * `value` - The value
+///
+/// # Returns
+///
+/// * `Result<i32, ApiError>` - The raw value, or `InvalidParameter` if it is too large
+fn raw_value(value: u32) -> Result<i32, ApiError> {
+    i32::try_from(value).map_err(|_| ApiError::InvalidParameter(format!("invalid value {}", value)))
+}
+
+/// Checks the raw value of a command, as sent in the DAT write frame
+///
+/// Shared by the `POST /api/dat/set_*` endpoints and the WebSocket commands, so that a
+/// value rejected by one is rejected by the other. Temperatures are in tenths of a
+/// degree. Commands without known range (e.g. the ones registered by plugins) are not
+/// checked.
+///
+/// # Arguments
+///
+/// * `data` - Shared state holding the stove data
+/// * `command` - The command
+/// * `value` - The raw value
+///
+/// # Returns
+///
+/// * `Result<(), ApiError>` - Success, or `InvalidParameter` if the value is out of the
+///   range of the command or of the one reported by the stove
+pub fn check_command_value(
+    data: &RwLock<SharedState>,
+    command: StoveCommands,
+    value: i32,
+) -> Result<(), ApiError> {
+    let out_of_range = |message: &str| Err(ApiError::InvalidParameter(message.to_string()));
+    match command {
+        StoveCommands::OnOff | StoveCommands::EcoMode | StoveCommands::ChronoOnOff
+            if !(0..=1).contains(&value) =>
+        {
+            out_of_range("Value must be 0 or 1")
+        }
+        StoveCommands::PowerLevel if !(0..=10).contains(&value) => {
+            out_of_range("Power level must be between 0 and 10")
+        }
+        StoveCommands::PowerLevel => check_reported_range(data, "power_level", f64::from(value)),
+        StoveCommands::FanSpeed1 | StoveCommands::FanSpeed2 | StoveCommands::FanSpeed3
+            if !(0..=5).contains(&value) =>
+        {
+            out_of_range("Fan speed must be between 0 and 5")
+        }
+        StoveCommands::FanSpeed1 => check_reported_range(data, "fan_speed_1", f64::from(value)),
+        StoveCommands::FanSpeed2 => check_reported_range(data, "fan_speed_2", f64::from(value)),
+        StoveCommands::FanSpeed3 => check_reported_range(data, "fan_speed_3", f64::from(value)),
+        StoveCommands::AmbianceTemperature1 => {
+            check_reported_range(data, "ambiance_temp_1", f64::from(value) / 10.0)
+        }
+        StoveCommands::AmbianceTemperature2 => {
+            check_reported_range(data, "ambiance_temp_2", f64::from(value) / 10.0)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Reads the body of a request in a middleware, so that the handler can read it again
+///
+/// The middlewares run before the route is matched, without the payload configuration
+/// of the resource, so the limit of `POST /api/history/import` is applied here.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request, whose payload is replaced by the body read
+///
+/// # Returns
+///
+/// * `Result<web::Bytes, Error>` - The body, or 413 if it is over the limit of the route
+pub async fn read_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let limit = if req.path() == HISTORY_IMPORT_PATH {
+        MAX_IMPORT_BYTES
+    } else {
+        MAX_BODY_BYTES
+    };
+    let bytes = req
+        .extract::<web::Payload>()
+        .await?
+        .to_bytes_limited(limit)
+        .await
+        .map_err(|_| PayloadError::Overflow)??;
+    req.set_payload(Payload::from(bytes.clone()));
+    Ok(bytes)
 }
 
 /// Handles a request and adds it to the queue
+///
+/// In dry-run mode, the request is built but not queued and the frame that
+/// would be sent to the stove is returned instead.
 async fn handle_request(
     request_queue: web::Data<Arc<RwLock<VecDeque<Request>>>>,
     request_id_counter: web::Data<Arc<Mutex<u32>>>,
+    data: web::Data<Arc<RwLock<SharedState>>>,
+    query: &CommandQuery,
     action: u32,
     value: impl ToString,
 ) -> Result<HttpResponse, ApiError> {
-    let mut id_lock = match request_id_counter.lock() {
-        Ok(lock) => lock,
-        Err(e) => {
-            error!("Failed to lock request ID counter: {}", e);
-            return Err(ApiError::InternalError(
-                "Failed to lock request ID counter".into(),
-            ));
-        }
-    };
+    let (dry_run, force) = (query.dry_run, query.force);
+    if dry_run {
+        let request_id = *mutex_lock(&request_id_counter, "request ID counter");
+        let params = vec![action.to_string(), value.to_string()];
+        let new_request =
+            Request::new(request_id, Command::Dat, CommandType::Write, params.clone());
+        let frame = String::from_utf8_lossy(&new_request.build_message()).into_owned();
+        debug!("Dry run for action: {}, frame: {:?}", action, frame);
+        return Ok(HttpResponse::Ok().json(json!({
+            "success": true,
+            "dry_run": true,
+            "command": Command::Dat.as_str(),
+            "command_type": CommandType::Write.as_str(),
+            "params": params,
+            "frame": frame
+        })));
+    }
 
-    let request_id = *id_lock;
-    let new_request = Request::new(
-        request_id,
-        Command::Dat,
-        CommandType::Write,
-        vec![action.to_string(), value.to_string()],
-    );
+    let confirmed = query.confirm.as_deref().is_some_and(|token| {
+        read_lock(&data, "shared state")
+            .get_confirmations()
+            .redeem(token, &[(action, value.to_string())])
+    });
+    let handle = send_command(
+        &request_queue,
+        &request_id_counter,
+        &data,
+        CommandOrigin::Manual { force, confirmed },
+        action,
+        &value,
+        query.ttl.map(Duration::from_secs),
+    )?;
+    let request_id = handle.get_request_id();
 
-    match request_queue.write() {
-        Ok(mut queue) => {
-            queue.push_back(new_request);
-            *id_lock = (*id_lock + 1) % 100000;
-            // Convert the action to StoveCommands to get the command name
-            let command_name = match action {
-                0 => "OnOff",
-                1 => "EcoMode",
-                2 => "PowerLevel",
-                3 => "AmbianceTemperature1",
-                4 => "AmbianceTemperature2",
-                5 => "FanSpeed1",
-                6 => "FanSpeed2",
-                7 => "FanSpeed3",
-                8 => "ChronoOnOff",
-                9 => "ChronoTemperature1",
-                10 => "ChronoTemperature2",
-                11 => "ChronoTemperature3",
-                12 => "SanTemperature",
-                13 => "PufTemperature",
-                14 => "BoilerTemperature",
-                15 => "HottohSetRecipe",
-                16 => "HottohSetPelSetpoint",
-                _ => "Unknown",
-            };
+    // Convert the action to StoveCommands to get the command name
+    let command_name = match action {
+        0 => "OnOff",
+        1 => "EcoMode",
+        2 => "PowerLevel",
+        3 => "AmbianceTemperature1",
+        4 => "AmbianceTemperature2",
+        5 => "FanSpeed1",
+        6 => "FanSpeed2",
+        7 => "FanSpeed3",
+        8 => "ChronoOnOff",
+        9 => "ChronoTemperature1",
+        10 => "ChronoTemperature2",
+        11 => "ChronoTemperature3",
+        12 => "SanTemperature",
+        13 => "PufTemperature",
+        14 => "BoilerTemperature",
+        15 => "HottohSetRecipe",
+        16 => "HottohSetPelSetpoint",
+        _ => "Unknown",
+    };
 
-            debug!(
-                "Request added for command: {}, value: {}, id: {}",
-                command_name,
-                value.to_string(),
-                request_id
-            );
-            Ok(HttpResponse::Ok().json(json!({
-                "success": true,
-                "message": format!("Request added for command: {}, value: {}, id: {}", command_name, value.to_string(), request_id),
-                "request_id": request_id
-            })))
-        }
-        Err(e) => {
-            error!("Failed to lock request queue: {}", e);
-            Err(ApiError::LockError("Failed to lock request queue".into()))
+    debug!(
+        "Request added for command: {}, value: {}, id: {}",
+        command_name,
+        value.to_string(),
+        request_id
+    );
+    let mut response = json!({
+        "success": true,
+        "message": format!("Request added for command: {}, value: {}, id: {}", command_name, value.to_string(), request_id),
+        "request_id": request_id
+    });
+    if let Some(wait) = query.wait.filter(|wait| *wait > 0) {
+        let timeout = Duration::from_secs(wait.min(MAX_COMMAND_WAIT_SECS));
+        let outcome = web::block(move || handle.wait(timeout))
+            .await
+            .unwrap_or(CommandOutcome::TimedOut);
+        response["outcome"] = json!(outcome.as_str());
+        match outcome {
+            CommandOutcome::Confirmed(value) => response["value"] = value,
+            CommandOutcome::Rejected(reason) => response["reason"] = json!(reason),
+            CommandOutcome::Accepted | CommandOutcome::TimedOut => {}
         }
     }
+    Ok(HttpResponse::Ok().json(response))
 }
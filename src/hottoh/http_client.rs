@@ -0,0 +1,239 @@
+use rustls::pki_types::ServerName;
+use rustls::{ClientConfig, ClientConnection, RootCertStore, StreamOwned};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use thiserror::Error;
+
+/// Timeout for connecting, sending and receiving
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Errors that can occur when calling a remote HTTP service
+#[derive(Error, Debug)]
+pub enum HttpClientError {
+    /// The URL is not a supported http:// or https:// URL
+    #[error("Invalid URL '{0}' (only http:// and https:// are supported)")]
+    InvalidUrl(String),
+    /// Connection or transfer error
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    /// The response could not be parsed
+    #[error("Invalid HTTP response: {0}")]
+    InvalidResponse(String),
+}
+
+/// Response of a remote HTTP service
+#[derive(Debug)]
+pub struct HttpClientResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response body
+    pub body: String,
+}
+
+impl HttpClientResponse {
+    /// Checks if the status code is a success (2xx)
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the request succeeded, false otherwise
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Sends a blocking HTTP/1.1 request
+///
+/// Used by the home automation integrations, which run on their own thread. https://
+/// URLs are verified against the Mozilla root certificates bundled in the binary.
+///
+/// # Arguments
+///
+/// * `method` - HTTP method (GET, POST, PUT...)
+/// * `url` - Full URL, including the query string
+/// * `headers` - Extra headers as (name, value) pairs
+/// * `body` - Request body (may be empty)
+///
+/// # Returns
+///
+/// * `Result<HttpClientResponse, HttpClientError>` - The response or an error
+pub fn send(
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> Result<HttpClientResponse, HttpClientError> {
+    let (tls, host, port, path) = split_url(url)?;
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| HttpClientError::InvalidUrl(url.to_string()))?;
+
+    let tcp = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    tcp.set_read_timeout(Some(TIMEOUT))?;
+    tcp.set_write_timeout(Some(TIMEOUT))?;
+    let mut stream: Box<dyn ReadWrite> = if tls {
+        let server_name = ServerName::try_from(host.to_string())
+            .map_err(|_| HttpClientError::InvalidUrl(url.to_string()))?;
+        let connection = ClientConnection::new(tls_config(), server_name)
+            .map_err(|e| HttpClientError::Io(std::io::Error::other(e)))?;
+        Box::new(StreamOwned::new(connection, tcp))
+    } else {
+        Box::new(tcp)
+    };
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        host,
+        port,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    stream.write_all(request.as_bytes())?;
+
+    let mut raw = Vec::new();
+    match stream.read_to_end(&mut raw) {
+        // Servers often close TLS connections without close_notify once the body is sent
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof && !raw.is_empty() => {}
+        result => {
+            result?;
+        }
+    }
+    parse_response(&raw)
+}
+
+/// Stream of a plain or TLS connection
+trait ReadWrite: Read + Write {}
+
+impl<T: Read + Write> ReadWrite for T {}
+
+/// Gets the TLS configuration of the https:// requests
+///
+/// # Returns
+///
+/// * `Arc<ClientConfig>` - The configuration, trusting the bundled Mozilla root certificates
+fn tls_config() -> Arc<ClientConfig> {
+    static CONFIG: OnceLock<Arc<ClientConfig>> = OnceLock::new();
+    Arc::clone(CONFIG.get_or_init(|| {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        Arc::new(
+            ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth(),
+        )
+    }))
+}
+
+/// Checks if a URL uses https://
+///
+/// # Arguments
+///
+/// * `url` - The URL
+///
+/// # Returns
+///
+/// * `bool` - True for an https:// URL
+pub fn is_https(url: &str) -> bool {
+    url.starts_with("https://")
+}
+
+/// Splits a http:// or https:// URL into scheme, host, port and path
+///
+/// # Arguments
+///
+/// * `url` - The URL
+///
+/// # Returns
+///
+/// * `Result<(bool, &str, u16, &str), HttpClientError>` - Whether TLS is used, host, port
+///   (80 or 443 by default) and path
+fn split_url(url: &str) -> Result<(bool, &str, u16, &str), HttpClientError> {
+    let invalid = || HttpClientError::InvalidUrl(url.to_string());
+    let (tls, rest) = match url.strip_prefix("https://") {
+        Some(rest) => (true, rest),
+        None => (false, url.strip_prefix("http://").ok_or_else(invalid)?),
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+        None => (authority, if tls { 443 } else { 80 }),
+    };
+    if host.is_empty() {
+        return Err(invalid());
+    }
+    Ok((tls, host, port, path))
+}
+
+/// Parses a raw HTTP/1.1 response, decoding chunked bodies
+///
+/// # Arguments
+///
+/// * `raw` - The bytes received until the connection was closed
+///
+/// # Returns
+///
+/// * `Result<HttpClientResponse, HttpClientError>` - The response or an error
+fn parse_response(raw: &[u8]) -> Result<HttpClientResponse, HttpClientError> {
+    let text = String::from_utf8_lossy(raw);
+    let (head, body) = text
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| HttpClientError::InvalidResponse("missing header end".into()))?;
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| HttpClientError::InvalidResponse("invalid status line".into()))?;
+
+    let chunked = lines.any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+    let body = if chunked {
+        decode_chunked(body)?
+    } else {
+        body.to_string()
+    };
+
+    Ok(HttpClientResponse { status, body })
+}
+
+/// Decodes a chunked transfer-encoded body
+///
+/// # Arguments
+///
+/// * `body` - The encoded body
+///
+/// # Returns
+///
+/// * `Result<String, HttpClientError>` - The decoded body or an error
+fn decode_chunked(mut body: &str) -> Result<String, HttpClientError> {
+    let invalid = || HttpClientError::InvalidResponse("invalid chunked body".into());
+    let mut decoded = String::new();
+    loop {
+        let (size_line, rest) = body.split_once("\r\n").ok_or_else(invalid)?;
+        let size_hex = size_line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size_hex, 16).map_err(|_| invalid())?;
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = rest.get(..size).ok_or_else(invalid)?;
+        decoded.push_str(chunk);
+        body = rest
+            .get(size..)
+            .ok_or_else(invalid)?
+            .trim_start_matches("\r\n");
+    }
+}
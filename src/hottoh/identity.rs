@@ -0,0 +1,124 @@
+use crate::hottoh::inbound_webhooks::to_hex;
+use log::{error, info};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// File keeping the bridge ID when `general.id_file` is not set
+pub const DEFAULT_ID_FILE: &str = "bridge.id";
+
+/// Generates a random UUID (version 4)
+///
+/// # Returns
+///
+/// * `io::Result<String>` - The UUID in its hyphenated form, or an error if no random bytes are available
+fn generate_uuid() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).map_err(|e| io::Error::other(e.to_string()))?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex = to_hex(&bytes);
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    ))
+}
+
+/// Checks if a string is a hyphenated UUID
+///
+/// # Arguments
+///
+/// * `value` - The string to check
+///
+/// # Returns
+///
+/// * `bool` - True if it has the 8-4-4-4-12 hexadecimal form
+fn is_uuid(value: &str) -> bool {
+    let groups: Vec<&str> = value.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Reads the bridge ID from its file, generating and saving it on the first run
+///
+/// # Arguments
+///
+/// * `path` - Path of the file holding the ID
+///
+/// # Returns
+///
+/// * `io::Result<String>` - The bridge ID, or an error if it cannot be read or saved
+fn load_or_create(path: &Path) -> io::Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) if is_uuid(content.trim()) => return Ok(content.trim().to_lowercase()),
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file does not contain a UUID",
+            ))
+        }
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => {}
+    }
+    let bridge_id = generate_uuid()?;
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{}\n", bridge_id))?;
+    info!("Generated bridge ID {} in {}", bridge_id, path.display());
+    Ok(bridge_id)
+}
+
+/// Persistent identity of the bridge
+pub struct BridgeIdentity {
+    /// UUID of the bridge
+    bridge_id: String,
+}
+
+impl BridgeIdentity {
+    /// Loads the identity of the bridge
+    ///
+    /// The ID is generated on the first run and kept in `general.id_file`, so clients
+    /// can recognize the bridge across restarts and IP changes. If the file cannot be
+    /// used, a temporary ID is generated for this run.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file holding the ID
+    ///
+    /// # Returns
+    ///
+    /// * `BridgeIdentity` - The identity
+    pub fn load(path: &str) -> Self {
+        let bridge_id = match load_or_create(Path::new(path)) {
+            Ok(bridge_id) => bridge_id,
+            Err(e) => {
+                error!(
+                    "Cannot use bridge ID file '{}': {}. Using a temporary ID.",
+                    path, e
+                );
+                generate_uuid().unwrap_or_default()
+            }
+        };
+        Self { bridge_id }
+    }
+
+    /// Gets the bridge ID
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The UUID of the bridge
+    pub fn get_bridge_id(&self) -> &str {
+        &self.bridge_id
+    }
+}
@@ -0,0 +1,309 @@
+use crate::hottoh::config::IgnitionMonitorConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::StoveState;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::startup::STARTUP_PHASES;
+use crate::hottoh::supervisor::supervise;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Ignitions kept in the history file
+const HISTORY_SIZE: usize = 100;
+/// Ignitions needed before the last ones, to learn the baseline
+const BASELINE_MIN: usize = 5;
+/// Ignitions before the last ones used for the baseline
+const BASELINE_SIZE: usize = 20;
+/// Typical duration under which a phase is not compared, as the polling makes it noisy
+const MIN_PHASE_SECS: f64 = 30.0;
+
+/// Ignition observed from its beginning to the Power state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct IgnitionRecord {
+    /// When the stove reached the Power state, in RFC 3339
+    finished_at: String,
+    /// Duration of the ignition, in seconds
+    duration_secs: u64,
+    /// Duration of each phase (Starting1 to Starting7), in seconds
+    phase_secs: Vec<u64>,
+    /// Rise of the smoke temperature during the ignition, in °C per minute
+    smoke_ramp: f64,
+}
+
+/// Ignition in progress
+struct Ignition {
+    started_at: Instant,
+    start_smoke_t: f32,
+    /// Phase in progress and when it was first seen
+    phase: (u8, Instant),
+    phase_secs: Vec<u64>,
+}
+
+impl Ignition {
+    /// Adds the time spent in the phase in progress to its duration
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - When the phase ended
+    fn close_phase(&mut self, now: Instant) {
+        let (number, since) = self.phase;
+        self.phase_secs[usize::from(number - 1)] += now.duration_since(since).as_secs();
+    }
+}
+
+/// Reads the ignition history
+///
+/// # Arguments
+///
+/// * `path` - Path of the history file
+///
+/// # Returns
+///
+/// * `io::Result<Vec<IgnitionRecord>>` - The ignitions, empty if the file does not exist yet
+fn load_history(path: &str) -> io::Result<Vec<IgnitionRecord>> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(io::Error::other),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Writes the ignition history
+///
+/// # Arguments
+///
+/// * `path` - Path of the history file
+/// * `history` - The ignitions
+///
+/// # Returns
+///
+/// * `io::Result<()>` - An error if the file cannot be written
+fn save_history(path: &str, history: &[IgnitionRecord]) -> io::Result<()> {
+    let content = serde_json::to_string_pretty(history).map_err(io::Error::other)?;
+    fs::write(path, content)
+}
+
+/// Computes the median of values
+///
+/// # Arguments
+///
+/// * `values` - The values
+///
+/// # Returns
+///
+/// * `f64` - The median, 0 if there is no value
+fn median(values: impl Iterator<Item = f64>) -> f64 {
+    let mut values: Vec<f64> = values.collect();
+    values.sort_by(f64::total_cmp);
+    match values.len() {
+        0 => 0.0,
+        len if len % 2 == 0 => (values[len / 2 - 1] + values[len / 2]) / 2.0,
+        len => values[len / 2],
+    }
+}
+
+/// Compares the last ignitions with the baseline learned from the previous ones
+///
+/// # Arguments
+///
+/// * `history` - The ignitions, oldest first
+/// * `slower_percent` - Slowdown from which an ignition is abnormal, in percent
+/// * `consecutive` - Number of last ignitions that must all be abnormal
+///
+/// # Returns
+///
+/// * `Option<String>` - The reason to suggest a maintenance, None if the ignitions are normal
+fn analyze(history: &[IgnitionRecord], slower_percent: u32, consecutive: usize) -> Option<String> {
+    if history.len() < consecutive + BASELINE_MIN {
+        return None;
+    }
+    let (baseline, recent) = history.split_at(history.len() - consecutive);
+    let baseline = &baseline[baseline.len().saturating_sub(BASELINE_SIZE)..];
+    let factor = 1.0 + f64::from(slower_percent) / 100.0;
+    let mut reasons = Vec::new();
+
+    let usual = median(baseline.iter().map(|record| record.duration_secs as f64));
+    if recent
+        .iter()
+        .all(|record| record.duration_secs as f64 > usual * factor)
+    {
+        reasons.push(format!(
+            "ignition took {:.0}s instead of {:.0}s",
+            median(recent.iter().map(|record| record.duration_secs as f64)),
+            usual
+        ));
+    }
+
+    for phase in 0..usize::from(STARTUP_PHASES) {
+        let duration = |record: &IgnitionRecord| {
+            record.phase_secs.get(phase).copied().unwrap_or_default() as f64
+        };
+        let usual = median(baseline.iter().map(duration));
+        if usual >= MIN_PHASE_SECS
+            && recent
+                .iter()
+                .all(|record| duration(record) > usual * factor)
+        {
+            reasons.push(format!(
+                "Starting{} took {:.0}s instead of {:.0}s",
+                phase + 1,
+                median(recent.iter().map(duration)),
+                usual
+            ));
+        }
+    }
+
+    let usual = median(baseline.iter().map(|record| record.smoke_ramp));
+    if usual > 0.0
+        && recent
+            .iter()
+            .all(|record| record.smoke_ramp * factor < usual)
+    {
+        reasons.push(format!(
+            "smoke temperature rose by {:.1}°C/min instead of {:.1}°C/min",
+            median(recent.iter().map(|record| record.smoke_ramp)),
+            usual
+        ));
+    }
+
+    (!reasons.is_empty()).then(|| {
+        format!(
+            "over the last {} ignition(s), {}",
+            consecutive,
+            reasons.join(", ")
+        )
+    })
+}
+
+/// Starts a thread learning the usual ignitions and detecting when they get slower
+///
+/// Each ignition seen from its beginning to the Power state is added to the history
+/// file. When the last ignitions all take significantly longer than the baseline
+/// learned from the previous ones, in total or in a phase, or the smoke temperature
+/// rises more slowly, a `maintenance_suggested` event is published, once until an
+/// ignition is normal again. It is an early sign of a dirty burn pot or a worn igniter.
+///
+/// # Arguments
+///
+/// * `config` - The `[ignition_monitor]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_ignition_monitor_thread(
+    config: Option<&IgnitionMonitorConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let path = config.history_file.clone();
+    let slower_percent = config.slower_percent;
+    let consecutive = config.consecutive.max(1);
+
+    Some(supervise(
+        "ignition_monitor",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let mut history = match load_history(&path) {
+                Ok(history) => history,
+                Err(e) => {
+                    error!(
+                        "Cannot read ignition history '{}': {}. Starting a new one.",
+                        path, e
+                    );
+                    Vec::new()
+                }
+            };
+            info!(
+                "Monitoring ignitions ({} in history {})",
+                history.len(),
+                path
+            );
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut ignition: Option<Ignition> = None;
+            let mut suggested = false;
+            while running.load(Ordering::SeqCst) {
+                let (from, to) = match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateTransition { from, to }) => (from, to),
+                    Ok(Event::ConnectionChanged { connected: false }) => {
+                        ignition = None;
+                        continue;
+                    }
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let now = Instant::now();
+                let smoke_t = || {
+                    read_lock(&shared_state, "shared state")
+                        .get_dat0()
+                        .get_index_smoke_t()
+                        .as_f32()
+                };
+
+                match (
+                    from.get_startup_phase(),
+                    to.get_startup_phase(),
+                    &mut ignition,
+                ) {
+                    (None, Some(number), _) => {
+                        ignition = Some(Ignition {
+                            started_at: now,
+                            start_smoke_t: smoke_t(),
+                            phase: (number, now),
+                            phase_secs: vec![0; usize::from(STARTUP_PHASES)],
+                        });
+                    }
+                    (Some(_), Some(number), Some(current)) => {
+                        current.close_phase(now);
+                        current.phase = (number, now);
+                    }
+                    (Some(_), None, Some(current)) if to == StoveState::Power => {
+                        current.close_phase(now);
+                        let duration = now.duration_since(current.started_at);
+                        let minutes = duration.as_secs_f64() / 60.0;
+                        let rise = f64::from(smoke_t() - current.start_smoke_t);
+                        history.push(IgnitionRecord {
+                            finished_at: chrono::Local::now().to_rfc3339(),
+                            duration_secs: duration.as_secs(),
+                            phase_secs: current.phase_secs.clone(),
+                            smoke_ramp: if minutes > 0.0 { rise / minutes } else { 0.0 },
+                        });
+                        ignition = None;
+                        if history.len() > HISTORY_SIZE {
+                            history.drain(..history.len() - HISTORY_SIZE);
+                        }
+                        if let Err(e) = save_history(&path, &history) {
+                            error!("Cannot write ignition history '{}': {}", path, e);
+                        }
+
+                        match analyze(&history, slower_percent, consecutive) {
+                            Some(reason) if !suggested => {
+                                warn!("Maintenance suggested: {}", reason);
+                                events.publish(Event::MaintenanceSuggested { reason });
+                                suggested = true;
+                            }
+                            Some(_) => {}
+                            None => suggested = false,
+                        }
+                    }
+                    _ => ignition = None,
+                }
+            }
+            info!("Ignition monitor thread stopped.");
+        },
+    ))
+}
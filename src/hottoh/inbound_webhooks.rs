@@ -0,0 +1,248 @@
+use crate::hottoh::config::InboundWebhooksConfig;
+use crate::hottoh::hottoh_const::StoveCommands;
+use hmac::{Hmac, KeyInit, Mac};
+use log::{error, info};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Named actions that can be triggered by signed inbound webhooks
+#[derive(Debug, Default)]
+pub struct InboundWebhooks {
+    /// Shared secret used to sign the webhook bodies
+    secret: String,
+    /// Commands to send for each action, as (command, value) pairs
+    actions: HashMap<String, Vec<(u32, i32)>>,
+}
+
+impl InboundWebhooks {
+    /// Builds the actions from the configuration
+    ///
+    /// Actions that cannot be parsed are logged and left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The inbound webhooks configuration, or None if the section is absent
+    ///
+    /// # Returns
+    ///
+    /// * `InboundWebhooks` - The configured actions (none if disabled)
+    pub fn from_config(config: Option<&InboundWebhooksConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        if config.secret.is_empty() {
+            error!("Inbound webhooks disabled: no secret configured");
+            return Self::default();
+        }
+
+        let mut actions = HashMap::new();
+        for (name, spec) in &config.actions {
+            match parse_action(spec) {
+                Ok(commands) => {
+                    actions.insert(name.clone(), commands);
+                }
+                Err(e) => error!("Ignoring inbound webhook action '{}': {}", name, e),
+            }
+        }
+        info!("{} inbound webhook action(s) configured", actions.len());
+
+        Self {
+            secret: config.secret.clone(),
+            actions,
+        }
+    }
+
+    /// Gets the commands of an action
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the action
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&[(u32, i32)]>` - The (command, value) pairs, or None if unknown
+    pub fn get_action(&self, name: &str) -> Option<&[(u32, i32)]> {
+        self.actions.get(name).map(Vec::as_slice)
+    }
+
+    /// Checks the signature of a webhook body
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The raw request body
+    /// * `signature` - The signature header, as `sha256=<hex HMAC of the body>`
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the signature matches, false otherwise
+    pub fn verify_signature(&self, body: &[u8], signature: &str) -> bool {
+        if self.secret.is_empty() {
+            return false;
+        }
+        let Some(received) = signature.strip_prefix("sha256=") else {
+            return false;
+        };
+        let expected = to_hex(&hmac_sha256(self.secret.as_bytes(), body));
+
+        // Constant-time comparison, to not leak how many characters match
+        received.len() == expected.len()
+            && received
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0, |diff, (a, b)| diff | (a.to_ascii_lowercase() ^ b))
+                == 0
+    }
+}
+
+/// Parses an action specification
+///
+/// The specification is a comma-separated list of `Command=value`, where `Command`
+/// is a StoveCommands name and `value` the raw value sent to the stove
+/// (e.g. `OnOff=1,PowerLevel=3`, or `AmbianceTemperature1=215` for 21.5°C).
+///
+/// # Arguments
+///
+/// * `spec` - The action specification
+///
+/// # Returns
+///
+/// * `Result<Vec<(u32, i32)>, String>` - The (command, value) pairs or an error
+pub fn parse_action(spec: &str) -> Result<Vec<(u32, i32)>, String> {
+    let commands = spec
+        .split(',')
+        .map(|item| {
+            let (command, value) = item
+                .trim()
+                .split_once('=')
+                .ok_or_else(|| format!("expected Command=value, got '{}'", item.trim()))?;
+            let command = StoveCommands::from_str(command.trim())
+                .map_err(|_| format!("unknown command '{}'", command.trim()))?;
+            let value = value
+                .trim()
+                .parse::<i32>()
+                .map_err(|_| format!("invalid value '{}'", value.trim()))?;
+            Ok((command as u32, value))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if commands.is_empty() {
+        return Err("no command".into());
+    }
+    Ok(commands)
+}
+
+/// Computes the HMAC-SHA256 of a message (RFC 2104)
+///
+/// # Arguments
+///
+/// * `key` - The secret key
+/// * `message` - The message to sign
+///
+/// # Returns
+///
+/// * `Vec<u8>` - The 32-byte MAC
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, hashing the ones longer than a block
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Encodes bytes as lowercase hexadecimal
+///
+/// # Arguments
+///
+/// * `bytes` - The bytes to encode
+///
+/// # Returns
+///
+/// * `String` - The hexadecimal string
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Decodes a hexadecimal string
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc4231_vectors() {
+        // Test cases 1 to 4, 6 and 7 of RFC 4231 (5 truncates the output)
+        let cases: [(Vec<u8>, Vec<u8>, &str); 6] = [
+            (
+                vec![0x0b; 20],
+                b"Hi There".to_vec(),
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+            (
+                vec![0xaa; 20],
+                vec![0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+            ),
+            (
+                (0x01..=0x19).collect(),
+                vec![0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+            ),
+            (
+                vec![0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First".to_vec(),
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+            ),
+            (
+                vec![0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than \
+                  block-size data. The key needs to be hashed before being used by the \
+                  HMAC algorithm."
+                    .to_vec(),
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+            ),
+        ];
+        for (key, message, expected) in cases {
+            assert_eq!(hmac_sha256(&key, &message), from_hex(expected));
+        }
+    }
+
+    #[test]
+    fn to_hex_is_lowercase() {
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xab, 0xff]), "000fabff");
+    }
+
+    #[test]
+    fn webhook_signature_is_verified() {
+        let webhooks = InboundWebhooks {
+            secret: "change-me".to_string(),
+            actions: HashMap::new(),
+        };
+        let body = b"{}";
+        let signature = format!("sha256={}", to_hex(&hmac_sha256(b"change-me", body)));
+        assert!(webhooks.verify_signature(body, &signature));
+        assert!(
+            webhooks.verify_signature(body, &signature.to_uppercase().replace("SHA256", "sha256"))
+        );
+        assert!(!webhooks.verify_signature(b"{ }", &signature));
+        assert!(!webhooks.verify_signature(body, signature.trim_start_matches("sha256=")));
+        assert!(!webhooks.verify_signature(body, &signature[..signature.len() - 2]));
+
+        let unsigned = InboundWebhooks {
+            secret: String::new(),
+            actions: HashMap::new(),
+        };
+        let signature = format!("sha256={}", to_hex(&hmac_sha256(b"", body)));
+        assert!(!unsigned.verify_signature(body, &signature));
+    }
+}
@@ -0,0 +1,446 @@
+use crate::hottoh::hottoh_const::{Command, CommandType};
+use crate::hottoh::hottoh_structs::CommandData;
+use crate::hottoh::install::{current_binary, resolve_config, systemd_unit};
+use crate::hottoh::layouts::FirmwareInfo;
+use crate::hottoh::tcp_client_structs::{Request, Response};
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream, UdpSocket};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// Default TCP port of the stove
+const DEFAULT_STOVE_PORT: u16 = 5001;
+/// Default port of the HTTP API
+const DEFAULT_HTTP_PORT: u16 = 3000;
+/// Default path of the written configuration
+const DEFAULT_OUTPUT: &str = "config.ini";
+/// Path where the systemd unit is installed
+const SYSTEMD_UNIT_PATH: &str = "/etc/systemd/system/hottoh_api.service";
+/// Timeout of a connection attempt while scanning the network
+const SCAN_TIMEOUT: Duration = Duration::from_millis(300);
+/// Timeout of the connection test
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Usage of the init subcommand
+const USAGE: &str = "Usage: hottoh_api init [options]
+
+Discovers the stove, tests the connection and writes a configuration file.
+Missing values are asked for when run from a terminal.
+
+Options:
+  --stove-ip <ip>      IP address of the stove (scans the local network if absent)
+  --stove-port <port>  TCP port of the stove (default 5001)
+  --http-port <port>   Port of the HTTP API (default 3000)
+  --output <path>      Configuration file to write (default config.ini)
+  --force              Overwrite an existing configuration file
+  --no-test            Do not test the connection to the stove
+  --systemd            Install a systemd unit running the bridge with this configuration
+  --help               Show this help";
+
+/// Options of the init subcommand
+#[derive(Debug)]
+struct InitOptions {
+    stove_ip: Option<String>,
+    stove_port: u16,
+    http_port: u16,
+    output: String,
+    force: bool,
+    test: bool,
+    systemd: bool,
+}
+
+/// Identity of a stove answering the connection test
+struct StoveIdentity {
+    hostname: String,
+    version: String,
+}
+
+/// Runs the first-run setup: `hottoh_api init [options]`
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `init`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error message
+pub fn run_init(args: &[String]) -> Result<(), String> {
+    let Some(options) = parse_options(args)? else {
+        println!("{}", USAGE);
+        return Ok(());
+    };
+    let interactive = io::stdin().is_terminal();
+
+    let output = Path::new(&options.output);
+    if output.exists() && !options.force {
+        if !interactive {
+            return Err(format!(
+                "{} already exists (use --force to overwrite it)",
+                output.display()
+            ));
+        }
+        if !confirm(&format!(
+            "{} already exists. Overwrite it?",
+            output.display()
+        ))? {
+            return Err("Aborted".into());
+        }
+    }
+
+    let stove_ip = match options.stove_ip.clone() {
+        Some(ip) => ip,
+        None => choose_stove(options.stove_port, interactive)?,
+    };
+    let stove_ip: IpAddr = stove_ip
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid stove IP address '{}'", stove_ip.trim()))?;
+    let stove_address = SocketAddr::new(stove_ip, options.stove_port);
+
+    if options.test {
+        println!("Testing the connection to {}...", stove_address);
+        match probe_stove(&stove_address) {
+            Ok(identity) => println!(
+                "Connected to stove '{}' (firmware {})",
+                identity.hostname, identity.version
+            ),
+            Err(e) => {
+                println!("Connection test failed: {}", e);
+                if !interactive {
+                    return Err("Connection test failed (use --no-test to skip it)".into());
+                }
+                if !confirm("Write the configuration anyway?")? {
+                    return Err("Aborted".into());
+                }
+            }
+        }
+    }
+
+    fs::write(
+        output,
+        render_config(&stove_ip.to_string(), options.stove_port, options.http_port),
+    )
+    .map_err(|e| format!("Cannot write {}: {}", output.display(), e))?;
+    println!("Configuration written to {}", output.display());
+
+    if options.systemd {
+        install_systemd_unit(output)?;
+    } else {
+        println!("Start the bridge with: hottoh_api {}", output.display());
+    }
+    Ok(())
+}
+
+/// Parses the options of the init subcommand
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `init`
+///
+/// # Returns
+///
+/// * `Result<Option<InitOptions>, String>` - The options, None if help was requested, or an error
+fn parse_options(args: &[String]) -> Result<Option<InitOptions>, String> {
+    let mut options = InitOptions {
+        stove_ip: None,
+        stove_port: DEFAULT_STOVE_PORT,
+        http_port: DEFAULT_HTTP_PORT,
+        output: DEFAULT_OUTPUT.to_string(),
+        force: false,
+        test: true,
+        systemd: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}\n\n{}", name, USAGE))
+        };
+        match arg.as_str() {
+            "--stove-ip" => options.stove_ip = Some(value(arg)?),
+            "--stove-port" => options.stove_port = parse_port(arg, &value(arg)?)?,
+            "--http-port" => options.http_port = parse_port(arg, &value(arg)?)?,
+            "--output" => options.output = value(arg)?,
+            "--force" => options.force = true,
+            "--no-test" => options.test = false,
+            "--systemd" => options.systemd = true,
+            "--help" | "-h" => return Ok(None),
+            other => return Err(format!("Unknown option '{}'\n\n{}", other, USAGE)),
+        }
+    }
+    Ok(Some(options))
+}
+
+/// Parses a port number
+///
+/// # Arguments
+///
+/// * `name` - Name of the option, for the error message
+/// * `value` - The value to parse
+///
+/// # Returns
+///
+/// * `Result<u16, String>` - The port, or an error message
+fn parse_port(name: &str, value: &str) -> Result<u16, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|port| *port != 0)
+        .ok_or_else(|| format!("Invalid port '{}' for {}", value, name))
+}
+
+/// Looks for stoves on the local network and lets the user pick one
+///
+/// # Arguments
+///
+/// * `port` - TCP port of the stove
+/// * `interactive` - Whether the user can be asked
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The IP address of the stove, or an error message
+fn choose_stove(port: u16, interactive: bool) -> Result<String, String> {
+    let found = match local_ipv4() {
+        Some(local) => {
+            println!(
+                "Scanning {}.0/24 for devices listening on port {}...",
+                subnet_prefix(local),
+                port
+            );
+            scan_subnet(local, port)
+        }
+        None => {
+            println!("Cannot determine the local network, skipping discovery.");
+            Vec::new()
+        }
+    };
+    match found.as_slice() {
+        [] => println!("No device found."),
+        found => {
+            for ip in found {
+                println!("  found {}", ip);
+            }
+        }
+    }
+
+    if !interactive {
+        return match found.as_slice() {
+            [ip] => Ok(ip.to_string()),
+            [] => Err("No stove found, use --stove-ip".into()),
+            _ => Err("Several devices found, use --stove-ip to choose one".into()),
+        };
+    }
+    let default = found.first().map(Ipv4Addr::to_string);
+    let answer = prompt("Stove IP address", default.as_deref())?;
+    if answer.is_empty() {
+        return Err("A stove IP address is required".into());
+    }
+    Ok(answer)
+}
+
+/// Finds the IPv4 address of the interface used to reach other hosts
+///
+/// Connecting a UDP socket selects a route without sending any packet.
+///
+/// # Returns
+///
+/// * `Option<Ipv4Addr>` - The local address, or None if there is no route
+fn local_ipv4() -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    socket.connect((Ipv4Addr::new(192, 0, 2, 1), 9)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) if !ip.is_loopback() && !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+/// Formats the first three octets of an address
+///
+/// # Arguments
+///
+/// * `ip` - The address
+///
+/// # Returns
+///
+/// * `String` - The /24 prefix (e.g. 192.168.1)
+fn subnet_prefix(ip: Ipv4Addr) -> String {
+    let [a, b, c, _] = ip.octets();
+    format!("{}.{}.{}", a, b, c)
+}
+
+/// Scans the /24 subnet of an address for hosts accepting connections on a port
+///
+/// # Arguments
+///
+/// * `local` - The local address, which is skipped
+/// * `port` - The TCP port to try
+///
+/// # Returns
+///
+/// * `Vec<Ipv4Addr>` - The hosts that accepted the connection, in address order
+fn scan_subnet(local: Ipv4Addr, port: u16) -> Vec<Ipv4Addr> {
+    let [a, b, c, _] = local.octets();
+    thread::scope(|scope| {
+        let probes: Vec<_> = (1..=254)
+            .map(|d| Ipv4Addr::new(a, b, c, d))
+            .filter(|ip| *ip != local)
+            .map(|ip| {
+                scope.spawn(move || {
+                    TcpStream::connect_timeout(&SocketAddr::new(IpAddr::V4(ip), port), SCAN_TIMEOUT)
+                        .is_ok()
+                        .then_some(ip)
+                })
+            })
+            .collect();
+        probes
+            .into_iter()
+            .filter_map(|probe| probe.join().ok().flatten())
+            .collect()
+    })
+}
+
+/// Tests the connection by requesting the INF page of the stove
+///
+/// # Arguments
+///
+/// * `address` - Address of the stove
+///
+/// # Returns
+///
+/// * `Result<StoveIdentity, String>` - The identity of the stove, or an error message
+fn probe_stove(address: &SocketAddr) -> Result<StoveIdentity, String> {
+    let mut stream =
+        TcpStream::connect_timeout(address, PROBE_TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(PROBE_TIMEOUT))
+        .map_err(|e| e.to_string())?;
+    let request = Request::new(0, Command::Inf, CommandType::Read, vec![]);
+    stream
+        .write_all(&request.build_message())
+        .map_err(|e| e.to_string())?;
+
+    let mut received = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let size = stream
+            .read(&mut buffer)
+            .map_err(|e| format!("no answer from the stove ({})", e))?;
+        if size == 0 {
+            return Err("connection closed by the stove".into());
+        }
+        received.extend_from_slice(&buffer[..size]);
+        let data = String::from_utf8_lossy(&received);
+        for frame in data.split_inclusive('\n').filter(|f| f.ends_with('\n')) {
+            let Some(frame) = frame.find('#').map(|start| &frame[start..]) else {
+                continue;
+            };
+            if let Ok(response) = Response::from_message(frame, FirmwareInfo::default().layout()) {
+                if let Some(CommandData::Inf(inf)) = response.get_command_data() {
+                    return Ok(StoveIdentity {
+                        hostname: inf.get_hostname().to_string(),
+                        version: inf.get_version().to_string(),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Renders the configuration file written by init
+///
+/// # Arguments
+///
+/// * `stove_ip` - IP address of the stove
+/// * `stove_port` - TCP port of the stove
+/// * `http_port` - Port of the HTTP API
+///
+/// # Returns
+///
+/// * `String` - The INI configuration
+fn render_config(stove_ip: &str, stove_port: u16, http_port: u16) -> String {
+    format!(
+        "[stove]
+# IP address and TCP port of the stove
+ip = {stove_ip}
+port = {stove_port}
+
+[http_api]
+# Address and port the HTTP API listens on
+ip = 0.0.0.0
+port = {http_port}
+
+[log]
+# Log level (trace, debug, info, warn, error)
+level = info
+# Directory where log files are stored
+directory = logs
+# Maximum number of log files to keep
+max_log_files = 7
+"
+    )
+}
+
+/// Installs and describes a systemd unit running the bridge
+///
+/// # Arguments
+///
+/// * `config` - Path of the configuration file
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error message
+fn install_systemd_unit(config: &Path) -> Result<(), String> {
+    let unit = systemd_unit(&current_binary()?, &resolve_config(config)?);
+    fs::write(SYSTEMD_UNIT_PATH, unit)
+        .map_err(|e| format!("Cannot write {} (run as root?): {}", SYSTEMD_UNIT_PATH, e))?;
+    println!("systemd unit written to {}", SYSTEMD_UNIT_PATH);
+    println!(
+        "Enable and start it with: systemctl daemon-reload && systemctl enable --now hottoh_api"
+    );
+    Ok(())
+}
+
+/// Asks the user for a value
+///
+/// # Arguments
+///
+/// * `question` - The question
+/// * `default` - Value used when the answer is empty
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The answer, or an error message if stdin cannot be read
+fn prompt(question: &str, default: Option<&str>) -> Result<String, String> {
+    match default {
+        Some(default) => print!("{} [{}]: ", question, default),
+        None => print!("{}: ", question),
+    }
+    io::stdout().flush().map_err(|e| e.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    let answer = answer.trim();
+    Ok(match (answer.is_empty(), default) {
+        (true, Some(default)) => default.to_string(),
+        _ => answer.to_string(),
+    })
+}
+
+/// Asks the user a yes/no question, defaulting to no
+///
+/// # Arguments
+///
+/// * `question` - The question
+///
+/// # Returns
+///
+/// * `Result<bool, String>` - Whether the user answered yes, or an error message
+fn confirm(question: &str) -> Result<bool, String> {
+    let answer = prompt(&format!("{} [y/N]", question), None)?;
+    Ok(matches!(answer.to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Default path of the configuration file
+const DEFAULT_CONFIG: &str = "config.ini";
+/// Directory where the configuration directory is mounted in the container
+const CONTAINER_CONFIG_DIR: &str = "/config";
+/// Path of the binary in the container
+const CONTAINER_BINARY: &str = "/usr/local/bin/hottoh_api";
+
+/// Usage of the install subcommand
+const USAGE: &str = "Usage: hottoh_api install (--systemd | --docker-compose) [--config <path>]
+
+Prints a service definition running this binary with the given configuration.
+
+Options:
+  --systemd         Print a systemd unit (save it as /etc/systemd/system/hottoh_api.service)
+  --docker-compose  Print a Docker compose service (save it as compose.yaml)
+  --config <path>   Configuration file used by the service (default config.ini)
+  --help            Show this help";
+
+/// Kind of service definition to generate
+#[derive(Debug, Clone, Copy)]
+enum Target {
+    Systemd,
+    DockerCompose,
+}
+
+/// Runs `hottoh_api install (--systemd | --docker-compose) [--config <path>]`
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `install`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error message
+pub fn run_install(args: &[String]) -> Result<(), String> {
+    let mut target = None;
+    let mut config = DEFAULT_CONFIG.to_string();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--systemd" => target = Some(Target::Systemd),
+            "--docker-compose" => target = Some(Target::DockerCompose),
+            "--config" => {
+                config = args
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| format!("Missing value for --config\n\n{}", USAGE))?
+            }
+            "--help" | "-h" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            other => return Err(format!("Unknown option '{}'\n\n{}", other, USAGE)),
+        }
+    }
+    let target =
+        target.ok_or_else(|| format!("Missing --systemd or --docker-compose\n\n{}", USAGE))?;
+
+    let binary = current_binary()?;
+    let config = resolve_config(Path::new(&config))?;
+    match target {
+        Target::Systemd => print!("{}", systemd_unit(&binary, &config)),
+        Target::DockerCompose => print!("{}", docker_compose_service(&binary, &config)),
+    }
+    Ok(())
+}
+
+/// Locates the running binary
+///
+/// # Returns
+///
+/// * `Result<PathBuf, String>` - The absolute path of the binary, or an error message
+pub fn current_binary() -> Result<PathBuf, String> {
+    std::env::current_exe().map_err(|e| format!("Cannot locate the hottoh_api binary: {}", e))
+}
+
+/// Resolves the absolute path of the configuration file
+///
+/// # Arguments
+///
+/// * `config` - Path of the configuration file
+///
+/// # Returns
+///
+/// * `Result<PathBuf, String>` - The absolute path, or an error message if it does not exist
+pub fn resolve_config(config: &Path) -> Result<PathBuf, String> {
+    fs::canonicalize(config).map_err(|e| format!("Cannot resolve {}: {}", config.display(), e))
+}
+
+/// Generates a systemd unit running the bridge
+///
+/// The working directory is the one of the configuration, so that a relative log
+/// directory ends up next to it.
+///
+/// # Arguments
+///
+/// * `binary` - Absolute path of the binary
+/// * `config` - Absolute path of the configuration file
+///
+/// # Returns
+///
+/// * `String` - The unit file
+pub fn systemd_unit(binary: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]
+Description=HottoH stove bridge
+After=network-online.target
+Wants=network-online.target
+
+[Service]
+ExecStart={} {}
+WorkingDirectory={}
+Restart=on-failure
+RestartSec=5
+
+[Install]
+WantedBy=multi-user.target
+",
+        binary.display(),
+        config.display(),
+        config.parent().unwrap_or(Path::new("/")).display()
+    )
+}
+
+/// Generates a Docker compose service running the bridge
+///
+/// The binary is mounted in a slim Debian image and the directory of the
+/// configuration is mounted as working directory, so that logs are kept on the host.
+/// Host networking lets the container reach the stove on the local network.
+///
+/// # Arguments
+///
+/// * `binary` - Absolute path of the binary
+/// * `config` - Absolute path of the configuration file
+///
+/// # Returns
+///
+/// * `String` - The compose file
+fn docker_compose_service(binary: &Path, config: &Path) -> String {
+    let config_dir = config.parent().unwrap_or(Path::new("/"));
+    let config_name = config
+        .file_name()
+        .map_or_else(|| DEFAULT_CONFIG.into(), |name| name.to_string_lossy());
+    format!(
+        "services:
+  hottoh_api:
+    image: debian:bookworm-slim
+    container_name: hottoh_api
+    restart: unless-stopped
+    network_mode: host
+    working_dir: {container_dir}
+    command: [\"{container_binary}\", \"{container_dir}/{config_name}\"]
+    volumes:
+      - {binary}:{container_binary}:ro
+      - {config_dir}:{container_dir}
+",
+        container_dir = CONTAINER_CONFIG_DIR,
+        container_binary = CONTAINER_BINARY,
+        config_name = config_name,
+        binary = binary.display(),
+        config_dir = config_dir.display(),
+    )
+}
@@ -0,0 +1,147 @@
+use crate::hottoh::config::JeedomConfig;
+use crate::hottoh::http_client;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field_value, parse_field_mapping, url_encode};
+use crate::hottoh::supervisor::supervise;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Home automation box receiving the values
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Platform {
+    /// Jeedom, through the virtual plugin API
+    Jeedom,
+    /// eedomus, through the local box API
+    Eedomus,
+}
+
+/// Starts a thread pushing state values to Jeedom or eedomus virtual devices
+///
+/// Each configured field is sent to its virtual command (Jeedom) or peripheral
+/// (eedomus) when its value changes. Values that fail to be sent are retried at the
+/// next interval.
+///
+/// # Arguments
+///
+/// * `config` - The Jeedom configuration, or None if the section is absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_jeedom_thread(
+    config: Option<&JeedomConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let platform = match config.platform.as_str() {
+        "jeedom" => Platform::Jeedom,
+        "eedomus" => Platform::Eedomus,
+        other => {
+            error!("Jeedom push disabled: unknown platform '{}'", other);
+            return None;
+        }
+    };
+    let fields = match parse_field_mapping(&config.fields) {
+        Ok(fields) if !fields.is_empty() => fields,
+        Ok(_) => {
+            error!("Jeedom push disabled: no field configured");
+            return None;
+        }
+        Err(e) => {
+            error!("Jeedom push disabled: invalid fields: {}", e);
+            return None;
+        }
+    };
+    let base_url = config.url.trim_end_matches('/').to_string();
+    let credentials = match platform {
+        Platform::Jeedom => format!("apikey={}", url_encode(&config.apikey)),
+        Platform::Eedomus => format!(
+            "api_user={}&api_secret={}",
+            url_encode(&config.api_user),
+            url_encode(&config.api_secret)
+        ),
+    };
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    info!(
+        "Pushing {} field(s) to {:?} at {}",
+        fields.len(),
+        platform,
+        base_url
+    );
+
+    Some(supervise(
+        "jeedom",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let mut last_sent: HashMap<String, String> = HashMap::new();
+            let mut last_push = Instant::now() - interval;
+            while running.load(Ordering::SeqCst) {
+                if last_push.elapsed() < interval {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                last_push = Instant::now();
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for Jeedom: {}", e);
+                        continue;
+                    }
+                };
+
+                for (field, target) in &fields {
+                    let Some(value) = get_field_value(&state, field) else {
+                        continue;
+                    };
+                    if last_sent.get(field) == Some(&value) {
+                        continue;
+                    }
+                    let url = match platform {
+                        Platform::Jeedom => format!(
+                            "{}/core/api/jeeApi.php?plugin=virtual&type=virtual&{}&id={}&value={}",
+                            base_url,
+                            credentials,
+                            url_encode(target),
+                            url_encode(&value)
+                        ),
+                        Platform::Eedomus => format!(
+                            "{}/api/set?action=periph.value&{}&periph_id={}&value={}",
+                            base_url,
+                            credentials,
+                            url_encode(target),
+                            url_encode(&value)
+                        ),
+                    };
+                    match http_client::send("GET", &url, &[], "") {
+                        Ok(response) if response.is_success() => {
+                            debug!("Pushed {}={} to {:?} {}", field, value, platform, target);
+                            last_sent.insert(field.clone(), value);
+                        }
+                        Ok(response) => warn!(
+                            "{:?} rejected {}={} (HTTP {}): {}",
+                            platform, field, value, response.status, response.body
+                        ),
+                        Err(e) => {
+                            warn!("Cannot push {} to {:?}: {}", field, platform, e);
+                            break;
+                        }
+                    }
+                }
+            }
+            info!("Jeedom push thread stopped.");
+        },
+    ))
+}
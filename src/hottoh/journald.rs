@@ -0,0 +1,154 @@
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+use log::{Level, Record};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+#[cfg(unix)]
+use std::path::Path;
+
+/// Socket of the native journal protocol
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+/// Identifier of the log entries in the journal
+const SYSLOG_IDENTIFIER: &str = "hottoh_api";
+
+/// Log writer sending records to the systemd journal
+///
+/// Uses the native journal protocol, so entries keep their priority (shown and
+/// filtered by `journalctl -p`) and are not written to files on the SD card. The
+/// journal only exists on Unix; elsewhere the writer cannot be created.
+pub struct JournaldWriter {
+    #[cfg(unix)]
+    socket: UnixDatagram,
+}
+
+impl JournaldWriter {
+    /// Creates a writer connected to the journal socket
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<JournaldWriter>` - The writer, or an error if the journal is not available
+    #[cfg(unix)]
+    pub fn new() -> std::io::Result<Self> {
+        let socket = UnixDatagram::unbound()?;
+        socket.connect(Path::new(JOURNAL_SOCKET))?;
+        Ok(Self { socket })
+    }
+
+    /// Creates a writer connected to the journal socket
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<JournaldWriter>` - Always an `Unsupported` error, the journal
+    ///   being only available on Unix
+    #[cfg(not(unix))]
+    pub fn new() -> std::io::Result<Self> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "the systemd journal is only available on Unix",
+        ))
+    }
+
+    /// Sends an entry to the journal
+    ///
+    /// # Arguments
+    ///
+    /// * `entry` - The entry, in the native journal protocol
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success or IO error
+    #[cfg(unix)]
+    fn send(&self, entry: &[u8]) -> std::io::Result<()> {
+        self.socket.send(entry).map(|_| ())
+    }
+
+    /// Sends an entry to the journal
+    ///
+    /// # Arguments
+    ///
+    /// * `_entry` - The entry, in the native journal protocol
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Always an `Unsupported` error
+    #[cfg(not(unix))]
+    fn send(&self, _entry: &[u8]) -> std::io::Result<()> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl LogWriter for JournaldWriter {
+    /// Sends a record to the journal
+    ///
+    /// # Arguments
+    ///
+    /// * `_now` - Timestamp of the record (the journal records its own)
+    /// * `record` - The log record
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success or IO error
+    fn write(&self, _now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        let mut entry = Vec::new();
+        append_field(&mut entry, "PRIORITY", priority(record.level()));
+        append_field(&mut entry, "SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER);
+        append_field(&mut entry, "TARGET", record.target());
+        if let Some(file) = record.file() {
+            append_field(&mut entry, "CODE_FILE", file);
+        }
+        if let Some(line) = record.line() {
+            append_field(&mut entry, "CODE_LINE", &line.to_string());
+        }
+        append_field(&mut entry, "MESSAGE", &record.args().to_string());
+        self.send(&entry)
+    }
+
+    /// Nothing is buffered, entries are sent as they are written
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Always successful
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Maps a log level to a syslog priority
+///
+/// # Arguments
+///
+/// * `level` - The log level
+///
+/// # Returns
+///
+/// * `&'static str` - The priority (3 error, 4 warning, 6 info, 7 debug)
+fn priority(level: Level) -> &'static str {
+    match level {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    }
+}
+
+/// Appends a field to a journal entry
+///
+/// Values containing a newline use the binary form of the protocol
+/// (name, newline, little-endian 64-bit length, value).
+///
+/// # Arguments
+///
+/// * `entry` - The entry being built
+/// * `name` - Name of the field
+/// * `value` - Value of the field
+fn append_field(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
@@ -0,0 +1,724 @@
+use crate::hottoh::api_keys::Role;
+use crate::hottoh::config::AuthConfig;
+use crate::hottoh::http_client;
+use crate::hottoh::inbound_webhooks::hmac_sha256;
+use crate::hottoh::locks::mutex_lock;
+use log::{debug, info, warn};
+use rsa::sha2::{Digest, Sha256};
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use serde_json::Value;
+use std::fmt;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Shortest HS256 secret accepted, in bytes
+const MIN_SECRET_LEN: usize = 32;
+/// Clock difference tolerated on `exp` and `nbf`, in seconds
+const LEEWAY_SECS: i64 = 60;
+/// Interval after which the key set is fetched again
+const JWKS_REFRESH: Duration = Duration::from_secs(3600);
+/// Shortest interval between two fetches of the key set, e.g. for tokens with an unknown key ID
+const JWKS_MIN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Source of the key set of the RS256 tokens
+#[derive(Debug, Clone, PartialEq)]
+enum KeySource {
+    /// https:// URL published by the identity provider
+    Url(String),
+    /// Local file pinned by the administrator
+    File(String),
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::Url(url) => write!(f, "{}", url),
+            KeySource::File(path) => write!(f, "{}", path),
+        }
+    }
+}
+
+/// Keys fetched from the JWKS URL or file
+#[derive(Default)]
+struct KeySet {
+    /// RSA keys with their key ID
+    keys: Vec<(Option<String>, RsaPublicKey)>,
+    /// When the keys were last fetched, successfully or not
+    fetched_at: Option<Instant>,
+}
+
+/// Validation of the JSON Web Tokens sent as `Authorization: Bearer`
+///
+/// Tokens are signed with HS256 and the shared `auth.jwt_secret`, or with RS256 and a
+/// key of the set published at `auth.jwks_url` by an identity provider, or pinned in
+/// `auth.jwks_file`. Their role comes from the `auth.jwt_role_claim` claim.
+pub struct JwtAuth {
+    /// Shared secret of the HS256 tokens
+    secret: Option<Vec<u8>>,
+    /// Source of the key set of the RS256 tokens
+    jwks: Option<KeySource>,
+    /// Expected `iss` claim, if any
+    issuer: Option<String>,
+    /// Expected `aud` claim, if any
+    audience: Option<String>,
+    /// Claim holding the roles
+    role_claim: String,
+    /// Claim values granting the control role
+    control_roles: Vec<String>,
+    /// Claim values granting the read-only role
+    read_roles: Vec<String>,
+    /// Keys fetched from the JWKS URL or file
+    key_set: Mutex<KeySet>,
+}
+
+/// Splits a comma-separated list, ignoring empty items
+///
+/// # Arguments
+///
+/// * `list` - The list
+///
+/// # Returns
+///
+/// * `Vec<String>` - The trimmed items
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+impl JwtAuth {
+    /// Creates the validation from the configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[auth]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<JwtAuth>, String>` - The validation, None if none of
+    ///   `jwt_secret`, `jwks_url` and `jwks_file` is set, or an error if the settings are
+    ///   invalid
+    pub fn from_config(config: &AuthConfig) -> Result<Option<Self>, String> {
+        let secret = config.jwt_secret.as_deref().map(str::trim);
+        let jwks = match (
+            config.jwks_url.as_deref().map(str::trim),
+            config.jwks_file.as_deref().map(str::trim),
+        ) {
+            (Some(_), Some(_)) => return Err("jwks_url and jwks_file are exclusive".into()),
+            // Keys fetched in plain text could be replaced by anyone on the path
+            (Some(url), None) if !http_client::is_https(url) => {
+                return Err(format!("jwks_url '{}' must be an https:// URL", url))
+            }
+            (Some(url), None) => Some(KeySource::Url(url.to_string())),
+            (None, Some(path)) => Some(KeySource::File(path.to_string())),
+            (None, None) => None,
+        };
+        if secret.is_none() && jwks.is_none() {
+            return Ok(None);
+        }
+        if let Some(secret) = secret.filter(|secret| secret.len() < MIN_SECRET_LEN) {
+            return Err(format!(
+                "jwt_secret must have at least {} characters, got {}",
+                MIN_SECRET_LEN,
+                secret.len()
+            ));
+        }
+        let control_roles = split_list(&config.jwt_control_roles);
+        let read_roles = split_list(&config.jwt_read_roles);
+        if control_roles.is_empty() && read_roles.is_empty() {
+            return Err("jwt_control_roles or jwt_read_roles is required".into());
+        }
+        Ok(Some(Self {
+            secret: secret.map(|secret| secret.as_bytes().to_vec()),
+            jwks,
+            issuer: config
+                .jwt_issuer
+                .clone()
+                .filter(|issuer| !issuer.is_empty()),
+            audience: config
+                .jwt_audience
+                .clone()
+                .filter(|audience| !audience.is_empty()),
+            role_claim: config.jwt_role_claim.trim().to_string(),
+            control_roles,
+            read_roles,
+            key_set: Mutex::new(KeySet::default()),
+        }))
+    }
+
+    /// Checks if a credential looks like a JSON Web Token rather than an API key
+    ///
+    /// # Arguments
+    ///
+    /// * `credential` - The credential received
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if it has the three parts of a token
+    pub fn is_token(credential: &str) -> bool {
+        credential.split('.').count() == 3
+    }
+
+    /// Validates a token
+    ///
+    /// Checks the signature, the `exp` claim (required), the `nbf` claim and the
+    /// configured issuer and audience, then maps the role claim to a role. May block
+    /// while the key set is fetched.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(String, Role), String>` - The subject of the token and its role, or
+    ///   the reason why it is rejected
+    pub fn validate(&self, token: &str) -> Result<(String, Role), String> {
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed token".into());
+        };
+        let decode_json = |part: &str| {
+            base64url_decode(part)
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+                .ok_or_else(|| "malformed token".to_string())
+        };
+        let header_json = decode_json(header)?;
+        let claims = decode_json(payload)?;
+        let signature = base64url_decode(signature).ok_or("malformed token")?;
+        let signing_input = &token[..header.len() + 1 + payload.len()];
+
+        match header_json.get("alg").and_then(Value::as_str) {
+            Some("HS256") => {
+                let secret = self
+                    .secret
+                    .as_ref()
+                    .ok_or("HS256 tokens are not accepted")?;
+                let expected = hmac_sha256(secret, signing_input.as_bytes());
+                let matches = expected.len() == signature.len()
+                    && expected
+                        .iter()
+                        .zip(&signature)
+                        .fold(0, |diff, (a, b)| diff | (a ^ b))
+                        == 0;
+                if !matches {
+                    return Err("invalid signature".into());
+                }
+            }
+            Some("RS256") => {
+                let kid = header_json.get("kid").and_then(Value::as_str);
+                let key = self.find_key(kid)?;
+                let hashed = Sha256::digest(signing_input.as_bytes());
+                key.verify(Pkcs1v15Sign::new::<Sha256>(), &hashed, &signature)
+                    .map_err(|_| "invalid signature".to_string())?;
+            }
+            Some(alg) => return Err(format!("unsupported algorithm '{}'", alg)),
+            None => return Err("missing algorithm".into()),
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let exp = claims
+            .get("exp")
+            .and_then(Value::as_i64)
+            .ok_or("missing exp claim")?;
+        if now > exp + LEEWAY_SECS {
+            return Err("token expired".into());
+        }
+        if let Some(nbf) = claims.get("nbf").and_then(Value::as_i64) {
+            if now + LEEWAY_SECS < nbf {
+                return Err("token not valid yet".into());
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            if claims.get("iss").and_then(Value::as_str) != Some(issuer.as_str()) {
+                return Err("unexpected issuer".into());
+            }
+        }
+        if let Some(audience) = &self.audience {
+            let matches = match claims.get("aud") {
+                Some(Value::String(aud)) => aud == audience,
+                Some(Value::Array(auds)) => auds.iter().any(|aud| aud.as_str() == Some(audience)),
+                _ => false,
+            };
+            if !matches {
+                return Err("unexpected audience".into());
+            }
+        }
+
+        let values: Vec<&str> = match claims.get(&self.role_claim) {
+            // Space-separated, like the OAuth `scope` claim
+            Some(Value::String(roles)) => roles.split_whitespace().collect(),
+            Some(Value::Array(roles)) => roles.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        let has = |roles: &[String]| {
+            values
+                .iter()
+                .any(|value| roles.iter().any(|role| role == value))
+        };
+        let role = if has(&self.control_roles) {
+            Role::Control
+        } else if has(&self.read_roles) {
+            Role::Read
+        } else {
+            return Err(format!(
+                "no role granted by the '{}' claim",
+                self.role_claim
+            ));
+        };
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown")
+            .to_string();
+        Ok((subject, role))
+    }
+
+    /// Finds the RSA key of a token, fetching the key set when needed
+    ///
+    /// The key set is fetched without holding its lock, so that a slow identity
+    /// provider does not block the validation of the tokens whose key is known.
+    ///
+    /// # Arguments
+    ///
+    /// * `kid` - Key ID of the token header, if any
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RsaPublicKey, String>` - The key, or an error if it is unknown
+    fn find_key(&self, kid: Option<&str>) -> Result<RsaPublicKey, String> {
+        let source = self.jwks.as_ref().ok_or("RS256 tokens are not accepted")?;
+        let find = |key_set: &KeySet| {
+            key_set
+                .keys
+                .iter()
+                .find(|(key_id, _)| kid.is_none() || key_id.as_deref() == kid)
+                .map(|(_, key)| key.clone())
+        };
+        let key = {
+            let mut key_set = mutex_lock(&self.key_set, "JWKS");
+            let stale = key_set
+                .fetched_at
+                .is_none_or(|fetched_at| fetched_at.elapsed() >= JWKS_REFRESH);
+            let key = find(&key_set);
+            let may_fetch = key_set
+                .fetched_at
+                .is_none_or(|fetched_at| fetched_at.elapsed() >= JWKS_MIN_INTERVAL);
+            if !(stale || key.is_none()) || !may_fetch {
+                return key.ok_or_else(|| "unknown signing key".to_string());
+            }
+            // Claimed before fetching, so that concurrent requests do not fetch it too
+            key_set.fetched_at = Some(Instant::now());
+            key
+        };
+        match fetch_key_set(source) {
+            Ok(keys) => {
+                info!("Fetched {} key(s) from {}", keys.len(), source);
+                let mut key_set = mutex_lock(&self.key_set, "JWKS");
+                key_set.keys = keys;
+                find(&key_set).ok_or_else(|| "unknown signing key".to_string())
+            }
+            Err(e) => {
+                warn!("Cannot fetch the JWKS from {}: {}", source, e);
+                key.ok_or_else(|| "unknown signing key".to_string())
+            }
+        }
+    }
+}
+
+/// Fetches the RSA keys of a JSON Web Key Set
+///
+/// # Arguments
+///
+/// * `source` - URL or file of the key set
+///
+/// # Returns
+///
+/// * `Result<Vec<(Option<String>, RsaPublicKey)>, String>` - The keys with their key
+///   ID, or an error if the set cannot be fetched or parsed
+fn fetch_key_set(source: &KeySource) -> Result<Vec<(Option<String>, RsaPublicKey)>, String> {
+    let body = match source {
+        KeySource::Url(url) => {
+            let response = http_client::send("GET", url, &[], "").map_err(|e| e.to_string())?;
+            if !response.is_success() {
+                return Err(format!("HTTP {}", response.status));
+            }
+            response.body
+        }
+        KeySource::File(path) => fs::read_to_string(path).map_err(|e| e.to_string())?,
+    };
+    let set: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+    let keys = set
+        .get("keys")
+        .and_then(Value::as_array)
+        .ok_or("no keys in the set")?;
+    Ok(keys
+        .iter()
+        .filter(|key| key.get("kty").and_then(Value::as_str) == Some("RSA"))
+        .filter(|key| {
+            key.get("use")
+                .and_then(Value::as_str)
+                .is_none_or(|usage| usage == "sig")
+        })
+        .filter_map(|key| {
+            let component = |name: &str| {
+                key.get(name)
+                    .and_then(Value::as_str)
+                    .and_then(base64url_decode)
+                    .map(|bytes| BigUint::from_bytes_be(&bytes))
+            };
+            let kid = key.get("kid").and_then(Value::as_str).map(str::to_string);
+            match RsaPublicKey::new(component("n")?, component("e")?) {
+                Ok(public_key) => Some((kid, public_key)),
+                Err(e) => {
+                    debug!("Ignoring JWKS key {:?}: {}", kid, e);
+                    None
+                }
+            }
+        })
+        .collect())
+}
+
+/// Decodes unpadded base64url, as used by JSON Web Tokens
+///
+/// # Arguments
+///
+/// * `text` - The encoded text, with or without padding
+///
+/// # Returns
+///
+/// * `Option<Vec<u8>>` - The bytes, or None if the text is not base64url
+fn base64url_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'-' => 62,
+            b'_' => 63,
+            _ => return None,
+        };
+        buffer = ((buffer << 6) | u32::from(value)) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePrivateKey;
+    use rsa::traits::PublicKeyParts;
+    use rsa::RsaPrivateKey;
+    use serde_json::json;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    /// Encodes bytes as unpadded base64url
+    fn base64url_encode(bytes: &[u8]) -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let buffer = chunk.iter().enumerate().fold(0u32, |buffer, (i, byte)| {
+                buffer | u32::from(*byte) << (16 - 8 * i)
+            });
+            for i in 0..=chunk.len() {
+                text.push(char::from(
+                    ALPHABET[(buffer >> (18 - 6 * i) & 0x3f) as usize],
+                ));
+            }
+        }
+        text
+    }
+
+    /// Builds the signing input of a token
+    fn signing_input(header: &Value, claims: &Value) -> String {
+        format!(
+            "{}.{}",
+            base64url_encode(header.to_string().as_bytes()),
+            base64url_encode(claims.to_string().as_bytes())
+        )
+    }
+
+    /// Builds an HS256 token
+    fn hs256_token(secret: &[u8], claims: &Value) -> String {
+        let input = signing_input(&json!({"alg": "HS256", "typ": "JWT"}), claims);
+        let signature = hmac_sha256(secret, input.as_bytes());
+        format!("{}.{}", input, base64url_encode(&signature))
+    }
+
+    /// Builds an RS256 token
+    fn rs256_token(key: &RsaPrivateKey, kid: &str, claims: &Value) -> String {
+        let input = signing_input(&json!({"alg": "RS256", "kid": kid}), claims);
+        let hashed = Sha256::digest(input.as_bytes());
+        let signature = key.sign(Pkcs1v15Sign::new::<Sha256>(), &hashed).unwrap();
+        format!("{}.{}", input, base64url_encode(&signature))
+    }
+
+    /// Loads a test key of `tests/fixtures`
+    fn rsa_key(pem: &str) -> RsaPrivateKey {
+        RsaPrivateKey::from_pkcs8_pem(pem).unwrap()
+    }
+
+    /// Claims valid for an hour, granting the control role
+    fn claims() -> Value {
+        let now = chrono::Utc::now().timestamp();
+        json!({"sub": "alice", "exp": now + 3600, "roles": ["control"]})
+    }
+
+    /// Creates the validation of an `[auth]` section
+    fn auth(config: Value) -> JwtAuth {
+        let config: AuthConfig = serde_json::from_value(config).unwrap();
+        JwtAuth::from_config(&config).unwrap().unwrap()
+    }
+
+    /// Writes a JWKS holding a key to a temporary file
+    fn jwks_file(name: &str, key: &RsaPrivateKey, kid: &str) -> String {
+        let public_key = key.to_public_key();
+        let jwks = json!({"keys": [{
+            "kty": "RSA",
+            "use": "sig",
+            "kid": kid,
+            "n": base64url_encode(&public_key.n().to_bytes_be()),
+            "e": base64url_encode(&public_key.e().to_bytes_be()),
+        }]});
+        let path =
+            std::env::temp_dir().join(format!("hottoh_jwks_{}_{}.json", std::process::id(), name));
+        fs::write(&path, jwks.to_string()).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn base64url_decodes_rfc4648_vectors() {
+        for (encoded, decoded) in [
+            ("", ""),
+            ("Zg", "f"),
+            ("Zm8", "fo"),
+            ("Zm9v", "foo"),
+            ("Zm9vYg", "foob"),
+            ("Zm9vYmE", "fooba"),
+            ("Zm9vYmFy", "foobar"),
+            ("Zm9vYg==", "foob"),
+        ] {
+            assert_eq!(base64url_decode(encoded).unwrap(), decoded.as_bytes());
+        }
+    }
+
+    #[test]
+    fn base64url_uses_the_url_alphabet() {
+        assert_eq!(base64url_decode("-_8").unwrap(), vec![0xfb, 0xff]);
+        assert_eq!(base64url_decode("+/8"), None);
+        assert_eq!(base64url_decode("Zm 9v"), None);
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(base64url_decode(&base64url_encode(&bytes)).unwrap(), bytes);
+    }
+
+    #[test]
+    fn hs256_token_is_accepted() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let token = hs256_token(SECRET.as_bytes(), &claims());
+        assert!(JwtAuth::is_token(&token));
+        assert_eq!(
+            jwt.validate(&token).unwrap(),
+            ("alice".to_string(), Role::Control)
+        );
+    }
+
+    #[test]
+    fn read_role_is_mapped() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let mut claims = claims();
+        claims["roles"] = json!("openid read");
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap().1, Role::Read);
+        claims["roles"] = json!(["admin"]);
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert!(jwt.validate(&token).unwrap_err().contains("no role"));
+    }
+
+    #[test]
+    fn bad_signature_is_rejected() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let token = hs256_token(b"another secret of at least 32 characters", &claims());
+        assert_eq!(jwt.validate(&token).unwrap_err(), "invalid signature");
+
+        // Claims changed after signing
+        let token = hs256_token(SECRET.as_bytes(), &claims());
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let mut forged = claims();
+        forged["roles"] = json!(["control", "admin"]);
+        let forged = base64url_encode(forged.to_string().as_bytes());
+        parts[1] = &forged;
+        assert_eq!(
+            jwt.validate(&parts.join(".")).unwrap_err(),
+            "invalid signature"
+        );
+
+        // Truncated signature
+        assert_eq!(
+            jwt.validate(&token[..token.len() - 4]).unwrap_err(),
+            "invalid signature"
+        );
+    }
+
+    #[test]
+    fn malformed_token_is_rejected() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        assert_eq!(jwt.validate("a.b").unwrap_err(), "malformed token");
+        assert_eq!(jwt.validate("a.b.c.d").unwrap_err(), "malformed token");
+        assert_eq!(jwt.validate("!!.e30.").unwrap_err(), "malformed token");
+    }
+
+    #[test]
+    fn exp_is_required_and_checked() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let now = chrono::Utc::now().timestamp();
+
+        let mut claims = claims();
+        claims.as_object_mut().unwrap().remove("exp");
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap_err(), "missing exp claim");
+
+        claims["exp"] = json!(now - LEEWAY_SECS - 10);
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap_err(), "token expired");
+
+        // Within the leeway
+        claims["exp"] = json!(now - LEEWAY_SECS / 2);
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert!(jwt.validate(&token).is_ok());
+    }
+
+    #[test]
+    fn nbf_is_checked() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let now = chrono::Utc::now().timestamp();
+        let mut claims = claims();
+
+        claims["nbf"] = json!(now + LEEWAY_SECS + 60);
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap_err(), "token not valid yet");
+
+        claims["nbf"] = json!(now + LEEWAY_SECS / 2);
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert!(jwt.validate(&token).is_ok());
+    }
+
+    #[test]
+    fn issuer_and_audience_are_checked() {
+        let jwt = auth(json!({
+            "jwt_secret": SECRET,
+            "jwt_issuer": "https://idp.local",
+            "jwt_audience": "hottoh",
+        }));
+        let mut claims = claims();
+        claims["iss"] = json!("https://idp.local");
+        claims["aud"] = json!(["other", "hottoh"]);
+        assert!(jwt
+            .validate(&hs256_token(SECRET.as_bytes(), &claims))
+            .is_ok());
+
+        claims["aud"] = json!("other");
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap_err(), "unexpected audience");
+
+        claims["iss"] = json!("https://evil.local");
+        let token = hs256_token(SECRET.as_bytes(), &claims);
+        assert_eq!(jwt.validate(&token).unwrap_err(), "unexpected issuer");
+    }
+
+    #[test]
+    fn unsigned_token_is_rejected() {
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let input = signing_input(&json!({"alg": "none"}), &claims());
+        assert_eq!(
+            jwt.validate(&format!("{}.", input)).unwrap_err(),
+            "unsupported algorithm 'none'"
+        );
+        let input = signing_input(&json!({"typ": "JWT"}), &claims());
+        assert_eq!(
+            jwt.validate(&format!("{}.", input)).unwrap_err(),
+            "missing algorithm"
+        );
+    }
+
+    #[test]
+    fn rs256_token_is_accepted_with_the_pinned_key_set() {
+        let key = rsa_key(include_str!("../../tests/fixtures/jwt_rsa_key.pem"));
+        let path = jwks_file("accepted", &key, "k1");
+        let jwt = auth(json!({"jwks_file": path}));
+        let token = rs256_token(&key, "k1", &claims());
+        assert_eq!(
+            jwt.validate(&token).unwrap(),
+            ("alice".to_string(), Role::Control)
+        );
+
+        let other = rsa_key(include_str!("../../tests/fixtures/jwt_rsa_other_key.pem"));
+        let token = rs256_token(&other, "k1", &claims());
+        assert_eq!(jwt.validate(&token).unwrap_err(), "invalid signature");
+
+        let token = rs256_token(&key, "k2", &claims());
+        assert_eq!(jwt.validate(&token).unwrap_err(), "unknown signing key");
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn algorithm_confusion_is_rejected() {
+        // HS256 token whose secret is the public RSA key, the classic confusion attack
+        let key = rsa_key(include_str!("../../tests/fixtures/jwt_rsa_key.pem"));
+        let path = jwks_file("confusion", &key, "k1");
+        let jwt = auth(json!({"jwks_file": path}));
+        let public_key = key.to_public_key().n().to_bytes_be();
+        let token = hs256_token(&public_key, &claims());
+        assert_eq!(
+            jwt.validate(&token).unwrap_err(),
+            "HS256 tokens are not accepted"
+        );
+        let _ = fs::remove_file(path);
+
+        // RS256 token when only the HS256 secret is configured
+        let jwt = auth(json!({"jwt_secret": SECRET}));
+        let token = rs256_token(&key, "k1", &claims());
+        assert_eq!(
+            jwt.validate(&token).unwrap_err(),
+            "RS256 tokens are not accepted"
+        );
+    }
+
+    #[test]
+    fn plain_http_key_set_is_refused() {
+        let config: AuthConfig =
+            serde_json::from_value(json!({"jwks_url": "http://idp.local/certs"})).unwrap();
+        assert!(JwtAuth::from_config(&config)
+            .err()
+            .unwrap()
+            .contains("https://"));
+        let config: AuthConfig =
+            serde_json::from_value(json!({"jwks_url": "https://idp.local/certs"})).unwrap();
+        assert!(JwtAuth::from_config(&config).unwrap().is_some());
+        let config: AuthConfig = serde_json::from_value(json!({
+            "jwks_url": "https://idp.local/certs",
+            "jwks_file": "jwks.json",
+        }))
+        .unwrap();
+        assert!(JwtAuth::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn short_secret_is_refused() {
+        let config: AuthConfig = serde_json::from_value(json!({"jwt_secret": "short"})).unwrap();
+        assert!(JwtAuth::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use crate::hottoh::hottoh_const::Command;
+use crate::hottoh::hottoh_structs::{ActuatorState, CommandData};
+use crate::hottoh::tcp_client_structs::{Response, ResponseError};
+use log::info;
+use std::borrow::Cow;
+
+/// Layout of a DAT page as sent by a given firmware
+#[derive(Debug)]
+pub struct PageLayout {
+    /// Page decoded with this layout
+    pub command: Command,
+    /// Value of the first param identifying the page, if the page has one
+    pub page_number: Option<&'static str>,
+    /// Number of params sent by the firmware for this page
+    pub len: usize,
+    /// Position in the frame of each param of the reference layout (all below `len`),
+    /// or None if the firmware sends them in the reference order
+    pub indexes: Option<&'static [usize]>,
+}
+
+/// Meaning of the raw value of a hydraulic component in DAT2
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActuatorKind {
+    /// On when the value is not 0
+    Switch,
+    /// Modulation from 0 to 100 percent, on when not 0
+    Percent,
+}
+
+impl ActuatorKind {
+    /// Decodes the raw value of a component
+    ///
+    /// # Arguments
+    ///
+    /// * `raw` - The value sent by the stove
+    ///
+    /// # Returns
+    ///
+    /// * `ActuatorState` - The state
+    pub fn decode(&self, raw: u16) -> ActuatorState {
+        match self {
+            ActuatorKind::Switch => ActuatorState::new(raw != 0, None),
+            ActuatorKind::Percent => ActuatorState::new(raw != 0, Some(raw.min(100))),
+        }
+    }
+}
+
+/// Meaning of the raw values of the hydraulic components of DAT2
+///
+/// Components not populated by the firmware are None.
+#[derive(Debug)]
+pub struct HydroMapping {
+    /// Water flow switch
+    pub flow_switch: Option<ActuatorKind>,
+    /// Circulation pump
+    pub generic_pump: Option<ActuatorKind>,
+    /// Auxiliary outputs 1 to 3
+    pub airex: [Option<ActuatorKind>; 3],
+}
+
+/// Hydraulic components as sent by the reference firmware
+///
+/// Only hydro stoves (with a boiler or a puffer) populate them, air stoves send 0.
+static REFERENCE_HYDRO: HydroMapping = HydroMapping {
+    flow_switch: Some(ActuatorKind::Switch),
+    generic_pump: Some(ActuatorKind::Switch),
+    airex: [Some(ActuatorKind::Percent); 3],
+};
+
+/// DAT page layouts of a family of firmwares
+#[derive(Debug)]
+pub struct FirmwareLayout {
+    /// Name of the layout, used in logs
+    pub name: &'static str,
+    /// Manufacturer code (DAT0 index 1) this layout applies to, or None for any
+    pub manufacturer: Option<u16>,
+    /// Prefix of the INF version this layout applies to, or None for any
+    pub version_prefix: Option<&'static str>,
+    /// Layout of each page
+    pub pages: &'static [PageLayout],
+    /// Meaning of the hydraulic components of DAT2
+    pub hydro: &'static HydroMapping,
+}
+
+/// Reference layout, as sent by the stoves the bridge was written against
+///
+/// The params of the other layouts are mapped onto this one before parsing.
+static REFERENCE_PAGES: &[PageLayout] = &[
+    PageLayout {
+        command: Command::Dat0,
+        page_number: Some("0"),
+        len: 36,
+        indexes: None,
+    },
+    PageLayout {
+        command: Command::Dat1,
+        page_number: Some("1"),
+        len: 11,
+        indexes: None,
+    },
+    PageLayout {
+        command: Command::Dat2,
+        page_number: Some("2"),
+        len: 22,
+        indexes: None,
+    },
+    PageLayout {
+        command: Command::DatReqResponse,
+        page_number: None,
+        len: 1,
+        indexes: None,
+    },
+];
+
+/// Known firmware layouts, most specific first
+///
+/// Only the reference layout is known so far, so every firmware is decoded with it.
+/// Layouts of other firmwares go before it, matched on their version and manufacturer,
+/// once captures of their frames are available. The last entry matches any firmware
+/// and is used until the INF version and the manufacturer are known.
+static LAYOUTS: &[FirmwareLayout] = &[FirmwareLayout {
+    name: "reference",
+    manufacturer: None,
+    version_prefix: None,
+    pages: REFERENCE_PAGES,
+    hydro: &REFERENCE_HYDRO,
+}];
+
+/// Selects the layout matching a firmware
+///
+/// # Arguments
+///
+/// * `version` - Firmware version reported by INF, if known
+/// * `manufacturer` - Manufacturer code reported by DAT0, if known
+///
+/// # Returns
+///
+/// * `&'static FirmwareLayout` - The first matching layout
+pub fn select_layout(version: Option<&str>, manufacturer: Option<u16>) -> &'static FirmwareLayout {
+    LAYOUTS
+        .iter()
+        .find(|layout| {
+            let manufacturer_matches = layout.manufacturer.is_none_or(|m| Some(m) == manufacturer);
+            let version_matches = layout
+                .version_prefix
+                .is_none_or(|prefix| version.is_some_and(|v| v.starts_with(prefix)));
+            manufacturer_matches && version_matches
+        })
+        .unwrap_or(&LAYOUTS[LAYOUTS.len() - 1])
+}
+
+impl FirmwareLayout {
+    /// Identifies the page of a DAT response
+    ///
+    /// A page with exactly the received number of params is preferred. Otherwise the
+    /// page is identified by its first param, so that frames with extra trailing params
+    /// are still decoded.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The response params
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&PageLayout>` - The page layout, or None if unknown
+    pub fn identify_page(&self, params: &[&str]) -> Option<&PageLayout> {
+        self.pages
+            .iter()
+            .find(|page| page.len == params.len())
+            .or_else(|| {
+                let first = params.first()?;
+                self.pages
+                    .iter()
+                    .find(|page| page.page_number == Some(*first))
+            })
+    }
+}
+
+impl PageLayout {
+    /// Maps the params of a frame onto the reference layout
+    ///
+    /// Extra trailing params are left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The response params
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Cow<[&str]>, ResponseError>` - The params in reference order, or an
+    ///   IncorrectLength error if the frame is too short
+    pub fn normalize<'a, 'b>(
+        &self,
+        params: &'b [&'a str],
+    ) -> Result<Cow<'b, [&'a str]>, ResponseError> {
+        if params.len() < self.len {
+            return Err(ResponseError::IncorrectLength {
+                page: self.command.as_str(),
+                expected: self.len,
+                actual: params.len(),
+            });
+        }
+        match self.indexes {
+            Some(indexes) => Ok(Cow::Owned(indexes.iter().map(|&i| params[i]).collect())),
+            None => Ok(Cow::Borrowed(&params[..self.len])),
+        }
+    }
+}
+
+/// Change of the firmware version detected while running, e.g. after an update
+#[derive(Debug, Clone, PartialEq)]
+pub struct FirmwareChange {
+    /// Version reported by INF before the change
+    pub from: String,
+    /// Version reported by INF now
+    pub to: String,
+    /// Name of the layout selected for the new version
+    pub layout: &'static str,
+}
+
+/// Firmware of the connected stove, as far as it is known
+///
+/// Updated from INF and DAT0 responses to select the layout of the next frames.
+#[derive(Debug)]
+pub struct FirmwareInfo {
+    version: Option<String>,
+    manufacturer: Option<u16>,
+    layout: &'static FirmwareLayout,
+}
+
+impl Default for FirmwareInfo {
+    fn default() -> Self {
+        Self {
+            version: None,
+            manufacturer: None,
+            layout: select_layout(None, None),
+        }
+    }
+}
+
+impl FirmwareInfo {
+    /// Gets the layout selected for the firmware
+    ///
+    /// # Returns
+    ///
+    /// * `&'static FirmwareLayout` - The selected layout
+    pub fn layout(&self) -> &'static FirmwareLayout {
+        self.layout
+    }
+
+    /// Records the firmware version or manufacturer carried by a response
+    ///
+    /// The layout is selected again if either of them changed.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The parsed response
+    ///
+    /// # Returns
+    ///
+    /// * `Option<FirmwareChange>` - The change if the version differs from the one
+    ///   known so far, None otherwise (including when the first version is received)
+    pub fn update(&mut self, response: &Response) -> Option<FirmwareChange> {
+        let mut previous_version = None;
+        match response.get_command_data() {
+            Some(CommandData::Inf(inf)) if self.version.as_deref() != Some(inf.get_version()) => {
+                previous_version = self.version.replace(inf.get_version().to_string());
+            }
+            Some(CommandData::Dat0(dat0))
+                if self.manufacturer != Some(dat0.get_index_manufacturer()) =>
+            {
+                self.manufacturer = Some(dat0.get_index_manufacturer());
+            }
+            _ => return None,
+        }
+
+        let layout = select_layout(self.version.as_deref(), self.manufacturer);
+        if !std::ptr::eq(layout, self.layout) {
+            info!(
+                "Using {} DAT layout for firmware {} (manufacturer {})",
+                layout.name,
+                self.version.as_deref().unwrap_or("unknown"),
+                self.manufacturer
+                    .map_or("unknown".to_string(), |m| m.to_string())
+            );
+            self.layout = layout;
+        }
+        Some(FirmwareChange {
+            from: previous_version?,
+            to: self.version.clone()?,
+            layout: self.layout.name,
+        })
+    }
+}
@@ -0,0 +1,214 @@
+use crate::hottoh::config::HttpApiConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::ServerConfig;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+
+/// Listener of the HTTP API
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listener {
+    /// Name of the listener, only used in the logs
+    name: String,
+    /// Address to bind
+    address: SocketAddr,
+    /// Certificate chain and private key files (PEM) of an HTTPS listener, None for HTTP
+    tls: Option<(String, String)>,
+    /// Whether the requests need an API key or a token, when `[auth]` is configured
+    api_key: bool,
+}
+
+impl Listener {
+    /// Parses a listener such as `ip=0.0.0.0, port=8443, tls_cert=cert.pem, tls_key=key.pem`
+    ///
+    /// `tls_cert` and `tls_key` go together and make it an HTTPS listener. `auth` is
+    /// `api_key` (the default) or `none`, which serves the requests without API key nor
+    /// token.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the listener
+    /// * `spec` - The listener
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Listener, String>` - The listener, or an error if it is invalid
+    fn parse(name: &str, spec: &str) -> Result<Self, String> {
+        let mut ip = None;
+        let mut port = None;
+        let mut tls_cert = None;
+        let mut tls_key = None;
+        let mut api_key = true;
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected setting=value, got '{}'", item))?;
+            let (key, value) = (key.trim(), value.trim());
+            let invalid = || format!("invalid value '{}' for {}", value, key);
+            match key {
+                "ip" => ip = Some(value.parse::<IpAddr>().map_err(|_| invalid())?),
+                "port" => {
+                    port = Some(
+                        value
+                            .parse::<u16>()
+                            .ok()
+                            .filter(|port| *port != 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "tls_cert" => tls_cert = Some(value.to_string()),
+                "tls_key" => tls_key = Some(value.to_string()),
+                "auth" => {
+                    api_key = match value {
+                        "api_key" => true,
+                        "none" => false,
+                        _ => return Err("auth must be api_key or none".into()),
+                    }
+                }
+                other => return Err(format!("unknown setting '{}'", other)),
+            }
+        }
+        let tls = match (tls_cert, tls_key) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => return Err("tls_cert and tls_key go together".into()),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            address: SocketAddr::new(ip.ok_or("ip is required")?, port.ok_or("port is required")?),
+            tls,
+            api_key,
+        })
+    }
+
+    /// Gets the name of the listener
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The name
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Gets the address to bind
+    ///
+    /// # Returns
+    ///
+    /// * `SocketAddr` - The address
+    pub fn get_address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Loads the TLS configuration of an HTTPS listener
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<ServerConfig>, String>` - The configuration, None for an HTTP
+    ///   listener, or an error if the certificate or the key cannot be loaded
+    pub fn load_tls(&self) -> Result<Option<ServerConfig>, String> {
+        let Some((cert, key)) = &self.tls else {
+            return Ok(None);
+        };
+        let certs = CertificateDer::pem_file_iter(cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| format!("cannot read certificate {}: {}", cert, e))?;
+        if certs.is_empty() {
+            return Err(format!("no certificate in {}", cert));
+        }
+        let key = PrivateKeyDer::from_pem_file(key)
+            .map_err(|e| format!("cannot read private key {}: {}", key, e))?;
+        ServerConfig::builder()
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .map(Some)
+            .map_err(|e| format!("invalid certificate or key: {}", e))
+    }
+}
+
+/// Listeners of the HTTP API, `http_api.ip` and `http_api.port` unless the `[listeners]`
+/// section is configured
+pub struct Listeners {
+    /// The listeners, sorted by name
+    listeners: Vec<Listener>,
+}
+
+impl Listeners {
+    /// Creates the listeners from the configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `http_api` - The HTTP API configuration, giving the default listener
+    /// * `config` - The listeners by name, or None if the section is absent
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Listeners, String>` - The listeners, or the first error
+    pub fn from_config(
+        http_api: &HttpApiConfig,
+        config: Option<&HashMap<String, String>>,
+    ) -> Result<Self, String> {
+        let Some(config) = config.filter(|config| !config.is_empty()) else {
+            let ip = http_api
+                .ip
+                .parse::<IpAddr>()
+                .map_err(|_| format!("http_api.ip: '{}' is not an IP address", http_api.ip))?;
+            return Ok(Self {
+                listeners: vec![Listener {
+                    name: "http_api".to_string(),
+                    address: SocketAddr::new(ip, http_api.port),
+                    tls: None,
+                    api_key: true,
+                }],
+            });
+        };
+        let mut names: Vec<&String> = config.keys().collect();
+        names.sort();
+        let listeners = names
+            .into_iter()
+            .map(|name| {
+                Listener::parse(name, &config[name]).map_err(|e| format!("{}: {}", name, e))
+            })
+            .collect::<Result<Vec<Listener>, String>>()?;
+        for (i, listener) in listeners.iter().enumerate() {
+            if let Some(other) = listeners[..i]
+                .iter()
+                .find(|other| other.address == listener.address)
+            {
+                return Err(format!(
+                    "{} and {} bind the same address {}",
+                    other.name, listener.name, listener.address
+                ));
+            }
+        }
+        Ok(Self { listeners })
+    }
+
+    /// Gets the listeners
+    ///
+    /// # Returns
+    ///
+    /// * `&[Listener]` - The listeners
+    pub fn get_listeners(&self) -> &[Listener] {
+        &self.listeners
+    }
+
+    /// Checks if the requests received on an address need an API key
+    ///
+    /// # Arguments
+    ///
+    /// * `local_addr` - Local address of the listener receiving the request
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - False if the listener has `auth=none`, true otherwise
+    pub fn requires_api_key(&self, local_addr: SocketAddr) -> bool {
+        self.listeners
+            .iter()
+            .find(|listener| listener.address == local_addr)
+            .is_none_or(|listener| listener.api_key)
+    }
+}
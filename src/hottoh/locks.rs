@@ -0,0 +1,118 @@
+use log::warn;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Acquires a read lock, recovering it if it was poisoned
+///
+/// A lock is poisoned when a thread panics while holding it. The data it protects
+/// (queues, shared state, counters) stays consistent between two statements, so the
+/// guard is recovered and the poison flag cleared instead of skipping work forever.
+///
+/// # Arguments
+///
+/// * `lock` - The lock to acquire
+/// * `name` - Name of the protected data, used in logs
+///
+/// # Returns
+///
+/// * `RwLockReadGuard<T>` - The read guard
+pub fn read_lock<'a, T>(lock: &'a RwLock<T>, name: &str) -> RwLockReadGuard<'a, T> {
+    lock.read().unwrap_or_else(|e| {
+        warn!("Recovering poisoned {} lock", name);
+        lock.clear_poison();
+        e.into_inner()
+    })
+}
+
+/// Acquires a write lock, recovering it if it was poisoned
+///
+/// # Arguments
+///
+/// * `lock` - The lock to acquire
+/// * `name` - Name of the protected data, used in logs
+///
+/// # Returns
+///
+/// * `RwLockWriteGuard<T>` - The write guard
+pub fn write_lock<'a, T>(lock: &'a RwLock<T>, name: &str) -> RwLockWriteGuard<'a, T> {
+    lock.write().unwrap_or_else(|e| {
+        warn!("Recovering poisoned {} lock", name);
+        lock.clear_poison();
+        e.into_inner()
+    })
+}
+
+/// Acquires a mutex, recovering it if it was poisoned
+///
+/// # Arguments
+///
+/// * `lock` - The mutex to acquire
+/// * `name` - Name of the protected data, used in logs
+///
+/// # Returns
+///
+/// * `MutexGuard<T>` - The mutex guard
+pub fn mutex_lock<'a, T>(lock: &'a Mutex<T>, name: &str) -> MutexGuard<'a, T> {
+    lock.lock().unwrap_or_else(|e| {
+        warn!("Recovering poisoned {} lock", name);
+        lock.clear_poison();
+        e.into_inner()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+
+    /// Poisons a lock by panicking in a thread while holding its guard
+    fn poison<T: Send + Sync + 'static>(lock: &Arc<T>, hold: fn(&T)) {
+        let lock = Arc::clone(lock);
+        let result = thread::spawn(move || hold(&lock)).join();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn read_lock_recovers_poisoned_rwlock() {
+        let lock = Arc::new(RwLock::new(7));
+        poison(&lock, |lock| {
+            let _guard = lock.write().unwrap();
+            panic!("poisoning the lock");
+        });
+        assert!(lock.is_poisoned());
+
+        assert_eq!(*read_lock(&lock, "test"), 7);
+        assert!(!lock.is_poisoned());
+    }
+
+    #[test]
+    fn write_lock_recovers_poisoned_rwlock() {
+        let lock = Arc::new(RwLock::new(vec![1, 2]));
+        poison(&lock, |lock| {
+            let mut guard = lock.write().unwrap();
+            guard.push(3);
+            panic!("poisoning the lock");
+        });
+        assert!(lock.is_poisoned());
+
+        let mut guard = write_lock(&lock, "test");
+        assert_eq!(*guard, vec![1, 2, 3]);
+        guard.push(4);
+        drop(guard);
+        assert!(!lock.is_poisoned());
+        assert_eq!(*lock.read().unwrap(), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn mutex_lock_recovers_poisoned_mutex() {
+        let lock = Arc::new(Mutex::new(String::from("data")));
+        poison(&lock, |lock| {
+            let _guard = lock.lock().unwrap();
+            panic!("poisoning the mutex");
+        });
+        assert!(lock.is_poisoned());
+
+        assert_eq!(*mutex_lock(&lock, "test"), "data");
+        assert!(!lock.is_poisoned());
+    }
+}
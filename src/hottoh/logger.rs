@@ -1,9 +1,17 @@
-use crate::hottoh::config::AppConfig;
+use crate::hottoh::config::{AppConfig, LogConfig, LogTarget};
+use crate::hottoh::journald::{JournaldWriter, JOURNAL_SOCKET};
+use crate::hottoh::syslog::SyslogWriter;
 use chrono::Local;
-use flexi_logger::{Cleanup, Criterion, Duplicate, FileSpec, Logger, Naming, WriteMode};
-use log::Record;
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{
+    Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, LogSpecification, Logger, Naming,
+    WriteMode,
+};
+use log::{warn, Record};
 use std::error::Error;
+use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 
 /// Custom log formatter that includes timestamp, log level, and message
@@ -43,7 +51,9 @@ fn custom_format(
 
 /// Initializes the application logger with configuration from AppConfig
 ///
-/// Sets up file logging with rotation, console output, and custom formatting.
+/// Sets up logging to the configured target (rotating files, console or systemd
+/// journal) with custom formatting. If the log directory or the journal cannot be
+/// used (e.g. on a read-only filesystem), logs are only written to stderr.
 ///
 /// # Arguments
 ///
@@ -56,23 +66,196 @@ pub fn initialize_logger(config: Arc<RwLock<AppConfig>>) -> Result<(), Box<dyn E
     let cfg = config
         .read()
         .expect("Cannot read config in initialize_logger.");
-    Logger::try_with_str(&cfg.log.level)
-        .map_err(|e| format!("Failed to initialize logger: {}", e))?
-        .log_to_file(
-            FileSpec::default()
-                .directory(&cfg.log.directory)
-                .suffix("log"),
-        )
-        .write_mode(WriteMode::BufferAndFlush)
-        .duplicate_to_stderr(Duplicate::Info)
+    let spec = LogSpecification::parse(&cfg.log.level)
+        .map_err(|e| format!("Failed to initialize logger: {}", e))?;
+
+    // A remote syslog collector receives the logs in addition to the target
+    let mut syslog_error = None;
+    let syslog = cfg.log.syslog.as_deref().and_then(|url| {
+        SyslogWriter::new(url)
+            .map_err(|e| syslog_error = Some(format!("Remote syslog disabled: {}", e)))
+            .ok()
+            .map(|writer| Box::new(writer) as Box<dyn LogWriter>)
+    });
+
+    let (logger, fallback_reason) = match cfg.log.get_target() {
+        LogTarget::Stdout => (console_logger(spec, syslog, Console::Stdout), None),
+        LogTarget::Journald => match JournaldWriter::new() {
+            Ok(journald) => {
+                let writer: Box<dyn LogWriter> = match syslog {
+                    Some(syslog) => Box::new(TeeWriter(vec![Box::new(journald), syslog])),
+                    None => Box::new(journald),
+                };
+                (Logger::with(spec).log_to_writer(writer), None)
+            }
+            Err(e) => (
+                console_logger(spec, syslog, Console::Stderr),
+                Some(format!(
+                    "Cannot log to the journal ({}): {}",
+                    JOURNAL_SOCKET, e
+                )),
+            ),
+        },
+        target @ (LogTarget::File | LogTarget::Both) => {
+            match check_log_directory(&cfg.log.directory) {
+                Ok(()) => {
+                    let file_spec = FileSpec::default()
+                        .directory(&cfg.log.directory)
+                        .suffix("log");
+                    let logger = match syslog {
+                        Some(syslog) => {
+                            Logger::with(spec).log_to_file_and_writer(file_spec, syslog)
+                        }
+                        None => Logger::with(spec).log_to_file(file_spec),
+                    };
+                    let duplicate = if target == LogTarget::Both {
+                        Duplicate::Info
+                    } else {
+                        Duplicate::None
+                    };
+                    let (criterion, cleanup) = rotation(&cfg.log);
+                    let logger = logger
+                        .write_mode(WriteMode::BufferAndFlush)
+                        .duplicate_to_stderr(duplicate)
+                        .rotate(criterion, Naming::Timestamps, cleanup);
+                    (logger, None)
+                }
+                Err(e) => (
+                    console_logger(spec, syslog, Console::Stderr),
+                    Some(format!(
+                        "Cannot log to directory '{}': {}",
+                        cfg.log.directory, e
+                    )),
+                ),
+            }
+        }
+    };
+    logger
         .format(custom_format)
-        .rotate(
-            Criterion::Age(flexi_logger::Age::Day),
-            Naming::Timestamps,
-            Cleanup::KeepLogFiles(cfg.log.max_log_files),
-        )
         .start()
         .map_err(|e| format!("Failed to start logger: {}", e))?;
 
+    // Problems are reported once the fallback logger is running
+    if let Some(reason) = fallback_reason {
+        warn!("{}. Logging to stderr only.", reason);
+    }
+    if let Some(error) = syslog_error {
+        warn!("{}", error);
+    }
+
     Ok(())
 }
+
+/// Gets when the log file is rotated and which rotated files are kept
+///
+/// # Arguments
+///
+/// * `config` - The `[log]` section
+///
+/// # Returns
+///
+/// * `(Criterion, Cleanup)` - Rotation daily or at `max_file_size_mb`, keeping
+///   `max_log_files` rotated files, gzipped if `compress` is set
+fn rotation(config: &LogConfig) -> (Criterion, Cleanup) {
+    let criterion = match config.max_file_size_mb {
+        Some(size) if size > 0 => Criterion::Size(size * 1024 * 1024),
+        _ => Criterion::Age(flexi_logger::Age::Day),
+    };
+    let cleanup = if config.compress {
+        Cleanup::KeepCompressedFiles(config.max_log_files)
+    } else {
+        Cleanup::KeepLogFiles(config.max_log_files)
+    };
+    (criterion, cleanup)
+}
+
+/// Console stream used when logs are not written to files or the journal
+#[derive(Clone, Copy)]
+enum Console {
+    Stdout,
+    Stderr,
+}
+
+/// Builds a logger writing to the console, and to an additional writer if any
+///
+/// # Arguments
+///
+/// * `spec` - The log specification
+/// * `writer` - Additional writer (remote syslog), or None
+/// * `console` - The console stream
+///
+/// # Returns
+///
+/// * `Logger` - The logger, not started yet
+fn console_logger(
+    spec: LogSpecification,
+    writer: Option<Box<dyn LogWriter>>,
+    console: Console,
+) -> Logger {
+    match (writer, console) {
+        (None, Console::Stdout) => Logger::with(spec).log_to_stdout(),
+        (None, Console::Stderr) => Logger::with(spec).log_to_stderr(),
+        (Some(writer), Console::Stdout) => Logger::with(spec)
+            .log_to_writer(writer)
+            .duplicate_to_stdout(Duplicate::All),
+        (Some(writer), Console::Stderr) => Logger::with(spec)
+            .log_to_writer(writer)
+            .duplicate_to_stderr(Duplicate::All),
+    }
+}
+
+/// Log writer forwarding each record to several writers
+struct TeeWriter(Vec<Box<dyn LogWriter>>);
+
+impl LogWriter for TeeWriter {
+    /// Writes a record to every writer
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - Timestamp of the record
+    /// * `record` - The log record
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success, or the last error of the writers
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for writer in &self.0 {
+            if let Err(e) = writer.write(now, record) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Flushes every writer
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success, or the last error of the writers
+    fn flush(&self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for writer in &self.0 {
+            if let Err(e) = writer.flush() {
+                result = Err(e);
+            }
+        }
+        result
+    }
+}
+
+/// Checks that log files can be created in a directory, creating it if needed
+///
+/// # Arguments
+///
+/// * `directory` - The log directory
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - Success, or the error preventing to write in the directory
+fn check_log_directory(directory: &str) -> std::io::Result<()> {
+    fs::create_dir_all(directory)?;
+    let probe = Path::new(directory).join(".hottoh_api_write_test");
+    fs::File::create(&probe)?;
+    fs::remove_file(probe)
+}
@@ -0,0 +1,187 @@
+use chrono::{DateTime, Datelike, Local, Timelike, Weekday};
+use std::fmt;
+
+/// Names of the days accepted in a window, starting on Monday
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+/// Number of minutes in a day, also the end of a window ending at 24:00
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// Recurring period during which automations must not control the stove
+///
+/// A window ending before it starts (e.g. `fri 22:00-02:00`) runs past midnight.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceWindow {
+    /// Days on which the window starts, indexed from Monday
+    days: [bool; 7],
+    /// Start, in minutes since midnight
+    start: u32,
+    /// End, in minutes since midnight (exclusive)
+    end: u32,
+    /// The window as configured, for the status
+    label: String,
+}
+
+impl MaintenanceWindow {
+    /// Parses a window such as `sat 10:00-12:00`
+    ///
+    /// The days are a day name (`sat`), a range (`mon-fri`) or `daily`.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The window
+    ///
+    /// # Returns
+    ///
+    /// * `Result<MaintenanceWindow, String>` - The window, or an error if it is invalid
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = spec.trim();
+        let (days, hours) = spec
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("expected '<days> HH:MM-HH:MM', got '{}'", spec))?;
+        let (start, end) = hours
+            .trim()
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM in '{}'", spec))?;
+        let (start, end) = (parse_time(start)?, parse_time(end)?);
+        if start == MINUTES_PER_DAY {
+            return Err(format!("window '{}' cannot start at 24:00", spec));
+        }
+        if start == end {
+            return Err(format!("empty window '{}'", spec));
+        }
+        Ok(Self {
+            days: parse_days(days)?,
+            start,
+            end,
+            label: spec.split_whitespace().collect::<Vec<_>>().join(" "),
+        })
+    }
+
+    /// Checks if a time falls within the window
+    ///
+    /// # Arguments
+    ///
+    /// * `weekday` - Day of the time
+    /// * `minute` - Minutes since midnight of the time
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the window is in progress
+    fn contains(&self, weekday: Weekday, minute: u32) -> bool {
+        let day = weekday.num_days_from_monday() as usize;
+        if self.start < self.end {
+            self.days[day] && (self.start..self.end).contains(&minute)
+        } else {
+            let previous_day = (day + 6) % 7;
+            (self.days[day] && minute >= self.start)
+                || (self.days[previous_day] && minute < self.end)
+        }
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.label)
+    }
+}
+
+/// Maintenance windows configured in `[maintenance]`
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceSchedule {
+    windows: Vec<MaintenanceWindow>,
+}
+
+impl MaintenanceSchedule {
+    /// Parses a comma-separated list of windows
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The list (e.g. `sat 10:00-12:00, mon-fri 02:00-03:00`)
+    ///
+    /// # Returns
+    ///
+    /// * `Result<MaintenanceSchedule, String>` - The schedule, or the first error
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let windows = spec
+            .split(',')
+            .filter(|window| !window.trim().is_empty())
+            .map(MaintenanceWindow::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self { windows })
+    }
+
+    /// Finds the window in progress at a given time
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The local time
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&MaintenanceWindow>` - The window in progress, if any
+    pub fn active_window(&self, now: DateTime<Local>) -> Option<&MaintenanceWindow> {
+        let minute = now.hour() * 60 + now.minute();
+        self.windows
+            .iter()
+            .find(|window| window.contains(now.weekday(), minute))
+    }
+}
+
+/// Parses a time of day
+///
+/// # Arguments
+///
+/// * `time` - The time, as `HH:MM`
+///
+/// # Returns
+///
+/// * `Result<u32, String>` - Minutes since midnight (24:00 is accepted as an end), or an error
+fn parse_time(time: &str) -> Result<u32, String> {
+    let time = time.trim();
+    time.split_once(':')
+        .and_then(|(hours, minutes)| {
+            Some((hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?))
+        })
+        .filter(|(hours, minutes)| *minutes < 60 && *hours * 60 + *minutes <= MINUTES_PER_DAY)
+        .map(|(hours, minutes)| hours * 60 + minutes)
+        .ok_or_else(|| format!("invalid time '{}' (HH:MM)", time))
+}
+
+/// Parses the days of a window
+///
+/// # Arguments
+///
+/// * `days` - A day name (`sat`), a range (`mon-fri`) or `daily`
+///
+/// # Returns
+///
+/// * `Result<[bool; 7], String>` - The selected days, indexed from Monday, or an error
+fn parse_days(days: &str) -> Result<[bool; 7], String> {
+    let day_index = |name: &str| {
+        let name = name.to_ascii_lowercase();
+        DAY_NAMES
+            .iter()
+            .position(|day| name.starts_with(day))
+            .ok_or_else(|| format!("unknown day '{}' (mon to sun, or daily)", name))
+    };
+    let mut selected = [false; 7];
+    if days.eq_ignore_ascii_case("daily") {
+        return Ok([true; 7]);
+    }
+    let (first, last) = match days.split_once('-') {
+        Some((first, last)) => (day_index(first)?, day_index(last)?),
+        None => {
+            let day = day_index(days)?;
+            (day, day)
+        }
+    };
+    let mut day = first;
+    loop {
+        selected[day] = true;
+        if day == last {
+            break;
+        }
+        day = (day + 1) % 7;
+    }
+    Ok(selected)
+}
@@ -0,0 +1,508 @@
+use crate::hottoh::build_info::{GIT_HASH, VERSION};
+use crate::hottoh::hottoh_const::PROTOCOL_PROFILE;
+use crate::hottoh::hottoh_structs::ActuatorState;
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::network_check::NetworkDiagnosis;
+use crate::hottoh::resource_usage::ResourceUsage;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bounds in seconds of the buckets of the response latency histogram
+///
+/// Requests without an answer after 5 seconds are dropped, so they are never observed.
+pub const LATENCY_BUCKETS: [f64; 7] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// Identifier of the stove in the exported metrics, until several stoves are supported
+pub const DEFAULT_STOVE_ID: &str = "default";
+
+/// Labels identifying a stove in the exported metrics, with its readings
+#[derive(Debug)]
+pub struct StoveLabels {
+    /// Identifier of the stove in the bridge
+    pub stove_id: String,
+    /// Hostname reported by INF
+    pub hostname: String,
+    /// Firmware version reported by INF
+    pub firmware: String,
+    /// Manufacturer reported by DAT0
+    pub manufacturer: String,
+    /// Hydraulic components reported by DAT2, by name
+    pub actuators: Vec<(&'static str, ActuatorState)>,
+}
+
+/// Distribution of the time between sending a request and receiving its answer
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    /// Number of observations per bucket of `LATENCY_BUCKETS`, the last one above them
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+    /// Sum of the observed latencies in seconds
+    sum: f64,
+}
+
+impl LatencyHistogram {
+    /// Records a latency
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - The time between the request and its answer
+    fn observe(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket] += 1;
+        self.sum += seconds;
+    }
+
+    /// Gets the number of observations per bucket
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(Option<f64>, u64)>` - Upper bound of each bucket, None for the last one,
+    ///   with the number of latencies in it (not cumulative)
+    pub fn get_buckets(&self) -> Vec<(Option<f64>, u64)> {
+        LATENCY_BUCKETS
+            .iter()
+            .map(|bound| Some(*bound))
+            .chain([None])
+            .zip(self.counts)
+            .collect()
+    }
+
+    /// Gets the number of observations
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of latencies recorded
+    pub fn get_count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Gets the sum of the observations
+    ///
+    /// # Returns
+    ///
+    /// * `f64` - The sum of the latencies in seconds
+    pub fn get_sum(&self) -> f64 {
+        self.sum
+    }
+}
+
+/// Runtime counters exported by the HTTP API in Prometheus text format
+#[derive(Debug)]
+pub struct Metrics {
+    /// Time at which the process started
+    started_at: Instant,
+    /// Number of responses dropped because they were already received within the dedup window
+    duplicate_responses_dropped: AtomicU64,
+    /// Number of reconnections because no DAT0 was received on a connection that looked alive
+    stale_reconnects: AtomicU64,
+    /// Number of panics per supervised thread
+    thread_crashes: Mutex<BTreeMap<&'static str, u64>>,
+    /// Address of the stove in use and whether it is a fallback one, None while disconnected
+    stove_address: Mutex<Option<(String, bool)>>,
+    /// Number of connections established to the stove
+    connections: AtomicU64,
+    /// Time at which the current connection was established, None while disconnected
+    connected_since: Mutex<Option<Instant>>,
+    /// Time between the requests sent to the stove and their answers
+    response_latency: Mutex<LatencyHistogram>,
+    /// Result of the last reachability checks, None while connected
+    network_diagnosis: Mutex<Option<NetworkDiagnosis>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// Creates a new Metrics instance with all counters set to zero
+    ///
+    /// # Returns
+    ///
+    /// * `Metrics` - A new instance with zeroed counters
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            duplicate_responses_dropped: AtomicU64::new(0),
+            stale_reconnects: AtomicU64::new(0),
+            thread_crashes: Mutex::new(BTreeMap::new()),
+            stove_address: Mutex::new(None),
+            connections: AtomicU64::new(0),
+            connected_since: Mutex::new(None),
+            response_latency: Mutex::new(LatencyHistogram::default()),
+            network_diagnosis: Mutex::new(None),
+        }
+    }
+
+    /// Gets the number of seconds elapsed since the process started
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The process uptime in seconds
+    pub fn get_uptime_secs(&self) -> u64 {
+        self.started_at.elapsed().as_secs()
+    }
+
+    /// Increments the duplicate responses counter
+    pub fn inc_duplicate_responses_dropped(&self) {
+        self.duplicate_responses_dropped
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Gets the number of duplicate responses dropped
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of duplicate responses dropped
+    pub fn get_duplicate_responses_dropped(&self) -> u64 {
+        self.duplicate_responses_dropped.load(Ordering::Relaxed)
+    }
+
+    /// Increments the stale connection reconnects counter
+    pub fn inc_stale_reconnects(&self) {
+        self.stale_reconnects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Gets the number of reconnections because of a stale connection
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of stale connection reconnects
+    pub fn get_stale_reconnects(&self) -> u64 {
+        self.stale_reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Increments the crash counter of a supervised thread
+    ///
+    /// # Arguments
+    ///
+    /// * `thread` - Name of the thread that crashed
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of crashes of this thread so far
+    pub fn inc_thread_crashes(&self, thread: &'static str) -> u64 {
+        let mut crashes = mutex_lock(&self.thread_crashes, "thread crashes");
+        let count = crashes.entry(thread).or_insert(0);
+        *count += 1;
+        *count
+    }
+
+    /// Gets the number of crashes per supervised thread
+    ///
+    /// # Returns
+    ///
+    /// * `BTreeMap<&'static str, u64>` - Crash count by thread name
+    pub fn get_thread_crashes(&self) -> BTreeMap<&'static str, u64> {
+        mutex_lock(&self.thread_crashes, "thread crashes").clone()
+    }
+
+    /// Sets the address of the stove in use
+    ///
+    /// Setting an address counts a new connection, and None ends it.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - The address and whether it is a fallback one, None when disconnected
+    pub fn set_stove_address(&self, address: Option<(String, bool)>) {
+        let connected_since = address.as_ref().map(|_| Instant::now());
+        if connected_since.is_some() {
+            self.connections.fetch_add(1, Ordering::Relaxed);
+        }
+        *mutex_lock(&self.connected_since, "connected since") = connected_since;
+        *mutex_lock(&self.stove_address, "stove address") = address;
+    }
+
+    /// Gets the number of connections established to the stove
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The number of connections, including the current one
+    pub fn get_connections(&self) -> u64 {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// Gets the duration of the current connection
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - Seconds since the connection was established, None while disconnected
+    pub fn get_connected_secs(&self) -> Option<u64> {
+        mutex_lock(&self.connected_since, "connected since").map(|since| since.elapsed().as_secs())
+    }
+
+    /// Records the time the stove took to answer a request
+    ///
+    /// # Arguments
+    ///
+    /// * `latency` - The time between sending the request and receiving its answer
+    pub fn observe_response_latency(&self, latency: Duration) {
+        mutex_lock(&self.response_latency, "response latency").observe(latency);
+    }
+
+    /// Gets the distribution of the response latencies
+    ///
+    /// # Returns
+    ///
+    /// * `LatencyHistogram` - A copy of the histogram
+    pub fn get_response_latency(&self) -> LatencyHistogram {
+        mutex_lock(&self.response_latency, "response latency").clone()
+    }
+
+    /// Gets the address of the stove in use
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(String, bool)>` - The address and whether it is a fallback one,
+    ///   None while disconnected
+    pub fn get_stove_address(&self) -> Option<(String, bool)> {
+        mutex_lock(&self.stove_address, "stove address").clone()
+    }
+
+    /// Sets the result of the reachability checks
+    ///
+    /// # Arguments
+    ///
+    /// * `diagnosis` - The result of the checks, None once connected
+    pub fn set_network_diagnosis(&self, diagnosis: Option<NetworkDiagnosis>) {
+        *mutex_lock(&self.network_diagnosis, "network diagnosis") = diagnosis;
+    }
+
+    /// Gets the result of the last reachability checks
+    ///
+    /// # Returns
+    ///
+    /// * `Option<NetworkDiagnosis>` - The result, None while connected or if not checked
+    pub fn get_network_diagnosis(&self) -> Option<NetworkDiagnosis> {
+        mutex_lock(&self.network_diagnosis, "network diagnosis").clone()
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    ///
+    /// A `target_info` metric is emitted for each stove, and per-stove metrics are
+    /// labelled with its `stove_id`.
+    ///
+    /// # Arguments
+    ///
+    /// * `stove` - Labels of the connected stove
+    /// * `resources` - Resources used by the process
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The metrics as text
+    pub fn render(&self, stove: &StoveLabels, resources: &ResourceUsage) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP hottoh_build_info Build information");
+        let _ = writeln!(out, "# TYPE hottoh_build_info gauge");
+        let _ = writeln!(
+            out,
+            "hottoh_build_info{{version=\"{}\",git_hash=\"{}\",protocol_profile=\"{}\"}} 1",
+            VERSION, GIT_HASH, PROTOCOL_PROFILE
+        );
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_process_uptime_seconds Seconds since the process started"
+        );
+        let _ = writeln!(out, "# TYPE hottoh_process_uptime_seconds gauge");
+        let _ = writeln!(
+            out,
+            "hottoh_process_uptime_seconds {}",
+            self.get_uptime_secs()
+        );
+
+        let process_gauges = [
+            (
+                "hottoh_process_resident_memory_bytes",
+                "Resident memory of the process",
+                resources.rss_bytes,
+            ),
+            (
+                "hottoh_process_open_fds",
+                "Open file descriptors of the process",
+                resources.open_fds,
+            ),
+            (
+                "hottoh_process_threads",
+                "Threads of the process",
+                resources.threads,
+            ),
+            (
+                "hottoh_queue_memory_bytes",
+                "Estimate of the memory held by the request and response queues",
+                Some(resources.queue_bytes),
+            ),
+        ];
+        for (name, help, value) in process_gauges {
+            if let Some(value) = value {
+                let _ = writeln!(out, "# HELP {} {}", name, help);
+                let _ = writeln!(out, "# TYPE {} gauge", name);
+                let _ = writeln!(out, "{} {}", name, value);
+            }
+        }
+
+        let _ = writeln!(out, "# HELP target_info Stove connected to the bridge");
+        let _ = writeln!(out, "# TYPE target_info gauge");
+        let _ = writeln!(
+            out,
+            "target_info{{stove_id=\"{}\",hostname=\"{}\",firmware=\"{}\",manufacturer=\"{}\"}} 1",
+            escape_label(&stove.stove_id),
+            escape_label(&stove.hostname),
+            escape_label(&stove.firmware),
+            escape_label(&stove.manufacturer)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_duplicate_responses_dropped_total Responses dropped because they were already received within the dedup window"
+        );
+        let _ = writeln!(
+            out,
+            "# TYPE hottoh_duplicate_responses_dropped_total counter"
+        );
+        let _ = writeln!(
+            out,
+            "hottoh_duplicate_responses_dropped_total{{stove_id=\"{}\"}} {}",
+            escape_label(&stove.stove_id),
+            self.get_duplicate_responses_dropped()
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_stale_reconnects_total Reconnections because no DAT0 was received on a connection that looked alive"
+        );
+        let _ = writeln!(out, "# TYPE hottoh_stale_reconnects_total counter");
+        let _ = writeln!(
+            out,
+            "hottoh_stale_reconnects_total{{stove_id=\"{}\"}} {}",
+            escape_label(&stove.stove_id),
+            self.get_stale_reconnects()
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_connections_total Connections established to the stove"
+        );
+        let _ = writeln!(out, "# TYPE hottoh_connections_total counter");
+        let _ = writeln!(
+            out,
+            "hottoh_connections_total{{stove_id=\"{}\"}} {}",
+            escape_label(&stove.stove_id),
+            self.get_connections()
+        );
+
+        let latency = self.get_response_latency();
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_response_latency_seconds Time between a request sent to the stove and its answer"
+        );
+        let _ = writeln!(out, "# TYPE hottoh_response_latency_seconds histogram");
+        let mut cumulative = 0;
+        for (bound, count) in latency.get_buckets() {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "hottoh_response_latency_seconds_bucket{{stove_id=\"{}\",le=\"{}\"}} {}",
+                escape_label(&stove.stove_id),
+                bound.map_or("+Inf".to_string(), |bound| bound.to_string()),
+                cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "hottoh_response_latency_seconds_sum{{stove_id=\"{}\"}} {}",
+            escape_label(&stove.stove_id),
+            latency.get_sum()
+        );
+        let _ = writeln!(
+            out,
+            "hottoh_response_latency_seconds_count{{stove_id=\"{}\"}} {}",
+            escape_label(&stove.stove_id),
+            latency.get_count()
+        );
+
+        if let Some((address, fallback)) = self.get_stove_address() {
+            let _ = writeln!(
+                out,
+                "# HELP hottoh_stove_address_info Address of the stove in use, fallback when the primary one is down"
+            );
+            let _ = writeln!(out, "# TYPE hottoh_stove_address_info gauge");
+            let _ = writeln!(
+                out,
+                "hottoh_stove_address_info{{stove_id=\"{}\",address=\"{}\",fallback=\"{}\"}} 1",
+                escape_label(&stove.stove_id),
+                escape_label(&address),
+                fallback
+            );
+        }
+
+        if !stove.actuators.is_empty() {
+            let _ = writeln!(
+                out,
+                "# HELP hottoh_actuator_on Whether a hydraulic component reported in DAT2 is active"
+            );
+            let _ = writeln!(out, "# TYPE hottoh_actuator_on gauge");
+            for (name, state) in &stove.actuators {
+                let _ = writeln!(
+                    out,
+                    "hottoh_actuator_on{{stove_id=\"{}\",actuator=\"{}\"}} {}",
+                    escape_label(&stove.stove_id),
+                    name,
+                    u8::from(state.is_on())
+                );
+            }
+            let _ = writeln!(
+                out,
+                "# HELP hottoh_actuator_percent Modulation of a hydraulic component reported in DAT2"
+            );
+            let _ = writeln!(out, "# TYPE hottoh_actuator_percent gauge");
+            for (name, state) in &stove.actuators {
+                if let Some(percent) = state.get_percent() {
+                    let _ = writeln!(
+                        out,
+                        "hottoh_actuator_percent{{stove_id=\"{}\",actuator=\"{}\"}} {}",
+                        escape_label(&stove.stove_id),
+                        name,
+                        percent
+                    );
+                }
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP hottoh_thread_crashes_total Panics of supervised threads"
+        );
+        let _ = writeln!(out, "# TYPE hottoh_thread_crashes_total counter");
+        for (thread, count) in self.get_thread_crashes() {
+            let _ = writeln!(
+                out,
+                "hottoh_thread_crashes_total{{thread=\"{}\"}} {}",
+                thread, count
+            );
+        }
+        out
+    }
+}
+
+/// Escapes a label value for the Prometheus text format
+///
+/// # Arguments
+///
+/// * `value` - The label value
+///
+/// # Returns
+///
+/// * `String` - The escaped value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -4,19 +4,161 @@
 //! that implement the Hottoh protocol. It includes TCP client functionality,
 //! data structures for representing stove state, and an HTTP API for remote control.
 
+/// Access log of the HTTP API with sampling and body redaction
+pub mod access_log;
+/// Alert rules on numeric fields, with debounce and auto-resolve
+pub mod alerts;
+/// API keys required by the HTTP API
+pub mod api_keys;
+/// Bans of the clients failing to authenticate, and log of the authentication events
+pub mod auth_guard;
+/// Build information (version, git hash)
+pub mod build_info;
+/// Validation of the configuration (check-config subcommand)
+pub mod check_config;
+/// PIN protection of the state-changing endpoints
+pub mod child_lock;
+/// Outcome of the write commands, followed on the event bus
+pub mod command_outcome;
 /// Configuration handling for the application
 pub mod config;
+/// Confirmation tokens of destructive commands
+pub mod confirmation;
+/// Crash reports written when a thread panics
+pub mod crash_report;
+/// Diagnostics bundle for bug reports
+pub mod diagnostics;
+/// Errors of the communication with the stove, by kind
+pub mod error;
+/// Internal event bus shared by the integrations
+pub mod events;
+/// Failover between the addresses of the stove
+pub mod failover;
+/// Fan speeds driven by the power level
+pub mod fan_curve;
+/// Capture of the last raw frames exchanged with the stove
+pub mod frame_capture;
+/// Detection of watched values that stop changing
+pub mod freeze_watchdog;
+/// Stove turned on when a temperature gets too low
+pub mod frost_guard;
+/// GraphQL schema over the shared state, with the commands and the state changes
+pub mod graphql;
+/// gRPC service with the state and the commands of the HTTP API
+pub mod grpc;
+/// History of the telemetry, downsampled as it ages
+pub mod history;
+/// Import of past history samples from CSV files
+pub mod history_import;
+/// Energy and runtime statistics pushed to Home Assistant
+pub mod home_assistant;
 /// Constants used throughout the application
 pub mod hottoh_const;
 /// Data structures for representing stove data
 pub mod hottoh_structs;
 /// HTTP API for remote control of the stove
 pub mod http_api;
+/// Minimal blocking HTTP client for the home automation integrations
+pub mod http_client;
+/// Persistent identity of the bridge
+pub mod identity;
+/// Detection of ignitions getting slower than usual
+pub mod ignition_monitor;
+/// Signed inbound webhooks mapped to stove commands
+pub mod inbound_webhooks;
+/// First-run setup writing the configuration file
+pub mod init;
+/// Generation of service definitions (systemd, Docker compose)
+pub mod install;
+/// Push of state values to Jeedom or eedomus virtual devices
+pub mod jeedom;
+/// Log writer for the systemd journal
+pub mod journald;
+/// Validation of the JSON Web Tokens of an identity provider
+pub mod jwt;
+/// DAT page layouts per firmware
+pub mod layouts;
+/// Listeners of the HTTP API, plain HTTP or HTTPS
+pub mod listeners;
+/// Poison-tolerant lock helpers
+pub mod locks;
 /// Logging functionality
 pub mod logger;
+/// Maintenance windows suppressing automation commands
+pub mod maintenance;
+/// Runtime metrics exported by the HTTP API
+pub mod metrics;
+/// Availability of the bridge and of the stove published to MQTT
+pub mod mqtt;
+/// Raw protocol multiplexer sharing the stove connection with other tools
+pub mod multiplexer;
+/// Ping of the stove and of the gateway when the stove is unreachable
+pub mod network_check;
+/// Restriction of the HTTP API to allowed client networks
+pub mod network_filter;
+/// Settable values with their range for the client UIs
+pub mod normalized;
+/// Notifications of the events, with their payload templates
+pub mod notifications;
+/// Synchronization of openHAB items with the stove
+pub mod openhab;
+/// One-time codes for the pairing of new clients
+pub mod pairing;
+/// Offline parsing of captured frames (parse-capture subcommand)
+pub mod parse_capture;
+/// Setpoints written to the stove but not yet read back
+pub mod pending;
+/// Registration of plugin routes, commands and notifiers
+pub mod plugins;
+/// Named profiles bundling settings applied together
+pub mod profiles;
+/// Limits of the power and the fans during the quiet hours
+pub mod quiet_hours;
+/// Masking of identifying fields
+pub mod redaction;
+/// HMAC signing of the state-changing requests
+pub mod request_signing;
+/// Memory, file descriptors and threads used by the process
+pub mod resource_usage;
+/// Log of every response parsed from the stove, as JSON lines
+pub mod response_log;
+/// Stream of every response parsed from the stove
+pub mod response_stream;
+/// Automation rules evaluated on state updates
+pub mod rules;
+/// Automatic stop of the time-limited runs
+pub mod run_timer;
+/// Scripting hooks run on the events of the bus
+pub mod scripts;
 /// Shared state between components
 pub mod shared_struct;
+/// Progress of the startup sequence of the stove
+pub mod startup;
+/// Access to shared state fields by name for the integrations
+pub mod state_fields;
+/// Live updates of the shared state over a WebSocket
+pub mod state_stream;
+/// Version of the shared state, to wait for its changes
+pub mod state_version;
+/// HTML page with the runtime metrics
+pub mod stats_page;
+/// Typed commands to the stove, checked against the ranges it reports
+pub mod stove_client;
+/// Supervision and restart of worker threads
+pub mod supervisor;
+/// Remote syslog (RFC 5424) log writer
+pub mod syslog;
 /// TCP client for communicating with the stove
 pub mod tcp_client;
 /// Data structures for TCP client requests and responses
 pub mod tcp_client_structs;
+/// Precision and rounding of the temperatures
+pub mod temperature;
+/// Limits on the commands sent to the stove
+pub mod throttle;
+/// Timezone used for local timestamps
+pub mod timezone;
+/// Wake-on-LAN of the Wi-Fi bridge of the stove
+pub mod wake_on_lan;
+/// Commands received on the state WebSocket
+pub mod ws_commands;
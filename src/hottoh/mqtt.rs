@@ -0,0 +1,244 @@
+use crate::hottoh::config::MqttConfig;
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::metrics::{Metrics, DEFAULT_STOVE_ID};
+use crate::hottoh::notifications::{Channel, Notifications};
+use crate::hottoh::supervisor::supervise;
+use log::{debug, error, info, warn};
+use rumqttc::{Client, Event as MqttEvent, LastWill, MqttOptions, Packet, QoS, RecvTimeoutError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::TryRecvError;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Payload of an available bridge or stove
+const ONLINE: &str = "online";
+/// Payload of an unavailable bridge or stove
+const OFFLINE: &str = "offline";
+/// Delay before reconnecting to the broker after a connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Availability topics of the bridge and of the stoves
+struct Topics {
+    /// Availability of the bridge, `offline` being also its Last Will
+    bridge: String,
+    /// Availability of the stove, following the TCP connection
+    stove: String,
+}
+
+impl Topics {
+    /// Builds the topics from their templates
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The MQTT configuration, with the prefix and the topic templates
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Topics, String>` - The topics, or an error if a template is invalid
+    fn from_config(config: &MqttConfig) -> Result<Self, String> {
+        let prefix = config.topic_prefix.trim_end_matches('/');
+        Ok(Self {
+            bridge: render_topic(&config.status_topic, prefix, None)?,
+            stove: render_topic(&config.availability_topic, prefix, Some(DEFAULT_STOVE_ID))?,
+        })
+    }
+}
+
+/// Renders a topic template
+///
+/// # Arguments
+///
+/// * `template` - The template, with `{prefix}` and `{stove_id}` placeholders
+/// * `prefix` - The topic prefix
+/// * `stove_id` - The id of the stove, None for the topics of the bridge
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The topic, or an error if a placeholder is unknown or
+///   the topic is not a valid topic name
+pub fn render_topic(
+    template: &str,
+    prefix: &str,
+    stove_id: Option<&str>,
+) -> Result<String, String> {
+    let mut topic = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        topic.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+        match (&rest[start + 1..start + end], stove_id) {
+            ("prefix", _) => topic.push_str(prefix),
+            ("stove_id", Some(stove_id)) => topic.push_str(stove_id),
+            (name, _) => {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in '{}'",
+                    name, template
+                ))
+            }
+        }
+        rest = &rest[start + end + 1..];
+    }
+    topic.push_str(rest);
+    if topic.is_empty() || topic.contains(['+', '#']) {
+        return Err(format!("invalid topic '{}'", topic));
+    }
+    Ok(topic)
+}
+
+/// Gets the availability payload of a connection state
+///
+/// # Arguments
+///
+/// * `connected` - Whether the connection is up
+///
+/// # Returns
+///
+/// * `&'static str` - `online` or `offline`
+fn availability(connected: bool) -> &'static str {
+    if connected {
+        ONLINE
+    } else {
+        OFFLINE
+    }
+}
+
+/// Starts a thread publishing the availability of the bridge and of the stove to MQTT
+///
+/// The bridge topic is set `online` once connected to the broker, and to `offline` by
+/// the broker itself (Last Will) when the bridge drops without disconnecting. The stove
+/// topic follows the TCP connection to the stove. Both are retained, so that Home
+/// Assistant entities using them as availability topics show "unavailable" rather than
+/// frozen values. The notifications are published to `notifications.mqtt_topic`, not
+/// retained.
+///
+/// # Arguments
+///
+/// * `config` - The MQTT configuration, or None if the section is absent
+/// * `events` - The event bus, publishing the connection changes
+/// * `notifications` - The notifications with their topic, None if not published to MQTT
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics, giving the connection state at startup, where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_mqtt_thread(
+    config: Option<&MqttConfig>,
+    events: Arc<EventBus>,
+    notifications: Option<(Arc<Notifications>, String)>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let topics = match Topics::from_config(config) {
+        Ok(topics) => topics,
+        Err(e) => {
+            error!("MQTT availability disabled: {}", e);
+            return None;
+        }
+    };
+    let prefix = config.topic_prefix.trim_end_matches('/');
+    let notifications =
+        notifications.and_then(
+            |(notifications, topic)| match render_topic(&topic, prefix, None) {
+                Ok(topic) => Some((notifications, topic)),
+                Err(e) => {
+                    error!("MQTT notifications disabled: {}", e);
+                    None
+                }
+            },
+        );
+    let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    options.set_keep_alive(Duration::from_secs(config.keep_alive_secs.max(5)));
+    options.set_last_will(LastWill::new(
+        &topics.bridge,
+        OFFLINE,
+        QoS::AtLeastOnce,
+        true,
+    ));
+    if let Some(username) = &config.username {
+        options.set_credentials(username, config.password.clone().unwrap_or_default());
+    }
+    info!(
+        "Publishing availability to MQTT broker {}:{} on {} and {}",
+        config.host, config.port, topics.bridge, topics.stove
+    );
+
+    Some(supervise(
+        "mqtt",
+        Arc::clone(&running),
+        Arc::clone(&metrics),
+        move || {
+            let (client, mut connection) = Client::new(options.clone(), 16);
+            let receiver = events.subscribe();
+            let mut stove_connected = metrics.get_connected_secs().is_some();
+            let publish = |topic: &str, payload: &str| {
+                if let Err(e) = client.try_publish(topic, QoS::AtLeastOnce, true, payload) {
+                    warn!("Cannot publish {} to {}: {}", payload, topic, e);
+                }
+            };
+            let mut retry_at: Option<Instant> = None;
+            while running.load(Ordering::SeqCst) {
+                loop {
+                    match receiver.try_recv() {
+                        Ok(Event::ConnectionChanged { connected }) => {
+                            stove_connected = connected;
+                            publish(&topics.stove, availability(connected));
+                        }
+                        Ok(event) => {
+                            let Some((notifications, topic)) = &notifications else {
+                                continue;
+                            };
+                            if let Some(payload) = notifications.render(Channel::Mqtt, &event) {
+                                if let Err(e) =
+                                    client.try_publish(topic, QoS::AtLeastOnce, false, payload)
+                                {
+                                    warn!("Cannot publish the notification to {}: {}", topic, e);
+                                }
+                            }
+                        }
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => return,
+                    }
+                }
+                if retry_at.is_some_and(|at| Instant::now() < at) {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                retry_at = None;
+                match connection.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Ok(MqttEvent::Incoming(Packet::ConnAck(_)))) => {
+                        info!("Connected to the MQTT broker");
+                        publish(&topics.bridge, ONLINE);
+                        publish(&topics.stove, availability(stove_connected));
+                    }
+                    Ok(Ok(event)) => debug!("MQTT event: {:?}", event),
+                    Ok(Err(e)) => {
+                        warn!("MQTT connection error, retrying: {}", e);
+                        retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+            // A clean disconnect does not trigger the Last Will
+            publish(&topics.bridge, OFFLINE);
+            if client.disconnect().is_ok() {
+                let deadline = Instant::now() + Duration::from_secs(1);
+                while Instant::now() < deadline {
+                    match connection.recv_timeout(Duration::from_millis(200)) {
+                        Ok(Ok(MqttEvent::Outgoing(rumqttc::Outgoing::Disconnect))) | Ok(Err(_)) => {
+                            break
+                        }
+                        Err(RecvTimeoutError::Disconnected) => break,
+                        _ => {}
+                    }
+                }
+            }
+            info!("MQTT thread stopped.");
+        },
+    ))
+}
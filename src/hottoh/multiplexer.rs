@@ -0,0 +1,369 @@
+use crate::hottoh::config::MultiplexerConfig;
+use crate::hottoh::hottoh_const::{Command, CommandType};
+use crate::hottoh::hottoh_structs::calculate_checksum;
+use crate::hottoh::locks::{mutex_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_request, CommandOrigin};
+use crate::hottoh::tcp_client_structs::Request;
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay after which a forwarded request without response is forgotten
+const ROUTE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Client waiting for the response to a forwarded request
+struct Route {
+    /// Request ID used by the client
+    client_req_id: u32,
+    /// Channel of the client connection
+    client: Sender<String>,
+    /// When the request was forwarded
+    forwarded_at: Instant,
+}
+
+/// Frame received from a client
+struct ClientFrame {
+    req_id: u32,
+    command: Command,
+    command_type: CommandType,
+    params: Vec<String>,
+}
+
+/// Re-exposes the Hottoh protocol to local clients over the single stove connection
+///
+/// The stove accepts one connection at a time. Clients of the multiplexer (hottohpy,
+/// scripts) talk the raw protocol to the bridge instead: their requests are queued
+/// with the requests of the bridge under a request ID of the bridge, and the stove
+/// responses are sent back with the request ID of the client.
+#[derive(Default)]
+pub struct Multiplexer {
+    /// Forwarded requests by request ID of the bridge
+    routes: Mutex<HashMap<u32, Route>>,
+}
+
+impl Multiplexer {
+    /// Creates a multiplexer without clients
+    ///
+    /// # Returns
+    ///
+    /// * `Multiplexer` - The multiplexer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a request forwarded for a client
+    ///
+    /// # Arguments
+    ///
+    /// * `req_id` - Request ID of the bridge
+    /// * `client_req_id` - Request ID of the client
+    /// * `client` - Channel of the client connection
+    fn add_route(&self, req_id: u32, client_req_id: u32, client: &Sender<String>) {
+        let mut routes = mutex_lock(&self.routes, "multiplexer routes");
+        routes.retain(|_, route| route.forwarded_at.elapsed() < ROUTE_TIMEOUT);
+        routes.insert(
+            req_id,
+            Route {
+                client_req_id,
+                client: client.clone(),
+                forwarded_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Sends a frame received from the stove to the client that requested it, if any
+    ///
+    /// The frame is still processed by the bridge afterwards, so the state is also
+    /// updated by the reads of the clients.
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The raw frame received from the stove
+    pub fn route_response(&self, frame: &str) {
+        let Some(req_id) = frame.get(1..6).and_then(|id| id.parse::<u32>().ok()) else {
+            return;
+        };
+        let Some(route) = mutex_lock(&self.routes, "multiplexer routes").remove(&req_id) else {
+            return;
+        };
+        match rewrite_req_id(frame, route.client_req_id) {
+            Some(response) => {
+                // The client may have disconnected in the meantime
+                let _ = route.client.send(response);
+            }
+            None => warn!(
+                "Cannot forward malformed frame to client: '{}'",
+                frame.trim()
+            ),
+        }
+    }
+}
+
+/// Replaces the request ID of a frame, updating its checksum
+///
+/// # Arguments
+///
+/// * `frame` - The frame (`#<id><header><params><crc>`)
+/// * `req_id` - The new request ID
+///
+/// # Returns
+///
+/// * `Option<String>` - The rewritten frame, or None if it is too short
+fn rewrite_req_id(frame: &str, req_id: u32) -> Option<String> {
+    let frame = frame.trim_end();
+    let body = frame.get(6..frame.len().checked_sub(4)?)?;
+    let body = format!("{:05}{}", req_id, body);
+    let checksum = calculate_checksum(&body);
+    Some(format!("#{}{}\n", body, checksum))
+}
+
+/// Parses a frame received from a client
+///
+/// # Arguments
+///
+/// * `frame` - The frame, without the trailing newline
+///
+/// # Returns
+///
+/// * `Result<ClientFrame, String>` - The request or an error
+fn parse_client_frame(frame: &str) -> Result<ClientFrame, String> {
+    if frame.len() < 22 || !frame.starts_with('#') || !frame.is_ascii() {
+        return Err("frame too short".into());
+    }
+    let crc_start = frame.len() - 4;
+    if calculate_checksum(&frame[1..crc_start]) != frame[crc_start..] {
+        return Err("invalid checksum".into());
+    }
+    let req_id = frame[1..6]
+        .parse::<u32>()
+        .map_err(|_| "invalid request ID".to_string())?;
+    let command = Command::from_str(&frame[14..17])
+        .map_err(|_| format!("unknown command '{}'", &frame[14..17]))?;
+    let command_type = CommandType::from_str(&frame[17..18])
+        .map_err(|_| format!("unknown command type '{}'", &frame[17..18]))?;
+    let params = frame[18..crc_start]
+        .trim_end_matches(';')
+        .split(';')
+        .map(str::to_string)
+        .collect();
+    Ok(ClientFrame {
+        req_id,
+        command,
+        command_type,
+        params,
+    })
+}
+
+/// Starts a thread accepting the clients of the raw protocol
+///
+/// # Arguments
+///
+/// * `config` - The `[multiplexer]` section, or None if absent
+/// * `multiplexer` - Routes of the forwarded requests, shared with the TCP client
+/// * `commands` - Queues used to send requests to the stove
+/// * `shared_state` - Shared state, checked before forwarding writes
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_multiplexer_thread(
+    config: Option<&MultiplexerConfig>,
+    multiplexer: Arc<Multiplexer>,
+    commands: CommandQueue,
+    shared_state: Arc<RwLock<SharedState>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let address = format!("{}:{}", config?.ip, config?.port);
+    let listener = match TcpListener::bind(&address).and_then(|listener| {
+        listener.set_nonblocking(true)?;
+        Ok(listener)
+    }) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Multiplexer disabled: cannot listen on {}: {}", address, e);
+            return None;
+        }
+    };
+    info!("Multiplexing the stove connection on {}", address);
+
+    Some(supervise(
+        "multiplexer",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            while running.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, peer)) => {
+                        info!("Multiplexer client {} connected", peer);
+                        let multiplexer = Arc::clone(&multiplexer);
+                        let commands = commands.clone();
+                        let shared_state = Arc::clone(&shared_state);
+                        let running = Arc::clone(&running);
+                        thread::spawn(move || {
+                            handle_client(
+                                stream,
+                                peer,
+                                &multiplexer,
+                                &commands,
+                                &shared_state,
+                                &running,
+                            );
+                            info!("Multiplexer client {} disconnected", peer);
+                        });
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(200));
+                    }
+                    Err(e) => {
+                        warn!("Multiplexer cannot accept a client: {}", e);
+                        thread::sleep(Duration::from_millis(200));
+                    }
+                }
+            }
+            info!("Multiplexer thread stopped.");
+        },
+    ))
+}
+
+/// Serves a client until it disconnects
+///
+/// # Arguments
+///
+/// * `stream` - Connection of the client
+/// * `peer` - Address of the client
+/// * `multiplexer` - Routes of the forwarded requests
+/// * `commands` - Queues used to send requests to the stove
+/// * `shared_state` - Shared state, checked before forwarding writes
+/// * `running` - Flag indicating whether the application is running
+fn handle_client(
+    mut stream: TcpStream,
+    peer: SocketAddr,
+    multiplexer: &Multiplexer,
+    commands: &CommandQueue,
+    shared_state: &RwLock<SharedState>,
+    running: &AtomicBool,
+) {
+    // Accepted connections may inherit the non-blocking mode of the listener
+    if let Err(e) = stream
+        .set_nonblocking(false)
+        .and_then(|_| stream.set_read_timeout(Some(Duration::from_millis(200))))
+    {
+        warn!("Multiplexer client {}: {}", peer, e);
+        return;
+    }
+    let (sender, receiver) = channel();
+    let mut pending = String::new();
+    let mut buffer = [0; 4096];
+
+    while running.load(Ordering::SeqCst) {
+        match stream.read(&mut buffer) {
+            Ok(0) => return,
+            Ok(size) => pending.push_str(&String::from_utf8_lossy(&buffer[..size])),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => {
+                debug!("Multiplexer client {}: {}", peer, e);
+                return;
+            }
+        }
+
+        while let Some(end) = pending.find('\n') {
+            let frame: String = pending.drain(..=end).collect();
+            let frame = frame.trim();
+            if frame.is_empty() {
+                continue;
+            }
+            match parse_client_frame(frame) {
+                Ok(frame) => forward(frame, peer, multiplexer, commands, shared_state, &sender),
+                Err(e) => warn!(
+                    "Multiplexer client {} sent an invalid frame ({}): '{}'",
+                    peer, e, frame
+                ),
+            }
+        }
+
+        for response in receiver.try_iter() {
+            if let Err(e) = stream.write_all(response.as_bytes()) {
+                debug!("Multiplexer client {}: {}", peer, e);
+                return;
+            }
+        }
+    }
+}
+
+/// Queues the request of a client and records where to send the response
+///
+/// Writes go through the same checks as the other automation commands, so they are
+/// dropped in read-only mode and during maintenance windows.
+///
+/// # Arguments
+///
+/// * `frame` - The request of the client
+/// * `peer` - Address of the client
+/// * `multiplexer` - Routes of the forwarded requests
+/// * `commands` - Queues used to send requests to the stove
+/// * `shared_state` - Shared state, checked before forwarding writes
+/// * `client` - Channel of the client connection
+fn forward(
+    frame: ClientFrame,
+    peer: SocketAddr,
+    multiplexer: &Multiplexer,
+    commands: &CommandQueue,
+    shared_state: &RwLock<SharedState>,
+    client: &Sender<String>,
+) {
+    if frame.command_type == CommandType::Write {
+        let (Some(action), Some(value)) = (
+            frame
+                .params
+                .first()
+                .and_then(|action| action.parse::<u32>().ok()),
+            frame.params.get(1),
+        ) else {
+            warn!(
+                "Multiplexer client {} sent an invalid write: {:?}",
+                peer, frame.params
+            );
+            return;
+        };
+        match queue_write_request(
+            &commands.request_queue,
+            &commands.request_id_counter,
+            shared_state,
+            CommandOrigin::Integration { confirmed: false },
+            action,
+            value,
+        ) {
+            Ok(req_id) => multiplexer.add_route(req_id, frame.req_id, client),
+            Err(e) => warn!(
+                "Multiplexer client {} write {}={} rejected: {}",
+                peer, action, value, e
+            ),
+        }
+        return;
+    }
+
+    let mut id_lock = mutex_lock(&commands.request_id_counter, "request ID counter");
+    let req_id = *id_lock;
+    multiplexer.add_route(req_id, frame.req_id, client);
+    write_lock(&commands.request_queue, "request queue").push_back(Request::new(
+        req_id,
+        frame.command,
+        frame.command_type,
+        frame.params,
+    ));
+    *id_lock = (*id_lock + 1) % 100000;
+}
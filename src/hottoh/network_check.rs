@@ -0,0 +1,92 @@
+use crate::hottoh::config::StoveConfig;
+use chrono::Local;
+use serde::Serialize;
+use std::process::{Command, Stdio};
+
+/// Result of the reachability checks made after a failed connection
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkDiagnosis {
+    /// Time of the checks, in RFC 3339
+    pub checked_at: String,
+    /// Whether the stove answers the ping, None if ping could not be run
+    pub stove_reachable: Option<bool>,
+    /// Whether the gateway answers the ping, None if no gateway is configured or ping
+    /// could not be run
+    pub gateway_reachable: Option<bool>,
+    /// `network_down`, `stove_down`, `stove_port_closed` or `unknown`
+    pub reason: &'static str,
+}
+
+/// Ping of the stove and of the gateway when the stove cannot be reached, telling a
+/// stove down from a network down
+///
+/// The system `ping` command is used, since ICMP sockets need privileges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkCheck {
+    /// Host of the stove
+    stove: String,
+    /// Host of the gateway, if configured
+    gateway: Option<String>,
+}
+
+impl NetworkCheck {
+    /// Creates the check from the `[stove]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[stove]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Option<NetworkCheck>` - The check, None if `ping_check` is disabled
+    pub fn from_config(config: &StoveConfig) -> Option<Self> {
+        config.ping_check.then(|| Self {
+            stove: config.ip.trim().to_string(),
+            gateway: Some(config.gateway.trim())
+                .filter(|gateway| !gateway.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Pings the gateway and the stove
+    ///
+    /// # Returns
+    ///
+    /// * `NetworkDiagnosis` - The results, with the most likely reason of the failure
+    pub fn run(&self) -> NetworkDiagnosis {
+        let gateway_reachable = self.gateway.as_deref().and_then(ping);
+        let stove_reachable = ping(&self.stove);
+        let reason = match (gateway_reachable, stove_reachable) {
+            (Some(false), _) => "network_down",
+            (_, Some(false)) => "stove_down",
+            (_, Some(true)) => "stove_port_closed",
+            (_, None) => "unknown",
+        };
+        NetworkDiagnosis {
+            checked_at: Local::now().to_rfc3339(),
+            stove_reachable,
+            gateway_reachable,
+            reason,
+        }
+    }
+}
+
+/// Sends a single ping, waiting for the answer at most 1 second
+///
+/// # Arguments
+///
+/// * `host` - The host to ping
+///
+/// # Returns
+///
+/// * `Option<bool>` - Whether the host answered, None if ping could not be run
+fn ping(host: &str) -> Option<bool> {
+    Command::new("ping")
+        .args(["-c", "1", "-W", "1", host])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .ok()
+        .map(|status| status.success())
+}
@@ -0,0 +1,357 @@
+use crate::hottoh::config::HttpApiConfig;
+use crate::hottoh::http_api::ApiError;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpRequest, ResponseError};
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Header listing the client and the proxies a request went through
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Range of IP addresses in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    /// Parses a network such as `192.168.1.0/24`, or a single address
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The network in CIDR notation
+    ///
+    /// # Returns
+    ///
+    /// * `Result<IpNetwork, String>` - The network, or an error if it is invalid
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (address, prefix) = match spec.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (spec, None),
+        };
+        let address = address
+            .trim()
+            .parse::<IpAddr>()
+            .map_err(|_| format!("'{}' is not an IP address or network", spec))?
+            .to_canonical();
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", spec))?,
+            None => max_prefix,
+        };
+        Ok(Self { address, prefix })
+    }
+
+    /// Checks if an address belongs to the network
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The address
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the address is in the network
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Parses a comma-separated list of networks
+///
+/// # Arguments
+///
+/// * `spec` - The list (e.g. `192.168.1.0/24, 10.8.0.0/16`)
+///
+/// # Returns
+///
+/// * `Result<Vec<IpNetwork>, String>` - The networks, or the first error
+pub fn parse_networks(spec: &str) -> Result<Vec<IpNetwork>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|network| !network.is_empty())
+        .map(IpNetwork::parse)
+        .collect()
+}
+
+/// Restriction of the clients allowed to use the HTTP API
+pub struct NetworkFilter {
+    /// Networks allowed to use the API (empty allows every client)
+    allowed: Vec<IpNetwork>,
+    /// Reverse proxies whose `X-Forwarded-For` header is trusted
+    trusted_proxies: Vec<IpNetwork>,
+}
+
+impl NetworkFilter {
+    /// Creates the filter from the HTTP API configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The HTTP API configuration
+    ///
+    /// # Returns
+    ///
+    /// * `Result<NetworkFilter, String>` - The filter, or an error if a network is invalid
+    pub fn from_config(config: &HttpApiConfig) -> Result<Self, String> {
+        Ok(Self {
+            allowed: parse_networks(&config.allowed_networks)
+                .map_err(|e| format!("http_api.allowed_networks: {}", e))?,
+            trusted_proxies: parse_networks(&config.trusted_proxies)
+                .map_err(|e| format!("http_api.trusted_proxies: {}", e))?,
+        })
+    }
+
+    /// Finds the address of the client behind the trusted proxies
+    ///
+    /// The `X-Forwarded-For` header is only used when the request comes from a trusted
+    /// proxy, and is read from the right so that a client cannot spoof its address by
+    /// sending the header itself.
+    ///
+    /// # Arguments
+    ///
+    /// * `peer` - Address of the connected peer
+    /// * `forwarded_for` - Value of the `X-Forwarded-For` header, if any
+    ///
+    /// # Returns
+    ///
+    /// * `IpAddr` - The address of the client
+    fn client_ip(&self, peer: IpAddr, forwarded_for: Option<&str>) -> IpAddr {
+        let mut client = peer;
+        if !self.is_trusted_proxy(client) {
+            return client;
+        }
+        let Some(forwarded_for) = forwarded_for else {
+            return client;
+        };
+        for hop in forwarded_for.rsplit(',') {
+            match hop.trim().parse::<IpAddr>() {
+                Ok(ip) => client = ip,
+                Err(_) => break,
+            }
+            if !self.is_trusted_proxy(client) {
+                break;
+            }
+        }
+        client
+    }
+
+    /// Checks if an address is a trusted proxy
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The address
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the address is in one of the trusted proxy networks
+    fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies
+            .iter()
+            .any(|network| network.contains(ip))
+    }
+
+    /// Checks if a client is allowed to use the API
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - Address of the client
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if no network is configured or the address is in one of them
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        self.allowed.is_empty() || self.allowed.iter().any(|network| network.contains(ip))
+    }
+}
+
+/// Middleware rejecting the clients outside of the allowed networks with 403
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<BoxBody>, Error>` - The response of the service, or 403
+pub async fn filter_request(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let filter = req.app_data::<web::Data<Arc<NetworkFilter>>>().cloned();
+    let Some(filter) = filter.filter(|filter| !filter.allowed.is_empty()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    let Some(client) = request_client_ip(req.request()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if filter.is_allowed(client) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let response = ApiError::Forbidden(format!("client {} is not in allowed_networks", client))
+        .error_response();
+    Ok(req.into_response(response))
+}
+
+/// Finds the address of the client of a request, behind the trusted proxies
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+///
+/// # Returns
+///
+/// * `Option<IpAddr>` - The address of the client, None if the peer is unknown
+pub fn request_client_ip(req: &HttpRequest) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let forwarded_for = req
+        .headers()
+        .get(FORWARDED_FOR_HEADER)
+        .and_then(|value| value.to_str().ok());
+    Some(match req.app_data::<web::Data<Arc<NetworkFilter>>>() {
+        Some(filter) => filter.client_ip(peer, forwarded_for),
+        None => peer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    fn filter(allowed: &str, trusted_proxies: &str) -> NetworkFilter {
+        NetworkFilter {
+            allowed: parse_networks(allowed).unwrap(),
+            trusted_proxies: parse_networks(trusted_proxies).unwrap(),
+        }
+    }
+
+    #[test]
+    fn parse_accepts_networks_and_single_addresses() {
+        let network = IpNetwork::parse(" 192.168.1.0 / 24 ").unwrap();
+        assert_eq!(network.address, ip("192.168.1.0"));
+        assert_eq!(network.prefix, 24);
+        assert_eq!(IpNetwork::parse("10.0.0.1").unwrap().prefix, 32);
+        assert_eq!(IpNetwork::parse("fd00::/8").unwrap().prefix, 8);
+        assert_eq!(IpNetwork::parse("::1").unwrap().prefix, 128);
+        // IPv4-mapped IPv6 addresses are handled as IPv4
+        let mapped = IpNetwork::parse("::ffff:10.1.2.3/32").unwrap();
+        assert_eq!(mapped.address, ip("10.1.2.3"));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_networks() {
+        assert_eq!(
+            IpNetwork::parse("192.168.1/24").unwrap_err(),
+            "'192.168.1/24' is not an IP address or network"
+        );
+        assert_eq!(
+            IpNetwork::parse("10.0.0.0/33").unwrap_err(),
+            "invalid prefix length in '10.0.0.0/33'"
+        );
+        assert!(IpNetwork::parse("fd00::/129").is_err());
+        assert!(IpNetwork::parse("10.0.0.0/x").is_err());
+        assert!(IpNetwork::parse("").is_err());
+    }
+
+    #[test]
+    fn contains_masks_the_prefix() {
+        let lan = IpNetwork::parse("192.168.1.0/24").unwrap();
+        assert!(lan.contains(ip("192.168.1.0")));
+        assert!(lan.contains(ip("192.168.1.255")));
+        assert!(lan.contains(ip("::ffff:192.168.1.7")));
+        assert!(!lan.contains(ip("192.168.2.1")));
+        assert!(!lan.contains(ip("fd00::1")));
+
+        let odd = IpNetwork::parse("10.0.0.0/13").unwrap();
+        assert!(odd.contains(ip("10.7.255.255")));
+        assert!(!odd.contains(ip("10.8.0.0")));
+
+        let everything = IpNetwork::parse("0.0.0.0/0").unwrap();
+        assert!(everything.contains(ip("203.0.113.9")));
+        assert!(!everything.contains(ip("::1")));
+
+        let host = IpNetwork::parse("10.0.0.1").unwrap();
+        assert!(host.contains(ip("10.0.0.1")));
+        assert!(!host.contains(ip("10.0.0.2")));
+
+        let ula = IpNetwork::parse("fd00::/8").unwrap();
+        assert!(ula.contains(ip("fdab::1")));
+        assert!(!ula.contains(ip("fe80::1")));
+        assert!(IpNetwork::parse("::/0")
+            .unwrap()
+            .contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn parse_networks_skips_empty_items_and_reports_errors() {
+        assert!(parse_networks("").unwrap().is_empty());
+        assert_eq!(
+            parse_networks("192.168.1.0/24, ,10.8.0.0/16,")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            parse_networks("10.0.0.0/8, nope").unwrap_err(),
+            "'nope' is not an IP address or network"
+        );
+    }
+
+    #[test]
+    fn is_allowed_without_networks_allows_everyone() {
+        assert!(filter("", "").is_allowed(ip("203.0.113.9")));
+        let filter = filter("192.168.1.0/24, 10.8.0.0/16", "");
+        assert!(filter.is_allowed(ip("10.8.3.4")));
+        assert!(!filter.is_allowed(ip("203.0.113.9")));
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_for_from_untrusted_peers() {
+        let filter = filter("192.168.1.0/24", "127.0.0.1");
+        assert_eq!(
+            filter.client_ip(ip("203.0.113.9"), Some("192.168.1.5")),
+            ip("203.0.113.9")
+        );
+        assert_eq!(filter.client_ip(ip("127.0.0.1"), None), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn client_ip_reads_forwarded_for_from_the_right() {
+        let filter = filter("192.168.1.0/24", "127.0.0.1, 10.0.0.0/8");
+        // A client spoofing the header only adds hops on the left
+        assert_eq!(
+            filter.client_ip(ip("127.0.0.1"), Some("192.168.1.5, 203.0.113.9, 10.0.0.2")),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            filter.client_ip(ip("127.0.0.1"), Some("192.168.1.5, 10.0.0.2")),
+            ip("192.168.1.5")
+        );
+        // An invalid hop stops at the last trusted address
+        assert_eq!(
+            filter.client_ip(ip("127.0.0.1"), Some("192.168.1.5, unknown, 10.0.0.2")),
+            ip("10.0.0.2")
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::hottoh::error::HottohError;
+use crate::hottoh::state_fields::get_field;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Bound of a settable value
+enum Bound {
+    /// Reported by the stove in a field, as `page.field`
+    Field(&'static str),
+    /// Fixed by the protocol
+    Fixed(f64),
+}
+
+/// Value that can be set through the API, with the fields describing it
+struct SettableField {
+    /// Name of the value in the normalized state
+    name: &'static str,
+    /// Field holding the measured value, as `page.field`
+    value: &'static str,
+    /// Field holding the setpoint, as `page.field`
+    set: &'static str,
+    /// Lowest setpoint
+    min: Bound,
+    /// Highest setpoint
+    max: Bound,
+    /// Smallest change of the setpoint
+    step: f64,
+}
+
+/// Values that can be set through the API
+const SETTABLE_FIELDS: &[SettableField] = &[
+    SettableField {
+        name: "power_level",
+        value: "dat0.index_power_level",
+        set: "dat0.index_power_set",
+        min: Bound::Field("dat0.index_power_min"),
+        max: Bound::Field("dat0.index_power_max"),
+        step: 1.0,
+    },
+    SettableField {
+        name: "ambiance_temp_1",
+        value: "dat0.index_ambient_t1",
+        set: "dat0.index_ambient_t1_set",
+        min: Bound::Field("dat0.index_ambient_t1_set_min"),
+        max: Bound::Field("dat0.index_ambient_t1_set_max"),
+        step: 0.1,
+    },
+    SettableField {
+        name: "ambiance_temp_2",
+        value: "dat0.index_ambient_t2",
+        set: "dat0.index_ambient_t2_set",
+        min: Bound::Field("dat0.index_ambient_t2_set_min"),
+        max: Bound::Field("dat0.index_ambient_t2_set_max"),
+        step: 0.1,
+    },
+    SettableField {
+        name: "fan_speed_1",
+        value: "dat0.index_fan_1",
+        set: "dat0.index_fan_1_set",
+        min: Bound::Fixed(0.0),
+        max: Bound::Field("dat0.index_fan_1_set_max"),
+        step: 1.0,
+    },
+    SettableField {
+        name: "fan_speed_2",
+        value: "dat0.index_fan_2",
+        set: "dat0.index_fan_2_set",
+        min: Bound::Fixed(0.0),
+        max: Bound::Field("dat0.index_fan_2_set_max"),
+        step: 1.0,
+    },
+    SettableField {
+        name: "fan_speed_3",
+        value: "dat0.index_fan_3",
+        set: "dat0.index_fan_3_set",
+        min: Bound::Fixed(0.0),
+        max: Bound::Field("dat0.index_fan_3_set_max"),
+        step: 1.0,
+    },
+];
+
+/// Settable value with the range accepted by the stove
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct NormalizedValue {
+    /// Measured value, null until received
+    value: Option<f64>,
+    /// Setpoint, or the value being written if any
+    set: Option<f64>,
+    /// Lowest setpoint
+    min: Option<f64>,
+    /// Highest setpoint
+    max: Option<f64>,
+    /// Smallest change of the setpoint
+    step: f64,
+}
+
+/// Gets a numeric field from the serialized shared state
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+/// * `field` - The field, as `page.field`
+///
+/// # Returns
+///
+/// * `Option<f64>` - The value, None if its page was not received yet
+fn get_number(state: &Value, field: &str) -> Option<f64> {
+    get_field(state, field).and_then(Value::as_f64)
+}
+
+/// Builds the settable values with their range, so that clients can render them
+/// without knowing the limits of each stove model
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+///
+/// # Returns
+///
+/// * `BTreeMap<&'static str, NormalizedValue>` - The values by name
+pub fn normalize(state: &Value) -> BTreeMap<&'static str, NormalizedValue> {
+    let bound = |bound: &Bound| match bound {
+        Bound::Field(field) => get_number(state, field),
+        Bound::Fixed(value) => Some(*value),
+    };
+    SETTABLE_FIELDS
+        .iter()
+        .map(|settable| {
+            let (_, set_name) = settable.set.split_once('.').unwrap_or_default();
+            let set = get_number(state, &format!("pending.{}", set_name))
+                .or_else(|| get_number(state, settable.set));
+            (
+                settable.name,
+                NormalizedValue {
+                    value: get_number(state, settable.value),
+                    set,
+                    min: bound(&settable.min),
+                    max: bound(&settable.max),
+                    step: settable.step,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Checks a setpoint against the range reported by the stove
+///
+/// The value is accepted if the range was not received yet, since the stove then
+/// checks it itself. A range reduced to a single value (e.g. a fan with a highest
+/// speed of 0) means the stove does not have the feature.
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+/// * `name` - Name of the value in the normalized state (e.g. `fan_speed_2`)
+/// * `value` - The setpoint
+///
+/// # Returns
+///
+/// * `Result<(), HottohError>` - Success, or a `NotSupported` or `OutOfRange` error
+pub fn check_setpoint(state: &Value, name: &str, value: f64) -> Result<(), HottohError> {
+    let normalized = normalize(state);
+    let Some(range) = normalized.get(name) else {
+        return Err(HottohError::NotSupported(format!("{} cannot be set", name)));
+    };
+    if !value.is_finite() {
+        return Err(HottohError::OutOfRange(format!("{} {}", name, value)));
+    }
+    let (Some(min), Some(max)) = (range.min, range.max) else {
+        return Ok(());
+    };
+    if max <= min {
+        return Err(HottohError::NotSupported(format!(
+            "{} is not available on this stove",
+            name
+        )));
+    }
+    if !(min..=max).contains(&value) {
+        return Err(HottohError::OutOfRange(format!(
+            "{} {} outside of {} to {}",
+            name, value, min, max
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,269 @@
+use crate::hottoh::config::NotificationsConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::http_client;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::supervisor::supervise;
+use log::{debug, info, warn};
+use minijinja::Environment;
+use serde_json::{json, Value};
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Base URL of the Telegram Bot API
+const TELEGRAM_API: &str = "https://api.telegram.org";
+
+/// Channel a notification is sent through, each with its own template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    /// POST to `notifications.webhook_url`
+    Webhook,
+    /// Publish to `notifications.mqtt_topic`
+    Mqtt,
+    /// Message of the Telegram bot
+    Telegram,
+}
+
+impl Channel {
+    /// Gets the name of the channel, naming its template
+    ///
+    /// # Returns
+    ///
+    /// * `&'static str` - The name
+    fn name(&self) -> &'static str {
+        match self {
+            Channel::Webhook => "webhook",
+            Channel::Mqtt => "mqtt",
+            Channel::Telegram => "telegram",
+        }
+    }
+}
+
+/// Notifications of the `[notifications]` section, rendering the payloads of the events
+pub struct Notifications {
+    /// Event types notified
+    events: Vec<String>,
+    /// Templates of the channels that have one, by channel name
+    templates: Environment<'static>,
+    /// Shared state, given to the templates
+    shared_state: Arc<RwLock<SharedState>>,
+}
+
+impl Notifications {
+    /// Creates the notifications from the configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The notifications configuration
+    /// * `shared_state` - Shared state, given to the templates
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Notifications, String>` - The notifications, or an error if a template
+    ///   cannot be read or does not compile
+    pub fn from_config(
+        config: &NotificationsConfig,
+        shared_state: Arc<RwLock<SharedState>>,
+    ) -> Result<Self, String> {
+        let mut templates = Environment::new();
+        for (channel, template) in [
+            (Channel::Webhook, &config.webhook_template),
+            (Channel::Mqtt, &config.mqtt_template),
+            (Channel::Telegram, &config.telegram_template),
+        ] {
+            let Some(template) = template else {
+                continue;
+            };
+            let source = load_template(template)
+                .map_err(|e| format!("{}_template: {}", channel.name(), e))?;
+            templates
+                .add_template_owned(channel.name(), source)
+                .map_err(|e| format!("{}_template: {}", channel.name(), e))?;
+        }
+        Ok(Self {
+            events: config
+                .events
+                .split(',')
+                .map(str::trim)
+                .filter(|event| !event.is_empty())
+                .map(str::to_string)
+                .collect(),
+            templates,
+            shared_state,
+        })
+    }
+
+    /// Renders the payload of an event for a channel
+    ///
+    /// # Arguments
+    ///
+    /// * `channel` - The channel
+    /// * `event` - The event
+    ///
+    /// # Returns
+    ///
+    /// * `Option<String>` - The payload, or None if the event type is not notified or
+    ///   the template fails (logged)
+    pub fn render(&self, channel: Channel, event: &Event) -> Option<String> {
+        let event = serde_json::to_value(event).ok()?;
+        let kind = event.get("type").and_then(Value::as_str)?;
+        if !self.events.iter().any(|notified| notified == kind) {
+            return None;
+        }
+        let Ok(template) = self.templates.get_template(channel.name()) else {
+            return Some(event.to_string());
+        };
+        let state = serde_json::to_value(&*read_lock(&self.shared_state, "shared state"))
+            .unwrap_or_default();
+        let context = json!({
+            "event": event,
+            "state": state,
+            "time": chrono::Local::now().to_rfc3339(),
+        });
+        match template.render(context) {
+            Ok(payload) => Some(payload),
+            Err(e) => {
+                warn!("Cannot render the {} notification: {}", channel.name(), e);
+                None
+            }
+        }
+    }
+}
+
+/// Loads a template
+///
+/// # Arguments
+///
+/// * `template` - The template, or `@` followed by the path of the file holding it
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The source of the template, or an error if the file
+///   cannot be read
+fn load_template(template: &str) -> Result<String, String> {
+    match template.strip_prefix('@') {
+        Some(path) => {
+            fs::read_to_string(path.trim()).map_err(|e| format!("cannot read {}: {}", path, e))
+        }
+        None => Ok(template.to_string()),
+    }
+}
+
+/// Checks the notifications configuration
+///
+/// # Arguments
+///
+/// * `config` - The notifications configuration
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or the first error
+pub fn check_notifications(config: &NotificationsConfig) -> Result<(), String> {
+    Notifications::from_config(config, Arc::new(RwLock::new(SharedState::new())))?;
+    if config.telegram_token.is_some() != config.telegram_chat_id.is_some() {
+        return Err("telegram_token and telegram_chat_id go together".into());
+    }
+    Ok(())
+}
+
+/// Sends a notification through an HTTP channel
+///
+/// # Arguments
+///
+/// * `channel` - The channel, used in the logs
+/// * `url` - URL the payload is POSTed to
+/// * `payload` - The body
+fn post(channel: Channel, url: &str, payload: &str) {
+    let headers = [("Content-Type", "application/json")];
+    match http_client::send("POST", url, &headers, payload) {
+        Ok(response) if response.is_success() => {
+            debug!("Sent the {} notification", channel.name())
+        }
+        Ok(response) => warn!(
+            "The {} notification was rejected (HTTP {}): {}",
+            channel.name(),
+            response.status,
+            response.body
+        ),
+        Err(e) => warn!("Cannot send the {} notification: {}", channel.name(), e),
+    }
+}
+
+/// Starts a thread sending the webhook and Telegram notifications of the events
+///
+/// The MQTT notifications are published by the MQTT thread, through its connection to
+/// the broker.
+///
+/// # Arguments
+///
+/// * `config` - The notifications configuration, or None if the section is absent
+/// * `notifications` - The notifications, rendering the payloads
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if
+///   disabled or without webhook nor Telegram bot
+pub fn start_notifications_thread(
+    config: Option<&NotificationsConfig>,
+    notifications: Option<Arc<Notifications>>,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let notifications = notifications?;
+    let webhook_url = config.webhook_url.clone();
+    let telegram = config
+        .telegram_token
+        .as_ref()
+        .zip(config.telegram_chat_id.as_ref())
+        .map(|(token, chat_id)| {
+            (
+                format!("{}/bot{}/sendMessage", TELEGRAM_API, token),
+                chat_id.clone(),
+            )
+        });
+    if webhook_url.is_none() && telegram.is_none() {
+        return None;
+    }
+    info!(
+        "Sending the notifications of {}",
+        notifications.events.join(", ")
+    );
+
+    Some(supervise(
+        "notifications",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events =
+                Arc::clone(read_lock(&notifications.shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            while running.load(Ordering::SeqCst) {
+                let event = match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(event) => event,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if let Some(url) = &webhook_url {
+                    if let Some(payload) = notifications.render(Channel::Webhook, &event) {
+                        post(Channel::Webhook, url, &payload);
+                    }
+                }
+                if let Some((url, chat_id)) = &telegram {
+                    if let Some(text) = notifications.render(Channel::Telegram, &event) {
+                        let body = json!({ "chat_id": chat_id, "text": text });
+                        post(Channel::Telegram, url, &body.to_string());
+                    }
+                }
+            }
+            info!("Notifications thread stopped.");
+        },
+    ))
+}
@@ -0,0 +1,255 @@
+use crate::hottoh::config::OpenHabConfig;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::http_client;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, parse_field_mapping, url_encode};
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_request, CommandOrigin};
+use crate::hottoh::tcp_client_structs::Request;
+use crate::hottoh::temperature::to_tenths;
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Shared queues used to send commands to the stove
+#[derive(Clone)]
+pub struct CommandQueue {
+    /// Queue of requests to be sent to the stove
+    pub request_queue: Arc<RwLock<VecDeque<Request>>>,
+    /// Counter for generating unique request IDs
+    pub request_id_counter: Arc<Mutex<u32>>,
+}
+
+/// Starts a thread synchronizing openHAB items with the stove
+///
+/// State fields are pushed to their items when they change. Command items are
+/// polled, and a change of their state sends the matching command to the stove.
+/// The state of a command item at startup is only recorded, not sent.
+///
+/// # Arguments
+///
+/// * `config` - The openHAB configuration, or None if the section is absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_openhab_thread(
+    config: Option<&OpenHabConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let items = match parse_field_mapping(&config.items) {
+        Ok(items) => items,
+        Err(e) => {
+            error!("openHAB sync disabled: invalid items: {}", e);
+            return None;
+        }
+    };
+    let command_items = match parse_command_items(&config.commands) {
+        Ok(command_items) => command_items,
+        Err(e) => {
+            error!("openHAB sync disabled: invalid commands: {}", e);
+            return None;
+        }
+    };
+    if items.is_empty() && command_items.is_empty() {
+        error!("openHAB sync disabled: no item configured");
+        return None;
+    }
+
+    let base_url = config.url.trim_end_matches('/').to_string();
+    let authorization = (!config.token.is_empty()).then(|| format!("Bearer {}", config.token));
+    let interval = Duration::from_secs(config.interval_secs.max(1));
+    info!(
+        "Synchronizing {} state item(s) and {} command item(s) with openHAB at {}",
+        items.len(),
+        command_items.len(),
+        base_url
+    );
+
+    Some(supervise(
+        "openhab",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let mut headers = vec![("Content-Type", "text/plain"), ("Accept", "text/plain")];
+            if let Some(authorization) = &authorization {
+                headers.push(("Authorization", authorization.as_str()));
+            }
+            let mut last_pushed: HashMap<String, String> = HashMap::new();
+            let mut last_polled: HashMap<String, String> = HashMap::new();
+            let mut last_sync = Instant::now() - interval;
+
+            while running.load(Ordering::SeqCst) {
+                if last_sync.elapsed() < interval {
+                    thread::sleep(Duration::from_millis(200));
+                    continue;
+                }
+                last_sync = Instant::now();
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for openHAB: {}", e);
+                        continue;
+                    }
+                };
+
+                for (field, item) in &items {
+                    let Some(value) = get_field(&state, field).and_then(item_state) else {
+                        continue;
+                    };
+                    if last_pushed.get(item) == Some(&value) {
+                        continue;
+                    }
+                    let url = format!("{}/rest/items/{}/state", base_url, url_encode(item));
+                    match http_client::send("PUT", &url, &headers, &value) {
+                        Ok(response) if response.is_success() => {
+                            debug!("Updated openHAB item {} to {}", item, value);
+                            last_pushed.insert(item.clone(), value);
+                        }
+                        Ok(response) => warn!(
+                            "openHAB rejected {}={} (HTTP {}): {}",
+                            item, value, response.status, response.body
+                        ),
+                        Err(e) => {
+                            warn!("Cannot update openHAB item {}: {}", item, e);
+                            break;
+                        }
+                    }
+                }
+
+                for (command, item) in &command_items {
+                    let url = format!("{}/rest/items/{}/state", base_url, url_encode(item));
+                    let polled = match http_client::send("GET", &url, &headers, "") {
+                        Ok(response) if response.is_success() => response.body.trim().to_string(),
+                        Ok(response) => {
+                            warn!(
+                                "Cannot read openHAB item {} (HTTP {})",
+                                item, response.status
+                            );
+                            continue;
+                        }
+                        Err(e) => {
+                            warn!("Cannot read openHAB item {}: {}", item, e);
+                            break;
+                        }
+                    };
+                    let previous = last_polled.insert(item.clone(), polled.clone());
+                    if previous.is_none() || previous.as_ref() == Some(&polled) {
+                        continue;
+                    }
+
+                    match command_value(command, &polled) {
+                        Some(value) => match queue_write_request(
+                            &commands.request_queue,
+                            &commands.request_id_counter,
+                            &shared_state,
+                            CommandOrigin::Integration { confirmed: false },
+                            *command as u32,
+                            &value,
+                        ) {
+                            Ok(request_id) => info!(
+                                "openHAB item {} changed to {}: queued {:?}={} (id {})",
+                                item, polled, command, value, request_id
+                            ),
+                            Err(e) => warn!(
+                                "openHAB item {} changed to {}: {:?}={} rejected: {}",
+                                item, polled, command, value, e
+                            ),
+                        },
+                        None => debug!("Ignoring openHAB item {} state '{}'", item, polled),
+                    }
+                }
+            }
+            info!("openHAB sync thread stopped.");
+        },
+    ))
+}
+
+/// Parses the mapping of stove commands to openHAB items
+///
+/// The mapping is a comma-separated list of `Command=item`, where `Command` is a
+/// StoveCommands name (e.g. `OnOff=Stove_Switch,PowerLevel=Stove_Power`).
+///
+/// # Arguments
+///
+/// * `spec` - The mapping
+///
+/// # Returns
+///
+/// * `Result<Vec<(StoveCommands, String)>, String>` - The (command, item) pairs or an error
+pub fn parse_command_items(spec: &str) -> Result<Vec<(StoveCommands, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (command, name) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected Command=item, got '{}'", item))?;
+            let command = StoveCommands::from_str(command.trim())
+                .map_err(|_| format!("unknown command '{}'", command.trim()))?;
+            Ok((command, name.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Converts a state field to an openHAB item state
+///
+/// # Arguments
+///
+/// * `value` - The field value
+///
+/// # Returns
+///
+/// * `Option<String>` - The item state (booleans as ON/OFF), or None for null values
+fn item_state(value: &Value) -> Option<String> {
+    match value {
+        Value::Bool(value) => Some(if *value { "ON" } else { "OFF" }.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// Converts an openHAB item state to the value of a stove command
+///
+/// # Arguments
+///
+/// * `command` - The stove command
+/// * `state` - The item state (ON/OFF, or a number with an optional unit)
+///
+/// # Returns
+///
+/// * `Option<i32>` - The value sent to the stove (temperatures in tenths of a degree),
+///   or None if the state is not usable (e.g. NULL or UNDEF)
+fn command_value(command: &StoveCommands, state: &str) -> Option<i32> {
+    match state {
+        "ON" => return Some(1),
+        "OFF" => return Some(0),
+        _ => {}
+    }
+    let number: f64 = state.split_whitespace().next()?.parse().ok()?;
+    if !number.is_finite() {
+        return None;
+    }
+    if command.is_temperature() {
+        Some(to_tenths(number))
+    } else {
+        Some(number.round() as i32)
+    }
+}
@@ -0,0 +1,123 @@
+use crate::hottoh::api_keys::Role;
+use crate::hottoh::locks::mutex_lock;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Validity of a pairing code
+pub const PAIRING_TTL: Duration = Duration::from_secs(600);
+/// Wrong codes accepted before the current code is dropped
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Pairing code waiting to be used
+struct PairingCode {
+    code: String,
+    /// Role of the API key issued to the client
+    role: Role,
+    expires_at: Instant,
+    attempts: u32,
+}
+
+/// One-time codes exchanged by new clients for an API key
+///
+/// A single code is valid at a time. It is dropped once used, when it expires, or
+/// after 5 wrong attempts, so it cannot be guessed.
+#[derive(Default)]
+pub struct PairingCodes {
+    current: Mutex<Option<PairingCode>>,
+}
+
+impl PairingCodes {
+    /// Creates the store without a valid code
+    ///
+    /// # Returns
+    ///
+    /// * `PairingCodes` - The code store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Generates a new code, replacing the previous one
+    ///
+    /// # Arguments
+    ///
+    /// * `role` - Role of the API key issued to the client using the code
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, String>` - The 6-digit code, or an error if no random bytes are available
+    pub fn generate(&self, role: Role) -> Result<String, String> {
+        let mut bytes = [0u8; 4];
+        getrandom::getrandom(&mut bytes).map_err(|e| format!("cannot generate code: {}", e))?;
+        let code = format!("{:06}", u32::from_le_bytes(bytes) % 1_000_000);
+        *mutex_lock(&self.current, "pairing code") = Some(PairingCode {
+            code: code.clone(),
+            role,
+            expires_at: Instant::now() + PAIRING_TTL,
+            attempts: 0,
+        });
+        Ok(code)
+    }
+
+    /// Consumes the code
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The code received
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Role>` - The role of the key to issue, or None if the code is invalid
+    pub fn redeem(&self, code: &str) -> Option<Role> {
+        let mut current = mutex_lock(&self.current, "pairing code");
+        let pairing = current.as_mut()?;
+        if pairing.expires_at <= Instant::now() {
+            *current = None;
+            return None;
+        }
+        if pairing.code == code.trim() {
+            return current.take().map(|pairing| pairing.role);
+        }
+        pairing.attempts += 1;
+        if pairing.attempts >= MAX_ATTEMPTS {
+            *current = None;
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redeem_once() {
+        let pairing = PairingCodes::new();
+        assert_eq!(pairing.redeem("000000"), None);
+        let code = pairing.generate(Role::Read).unwrap();
+        assert_eq!(code.len(), 6);
+        assert_eq!(pairing.redeem(&format!(" {} ", code)), Some(Role::Read));
+        assert_eq!(pairing.redeem(&code), None);
+    }
+
+    #[test]
+    fn test_attempts() {
+        let pairing = PairingCodes::new();
+        let code = pairing.generate(Role::Control).unwrap();
+        let wrong = if code == "999999" { "000000" } else { "999999" };
+        for _ in 0..MAX_ATTEMPTS {
+            assert_eq!(pairing.redeem(wrong), None);
+        }
+        assert_eq!(pairing.redeem(&code), None);
+    }
+
+    #[test]
+    fn test_generate_replaces() {
+        let pairing = PairingCodes::new();
+        let first = pairing.generate(Role::Read).unwrap();
+        let second = pairing.generate(Role::Control).unwrap();
+        if first != second {
+            assert_eq!(pairing.redeem(&first), None);
+        }
+        assert_eq!(pairing.redeem(&second), Some(Role::Control));
+    }
+}
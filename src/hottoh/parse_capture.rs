@@ -0,0 +1,265 @@
+use crate::hottoh::layouts::FirmwareInfo;
+use crate::hottoh::tcp_client::split_frames;
+use crate::hottoh::tcp_client_structs::Response;
+use serde_json::Value;
+use std::fs;
+
+/// Usage of the parse-capture subcommand
+const USAGE: &str = "Usage: hottoh_api parse-capture <frames.log>
+
+Parses the frames received from a stove, as captured in the frames.log file of the
+diagnostics bundle (or one raw frame per line), with the same layout detection as
+the bridge. Reports the frames that cannot be parsed and the extra params of the
+DAT pages, to triage unsupported stoves. Exits with 1 if a frame cannot be parsed.";
+
+/// Runs `hottoh_api parse-capture <frames.log>`
+///
+/// # Arguments
+///
+/// * `args` - The arguments following `parse-capture`
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success if every received frame was parsed, or a summary
+///   of the failures
+pub fn run_parse_capture(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [flag] if flag == "--help" || flag == "-h" => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        [path] => path,
+        [] => return Err(format!("Missing capture file\n\n{}", USAGE)),
+        _ => return Err(format!("Too many arguments\n\n{}", USAGE)),
+    };
+    let capture = fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path, e))?;
+
+    let mut firmware = FirmwareInfo::default();
+    let (mut parsed, mut failed) = (0, 0);
+    for (number, line) in capture.lines().enumerate() {
+        let Some(data) = received_data(line) else {
+            continue;
+        };
+        for frame in split_frames(data) {
+            match parse_frame(frame, &mut firmware) {
+                Ok(response) => {
+                    parsed += 1;
+                    println!("line {}: {}", number + 1, summarize(&response));
+                }
+                Err(e) => {
+                    failed += 1;
+                    println!("line {}: ERROR {} in '{}'", number + 1, e, frame.trim_end());
+                }
+            }
+        }
+    }
+    println!(
+        "{} frame(s) parsed, {} failed, {} DAT layout",
+        parsed,
+        failed,
+        firmware.layout().name
+    );
+    if failed > 0 {
+        return Err(format!("{} frame(s) cannot be parsed", failed));
+    }
+    Ok(())
+}
+
+/// Gets the data received from the stove on a line of the capture
+///
+/// # Arguments
+///
+/// * `line` - A line of frames.log (`<time> << <frame>`), or a raw frame
+///
+/// # Returns
+///
+/// * `Option<&str>` - The received data, None for sent frames and other lines
+fn received_data(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return Some(line);
+    }
+    line.split_once(" << ").map(|(_, data)| data.trim())
+}
+
+/// Parses a received frame, updating the detected firmware if its CRC is valid
+///
+/// # Arguments
+///
+/// * `frame` - The frame, starting with '#'
+/// * `firmware` - The firmware detected from the previous frames
+///
+/// # Returns
+///
+/// * `Result<Response, String>` - The parsed frame, or the parse error
+fn parse_frame(frame: &str, firmware: &mut FirmwareInfo) -> Result<Response, String> {
+    let frame = format!("{}\n", frame.trim_end());
+    let response = Response::from_message(&frame, firmware.layout()).map_err(|e| e.to_string())?;
+    if response.get_command_data().is_none() {
+        return Err("no data".into());
+    }
+    // A corrupted frame must not change the layout of the next ones
+    if response.check_crc().is_ok() {
+        if let Some(change) = firmware.update(&response) {
+            println!(
+                "firmware changed from {} to {}, using {} DAT layout",
+                change.from, change.to, change.layout
+            );
+        }
+    }
+    Ok(response)
+}
+
+/// Summarizes a parsed frame
+///
+/// # Arguments
+///
+/// * `response` - The parsed frame
+///
+/// # Returns
+///
+/// * `String` - The page, with the CRC error and the number of extra params if any
+fn summarize(response: &Response) -> String {
+    let Some(data) = response.get_command_data() else {
+        return "no data".into();
+    };
+    let extra = data
+        .to_json()
+        .get("raw_extra")
+        .and_then(Value::as_array)
+        .map_or(0, Vec::len);
+    let mut summary = format!("{} ok", data.page());
+    if let Err(e) = response.check_crc() {
+        summary.push_str(&format!(", {}", e));
+    }
+    if extra > 0 {
+        summary.push_str(&format!(", {} extra param(s) in raw_extra", extra));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    /// Corpus of captures, each `<name>.log` with the fields expected in `<name>.json`
+    const VECTORS_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors");
+
+    /// Parses a capture, keeping the last data of each page
+    fn decode(capture: &str) -> Result<BTreeMap<String, Value>, String> {
+        let mut firmware = FirmwareInfo::default();
+        let mut pages = BTreeMap::new();
+        for line in capture.lines() {
+            let Some(data) = received_data(line) else {
+                continue;
+            };
+            for frame in split_frames(data) {
+                let response =
+                    parse_frame(frame, &mut firmware).map_err(|e| format!("{} in {}", e, frame))?;
+                response
+                    .check_crc()
+                    .map_err(|e| format!("{} in {}", e, frame))?;
+                if let Some(data) = response.get_command_data() {
+                    pages.insert(data.page().to_string(), data.to_json());
+                }
+            }
+        }
+        Ok(pages)
+    }
+
+    /// Lists the captures of the corpus
+    fn captures() -> Vec<PathBuf> {
+        let mut captures: Vec<_> = fs::read_dir(VECTORS_DIR)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+            .collect();
+        captures.sort();
+        assert!(!captures.is_empty(), "no capture in {}", VECTORS_DIR);
+        captures
+    }
+
+    #[test]
+    fn captures_decode_to_the_expected_fields() {
+        for path in captures() {
+            let name = path.file_name().unwrap().to_string_lossy();
+            let pages = decode(&fs::read_to_string(&path).unwrap())
+                .unwrap_or_else(|e| panic!("{}: {}", name, e));
+            let expected: Value =
+                serde_json::from_str(&fs::read_to_string(path.with_extension("json")).unwrap())
+                    .unwrap_or_else(|e| panic!("{}: invalid expected fields: {}", name, e));
+            for (page, fields) in expected.as_object().unwrap() {
+                let decoded = pages
+                    .get(page)
+                    .unwrap_or_else(|| panic!("{}: no {} frame", name, page));
+                for (field, value) in fields.as_object().unwrap() {
+                    assert_eq!(&decoded[field], value, "{}: {}.{}", name, page, field);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn run_parse_capture_accepts_the_corpus() {
+        for path in captures() {
+            let path = path.to_string_lossy().into_owned();
+            assert_eq!(run_parse_capture(&[path]), Ok(()));
+        }
+    }
+
+    #[test]
+    fn received_data_skips_sent_frames() {
+        assert_eq!(
+            received_data("2025-01-15T10:00:01.120Z << #00001C---0002DATW1;5B73"),
+            Some("#00001C---0002DATW1;5B73")
+        );
+        assert_eq!(
+            received_data("2025-01-15T10:00:00.000Z >> #00001C---0001INFR;9C04"),
+            None
+        );
+        assert_eq!(
+            received_data("  #00001C---0002DATW1;5B73 "),
+            Some("#00001C---0002DATW1;5B73")
+        );
+        assert_eq!(received_data("frames of the living room"), None);
+    }
+
+    #[test]
+    fn parse_frame_reports_invalid_frames() {
+        let mut firmware = FirmwareInfo::default();
+        assert!(parse_frame("#00001C---0002DATW1;", &mut firmware).is_err());
+        assert!(parse_frame("#00001C---0002XYZR1;5B73", &mut firmware).is_err());
+        // A frame with a wrong CRC is parsed, the CRC error being reported in the summary
+        let response = parse_frame("#00005C---0002DATW1;0000", &mut firmware).unwrap();
+        assert!(summarize(&response).starts_with("DAT write ok, "));
+    }
+
+    #[test]
+    fn frames_with_a_wrong_crc_do_not_change_the_firmware() {
+        let mut firmware = FirmwareInfo::default();
+        parse_frame(
+            "#00001C---0018INFRHOTTOH-5CCF7F;2.4.5;-62;5186",
+            &mut firmware,
+        )
+        .unwrap();
+        let corrupted = parse_frame(
+            "#00011C---0018INFRHOTTOH-A1B2C3;2.6.1;-71;0000",
+            &mut firmware,
+        )
+        .unwrap();
+        assert!(corrupted.check_crc().is_err());
+
+        let valid = Response::from_message(
+            "#00011C---0018INFRHOTTOH-A1B2C3;2.6.1;-71;23C7\n",
+            firmware.layout(),
+        )
+        .unwrap();
+        let change = firmware.update(&valid).expect("version still 2.4.5");
+        assert_eq!(
+            (change.from.as_str(), change.to.as_str()),
+            ("2.4.5", "2.6.1")
+        );
+    }
+}
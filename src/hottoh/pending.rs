@@ -0,0 +1,242 @@
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::hottoh_structs::Tenths;
+use log::debug;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+/// Delay after which an unconfirmed pending value is dropped
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Page holding the setpoint changed by a command
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PendingPage {
+    Dat0,
+    Dat1,
+}
+
+/// Representation of a setpoint in the state JSON
+#[derive(Debug, Clone, Copy)]
+enum FieldKind {
+    /// Serialized as true/false, sent as 1/0
+    Bool,
+    /// Serialized as is
+    Number,
+    /// Serialized in degrees, sent in tenths of a degree
+    Tenths,
+}
+
+/// Value expected in the state after a queued write
+#[derive(Debug, Clone)]
+struct PendingValue {
+    page: PendingPage,
+    value: Value,
+    request_id: u32,
+    queued_at: Instant,
+}
+
+/// Setpoints written to the stove but not yet read back
+///
+/// Serialized as the `pending` section of the state (e.g. `pending.index_power_set = 5`)
+/// so that UIs can show the requested value during the confirmation window. An entry
+/// is removed when the stove reports the value, or after 10 seconds.
+#[derive(Debug, Clone, Default)]
+pub struct PendingState {
+    entries: BTreeMap<&'static str, PendingValue>,
+}
+
+impl PendingState {
+    /// Records the value expected after a write request
+    ///
+    /// Commands that do not map to a setpoint of the state are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the write request
+    /// * `action` - The StoveCommands value
+    /// * `value` - The value sent to the stove
+    pub fn add(&mut self, request_id: u32, action: u32, value: &str) {
+        let Some((page, field, kind)) = setpoint_field(action) else {
+            return;
+        };
+        let Ok(raw) = value.trim().parse::<i32>() else {
+            return;
+        };
+        let value = match kind {
+            FieldKind::Bool => json!(raw != 0),
+            FieldKind::Number => json!(raw),
+            FieldKind::Tenths => json!(Tenths(raw as i16)),
+        };
+        self.entries.insert(
+            field,
+            PendingValue {
+                page,
+                value,
+                request_id,
+                queued_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Drops the value expected after a write request that will not be sent
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the write request
+    pub fn remove(&mut self, request_id: u32) {
+        self.entries
+            .retain(|_, pending| pending.request_id != request_id);
+    }
+
+    /// Checks if no value is pending
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if there is no pending value
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Drops the values confirmed by a page received from the stove, and the expired ones
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The received page
+    /// * `data` - The page serialized as JSON
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(u32, &'static str, Value)>` - Request ID, field and value of the confirmed entries
+    pub fn confirm(&mut self, page: PendingPage, data: &Value) -> Vec<(u32, &'static str, Value)> {
+        let mut confirmed_entries = Vec::new();
+        self.entries.retain(|field, pending| {
+            if pending.queued_at.elapsed() > PENDING_TIMEOUT {
+                debug!(
+                    "Pending {}={} (request {}) not confirmed in time",
+                    field, pending.value, pending.request_id
+                );
+                return false;
+            }
+            let confirmed = pending.page == page
+                && data
+                    .get(*field)
+                    .is_some_and(|actual| same_value(actual, &pending.value));
+            if confirmed {
+                debug!(
+                    "Pending {}={} (request {}) confirmed",
+                    field, pending.value, pending.request_id
+                );
+                confirmed_entries.push((pending.request_id, *field, pending.value.clone()));
+            }
+            !confirmed
+        });
+        confirmed_entries
+    }
+}
+
+impl Serialize for PendingState {
+    /// Serializes the pending values as a map of field to expected value
+    ///
+    /// Expired values are left out even if no page was received since.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|(_, pending)| pending.queued_at.elapsed() <= PENDING_TIMEOUT)
+            .collect();
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (field, pending) in entries {
+            map.serialize_entry(field, &pending.value)?;
+        }
+        map.end()
+    }
+}
+
+/// Gets the current value of the temperature setpoint changed by a command
+///
+/// # Arguments
+///
+/// * `action` - The StoveCommands value
+/// * `dat0` - The DAT0 page serialized as JSON
+///
+/// # Returns
+///
+/// * `Option<f64>` - The setpoint in degrees, or None if the command sets no ambiance temperature
+pub fn current_temperature_setpoint(action: u32, dat0: &Value) -> Option<f64> {
+    match setpoint_field(action)? {
+        (PendingPage::Dat0, field, FieldKind::Tenths) => dat0.get(field)?.as_f64(),
+        _ => None,
+    }
+}
+
+/// Checks if the stove reports the value set by a command
+///
+/// # Arguments
+///
+/// * `action` - The StoveCommands value
+///
+/// # Returns
+///
+/// * `bool` - True if the command changes a setpoint of the DAT0 or DAT1 page
+pub fn has_read_back(action: u32) -> bool {
+    setpoint_field(action).is_some()
+}
+
+/// Finds the setpoint changed by a command
+///
+/// # Arguments
+///
+/// * `action` - The StoveCommands value
+///
+/// # Returns
+///
+/// * `Option<(PendingPage, &'static str, FieldKind)>` - The page, field and kind of the
+///   setpoint, or None if the command has no read-back
+fn setpoint_field(action: u32) -> Option<(PendingPage, &'static str, FieldKind)> {
+    use PendingPage::{Dat0, Dat1};
+    let field = match action {
+        a if a == StoveCommands::OnOff as u32 => (Dat0, "index_stove_on", FieldKind::Bool),
+        a if a == StoveCommands::EcoMode as u32 => (Dat0, "index_eco_mode", FieldKind::Bool),
+        a if a == StoveCommands::PowerLevel as u32 => (Dat0, "index_power_set", FieldKind::Number),
+        a if a == StoveCommands::AmbianceTemperature1 as u32 => {
+            (Dat0, "index_ambient_t1_set", FieldKind::Tenths)
+        }
+        a if a == StoveCommands::AmbianceTemperature2 as u32 => {
+            (Dat0, "index_ambient_t2_set", FieldKind::Tenths)
+        }
+        a if a == StoveCommands::FanSpeed1 as u32 => (Dat0, "index_fan_1_set", FieldKind::Number),
+        a if a == StoveCommands::FanSpeed2 as u32 => (Dat0, "index_fan_2_set", FieldKind::Number),
+        a if a == StoveCommands::FanSpeed3 as u32 => (Dat0, "index_fan_3_set", FieldKind::Number),
+        a if a == StoveCommands::ChronoOnOff as u32 => (Dat1, "index_state", FieldKind::Bool),
+        a if a == StoveCommands::ChronoTemperature1 as u32 => {
+            (Dat1, "index_temperature_1", FieldKind::Number)
+        }
+        a if a == StoveCommands::ChronoTemperature2 as u32 => {
+            (Dat1, "index_temperature_2", FieldKind::Number)
+        }
+        a if a == StoveCommands::ChronoTemperature3 as u32 => {
+            (Dat1, "index_temperature_3", FieldKind::Number)
+        }
+        _ => return None,
+    };
+    Some(field)
+}
+
+/// Compares a value read from the stove with an expected one
+///
+/// # Arguments
+///
+/// * `actual` - The value read from the stove
+/// * `expected` - The expected value
+///
+/// # Returns
+///
+/// * `bool` - True if equal (numbers within a hundredth)
+fn same_value(actual: &Value, expected: &Value) -> bool {
+    match (actual.as_f64(), expected.as_f64()) {
+        (Some(actual), Some(expected)) => (actual - expected).abs() < 0.01,
+        _ => actual == expected,
+    }
+}
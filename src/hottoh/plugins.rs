@@ -0,0 +1,369 @@
+use crate::hottoh::config::PluginsConfig;
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::supervisor::supervise;
+use actix_web::{web, HttpResponse};
+use log::{info, warn};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Function adding routes to the HTTP API
+pub type RouteConfigurator = Arc<dyn Fn(&mut web::ServiceConfig) + Send + Sync>;
+
+/// Optional module extending the bridge at startup
+pub trait Plugin {
+    /// Name of the plugin, used in the `plugins.enabled` setting
+    fn name(&self) -> &'static str;
+
+    /// Registers the routes, commands and notifiers of the plugin
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if a registration conflicts with an existing one
+    fn register(&self, registry: &mut PluginRegistry) -> Result<(), String>;
+}
+
+/// Receiver of the events published on the bus
+pub trait Notifier: Send + Sync {
+    /// Name of the notifier, used in logs and metrics
+    fn name(&self) -> &'static str;
+
+    /// Handles an event
+    ///
+    /// Called from a dedicated thread, so it may block (e.g. on a network call).
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event
+    fn notify(&self, event: &Event);
+}
+
+/// Routes, commands and notifiers registered by the plugins
+///
+/// Filled at startup, then shared read-only with the HTTP API and the notifier threads.
+#[derive(Default)]
+pub struct PluginRegistry {
+    /// Names of the loaded plugins
+    plugins: Vec<&'static str>,
+    /// Route configurators, applied in registration order
+    routes: Vec<RouteConfigurator>,
+    /// Commands by name, with their StoveCommands value
+    commands: BTreeMap<String, u32>,
+    /// Notifiers subscribed to the event bus
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl PluginRegistry {
+    /// Creates an empty registry
+    ///
+    /// # Returns
+    ///
+    /// * `PluginRegistry` - The registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates the registry from the configuration
+    ///
+    /// Loads the enabled built-in plugins, then the commands declared in the configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[plugins]` section, or None if absent
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PluginRegistry, String>` - The registry or an error
+    pub fn from_config(config: Option<&PluginsConfig>) -> Result<Self, String> {
+        let mut registry = Self::new();
+        let Some(config) = config else {
+            return Ok(registry);
+        };
+        let available = builtin_plugins();
+        for name in split_list(&config.enabled) {
+            let plugin = available
+                .iter()
+                .find(|plugin| plugin.name() == name)
+                .ok_or_else(|| format!("unknown plugin '{}'", name))?;
+            registry.load(plugin.as_ref())?;
+        }
+        for (name, action) in parse_commands(&config.commands)? {
+            registry.add_command(&name, action)?;
+        }
+        Ok(registry)
+    }
+
+    /// Loads a plugin
+    ///
+    /// # Arguments
+    ///
+    /// * `plugin` - The plugin
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the plugin is already loaded or conflicts
+    pub fn load(&mut self, plugin: &dyn Plugin) -> Result<(), String> {
+        if self.plugins.contains(&plugin.name()) {
+            return Err(format!("plugin '{}' loaded twice", plugin.name()));
+        }
+        plugin
+            .register(self)
+            .map_err(|e| format!("plugin '{}': {}", plugin.name(), e))?;
+        info!("Plugin '{}' loaded", plugin.name());
+        self.plugins.push(plugin.name());
+        Ok(())
+    }
+
+    /// Registers routes of the HTTP API
+    ///
+    /// The routes see the same application data as the built-in ones, and go through
+    /// the same middlewares (network filter, PIN, access log).
+    ///
+    /// # Arguments
+    ///
+    /// * `configure` - Function adding the routes
+    pub fn add_routes(
+        &mut self,
+        configure: impl Fn(&mut web::ServiceConfig) + Send + Sync + 'static,
+    ) {
+        self.routes.push(Arc::new(configure));
+    }
+
+    /// Registers a command of the stove protocol
+    ///
+    /// The command can then be sent with `POST /api/plugins/commands/{name}`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the command
+    /// * `action` - Command number sent in the DAT write frame
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the name is already used
+    pub fn add_command(&mut self, name: &str, action: u32) -> Result<(), String> {
+        if StoveCommands::from_str(name).is_ok() || self.commands.contains_key(name) {
+            return Err(format!("command '{}' already exists", name));
+        }
+        self.commands.insert(name.to_string(), action);
+        Ok(())
+    }
+
+    /// Registers a notifier of the events
+    ///
+    /// # Arguments
+    ///
+    /// * `notifier` - The notifier
+    pub fn add_notifier(&mut self, notifier: Arc<dyn Notifier>) {
+        self.notifiers.push(notifier);
+    }
+
+    /// Gets a registered command
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the command
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u32>` - The command number, if registered
+    pub fn get_command(&self, name: &str) -> Option<u32> {
+        self.commands.get(name).copied()
+    }
+
+    /// Adds the registered routes to the HTTP API
+    ///
+    /// # Arguments
+    ///
+    /// * `cfg` - The service configuration of the application
+    pub fn configure_routes(&self, cfg: &mut web::ServiceConfig) {
+        for configure in &self.routes {
+            configure(cfg);
+        }
+    }
+
+    /// Describes the registered extensions
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - The plugins, commands and notifiers as JSON
+    pub fn describe(&self) -> Value {
+        json!({
+            "plugins": self.plugins,
+            "commands": self.commands,
+            "notifiers": self.notifiers.iter().map(|notifier| notifier.name()).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Starts a thread per notifier, receiving the events of the bus
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The event bus
+    /// * `running` - Flag indicating whether the application is running
+    /// * `metrics` - Runtime metrics where crashes are recorded
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<thread::JoinHandle<()>>` - Handles to the spawned threads
+    pub fn start_notifier_threads(
+        &self,
+        events: &Arc<EventBus>,
+        running: &Arc<AtomicBool>,
+        metrics: &Arc<Metrics>,
+    ) -> Vec<thread::JoinHandle<()>> {
+        self.notifiers
+            .iter()
+            .map(|notifier| {
+                let notifier = Arc::clone(notifier);
+                let events = Arc::clone(events);
+                let running = Arc::clone(running);
+                supervise(
+                    notifier.name(),
+                    Arc::clone(&running),
+                    Arc::clone(metrics),
+                    move || {
+                        let receiver = events.subscribe();
+                        while running.load(Ordering::SeqCst) {
+                            match receiver.recv_timeout(Duration::from_millis(200)) {
+                                Ok(event) => notifier.notify(&event),
+                                Err(RecvTimeoutError::Timeout) => {}
+                                Err(RecvTimeoutError::Disconnected) => break,
+                            }
+                        }
+                        info!("Notifier {} stopped.", notifier.name());
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// Splits a comma-separated list, ignoring empty items
+///
+/// # Arguments
+///
+/// * `list` - The list
+///
+/// # Returns
+///
+/// * `impl Iterator<Item = &str>` - The trimmed items
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Parses the commands declared in the configuration
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated `Name=number` list (e.g. `SetRecipe2=17`)
+///
+/// # Returns
+///
+/// * `Result<Vec<(String, u32)>, String>` - The (name, command number) pairs or an error
+fn parse_commands(spec: &str) -> Result<Vec<(String, u32)>, String> {
+    split_list(spec)
+        .map(|item| {
+            let (name, action) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected Name=number, got '{}'", item))?;
+            let name = name.trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("invalid command name '{}'", name));
+            }
+            let action = action
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("invalid command number '{}'", action.trim()))?;
+            Ok((name.to_string(), action))
+        })
+        .collect()
+}
+
+/// Gets the plugins compiled in the binary
+///
+/// # Returns
+///
+/// * `Vec<Box<dyn Plugin>>` - The available plugins
+pub fn builtin_plugins() -> Vec<Box<dyn Plugin>> {
+    vec![Box::new(EventLogPlugin)]
+}
+
+/// Number of events kept by the event log
+const EVENT_LOG_SIZE: usize = 50;
+
+/// Built-in plugin keeping the last events, served at `/api/plugins/event_log`
+struct EventLogPlugin;
+
+/// Notifier of the event log plugin
+struct EventLog {
+    events: Mutex<VecDeque<Value>>,
+}
+
+impl Plugin for EventLogPlugin {
+    fn name(&self) -> &'static str {
+        "event_log"
+    }
+
+    fn register(&self, registry: &mut PluginRegistry) -> Result<(), String> {
+        let log = Arc::new(EventLog {
+            events: Mutex::new(VecDeque::with_capacity(EVENT_LOG_SIZE)),
+        });
+        registry.add_notifier(log.clone());
+        registry.add_routes(move |cfg| {
+            let log = Arc::clone(&log);
+            cfg.route(
+                "/api/plugins/event_log",
+                web::get().to(move || {
+                    let events: Vec<Value> = mutex_lock(&log.events, "event log")
+                        .iter()
+                        .cloned()
+                        .collect();
+                    async move { HttpResponse::Ok().json(json!({ "events": events })) }
+                }),
+            );
+        });
+        Ok(())
+    }
+}
+
+impl Notifier for EventLog {
+    fn name(&self) -> &'static str {
+        "event_log"
+    }
+
+    fn notify(&self, event: &Event) {
+        if let Event::StateUpdated { .. } = event {
+            return;
+        }
+        let value = match serde_json::to_value(event) {
+            Ok(value) => json!({
+                "time": chrono::Local::now().to_rfc3339(),
+                "event": value,
+            }),
+            Err(e) => {
+                warn!("Cannot serialize event {:?}: {}", event, e);
+                return;
+            }
+        };
+        let mut events = mutex_lock(&self.events, "event log");
+        if events.len() == EVENT_LOG_SIZE {
+            events.pop_front();
+        }
+        events.push_back(value);
+    }
+}
@@ -0,0 +1,101 @@
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::inbound_webhooks::parse_action;
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// Parses the settings of a profile
+///
+/// The specification uses the syntax of the inbound webhook actions
+/// (e.g. `PowerLevel=3,AmbianceTemperature1=205,FanSpeed1=2,EcoMode=1`). Turning the
+/// stove on or off is not a setting: it needs a confirmation when the stove is running,
+/// as do the recipe and pellet setpoint commands, which are not allowed either.
+///
+/// # Arguments
+///
+/// * `spec` - The settings of the profile
+///
+/// # Returns
+///
+/// * `Result<Vec<(u32, i32)>, String>` - The (command, value) pairs or an error
+pub fn parse_profile(spec: &str) -> Result<Vec<(u32, i32)>, String> {
+    let commands = parse_action(spec)?;
+    for forbidden in [
+        StoveCommands::OnOff,
+        StoveCommands::HottohSetRecipe,
+        StoveCommands::HottohSetPelSetpoint,
+    ] {
+        if commands
+            .iter()
+            .any(|(command, _)| *command == forbidden as u32)
+        {
+            return Err(format!("{:?} is not allowed in a profile", forbidden));
+        }
+    }
+    Ok(commands)
+}
+
+/// Named profiles bundling settings applied together
+#[derive(Debug, Default)]
+pub struct Profiles {
+    /// Settings as configured and parsed, by profile name
+    profiles: BTreeMap<String, (String, Vec<(u32, i32)>)>,
+}
+
+impl Profiles {
+    /// Builds the profiles from the configuration
+    ///
+    /// Profiles that cannot be parsed are logged and left out.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[profiles]` section, or None if absent
+    ///
+    /// # Returns
+    ///
+    /// * `Profiles` - The configured profiles (none if absent)
+    pub fn from_config(config: Option<&HashMap<String, String>>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+
+        let mut profiles = BTreeMap::new();
+        for (name, spec) in config {
+            match parse_profile(spec) {
+                Ok(commands) => {
+                    profiles.insert(name.clone(), (spec.trim().to_string(), commands));
+                }
+                Err(e) => error!("Ignoring profile '{}': {}", name, e),
+            }
+        }
+        info!("{} profile(s) configured", profiles.len());
+        Self { profiles }
+    }
+
+    /// Gets the settings of a profile
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the profile
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&[(u32, i32)]>` - The (command, value) pairs, or None if unknown
+    pub fn get_profile(&self, name: &str) -> Option<&[(u32, i32)]> {
+        self.profiles
+            .get(name)
+            .map(|(_, commands)| commands.as_slice())
+    }
+
+    /// Gets the settings of every profile, as configured
+    ///
+    /// # Returns
+    ///
+    /// * `BTreeMap<&str, &str>` - The settings by profile name
+    pub fn get_profiles(&self) -> BTreeMap<&str, &str> {
+        self.profiles
+            .iter()
+            .map(|(name, (spec, _))| (name.as_str(), spec.as_str()))
+            .collect()
+    }
+}
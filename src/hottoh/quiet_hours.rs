@@ -0,0 +1,274 @@
+use crate::hottoh::config::QuietHoursConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::{read_lock, write_lock};
+use crate::hottoh::maintenance::MaintenanceSchedule;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::get_field;
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_request, CommandOrigin};
+use chrono::{DateTime, Local};
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Name of the profile applying the limits of the quiet hours
+pub const QUIET_PROFILE: &str = "quiet";
+/// Name of the profile without limits
+pub const NORMAL_PROFILE: &str = "normal";
+
+/// Setpoints limited during the quiet hours, with the field holding their value
+const LIMITED_SETPOINTS: [(StoveCommands, &str); 4] = [
+    (StoveCommands::PowerLevel, "index_power_set"),
+    (StoveCommands::FanSpeed1, "index_fan_1_set"),
+    (StoveCommands::FanSpeed2, "index_fan_2_set"),
+    (StoveCommands::FanSpeed3, "index_fan_3_set"),
+];
+
+/// Quiet hours, during which the power and the fan speeds are limited
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    /// Windows of the quiet hours
+    schedule: MaintenanceSchedule,
+    /// Highest power level
+    max_power: Option<u64>,
+    /// Highest speed of the fans
+    max_fan_speed: Option<u64>,
+    /// Profile activated through the API, with the scheduled one at that time
+    forced: Option<(bool, bool)>,
+}
+
+impl QuietHours {
+    /// Creates the quiet hours from the `[quiet_hours]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[quiet_hours]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Result<QuietHours, String>` - The quiet hours, or an error if they are invalid
+    pub fn from_config(config: &QuietHoursConfig) -> Result<Self, String> {
+        if config.max_power.is_none() && config.max_fan_speed.is_none() {
+            return Err("max_power or max_fan_speed is required".into());
+        }
+        Ok(Self {
+            schedule: MaintenanceSchedule::parse(&config.windows)?,
+            max_power: config.max_power,
+            max_fan_speed: config.max_fan_speed,
+            forced: None,
+        })
+    }
+
+    /// Checks if the limits apply
+    ///
+    /// A profile activated through the API applies until the scheduled profile changes.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The local time
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True during the quiet hours
+    pub fn is_active(&mut self, now: DateTime<Local>) -> bool {
+        let scheduled = self.schedule.active_window(now).is_some();
+        match self.forced {
+            Some((quiet, forced_while)) if forced_while == scheduled => quiet,
+            Some(_) => {
+                self.forced = None;
+                scheduled
+            }
+            None => scheduled,
+        }
+    }
+
+    /// Activates a profile until the scheduled profile changes
+    ///
+    /// # Arguments
+    ///
+    /// * `quiet` - True for the quiet profile, false for the normal one
+    /// * `now` - The local time
+    pub fn activate(&mut self, quiet: bool, now: DateTime<Local>) {
+        let scheduled = self.schedule.active_window(now).is_some();
+        self.forced = (quiet != scheduled).then_some((quiet, scheduled));
+    }
+
+    /// Gets the highest value of a setpoint during the quiet hours
+    ///
+    /// # Arguments
+    ///
+    /// * `command` - The command setting the setpoint
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The highest value, None if the setpoint is not limited
+    fn get_limit(&self, command: StoveCommands) -> Option<u64> {
+        match command {
+            StoveCommands::PowerLevel => self.max_power,
+            _ => self.max_fan_speed,
+        }
+    }
+}
+
+/// Gets the value of a setpoint, or the value being written if any
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+/// * `field` - The field of the setpoint in DAT0
+///
+/// # Returns
+///
+/// * `Option<u64>` - The value, None if not received yet
+fn get_setpoint(state: &Value, field: &str) -> Option<u64> {
+    get_field(state, &format!("pending.{}", field))
+        .or_else(|| get_field(state, &format!("dat0.{}", field)))
+        .and_then(Value::as_u64)
+}
+
+/// Queues a command setting a setpoint
+///
+/// # Arguments
+///
+/// * `shared_state` - Shared state where the pending value is recorded
+/// * `commands` - Queues used to send commands to the stove
+/// * `command` - The command
+/// * `value` - The value
+fn send(
+    shared_state: &RwLock<SharedState>,
+    commands: &CommandQueue,
+    command: StoveCommands,
+    value: u64,
+) {
+    match queue_write_request(
+        &commands.request_queue,
+        &commands.request_id_counter,
+        shared_state,
+        CommandOrigin::Automation,
+        command as u32,
+        &value,
+    ) {
+        Ok(request_id) => debug!(
+            "Quiet hours set {:?} to {} (id {})",
+            command, value, request_id
+        ),
+        Err(e) => warn!(
+            "Quiet hours command {:?}={} rejected: {}",
+            command, value, e
+        ),
+    }
+}
+
+/// Starts a thread applying the limits of the quiet hours
+///
+/// During the quiet hours, the power level and the fan speeds are lowered to their
+/// limits whenever they exceed them. The values lowered are set again when the quiet
+/// hours end, unless they were changed in the meantime. Commands are sent as
+/// automation commands, so they are suppressed in read-only mode and during
+/// maintenance windows.
+///
+/// # Arguments
+///
+/// * `config` - The `[quiet_hours]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data and the quiet hours
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_quiet_hours_thread(
+    config: Option<&QuietHoursConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let quiet_hours = match QuietHours::from_config(config?) {
+        Ok(quiet_hours) => quiet_hours,
+        Err(e) => {
+            error!("Quiet hours disabled: {}", e);
+            return None;
+        }
+    };
+    info!("Quiet hours: {}", config?.windows);
+    write_lock(&shared_state, "shared state").set_quiet_hours(quiet_hours);
+
+    Some(supervise(
+        "quiet_hours",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut was_active = false;
+            // Values lowered during the quiet hours, with their limit
+            let mut lowered: BTreeMap<&str, (StoveCommands, u64, u64)> = BTreeMap::new();
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { page: "dat0" }) => {}
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let (active, quiet_hours) = {
+                    let mut state = write_lock(&shared_state, "shared state");
+                    let Some(quiet_hours) = state.get_quiet_hours_mut() else {
+                        continue;
+                    };
+                    (quiet_hours.is_active(Local::now()), quiet_hours.clone())
+                };
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for the quiet hours: {}", e);
+                        continue;
+                    }
+                };
+
+                if active != was_active {
+                    was_active = active;
+                    if active {
+                        info!("Quiet hours started");
+                    } else {
+                        info!("Quiet hours ended");
+                        for (field, (command, value, limit)) in std::mem::take(&mut lowered) {
+                            if get_setpoint(&state, field) == Some(limit) {
+                                send(&shared_state, &commands, command, value);
+                            }
+                        }
+                    }
+                }
+                if !active {
+                    continue;
+                }
+
+                for (command, field) in LIMITED_SETPOINTS {
+                    let Some(limit) = quiet_hours.get_limit(command) else {
+                        continue;
+                    };
+                    let Some(value) = get_setpoint(&state, field).filter(|value| *value > limit)
+                    else {
+                        continue;
+                    };
+                    info!(
+                        "Lowering {:?} from {} to {} for the quiet hours",
+                        command, value, limit
+                    );
+                    lowered.insert(field, (command, value, limit));
+                    send(&shared_state, &commands, command, limit);
+                }
+            }
+            info!("Quiet hours thread stopped.");
+        },
+    ))
+}
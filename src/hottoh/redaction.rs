@@ -0,0 +1,176 @@
+use crate::hottoh::config::LogConfig;
+use crate::hottoh::hottoh_structs::{DAT0Data, DAT1Data, DAT2Data, INFData};
+use crate::hottoh::layouts::FirmwareLayout;
+use log::warn;
+use std::borrow::Cow;
+
+/// Replacement for identifying values
+pub const REDACTED: &str = "<redacted>";
+
+/// Masks the identifying params of a raw frame before it is logged
+///
+/// Only INF frames carry identifying data (the hostname, first param).
+///
+/// # Arguments
+///
+/// * `frame` - The raw frame, starting with '#'
+///
+/// # Returns
+///
+/// * `Cow<str>` - The frame with the hostname replaced, or unchanged if not an INF frame
+pub fn redact_frame(frame: &str) -> Cow<'_, str> {
+    if frame.get(14..17) != Some("INF") {
+        return Cow::Borrowed(frame);
+    }
+    // Params start after the 3-char command and the 1-char command type
+    let Some(params) = frame.get(18..) else {
+        return Cow::Borrowed(frame);
+    };
+    match params.find(';') {
+        Some(end) => Cow::Owned(format!("{}{}{}", &frame[..18], REDACTED, &params[end..])),
+        None => Cow::Borrowed(frame),
+    }
+}
+
+/// Fields masked in the frames written to the logs when `general.redact` is set
+const REDACTED_FIELDS: &str = "inf.hostname";
+
+/// Masking and truncation of the raw frames written to the logs
+///
+/// Frames logged at debug level can then be shared publicly when asking for help
+/// with the protocol, without leaking identifying data.
+#[derive(Debug, Clone, Default)]
+pub struct FrameMask {
+    /// Masked fields, as the page command (`INF`, `DAT0`...) and the position of the
+    /// field in the reference layout
+    fields: Vec<(&'static str, usize)>,
+    /// Length beyond which frames are truncated, 0 to keep them whole
+    max_len: usize,
+}
+
+impl FrameMask {
+    /// Creates the mask from the `[log]` section
+    ///
+    /// Unknown fields are reported and ignored; `check-config` rejects them.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[log]` section
+    /// * `redact` - Whether `general.redact` is set, which always masks the hostname
+    ///
+    /// # Returns
+    ///
+    /// * `FrameMask` - The mask
+    pub fn from_config(config: &LogConfig, redact: bool) -> Self {
+        let mut fields = parse_frame_mask(&config.frame_mask).unwrap_or_else(|e| {
+            warn!("Ignoring log.frame_mask: {}", e);
+            Vec::new()
+        });
+        if redact {
+            fields.extend(parse_frame_mask(REDACTED_FIELDS).unwrap_or_default());
+        }
+        Self {
+            fields,
+            max_len: config.frame_max_len,
+        }
+    }
+
+    /// Prepares a raw frame for logging
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The raw frame, starting with '#'
+    /// * `layout` - Layout of the DAT pages sent by the stove firmware
+    ///
+    /// # Returns
+    ///
+    /// * `Cow<str>` - The frame with the masked fields replaced and truncated if too long
+    pub fn apply<'a>(&self, frame: &'a str, layout: &FirmwareLayout) -> Cow<'a, str> {
+        let frame = frame.trim_end();
+        let masked = self.mask(frame, layout);
+        match masked.char_indices().nth(self.max_len) {
+            Some((end, _)) if self.max_len > 0 => Cow::Owned(format!(
+                "{}... ({} more bytes)",
+                &masked[..end],
+                masked.len() - end
+            )),
+            _ => masked,
+        }
+    }
+
+    /// Replaces the masked fields of a frame
+    ///
+    /// # Arguments
+    ///
+    /// * `frame` - The raw frame, starting with '#'
+    /// * `layout` - Layout of the DAT pages sent by the stove firmware
+    ///
+    /// # Returns
+    ///
+    /// * `Cow<str>` - The frame with the masked fields replaced
+    fn mask<'a>(&self, frame: &'a str, layout: &FirmwareLayout) -> Cow<'a, str> {
+        let (Some(command), Some(section)) = (frame.get(14..17), frame.get(18..)) else {
+            return Cow::Borrowed(frame);
+        };
+        // The last part is the CRC
+        let mut parts: Vec<&str> = section.split(';').collect();
+        let params = &parts[..parts.len() - 1];
+        let (page, indexes) = if command == "DAT" {
+            match layout.identify_page(params) {
+                Some(page) => (page.command.as_str(), page.indexes),
+                None => return Cow::Borrowed(frame),
+            }
+        } else {
+            (command, None)
+        };
+
+        let mut masked = false;
+        for &(_, index) in self.fields.iter().filter(|(p, _)| *p == page) {
+            let position = indexes.and_then(|i| i.get(index).copied()).unwrap_or(index);
+            if position + 1 < parts.len() && !parts[position].is_empty() {
+                parts[position] = REDACTED;
+                masked = true;
+            }
+        }
+        if masked {
+            Cow::Owned(format!("{}{}", &frame[..18], parts.join(";")))
+        } else {
+            Cow::Borrowed(frame)
+        }
+    }
+}
+
+/// Parses the fields masked in the logged frames
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated `page.field`, e.g. `inf.hostname`, where `page` is one of
+///   `inf`, `dat0`, `dat1` or `dat2`
+///
+/// # Returns
+///
+/// * `Result<Vec<(&'static str, usize)>, String>` - The page command and the position
+///   of each field, or an error naming the unknown field
+pub fn parse_frame_mask(spec: &str) -> Result<Vec<(&'static str, usize)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (page, name) = item
+                .split_once('.')
+                .ok_or_else(|| format!("expected page.field, got '{}'", item))?;
+            let (command, fields) = match page {
+                "inf" => ("INF", INFData::FIELDS),
+                "dat0" => ("DAT0", DAT0Data::FIELDS),
+                "dat1" => ("DAT1", DAT1Data::FIELDS),
+                "dat2" => ("DAT2", DAT2Data::FIELDS),
+                _ => return Err(format!("unknown page '{}'", page)),
+            };
+            fields
+                .iter()
+                .find(|(field, _)| *field == name)
+                .map(|&(_, index)| (command, index))
+                .ok_or_else(|| format!("unknown field '{}'", item))
+        })
+        .collect()
+}
@@ -0,0 +1,364 @@
+use crate::hottoh::child_lock::{is_state_changing, ChildLock};
+use crate::hottoh::config::HttpApiConfig;
+use crate::hottoh::http_api::{read_body, ApiError};
+use crate::hottoh::inbound_webhooks::{hmac_sha256, to_hex};
+use crate::hottoh::locks::mutex_lock;
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{web, Error, HttpMessage, ResponseError};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Header carrying the signature of a request, as `sha256=<hex HMAC-SHA256>`
+pub const SIGNATURE_HEADER: &str = "X-Hottoh-Signature";
+/// Header carrying the time at which the request was signed, in Unix seconds
+pub const TIMESTAMP_HEADER: &str = "X-Hottoh-Timestamp";
+/// Largest difference between the timestamp of a request and the clock of the bridge,
+/// in seconds
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Marker of the requests whose signature was verified, set in their extensions
+#[derive(Debug, Clone, Copy)]
+pub struct SignedRequest;
+
+/// Signing of the state-changing requests with a shared secret
+///
+/// An alternative to the PIN for clients which cannot use TLS client certificates,
+/// such as microcontroller wall panels. Each signature is accepted once: the ones
+/// seen within the clock skew are remembered, so that a captured request cannot be
+/// replayed.
+pub struct RequestSigning {
+    /// The shared secret (empty disables the signing)
+    secret: String,
+    /// Signatures accepted, with their timestamp, until they are stale
+    seen: Mutex<HashMap<String, i64>>,
+}
+
+impl RequestSigning {
+    /// Creates the signing from the HTTP API configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The HTTP API configuration
+    ///
+    /// # Returns
+    ///
+    /// * `RequestSigning` - The signing
+    pub fn from_config(config: &HttpApiConfig) -> Self {
+        Self {
+            secret: config.signing_secret.trim().to_string(),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Checks if the signing is enabled
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if `http_api.signing_secret` is set
+    pub fn is_enabled(&self) -> bool {
+        !self.secret.is_empty()
+    }
+
+    /// Checks the signature of a request
+    ///
+    /// The signed message is `<timestamp>.<METHOD>.<path and query>.` followed by the
+    /// raw body, e.g. `1700000000.POST./api/dat/set_power_level.{"value":3}`. The
+    /// WebSocket commands use `WS` as method and `/api/ws` as path.
+    ///
+    /// # Arguments
+    ///
+    /// * `timestamp` - The timestamp header
+    /// * `method` - Method of the request
+    /// * `path` - Path of the request, with its query string
+    /// * `body` - The raw request body
+    /// * `signature` - The signature header
+    /// * `now` - The current time, in Unix seconds
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), String>` - An error if the signature is stale, does not match or was
+    ///   already used
+    pub fn verify(
+        &self,
+        timestamp: &str,
+        method: &str,
+        path: &str,
+        body: &[u8],
+        signature: &str,
+        now: i64,
+    ) -> Result<(), String> {
+        let signed_at = timestamp
+            .trim()
+            .parse::<i64>()
+            .map_err(|_| format!("invalid {} header", TIMESTAMP_HEADER))?;
+        if (now - signed_at).abs() > MAX_CLOCK_SKEW_SECS {
+            return Err(format!(
+                "{} is more than {} seconds away from the bridge clock",
+                TIMESTAMP_HEADER, MAX_CLOCK_SKEW_SECS
+            ));
+        }
+        let received = signature
+            .trim()
+            .strip_prefix("sha256=")
+            .ok_or_else(|| format!("{} must be sha256=<hex>", SIGNATURE_HEADER))?;
+
+        let mut message = format!("{}.{}.{}.", signed_at, method, path).into_bytes();
+        message.extend_from_slice(body);
+        let expected = to_hex(&hmac_sha256(self.secret.as_bytes(), &message));
+
+        // Constant-time comparison, to not leak how many characters match
+        let matches = received.len() == expected.len()
+            && received
+                .bytes()
+                .zip(expected.bytes())
+                .fold(0, |diff, (a, b)| diff | (a.to_ascii_lowercase() ^ b))
+                == 0;
+        if !matches {
+            return Err("invalid signature".into());
+        }
+
+        // Stale signatures are rejected by the clock check, so they can be forgotten
+        let mut seen = mutex_lock(&self.seen, "seen signatures");
+        seen.retain(|_, signed_at| now - *signed_at <= MAX_CLOCK_SKEW_SECS);
+        if seen.insert(expected, signed_at).is_some() {
+            return Err("signature already used".into());
+        }
+        Ok(())
+    }
+}
+
+/// Middleware checking the signature of state-changing requests
+///
+/// Signed requests carry the `X-Hottoh-Timestamp` and `X-Hottoh-Signature` headers.
+/// A request with a valid signature is accepted without the PIN of the child lock,
+/// while a wrong or stale signature is rejected with 401. Requests without signature
+/// are rejected with 401 too, unless a PIN is configured: they then need the PIN.
+/// Inbound webhooks and the pairing are exempt, like for the child lock.
+///
+/// # Arguments
+///
+/// * `req` - The incoming request
+/// * `next` - The rest of the service chain
+///
+/// # Returns
+///
+/// * `Result<ServiceResponse<BoxBody>, Error>` - The response of the service, or 401
+pub async fn require_signature(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let signing = req.app_data::<web::Data<Arc<RequestSigning>>>().cloned();
+    let Some(signing) = signing.filter(|signing| signing.is_enabled()) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if !is_state_changing(req.method(), req.path()) {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (timestamp, signature) = (header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER));
+    let Some(signature) = signature else {
+        let pin_set = req
+            .app_data::<web::Data<Arc<ChildLock>>>()
+            .is_some_and(|lock| lock.get_pin().is_some());
+        if pin_set {
+            return Ok(next.call(req).await?.map_into_boxed_body());
+        }
+        let response = ApiError::Unauthorized(format!(
+            "signature required ({} and {} headers)",
+            TIMESTAMP_HEADER, SIGNATURE_HEADER
+        ))
+        .error_response();
+        return Ok(req.into_response(response));
+    };
+    let Some(timestamp) = timestamp else {
+        let response = ApiError::Unauthorized(format!("{} header required", TIMESTAMP_HEADER))
+            .error_response();
+        return Ok(req.into_response(response));
+    };
+
+    let bytes = read_body(&mut req).await?;
+    let path = req
+        .uri()
+        .path_and_query()
+        .map_or_else(|| req.path().to_string(), |path| path.to_string());
+    let result = signing.verify(
+        &timestamp,
+        req.method().as_str(),
+        &path,
+        &bytes,
+        &signature,
+        Utc::now().timestamp(),
+    );
+    match result {
+        Ok(()) => {
+            req.extensions_mut().insert(SignedRequest);
+            Ok(next.call(req).await?.map_into_boxed_body())
+        }
+        Err(e) => {
+            let response = ApiError::Unauthorized(e).error_response();
+            Ok(req.into_response(response))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn signing() -> RequestSigning {
+        RequestSigning {
+            secret: "change-me".into(),
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Signs a request as a client would
+    fn sign(timestamp: i64, method: &str, path: &str, body: &str) -> String {
+        let message = format!("{}.{}.{}.{}", timestamp, method, path, body);
+        format!(
+            "sha256={}",
+            to_hex(&hmac_sha256(b"change-me", message.as_bytes()))
+        )
+    }
+
+    #[test]
+    fn verify_accepts_a_valid_signature() {
+        let signing = signing();
+        let body = r#"{"value":3}"#;
+        let signature = sign(NOW, "POST", "/api/dat/set_power_level", body);
+        assert_eq!(
+            signing.verify(
+                &NOW.to_string(),
+                "POST",
+                "/api/dat/set_power_level",
+                body.as_bytes(),
+                &signature,
+                NOW + 10,
+            ),
+            Ok(())
+        );
+        // Upper-case hex is accepted
+        let signature = sign(NOW, "WS", "/api/ws", "{}").to_uppercase();
+        let signature = signature.replacen("SHA256=", "sha256=", 1);
+        assert!(signing
+            .verify(
+                &format!(" {} ", NOW),
+                "WS",
+                "/api/ws",
+                b"{}",
+                &signature,
+                NOW
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn verify_rejects_any_change_to_the_request() {
+        let signing = signing();
+        let signature = sign(NOW, "POST", "/api/dat/set_power_level?force=true", "{}");
+        let timestamp = NOW.to_string();
+        for (method, path, body) in [
+            ("PUT", "/api/dat/set_power_level?force=true", "{}"),
+            ("POST", "/api/dat/set_power_level", "{}"),
+            ("POST", "/api/dat/set_fan_speed?force=true", "{}"),
+            ("POST", "/api/dat/set_power_level?force=true", "{ }"),
+        ] {
+            assert_eq!(
+                signing.verify(&timestamp, method, path, body.as_bytes(), &signature, NOW),
+                Err("invalid signature".into())
+            );
+        }
+        let path = "/api/dat/set_power_level?force=true";
+        let other_time = (NOW + 1).to_string();
+        assert!(signing
+            .verify(&other_time, "POST", path, b"{}", &signature, NOW)
+            .is_err());
+        let other_secret = RequestSigning {
+            secret: "other".into(),
+            seen: Mutex::new(HashMap::new()),
+        };
+        assert!(other_secret
+            .verify(&timestamp, "POST", path, b"{}", &signature, NOW)
+            .is_err());
+        let truncated = &signature[..signature.len() - 2];
+        assert!(signing
+            .verify(&timestamp, "POST", path, b"{}", truncated, NOW)
+            .is_err());
+    }
+
+    #[test]
+    fn verify_rejects_stale_and_malformed_headers() {
+        let signing = signing();
+        for skew in [-MAX_CLOCK_SKEW_SECS - 1, MAX_CLOCK_SKEW_SECS + 1] {
+            let signature = sign(NOW, "POST", "/p", "");
+            let error = signing
+                .verify(&NOW.to_string(), "POST", "/p", b"", &signature, NOW + skew)
+                .unwrap_err();
+            assert!(
+                error.contains("seconds away from the bridge clock"),
+                "{}",
+                error
+            );
+        }
+        let signature = sign(NOW, "POST", "/p", "");
+        assert_eq!(
+            signing.verify("yesterday", "POST", "/p", b"", &signature, NOW),
+            Err(format!("invalid {} header", TIMESTAMP_HEADER))
+        );
+        assert_eq!(
+            signing.verify(
+                &NOW.to_string(),
+                "POST",
+                "/p",
+                b"",
+                signature.trim_start_matches("sha256="),
+                NOW
+            ),
+            Err(format!("{} must be sha256=<hex>", SIGNATURE_HEADER))
+        );
+    }
+
+    #[test]
+    fn verify_rejects_replayed_signatures() {
+        let signing = signing();
+        let signature = sign(NOW, "POST", "/p", "{}");
+        let verify = |now| signing.verify(&NOW.to_string(), "POST", "/p", b"{}", &signature, now);
+        assert_eq!(verify(NOW), Ok(()));
+        assert_eq!(verify(NOW + 1), Err("signature already used".into()));
+        assert_eq!(
+            verify(NOW + MAX_CLOCK_SKEW_SECS),
+            Err("signature already used".into())
+        );
+        // Another request signed in the same second is not a replay
+        let other = sign(NOW, "POST", "/p", "{\"id\":2}");
+        assert!(signing
+            .verify(&NOW.to_string(), "POST", "/p", b"{\"id\":2}", &other, NOW)
+            .is_ok());
+    }
+
+    #[test]
+    fn stale_signatures_are_forgotten() {
+        let signing = signing();
+        let first = sign(NOW, "POST", "/p", "");
+        assert!(signing
+            .verify(&NOW.to_string(), "POST", "/p", b"", &first, NOW)
+            .is_ok());
+        let later = NOW + MAX_CLOCK_SKEW_SECS + 1;
+        let second = sign(later, "POST", "/p", "");
+        assert!(signing
+            .verify(&later.to_string(), "POST", "/p", b"", &second, later)
+            .is_ok());
+        assert_eq!(mutex_lock(&signing.seen, "test").len(), 1);
+    }
+}
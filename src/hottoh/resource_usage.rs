@@ -0,0 +1,79 @@
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::tcp_client_structs::{Request, Response};
+use std::collections::VecDeque;
+use std::fs;
+use std::mem::size_of;
+use std::sync::RwLock;
+
+/// Resources used by the process, to check that it stays within the budget of small
+/// boards over weeks of uptime
+///
+/// The process values are read from `/proc/self`, so they are only known on Linux.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceUsage {
+    /// Resident memory in bytes
+    pub rss_bytes: Option<u64>,
+    /// Number of open file descriptors, sockets included
+    pub open_fds: Option<u64>,
+    /// Number of threads, including the workers of the HTTP server
+    pub threads: Option<u64>,
+    /// Estimate of the memory held by the request and response queues, in bytes
+    pub queue_bytes: u64,
+}
+
+impl ResourceUsage {
+    /// Measures the resources used by the process
+    ///
+    /// # Arguments
+    ///
+    /// * `request_queue` - Queue of the requests to the stove
+    /// * `response_queue` - Queue of the responses of the stove
+    ///
+    /// # Returns
+    ///
+    /// * `ResourceUsage` - The current usage
+    pub fn collect(
+        request_queue: &RwLock<VecDeque<Request>>,
+        response_queue: &RwLock<VecDeque<Response>>,
+    ) -> Self {
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let requests: usize = read_lock(request_queue, "request queue")
+            .iter()
+            .map(|request| {
+                size_of::<Request>()
+                    + request
+                        .get_params()
+                        .iter()
+                        .map(String::capacity)
+                        .sum::<usize>()
+            })
+            .sum();
+        let responses = read_lock(response_queue, "response queue").len() * size_of::<Response>();
+        Self {
+            rss_bytes: status_value(&status, "VmRSS").map(|kb| kb * 1024),
+            open_fds: fs::read_dir("/proc/self/fd")
+                .ok()
+                .map(|entries| entries.count() as u64),
+            threads: status_value(&status, "Threads"),
+            queue_bytes: (requests + responses) as u64,
+        }
+    }
+}
+
+/// Reads a number from the content of `/proc/self/status`
+///
+/// # Arguments
+///
+/// * `status` - The content of the file
+/// * `key` - The key, e.g. `VmRSS` (in kB) or `Threads`
+///
+/// # Returns
+///
+/// * `Option<u64>` - The number, without its unit, or None if absent
+fn status_value(status: &str, key: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
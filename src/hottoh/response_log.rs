@@ -0,0 +1,73 @@
+use crate::hottoh::locks::mutex_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::response_stream::Responses;
+use crate::hottoh::supervisor::supervise;
+use chrono::Local;
+use log::{error, info};
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Starts a thread appending every response parsed from the stove to a file
+///
+/// Each line is a JSON object with the time of reception, the page and its fields,
+/// e.g. `{"time": "...", "page": "DAT0", "data": {...}}`, to study the behaviour of
+/// the stove over time or the params of an unsupported model.
+///
+/// # Arguments
+///
+/// * `path` - The `general.response_log` file, or None if disabled
+/// * `responses` - The responses of the TCP client
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_response_log_thread(
+    path: Option<String>,
+    responses: Responses,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let path = path?;
+    // Kept across restarts of the thread, the subscription cannot be renewed
+    let responses = Mutex::new(responses);
+
+    Some(supervise(
+        "response_log",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let mut file = match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    error!("Response log disabled, cannot open '{}': {}", path, e);
+                    return;
+                }
+            };
+            info!("Logging the responses of the stove to {}", path);
+            let responses = mutex_lock(&responses, "responses");
+            while running.load(Ordering::SeqCst) {
+                let data = match responses.next_timeout(Duration::from_millis(200)) {
+                    Ok(data) => data,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let line = json!({
+                    "time": Local::now().to_rfc3339(),
+                    "page": data.page(),
+                    "data": data.to_json(),
+                });
+                if let Err(e) = writeln!(file, "{}", line) {
+                    error!("Cannot write to the response log '{}': {}", path, e);
+                }
+            }
+        },
+    ))
+}
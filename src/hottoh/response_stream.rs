@@ -0,0 +1,81 @@
+use crate::hottoh::hottoh_structs::CommandData;
+use crate::hottoh::locks::mutex_lock;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Bus distributing every response parsed from the stove
+///
+/// Unlike the shared state, which only keeps the last page of each kind, the
+/// subscribers get every frame, including the retransmissions and the answers to
+/// the write commands. Subscribers that dropped their stream are removed at the next
+/// response.
+#[derive(Debug, Default)]
+pub struct ResponseBus {
+    subscribers: Mutex<Vec<Sender<CommandData>>>,
+}
+
+impl ResponseBus {
+    /// Creates a bus without subscribers
+    ///
+    /// # Returns
+    ///
+    /// * `ResponseBus` - The bus
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribes to the responses received from now on
+    ///
+    /// # Returns
+    ///
+    /// * `Responses` - The stream of the responses
+    pub fn subscribe(&self) -> Responses {
+        let (sender, receiver) = channel();
+        mutex_lock(&self.subscribers, "response subscribers").push(sender);
+        Responses { receiver }
+    }
+
+    /// Publishes a response to every subscriber
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data of the response
+    pub fn publish(&self, data: &CommandData) {
+        let mut subscribers = mutex_lock(&self.subscribers, "response subscribers");
+        if !subscribers.is_empty() {
+            subscribers.retain(|subscriber| subscriber.send(data.clone()).is_ok());
+        }
+    }
+}
+
+/// Stream of the responses received from the stove, in order of arrival
+///
+/// Iterating blocks until the next response, and ends when the bus is dropped.
+pub struct Responses {
+    receiver: Receiver<CommandData>,
+}
+
+impl Responses {
+    /// Waits for the next response at most for a given time
+    ///
+    /// # Arguments
+    ///
+    /// * `timeout` - Longest time to wait
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandData, RecvTimeoutError>` - The response, or `Timeout` if none
+    ///   was received in time, or `Disconnected` if the bus was dropped
+    pub fn next_timeout(&self, timeout: Duration) -> Result<CommandData, RecvTimeoutError> {
+        self.receiver.recv_timeout(timeout)
+    }
+}
+
+impl Iterator for Responses {
+    type Item = CommandData;
+
+    fn next(&mut self) -> Option<CommandData> {
+        self.receiver.recv().ok()
+    }
+}
@@ -0,0 +1,674 @@
+use crate::hottoh::events::Event;
+use crate::hottoh::inbound_webhooks::parse_action;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_request, CommandOrigin};
+use log::{debug, error, info, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+/// Comparison operator of a condition
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+/// Operand of a comparison
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// Field of the state, as `page.field`
+    Field(String),
+    /// Number literal
+    Number(f64),
+    /// Text literal (a bare word, e.g. `Power` for the stove state)
+    Text(String),
+}
+
+/// Condition of a rule
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Comparison, Operand),
+    /// An operand alone, true if it is a true boolean or a non-zero number
+    Truthy(Operand),
+}
+
+/// Action run when a rule is triggered
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    /// Sends a command to the stove, as a StoveCommands value and a raw value
+    Command(u32, i32),
+    /// Logs a warning naming the rule
+    Notify,
+}
+
+/// Rule of the `[rules]` section
+#[derive(Debug, Clone)]
+pub struct Rule {
+    name: String,
+    condition: Expr,
+    actions: Vec<Action>,
+}
+
+impl Rule {
+    /// Parses a rule such as `dat0.smoke_t > 180 && dat0.fan_1_set < 3 => FanSpeed1=3, notify`
+    ///
+    /// Fields are written `page.field`, where the `index_` prefix of the field can be
+    /// omitted. Actions are `Command=value` (a StoveCommands name and the raw value
+    /// sent to the stove) or `notify`.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the rule
+    /// * `spec` - The rule
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Rule, String>` - The rule, or an error if it is invalid
+    pub fn parse(name: &str, spec: &str) -> Result<Self, String> {
+        let (condition, actions) = spec
+            .split_once("=>")
+            .ok_or_else(|| "expected '<condition> => <actions>'".to_string())?;
+
+        let fields = known_fields();
+        let tokens = tokenize(condition)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            position: 0,
+            fields: &fields,
+        };
+        let condition = parser.parse_or()?;
+        if let Some(token) = tokens.get(parser.position) {
+            return Err(format!("unexpected '{}' in condition", token));
+        }
+
+        let actions = actions
+            .split(',')
+            .map(str::trim)
+            .map(|action| {
+                if action.eq_ignore_ascii_case("notify") {
+                    return Ok(Action::Notify);
+                }
+                let (command, value) = parse_action(action)?[0];
+                Ok(Action::Command(command, value))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            condition,
+            actions,
+        })
+    }
+}
+
+/// Parses the rules of the `[rules]` section
+///
+/// # Arguments
+///
+/// * `rules` - The rules by name
+///
+/// # Returns
+///
+/// * `Result<Vec<Rule>, String>` - The rules sorted by name, or the first error
+pub fn parse_rules(rules: &HashMap<String, String>) -> Result<Vec<Rule>, String> {
+    let mut names: Vec<&String> = rules.keys().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| Rule::parse(name, &rules[name]).map_err(|e| format!("{}: {}", name, e)))
+        .collect()
+}
+
+/// Starts a thread evaluating the rules on every state update published on the event bus
+///
+/// The actions of a rule run once when its condition becomes true, and again only
+/// after it has been false. Commands are sent as automation commands, so they are
+/// suppressed in read-only mode and during maintenance windows.
+///
+/// # Arguments
+///
+/// * `rules` - The `[rules]` section, or None if absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_rules_thread(
+    rules: Option<&HashMap<String, String>>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let rules = match parse_rules(rules?) {
+        Ok(rules) if !rules.is_empty() => rules,
+        Ok(_) => return None,
+        Err(e) => {
+            error!("Rules disabled: invalid rule {}", e);
+            return None;
+        }
+    };
+    info!("Evaluating {} rule(s)", rules.len());
+
+    Some(supervise(
+        "rules",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut active = vec![false; rules.len()];
+            while running.load(Ordering::SeqCst) {
+                match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { .. }) => {}
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+
+                let state = match serde_json::to_value(&*read_lock(&shared_state, "shared state")) {
+                    Ok(state) => state,
+                    Err(e) => {
+                        error!("Cannot serialize shared state for rules: {}", e);
+                        continue;
+                    }
+                };
+
+                for (rule, active) in rules.iter().zip(active.iter_mut()) {
+                    let matched = evaluate(&rule.condition, &state);
+                    if matched && !*active {
+                        run_actions(rule, &shared_state, &commands);
+                    }
+                    *active = matched;
+                }
+            }
+            info!("Rules thread stopped.");
+        },
+    ))
+}
+
+/// Runs the actions of a triggered rule
+///
+/// # Arguments
+///
+/// * `rule` - The rule
+/// * `shared_state` - Shared state where the pending values are recorded
+/// * `commands` - Queues used to send commands to the stove
+fn run_actions(rule: &Rule, shared_state: &RwLock<SharedState>, commands: &CommandQueue) {
+    info!("Rule '{}' triggered", rule.name);
+    for action in &rule.actions {
+        match action {
+            Action::Notify => warn!("Rule '{}' condition is met", rule.name),
+            Action::Command(command, value) => match queue_write_request(
+                &commands.request_queue,
+                &commands.request_id_counter,
+                shared_state,
+                CommandOrigin::Automation,
+                *command,
+                value,
+            ) {
+                Ok(request_id) => debug!(
+                    "Rule '{}' queued command {}={} (id {})",
+                    rule.name, command, value, request_id
+                ),
+                Err(e) => warn!(
+                    "Rule '{}' command {}={} rejected: {}",
+                    rule.name, command, value, e
+                ),
+            },
+        }
+    }
+}
+
+/// Evaluates a condition against the state
+///
+/// A comparison involving a field not received yet is false.
+///
+/// # Arguments
+///
+/// * `expr` - The condition
+/// * `state` - The shared state serialized as JSON
+///
+/// # Returns
+///
+/// * `bool` - True if the condition is met
+fn evaluate(expr: &Expr, state: &Value) -> bool {
+    match expr {
+        Expr::Or(left, right) => evaluate(left, state) || evaluate(right, state),
+        Expr::And(left, right) => evaluate(left, state) && evaluate(right, state),
+        Expr::Not(inner) => !evaluate(inner, state),
+        Expr::Truthy(operand) => match resolve(operand, state) {
+            Some(Value::Bool(value)) => value,
+            Some(Value::Number(value)) => value.as_f64().is_some_and(|value| value != 0.0),
+            _ => false,
+        },
+        Expr::Compare(left, comparison, right) => {
+            let (Some(left), Some(right)) = (resolve(left, state), resolve(right, state)) else {
+                return false;
+            };
+            compare(&left, *comparison, &right)
+        }
+    }
+}
+
+/// Gets the value of an operand
+///
+/// # Arguments
+///
+/// * `operand` - The operand
+/// * `state` - The shared state serialized as JSON
+///
+/// # Returns
+///
+/// * `Option<Value>` - The value, or None if the field was not received yet
+fn resolve(operand: &Operand, state: &Value) -> Option<Value> {
+    match operand {
+        Operand::Field(field) => get_field(state, field).cloned(),
+        Operand::Number(number) => Some(Value::from(*number)),
+        Operand::Text(text) => Some(Value::from(text.as_str())),
+    }
+}
+
+/// Compares two values
+///
+/// Numbers and booleans (as 1/0) are compared numerically, text only for equality.
+///
+/// # Arguments
+///
+/// * `left` - The left value
+/// * `comparison` - The operator
+/// * `right` - The right value
+///
+/// # Returns
+///
+/// * `bool` - The result of the comparison
+fn compare(left: &Value, comparison: Comparison, right: &Value) -> bool {
+    let number = |value: &Value| match value {
+        Value::Bool(value) => Some(if *value { 1.0 } else { 0.0 }),
+        Value::Number(value) => value.as_f64(),
+        _ => None,
+    };
+    match (number(left), number(right)) {
+        (Some(left), Some(right)) => match comparison {
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Equal => (left - right).abs() < f64::EPSILON,
+            Comparison::NotEqual => (left - right).abs() >= f64::EPSILON,
+        },
+        _ => {
+            let equal = match (left.as_str(), right.as_str()) {
+                (Some(left), Some(right)) => left.eq_ignore_ascii_case(right),
+                _ => left == right,
+            };
+            match comparison {
+                Comparison::Equal => equal,
+                Comparison::NotEqual => !equal,
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Operators of a condition
+const OPERATORS: &[&str] = &[">", ">=", "<", "<=", "==", "!=", "!", "&&", "||"];
+
+/// Splits a condition into tokens
+///
+/// # Arguments
+///
+/// * `condition` - The condition
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, String>` - The tokens, or an error on an unexpected character
+fn tokenize(condition: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = condition.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-') {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        } else if c == '(' || c == ')' {
+            tokens.push(c.to_string());
+            chars.next();
+        } else {
+            let mut operator = String::new();
+            while let Some(&c) = chars.peek() {
+                let longer = format!("{}{}", operator, c);
+                if !OPERATORS.iter().any(|known| known.starts_with(&longer)) {
+                    break;
+                }
+                operator = longer;
+                chars.next();
+            }
+            if operator.is_empty() {
+                return Err(format!("unexpected character '{}'", c));
+            }
+            tokens.push(operator);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser of conditions
+///
+/// `||` binds looser than `&&`, which binds looser than `!` and the comparisons.
+struct Parser<'a> {
+    tokens: &'a [String],
+    position: usize,
+    /// Known fields, as `page.field`
+    fields: &'a [String],
+}
+
+impl Parser<'_> {
+    /// Returns the next token without consuming it
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The next token, or None at the end of the condition
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(String::as_str)
+    }
+
+    /// Consumes the next token
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The token, or None at the end of the condition
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position).map(String::as_str);
+        self.position += 1;
+        token
+    }
+
+    /// Parses `a || b || ...`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Expr, String>` - The expression, or an error
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some("||") {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parses `a && b && ...`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Expr, String>` - The expression, or an error
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.peek() == Some("&&") {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    /// Parses `!a`, `(a)` or a comparison
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Expr, String>` - The expression, or an error
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek() {
+            Some("!") => {
+                self.next();
+                Ok(Expr::Not(Box::new(self.parse_unary()?)))
+            }
+            Some("(") => {
+                self.next();
+                let expr = self.parse_or()?;
+                match self.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err("missing ')'".into()),
+                }
+            }
+            _ => self.parse_comparison(),
+        }
+    }
+
+    /// Parses `operand [operator operand]`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Expr, String>` - The expression, or an error
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_operand()?;
+        let comparison = match self.peek() {
+            Some(">") => Comparison::Greater,
+            Some(">=") => Comparison::GreaterOrEqual,
+            Some("<") => Comparison::Less,
+            Some("<=") => Comparison::LessOrEqual,
+            Some("==") => Comparison::Equal,
+            Some("!=") => Comparison::NotEqual,
+            _ => return Ok(Expr::Truthy(left)),
+        };
+        self.next();
+        let right = self.parse_operand()?;
+        Ok(Expr::Compare(left, comparison, right))
+    }
+
+    /// Parses a field, a number, a boolean (as 1/0) or a text
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Operand, String>` - The operand, or an error if the field is unknown
+    fn parse_operand(&mut self) -> Result<Operand, String> {
+        let fields = self.fields;
+        let token = self
+            .next()
+            .ok_or_else(|| "unexpected end of condition".to_string())?;
+        if let Ok(number) = token.parse::<f64>() {
+            return Ok(Operand::Number(number));
+        }
+        match token.to_ascii_lowercase().as_str() {
+            "true" => return Ok(Operand::Number(1.0)),
+            "false" => return Ok(Operand::Number(0.0)),
+            _ => {}
+        }
+        if !token.contains('.') {
+            if !token.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("unexpected '{}' in condition", token));
+            }
+            return Ok(Operand::Text(token.to_string()));
+        }
+        resolve_field(fields, token).map(Operand::Field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hottoh::hottoh_const::StoveCommands;
+    use serde_json::json;
+
+    /// Serialized state with a received `dat0` page
+    fn state(smoke_t: f64, fan_1_set: u16, stove_on: bool) -> Value {
+        json!({
+            "dat0": {
+                "last_updated": "2024-01-01T00:00:00+00:00",
+                "index_smoke_t": smoke_t,
+                "index_fan_1_set": fan_1_set,
+                "index_stove_on": stove_on,
+                "index_stove_state": "Power",
+            }
+        })
+    }
+
+    #[test]
+    fn tokenize_splits_words_operators_and_parentheses() {
+        assert_eq!(
+            tokenize("(dat0.smoke_t>=180.5&&!dat0.stove_on) || x != -2").unwrap(),
+            [
+                "(",
+                "dat0.smoke_t",
+                ">=",
+                "180.5",
+                "&&",
+                "!",
+                "dat0.stove_on",
+                ")",
+                "||",
+                "x",
+                "!=",
+                "-2"
+            ]
+        );
+        assert_eq!(
+            tokenize("dat0.smoke_t > 180 + 1").unwrap_err(),
+            "unexpected character '+'"
+        );
+    }
+
+    #[test]
+    fn parse_resolves_fields_and_actions() {
+        let rule = Rule::parse("hot", "dat0.smoke_t > 180 => FanSpeed1=3, notify").unwrap();
+        assert_eq!(rule.name, "hot");
+        assert_eq!(
+            rule.condition,
+            Expr::Compare(
+                Operand::Field("dat0.index_smoke_t".into()),
+                Comparison::Greater,
+                Operand::Number(180.0)
+            )
+        );
+        assert_eq!(
+            rule.actions,
+            [
+                Action::Command(StoveCommands::FanSpeed1 as u32, 3),
+                Action::Notify
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_gives_and_precedence_over_or() {
+        let rule = Rule::parse("r", "a || dat0.stove_on && !b => notify").unwrap();
+        assert_eq!(
+            rule.condition,
+            Expr::Or(
+                Box::new(Expr::Truthy(Operand::Text("a".into()))),
+                Box::new(Expr::And(
+                    Box::new(Expr::Truthy(Operand::Field("dat0.index_stove_on".into()))),
+                    Box::new(Expr::Not(Box::new(Expr::Truthy(Operand::Text("b".into())))))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn parse_rejects_invalid_rules() {
+        let error = |spec| Rule::parse("r", spec).unwrap_err();
+        assert_eq!(
+            error("dat0.smoke_t > 180"),
+            "expected '<condition> => <actions>'"
+        );
+        assert_eq!(
+            error("dat0.nothing > 1 => notify"),
+            "unknown field 'dat0.nothing'"
+        );
+        assert_eq!(error("(dat0.smoke_t > 1 => notify"), "missing ')'");
+        assert_eq!(
+            error("dat0.smoke_t > => notify"),
+            "unexpected end of condition"
+        );
+        assert_eq!(
+            error("dat0.smoke_t > 1 ) => notify"),
+            "unexpected ')' in condition"
+        );
+        assert_eq!(
+            error("dat0.smoke_t > 1 => Nothing=1"),
+            "unknown command 'Nothing'"
+        );
+        assert_eq!(
+            error("dat0.smoke_t > 1 => FanSpeed1=x"),
+            "invalid value 'x'"
+        );
+    }
+
+    #[test]
+    fn parse_rules_sorts_by_name_and_prefixes_errors() {
+        let rules = HashMap::from([
+            ("b".to_string(), "dat0.stove_on => notify".to_string()),
+            ("a".to_string(), "dat0.smoke_t > 1 => notify".to_string()),
+        ]);
+        let names: Vec<String> = parse_rules(&rules)
+            .unwrap()
+            .into_iter()
+            .map(|rule| rule.name)
+            .collect();
+        assert_eq!(names, ["a", "b"]);
+
+        let rules = HashMap::from([("bad".to_string(), "x > 1".to_string())]);
+        assert_eq!(
+            parse_rules(&rules).unwrap_err(),
+            "bad: expected '<condition> => <actions>'"
+        );
+    }
+
+    #[test]
+    fn evaluate_compares_numbers_booleans_and_text() {
+        let matches = |condition: &str, state: &Value| {
+            let rule = Rule::parse("r", &format!("{} => notify", condition)).unwrap();
+            evaluate(&rule.condition, state)
+        };
+        let hot = state(185.0, 2, true);
+        assert!(matches("dat0.smoke_t > 180 && dat0.fan_1_set < 3", &hot));
+        assert!(!matches("dat0.smoke_t > 180 && dat0.fan_1_set < 2", &hot));
+        assert!(matches("dat0.smoke_t <= 180 || dat0.fan_1_set == 2", &hot));
+        assert!(matches("dat0.stove_on", &hot));
+        assert!(matches("dat0.stove_on == true", &hot));
+        assert!(!matches("!dat0.stove_on", &hot));
+        assert!(matches("dat0.stove_state == power", &hot));
+        assert!(matches("dat0.stove_state != Off", &hot));
+        assert!(!matches("dat0.stove_state > Off", &hot));
+
+        let off = state(20.0, 0, false);
+        assert!(!matches("dat0.stove_on", &off));
+        assert!(matches("!(dat0.smoke_t > 180 || dat0.stove_on)", &off));
+    }
+
+    #[test]
+    fn evaluate_is_false_until_the_page_is_received() {
+        let rule = Rule::parse("r", "dat0.smoke_t < 1000 => notify").unwrap();
+        let mut pending = state(20.0, 0, false);
+        pending["dat0"]["last_updated"] = json!("");
+        assert!(!evaluate(&rule.condition, &pending));
+        assert!(!evaluate(&rule.condition, &json!({})));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::{mutex_lock, read_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_requests, CommandOrigin};
+use chrono::{DateTime, Local};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// File keeping the scheduled stop when `general.run_timer_file` is not set
+pub const DEFAULT_RUN_TIMER_FILE: &str = "run_timer.json";
+/// Longest time-limited run, in minutes
+pub const MAX_RUN_MINUTES: u64 = 24 * 60;
+/// Delay before turning the stove off again after the command was rejected
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Stop scheduled at the end of a time-limited run, as kept in its file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ScheduledStop {
+    /// When the stove is turned off, in RFC 3339
+    off_at: String,
+    /// Turn the stove off even during a maintenance window
+    force: bool,
+}
+
+/// Automatic stop of the stove at the end of a time-limited run
+///
+/// The scheduled stop is kept in a file, so a run started before a restart of the
+/// bridge still ends on time. The timer lives in the shared state, and its changes
+/// return a `RunTimerSave` that writes the file once the state is unlocked.
+#[derive(Debug, Clone)]
+pub struct RunTimer {
+    /// Path of the file keeping the scheduled stop
+    path: String,
+    /// When the stove is turned off and whether maintenance windows are bypassed,
+    /// None if no run is in progress
+    stop: Option<(DateTime<Local>, bool)>,
+    /// Number of changes of the scheduled stop
+    generation: u64,
+    /// Generation of the last change written to the file
+    saved: Arc<Mutex<u64>>,
+}
+
+/// Change of the scheduled stop to write to its file
+///
+/// Changes saved out of order are not written over a later one.
+#[derive(Debug)]
+#[must_use = "the change is lost on restart if it is not saved"]
+pub struct RunTimerSave {
+    /// Path of the file keeping the scheduled stop
+    path: String,
+    /// The scheduled stop, None to remove the file
+    stop: Option<ScheduledStop>,
+    /// Generation of the change
+    generation: u64,
+    /// Generation of the last change written to the file
+    saved: Arc<Mutex<u64>>,
+}
+
+impl RunTimerSave {
+    /// Writes the change to the file, or removes it if the stop was cancelled
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<()>` - An error if the file cannot be written or removed
+    pub fn save(self) -> io::Result<()> {
+        let mut saved = mutex_lock(&self.saved, "run timer file");
+        if *saved > self.generation {
+            return Ok(());
+        }
+        *saved = self.generation;
+        match self.stop {
+            Some(stop) => fs::write(
+                &self.path,
+                serde_json::to_string_pretty(&stop).map_err(io::Error::other)?,
+            ),
+            None => match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            },
+        }
+    }
+}
+
+impl RunTimer {
+    /// Loads the scheduled stop from its file
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the file keeping the scheduled stop
+    ///
+    /// # Returns
+    ///
+    /// * `RunTimer` - The timer, without scheduled stop if the file is absent or invalid
+    pub fn load(path: &str) -> Self {
+        let stop = match fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<ScheduledStop>(&content)
+                .map_err(|e| e.to_string())
+                .and_then(|stop| {
+                    DateTime::parse_from_rfc3339(&stop.off_at)
+                        .map(|off_at| (off_at.with_timezone(&Local), stop.force))
+                        .map_err(|e| e.to_string())
+                }) {
+                Ok(stop) => Some(stop),
+                Err(e) => {
+                    error!("Invalid run timer file '{}': {}. Ignoring it.", path, e);
+                    None
+                }
+            },
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => {
+                error!("Cannot read run timer file '{}': {}", path, e);
+                None
+            }
+        };
+        if let Some((off_at, _)) = stop {
+            info!("Stove will be turned off at {}", off_at.to_rfc3339());
+        }
+        Self {
+            path: path.to_string(),
+            stop,
+            generation: 0,
+            saved: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    /// Gets when the stove is turned off
+    ///
+    /// # Returns
+    ///
+    /// * `Option<DateTime<Local>>` - The time of the stop, None if no run is in progress
+    pub fn get_off_at(&self) -> Option<DateTime<Local>> {
+        self.stop.map(|(off_at, _)| off_at)
+    }
+
+    /// Schedules the stop of the stove, replacing the previous one
+    ///
+    /// # Arguments
+    ///
+    /// * `off_at` - When the stove is turned off
+    /// * `force` - Turn the stove off even during a maintenance window
+    ///
+    /// # Returns
+    ///
+    /// * `RunTimerSave` - The change to save, without which the stop is kept until the
+    ///   next restart only
+    pub fn schedule(&mut self, off_at: DateTime<Local>, force: bool) -> RunTimerSave {
+        self.stop = Some((off_at, force));
+        self.change(Some(ScheduledStop {
+            off_at: off_at.to_rfc3339(),
+            force,
+        }))
+    }
+
+    /// Cancels the scheduled stop
+    ///
+    /// # Returns
+    ///
+    /// * `RunTimerSave` - The change to save, removing the file of the stop
+    pub fn cancel(&mut self) -> RunTimerSave {
+        self.stop = None;
+        self.change(None)
+    }
+
+    /// Records a change of the scheduled stop
+    ///
+    /// # Arguments
+    ///
+    /// * `stop` - The stop to write to the file, None to remove it
+    ///
+    /// # Returns
+    ///
+    /// * `RunTimerSave` - The change to save
+    fn change(&mut self, stop: Option<ScheduledStop>) -> RunTimerSave {
+        self.generation += 1;
+        RunTimerSave {
+            path: self.path.clone(),
+            stop,
+            generation: self.generation,
+            saved: Arc::clone(&self.saved),
+        }
+    }
+
+    /// Gets the stop if it is due
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The local time
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(DateTime<Local>, bool)>` - The time and the `force` flag of the stop,
+    ///   None if no stop is due
+    fn due(&self, now: DateTime<Local>) -> Option<(DateTime<Local>, bool)> {
+        self.stop.filter(|(off_at, _)| *off_at <= now)
+    }
+}
+
+/// Starts a thread turning the stove off at the end of the time-limited runs
+///
+/// The stop is sent as a manual command which never expires, so a stove disconnected
+/// at the end of the run is turned off when it comes back. If the command is rejected
+/// (read-only mode, maintenance window, throttling), it is tried again every 30 seconds.
+///
+/// # Arguments
+///
+/// * `shared_state` - Shared state holding the stove data and the run timer
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `thread::JoinHandle<()>` - Handle to the spawned thread
+pub fn start_run_timer_thread(
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> thread::JoinHandle<()> {
+    supervise("run_timer", Arc::clone(&running), metrics, move || {
+        let mut next_attempt = Instant::now();
+        while running.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(200));
+            if Instant::now() < next_attempt {
+                continue;
+            }
+            let Some((off_at, force)) = read_lock(&shared_state, "shared state")
+                .get_run_timer()
+                .and_then(|run_timer| run_timer.due(Local::now()))
+            else {
+                continue;
+            };
+            match queue_write_requests(
+                &commands.request_queue,
+                &commands.request_id_counter,
+                &shared_state,
+                // Turning the stove off is the purpose of the run, confirmed by its request
+                CommandOrigin::Manual {
+                    force,
+                    confirmed: true,
+                },
+                &[(StoveCommands::OnOff as u32, "0".to_string())],
+                Some(Duration::ZERO),
+            ) {
+                Ok(request_ids) => {
+                    info!(
+                        "End of the run, turning the stove off (id {})",
+                        request_ids[0]
+                    )
+                }
+                Err(e) => {
+                    warn!("End of the run, cannot turn the stove off: {}", e);
+                    next_attempt = Instant::now() + RETRY_DELAY;
+                    continue;
+                }
+            }
+            // A run started in the meantime keeps its own stop
+            let cancelled = write_lock(&shared_state, "shared state")
+                .get_run_timer_mut()
+                .filter(|run_timer| run_timer.get_off_at() == Some(off_at))
+                .map(RunTimer::cancel);
+            if let Some(Err(e)) = cancelled.map(RunTimerSave::save) {
+                error!("Cannot remove run timer file: {}", e);
+            }
+        }
+        info!("Run timer thread stopped.");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_saved_out_of_order_keep_the_last_one() {
+        let path = std::env::temp_dir().join(format!("run_timer_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut run_timer = RunTimer::load(path);
+        let off_at = Local::now() + Duration::from_secs(3600);
+
+        let scheduled = run_timer.schedule(off_at, false);
+        let cancelled = run_timer.cancel();
+        cancelled.save().unwrap();
+        scheduled.save().unwrap();
+        assert!(!std::path::Path::new(path).exists());
+
+        run_timer.schedule(off_at, true).save().unwrap();
+        let loaded = RunTimer::load(path);
+        assert_eq!(
+            loaded.get_off_at().map(|off_at| off_at.timestamp()),
+            Some(off_at.timestamp())
+        );
+        assert_eq!(loaded.due(off_at).map(|(_, force)| force), Some(true));
+        run_timer.cancel().save().unwrap();
+        assert!(!std::path::Path::new(path).exists());
+    }
+}
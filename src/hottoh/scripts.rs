@@ -0,0 +1,354 @@
+use crate::hottoh::config::ScriptsConfig;
+use crate::hottoh::events::Event;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client::{queue_write_request, CommandOrigin};
+use log::{debug, error, info, warn};
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Scope, AST};
+use serde_json::json;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Extension of the script files
+const SCRIPT_EXTENSION: &str = "rhai";
+/// Interval between two checks of the script directory
+const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+/// Hook called with `(state, page)` when a page is received from the stove
+const ON_STATE_CHANGE: &str = "on_state_change";
+/// Hook called with `(state, command)` when a command is queued
+const ON_COMMAND: &str = "on_command";
+/// Hook called with `(state, alarm)` when the stove enters an alarm state
+const ON_ALARM: &str = "on_alarm";
+
+/// Script loaded from the script directory
+struct Script {
+    /// File name of the script, used in the logs
+    name: String,
+    /// The compiled script
+    ast: AST,
+}
+
+impl Script {
+    /// Checks if the script defines a hook
+    ///
+    /// # Arguments
+    ///
+    /// * `hook` - Name of the hook, taking two parameters
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the script has a function with this name and two parameters
+    fn has_hook(&self, hook: &str) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|function| function.name == hook && function.params.len() == 2)
+    }
+}
+
+/// Scripts of the script directory, with the modification times they were loaded at
+struct Scripts {
+    /// The scripts that compiled, sorted by file name
+    scripts: Vec<Script>,
+    /// Script files with their modification time, including the ones that did not compile
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl Scripts {
+    /// Loads the scripts of a directory
+    ///
+    /// The top-level statements of each script are run once. Scripts that do not
+    /// compile or fail to run are logged and skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The script engine
+    /// * `directory` - The script directory
+    ///
+    /// # Returns
+    ///
+    /// * `Scripts` - The loaded scripts
+    fn load(engine: &Engine, directory: &Path) -> Self {
+        let files = list_scripts(directory);
+        let mut scripts = Vec::new();
+        for (path, _) in &files {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let loaded = engine
+                .compile_file(path.clone())
+                .and_then(|ast| engine.run_ast(&ast).map(|_| ast));
+            match loaded {
+                Ok(ast) => scripts.push(Script { name, ast }),
+                Err(e) => error!("Script {} not loaded: {}", name, e),
+            }
+        }
+        info!(
+            "Loaded {} script(s) from {}",
+            scripts.len(),
+            directory.display()
+        );
+        Self { scripts, files }
+    }
+
+    /// Checks if a script was added, removed or modified since the scripts were loaded
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` - The script directory
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the scripts must be reloaded
+    fn is_stale(&self, directory: &Path) -> bool {
+        list_scripts(directory) != self.files
+    }
+
+    /// Calls a hook of every script defining it
+    ///
+    /// Errors, including the scripts exceeding `scripts.max_operations`, are logged.
+    ///
+    /// # Arguments
+    ///
+    /// * `engine` - The script engine
+    /// * `hook` - Name of the hook
+    /// * `shared_state` - Shared state, passed to the hook as a map
+    /// * `argument` - Second argument of the hook
+    fn call(
+        &self,
+        engine: &Engine,
+        hook: &str,
+        shared_state: &RwLock<SharedState>,
+        argument: Dynamic,
+    ) {
+        let scripts: Vec<&Script> = self
+            .scripts
+            .iter()
+            .filter(|script| script.has_hook(hook))
+            .collect();
+        if scripts.is_empty() {
+            return;
+        }
+        let state = serde_json::to_value(&*read_lock(shared_state, "shared state"))
+            .ok()
+            .and_then(|state| rhai::serde::to_dynamic(state).ok())
+            .unwrap_or(Dynamic::UNIT);
+        for script in scripts {
+            let result = engine.call_fn_with_options::<Dynamic>(
+                CallFnOptions::new().eval_ast(false),
+                &mut Scope::new(),
+                &script.ast,
+                hook,
+                (state.clone(), argument.clone()),
+            );
+            if let Err(e) = result {
+                warn!("Script {} failed in {}: {}", script.name, hook, e);
+            }
+        }
+    }
+}
+
+/// Lists the script files of a directory
+///
+/// # Arguments
+///
+/// * `directory` - The script directory
+///
+/// # Returns
+///
+/// * `Vec<(PathBuf, Option<SystemTime>)>` - The `.rhai` files with their modification
+///   time, sorted by name (empty if the directory cannot be read)
+fn list_scripts(directory: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut files: Vec<(PathBuf, Option<SystemTime>)> = fs::read_dir(directory)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| {
+                    path.is_file()
+                        && path.extension().and_then(|ext| ext.to_str()) == Some(SCRIPT_EXTENSION)
+                })
+                .map(|path| {
+                    let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+                    (path, modified)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    files.sort();
+    files
+}
+
+/// Creates the script engine
+///
+/// Scripts can call `send_command(name, value)`, which queues a `StoveCommands` command
+/// with its raw value as an automation command and returns its request ID. `print` and
+/// `debug` write to the log.
+///
+/// # Arguments
+///
+/// * `max_operations` - Operations a hook may run before it is aborted (0 for no limit)
+/// * `shared_state` - Shared state where the pending values are recorded
+/// * `commands` - Queues used to send commands to the stove
+///
+/// # Returns
+///
+/// * `Engine` - The engine
+fn build_engine(
+    max_operations: u64,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+) -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(max_operations);
+    engine.on_print(|text| info!("Script: {}", text));
+    engine.on_debug(|text, source, position| {
+        debug!("Script {} {}: {}", source.unwrap_or(""), position, text)
+    });
+    engine.register_fn(
+        "send_command",
+        move |command: &str, value: i64| -> Result<i64, Box<EvalAltResult>> {
+            let action = StoveCommands::from_str(command)
+                .map_err(|_| format!("unknown command '{}'", command))?;
+            let value = i32::try_from(value).map_err(|_| format!("invalid value {}", value))?;
+            let request_id = queue_write_request(
+                &commands.request_queue,
+                &commands.request_id_counter,
+                &shared_state,
+                CommandOrigin::Automation,
+                action as u32,
+                &value,
+            )
+            .map_err(|e| format!("command {}={} rejected: {}", command, value, e))?;
+            info!(
+                "Script queued command {}={} (id {})",
+                command, value, request_id
+            );
+            Ok(i64::from(request_id))
+        },
+    );
+    engine
+}
+
+/// Checks the scripts of the script directory
+///
+/// # Arguments
+///
+/// * `config` - The scripts configuration
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or the errors of the scripts that do not compile
+pub fn check_scripts(config: &ScriptsConfig) -> Result<(), String> {
+    let directory = Path::new(&config.directory);
+    if !directory.is_dir() {
+        return Err(format!("'{}' is not a directory", config.directory));
+    }
+    let engine = Engine::new();
+    let errors: Vec<String> = list_scripts(directory)
+        .into_iter()
+        .filter_map(|(path, _)| {
+            engine
+                .compile_file(path.clone())
+                .err()
+                .map(|e| format!("{}: {}", path.display(), e))
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Starts a thread running the hooks of the scripts on the events of the bus
+///
+/// Every `.rhai` file of the script directory may define `on_state_change(state, page)`,
+/// `on_command(state, command)` and `on_alarm(state, alarm)`, where `state` is the shared
+/// state as returned by `/api/state`. The scripts are reloaded when a file is added,
+/// removed or modified. Their commands are automation commands, so they are suppressed
+/// in read-only mode and during maintenance windows.
+///
+/// # Arguments
+///
+/// * `config` - The scripts configuration, or None if the section is absent
+/// * `shared_state` - Shared state holding the stove data
+/// * `commands` - Queues used to send commands to the stove
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+///
+/// # Returns
+///
+/// * `Option<thread::JoinHandle<()>>` - Handle to the spawned thread, or None if disabled
+pub fn start_scripts_thread(
+    config: Option<&ScriptsConfig>,
+    shared_state: Arc<RwLock<SharedState>>,
+    commands: CommandQueue,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+) -> Option<thread::JoinHandle<()>> {
+    let config = config?;
+    let directory = PathBuf::from(&config.directory);
+    let max_operations = config.max_operations;
+    info!("Running the scripts of {}", directory.display());
+
+    Some(supervise(
+        "scripts",
+        Arc::clone(&running),
+        metrics,
+        move || {
+            let engine = build_engine(max_operations, Arc::clone(&shared_state), commands.clone());
+            let events = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+            let receiver = events.subscribe();
+            let mut scripts = Scripts::load(&engine, &directory);
+            let mut checked_at = Instant::now();
+            while running.load(Ordering::SeqCst) {
+                if checked_at.elapsed() >= RELOAD_INTERVAL {
+                    checked_at = Instant::now();
+                    if scripts.is_stale(&directory) {
+                        info!("Scripts changed, reloading");
+                        scripts = Scripts::load(&engine, &directory);
+                    }
+                }
+                let (hook, argument) = match receiver.recv_timeout(Duration::from_millis(200)) {
+                    Ok(Event::StateUpdated { page }) => (ON_STATE_CHANGE, json!(page)),
+                    Ok(Event::CommandQueued {
+                        request_id,
+                        action,
+                        value,
+                        origin,
+                    }) => {
+                        let name: Option<&'static str> =
+                            StoveCommands::from_repr(action as usize).map(|command| command.into());
+                        (
+                            ON_COMMAND,
+                            json!({
+                                "request_id": request_id,
+                                "action": action,
+                                "command": name,
+                                "value": value,
+                                "origin": origin,
+                            }),
+                        )
+                    }
+                    Ok(Event::AlarmRaised { state }) => (ON_ALARM, json!(state)),
+                    Ok(_) | Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                let argument = rhai::serde::to_dynamic(&argument).unwrap_or(Dynamic::UNIT);
+                scripts.call(&engine, hook, &shared_state, argument);
+            }
+            info!("Scripts thread stopped.");
+        },
+    ))
+}
@@ -1,5 +1,19 @@
+use crate::hottoh::alerts::{ActiveAlert, Silence};
+use crate::hottoh::confirmation::ConfirmationTokens;
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::freeze_watchdog::FrozenValue;
+use crate::hottoh::history::History;
 use crate::hottoh::hottoh_structs::{DAT0Data, DAT1Data, DAT2Data, INFData};
+use crate::hottoh::maintenance::MaintenanceSchedule;
+use crate::hottoh::pending::{current_temperature_setpoint, PendingPage, PendingState};
+use crate::hottoh::quiet_hours::QuietHours;
+use crate::hottoh::run_timer::RunTimer;
+use crate::hottoh::startup::{StartupProgress, StartupTracker};
+use crate::hottoh::state_version::{serialize_version, StateVersion};
+use crate::hottoh::throttle::{CommandThrottle, ThrottleViolation};
 use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Shared state containing all data from the stove
 ///
@@ -7,6 +21,9 @@ use serde::Serialize;
 /// including general information, status, temperatures, and settings.
 #[derive(Debug, Serialize, Default, Clone)]
 pub struct SharedState {
+    /// Version of the state, incremented at each change
+    #[serde(serialize_with = "serialize_version")]
+    version: Arc<StateVersion>,
     /// General information about the stove
     inf: INFData,
     /// Main stove data (status, temperatures, power levels, etc.)
@@ -15,6 +32,49 @@ pub struct SharedState {
     dat1: DAT1Data,
     /// Additional stove data (pumps, valves, etc.)
     dat2: DAT2Data,
+    /// Setpoints written to the stove but not yet read back
+    pending: PendingState,
+    /// Whether commands to the stove are rejected
+    read_only: bool,
+    /// Windows during which commands are suppressed
+    #[serde(skip)]
+    maintenance: MaintenanceSchedule,
+    /// Bus notified of the updates
+    #[serde(skip)]
+    events: Arc<EventBus>,
+    /// Limits on the commands sent to the stove
+    #[serde(skip)]
+    throttle: CommandThrottle,
+    /// Tokens of the destructive commands waiting for their confirmation
+    #[serde(skip)]
+    confirmations: Arc<ConfirmationTokens>,
+    /// Delay after which a write not sent yet is dropped, zero to keep it
+    #[serde(skip)]
+    command_ttl: Duration,
+    /// Watched fields whose value stopped changing
+    #[serde(skip)]
+    frozen_values: Vec<FrozenValue>,
+    /// Alerts fired and not resolved yet
+    #[serde(skip)]
+    alerts: Vec<ActiveAlert>,
+    /// Silences of the alert notifications, including expired ones until the next change
+    #[serde(skip)]
+    silences: Vec<Silence>,
+    /// ID of the next silence
+    #[serde(skip)]
+    next_silence_id: u64,
+    /// Progress of the startup sequence
+    #[serde(skip)]
+    startup: StartupTracker,
+    /// Limits of the quiet hours, None if not configured
+    #[serde(skip)]
+    quiet_hours: Option<QuietHours>,
+    /// Automatic stop of the time-limited runs, None until loaded
+    #[serde(skip)]
+    run_timer: Option<RunTimer>,
+    /// History of the telemetry, disabled until configured
+    #[serde(skip)]
+    history: Arc<History>,
 }
 
 impl SharedState {
@@ -25,10 +85,26 @@ impl SharedState {
     /// * `SharedState` - A new instance with default values
     pub fn new() -> Self {
         Self {
+            version: Arc::new(StateVersion::default()),
             inf: INFData::default(),
             dat0: DAT0Data::default(),
             dat1: DAT1Data::default(),
             dat2: DAT2Data::default(),
+            pending: PendingState::default(),
+            read_only: false,
+            maintenance: MaintenanceSchedule::default(),
+            events: Arc::new(EventBus::new()),
+            throttle: CommandThrottle::default(),
+            confirmations: Arc::new(ConfirmationTokens::new()),
+            command_ttl: Duration::ZERO,
+            frozen_values: Vec::new(),
+            alerts: Vec::new(),
+            silences: Vec::new(),
+            next_silence_id: 1,
+            startup: StartupTracker::default(),
+            quiet_hours: None,
+            run_timer: None,
+            history: Arc::new(History::default()),
         }
     }
 
@@ -68,22 +144,420 @@ impl SharedState {
         &self.dat2
     }
 
+    /// Gets the version of the state
+    ///
+    /// # Returns
+    ///
+    /// * `&Arc<StateVersion>` - The version, incremented at each change
+    pub fn get_version(&self) -> &Arc<StateVersion> {
+        &self.version
+    }
+
+    /// Gets the setpoints written to the stove but not yet read back
+    ///
+    /// # Returns
+    ///
+    /// * `&PendingState` - Reference to the pending values
+    pub fn get_pending(&self) -> &PendingState {
+        &self.pending
+    }
+
+    /// Records the value expected after a write request
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the write request
+    /// * `action` - The StoveCommands value
+    /// * `value` - The value sent to the stove
+    pub fn add_pending(&mut self, request_id: u32, action: u32, value: &str) {
+        self.version.increment();
+        self.pending.add(request_id, action, value);
+    }
+
+    /// Drops the value expected after a write request that will not be sent
+    ///
+    /// # Arguments
+    ///
+    /// * `request_id` - ID of the write request
+    pub fn remove_pending(&mut self, request_id: u32) {
+        self.version.increment();
+        self.pending.remove(request_id);
+    }
+
+    /// Checks if commands to the stove are rejected
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the read-only mode is enabled
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Enables or disables the read-only mode
+    ///
+    /// # Arguments
+    ///
+    /// * `read_only` - True to reject commands to the stove
+    pub fn set_read_only(&mut self, read_only: bool) {
+        self.version.increment();
+        self.read_only = read_only;
+    }
+
+    /// Gets the tokens of the destructive commands waiting for their confirmation
+    ///
+    /// # Returns
+    ///
+    /// * `&Arc<ConfirmationTokens>` - The tokens
+    pub fn get_confirmations(&self) -> &Arc<ConfirmationTokens> {
+        &self.confirmations
+    }
+
+    /// Gets the maintenance windows
+    ///
+    /// # Returns
+    ///
+    /// * `&MaintenanceSchedule` - The maintenance windows
+    pub fn get_maintenance(&self) -> &MaintenanceSchedule {
+        &self.maintenance
+    }
+
+    /// Sets the maintenance windows
+    ///
+    /// # Arguments
+    ///
+    /// * `maintenance` - The maintenance windows
+    pub fn set_maintenance(&mut self, maintenance: MaintenanceSchedule) {
+        self.version.increment();
+        self.maintenance = maintenance;
+    }
+
+    /// Sets the limits on the commands sent to the stove
+    ///
+    /// # Arguments
+    ///
+    /// * `throttle` - The throttling profiles
+    pub fn set_throttle(&mut self, throttle: CommandThrottle) {
+        self.version.increment();
+        self.throttle = throttle;
+    }
+
+    /// Gets the delay after which a write not sent yet is dropped
+    ///
+    /// # Returns
+    ///
+    /// * `Duration` - The delay, zero if writes are kept until sent
+    pub fn get_command_ttl(&self) -> Duration {
+        self.command_ttl
+    }
+
+    /// Sets the delay after which a write not sent yet is dropped
+    ///
+    /// # Arguments
+    ///
+    /// * `command_ttl` - The delay, zero to keep writes until sent
+    pub fn set_command_ttl(&mut self, command_ttl: Duration) {
+        self.version.increment();
+        self.command_ttl = command_ttl;
+    }
+
+    /// Checks writes against the throttling profile of the stove, and records them if accepted
+    ///
+    /// The writes are checked as a whole: if one of them is rejected, none is recorded.
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - The StoveCommands values with the values of the commands
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ThrottleViolation>` - Ok if the writes can be sent
+    pub fn check_throttle(&mut self, commands: &[(u32, String)]) -> Result<(), ThrottleViolation> {
+        // The setpoints are unknown until the first DAT0 page is received
+        let dat0 = self
+            .dat0
+            .is_received()
+            .then(|| serde_json::to_value(&self.dat0).unwrap_or_default());
+        let manufacturer = self.dat0.get_manufacturer_name();
+        let saved = self.throttle.clone();
+        for (action, value) in commands {
+            let current = dat0
+                .as_ref()
+                .and_then(|dat0| current_temperature_setpoint(*action, dat0));
+            if let Err(e) = self.throttle.check(
+                &manufacturer,
+                self.dat0.get_index_manufacturer(),
+                *action,
+                value,
+                current,
+            ) {
+                self.throttle = saved;
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the event bus notified of the updates
+    ///
+    /// # Returns
+    ///
+    /// * `&Arc<EventBus>` - The event bus
+    pub fn get_events(&self) -> &Arc<EventBus> {
+        &self.events
+    }
+
+    /// Sets the event bus notified of the updates
+    ///
+    /// # Arguments
+    ///
+    /// * `events` - The event bus
+    pub fn set_events(&mut self, events: Arc<EventBus>) {
+        self.version.increment();
+        self.events = events;
+    }
+
+    /// Gets the watched fields whose value stopped changing
+    ///
+    /// # Returns
+    ///
+    /// * `&[FrozenValue]` - The frozen values
+    pub fn get_frozen_values(&self) -> &[FrozenValue] {
+        &self.frozen_values
+    }
+
+    /// Sets the watched fields whose value stopped changing
+    ///
+    /// # Arguments
+    ///
+    /// * `frozen_values` - The frozen values
+    pub fn set_frozen_values(&mut self, frozen_values: Vec<FrozenValue>) {
+        self.version.increment();
+        self.frozen_values = frozen_values;
+    }
+
+    /// Gets the alerts fired and not resolved yet
+    ///
+    /// # Returns
+    ///
+    /// * `&[ActiveAlert]` - The active alerts
+    pub fn get_alerts(&self) -> &[ActiveAlert] {
+        &self.alerts
+    }
+
+    /// Sets the alerts fired and not resolved yet
+    ///
+    /// # Arguments
+    ///
+    /// * `alerts` - The active alerts
+    pub fn set_alerts(&mut self, alerts: Vec<ActiveAlert>) {
+        self.version.increment();
+        self.alerts = alerts;
+    }
+
+    /// Acknowledges an active alert, which stops its repeated notifications
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the rule
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the rule has an active alert
+    pub fn acknowledge_alert(&mut self, name: &str) -> bool {
+        let Some(alert) = self
+            .alerts
+            .iter_mut()
+            .find(|alert| alert.get_name() == name)
+        else {
+            return false;
+        };
+        if !alert.is_acknowledged() {
+            alert.acknowledge();
+            self.version.increment();
+        }
+        true
+    }
+
+    /// Gets the silences not expired yet
+    ///
+    /// # Returns
+    ///
+    /// * `impl Iterator<Item = &Silence>` - The silences
+    pub fn get_silences(&self) -> impl Iterator<Item = &Silence> {
+        self.silences.iter().filter(|silence| !silence.is_expired())
+    }
+
+    /// Adds a silence, and drops the expired ones
+    ///
+    /// # Arguments
+    ///
+    /// * `rule` - Name of the rule silenced, or None to silence a label
+    /// * `label` - Label silenced, or None to silence a rule
+    /// * `comment` - Reason of the silence
+    /// * `secs` - Duration of the silence, in seconds
+    ///
+    /// # Returns
+    ///
+    /// * `Silence` - The silence
+    pub fn add_silence(
+        &mut self,
+        rule: Option<String>,
+        label: Option<String>,
+        comment: String,
+        secs: i64,
+    ) -> Silence {
+        let silence = Silence::new(self.next_silence_id, rule, label, comment, secs);
+        self.next_silence_id += 1;
+        self.silences.retain(|silence| !silence.is_expired());
+        self.silences.push(silence.clone());
+        self.version.increment();
+        silence
+    }
+
+    /// Removes a silence
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - ID of the silence
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the silence existed and had not expired
+    pub fn remove_silence(&mut self, id: u64) -> bool {
+        let Some(index) = self
+            .silences
+            .iter()
+            .position(|silence| silence.get_id() == id && !silence.is_expired())
+        else {
+            return false;
+        };
+        self.silences.remove(index);
+        self.version.increment();
+        true
+    }
+
+    /// Gets the quiet hours
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&mut QuietHours>` - The quiet hours, None if not configured
+    pub fn get_quiet_hours_mut(&mut self) -> Option<&mut QuietHours> {
+        self.quiet_hours.as_mut()
+    }
+
+    /// Sets the quiet hours
+    ///
+    /// # Arguments
+    ///
+    /// * `quiet_hours` - The quiet hours
+    pub fn set_quiet_hours(&mut self, quiet_hours: QuietHours) {
+        self.version.increment();
+        self.quiet_hours = Some(quiet_hours);
+    }
+
+    /// Gets the automatic stop of the time-limited runs
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&RunTimer>` - The run timer, None until loaded
+    pub fn get_run_timer(&self) -> Option<&RunTimer> {
+        self.run_timer.as_ref()
+    }
+
+    /// Gets the automatic stop of the time-limited runs for modification
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&mut RunTimer>` - The run timer, None until loaded
+    pub fn get_run_timer_mut(&mut self) -> Option<&mut RunTimer> {
+        self.run_timer.as_mut()
+    }
+
+    /// Sets the automatic stop of the time-limited runs
+    ///
+    /// # Arguments
+    ///
+    /// * `run_timer` - The run timer
+    pub fn set_run_timer(&mut self, run_timer: RunTimer) {
+        self.version.increment();
+        self.run_timer = Some(run_timer);
+    }
+
+    /// Gets the history of the telemetry
+    ///
+    /// # Returns
+    ///
+    /// * `&Arc<History>` - The history, disabled if not configured
+    pub fn get_history(&self) -> &Arc<History> {
+        &self.history
+    }
+
+    /// Sets the history of the telemetry
+    ///
+    /// # Arguments
+    ///
+    /// * `history` - The history
+    pub fn set_history(&mut self, history: Arc<History>) {
+        self.version.increment();
+        self.history = history;
+    }
+
+    /// Gets the progress of the startup sequence
+    ///
+    /// # Returns
+    ///
+    /// * `Option<StartupProgress>` - The progress, None if the stove is not starting
+    pub fn get_startup_progress(&self) -> Option<StartupProgress> {
+        self.startup.get_progress()
+    }
+
     /// Updates the general information data
     ///
     /// # Arguments
     ///
     /// * `inf` - The new INF data
-    pub fn set_inf(&mut self, inf: &INFData) {
-        self.inf = inf.clone();
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Event>` - The events to publish once the state is unlocked
+    pub fn set_inf(&mut self, inf: INFData) -> Vec<Event> {
+        self.version.increment();
+        self.inf = inf;
+        vec![Event::StateUpdated { page: "inf" }]
     }
 
     /// Updates the main stove data
     ///
+    /// Reports the state transition and the alarms in addition to the update.
+    ///
     /// # Arguments
     ///
     /// * `dat0` - The new DAT0 data
-    pub fn set_dat0(&mut self, dat0: &DAT0Data) {
-        self.dat0 = dat0.clone();
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Event>` - The events to publish once the state is unlocked
+    pub fn set_dat0(&mut self, dat0: DAT0Data) -> Vec<Event> {
+        self.version.increment();
+        let previous = self
+            .dat0
+            .is_received()
+            .then(|| self.dat0.get_index_stove_state().clone());
+        self.dat0 = dat0;
+        let mut events = self.confirm_pending(PendingPage::Dat0);
+        events.push(Event::StateUpdated { page: "dat0" });
+
+        let state = self.dat0.get_index_stove_state().clone();
+        self.startup.record(&state);
+        if let Some(from) = previous.filter(|from| *from != state) {
+            if state.is_alarm() && !from.is_alarm() {
+                events.push(Event::AlarmRaised {
+                    state: state.clone(),
+                });
+            }
+            events.push(Event::StateTransition { from, to: state });
+        }
+        events
     }
 
     /// Updates the additional temperature data
@@ -91,8 +565,16 @@ impl SharedState {
     /// # Arguments
     ///
     /// * `dat1` - The new DAT1 data
-    pub fn set_dat1(&mut self, dat1: &DAT1Data) {
-        self.dat1 = dat1.clone();
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Event>` - The events to publish once the state is unlocked
+    pub fn set_dat1(&mut self, dat1: DAT1Data) -> Vec<Event> {
+        self.version.increment();
+        self.dat1 = dat1;
+        let mut events = self.confirm_pending(PendingPage::Dat1);
+        events.push(Event::StateUpdated { page: "dat1" });
+        events
     }
 
     /// Updates the additional pump and valve data
@@ -100,7 +582,42 @@ impl SharedState {
     /// # Arguments
     ///
     /// * `dat2` - The new DAT2 data
-    pub fn set_dat2(&mut self, dat2: &DAT2Data) {
-        self.dat2 = dat2.clone();
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Event>` - The events to publish once the state is unlocked
+    pub fn set_dat2(&mut self, dat2: DAT2Data) -> Vec<Event> {
+        self.version.increment();
+        self.dat2 = dat2;
+        vec![Event::StateUpdated { page: "dat2" }]
+    }
+
+    /// Confirms the pending values reported by a received page
+    ///
+    /// # Arguments
+    ///
+    /// * `page` - The received page
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<Event>` - The confirmations of the commands
+    fn confirm_pending(&mut self, page: PendingPage) -> Vec<Event> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let data = match page {
+            PendingPage::Dat0 => serde_json::to_value(&self.dat0),
+            PendingPage::Dat1 => serde_json::to_value(&self.dat1),
+        }
+        .unwrap_or_default();
+        self.pending
+            .confirm(page, &data)
+            .into_iter()
+            .map(|(request_id, field, value)| Event::CommandConfirmed {
+                request_id,
+                field,
+                value,
+            })
+            .collect()
     }
 }
@@ -0,0 +1,181 @@
+use crate::hottoh::hottoh_const::StoveState;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Number of phases of the startup sequence (Starting1 to Starting7)
+pub const STARTUP_PHASES: u8 = 7;
+/// Durations kept per phase to compute the typical duration
+const HISTORY_SIZE: usize = 10;
+
+/// Phase of the startup sequence in progress
+#[derive(Debug, Clone)]
+struct Phase {
+    /// Stove state of the phase
+    state: StoveState,
+    /// Phase number, from 1 to 7
+    number: u8,
+    /// When the phase was first seen
+    since: Instant,
+    /// Whether the phase was seen from its beginning, so its duration can be learned
+    complete: bool,
+}
+
+/// Progress of the startup sequence, for UIs showing a progress bar
+#[derive(Debug, Clone, Serialize)]
+pub struct StartupProgress {
+    /// Phase number, from 1 to 7
+    phase: u8,
+    /// Number of phases
+    phases: u8,
+    /// Stove state of the phase (`Starting1` to `Starting7`)
+    name: StoveState,
+    /// Time spent in the phase, in seconds
+    elapsed_secs: u64,
+    /// Time spent since the startup began, in seconds
+    startup_elapsed_secs: u64,
+    /// Typical duration of the phase, in seconds, None until a startup was seen
+    typical_secs: Option<u64>,
+    /// Estimated time until the stove reaches the Power state, in seconds, None until
+    /// the typical duration of the remaining phases is known
+    remaining_secs: Option<u64>,
+    /// Overall progress, from 0 to 100
+    percent: u8,
+}
+
+/// Tracker of the startup sequence, learning the typical duration of each phase
+///
+/// The durations are learned from the phases the stove completed while the bridge
+/// was running, so the estimates get available after the first full startup.
+#[derive(Debug, Clone, Default)]
+pub struct StartupTracker {
+    /// Phase in progress, None outside of the startup sequence
+    current: Option<Phase>,
+    /// When the startup in progress began
+    started_at: Option<Instant>,
+    /// Whether a stove state was already recorded
+    seen: bool,
+    /// Last durations of each phase
+    history: [VecDeque<Duration>; STARTUP_PHASES as usize],
+}
+
+impl StartupTracker {
+    /// Records the current state of the stove
+    ///
+    /// A phase duration is learned when the stove moves to a later phase or to the
+    /// Power state, not when the startup is interrupted.
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - The state reported by the stove
+    pub fn record(&mut self, state: &StoveState) {
+        let number = state.get_startup_phase();
+        if self.seen && number == self.current.as_ref().map(|phase| phase.number) {
+            return;
+        }
+        let now = Instant::now();
+
+        if let Some(previous) = self.current.take() {
+            let next = match number {
+                Some(number) => number,
+                None if *state == StoveState::Power => STARTUP_PHASES + 1,
+                None => 0,
+            };
+            if next > previous.number && previous.complete {
+                self.learn(previous.number, now.duration_since(previous.since));
+                // Phases shorter than the polling interval are not seen, their time
+                // is counted in the previous phase
+                for skipped in previous.number + 1..next {
+                    self.learn(skipped, Duration::ZERO);
+                }
+            }
+        }
+
+        match number {
+            Some(number) => {
+                self.started_at.get_or_insert(now);
+                self.current = Some(Phase {
+                    state: state.clone(),
+                    number,
+                    since: now,
+                    complete: self.seen,
+                });
+            }
+            None => self.started_at = None,
+        }
+        self.seen = true;
+    }
+
+    /// Records the duration of a completed phase
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - Phase number, from 1 to 7
+    /// * `duration` - Time spent in the phase
+    fn learn(&mut self, number: u8, duration: Duration) {
+        let history = &mut self.history[usize::from(number - 1)];
+        if history.len() == HISTORY_SIZE {
+            history.pop_front();
+        }
+        history.push_back(duration);
+    }
+
+    /// Gets the typical duration of a phase
+    ///
+    /// # Arguments
+    ///
+    /// * `number` - Phase number, from 1 to 7
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Duration>` - The average of the learned durations, None if none was learned
+    fn get_typical_duration(&self, number: u8) -> Option<Duration> {
+        let history = &self.history[usize::from(number - 1)];
+        let count = u32::try_from(history.len())
+            .ok()
+            .filter(|count| *count > 0)?;
+        Some(history.iter().sum::<Duration>() / count)
+    }
+
+    /// Gets the progress of the startup in progress
+    ///
+    /// The percentage is based on the typical durations once every phase was learned,
+    /// and on the phase number before.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<StartupProgress>` - The progress, None outside of the startup sequence
+    pub fn get_progress(&self) -> Option<StartupProgress> {
+        let current = self.current.as_ref()?;
+        let elapsed = current.since.elapsed();
+        let typical = |number| self.get_typical_duration(number);
+        let typical_secs = typical(current.number).map(|typical| typical.as_secs());
+
+        let remaining = (current.number..=STARTUP_PHASES)
+            .map(typical)
+            .sum::<Option<Duration>>()
+            .map(|remaining| remaining.saturating_sub(elapsed));
+        let percent = match (1..=STARTUP_PHASES).map(typical).sum::<Option<Duration>>() {
+            Some(total) if !total.is_zero() => {
+                let done = (1..current.number).filter_map(typical).sum::<Duration>()
+                    + typical(current.number).map_or(elapsed, |typical| elapsed.min(typical));
+                done.as_secs_f64() / total.as_secs_f64() * 100.0
+            }
+            _ => f64::from(current.number - 1) / f64::from(STARTUP_PHASES) * 100.0,
+        };
+
+        Some(StartupProgress {
+            phase: current.number,
+            phases: STARTUP_PHASES,
+            name: current.state.clone(),
+            elapsed_secs: elapsed.as_secs(),
+            startup_elapsed_secs: self
+                .started_at
+                .map_or(elapsed, |started_at| started_at.elapsed())
+                .as_secs(),
+            typical_secs,
+            remaining_secs: remaining.map(|remaining| remaining.as_secs()),
+            percent: percent.round().min(100.0) as u8,
+        })
+    }
+}
@@ -0,0 +1,135 @@
+use crate::hottoh::shared_struct::SharedState;
+use serde_json::Value;
+
+/// Parses a mapping of state fields to remote identifiers
+///
+/// The mapping is a comma-separated list of `page.field=target`, e.g.
+/// `dat0.index_ambient_t1=123,dat0.index_power_level=124`, where `page` is one of
+/// `inf`, `dat0`, `dat1` or `dat2` and `field` a field of that page as shown by the API.
+///
+/// # Arguments
+///
+/// * `spec` - The mapping
+///
+/// # Returns
+///
+/// * `Result<Vec<(String, String)>, String>` - The (field, target) pairs or an error
+pub fn parse_field_mapping(spec: &str) -> Result<Vec<(String, String)>, String> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (field, target) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected page.field=target, got '{}'", item))?;
+            let (page, name) = field
+                .trim()
+                .split_once('.')
+                .ok_or_else(|| format!("expected page.field, got '{}'", field.trim()))?;
+            if !matches!(page, "inf" | "dat0" | "dat1" | "dat2") || name.is_empty() {
+                return Err(format!("unknown field '{}'", field.trim()));
+            }
+            Ok((field.trim().to_string(), target.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Pages whose fields can be read by name
+const PAGES: &[&str] = &["inf", "dat0", "dat1", "dat2"];
+
+/// Lists the fields of each page, from a default state
+///
+/// # Returns
+///
+/// * `Vec<String>` - The fields, as `page.field`
+pub fn known_fields() -> Vec<String> {
+    let state = serde_json::to_value(SharedState::new()).unwrap_or_default();
+    PAGES
+        .iter()
+        .filter_map(|page| Some((page, state.get(page)?.as_object()?)))
+        .flat_map(|(page, fields)| {
+            fields
+                .keys()
+                .map(move |field| format!("{}.{}", page, field))
+        })
+        .collect()
+}
+
+/// Resolves a field name, where the `index_` prefix of the field can be omitted
+///
+/// # Arguments
+///
+/// * `fields` - The known fields, as returned by `known_fields`
+/// * `name` - The field, as `page.field` (e.g. `dat0.smoke_t`)
+///
+/// # Returns
+///
+/// * `Result<String, String>` - The field as named in the state, or an error if it is unknown
+pub fn resolve_field(fields: &[String], name: &str) -> Result<String, String> {
+    let (page, field) = name.split_once('.').unwrap_or_default();
+    [name.to_string(), format!("{}.index_{}", page, field)]
+        .into_iter()
+        .find(|candidate| fields.contains(candidate))
+        .ok_or_else(|| format!("unknown field '{}'", name))
+}
+
+/// Gets a field from the serialized shared state
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+/// * `field` - The field, as `page.field`
+///
+/// # Returns
+///
+/// * `Option<&Value>` - The field, or None if it does not exist or its page was not
+///   received yet
+pub fn get_field<'a>(state: &'a Value, field: &str) -> Option<&'a Value> {
+    let (page, name) = field.split_once('.')?;
+    let page = state.get(page)?;
+    if page.get("last_updated").and_then(Value::as_str) == Some("") {
+        return None;
+    }
+    page.get(name)
+}
+
+/// Gets the value of a field from the serialized shared state as text
+///
+/// # Arguments
+///
+/// * `state` - The shared state serialized as JSON
+/// * `field` - The field, as `page.field`
+///
+/// # Returns
+///
+/// * `Option<String>` - The value as text (booleans as 1/0), or None if the field does
+///   not exist or its page was not received yet
+pub fn get_field_value(state: &Value, field: &str) -> Option<String> {
+    match get_field(state, field)? {
+        Value::Bool(value) => Some(if *value { "1" } else { "0" }.to_string()),
+        Value::String(value) => Some(value.clone()),
+        Value::Null => None,
+        value => Some(value.to_string()),
+    }
+}
+
+/// Percent-encodes a value for use in a URL
+///
+/// # Arguments
+///
+/// * `value` - The value to encode
+///
+/// # Returns
+///
+/// * `String` - The encoded value
+pub fn url_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
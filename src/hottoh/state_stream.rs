@@ -0,0 +1,468 @@
+use crate::hottoh::command_outcome::CommandOutcome;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::state_fields::{get_field, known_fields, resolve_field};
+use crate::hottoh::state_version::wait_for_version;
+use crate::hottoh::ws_commands::WsCommands;
+use actix_web::{rt, web};
+use actix_ws::{Message, MessageStream, Session};
+use futures_util::{stream, Stream};
+use log::{debug, warn};
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+/// Longest wait for a change before checking that the client is still connected,
+/// asked for a resync or dropped its subscription
+const WAIT_SECS: u64 = 1;
+
+/// Parses the parts of the state a client subscribes to
+///
+/// # Arguments
+///
+/// * `spec` - Comma-separated pages or fields, e.g. `dat0.index_ambient_t1,inf`, where
+///   the `index_` prefix of a field can be omitted
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, String>` - The pages and the fields as named in the state, or
+///   an error if one is unknown or none is given
+pub fn parse_stream_fields(spec: &str) -> Result<Vec<String>, String> {
+    let state = serde_json::to_value(SharedState::new()).unwrap_or_default();
+    let known = known_fields();
+    let fields = spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| match item.contains('.') {
+            true => resolve_field(&known, item),
+            false if state.get(item).is_some() => Ok(item.to_string()),
+            false => Err(format!("unknown page '{}'", item)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if fields.is_empty() {
+        return Err("at least one page or field is required".into());
+    }
+    Ok(fields)
+}
+
+/// Keeps the parts of the serialized state a client subscribes to
+///
+/// # Arguments
+///
+/// * `state` - The serialized state
+/// * `fields` - The pages and fields, as returned by `parse_stream_fields`
+///
+/// # Returns
+///
+/// * `Value` - The pages, and the pages holding only the fields, once received
+pub fn select_fields(state: &Value, fields: &[String]) -> Value {
+    let mut selected = Map::new();
+    for page in fields.iter().filter(|field| !field.contains('.')) {
+        if let Some(value) = state.get(page) {
+            selected.insert(page.clone(), value.clone());
+        }
+    }
+    for field in fields.iter().filter(|field| field.contains('.')) {
+        let (page, name) = field.split_once('.').unwrap_or_default();
+        let Some(value) = get_field(state, field) else {
+            continue;
+        };
+        if let Value::Object(page) = selected
+            .entry(page.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+        {
+            page.entry(name.to_string())
+                .or_insert_with(|| value.clone());
+        }
+    }
+    Value::Object(selected)
+}
+
+/// Reads the shared state and its version under the same lock
+///
+/// # Arguments
+///
+/// * `shared_state` - The shared state
+/// * `fields` - The pages and fields streamed to the client, None for the whole state
+///
+/// # Returns
+///
+/// * `(u64, Value)` - The version and the serialized state
+pub fn snapshot(shared_state: &RwLock<SharedState>, fields: Option<&[String]>) -> (u64, Value) {
+    let (version, state) = {
+        let state = read_lock(shared_state, "shared state");
+        (
+            state.get_version().get(),
+            serde_json::to_value(&*state).unwrap_or_default(),
+        )
+    };
+    match fields {
+        Some(fields) => (version, select_fields(&state, fields)),
+        None => (version, state),
+    }
+}
+
+/// Streams the state at each change
+///
+/// The state is given at once, then each time it changes, or each time one of `fields`
+/// changes when given.
+///
+/// # Arguments
+///
+/// * `shared_state` - The shared state
+/// * `fields` - The pages and fields whose changes are streamed, None for every change
+///
+/// # Returns
+///
+/// * `impl Stream` - The versions with the serialized states
+pub fn state_changes(
+    shared_state: Arc<RwLock<SharedState>>,
+    fields: Option<Vec<String>>,
+) -> impl Stream<Item = (u64, Value)> + Send {
+    stream::unfold(
+        (shared_state, fields, None::<(u64, Value)>),
+        |(shared_state, fields, mut last)| async move {
+            loop {
+                if let Some((version, _)) = &last {
+                    let version = *version;
+                    let data = Arc::clone(&shared_state);
+                    let current = web::block(move || {
+                        wait_for_version(&data, version, Duration::from_secs(WAIT_SECS))
+                    })
+                    .await
+                    .unwrap_or(version);
+                    if current == version {
+                        continue;
+                    }
+                }
+                let (version, state) = snapshot(&shared_state, None);
+                let watched = match &fields {
+                    Some(fields) => select_fields(&state, fields),
+                    None => Value::Null,
+                };
+                let changed = match &last {
+                    Some((_, previous)) => fields.is_none() || *previous != watched,
+                    None => true,
+                };
+                last = Some((version, watched));
+                if changed {
+                    return Some(((version, state), (shared_state, fields, last)));
+                }
+            }
+        },
+    )
+}
+
+/// Computes the changes between two serialized states, as an RFC 7386 merge patch
+///
+/// Objects (the pages `dat0`, `inf`... and the objects they hold) are compared field
+/// by field, so that an update only holds the fields that changed; other values, arrays
+/// included, are replaced as a whole. Removed values are given as null, so that
+/// applying the changes to the previous state with a JSON merge patch gives the new one.
+///
+/// # Arguments
+///
+/// * `old` - The state previously sent
+/// * `new` - The current state
+///
+/// # Returns
+///
+/// * `Map<String, Value>` - The changed values by name, objects holding their changed fields
+pub fn state_diff(old: &Value, new: &Value) -> Map<String, Value> {
+    let empty = Map::new();
+    let old = old.as_object().unwrap_or(&empty);
+    let new = new.as_object().unwrap_or(&empty);
+    let mut changes = Map::new();
+    for (name, value) in new {
+        match (old.get(name), value) {
+            (Some(old), new) if old == new => {}
+            (Some(old @ Value::Object(_)), Value::Object(_)) => {
+                changes.insert(name.clone(), Value::Object(state_diff(old, value)));
+            }
+            _ => {
+                changes.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    for name in old.keys().filter(|name| !new.contains_key(*name)) {
+        changes.insert(name.clone(), Value::Null);
+    }
+    changes
+}
+
+/// Handles a text message of a WebSocket client
+///
+/// Command messages (`{"type": "command", ...}`, or signed as `{"type": "signed",
+/// ...}`) are acknowledged on the socket, and
+/// followed by an `{"type": "outcome", "id", "request_id", "outcome"}` message when the
+/// client asked to wait for the outcome. A `{"type": "resync"}` message asks for the
+/// whole state again, sent by the stream.
+///
+/// # Arguments
+///
+/// * `session` - Session of the WebSocket
+/// * `commands` - Handler of the commands
+/// * `resync` - Set when the client asks for the whole state
+/// * `text` - The message
+///
+/// # Returns
+///
+/// * `bool` - False if the socket must be closed
+async fn handle_message(
+    session: &mut Session,
+    commands: &Arc<WsCommands>,
+    resync: &AtomicBool,
+    text: &str,
+) -> bool {
+    let kind = serde_json::from_str::<Value>(text)
+        .ok()
+        .and_then(|message| {
+            message
+                .get("type")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+    let (ack, wait) = match kind.as_deref() {
+        Some("resync") => {
+            debug!("WebSocket client asked for a resync");
+            resync.store(true, Ordering::SeqCst);
+            return true;
+        }
+        Some("command" | "signed") => commands.handle(text),
+        _ => (
+            json!({
+                "type": "error",
+                "status": 400,
+                "error": "expected a JSON message with a type (command, signed or resync)",
+            }),
+            None,
+        ),
+    };
+    if session.text(ack.to_string()).await.is_err() {
+        return false;
+    }
+    if let Some((handle, timeout)) = wait {
+        let mut session = session.clone();
+        let id = ack["id"].clone();
+        let request_id = handle.get_request_id();
+        rt::spawn(async move {
+            let outcome = web::block(move || handle.wait(timeout))
+                .await
+                .unwrap_or(CommandOutcome::TimedOut);
+            let mut message = json!({
+                "type": "outcome",
+                "id": id,
+                "request_id": request_id,
+                "outcome": outcome.as_str(),
+            });
+            match outcome {
+                CommandOutcome::Confirmed(value) => message["value"] = value,
+                CommandOutcome::Rejected(reason) => message["reason"] = json!(reason),
+                CommandOutcome::Accepted | CommandOutcome::TimedOut => {}
+            }
+            let _ = session.text(message.to_string()).await;
+        });
+    }
+    if commands.is_banned() {
+        warn!("Closing the WebSocket of a client banned for wrong PINs");
+        return false;
+    }
+    true
+}
+
+/// Streams the shared state to a WebSocket client
+///
+/// The whole state is sent first as `{"type": "full", "seq", "version", "state"}`, then
+/// each change as `{"type": "update", "seq", "version", "changes"}` with only the values
+/// that changed, as a merge patch of the previous state. `seq` counts the messages of
+/// the stream of this client, from 1, so that a client missing one (a gap in `seq`)
+/// sends `{"type": "resync"}` and gets the whole state again with the next `seq`.
+/// With `fields`, the state only holds these pages and fields, and no update is sent
+/// while none of them changes. Command messages of the client are handled by
+/// `commands` and acknowledged on the same socket. Pings are answered, and the stream
+/// ends when the client closes the connection.
+///
+/// # Arguments
+///
+/// * `session` - Session of the WebSocket
+/// * `messages` - Messages received from the client
+/// * `shared_state` - The shared state
+/// * `fields` - The pages and fields the client subscribes to, None for the whole state
+/// * `commands` - Handler of the commands sent by the client
+pub async fn stream_state(
+    mut session: Session,
+    mut messages: MessageStream,
+    shared_state: Arc<RwLock<SharedState>>,
+    fields: Option<Vec<String>>,
+    commands: WsCommands,
+) {
+    let closed = Arc::new(AtomicBool::new(false));
+    let resync = Arc::new(AtomicBool::new(true));
+    {
+        let mut session = session.clone();
+        let closed = Arc::clone(&closed);
+        let resync = Arc::clone(&resync);
+        let commands = Arc::new(commands);
+        rt::spawn(async move {
+            while let Some(Ok(message)) = messages.recv().await {
+                match message {
+                    Message::Ping(bytes) if session.pong(&bytes).await.is_err() => break,
+                    Message::Text(text)
+                        if !handle_message(&mut session, &commands, &resync, &text).await =>
+                    {
+                        break
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
+            closed.store(true, Ordering::SeqCst);
+        });
+    }
+
+    let mut seq: u64 = 0;
+    let (mut version, mut state) = (0, Value::Null);
+    while !closed.load(Ordering::SeqCst) {
+        if resync.swap(false, Ordering::SeqCst) {
+            (version, state) = snapshot(&shared_state, fields.as_deref());
+            seq += 1;
+            let full = json!({ "type": "full", "seq": seq, "version": version, "state": state });
+            if session.text(full.to_string()).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        let data = Arc::clone(&shared_state);
+        let current =
+            web::block(move || wait_for_version(&data, version, Duration::from_secs(WAIT_SECS)))
+                .await
+                .unwrap_or(version);
+        if current == version {
+            continue;
+        }
+        let (new_version, new_state) = snapshot(&shared_state, fields.as_deref());
+        let changes = state_diff(&state, &new_state);
+        (version, state) = (new_version, new_state);
+        if changes.is_empty() {
+            continue;
+        }
+        seq += 1;
+        let update = json!({
+            "type": "update",
+            "seq": seq,
+            "version": version,
+            "changes": changes
+        });
+        if session.text(update.to_string()).await.is_err() {
+            break;
+        }
+    }
+    debug!("WebSocket client disconnected");
+    let _ = session.close(None).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies an RFC 7386 merge patch, as a client of the stream does
+    fn merge_patch(target: &mut Value, patch: &Value) {
+        let Value::Object(patch) = patch else {
+            *target = patch.clone();
+            return;
+        };
+        if !target.is_object() {
+            *target = Value::Object(Map::new());
+        }
+        let target = target.as_object_mut().unwrap();
+        for (name, value) in patch {
+            if value.is_null() {
+                target.remove(name);
+            } else {
+                merge_patch(target.entry(name.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_stream_fields() {
+        assert_eq!(
+            parse_stream_fields("dat0.ambient_t1, inf").unwrap(),
+            vec!["dat0.index_ambient_t1".to_string(), "inf".to_string()]
+        );
+        assert!(parse_stream_fields("dat0.nothing").is_err());
+        assert!(parse_stream_fields("dat9").is_err());
+        assert!(parse_stream_fields(" , ").is_err());
+    }
+
+    #[test]
+    fn test_select_fields() {
+        let state = json!({
+            "dat0": {"last_updated": "2026-01-01T00:00:00Z", "index_ambient_t1": 20.5, "index_fan_speed": 3},
+            "dat1": {"last_updated": "", "index_x": 1},
+            "inf": {"last_updated": "2026-01-01T00:00:00Z", "index_model": 2}
+        });
+        let fields = [
+            "dat0.index_ambient_t1".to_string(),
+            "dat1.index_x".to_string(),
+        ];
+        assert_eq!(
+            select_fields(&state, &fields),
+            json!({"dat0": {"index_ambient_t1": 20.5}})
+        );
+        let fields = ["inf".to_string(), "inf.index_model".to_string()];
+        assert_eq!(select_fields(&state, &fields), json!({"inf": state["inf"]}));
+
+        // A change of another field gives no update
+        let mut changed = state.clone();
+        changed["dat0"]["index_fan_speed"] = json!(4);
+        let fields = ["dat0.index_ambient_t1".to_string()];
+        assert!(state_diff(
+            &select_fields(&state, &fields),
+            &select_fields(&changed, &fields)
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_state_diff_fields() {
+        let old = json!({"dat0": {"index_smoke_t": 140.0, "index_stove_state": 3}, "version": 1});
+        let new = json!({"dat0": {"index_smoke_t": 142.5, "index_stove_state": 3}, "version": 2});
+        let changes = state_diff(&old, &new);
+        assert_eq!(
+            Value::Object(changes),
+            json!({"dat0": {"index_smoke_t": 142.5}, "version": 2})
+        );
+        assert!(state_diff(&new, &new).is_empty());
+    }
+
+    #[test]
+    fn test_state_diff_merge_patch() {
+        let old = json!({
+            "dat0": {"a": 1, "b": {"x": 1, "y": 2}, "gone": true},
+            "list": [1, 2],
+            "page": null,
+            "removed": "x"
+        });
+        let new = json!({
+            "dat0": {"a": 1, "b": {"x": 1, "z": 3}},
+            "list": [1, 2, 3],
+            "page": {"c": 4}
+        });
+        let changes = Value::Object(state_diff(&old, &new));
+        assert_eq!(
+            changes,
+            json!({
+                "dat0": {"b": {"y": null, "z": 3}, "gone": null},
+                "list": [1, 2, 3],
+                "page": {"c": 4},
+                "removed": null
+            })
+        );
+        let mut patched = old.clone();
+        merge_patch(&mut patched, &changes);
+        assert_eq!(patched, new);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::hottoh::locks::{mutex_lock, read_lock};
+use crate::hottoh::shared_struct::SharedState;
+use serde::Serializer;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::Duration;
+
+/// Version of the shared state, incremented at each change
+///
+/// Pollers compare it with the version they last saw to know whether anything
+/// changed, and stream clients resynchronize from it. It is kept outside of the lock
+/// of the shared state so that waiting for a change does not block the writers.
+#[derive(Debug, Default)]
+pub struct StateVersion {
+    /// Current version, 0 until the first change
+    version: Mutex<u64>,
+    /// Notified at each change
+    changed: Condvar,
+}
+
+impl StateVersion {
+    /// Gets the current version
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The version
+    pub fn get(&self) -> u64 {
+        *mutex_lock(&self.version, "state version")
+    }
+
+    /// Increments the version and wakes up the waiting threads
+    pub fn increment(&self) {
+        *mutex_lock(&self.version, "state version") += 1;
+        self.changed.notify_all();
+    }
+
+    /// Waits until the version is greater than a given one
+    ///
+    /// # Arguments
+    ///
+    /// * `version` - The version last seen
+    /// * `timeout` - Longest time to wait
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The current version, still `version` if nothing changed in time
+    pub fn wait_for(&self, version: u64, timeout: Duration) -> u64 {
+        let current = mutex_lock(&self.version, "state version");
+        let (current, _) = self
+            .changed
+            .wait_timeout_while(current, timeout, |current| *current <= version)
+            .unwrap_or_else(|e| e.into_inner());
+        *current
+    }
+}
+
+/// Serializes the version of the shared state as a number
+///
+/// # Arguments
+///
+/// * `version` - The version
+/// * `serializer` - The serializer
+///
+/// # Returns
+///
+/// * `Result<S::Ok, S::Error>` - The serialized version
+pub fn serialize_version<S: Serializer>(
+    version: &Arc<StateVersion>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(version.get())
+}
+
+/// Waits until the shared state changed since a given version
+///
+/// The lock of the shared state is only held to get its version, not while waiting.
+///
+/// # Arguments
+///
+/// * `shared_state` - The shared state
+/// * `version` - The version last seen
+/// * `timeout` - Longest time to wait
+///
+/// # Returns
+///
+/// * `u64` - The current version, still `version` if nothing changed in time
+pub fn wait_for_version(
+    shared_state: &RwLock<SharedState>,
+    version: u64,
+    timeout: Duration,
+) -> u64 {
+    let state_version = Arc::clone(read_lock(shared_state, "shared state").get_version());
+    state_version.wait_for(version, timeout)
+}
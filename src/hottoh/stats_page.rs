@@ -0,0 +1,252 @@
+use crate::hottoh::build_info::VERSION;
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::resource_usage::ResourceUsage;
+use std::fmt::Write;
+
+/// Number of requests and responses waiting in the queues of the TCP client
+#[derive(Debug, Default)]
+pub struct QueueDepths {
+    /// Requests not sent yet
+    pub pending_requests: usize,
+    /// Requests sent and waiting for their answer
+    pub sent_requests: usize,
+    /// Responses not matched with their request yet
+    pub responses: usize,
+}
+
+/// Renders the `/stats` page
+///
+/// The page is self-contained, without scripts nor external resources, and reloads
+/// itself every 10 seconds, so that it can be opened from a phone for a quick health
+/// check without Prometheus or Grafana.
+///
+/// # Arguments
+///
+/// * `metrics` - Runtime metrics of the bridge
+/// * `queues` - Depths of the queues of the TCP client
+/// * `resources` - Resources used by the process
+///
+/// # Returns
+///
+/// * `String` - The HTML page
+pub fn render_stats_page(
+    metrics: &Metrics,
+    queues: &QueueDepths,
+    resources: &ResourceUsage,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <meta http-equiv=\"refresh\" content=\"10\"><title>Hottoh stats</title>\
+         <style>body{{font-family:sans-serif;margin:1em;max-width:40em}}\
+         table{{border-collapse:collapse;width:100%;margin-bottom:1em}}\
+         td,th{{padding:.3em;border-bottom:1px solid #ddd;text-align:left}}\
+         .bar{{background:#4a90d9;height:.8em}}.down{{color:#c00}}</style></head><body>"
+    );
+    let _ = writeln!(out, "<h1>Hottoh {}</h1>", escape_html(VERSION));
+
+    let _ = writeln!(out, "<h2>Connection</h2><table>");
+    row(
+        &mut out,
+        "Uptime",
+        &format_duration(metrics.get_uptime_secs()),
+    );
+    match metrics.get_stove_address() {
+        Some((address, fallback)) => {
+            let fallback = if fallback { " (fallback)" } else { "" };
+            row(&mut out, "Stove", &format!("{}{}", address, fallback));
+        }
+        None => {
+            let _ = writeln!(
+                out,
+                "<tr><th>Stove</th><td class=\"down\">disconnected</td></tr>"
+            );
+        }
+    }
+    if let Some(secs) = metrics.get_connected_secs() {
+        row(&mut out, "Connected for", &format_duration(secs));
+    }
+    if let Some(diagnosis) = metrics.get_network_diagnosis() {
+        row(&mut out, "Last failure", diagnosis.reason);
+    }
+    row(
+        &mut out,
+        "Connections",
+        &metrics.get_connections().to_string(),
+    );
+    row(
+        &mut out,
+        "Stale reconnects",
+        &metrics.get_stale_reconnects().to_string(),
+    );
+    row(
+        &mut out,
+        "Duplicates dropped",
+        &metrics.get_duplicate_responses_dropped().to_string(),
+    );
+    for (thread, count) in metrics.get_thread_crashes() {
+        row(
+            &mut out,
+            &format!("Crashes of {}", thread),
+            &count.to_string(),
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    let _ = writeln!(out, "<h2>Queues</h2><table>");
+    row(
+        &mut out,
+        "Requests to send",
+        &queues.pending_requests.to_string(),
+    );
+    row(
+        &mut out,
+        "Requests waiting for an answer",
+        &queues.sent_requests.to_string(),
+    );
+    row(
+        &mut out,
+        "Responses to match",
+        &queues.responses.to_string(),
+    );
+    let _ = writeln!(out, "</table>");
+
+    let _ = writeln!(out, "<h2>Resources</h2><table>");
+    let unknown = || "unknown".to_string();
+    row(
+        &mut out,
+        "Memory",
+        &resources.rss_bytes.map_or_else(unknown, format_bytes),
+    );
+    row(
+        &mut out,
+        "Open files",
+        &resources
+            .open_fds
+            .map_or_else(unknown, |fds| fds.to_string()),
+    );
+    row(
+        &mut out,
+        "Threads",
+        &resources
+            .threads
+            .map_or_else(unknown, |threads| threads.to_string()),
+    );
+    row(
+        &mut out,
+        "Queue memory",
+        &format_bytes(resources.queue_bytes),
+    );
+    let _ = writeln!(out, "</table>");
+
+    let latency = metrics.get_response_latency();
+    let count = latency.get_count();
+    let _ = writeln!(out, "<h2>Response latency</h2>");
+    if count == 0 {
+        let _ = writeln!(out, "<p>No response yet</p>");
+    } else {
+        let _ = writeln!(
+            out,
+            "<p>{} responses, {:.0} ms on average</p><table>",
+            count,
+            latency.get_sum() / count as f64 * 1000.0
+        );
+        let buckets = latency.get_buckets();
+        let max = buckets.iter().map(|(_, n)| *n).max().unwrap_or(0).max(1);
+        let mut lower = 0.0;
+        for (bound, n) in buckets {
+            let label = match bound {
+                Some(bound) => format!("{:.0}-{:.0} ms", lower * 1000.0, bound * 1000.0),
+                None => format!("&gt; {:.0} ms", lower * 1000.0),
+            };
+            let _ = writeln!(
+                out,
+                "<tr><th>{}</th><td>{}</td><td style=\"width:60%\">\
+                 <div class=\"bar\" style=\"width:{}%\"></div></td></tr>",
+                label,
+                n,
+                n * 100 / max
+            );
+            lower = bound.unwrap_or(lower);
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    let _ = writeln!(out, "</body></html>");
+    out
+}
+
+/// Appends a row of the page
+///
+/// # Arguments
+///
+/// * `out` - The page being rendered
+/// * `name` - Name of the value
+/// * `value` - The value, escaped before being written
+fn row(out: &mut String, name: &str, value: &str) {
+    let _ = writeln!(
+        out,
+        "<tr><th>{}</th><td>{}</td></tr>",
+        escape_html(name),
+        escape_html(value)
+    );
+}
+
+/// Formats a duration for humans
+///
+/// # Arguments
+///
+/// * `secs` - The duration in seconds
+///
+/// # Returns
+///
+/// * `String` - The duration, e.g. `2d 3h 4m` or `42s`
+fn format_duration(secs: u64) -> String {
+    let (days, hours, minutes) = (secs / 86_400, secs % 86_400 / 3600, secs % 3600 / 60);
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m {}s", minutes, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Formats a size for humans
+///
+/// # Arguments
+///
+/// * `bytes` - The size in bytes
+///
+/// # Returns
+///
+/// * `String` - The size, e.g. `12.3 MB` or `512 B`
+fn format_bytes(bytes: u64) -> String {
+    if bytes >= 1024 * 1024 {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    } else if bytes >= 1024 {
+        format!("{:.1} kB", bytes as f64 / 1024.0)
+    } else {
+        format!("{} B", bytes)
+    }
+}
+
+/// Escapes a text for HTML
+///
+/// # Arguments
+///
+/// * `text` - The text
+///
+/// # Returns
+///
+/// * `String` - The escaped text
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,157 @@
+use crate::hottoh::command_outcome::{send_command, CommandHandle};
+use crate::hottoh::error::HottohError;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::normalized::check_setpoint;
+use crate::hottoh::openhab::CommandQueue;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::tcp_client::CommandOrigin;
+use crate::hottoh::temperature::to_tenths;
+use std::sync::{Arc, RwLock};
+
+/// Ambiance zone of the stove
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zone {
+    /// First zone, measured by the stove
+    One,
+    /// Second zone, on stoves with a second probe
+    Two,
+}
+
+/// Typed commands to the stove, checked against what it reports
+///
+/// Each setter checks that the stove has the feature and that the value is within
+/// the range it reports (see `GET /api/state/normalized`) before encoding the frame,
+/// instead of sending raw action/value pairs.
+pub struct StoveClient {
+    /// Queues used to send commands to the stove
+    commands: CommandQueue,
+    /// Shared state holding the ranges reported by the stove
+    shared_state: Arc<RwLock<SharedState>>,
+    /// Source of the commands
+    origin: CommandOrigin,
+}
+
+impl StoveClient {
+    /// Creates a client sending its commands with a given origin
+    ///
+    /// # Arguments
+    ///
+    /// * `commands` - Queues used to send commands to the stove
+    /// * `shared_state` - Shared state holding the stove data
+    /// * `origin` - Source of the commands
+    ///
+    /// # Returns
+    ///
+    /// * `StoveClient` - The client
+    pub fn new(
+        commands: CommandQueue,
+        shared_state: Arc<RwLock<SharedState>>,
+        origin: CommandOrigin,
+    ) -> Self {
+        Self {
+            commands,
+            shared_state,
+            origin,
+        }
+    }
+
+    /// Sets the temperature of an ambiance zone
+    ///
+    /// # Arguments
+    ///
+    /// * `zone` - The zone
+    /// * `celsius` - The temperature in degrees
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandHandle, HottohError>` - The handle on the queued command, or a
+    ///   `NotSupported`, `OutOfRange` or `Busy` error
+    #[allow(dead_code)]
+    pub fn set_room_temperature(
+        &self,
+        zone: Zone,
+        celsius: f64,
+    ) -> Result<CommandHandle, HottohError> {
+        let (name, command) = match zone {
+            Zone::One => ("ambiance_temp_1", StoveCommands::AmbianceTemperature1),
+            Zone::Two => ("ambiance_temp_2", StoveCommands::AmbianceTemperature2),
+        };
+        self.send(name, command, celsius, i64::from(to_tenths(celsius)))
+    }
+
+    /// Sets the power level
+    ///
+    /// # Arguments
+    ///
+    /// * `level` - The power level
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandHandle, HottohError>` - The handle on the queued command, or an
+    ///   `OutOfRange` or `Busy` error
+    #[allow(dead_code)]
+    pub fn set_power_level(&self, level: u8) -> Result<CommandHandle, HottohError> {
+        self.send(
+            "power_level",
+            StoveCommands::PowerLevel,
+            f64::from(level),
+            i64::from(level),
+        )
+    }
+
+    /// Sets the speed of a fan
+    ///
+    /// # Arguments
+    ///
+    /// * `fan` - The fan number, from 1 to 3
+    /// * `speed` - The speed, 0 for automatic
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandHandle, HottohError>` - The handle on the queued command, or a
+    ///   `NotSupported`, `OutOfRange` or `Busy` error
+    pub fn set_fan_speed(&self, fan: u8, speed: u8) -> Result<CommandHandle, HottohError> {
+        let (name, command) = match fan {
+            1 => ("fan_speed_1", StoveCommands::FanSpeed1),
+            2 => ("fan_speed_2", StoveCommands::FanSpeed2),
+            3 => ("fan_speed_3", StoveCommands::FanSpeed3),
+            _ => return Err(HottohError::NotSupported(format!("fan {}", fan))),
+        };
+        self.send(name, command, f64::from(speed), i64::from(speed))
+    }
+
+    /// Checks a setpoint and queues its command
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the value in the normalized state
+    /// * `command` - The command setting it
+    /// * `value` - The setpoint, in the unit of the normalized state
+    /// * `raw` - The value sent to the stove
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandHandle, HottohError>` - The handle on the queued command
+    fn send(
+        &self,
+        name: &str,
+        command: StoveCommands,
+        value: f64,
+        raw: i64,
+    ) -> Result<CommandHandle, HottohError> {
+        let state = serde_json::to_value(&*read_lock(&self.shared_state, "shared state"))
+            .unwrap_or_default();
+        check_setpoint(&state, name, value)?;
+        Ok(send_command(
+            &self.commands.request_queue,
+            &self.commands.request_id_counter,
+            &self.shared_state,
+            self.origin,
+            command as u32,
+            &raw,
+            None,
+        )?)
+    }
+}
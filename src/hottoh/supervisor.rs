@@ -0,0 +1,97 @@
+use crate::hottoh::metrics::Metrics;
+use log::{error, info};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Delay before the first restart of a crashed worker
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+/// Maximum delay between two restarts of a crashed worker
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// A worker running longer than this without crashing gets its backoff reset
+const STABLE_RUN: Duration = Duration::from_secs(300);
+
+/// Spawns a supervised worker thread
+///
+/// The worker body is run inside `catch_unwind`. If it panics, the crash is counted
+/// in the metrics and the body is restarted after an exponential backoff, as long as
+/// the application is running. The thread ends when the body returns normally.
+///
+/// # Arguments
+///
+/// * `name` - Name of the worker, used in logs and metrics
+/// * `running` - Flag indicating whether the application is running
+/// * `metrics` - Runtime metrics where crashes are recorded
+/// * `body` - The worker body
+///
+/// # Returns
+///
+/// * `thread::JoinHandle<()>` - Handle to the spawned thread
+pub fn supervise<F>(
+    name: &'static str,
+    running: Arc<AtomicBool>,
+    metrics: Arc<Metrics>,
+    body: F,
+) -> thread::JoinHandle<()>
+where
+    F: Fn() + Send + 'static,
+{
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let mut backoff = INITIAL_BACKOFF;
+            loop {
+                let started_at = Instant::now();
+                match panic::catch_unwind(AssertUnwindSafe(&body)) {
+                    Ok(()) => break,
+                    Err(err) => {
+                        let crashes = metrics.inc_thread_crashes(name);
+                        error!(
+                            "Thread {} panicked ({} crash(es) so far): {}",
+                            name,
+                            crashes,
+                            panic_message(&err)
+                        );
+                    }
+                }
+
+                if !running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if started_at.elapsed() >= STABLE_RUN {
+                    backoff = INITIAL_BACKOFF;
+                }
+                info!("Restarting thread {} in {:?}...", name, backoff);
+                let restart_at = Instant::now() + backoff;
+                while Instant::now() < restart_at {
+                    if !running.load(Ordering::SeqCst) {
+                        return;
+                    }
+                    thread::sleep(Duration::from_millis(200));
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        })
+        .expect("Failed to spawn supervised thread")
+}
+
+/// Extracts a readable message from a panic payload
+///
+/// # Arguments
+///
+/// * `err` - The panic payload
+///
+/// # Returns
+///
+/// * `String` - The panic message, or a placeholder if it is not a string
+fn panic_message(err: &Box<dyn std::any::Any + Send>) -> String {
+    if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
@@ -0,0 +1,241 @@
+use crate::hottoh::locks::mutex_lock;
+use chrono::{SecondsFormat, Utc};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::DeferredNow;
+use log::{Level, Record};
+use std::fs;
+use std::io::Write;
+use std::net::{TcpStream, UdpSocket};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Name of the application in the syslog messages
+const APP_NAME: &str = "hottoh_api";
+/// Facility of the syslog messages (daemon)
+const FACILITY: u8 = 3;
+/// Timeout of the connection and writes to a TCP collector
+const TCP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Delay before reconnecting to an unreachable TCP collector, messages are dropped meanwhile
+const RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Transport to the syslog collector
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyslogProtocol {
+    /// One datagram per message
+    Udp,
+    /// Octet-counted messages (RFC 6587) on a persistent connection
+    Tcp,
+}
+
+/// Parses the URL of a syslog collector
+///
+/// # Arguments
+///
+/// * `url` - The URL, `udp://host:port` or `tcp://host:port`
+///
+/// # Returns
+///
+/// * `Result<(SyslogProtocol, String), String>` - The protocol and the `host:port` address, or an error
+pub fn parse_syslog_url(url: &str) -> Result<(SyslogProtocol, String), String> {
+    let (scheme, address) = url
+        .split_once("://")
+        .ok_or_else(|| format!("expected udp://host:port or tcp://host:port, got '{}'", url))?;
+    let protocol = match scheme {
+        "udp" => SyslogProtocol::Udp,
+        "tcp" => SyslogProtocol::Tcp,
+        "tls" => return Err("tls:// is not supported, use a local relay".into()),
+        other => return Err(format!("unknown protocol '{}' (udp or tcp)", other)),
+    };
+    let address = address.trim_end_matches('/');
+    if address
+        .rsplit_once(':')
+        .is_none_or(|(_, port)| port.parse::<u16>().is_err())
+    {
+        return Err(format!("missing or invalid port in '{}'", url));
+    }
+    Ok((protocol, address.to_string()))
+}
+
+/// Log writer sending RFC 5424 messages to a remote syslog collector
+pub struct SyslogWriter {
+    address: String,
+    hostname: String,
+    udp: Option<UdpSocket>,
+    tcp: Mutex<TcpConnection>,
+}
+
+/// Connection to a TCP collector
+#[derive(Default)]
+struct TcpConnection {
+    /// Opened on the first message and reopened after an error
+    stream: Option<TcpStream>,
+    /// No connection is attempted before this time after a failure
+    retry_at: Option<Instant>,
+}
+
+impl SyslogWriter {
+    /// Creates a writer for a collector
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - URL of the collector, `udp://host:port` or `tcp://host:port`
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SyslogWriter, String>` - The writer, or an error if the URL is invalid
+    pub fn new(url: &str) -> Result<Self, String> {
+        let (protocol, address) = parse_syslog_url(url)?;
+        let udp = match protocol {
+            SyslogProtocol::Udp => {
+                let socket = UdpSocket::bind("0.0.0.0:0").map_err(|e| e.to_string())?;
+                socket
+                    .connect(&address)
+                    .map_err(|e| format!("cannot resolve {}: {}", address, e))?;
+                Some(socket)
+            }
+            SyslogProtocol::Tcp => None,
+        };
+        Ok(Self {
+            address,
+            hostname: local_hostname(),
+            udp,
+            tcp: Mutex::new(TcpConnection::default()),
+        })
+    }
+
+    /// Sends a message over TCP, reconnecting once if the connection was lost
+    ///
+    /// While the collector is unreachable, messages are dropped and a connection is
+    /// only attempted every 30 seconds, so that logging does not block the workers.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The RFC 5424 message
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success or IO error
+    fn send_tcp(&self, message: &str) -> std::io::Result<()> {
+        let frame = format!("{} {}", message.len(), message);
+        let mut tcp = mutex_lock(&self.tcp, "syslog connection");
+        for _ in 0..2 {
+            if tcp.stream.is_none() {
+                if tcp
+                    .retry_at
+                    .is_some_and(|retry_at| Instant::now() < retry_at)
+                {
+                    return Ok(());
+                }
+                match connect_tcp(&self.address) {
+                    Ok(stream) => tcp.stream = Some(stream),
+                    Err(e) => {
+                        tcp.retry_at = Some(Instant::now() + RECONNECT_DELAY);
+                        return Err(e);
+                    }
+                }
+            }
+            if let Some(stream) = tcp.stream.as_mut() {
+                match stream.write_all(frame.as_bytes()) {
+                    Ok(()) => return Ok(()),
+                    Err(_) => tcp.stream = None,
+                }
+            }
+        }
+        Err(std::io::Error::other(format!(
+            "cannot send to syslog collector {}",
+            self.address
+        )))
+    }
+}
+
+impl LogWriter for SyslogWriter {
+    /// Sends a record to the collector
+    ///
+    /// # Arguments
+    ///
+    /// * `_now` - Timestamp of the record (messages are stamped in UTC)
+    /// * `record` - The log record
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Success or IO error
+    fn write(&self, _now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        let message = format!(
+            "<{}>1 {} {} {} {} - - [{}] {}",
+            FACILITY * 8 + severity(record.level()),
+            Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            self.hostname,
+            APP_NAME,
+            std::process::id(),
+            record.target(),
+            record.args()
+        );
+        match &self.udp {
+            Some(socket) => socket.send(message.as_bytes()).map(|_| ()),
+            None => self.send_tcp(&message),
+        }
+    }
+
+    /// Nothing is buffered, messages are sent as they are written
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<()>` - Always successful
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Opens a connection to a TCP collector
+///
+/// # Arguments
+///
+/// * `address` - The `host:port` address
+///
+/// # Returns
+///
+/// * `std::io::Result<TcpStream>` - The connection, or the error of the last address tried
+fn connect_tcp(address: &str) -> std::io::Result<TcpStream> {
+    let mut last_error = std::io::Error::other(format!("cannot resolve {}", address));
+    for addr in std::net::ToSocketAddrs::to_socket_addrs(address)? {
+        match TcpStream::connect_timeout(&addr, TCP_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+/// Maps a log level to a syslog severity
+///
+/// # Arguments
+///
+/// * `level` - The log level
+///
+/// # Returns
+///
+/// * `u8` - The severity (3 error, 4 warning, 6 info, 7 debug)
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Gets the hostname of the machine for the syslog header
+///
+/// # Returns
+///
+/// * `String` - The hostname, or "-" (nil value) if unknown
+fn local_hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty() && !name.contains(' '))
+        .unwrap_or_else(|| "-".to_string())
+}
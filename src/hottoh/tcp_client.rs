@@ -1,18 +1,97 @@
 use super::hottoh_const::*;
 use super::hottoh_structs::*;
 use crate::hottoh::config::AppConfig;
+use crate::hottoh::confirmation::needs_confirmation;
+use crate::hottoh::events::{Event, EventBus};
+use crate::hottoh::failover::StoveAddresses;
+use crate::hottoh::frame_capture::{FrameCapture, FrameDirection};
+use crate::hottoh::layouts::FirmwareInfo;
+use crate::hottoh::locks::{mutex_lock, read_lock, write_lock};
+use crate::hottoh::metrics::Metrics;
+use crate::hottoh::multiplexer::Multiplexer;
+use crate::hottoh::network_check::NetworkCheck;
+use crate::hottoh::redaction::FrameMask;
+use crate::hottoh::response_stream::{ResponseBus, Responses};
 use crate::hottoh::shared_struct::SharedState;
-use crate::hottoh::tcp_client_structs::{Request, Response};
-use log::{error, info, warn};
+use crate::hottoh::supervisor::supervise;
+use crate::hottoh::tcp_client_structs::{Request, Response, ResponseDeduplicator};
+use crate::hottoh::throttle::ThrottleViolation;
+use crate::hottoh::wake_on_lan::WakeOnLan;
+use chrono::Local;
+use log::{debug, error, info, warn};
+use serde::Serialize;
 use std::collections::VecDeque;
 use std::io::{Read, Write};
-use std::net::TcpStream;
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc, Mutex, RwLock,
 };
+use std::thread;
 use std::time::{Duration, Instant};
-use std::{panic, thread};
+use thiserror::Error;
+
+/// Source of a command to the stove
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandOrigin {
+    /// Sent by a user through the API, `force` bypasses the maintenance windows and
+    /// `confirmed` allows the destructive commands
+    Manual { force: bool, confirmed: bool },
+    /// Sent by an integration (inbound webhook, openHAB, multiplexer client),
+    /// `confirmed` allows the destructive commands
+    Integration { confirmed: bool },
+    /// Sent by an automation of the configuration (rules, frost guard, quiet hours,
+    /// fan curve)
+    Automation,
+}
+
+impl CommandOrigin {
+    /// Checks if destructive commands may be sent without a confirmation
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True for confirmed commands and for the automations configured by
+    ///   the owner of the stove
+    fn is_confirmed(&self) -> bool {
+        match self {
+            CommandOrigin::Manual { confirmed, .. } | CommandOrigin::Integration { confirmed } => {
+                *confirmed
+            }
+            CommandOrigin::Automation => true,
+        }
+    }
+}
+
+/// Reasons for which a command is not queued
+#[derive(Error, Debug)]
+pub enum CommandError {
+    /// Commands are disabled
+    #[error("read-only mode is enabled")]
+    ReadOnly,
+    /// A maintenance window is in progress
+    #[error("maintenance window '{0}' in progress")]
+    Maintenance(String),
+    /// The command exceeds a limit of the throttling profile
+    #[error("throttled: {}", .0.reason)]
+    Throttled(ThrottleViolation),
+    /// The command is destructive and must be sent again with the token
+    #[error("confirmation required")]
+    ConfirmationRequired(String),
+    /// No confirmation token can be issued
+    #[error("cannot issue confirmation token: {0}")]
+    Confirmation(String),
+}
+
+/// Reasons for which a command is not cancelled
+#[derive(Error, Debug)]
+pub enum CancelError {
+    /// No write request with this ID is waiting in the queue
+    #[error("no queued command with id {0}")]
+    NotFound(u32),
+    /// The request was already sent to the stove
+    #[error("command {0} was already sent to the stove")]
+    AlreadySent(u32),
+}
 
 /// TCP client for communicating with the stove
 ///
@@ -24,6 +103,14 @@ pub struct TcpClient {
     response_queue: Arc<RwLock<VecDeque<Response>>>,
     /// Flag indicating whether the client is running
     running: Arc<AtomicBool>,
+    /// Runtime metrics
+    metrics: Arc<Metrics>,
+    /// Capture of the last raw frames
+    frame_capture: Arc<FrameCapture>,
+    events: Arc<EventBus>,
+    multiplexer: Arc<Multiplexer>,
+    /// Bus receiving every parsed response
+    response_bus: Arc<ResponseBus>,
 }
 
 impl TcpClient {
@@ -34,6 +121,10 @@ impl TcpClient {
     /// * `request_queue` - Queue of requests to be sent to the stove
     /// * `response_queue` - Queue of responses received from the stove
     /// * `running` - Flag indicating whether the client is running
+    /// * `metrics` - Runtime metrics
+    /// * `frame_capture` - Capture of the last raw frames
+    /// * `events` - Bus notified of the connection changes
+    /// * `multiplexer` - Routes of the requests forwarded for the multiplexer clients
     ///
     /// # Returns
     ///
@@ -42,17 +133,43 @@ impl TcpClient {
         request_queue: Arc<RwLock<VecDeque<Request>>>,
         response_queue: Arc<RwLock<VecDeque<Response>>>,
         running: Arc<AtomicBool>,
+        metrics: Arc<Metrics>,
+        frame_capture: Arc<FrameCapture>,
+        events: Arc<EventBus>,
+        multiplexer: Arc<Multiplexer>,
     ) -> Self {
         TcpClient {
             request_queue,
             response_queue,
             running,
+            metrics,
+            frame_capture,
+            events,
+            multiplexer,
+            response_bus: Arc::new(ResponseBus::new()),
         }
     }
 
+    /// Subscribes to every response parsed from the stove
+    ///
+    /// Each frame is delivered, including the retransmissions dropped before the
+    /// shared state and the answers to the write commands.
+    ///
+    /// # Returns
+    ///
+    /// * `Responses` - The stream of the responses received from now on
+    pub fn responses(&self) -> Responses {
+        self.response_bus.subscribe()
+    }
+
     /// Starts a thread for TCP communication with the stove
     ///
-    /// This thread handles connecting to the stove, sending requests, and receiving responses
+    /// This thread handles connecting to the stove, sending requests, and receiving responses.
+    /// The addresses of the stove are tried in failover order, and while connected to a
+    /// fallback one the primary address is tried again every `stove.failback_secs`. When
+    /// none is reachable, the stove and the gateway are pinged if `stove.ping_check` is
+    /// enabled, and a Wake-on-LAN magic packet is sent if `stove.wake_mac` is set.
+    /// It is restarted by the supervisor if it panics.
     ///
     /// # Arguments
     ///
@@ -63,54 +180,138 @@ impl TcpClient {
     /// * `thread::JoinHandle<()>` - Handle to the spawned thread
     pub fn start_tcp_thread(&self, config: Arc<RwLock<AppConfig>>) -> thread::JoinHandle<()> {
         let cfg = config.read().expect("Cannot read config in tcp thread.");
-        let stove_address = format!("{}:{}", cfg.stove.ip, cfg.stove.port);
+        let addresses = match StoveAddresses::from_config(&cfg.stove) {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                error!("Ignoring stove.fallback_addresses: {}", e);
+                StoveAddresses::primary(&cfg.stove)
+            }
+        };
+        let network_check = NetworkCheck::from_config(&cfg.stove);
+        let wake_on_lan = WakeOnLan::from_config(&cfg.stove).unwrap_or_else(|e| {
+            error!("Wake-on-LAN disabled: {}", e);
+            None
+        });
+        let dedup_window = Duration::from_secs(cfg.stove.dedup_window_secs);
+        let stale_reconnect = Duration::from_secs(cfg.stove.stale_reconnect_secs);
+        let redact = cfg.general.redact;
+        let frame_mask = FrameMask::from_config(&cfg.log, redact);
         let request_queue = Arc::clone(&self.request_queue);
         let response_queue = Arc::clone(&self.response_queue);
         let running = Arc::clone(&self.running);
+        let metrics = Arc::clone(&self.metrics);
+        let frame_capture = Arc::clone(&self.frame_capture);
+        let events = Arc::clone(&self.events);
+        let multiplexer = Arc::clone(&self.multiplexer);
+        let response_bus = Arc::clone(&self.response_bus);
 
-        thread::spawn(move || {
-            let result = panic::catch_unwind(|| loop {
-                if !running.load(Ordering::SeqCst) {
-                    info!("TCP client thread stopped.");
-                    break;
-                }
-
-                let mut stream = match TcpStream::connect(&stove_address) {
-                    Ok(stream) => {
-                        info!("Connected to stove at {}", &stove_address);
-                        stream
-                            .set_nonblocking(true)
-                            .expect("Failed to set non-blocking");
-                        stream
+        supervise(
+            "tcp_client",
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+            move || {
+                let mut deduplicator = ResponseDeduplicator::new(dedup_window);
+                let mut addresses = addresses.clone();
+                let mut firmware = FirmwareInfo::default();
+                loop {
+                    if !running.load(Ordering::SeqCst) {
+                        info!("TCP client thread stopped.");
+                        break;
                     }
-                    Err(e) => {
+
+                    let (active, mut stream) = match addresses.connect() {
+                        Some((active, stream)) => {
+                            info!("Connected to stove at {}", addresses.get(active));
+                            stream
+                                .set_nonblocking(true)
+                                .expect("Failed to set non-blocking");
+                            metrics.set_network_diagnosis(None);
+                            metrics.set_stove_address(Some((
+                                addresses.get(active).to_string(),
+                                active > 0,
+                            )));
+                            events.publish(Event::ConnectionChanged { connected: true });
+                            (active, stream)
+                        }
+                        None => {
+                            if !running.load(Ordering::SeqCst) {
+                                info!("TCP client thread stopped.");
+                                break;
+                            }
+                            warn!("Could not connect to stove. Retrying in 5 seconds...");
+                            if let Some(network_check) = &network_check {
+                                let diagnosis = network_check.run();
+                                warn!(
+                                    "Network check: {} (stove ping: {:?}, gateway ping: {:?})",
+                                    diagnosis.reason,
+                                    diagnosis.stove_reachable,
+                                    diagnosis.gateway_reachable
+                                );
+                                metrics.set_network_diagnosis(Some(diagnosis));
+                            }
+                            // A sleeping Wi-Fi bridge is woken up before the next attempt
+                            if let Some(wake_on_lan) = &wake_on_lan {
+                                match wake_on_lan.send() {
+                                    Ok(()) => debug!("Wake-on-LAN magic packet sent"),
+                                    Err(e) => warn!("Cannot send Wake-on-LAN magic packet: {}", e),
+                                }
+                            }
+                            thread::sleep(Duration::from_secs(5));
+                            continue;
+                        }
+                    };
+
+                    let mut last_sent = Instant::now();
+                    let mut last_dat0 = Instant::now();
+                    let mut last_failback = Instant::now();
+                    let mut buffer = [0; 4096];
+
+                    loop {
                         if !running.load(Ordering::SeqCst) {
                             info!("TCP client thread stopped.");
                             break;
                         }
-                        warn!(
-                            "Could not connect to stove: {}. Retrying in 5 seconds...",
-                            e
-                        );
-                        thread::sleep(Duration::from_secs(5));
-                        continue;
-                    }
-                };
 
-                let mut last_sent = Instant::now();
+                        // A half-open connection looks alive but no longer delivers data
+                        if !stale_reconnect.is_zero() && last_dat0.elapsed() >= stale_reconnect {
+                            warn!(
+                                "No DAT0 received for {} seconds, connection considered half-open. Reconnecting...",
+                                last_dat0.elapsed().as_secs()
+                            );
+                            metrics.inc_stale_reconnects();
+                            break;
+                        }
 
-                loop {
-                    if !running.load(Ordering::SeqCst) {
-                        info!("TCP client thread stopped.");
-                        break;
-                    }
+                        if let Some(failback) = addresses.get_failback().filter(|_| active > 0) {
+                            if last_failback.elapsed() >= failback {
+                                last_failback = Instant::now();
+                                if addresses.primary_reachable() {
+                                    info!(
+                                        "Primary stove address {} is back. Reconnecting...",
+                                        addresses.get(0)
+                                    );
+                                    break;
+                                }
+                            }
+                        }
 
-                    if last_sent.elapsed() >= Duration::from_millis(1000) {
-                        if let Ok(mut req_queue) = request_queue.write() {
+                        if last_sent.elapsed() >= Duration::from_millis(1000) {
+                            let mut req_queue = write_lock(&request_queue, "request queue");
                             if let Some(request) = req_queue.front_mut() {
-                                if !request.is_sent() {
-                                    match stream.write_all(&request.build_message()) {
+                                // Cancelled and expired requests wait for the cleanup
+                                if !request.is_sent()
+                                    && !request.is_marked_as_deleted()
+                                    && !request.is_expired()
+                                {
+                                    let message = request.build_message();
+                                    match stream.write_all(&message) {
                                         Ok(_) => {
+                                            let frame = String::from_utf8_lossy(&message);
+                                            debug!(
+                                                "Sent frame: '{}'",
+                                                frame_mask.apply(&frame, firmware.layout())
+                                            );
+                                            frame_capture.record(FrameDirection::Sent, &frame);
                                             request.mark_as_sent();
                                             last_sent = Instant::now();
                                         }
@@ -125,66 +326,108 @@ impl TcpClient {
                                 }
                             }
                         }
-                    }
 
-                    let mut buffer = [0; 4096];
-                    match stream.read(&mut buffer) {
-                        Ok(size) if size > 0 => {
-                            let response_str = String::from_utf8_lossy(&buffer[..size]);
-                            // Split the string into individual messages
-                            let messages: Vec<&str> =
-                                response_str.split('#').filter(|s| !s.is_empty()).collect();
-                            for message in messages {
-                                let message_with_prefix = format!("#{}", message);
-                                match Response::from_message(&message_with_prefix) {
-                                    Ok(response) => {
-                                        if let Ok(mut resp_queue) = response_queue.write() {
-                                            resp_queue.push_back(response);
+                        match stream.read(&mut buffer) {
+                            Ok(size) if size > 0 => {
+                                let response_str = String::from_utf8_lossy(&buffer[..size]);
+                                // Split the string into individual messages, keeping the '#' prefix
+                                for message_with_prefix in split_frames(&response_str) {
+                                    debug!(
+                                        "Received frame: '{}'",
+                                        frame_mask.apply(message_with_prefix, firmware.layout())
+                                    );
+                                    frame_capture
+                                        .record(FrameDirection::Received, message_with_prefix);
+                                    multiplexer.route_response(message_with_prefix);
+                                    match Response::from_message(
+                                        message_with_prefix,
+                                        firmware.layout(),
+                                    ) {
+                                        Ok(mut response) => {
+                                            // A corrupted frame must not change the layout
+                                            // of the next ones
+                                            let change = response
+                                                .check_crc()
+                                                .ok()
+                                                .and_then(|()| firmware.update(&response));
+                                            if let Some(change) = change {
+                                                warn!(
+                                                    "Stove firmware changed from {} to {}, using {} DAT layout",
+                                                    change.from, change.to, change.layout
+                                                );
+                                                events.publish(Event::FirmwareChanged {
+                                                    from: change.from,
+                                                    to: change.to,
+                                                    layout: change.layout,
+                                                });
+                                            }
+                                            if redact {
+                                                response.redact();
+                                            }
+                                            if let Some(data) = response.get_command_data() {
+                                                if let CommandData::Dat0(_) = data {
+                                                    last_dat0 = Instant::now();
+                                                }
+                                                response_bus.publish(data);
+                                            }
+                                            if deduplicator.is_duplicate(&response) {
+                                                debug!(
+                                                    "Dropping duplicate response: '{}'",
+                                                    frame_mask.apply(
+                                                        message_with_prefix,
+                                                        firmware.layout()
+                                                    )
+                                                );
+                                                metrics.inc_duplicate_responses_dropped();
+                                                continue;
+                                            }
+                                            write_lock(&response_queue, "response queue")
+                                                .push_back(response);
+                                        }
+                                        Err(e) => {
+                                            error!(
+                                                "Error parsing response: {}. Raw response: '{}'",
+                                                e,
+                                                frame_mask
+                                                    .apply(message_with_prefix, firmware.layout())
+                                            );
                                         }
-                                    }
-                                    Err(e) => {
-                                        error!(
-                                            "Error parsing response: {}. Raw response: '{}'",
-                                            e, message_with_prefix
-                                        );
                                     }
                                 }
                             }
-                        }
-                        Ok(_) => {}
-                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
-                        Err(e) => {
-                            if !running.load(Ordering::SeqCst) {
-                                info!("TCP client thread stopped.");
+                            Ok(_) => {}
+                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+                            Err(e) => {
+                                if !running.load(Ordering::SeqCst) {
+                                    info!("TCP client thread stopped.");
+                                    break;
+                                }
+                                warn!("Failed to receive response: {}. Reconnecting...", e);
                                 break;
                             }
-                            warn!("Failed to receive response: {}. Reconnecting...", e);
-                            break;
                         }
+
+                        thread::sleep(Duration::from_millis(200));
                     }
+                    metrics.set_stove_address(None);
+                    events.publish(Event::ConnectionChanged { connected: false });
 
-                    thread::sleep(Duration::from_millis(200));
-                }
+                    if !running.load(Ordering::SeqCst) {
+                        info!("TCP client thread stopped.");
+                        break;
+                    }
 
-                if !running.load(Ordering::SeqCst) {
-                    info!("TCP client thread stopped.");
-                    break;
+                    info!("Disconnected from stove. Reconnecting in 5 seconds...");
+                    thread::sleep(Duration::from_secs(5));
                 }
-
-                info!("Disconnected from stove. Reconnecting in 5 seconds...");
-                thread::sleep(Duration::from_secs(5));
-            });
-
-            if let Err(err) = result {
-                error!("Thread panicked: {:?}", err);
-            }
-        })
+            },
+        )
     }
 
     /// Starts a thread for managing messages between requests and responses
     ///
     /// This thread matches responses to requests, updates the shared state,
-    /// and handles timeouts
+    /// and handles timeouts. It is restarted by the supervisor if it panics.
     ///
     /// # Arguments
     ///
@@ -200,11 +443,19 @@ impl TcpClient {
         let request_queue = Arc::clone(&self.request_queue);
         let response_queue = Arc::clone(&self.response_queue);
         let running = Arc::clone(&self.running);
+        let metrics = Arc::clone(&self.metrics);
 
-        thread::spawn(move || {
-            while running.load(Ordering::SeqCst) {
-                if let Ok(mut req_queue) = request_queue.write() {
-                    if let Ok(mut res_queue) = response_queue.write() {
+        supervise(
+            "message_management",
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+            move || {
+                while running.load(Ordering::SeqCst) {
+                    // Published once the queues and the state are unlocked
+                    let mut events = Vec::new();
+                    {
+                        let mut req_queue = write_lock(&request_queue, "request queue");
+                        let mut res_queue = write_lock(&response_queue, "response queue");
                         for res in res_queue.iter_mut() {
                             // Check if the response corresponds to an existing request
                             let matching_req = req_queue
@@ -231,6 +482,23 @@ impl TcpClient {
                             if req.is_marked_as_deleted() {
                                 continue;
                             }
+                            if req.is_expired() {
+                                warn!(
+                                    "Request expired before being sent: req_id={}, command={:?}, command_type={:?}, params={:?}",
+                                    req.get_req_id(),
+                                    req.get_command(),
+                                    req.get_command_type(),
+                                    req.get_params()
+                                );
+                                req.set_marked_as_deleted(true);
+                                write_lock(&shared_state, "shared state")
+                                    .remove_pending(req.get_req_id());
+                                events.push(Event::CommandDropped {
+                                    request_id: req.get_req_id(),
+                                    reason: "expired",
+                                });
+                                continue;
+                            }
                             if req.is_sent() && req.get_sent_at().unwrap().elapsed().as_secs() > 5 {
                                 warn!("Request timeout: req_id={}, command={:?}, command_type={:?}, params={:?}",
                                     req.get_req_id(),
@@ -239,30 +507,54 @@ impl TcpClient {
                                     req.get_params()
                                 );
                                 req.set_marked_as_deleted(true);
+                                if *req.get_command_type() == CommandType::Write {
+                                    events.push(Event::CommandDropped {
+                                        request_id: req.get_req_id(),
+                                        reason: "no_answer",
+                                    });
+                                }
                             }
                             for res in res_queue.iter_mut() {
                                 if res.is_marked_as_deleted() {
                                     break;
                                 }
                                 if req.get_req_id() == res.get_req_id() {
-                                    if res.is_crc_valid() {
-                                        if let Ok(mut state) = shared_state.write() {
-                                            match res.get_command_data() {
-                                                CommandData::Inf(inf_data) => {
+                                    match res.check_crc() {
+                                        Ok(()) => {
+                                            if let Some(sent_at) = req.get_sent_at() {
+                                                metrics.observe_response_latency(
+                                                    res.get_received_at()
+                                                        .saturating_duration_since(sent_at),
+                                                );
+                                            }
+                                            let mut state =
+                                                write_lock(&shared_state, "shared state");
+                                            events.extend(match res.take_command_data() {
+                                                Some(CommandData::Inf(inf_data)) => {
                                                     state.set_inf(inf_data)
                                                 }
-                                                CommandData::Dat0(dat0_data) => {
+                                                Some(CommandData::Dat0(dat0_data)) => {
                                                     state.set_dat0(dat0_data)
                                                 }
-                                                CommandData::Dat1(dat1_data) => {
+                                                Some(CommandData::Dat1(dat1_data)) => {
                                                     state.set_dat1(dat1_data)
                                                 }
-                                                CommandData::Dat2(dat2_data) => {
+                                                Some(CommandData::Dat2(dat2_data)) => {
                                                     state.set_dat2(dat2_data)
                                                 }
-                                                _ => {}
+                                                _ => Vec::new(),
+                                            });
+                                            if *req.get_command_type() == CommandType::Write {
+                                                events.push(Event::CommandAccepted {
+                                                    request_id: req.get_req_id(),
+                                                });
                                             }
                                         }
+                                        Err(e) => warn!(
+                                            "Ignoring response {} of the stove: {}",
+                                            res.get_req_id(),
+                                            e
+                                        ),
                                     }
                                     res.set_marked_as_deleted(true);
                                     req.set_marked_as_deleted(true);
@@ -271,17 +563,24 @@ impl TcpClient {
                             }
                         }
                     }
+                    if !events.is_empty() {
+                        let bus = Arc::clone(read_lock(&shared_state, "shared state").get_events());
+                        for event in events {
+                            bus.publish(event);
+                        }
+                    }
+                    clean_queues(&request_queue, &response_queue);
+                    thread::sleep(Duration::from_millis(200));
                 }
-                clean_queues(&request_queue, &response_queue);
-                thread::sleep(Duration::from_millis(200));
-            }
-            info!("Message management thread stopped.");
-        })
+                info!("Message management thread stopped.");
+            },
+        )
     }
 
     /// Starts a thread for sending periodic requests to the stove
     ///
-    /// This thread sends INF and DAT requests at regular intervals
+    /// This thread sends INF and DAT requests at regular intervals.
+    /// It is restarted by the supervisor if it panics.
     ///
     /// # Arguments
     ///
@@ -296,89 +595,263 @@ impl TcpClient {
     ) -> thread::JoinHandle<()> {
         let request_queue = Arc::clone(&self.request_queue);
         let running = Arc::clone(&self.running);
+        let metrics = Arc::clone(&self.metrics);
 
-        thread::spawn(move || {
-            let result = panic::catch_unwind(|| {
+        supervise(
+            "periodic_request",
+            Arc::clone(&running),
+            metrics,
+            move || {
                 while running.load(Ordering::SeqCst) {
-                    if let Ok(mut id_lock) = request_id_counter.lock() {
+                    {
+                        let mut id_lock = mutex_lock(&request_id_counter, "request ID counter");
                         let request_id = *id_lock;
                         if !already_existing_request(
                             &Command::Inf,
                             &CommandType::Read,
                             &[],
                             &request_queue,
-                        )
-                        .unwrap_or(false)
-                        {
-                            if let Err(e) = send_request(
+                        ) {
+                            send_request(
                                 Request::new(request_id, Command::Inf, CommandType::Read, vec![]),
                                 &request_queue,
-                            ) {
-                                warn!("Failed to send INF request: {:?}", e);
-                            }
+                            );
                             *id_lock = (*id_lock + 1) % 100000;
                         }
                     }
 
                     for i in 0..3 {
-                        if let Ok(mut id_lock) = request_id_counter.lock() {
-                            let request_id = *id_lock;
-                            if !already_existing_request(
-                                &Command::Dat,
-                                &CommandType::Read,
-                                &[i.to_string()],
+                        let mut id_lock = mutex_lock(&request_id_counter, "request ID counter");
+                        let request_id = *id_lock;
+                        if !already_existing_request(
+                            &Command::Dat,
+                            &CommandType::Read,
+                            &[i.to_string()],
+                            &request_queue,
+                        ) {
+                            send_request(
+                                Request::new(
+                                    request_id,
+                                    Command::Dat,
+                                    CommandType::Read,
+                                    vec![i.to_string()],
+                                ),
                                 &request_queue,
-                            )
-                            .unwrap_or(false)
-                            {
-                                if let Err(e) = send_request(
-                                    Request::new(
-                                        request_id,
-                                        Command::Dat,
-                                        CommandType::Read,
-                                        vec![i.to_string()],
-                                    ),
-                                    &request_queue,
-                                ) {
-                                    warn!("Failed to send DAT{} request: {:?}", i, e);
-                                }
-                                *id_lock = (*id_lock + 1) % 100000;
-                            }
+                            );
+                            *id_lock = (*id_lock + 1) % 100000;
                         }
                     }
                     thread::sleep(Duration::from_secs(1));
                 }
 
                 info!("Periodic request thread stopped.");
-            });
-
-            if let Err(err) = result {
-                error!("Thread panicked: {:?}", err);
-            }
-        })
+            },
+        )
     }
 }
 
+/// Splits received data into frames, each starting with '#'
+///
+/// # Arguments
+///
+/// * `data` - The received data
+///
+/// # Returns
+///
+/// * `impl Iterator<Item = &str>` - The frames, borrowed from the data
+pub fn split_frames(data: &str) -> impl Iterator<Item = &str> {
+    data.match_indices('#').map(move |(start, _)| {
+        let end = data[start + 1..]
+            .find('#')
+            .map_or(data.len(), |next| start + 1 + next);
+        &data[start..end]
+    })
+}
+
 /// Sends a request to the stove by adding it to the request queue
 ///
 /// # Arguments
 ///
 /// * `request` - The request to send
 /// * `request_queue` - Queue of requests to be sent to the stove
+pub fn send_request(request: Request, request_queue: &Arc<RwLock<VecDeque<Request>>>) {
+    write_lock(request_queue, "request queue").push_back(request);
+}
+
+/// Adds a write request to the queue
+///
+/// The expected value is recorded in the pending section of the shared state until
+/// the stove reports it. Nothing is queued in read-only mode, nor during a maintenance
+/// window unless the command is manual and forced, nor for a destructive command that
+/// was not confirmed.
+///
+/// # Arguments
+///
+/// * `request_queue` - The request queue
+/// * `request_id_counter` - The request ID counter
+/// * `shared_state` - Shared state where the pending value is recorded
+/// * `origin` - Source of the command
+/// * `action` - The StoveCommands value
+/// * `value` - The value sent to the stove
 ///
 /// # Returns
 ///
-/// * `Result<(), String>` - Success or error message
-pub fn send_request(
-    request: Request,
-    request_queue: &Arc<RwLock<VecDeque<Request>>>,
-) -> Result<(), String> {
-    if let Ok(mut queue) = request_queue.write() {
-        queue.push_back(request);
-        Ok(())
-    } else {
-        Err("Failed to lock request queue".to_string())
+/// * `Result<u32, CommandError>` - The ID of the queued request, or the reason it was rejected
+pub fn queue_write_request(
+    request_queue: &RwLock<VecDeque<Request>>,
+    request_id_counter: &Mutex<u32>,
+    shared_state: &RwLock<SharedState>,
+    origin: CommandOrigin,
+    action: u32,
+    value: &impl ToString,
+) -> Result<u32, CommandError> {
+    let request_ids = queue_write_requests(
+        request_queue,
+        request_id_counter,
+        shared_state,
+        origin,
+        &[(action, value.to_string())],
+        None,
+    )?;
+    Ok(request_ids[0])
+}
+
+/// Adds several write requests to the queue, all or none of them
+///
+/// The requests are checked together against the read-only mode, the maintenance
+/// windows, the confirmation of the destructive commands and the throttling, then
+/// queued at once so no other command can be interleaved between them. They are
+/// dropped if not sent within their TTL.
+///
+/// # Arguments
+///
+/// * `request_queue` - The request queue
+/// * `request_id_counter` - The request ID counter
+/// * `shared_state` - Shared state where the pending values are recorded
+/// * `origin` - Source of the commands
+/// * `commands` - The StoveCommands values with the values sent to the stove
+/// * `ttl` - Delay after which the requests are dropped if not sent, zero to keep them,
+///   None for the configured delay
+///
+/// # Returns
+///
+/// * `Result<Vec<u32>, CommandError>` - The IDs of the queued requests, or the reason they were rejected
+pub fn queue_write_requests(
+    request_queue: &RwLock<VecDeque<Request>>,
+    request_id_counter: &Mutex<u32>,
+    shared_state: &RwLock<SharedState>,
+    origin: CommandOrigin,
+    commands: &[(u32, String)],
+    ttl: Option<Duration>,
+) -> Result<Vec<u32>, CommandError> {
+    let mut id_lock = mutex_lock(request_id_counter, "request ID counter");
+    let request_ids: Vec<u32> = (0..commands.len() as u32)
+        .map(|offset| (*id_lock + offset) % 100000)
+        .collect();
+    let (events, ttl) = {
+        let mut state = write_lock(shared_state, "shared state");
+        if state.is_read_only() {
+            return Err(CommandError::ReadOnly);
+        }
+        if !matches!(origin, CommandOrigin::Manual { force: true, .. }) {
+            if let Some(window) = state.get_maintenance().active_window(Local::now()) {
+                return Err(CommandError::Maintenance(window.to_string()));
+            }
+        }
+        if !origin.is_confirmed() {
+            let stove_state = state.get_dat0().get_index_stove_state();
+            if let Some((action, value)) = commands
+                .iter()
+                .find(|(action, value)| needs_confirmation(*action, value, stove_state))
+            {
+                return Err(state.get_confirmations().issue(*action, value).map_or_else(
+                    CommandError::Confirmation,
+                    CommandError::ConfirmationRequired,
+                ));
+            }
+        }
+        state
+            .check_throttle(commands)
+            .map_err(CommandError::Throttled)?;
+        for (request_id, (action, value)) in request_ids.iter().zip(commands) {
+            state.add_pending(*request_id, *action, value);
+        }
+        (
+            Arc::clone(state.get_events()),
+            ttl.unwrap_or(state.get_command_ttl()),
+        )
+    };
+    {
+        let mut queue = write_lock(request_queue, "request queue");
+        for (request_id, (action, value)) in request_ids.iter().zip(commands) {
+            let mut request = Request::new(
+                *request_id,
+                Command::Dat,
+                CommandType::Write,
+                vec![action.to_string(), value.clone()],
+            );
+            if !ttl.is_zero() {
+                request.set_ttl(ttl);
+            }
+            queue.push_back(request);
+        }
+    }
+    *id_lock = (*id_lock + commands.len() as u32) % 100000;
+    for (request_id, (action, value)) in request_ids.iter().zip(commands) {
+        events.publish(Event::CommandQueued {
+            request_id: *request_id,
+            action: *action,
+            value: value.clone(),
+            origin,
+        });
+    }
+    Ok(request_ids)
+}
+
+/// Cancels a write request that was not sent yet
+///
+/// The request is dropped at the next cleanup of the queue and its pending value
+/// is removed.
+///
+/// # Arguments
+///
+/// * `request_queue` - The request queue
+/// * `shared_state` - Shared state where the pending value is recorded
+/// * `request_id` - ID of the request
+///
+/// # Returns
+///
+/// * `Result<(), CancelError>` - Ok if the request was cancelled
+pub fn cancel_request(
+    request_queue: &RwLock<VecDeque<Request>>,
+    shared_state: &RwLock<SharedState>,
+    request_id: u32,
+) -> Result<(), CancelError> {
+    {
+        let mut queue = write_lock(request_queue, "request queue");
+        let request = queue
+            .iter_mut()
+            .find(|request| {
+                request.get_req_id() == request_id
+                    && *request.get_command_type() == CommandType::Write
+                    && !request.is_marked_as_deleted()
+            })
+            .ok_or(CancelError::NotFound(request_id))?;
+        if request.is_sent() {
+            return Err(CancelError::AlreadySent(request_id));
+        }
+        request.set_marked_as_deleted(true);
     }
+    let events = {
+        let mut state = write_lock(shared_state, "shared state");
+        state.remove_pending(request_id);
+        Arc::clone(state.get_events())
+    };
+    events.publish(Event::CommandDropped {
+        request_id,
+        reason: "cancelled",
+    });
+    Ok(())
 }
 
 /// Checks if a request with the same command, type, and parameters already exists in the queue
@@ -392,24 +865,81 @@ pub fn send_request(
 ///
 /// # Returns
 ///
-/// * `Result<bool, String>` - True if a similar request exists, false otherwise, or an error
+/// * `bool` - True if a similar request exists, false otherwise
 pub fn already_existing_request(
     command: &Command,
     command_type: &CommandType,
     params: &[String],
     request_queue: &Arc<RwLock<VecDeque<Request>>>,
-) -> Result<bool, String> {
-    if let Ok(queue) = request_queue.read() {
-        let similar_exists = queue.iter().any(|r| {
-            !r.is_marked_as_deleted()
-                && r.get_command() == command
-                && r.get_command_type() == command_type
-                && r.get_params() == params
+) -> bool {
+    read_lock(request_queue, "request queue").iter().any(|r| {
+        !r.is_marked_as_deleted()
+            && r.get_command() == command
+            && r.get_command_type() == command_type
+            && r.get_params() == params
+    })
+}
+
+/// Drops pending requests and responses from their respective queues
+///
+/// As with `cancel_request`, the pending value of each dropped write is removed and a
+/// `command_dropped` event is published with the `cleared` reason.
+///
+/// # Arguments
+///
+/// * `request_queue` - Queue of requests to clear
+/// * `response_queue` - Queue of responses to clear
+/// * `shared_state` - Shared state where the pending values are recorded
+/// * `only_reads` - If true, only read requests and responses are dropped
+///
+/// # Returns
+///
+/// * `(usize, usize)` - Number of requests and responses dropped
+pub fn clear_queues(
+    request_queue: &Arc<RwLock<VecDeque<Request>>>,
+    response_queue: &Arc<RwLock<VecDeque<Response>>>,
+    shared_state: &RwLock<SharedState>,
+    only_reads: bool,
+) -> (usize, usize) {
+    let (requests_dropped, responses_dropped, dropped_writes) = {
+        let mut req_queue = write_lock(request_queue, "request queue");
+        let req_len = req_queue.len();
+        let mut dropped_writes = Vec::new();
+        req_queue.retain(|req| {
+            let keep = only_reads && *req.get_command_type() != CommandType::Read;
+            if !keep && *req.get_command_type() == CommandType::Write && !req.is_marked_as_deleted()
+            {
+                dropped_writes.push(req.get_req_id());
+            }
+            keep
         });
-        Ok(similar_exists)
-    } else {
-        Err("Failed to lock request queue for reading".to_string())
+        let requests_dropped = req_len - req_queue.len();
+
+        let mut res_queue = write_lock(response_queue, "response queue");
+        let res_len = res_queue.len();
+        res_queue.retain(|res| only_reads && *res.get_command_type() != CommandType::Read);
+        let responses_dropped = res_len - res_queue.len();
+
+        (requests_dropped, responses_dropped, dropped_writes)
+    };
+
+    if !dropped_writes.is_empty() {
+        let events = {
+            let mut state = write_lock(shared_state, "shared state");
+            for request_id in &dropped_writes {
+                state.remove_pending(*request_id);
+            }
+            Arc::clone(state.get_events())
+        };
+        for request_id in dropped_writes {
+            events.publish(Event::CommandDropped {
+                request_id,
+                reason: "cleared",
+            });
+        }
     }
+
+    (requests_dropped, responses_dropped)
 }
 
 /// Removes requests and responses that are marked for deletion from their respective queues
@@ -422,15 +952,6 @@ fn clean_queues(
     request_queue: &Arc<RwLock<VecDeque<Request>>>,
     response_queue: &Arc<RwLock<VecDeque<Response>>>,
 ) {
-    if let Ok(mut req_queue) = request_queue.write() {
-        req_queue.retain(|req| !req.is_marked_as_deleted());
-    } else {
-        warn!("Failed to lock request queue");
-    }
-
-    if let Ok(mut res_queue) = response_queue.write() {
-        res_queue.retain(|res| !res.is_marked_as_deleted());
-    } else {
-        warn!("Failed to lock response queue");
-    }
+    write_lock(request_queue, "request queue").retain(|req| !req.is_marked_as_deleted());
+    write_lock(response_queue, "response queue").retain(|res| !res.is_marked_as_deleted());
 }
@@ -1,11 +1,14 @@
+use crate::hottoh::error::HottohError;
 use crate::hottoh::hottoh_const::Command::Dat;
 use crate::hottoh::hottoh_const::{Command, CommandType};
 use crate::hottoh::hottoh_structs::{
     calculate_checksum, CommandData, DAT0Data, DAT1Data, DAT2Data, DATReqResponseData, INFData,
 };
-use log::warn;
+use crate::hottoh::layouts::FirmwareLayout;
+use log::{debug, warn};
+use std::collections::VecDeque;
 use std::str::FromStr;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 /// Errors that can occur when processing responses from the stove
@@ -14,11 +17,29 @@ pub enum ResponseError {
     /// Command not implemented
     #[error("{0}")]
     NotImplemented(String),
-    /// Response data has incorrect structure
-    #[error("Incorrect response data: {0}")]
-    IncorrectResponseStruct(String),
+    /// Response data does not have the expected number of elements
+    #[error("Incorrect number of elements in {page} struct: expected {expected}, got {actual}")]
+    IncorrectLength {
+        /// Name of the page
+        page: &'static str,
+        /// Expected number of elements
+        expected: usize,
+        /// Received number of elements
+        actual: usize,
+    },
+    /// An element of the response data could not be parsed
+    #[error("Invalid {field} at index {index}")]
+    InvalidField {
+        /// Name of the field
+        field: &'static str,
+        /// Index of the element in the response data
+        index: usize,
+    },
 }
 
+/// Shortest frame: '#', req_id, length, command, type, CRC and trailing '\n'
+const MIN_FRAME_LEN: usize = 24;
+
 /// Request to be sent to the stove
 #[derive(Debug)]
 pub struct Request {
@@ -26,8 +47,10 @@ pub struct Request {
     command: Command,
     command_type: CommandType,
     params: Vec<String>,
+    queued_at: Instant,
     sent: bool,
     sent_at: Option<Instant>,
+    expires_at: Option<Instant>,
     marked_as_deleted: bool,
 }
 
@@ -74,12 +97,44 @@ impl Request {
             command,
             command_type,
             params,
+            queued_at: Instant::now(),
             sent: false,
             sent_at: None,
+            expires_at: None,
             marked_as_deleted: false,
         }
     }
 
+    /// Sets the delay after which the request is dropped if it was not sent
+    ///
+    /// # Arguments
+    ///
+    /// * `ttl` - Delay from the time the request was queued
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.expires_at = Some(self.queued_at + ttl);
+    }
+
+    /// Gets the time after which the request is dropped if it was not sent
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Instant>` - The expiry, None if the request does not expire
+    pub fn get_expires_at(&self) -> Option<Instant> {
+        self.expires_at
+    }
+
+    /// Checks if the request expired before being sent
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the request was not sent and its expiry is reached
+    pub fn is_expired(&self) -> bool {
+        !self.sent
+            && self
+                .expires_at
+                .is_some_and(|expires_at| expires_at <= Instant::now())
+    }
+
     /// Marks the request as sent
     ///
     /// Sets the sent flag to true and records the current time
@@ -149,6 +204,15 @@ impl Request {
         &self.params
     }
 
+    /// Gets the time at which the request was created
+    ///
+    /// # Returns
+    ///
+    /// * `Instant` - The creation time
+    pub fn get_queued_at(&self) -> Instant {
+        self.queued_at
+    }
+
     /// Checks if the request has been sent
     ///
     /// # Returns
@@ -193,10 +257,10 @@ pub struct Response {
     command: Command,
     command_type: CommandType,
     params_len: u32,
-    params: Vec<String>,
-    command_data: CommandData,
+    command_data: Option<CommandData>,
     crc: String,
-    crc_is_valid: bool,
+    computed_crc: String,
+    received_at: Instant,
     marked_as_deleted: bool,
 }
 
@@ -212,7 +276,7 @@ impl Response {
     ///
     /// * `Result<CommandData, ResponseError>` - The parsed command data or an error
     fn command_data_from_vec(
-        data: &Vec<&str>,
+        data: &[&str],
         command: &Command,
     ) -> Result<CommandData, ResponseError> {
         match command {
@@ -235,65 +299,86 @@ impl Response {
     /// # Arguments
     ///
     /// * `message` - The message to parse
+    /// * `layout` - Layout of the DAT pages sent by the stove firmware
     ///
     /// # Returns
     ///
-    /// * `Result<Response, Box<dyn std::error::Error>>` - The parsed response or an error
-    pub fn from_message(message: &str) -> Result<Response, Box<dyn std::error::Error>> {
-        let req_id = str::parse(&message[1..6]).map_err(|_| "Invalid req_id")?;
-        let req_id_char = message
-            .chars()
-            .nth(6)
-            .ok_or("Missing req_id separator character")?;
+    /// * `Result<Response, HottohError>` - The parsed response, or a `Protocol` or
+    ///   `NotSupported` error
+    pub fn from_message(message: &str, layout: &FirmwareLayout) -> Result<Response, HottohError> {
+        // The header and the CRC are at fixed offsets
+        if !message.is_ascii() || message.len() < MIN_FRAME_LEN {
+            return Err(HottohError::Protocol(format!(
+                "truncated or not ASCII frame of {} bytes",
+                message.len()
+            )));
+        }
+        let req_id = str::parse(&message[1..6])
+            .map_err(|_| HottohError::Protocol("Invalid req_id".into()))?;
 
-        let params_len =
-            usize::from_str_radix(&message[10..14], 16).map_err(|_| "Invalid param length")?;
+        let params_len = usize::from_str_radix(&message[10..14], 16)
+            .map_err(|_| HottohError::Protocol("Invalid param length".into()))?;
 
-        let mut command = Command::from_str(&message[14..17])?;
-        let command_type = CommandType::from_str(&message[17..18])?;
+        let mut command = Command::from_str(&message[14..17]).map_err(HottohError::Protocol)?;
+        let command_type =
+            CommandType::from_str(&message[17..18]).map_err(HottohError::Protocol)?;
         let params_section = &message[18..&message.len() - 6];
         let crc = &message[&message.len() - 5..&message.len() - 1];
 
         let params: Vec<&str> = params_section.split(';').collect();
 
-        let crc_response = format!(
-            "{:05}{}---{:04X}{}{}{}",
-            req_id,
-            req_id_char,
-            params_len,
-            command.as_str(),
-            command_type.as_str(),
-            params.join(";") + ";"
-        );
-        let crc_is_valid = crc == calculate_checksum(&crc_response).as_str();
+        // The checksum covers everything between the leading '#' and the CRC itself
+        let computed_crc = calculate_checksum(&message[1..message.len() - 5]);
 
+        let normalized;
+        let mut data: &[&str] = &params;
+        let mut raw_extra: &[&str] = &[];
         if command == Dat {
-            match params.len() {
-                36 => command = Command::Dat0,
-                11 => command = Command::Dat1,
-                22 => command = Command::Dat2,
-                1 => command = Command::DatReqResponse,
-                _ => {
+            match layout.identify_page(&params) {
+                Some(page) => {
+                    if params.len() > page.len {
+                        raw_extra = &params[page.len..];
+                        debug!(
+                            "Keeping {} extra params in {} response as raw_extra ({} layout)",
+                            raw_extra.len(),
+                            page.command.as_str(),
+                            layout.name
+                        );
+                    }
+                    command = page.command.clone();
+                    normalized = page.normalize(&params)?;
+                    data = &normalized;
+                }
+                None => {
                     warn!(
-                        "Incorrect {} response structure: {}",
+                        "Incorrect {} response structure for {} layout: {}",
                         command.as_str(),
+                        layout.name,
                         &message
                     );
                 }
             }
         }
 
-        let command_data = Response::command_data_from_vec(&params, &command)?;
+        let mut command_data = Response::command_data_from_vec(data, &command)?;
+        if let CommandData::Dat2(dat2) = &mut command_data {
+            dat2.decode_actuators(layout.hydro);
+        }
+        if !raw_extra.is_empty() {
+            command_data.set_raw_extra(raw_extra.iter().map(|p| p.to_string()).collect());
+        }
 
         Ok(Response {
             req_id,
             command,
             command_type,
-            params_len: params_len.try_into()?,
-            params: params.iter().map(|&s| s.to_string()).collect(),
-            command_data,
+            params_len: params_len
+                .try_into()
+                .map_err(|_| HottohError::Protocol("Invalid param length".into()))?,
+            command_data: Some(command_data),
             crc: crc.to_string(),
-            crc_is_valid,
+            computed_crc,
+            received_at: Instant::now(),
             marked_as_deleted: false,
         })
     }
@@ -307,22 +392,74 @@ impl Response {
         self.req_id
     }
 
+    /// Gets the time at which the response was parsed
+    ///
+    /// # Returns
+    ///
+    /// * `Instant` - The time of reception
+    pub fn get_received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// Gets the command type
+    ///
+    /// # Returns
+    ///
+    /// * `&CommandType` - Reference to the command type
+    pub fn get_command_type(&self) -> &CommandType {
+        &self.command_type
+    }
+
     /// Gets the command data
     ///
     /// # Returns
     ///
-    /// * `&CommandData` - Reference to the command data
-    pub fn get_command_data(&self) -> &CommandData {
-        &self.command_data
+    /// * `Option<&CommandData>` - Reference to the command data, or None if already taken
+    pub fn get_command_data(&self) -> Option<&CommandData> {
+        self.command_data.as_ref()
+    }
+
+    /// Takes the command data out of the response
+    ///
+    /// The data is moved rather than cloned, so it can only be taken once.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<CommandData>` - The command data, or None if already taken
+    pub fn take_command_data(&mut self) -> Option<CommandData> {
+        self.command_data.take()
+    }
+
+    /// Replaces the identifying fields of the command data with a placeholder
+    pub fn redact(&mut self) {
+        if let Some(CommandData::Inf(inf)) = &mut self.command_data {
+            inf.redact();
+        }
+    }
+
+    /// Gets the CRC received with the response
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The CRC as sent by the stove
+    pub fn get_crc(&self) -> &str {
+        &self.crc
     }
 
-    /// Checks if the CRC is valid
+    /// Checks the CRC received with the response
     ///
     /// # Returns
     ///
-    /// * `bool` - True if the CRC is valid, false otherwise
-    pub fn is_crc_valid(&self) -> bool {
-        self.crc_is_valid
+    /// * `Result<(), HottohError>` - Success if the CRC matches the content, or a `Crc`
+    ///   error
+    pub fn check_crc(&self) -> Result<(), HottohError> {
+        if self.crc == self.computed_crc {
+            return Ok(());
+        }
+        Err(HottohError::Crc {
+            received: self.crc.clone(),
+            computed: self.computed_crc.clone(),
+        })
     }
 
     /// Checks if the response is marked for deletion
@@ -343,3 +480,69 @@ impl Response {
         self.marked_as_deleted = value;
     }
 }
+
+/// Sliding window of recently received responses
+///
+/// Some Wi-Fi bridges retransmit frames, so the same response can be received
+/// several times. Responses are identified by their req_id and CRC.
+pub struct ResponseDeduplicator {
+    window: Duration,
+    seen: VecDeque<(u32, String, Instant)>,
+}
+
+impl ResponseDeduplicator {
+    /// Creates a new deduplicator
+    ///
+    /// # Arguments
+    ///
+    /// * `window` - Duration during which an identical response is considered a duplicate.
+    ///   A zero duration disables deduplication.
+    ///
+    /// # Returns
+    ///
+    /// * `ResponseDeduplicator` - A new deduplicator
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: VecDeque::new(),
+        }
+    }
+
+    /// Checks whether a response was already received within the window
+    ///
+    /// Responses that are not duplicates are recorded for later checks.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The response to check
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the response is a duplicate, false otherwise
+    pub fn is_duplicate(&mut self, response: &Response) -> bool {
+        if self.window.is_zero() {
+            return false;
+        }
+
+        while let Some((_, _, received_at)) = self.seen.front() {
+            if received_at.elapsed() > self.window {
+                self.seen.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        let duplicate = self
+            .seen
+            .iter()
+            .any(|(req_id, crc, _)| *req_id == response.get_req_id() && crc == response.get_crc());
+        if !duplicate {
+            self.seen.push_back((
+                response.get_req_id(),
+                response.get_crc().to_string(),
+                Instant::now(),
+            ));
+        }
+        duplicate
+    }
+}
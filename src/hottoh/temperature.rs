@@ -0,0 +1,126 @@
+use crate::hottoh::config::{GeneralConfig, TemperatureRounding};
+use serde::Serializer;
+use std::sync::OnceLock;
+
+/// Format of the temperatures, set once at startup
+static FORMAT: OnceLock<TemperatureFormat> = OnceLock::new();
+
+/// Precision and rounding of the temperatures
+#[derive(Debug, Clone, Copy)]
+struct TemperatureFormat {
+    /// Decimals of the serialized temperatures, 0 or 1
+    decimals: u8,
+    /// Rounding of the temperatures written to the stove and of the integer ones
+    rounding: TemperatureRounding,
+}
+
+impl Default for TemperatureFormat {
+    fn default() -> Self {
+        Self {
+            decimals: 1,
+            rounding: TemperatureRounding::HalfUp,
+        }
+    }
+}
+
+/// Sets the precision and the rounding of the temperatures
+///
+/// Must be called before the state is first serialized; later calls are ignored.
+///
+/// # Arguments
+///
+/// * `config` - The `[general]` section
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if `temperature_decimals` is not 0 or 1
+pub fn apply_temperature_format(config: &GeneralConfig) -> Result<(), String> {
+    let decimals = validate_decimals(config.temperature_decimals)
+        .map_err(|e| format!("general.temperature_decimals {}", e))?;
+    let _ = FORMAT.set(TemperatureFormat {
+        decimals,
+        rounding: config.temperature_rounding,
+    });
+    Ok(())
+}
+
+/// Checks the number of decimals of the temperatures
+///
+/// # Arguments
+///
+/// * `decimals` - The `temperature_decimals` setting, None for the default
+///
+/// # Returns
+///
+/// * `Result<u8, String>` - The number of decimals, or an error if not 0 or 1
+pub fn validate_decimals(decimals: Option<u8>) -> Result<u8, String> {
+    match decimals.unwrap_or(1) {
+        decimals @ (0 | 1) => Ok(decimals),
+        decimals => Err(format!("must be 0 or 1, not {}", decimals)),
+    }
+}
+
+/// Gets the configured format, the default one until it is set
+///
+/// # Returns
+///
+/// * `TemperatureFormat` - The format
+fn format() -> TemperatureFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Rounds a number to an integer
+///
+/// # Arguments
+///
+/// * `value` - The number
+/// * `rounding` - Half away from zero, or half to even
+///
+/// # Returns
+///
+/// * `f64` - The rounded number
+fn round(value: f64, rounding: TemperatureRounding) -> f64 {
+    // Products such as 20.35 * 10 = 203.49999999999997 are brought back to the tie first
+    let value = (value * 1e6).round() / 1e6;
+    match rounding {
+        TemperatureRounding::HalfUp => value.round(),
+        TemperatureRounding::HalfEven => value.round_ties_even(),
+    }
+}
+
+/// Converts a temperature to the tenths of a degree sent to the stove
+///
+/// # Arguments
+///
+/// * `degrees` - The temperature in degrees
+///
+/// # Returns
+///
+/// * `i32` - The temperature in tenths of a degree, rounded as configured
+pub fn to_tenths(degrees: f64) -> i32 {
+    round(degrees * 10.0, format().rounding) as i32
+}
+
+/// Serializes a temperature received in tenths of a degree
+///
+/// With one decimal the value is computed in `f64`, so that 203 gives 20.3 and not
+/// 20.299999237060547 once converted by the clients. With no decimal it is rounded as
+/// configured and serialized as an integer.
+///
+/// # Arguments
+///
+/// * `tenths` - The temperature in tenths of a degree
+/// * `serializer` - The serializer
+///
+/// # Returns
+///
+/// * `Result<S::Ok, S::Error>` - The serialized temperature
+pub fn serialize_tenths<S: Serializer>(tenths: i16, serializer: S) -> Result<S::Ok, S::Error> {
+    let format = format();
+    let degrees = f64::from(tenths) / 10.0;
+    if format.decimals == 0 {
+        serializer.serialize_i64(round(degrees, format.rounding) as i64)
+    } else {
+        serializer.serialize_f64(degrees)
+    }
+}
@@ -0,0 +1,222 @@
+use crate::hottoh::hottoh_const::StoveCommands;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Window over which the writes are counted
+const WRITE_WINDOW: Duration = Duration::from_secs(60);
+/// Key of the profile applying to the stoves without their own profile
+const DEFAULT_PROFILE: &str = "default";
+
+/// Limits protecting the stove board from too many commands
+///
+/// Some boards reboot their Wi-Fi module when they receive commands too quickly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThrottleProfile {
+    /// Maximum number of writes per minute (0: unlimited)
+    max_writes_per_minute: usize,
+    /// Minimum delay between two on/off commands
+    min_on_off_gap: Duration,
+    /// Maximum change of a temperature setpoint per command, in degrees (0: unlimited)
+    max_setpoint_step: f64,
+}
+
+impl Default for ThrottleProfile {
+    /// Limits applied when none is configured
+    fn default() -> Self {
+        Self {
+            max_writes_per_minute: 30,
+            min_on_off_gap: Duration::from_secs(60),
+            max_setpoint_step: 0.0,
+        }
+    }
+}
+
+impl ThrottleProfile {
+    /// Parses a profile such as `max_writes_per_minute=10, min_on_off_gap_secs=300`
+    ///
+    /// Missing limits keep the value of the base profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `spec` - The profile
+    /// * `base` - The profile providing the missing limits
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ThrottleProfile, String>` - The profile, or an error if it is invalid
+    fn parse(spec: &str, base: ThrottleProfile) -> Result<Self, String> {
+        let mut profile = base;
+        for item in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (key, value) = item
+                .split_once('=')
+                .ok_or_else(|| format!("expected limit=value, got '{}'", item))?;
+            let value = value.trim();
+            let invalid = || format!("invalid value '{}' for {}", value, key.trim());
+            match key.trim() {
+                "max_writes_per_minute" => {
+                    profile.max_writes_per_minute = value.parse().map_err(|_| invalid())?
+                }
+                "min_on_off_gap_secs" => {
+                    profile.min_on_off_gap =
+                        Duration::from_secs(value.parse().map_err(|_| invalid())?)
+                }
+                "max_setpoint_step" => {
+                    profile.max_setpoint_step = value
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|step| step.is_finite() && *step >= 0.0)
+                        .ok_or_else(invalid)?
+                }
+                other => return Err(format!("unknown limit '{}'", other)),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// Command refused because it exceeds a limit
+#[derive(Debug)]
+pub struct ThrottleViolation {
+    /// Description of the exceeded limit
+    pub reason: String,
+    /// Delay after which the command would be accepted, None if it never would
+    pub retry_after: Option<Duration>,
+}
+
+/// Throttling profiles, by manufacturer, and history of the accepted writes
+#[derive(Debug, Clone, Default)]
+pub struct CommandThrottle {
+    /// Profile of the stoves without their own profile
+    default: ThrottleProfile,
+    /// Profiles by lowercase manufacturer name or code
+    profiles: HashMap<String, ThrottleProfile>,
+    /// When the writes of the last minute were accepted
+    writes: VecDeque<Instant>,
+    /// When the last on/off command was accepted
+    last_on_off: Option<Instant>,
+}
+
+impl CommandThrottle {
+    /// Builds the profiles from the `[throttle]` section
+    ///
+    /// The `default` key applies to every stove, other keys to the stoves of a
+    /// manufacturer (name or code, e.g. `Edilkamin` or `85`), on top of the default.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The profiles by key
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandThrottle, String>` - The throttle, or an error naming the invalid profile
+    pub fn from_config(config: &HashMap<String, String>) -> Result<Self, String> {
+        let default = match config.get(DEFAULT_PROFILE) {
+            Some(spec) => ThrottleProfile::parse(spec, ThrottleProfile::default())
+                .map_err(|e| format!("{}: {}", DEFAULT_PROFILE, e))?,
+            None => ThrottleProfile::default(),
+        };
+        let profiles = config
+            .iter()
+            .filter(|(key, _)| key.as_str() != DEFAULT_PROFILE)
+            .map(|(key, spec)| {
+                ThrottleProfile::parse(spec, default)
+                    .map(|profile| (key.trim().to_lowercase(), profile))
+                    .map_err(|e| format!("{}: {}", key, e))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Self {
+            default,
+            profiles,
+            ..Self::default()
+        })
+    }
+
+    /// Checks a write against the profile of the stove, and records it if accepted
+    ///
+    /// # Arguments
+    ///
+    /// * `manufacturer` - Name of the manufacturer, as reported by the stove
+    /// * `manufacturer_code` - Code of the manufacturer
+    /// * `action` - The StoveCommands value
+    /// * `value` - The value of the command
+    /// * `current` - The current value of the setpoint changed by the command, in degrees
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ThrottleViolation>` - Ok if the write can be sent
+    pub fn check(
+        &mut self,
+        manufacturer: &str,
+        manufacturer_code: u16,
+        action: u32,
+        value: &str,
+        current: Option<f64>,
+    ) -> Result<(), ThrottleViolation> {
+        let profile = self
+            .profiles
+            .get(&manufacturer.to_lowercase())
+            .or_else(|| self.profiles.get(&manufacturer_code.to_string()))
+            .copied()
+            .unwrap_or(self.default);
+        let now = Instant::now();
+
+        while self
+            .writes
+            .front()
+            .is_some_and(|write| now.duration_since(*write) >= WRITE_WINDOW)
+        {
+            self.writes.pop_front();
+        }
+        if profile.max_writes_per_minute > 0 && self.writes.len() >= profile.max_writes_per_minute {
+            let oldest = self.writes.front().copied().unwrap_or(now);
+            return Err(ThrottleViolation {
+                reason: format!(
+                    "at most {} writes per minute are allowed",
+                    profile.max_writes_per_minute
+                ),
+                retry_after: Some(WRITE_WINDOW.saturating_sub(now.duration_since(oldest))),
+            });
+        }
+
+        let is_on_off = action == StoveCommands::OnOff as u32;
+        if is_on_off {
+            if let Some(last) = self.last_on_off {
+                let elapsed = now.duration_since(last);
+                if elapsed < profile.min_on_off_gap {
+                    return Err(ThrottleViolation {
+                        reason: format!(
+                            "on/off commands must be at least {} seconds apart",
+                            profile.min_on_off_gap.as_secs()
+                        ),
+                        retry_after: Some(profile.min_on_off_gap - elapsed),
+                    });
+                }
+            }
+        }
+
+        if profile.max_setpoint_step > 0.0 {
+            let target = value.trim().parse::<f64>().ok().map(|tenths| tenths / 10.0);
+            if let (Some(current), Some(target)) = (current, target) {
+                if (target - current).abs() > profile.max_setpoint_step + 1e-6 {
+                    return Err(ThrottleViolation {
+                        reason: format!(
+                            "setpoint can change by at most {}°C per command ({}°C to {}°C requested)",
+                            profile.max_setpoint_step, current, target
+                        ),
+                        retry_after: None,
+                    });
+                }
+            }
+        }
+
+        self.writes.push_back(now);
+        if is_on_off {
+            self.last_on_off = Some(now);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use chrono_tz::Tz;
+use std::path::Path;
+
+/// Directory holding the timezone database of the system, read by chrono for `Local`
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// Sets the timezone used for local timestamps (logs and serialized data)
+///
+/// chrono reads the `TZ` environment variable for its local timezone, so setting it
+/// makes every `Local` timestamp of the process use the configured zone instead of
+/// the one of the system. Must be called before any other thread is started, the
+/// environment not being safe to change while another thread reads it.
+///
+/// # Arguments
+///
+/// * `timezone` - IANA timezone name (e.g. Europe/Paris), or None to keep the system one
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if the timezone is unknown
+pub fn apply_timezone(timezone: Option<&str>) -> Result<(), String> {
+    let Some(timezone) = timezone.map(str::trim).filter(|tz| !tz.is_empty()) else {
+        return Ok(());
+    };
+
+    // chrono silently falls back to UTC on an unknown zone, so check it exists first
+    validate_timezone(timezone)?;
+    if !Path::new(ZONEINFO_DIR).join(timezone).is_file() {
+        eprintln!(
+            "Timezone '{}' is missing from {} (tzdata not installed?), local times will be in UTC",
+            timezone, ZONEINFO_DIR
+        );
+    }
+
+    std::env::set_var("TZ", timezone);
+    Ok(())
+}
+
+/// Checks that a timezone exists in the IANA timezone database
+///
+/// The database is the one built into the binary, so the check does not depend on the
+/// system it runs on.
+///
+/// # Arguments
+///
+/// * `timezone` - IANA timezone name (e.g. Europe/Paris)
+///
+/// # Returns
+///
+/// * `Result<(), String>` - Success, or an error if the timezone is unknown
+pub fn validate_timezone(timezone: &str) -> Result<(), String> {
+    timezone
+        .parse::<Tz>()
+        .map(|_| ())
+        .map_err(|_| format!("Unknown timezone '{}'", timezone))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_timezone() {
+        assert!(validate_timezone("Europe/Paris").is_ok());
+        assert!(validate_timezone("UTC").is_ok());
+        assert!(validate_timezone("Europe/Atlantis").is_err());
+        assert!(validate_timezone("../../etc/passwd").is_err());
+        assert!(validate_timezone("").is_err());
+    }
+
+    #[test]
+    fn test_apply_no_timezone() {
+        assert!(apply_timezone(None).is_ok());
+        assert!(apply_timezone(Some("  ")).is_ok());
+        assert!(apply_timezone(Some("Europe/Atlantis")).is_err());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::hottoh::config::StoveConfig;
+use std::io;
+use std::net::UdpSocket;
+
+/// Wake-on-LAN of the Wi-Fi bridge of the stove
+///
+/// Some external Wi-Fi bridges go to sleep and stop accepting connections. A magic
+/// packet (6 bytes 0xFF followed by 16 times the MAC address) is broadcast over UDP to
+/// wake them up before connecting again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WakeOnLan {
+    /// MAC address of the bridge
+    mac: [u8; 6],
+    /// Address the magic packet is sent to, as `host:port`
+    target: String,
+}
+
+impl WakeOnLan {
+    /// Creates the Wake-on-LAN from the `[stove]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[stove]` section
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<WakeOnLan>, String>` - The Wake-on-LAN, None if `wake_mac` is not
+    ///   set, or an error if the MAC address is invalid
+    pub fn from_config(config: &StoveConfig) -> Result<Option<Self>, String> {
+        let mac = config.wake_mac.trim();
+        if mac.is_empty() {
+            return Ok(None);
+        }
+        let bytes = mac
+            .split([':', '-'])
+            .map(|byte| {
+                u8::from_str_radix(byte, 16)
+                    .ok()
+                    .filter(|_| byte.len() == 2)
+            })
+            .collect::<Option<Vec<u8>>>()
+            .and_then(|bytes| <[u8; 6]>::try_from(bytes).ok())
+            .ok_or_else(|| format!("invalid MAC address '{}', expected AA:BB:CC:DD:EE:FF", mac))?;
+        Ok(Some(Self {
+            mac: bytes,
+            target: config.wake_broadcast.trim().to_string(),
+        }))
+    }
+
+    /// Broadcasts the magic packet
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<()>` - An error if the packet cannot be sent
+    pub fn send(&self) -> io::Result<()> {
+        let mut packet = vec![0xFF; 6];
+        for _ in 0..16 {
+            packet.extend_from_slice(&self.mac);
+        }
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_broadcast(true)?;
+        socket.send_to(&packet, &self.target)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,353 @@
+use crate::hottoh::api_keys::Role;
+use crate::hottoh::auth_guard::AuthGuard;
+use crate::hottoh::child_lock::{ChildLock, PIN_FIELD};
+use crate::hottoh::command_outcome::{send_command, CommandHandle};
+use crate::hottoh::confirmation::CONFIRMATION_TTL;
+use crate::hottoh::hottoh_const::StoveCommands;
+use crate::hottoh::http_api::{check_command_value, ApiError, MAX_COMMAND_WAIT_SECS};
+use crate::hottoh::locks::read_lock;
+use crate::hottoh::plugins::PluginRegistry;
+use crate::hottoh::request_signing::RequestSigning;
+use crate::hottoh::shared_struct::SharedState;
+use crate::hottoh::tcp_client::{CommandError, CommandOrigin};
+use crate::hottoh::tcp_client_structs::Request;
+use actix_web::http::Method;
+use actix_web::ResponseError;
+use chrono::Utc;
+use log::info;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+/// Command message sent by a WebSocket client
+///
+/// e.g. `{"type": "command", "id": 7, "command": "PowerLevel", "value": 3, "pin": "1234"}`
+#[derive(Deserialize)]
+struct CommandMessage {
+    /// Identifier chosen by the client, echoed in the acknowledgement
+    #[serde(default)]
+    id: Value,
+    /// Name of the command, a `StoveCommands` name or a command registered by a plugin
+    command: String,
+    /// Raw value sent to the stove, as with the inbound webhooks
+    value: i32,
+    /// PIN, when the child lock is enabled and was not given on the handshake
+    #[serde(default)]
+    pin: Option<Value>,
+    /// Send the command even during a maintenance window
+    #[serde(default)]
+    force: bool,
+    /// Token of a previous acknowledgement, for a command needing a confirmation
+    #[serde(default)]
+    confirm: Option<String>,
+    /// Seconds after which the command is dropped if not sent yet (0 keeps it until
+    /// sent, `stove.command_ttl_secs` if absent)
+    #[serde(default)]
+    ttl: Option<u64>,
+    /// Seconds to wait for the outcome of the command (at most 30), sent in an
+    /// `outcome` message after the acknowledgement
+    #[serde(default)]
+    wait: Option<u64>,
+}
+
+/// Command message signed with `http_api.signing_secret`
+///
+/// e.g. `{"type": "signed", "timestamp": 1700000000, "signature": "sha256=...",
+/// "message": "{\"type\": \"command\", ...}"}`, the signature covering
+/// `<timestamp>.WS./api/ws.<message>` as for the signed HTTP requests.
+#[derive(Deserialize)]
+struct SignedMessage {
+    /// Time at which the message was signed, in Unix seconds
+    timestamp: Value,
+    /// Signature, as `sha256=<hex HMAC-SHA256>`
+    signature: String,
+    /// The command message, as sent unsigned
+    message: String,
+}
+
+/// Commands received on the state WebSocket
+///
+/// Each command goes through the same checks as `POST /api/dat/set_*` (role of the API
+/// key, PIN or signature, range of the value, read-only mode, maintenance windows and
+/// throttling) and is acknowledged
+/// on the socket as `{"type": "ack", "id", "success": true, "request_id"}`, or
+/// `{"type": "ack", "id", "success": false, "status", "error"}` with the HTTP status
+/// the same request would have got. A destructive command is acknowledged with status
+/// 202 and a `confirmation_token`, to send back in the `confirm` field.
+pub struct WsCommands {
+    /// The request queue
+    pub request_queue: Arc<RwLock<VecDeque<Request>>>,
+    /// The request ID counter
+    pub request_id_counter: Arc<Mutex<u32>>,
+    /// Shared state holding the stove data
+    pub shared_state: Arc<RwLock<SharedState>>,
+    /// Commands registered by the plugins
+    pub plugins: Arc<PluginRegistry>,
+    /// PIN required by the commands
+    pub child_lock: Arc<ChildLock>,
+    /// Bans of the clients sending wrong PINs, with the address of the client
+    pub auth_guard: Option<(Arc<AuthGuard>, IpAddr)>,
+    /// Role of the API key of the handshake, None if the API keys are disabled
+    pub role: Option<Role>,
+    /// PIN of the `X-Hottoh-Pin` header of the handshake, if any
+    pub pin: Option<String>,
+    /// Signing of the commands, which then need a signature unless a PIN is given
+    pub signing: Arc<RequestSigning>,
+}
+
+impl WsCommands {
+    /// Handles a message of the client, a command or a signed command
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The message
+    ///
+    /// # Returns
+    ///
+    /// * `(Value, Option<(CommandHandle, Duration)>)` - The acknowledgement, and the
+    ///   handle of the queued command with the time to wait for its outcome, if asked
+    pub fn handle(&self, text: &str) -> (Value, Option<(CommandHandle, Duration)>) {
+        let kind = serde_json::from_str::<Value>(text)
+            .ok()
+            .and_then(|message| {
+                message
+                    .get("type")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            });
+        if kind.as_deref() != Some("signed") {
+            return self.handle_command(text, false);
+        }
+        let signed = match serde_json::from_str::<SignedMessage>(text) {
+            Ok(signed) => signed,
+            Err(e) => {
+                let error = ApiError::InvalidParameter(format!("invalid signed message: {}", e));
+                return (rejection(&Value::Null, error), None);
+            }
+        };
+        let timestamp = match &signed.timestamp {
+            Value::String(timestamp) => timestamp.clone(),
+            timestamp => timestamp.to_string(),
+        };
+        let verified = if self.signing.is_enabled() {
+            self.signing.verify(
+                &timestamp,
+                "WS",
+                "/api/ws",
+                signed.message.as_bytes(),
+                &signed.signature,
+                Utc::now().timestamp(),
+            )
+        } else {
+            Err("request signing is not enabled".into())
+        };
+        match verified {
+            Ok(()) => self.handle_command(&signed.message, true),
+            Err(e) => {
+                if let Some((guard, client)) = &self.auth_guard {
+                    guard.fail(*client, "/api/ws");
+                }
+                (rejection(&Value::Null, ApiError::Unauthorized(e)), None)
+            }
+        }
+    }
+
+    /// Handles a command message whose request was authenticated by the middlewares
+    ///
+    /// Used by the GraphQL mutations sent with POST, whose PIN or signature was checked
+    /// with the request; the role of the API key is still checked.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The message
+    ///
+    /// # Returns
+    ///
+    /// * `Value` - The acknowledgement
+    pub fn handle_verified(&self, text: &str) -> Value {
+        self.handle_command(text, true).0
+    }
+
+    /// Handles a command message
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The message
+    /// * `signed` - Whether the message came with a valid signature
+    ///
+    /// # Returns
+    ///
+    /// * `(Value, Option<(CommandHandle, Duration)>)` - The acknowledgement, and the
+    ///   handle of the queued command with the time to wait for its outcome, if asked
+    fn handle_command(
+        &self,
+        text: &str,
+        signed: bool,
+    ) -> (Value, Option<(CommandHandle, Duration)>) {
+        let message = match serde_json::from_str::<CommandMessage>(text) {
+            Ok(message) => message,
+            Err(e) => {
+                let id = serde_json::from_str::<Value>(text)
+                    .ok()
+                    .and_then(|value| value.get("id").cloned())
+                    .unwrap_or(Value::Null);
+                return (
+                    rejection(
+                        &id,
+                        ApiError::InvalidParameter(format!("invalid command message: {}", e)),
+                    ),
+                    None,
+                );
+            }
+        };
+        match self.send(&message, signed) {
+            Ok(handle) => {
+                let request_id = handle.get_request_id();
+                info!(
+                    "WebSocket command {}={} queued as request {}",
+                    message.command, message.value, request_id
+                );
+                let ack = json!({
+                    "type": "ack",
+                    "id": message.id,
+                    "success": true,
+                    "request_id": request_id,
+                });
+                let wait = message
+                    .wait
+                    .filter(|wait| *wait > 0)
+                    .map(|wait| (handle, Duration::from_secs(wait.min(MAX_COMMAND_WAIT_SECS))));
+                (ack, wait)
+            }
+            Err(e) => (rejection(&message.id, e), None),
+        }
+    }
+
+    /// Checks whether the client was banned for sending wrong PINs
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the client is banned, and the socket should be closed
+    pub fn is_banned(&self) -> bool {
+        self.auth_guard
+            .as_ref()
+            .is_some_and(|(guard, client)| guard.banned_for(*client).is_some())
+    }
+
+    /// Checks and queues a command
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The command message
+    /// * `signed` - Whether the message came with a valid signature, accepted without PIN
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CommandHandle, ApiError>` - The handle of the queued command, or the
+    ///   reason it was rejected
+    fn send(&self, message: &CommandMessage, signed: bool) -> Result<CommandHandle, ApiError> {
+        if self.role.is_some_and(|role| !role.allows(&Method::POST)) {
+            return Err(ApiError::Forbidden("the API key is read-only".into()));
+        }
+        if !signed {
+            // As for the HTTP requests, a signature is required unless a PIN is set
+            if self.signing.is_enabled() && self.child_lock.get_pin().is_none() {
+                return Err(ApiError::Unauthorized(
+                    "signature required (signed message)".into(),
+                ));
+            }
+            self.check_pin(message)?;
+        }
+        let action = match StoveCommands::from_str(&message.command) {
+            Ok(command) => {
+                check_command_value(&self.shared_state, command, message.value)?;
+                command as u32
+            }
+            Err(_) => self.plugins.get_command(&message.command).ok_or_else(|| {
+                ApiError::NotFound(format!("Unknown command '{}'", message.command))
+            })?,
+        };
+        let confirmed = message.confirm.as_deref().is_some_and(|token| {
+            read_lock(&self.shared_state, "shared state")
+                .get_confirmations()
+                .redeem(token, &[(action, message.value.to_string())])
+        });
+        Ok(send_command(
+            &self.request_queue,
+            &self.request_id_counter,
+            &self.shared_state,
+            CommandOrigin::Manual {
+                force: message.force,
+                confirmed,
+            },
+            action,
+            &message.value,
+            message.ttl.map(Duration::from_secs),
+        )?)
+    }
+
+    /// Checks the PIN of a command, given in the message or on the handshake
+    ///
+    /// A wrong PIN counts as a failed authentication of the client.
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - The command message
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ApiError>` - Success, or `Unauthorized` if the PIN is missing or wrong
+    fn check_pin(&self, message: &CommandMessage) -> Result<(), ApiError> {
+        if self.child_lock.get_pin().is_none() {
+            return Ok(());
+        }
+        let pin = match &message.pin {
+            Some(Value::String(pin)) => Some(pin.clone()),
+            Some(Value::Number(pin)) => Some(pin.to_string()),
+            _ => self.pin.clone(),
+        };
+        match pin {
+            Some(pin) if self.child_lock.verify(pin.trim()) => Ok(()),
+            Some(_) => {
+                if let Some((guard, client)) = &self.auth_guard {
+                    guard.fail(*client, "/api/ws");
+                }
+                Err(ApiError::Unauthorized("wrong PIN".into()))
+            }
+            None => Err(ApiError::Unauthorized(format!(
+                "PIN required ('{}' field of the command)",
+                PIN_FIELD
+            ))),
+        }
+    }
+}
+
+/// Builds the acknowledgement of a rejected command
+///
+/// # Arguments
+///
+/// * `id` - Identifier of the command chosen by the client
+/// * `error` - The reason of the rejection
+///
+/// # Returns
+///
+/// * `Value` - The acknowledgement, with the HTTP status the same request would have got
+///   and the confirmation token of a destructive command
+fn rejection(id: &Value, error: ApiError) -> Value {
+    let mut ack = json!({
+        "type": "ack",
+        "id": id,
+        "success": false,
+        "status": error.error_response().status().as_u16(),
+        "error": error.to_string(),
+    });
+    if let ApiError::CommandRejected(CommandError::ConfirmationRequired(token)) = &error {
+        ack["confirmation_token"] = json!(token);
+        ack["expires_in_seconds"] = json!(CONFIRMATION_TTL.as_secs());
+    }
+    ack
+}
@@ -0,0 +1,5 @@
+//! Bridge between Hottoh stoves and home automation systems
+//!
+//! The binary runs the bridge; the library exposes its modules to the benchmarks.
+
+pub mod hottoh;
@@ -1,20 +1,74 @@
-mod hottoh;
-use crate::hottoh::http_api::start_http_server;
-use crate::hottoh::shared_struct::SharedState;
-use hottoh::config::load_config;
+use hottoh::alerts::start_alerts_thread;
+use hottoh::check_config::run_check_config;
+use hottoh::config::{load_config, run_config_schema, AppConfig, LogTarget};
+use hottoh::crash_report::{install_crash_reporter, DEFAULT_CRASH_DIR};
+use hottoh::events::EventBus;
+use hottoh::fan_curve::start_fan_curve_thread;
+use hottoh::frame_capture::FrameCapture;
+use hottoh::freeze_watchdog::start_freeze_watchdog_thread;
+use hottoh::frost_guard::start_frost_guard_thread;
+use hottoh::history::start_history_thread;
+use hottoh::history_import::run_import_history;
+use hottoh::home_assistant::start_home_assistant_thread;
+use hottoh::http_api::start_http_server;
+use hottoh::ignition_monitor::start_ignition_monitor_thread;
+use hottoh::init::run_init;
+use hottoh::install::run_install;
+use hottoh::jeedom::start_jeedom_thread;
 use hottoh::logger::initialize_logger;
+use hottoh::maintenance::MaintenanceSchedule;
+use hottoh::metrics::Metrics;
+use hottoh::mqtt::start_mqtt_thread;
+use hottoh::multiplexer::{start_multiplexer_thread, Multiplexer};
+use hottoh::notifications::{start_notifications_thread, Notifications};
+use hottoh::openhab::{start_openhab_thread, CommandQueue};
+use hottoh::parse_capture::run_parse_capture;
+use hottoh::plugins::PluginRegistry;
+use hottoh::quiet_hours::start_quiet_hours_thread;
+use hottoh::response_log::start_response_log_thread;
+use hottoh::rules::start_rules_thread;
+use hottoh::run_timer::{start_run_timer_thread, RunTimer, DEFAULT_RUN_TIMER_FILE};
+use hottoh::scripts::start_scripts_thread;
+use hottoh::shared_struct::SharedState;
 use hottoh::tcp_client::TcpClient;
 use hottoh::tcp_client_structs::{Request, Response};
-use log::info;
+use hottoh::temperature::apply_temperature_format;
+use hottoh::throttle::CommandThrottle;
+use hottoh::timezone::apply_timezone;
+use hottoh_api::hottoh;
+use log::{error, info};
 use std::collections::VecDeque;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 
 use actix_web::rt::System;
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
+/// Entry point of a subcommand, called with the arguments following its name
+type Subcommand = fn(&[String]) -> Result<(), String>;
+
+/// Runs a subcommand, or loads the configuration and starts the bridge
+///
+/// The timezone is applied before the runtime of actix is created, since `TZ` must not
+/// change while other threads may read the environment.
+fn main() -> std::io::Result<()> {
     let args: Vec<String> = std::env::args().collect();
+    let subcommand: Option<Subcommand> = match args.get(1).map(String::as_str) {
+        Some("init") => Some(run_init),
+        Some("install") => Some(run_install),
+        Some("check-config") => Some(run_check_config),
+        Some("config-schema") => Some(run_config_schema),
+        Some("parse-capture") => Some(run_parse_capture),
+        Some("import-history") => Some(run_import_history),
+        _ => None,
+    };
+    if let Some(subcommand) = subcommand {
+        if let Err(e) = subcommand(&args[2..]) {
+            eprintln!("{} failed: {}", args[1], e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
     let config_path = args.get(1).map(|s| s.as_str());
     // Load configuration
     let config = match load_config(config_path) {
@@ -25,6 +79,14 @@ async fn main() -> std::io::Result<()> {
                 config.stove.ip, config.stove.port
             );
             println!("HTTP API Port: {}", config.http_api.port);
+            if let Err(e) = apply_timezone(config.general.timezone.as_deref()) {
+                eprintln!("Failed to load configuration: {}", e);
+                std::process::exit(1);
+            }
+            if let Err(e) = apply_temperature_format(&config.general) {
+                eprintln!("Failed to load configuration: {}", e);
+                std::process::exit(1);
+            }
             Arc::new(RwLock::new(config))
         }
         Err(e) => {
@@ -32,6 +94,19 @@ async fn main() -> std::io::Result<()> {
             std::process::exit(1);
         }
     };
+    System::new().block_on(run(config))
+}
+
+/// Starts the threads and the HTTP server, and waits for them to stop
+///
+/// # Arguments
+///
+/// * `config` - The configuration, with the timezone already applied
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - Success, or the error of the HTTP server
+async fn run(config: Arc<RwLock<AppConfig>>) -> std::io::Result<()> {
     initialize_logger(Arc::clone(&config)).expect("Failed to initialize logger");
     info!("Starting...");
     let running = Arc::new(AtomicBool::new(true));
@@ -49,20 +124,296 @@ async fn main() -> std::io::Result<()> {
     let request_id_counter = Arc::new(Mutex::new(0));
     let request_queue = Arc::new(RwLock::new(VecDeque::<Request>::new()));
     let response_queue = Arc::new(RwLock::new(VecDeque::<Response>::new()));
+    let metrics = Arc::new(Metrics::new());
+    let (redact, read_only) = {
+        let cfg = config.read().expect("Cannot read config in main thread.");
+        (cfg.general.redact, cfg.general.read_only)
+    };
+    let frame_capture = Arc::new(FrameCapture::new(redact));
+    let events = Arc::new(EventBus::new());
+    let multiplexer = Arc::new(Multiplexer::new());
     let tcp_client = TcpClient::new(
         Arc::clone(&request_queue),
         Arc::clone(&response_queue),
         Arc::clone(&running),
+        Arc::clone(&metrics),
+        Arc::clone(&frame_capture),
+        Arc::clone(&events),
+        Arc::clone(&multiplexer),
     );
-    let shared_state = Arc::new(RwLock::new(SharedState::new()));
+    let mut state = SharedState::new();
+    state.set_events(Arc::clone(&events));
+    state.set_read_only(read_only);
+    state.set_command_ttl(Duration::from_secs(
+        config
+            .read()
+            .expect("Cannot read config in main thread.")
+            .stove
+            .command_ttl_secs,
+    ));
+    state.set_run_timer(RunTimer::load(
+        config
+            .read()
+            .expect("Cannot read config in main thread.")
+            .general
+            .run_timer_file
+            .as_deref()
+            .unwrap_or(DEFAULT_RUN_TIMER_FILE),
+    ));
+    if let Some(maintenance) = &config
+        .read()
+        .expect("Cannot read config in main thread.")
+        .maintenance
+    {
+        match MaintenanceSchedule::parse(&maintenance.windows) {
+            Ok(schedule) => state.set_maintenance(schedule),
+            Err(e) => error!("Maintenance windows disabled: {}", e),
+        }
+    }
+    if let Some(throttle) = &config
+        .read()
+        .expect("Cannot read config in main thread.")
+        .throttle
+    {
+        match CommandThrottle::from_config(throttle) {
+            Ok(throttle) => state.set_throttle(throttle),
+            Err(e) => error!(
+                "Invalid throttling profile, using the built-in limits: {}",
+                e
+            ),
+        }
+    }
+    let shared_state = Arc::new(RwLock::new(state));
+    {
+        let cfg = config.read().expect("Cannot read config in main thread.");
+        let log_directory = matches!(cfg.log.get_target(), LogTarget::File | LogTarget::Both)
+            .then(|| cfg.log.directory.clone());
+        install_crash_reporter(
+            cfg.general
+                .crash_dir
+                .clone()
+                .unwrap_or_else(|| DEFAULT_CRASH_DIR.to_string()),
+            log_directory,
+            Arc::clone(&shared_state),
+            Arc::clone(&metrics),
+        );
+    }
+    let plugins = {
+        let cfg = config.read().expect("Cannot read config in main thread.");
+        match PluginRegistry::from_config(cfg.plugins.as_ref()) {
+            Ok(plugins) => plugins,
+            Err(e) => {
+                error!("Plugins disabled: {}", e);
+                PluginRegistry::new()
+            }
+        }
+    };
+    let notifier_handles = plugins.start_notifier_threads(&events, &running, &metrics);
+    let plugins = Arc::new(plugins);
 
     let http_server_task = start_http_server(
-        Arc::clone(&request_queue),
+        CommandQueue {
+            request_queue: Arc::clone(&request_queue),
+            request_id_counter: Arc::clone(&request_id_counter),
+        },
+        Arc::clone(&response_queue),
         Arc::clone(&shared_state),
-        Arc::clone(&request_id_counter),
+        Arc::clone(&metrics),
+        Arc::clone(&frame_capture),
+        Arc::clone(&plugins),
         Arc::clone(&config),
     );
 
+    let (
+        jeedom_handle,
+        openhab_handle,
+        home_assistant_handle,
+        mqtt_handle,
+        notifications_handle,
+        rules_handle,
+        scripts_handle,
+        fan_curve_handle,
+        quiet_hours_handle,
+        frost_guard_handle,
+        watchdog_handle,
+        alerts_handle,
+        history_handle,
+        ignition_handle,
+        multiplexer_handle,
+    ) = {
+        let cfg = config.read().expect("Cannot read config in main thread.");
+        let jeedom_handle = start_jeedom_thread(
+            cfg.jeedom.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let openhab_handle = start_openhab_thread(
+            cfg.openhab.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let home_assistant_handle = start_home_assistant_thread(
+            cfg.home_assistant.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let notifications = cfg.notifications.as_ref().and_then(|config| {
+            match Notifications::from_config(config, Arc::clone(&shared_state)) {
+                Ok(notifications) => Some(Arc::new(notifications)),
+                Err(e) => {
+                    error!("Notifications disabled: {}", e);
+                    None
+                }
+            }
+        });
+        let notifications_handle = start_notifications_thread(
+            cfg.notifications.as_ref(),
+            notifications.clone(),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let mqtt_handle = start_mqtt_thread(
+            cfg.mqtt.as_ref(),
+            Arc::clone(&events),
+            notifications.zip(
+                cfg.notifications
+                    .as_ref()
+                    .and_then(|config| config.mqtt_topic.clone()),
+            ),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let rules_handle = start_rules_thread(
+            cfg.rules.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let scripts_handle = start_scripts_thread(
+            cfg.scripts.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let fan_curve_handle = start_fan_curve_thread(
+            cfg.fan_curve.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let quiet_hours_handle = start_quiet_hours_thread(
+            cfg.quiet_hours.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let frost_guard_handle = start_frost_guard_thread(
+            cfg.frost_guard.as_ref(),
+            Arc::clone(&shared_state),
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let watchdog_handle = start_freeze_watchdog_thread(
+            cfg.freeze_watchdog.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let alerts_handle = start_alerts_thread(
+            cfg.alerts.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let history_handle = start_history_thread(
+            cfg.history.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let ignition_handle = start_ignition_monitor_thread(
+            cfg.ignition_monitor.as_ref(),
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        let multiplexer_handle = start_multiplexer_thread(
+            cfg.multiplexer.as_ref(),
+            multiplexer,
+            CommandQueue {
+                request_queue: Arc::clone(&request_queue),
+                request_id_counter: Arc::clone(&request_id_counter),
+            },
+            Arc::clone(&shared_state),
+            Arc::clone(&running),
+            Arc::clone(&metrics),
+        );
+        (
+            jeedom_handle,
+            openhab_handle,
+            home_assistant_handle,
+            mqtt_handle,
+            notifications_handle,
+            rules_handle,
+            scripts_handle,
+            fan_curve_handle,
+            quiet_hours_handle,
+            frost_guard_handle,
+            watchdog_handle,
+            alerts_handle,
+            history_handle,
+            ignition_handle,
+            multiplexer_handle,
+        )
+    };
+
+    let run_timer_handle = start_run_timer_thread(
+        Arc::clone(&shared_state),
+        CommandQueue {
+            request_queue: Arc::clone(&request_queue),
+            request_id_counter: Arc::clone(&request_id_counter),
+        },
+        Arc::clone(&running),
+        Arc::clone(&metrics),
+    );
+    let response_log_handle = start_response_log_thread(
+        config
+            .read()
+            .expect("Cannot read config in main thread.")
+            .general
+            .response_log
+            .clone(),
+        tcp_client.responses(),
+        Arc::clone(&running),
+        Arc::clone(&metrics),
+    );
     let comm_handle = tcp_client.start_tcp_thread(Arc::clone(&config));
     let manage_handle = tcp_client.message_management_thread(shared_state);
     let periodic_handle = tcp_client.periodic_request_thread(Arc::clone(&request_id_counter));
@@ -77,6 +428,33 @@ async fn main() -> std::io::Result<()> {
     comm_handle.join().unwrap();
     manage_handle.join().unwrap();
     periodic_handle.join().unwrap();
+    run_timer_handle.join().unwrap();
+    for handle in [
+        jeedom_handle,
+        openhab_handle,
+        home_assistant_handle,
+        mqtt_handle,
+        notifications_handle,
+        rules_handle,
+        scripts_handle,
+        fan_curve_handle,
+        quiet_hours_handle,
+        frost_guard_handle,
+        watchdog_handle,
+        alerts_handle,
+        history_handle,
+        ignition_handle,
+        multiplexer_handle,
+        response_log_handle,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        handle.join().unwrap();
+    }
+    for handle in notifier_handles {
+        handle.join().unwrap();
+    }
 
     Ok(())
 }